@@ -4,6 +4,8 @@ use tokio::io::AsyncReadExt;
 use url::Url;
 use y_sweet_core::api_types::Authorization;
 use y_sweet_core::auth::{AuthError, Authenticator};
+use y_sweet_core::doc_dump;
+use y_sweet_core::store::Store;
 
 pub fn print_auth_message(auth: &Authenticator) {
     match auth.key_material() {
@@ -110,8 +112,12 @@ pub async fn sign_stdin(auth: &Authenticator) -> anyhow::Result<()> {
 
     let authorization = match input.authorization.as_deref() {
         Some("read") => Authorization::ReadOnly,
+        Some("suggest") => Authorization::Suggest,
         Some("full") => Authorization::Full,
-        Some(other) => anyhow::bail!("Invalid authorization: {}. Must be 'read' or 'full'", other),
+        Some(other) => anyhow::bail!(
+            "Invalid authorization: {}. Must be 'read', 'suggest' or 'full'",
+            other
+        ),
         None => Authorization::Full,
     };
 
@@ -260,6 +266,7 @@ pub async fn verify_stdin(auth: &Authenticator, id: Option<&str>) -> anyhow::Res
                         // Successfully verified as file hash
                         let auth_str = match authorization {
                             Authorization::ReadOnly => "read",
+                            Authorization::Suggest => "suggest",
                             Authorization::Full => "full",
                         };
 
@@ -275,6 +282,7 @@ pub async fn verify_stdin(auth: &Authenticator, id: Option<&str>) -> anyhow::Res
                         // Successfully verified as doc_id
                         let auth_str = match authorization {
                             Authorization::ReadOnly => "read",
+                            Authorization::Suggest => "suggest",
                             Authorization::Full => "full",
                         };
 
@@ -306,6 +314,7 @@ pub async fn verify_stdin(auth: &Authenticator, id: Option<&str>) -> anyhow::Res
                         Ok(authorization) => {
                             let auth_str = match authorization {
                                 Authorization::ReadOnly => "read",
+                                Authorization::Suggest => "suggest",
                                 Authorization::Full => "full",
                             };
 
@@ -360,3 +369,53 @@ pub async fn verify_stdin(auth: &Authenticator, id: Option<&str>) -> anyhow::Res
     println!("{}", serde_json::to_string_pretty(&output)?);
     Ok(())
 }
+
+/// Send a request to a running server's admin endpoints (folder snapshots,
+/// fsck, dump) and print the JSON response.
+pub async fn server_api_request(
+    server_url: &str,
+    token: Option<&str>,
+    method: reqwest::Method,
+    path: &str,
+    body: Option<serde_json::Value>,
+) -> anyhow::Result<()> {
+    let url = Url::parse(&format!("{}/{}", server_url.trim_end_matches('/'), path))?;
+    let mut request = reqwest::Client::new().request(method, url);
+    if let Some(token) = token {
+        request = request.bearer_auth(token);
+    }
+    if let Some(body) = body {
+        request = request.json(&body);
+    }
+
+    let response = request.send().await?;
+    let status = response.status();
+    let text = response.text().await?;
+    if !status.is_success() {
+        anyhow::bail!("Server returned {}: {}", status, text);
+    }
+
+    let output: serde_json::Value = serde_json::from_str(&text)?;
+    println!("{}", serde_json::to_string_pretty(&output)?);
+    Ok(())
+}
+
+/// Print every root type of a doc read straight from `store`, in the same
+/// shape as the server's `GET /dump`. With `folder`, `target` is a folder
+/// name and every doc in the store is read to find its folder doc.
+pub async fn dump_from_store(store: &dyn Store, target: &str, folder: bool) -> anyhow::Result<()> {
+    let doc_id = if folder {
+        doc_dump::find_stored_folder(store, target)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("Folder '{}' not found in the store", target))?
+    } else {
+        target.to_string()
+    };
+    let roots = doc_dump::dump_stored_doc(store, &doc_id)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("Document not found in the store: {}", doc_id))?;
+
+    let output = serde_json::json!({ "docId": doc_id, "roots": roots });
+    println!("{}", serde_json::to_string_pretty(&output)?);
+    Ok(())
+}
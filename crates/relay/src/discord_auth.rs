@@ -0,0 +1,672 @@
+//! Discord OAuth2 login.
+//!
+//! `GET /auth/discord/login` sends the browser to Discord with a signed
+//! `state` (a nonce and an expiry, HMAC'd with a per-process key), also set
+//! in a cookie so that only the browser that started the login can finish
+//! it. Nothing is stored per login, so starting logins costs the relay no
+//! memory and no client can crowd out another's. Discord redirects back to `/auth/discord/callback`
+//! with a code, which is exchanged for an access token and the member's
+//! record in the configured guild (`/users/@me/guilds/{guild}/member`). The
+//! member's guild roles are mapped to folder roles by
+//! `.config/discord_roles.json`, the login is kept as an in-memory session,
+//! and the browser is sent on to the app with the session ID in the URL
+//! fragment. The session ID is the bearer for `GET /auth/discord/session`
+//! and `POST /auth/discord/doc/:doc_id`, which mints an ordinary CWT doc
+//! token carrying the member's user ID (`discord:<snowflake>`), so ACL rules
+//! and everything downstream see who connected.
+//!
+//! Sessions, and logins in progress, do not survive a restart; clients log
+//! in again.
+
+use anyhow::{Context, Result};
+use dashmap::DashMap;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use url::Url;
+use y_sweet_core::acl::{AclRole, ALL_FOLDERS};
+use y_sweet_core::store::Store;
+
+/// Store key of the guild role to folder role mapping.
+pub const ROLE_MAPPING_KEY: &str = ".config/discord_roles.json";
+
+/// Prefix of the user IDs minted tokens carry.
+pub const USER_PREFIX: &str = "discord:";
+
+const DEFAULT_API_URL: &str = "https://discord.com/api/v10";
+const DEFAULT_AUTHORIZE_URL: &str = "https://discord.com/oauth2/authorize";
+const SCOPES: &str = "identify guilds.members.read";
+
+/// Cookie holding the `state` of the login this browser started.
+pub const STATE_COOKIE: &str = "relay_discord_state";
+
+/// How long a login may take between `/login` and `/callback`.
+pub const STATE_TTL: Duration = Duration::from_secs(10 * 60);
+/// Bytes of HMAC-SHA256 kept in a `state`.
+const STATE_SIG_LEN: usize = 16;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone)]
+pub struct DiscordAuthConfig {
+    pub client_id: String,
+    pub client_secret: String,
+    /// Must match a redirect registered for the application, e.g.
+    /// `https://relay.example.com/auth/discord/callback`.
+    pub redirect_uri: String,
+    /// The guild whose roles decide access.
+    pub guild_id: String,
+    /// Where the browser goes after a login, with `#session=...` appended.
+    pub app_url: String,
+    /// Discord API base; pointed at a mock provider in tests.
+    pub api_url: String,
+    pub authorize_url: String,
+    pub session_ttl: Duration,
+}
+
+impl DiscordAuthConfig {
+    /// Read `RELAY_DISCORD_CLIENT_ID`, `RELAY_DISCORD_CLIENT_SECRET`,
+    /// `RELAY_DISCORD_REDIRECT_URI`, `RELAY_DISCORD_GUILD_ID` and
+    /// `RELAY_DISCORD_APP_URL`, all required to enable login, plus the optional `RELAY_DISCORD_API_URL`,
+    /// `RELAY_DISCORD_AUTHORIZE_URL` and `RELAY_DISCORD_SESSION_HOURS`
+    /// (default 24). Invalid numbers are logged and ignored.
+    pub fn from_env() -> Option<Self> {
+        let var = |name: &str| {
+            std::env::var(name)
+                .ok()
+                .map(|value| value.trim().to_string())
+                .filter(|value| !value.is_empty())
+        };
+        let client_id = var("RELAY_DISCORD_CLIENT_ID")?;
+        let (Some(client_secret), Some(redirect_uri), Some(guild_id), Some(app_url)) = (
+            var("RELAY_DISCORD_CLIENT_SECRET"),
+            var("RELAY_DISCORD_REDIRECT_URI"),
+            var("RELAY_DISCORD_GUILD_ID"),
+            var("RELAY_DISCORD_APP_URL"),
+        ) else {
+            tracing::warn!(
+                "RELAY_DISCORD_CLIENT_ID is set but RELAY_DISCORD_CLIENT_SECRET, \
+                 RELAY_DISCORD_REDIRECT_URI, RELAY_DISCORD_GUILD_ID or \
+                 RELAY_DISCORD_APP_URL is missing; Discord login disabled"
+            );
+            return None;
+        };
+        let session_hours = match var("RELAY_DISCORD_SESSION_HOURS") {
+            Some(value) => value.parse::<u64>().unwrap_or_else(|e| {
+                tracing::warn!("Ignoring RELAY_DISCORD_SESSION_HOURS={}: {}", value, e);
+                24
+            }),
+            None => 24,
+        };
+        Some(Self {
+            client_id,
+            client_secret,
+            redirect_uri,
+            guild_id,
+            app_url,
+            api_url: var("RELAY_DISCORD_API_URL").unwrap_or_else(|| DEFAULT_API_URL.to_string()),
+            authorize_url: var("RELAY_DISCORD_AUTHORIZE_URL")
+                .unwrap_or_else(|| DEFAULT_AUTHORIZE_URL.to_string()),
+            session_ttl: Duration::from_secs(session_hours.saturating_mul(3600)),
+        })
+    }
+}
+
+/// Folder roles granted per Discord role ID. Folders are keyed by name or
+/// UUID, `*` for every folder; the guild ID doubles as the ID of the
+/// `@everyone` role, so mapping it covers every member.
+///
+/// ```json
+/// {"roles": {"<role id>": {"Lens Edu": "edit", "*": "view"}}}
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RoleMapping {
+    #[serde(default)]
+    pub roles: BTreeMap<String, BTreeMap<String, AclRole>>,
+}
+
+impl RoleMapping {
+    /// Folder roles for a member holding `member_roles`. Grants add up: each
+    /// folder key gets the highest role any of the member's roles maps it to.
+    pub fn folder_roles(&self, member_roles: &[String]) -> BTreeMap<String, AclRole> {
+        let mut folders: BTreeMap<String, AclRole> = BTreeMap::new();
+        for grants in member_roles.iter().filter_map(|id| self.roles.get(id)) {
+            for (folder, role) in grants {
+                let entry = folders.entry(folder.clone()).or_insert(*role);
+                *entry = (*entry).max(*role);
+            }
+        }
+        folders
+    }
+}
+
+/// Read the role mapping from the store; empty when none is saved.
+pub async fn load_role_mapping(store: &dyn Store) -> Result<RoleMapping> {
+    let Some(data) = store.get(ROLE_MAPPING_KEY).await? else {
+        return Ok(RoleMapping::default());
+    };
+    serde_json::from_slice(&data).with_context(|| format!("Invalid {}", ROLE_MAPPING_KEY))
+}
+
+/// A guild member as Discord reports them.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DiscordIdentity {
+    pub id: String,
+    pub username: String,
+    pub global_name: Option<String>,
+    /// Guild role IDs, including the guild ID for `@everyone`.
+    #[serde(skip)]
+    pub roles: Vec<String>,
+}
+
+impl DiscordIdentity {
+    /// User ID for tokens and ACL rules.
+    pub fn user_id(&self) -> String {
+        format!("{}{}", USER_PREFIX, self.id)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DiscordSession {
+    pub identity: DiscordIdentity,
+    /// Folder roles by folder name, UUID or `*`, fixed at login.
+    pub folder_roles: BTreeMap<String, AclRole>,
+    pub expires_at: Instant,
+}
+
+impl DiscordSession {
+    /// The role in the folder known by `folder_keys` (its name and UUID),
+    /// counting `*` grants; None when the member has none.
+    pub fn role_for_folder(&self, folder_keys: &[&str]) -> Option<AclRole> {
+        folder_keys
+            .iter()
+            .chain(std::iter::once(&ALL_FOLDERS))
+            .filter_map(|key| self.folder_roles.get(*key))
+            .max()
+            .copied()
+            .filter(|role| *role != AclRole::None)
+    }
+}
+
+type HmacSha256 = Hmac<Sha256>;
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// A login started by `DiscordAuth::authorize_url`.
+#[derive(Debug, Clone)]
+pub struct PendingLogin {
+    /// Discord's consent page.
+    pub url: String,
+    /// To be set in the browser's `STATE_COOKIE`.
+    pub state: String,
+}
+
+#[derive(Debug)]
+pub enum LoginError {
+    /// The callback's `state` was not signed by this relay, has expired, or
+    /// does not match the browser's state cookie.
+    InvalidState,
+    /// Discord refused the code (expired, reused, wrong redirect).
+    Rejected(String),
+    /// The user is not in the configured guild.
+    NotMember,
+    /// Discord could not be reached or answered nonsense.
+    Upstream(anyhow::Error),
+}
+
+impl fmt::Display for LoginError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoginError::InvalidState => write!(f, "Unknown or expired login state"),
+            LoginError::Rejected(reason) => write!(f, "Discord rejected the login: {}", reason),
+            LoginError::NotMember => write!(f, "Not a member of the configured Discord server"),
+            LoginError::Upstream(e) => write!(f, "Discord request failed: {:#}", e),
+        }
+    }
+}
+
+impl std::error::Error for LoginError {}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+}
+
+#[derive(Deserialize)]
+struct MemberResponse {
+    user: MemberUser,
+    #[serde(default)]
+    roles: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct MemberUser {
+    id: String,
+    username: String,
+    #[serde(default)]
+    global_name: Option<String>,
+}
+
+pub struct DiscordAuth {
+    config: DiscordAuthConfig,
+    http: reqwest::Client,
+    /// Signs OAuth `state` values. Random per process, so a restart voids
+    /// logins in progress.
+    state_key: [u8; 32],
+    sessions: DashMap<String, DiscordSession>,
+}
+
+impl DiscordAuth {
+    pub fn new(config: DiscordAuthConfig) -> Self {
+        Self {
+            config,
+            http: reqwest::Client::builder()
+                .timeout(REQUEST_TIMEOUT)
+                .build()
+                .expect("static reqwest client"),
+            state_key: rand::random(),
+            sessions: DashMap::new(),
+        }
+    }
+
+    pub fn config(&self) -> &DiscordAuthConfig {
+        &self.config
+    }
+
+    fn state_mac(&self, nonce: &str, expires: u64) -> HmacSha256 {
+        let mut mac =
+            HmacSha256::new_from_slice(&self.state_key).expect("HMAC accepts any key length");
+        mac.update(format!("{}.{}", nonce, expires).as_bytes());
+        mac
+    }
+
+    /// A `state` of `{nonce}.{expires}.{sig}`, valid until `expires` (unix
+    /// seconds).
+    fn sign_state(&self, nonce: &str, expires: u64) -> String {
+        let sig = self.state_mac(nonce, expires).finalize().into_bytes();
+        format!(
+            "{}.{}.{}",
+            nonce,
+            expires,
+            hex::encode(&sig[..STATE_SIG_LEN])
+        )
+    }
+
+    /// Whether `state` was signed by `sign_state` and has not expired.
+    fn verify_state(&self, state: &str) -> bool {
+        let mut parts = state.splitn(3, '.');
+        let (Some(nonce), Some(expires), Some(sig)) = (parts.next(), parts.next(), parts.next())
+        else {
+            return false;
+        };
+        let (Ok(expires), Ok(sig)) = (expires.parse::<u64>(), hex::decode(sig)) else {
+            return false;
+        };
+        sig.len() == STATE_SIG_LEN
+            && self
+                .state_mac(nonce, expires)
+                .verify_truncated_left(&sig)
+                .is_ok()
+            && unix_now() < expires
+    }
+
+    /// Discord's consent page, with a fresh signed `state`.
+    pub fn authorize_url(&self) -> Result<PendingLogin, LoginError> {
+        let mut url = Url::parse(&self.config.authorize_url)
+            .with_context(|| format!("Invalid authorize URL {}", self.config.authorize_url))
+            .map_err(LoginError::Upstream)?;
+        let state = self.sign_state(&nanoid::nanoid!(22), unix_now() + STATE_TTL.as_secs());
+        url.query_pairs_mut()
+            .append_pair("client_id", &self.config.client_id)
+            .append_pair("redirect_uri", &self.config.redirect_uri)
+            .append_pair("response_type", "code")
+            .append_pair("scope", SCOPES)
+            .append_pair("state", &state);
+        Ok(PendingLogin {
+            url: url.to_string(),
+            state,
+        })
+    }
+
+    /// Finish a login: check that `state` is one this relay signed and the
+    /// one in the browser's cookie (`browser_state`), exchange `code` and
+    /// open a session with the folder roles `mapping` gives the member.
+    /// Returns the session ID. Replaying a callback is stopped by Discord,
+    /// which accepts each code once.
+    pub async fn login(
+        &self,
+        state: &str,
+        browser_state: Option<&str>,
+        code: &str,
+        mapping: &RoleMapping,
+    ) -> Result<(String, DiscordSession), LoginError> {
+        // Without the cookie, a callback URL from someone else's login would
+        // sign this browser in as them.
+        if browser_state != Some(state) || !self.verify_state(state) {
+            return Err(LoginError::InvalidState);
+        }
+        let identity = self.exchange_code(code).await?;
+        let session = DiscordSession {
+            folder_roles: mapping.folder_roles(&identity.roles),
+            identity,
+            expires_at: Instant::now() + self.config.session_ttl,
+        };
+        let now = Instant::now();
+        self.sessions.retain(|_, session| session.expires_at > now);
+        let session_id = nanoid::nanoid!(43);
+        self.sessions.insert(session_id.clone(), session.clone());
+        Ok((session_id, session))
+    }
+
+    /// Trade an authorization code for the member's identity and roles.
+    pub async fn exchange_code(&self, code: &str) -> Result<DiscordIdentity, LoginError> {
+        let api = self.config.api_url.trim_end_matches('/');
+        let response = self
+            .http
+            .post(format!("{}/oauth2/token", api))
+            .form(&[
+                ("grant_type", "authorization_code"),
+                ("code", code),
+                ("redirect_uri", self.config.redirect_uri.as_str()),
+                ("client_id", self.config.client_id.as_str()),
+                ("client_secret", self.config.client_secret.as_str()),
+            ])
+            .send()
+            .await
+            .map_err(|e| LoginError::Upstream(e.into()))?;
+        if response.status().is_client_error() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(LoginError::Rejected(body));
+        }
+        let token: TokenResponse = response
+            .error_for_status()
+            .map_err(|e| LoginError::Upstream(e.into()))?
+            .json()
+            .await
+            .map_err(|e| LoginError::Upstream(e.into()))?;
+
+        let response = self
+            .http
+            .get(format!(
+                "{}/users/@me/guilds/{}/member",
+                api, self.config.guild_id
+            ))
+            .bearer_auth(&token.access_token)
+            .send()
+            .await
+            .map_err(|e| LoginError::Upstream(e.into()))?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Err(LoginError::NotMember);
+        }
+        let member: MemberResponse = response
+            .error_for_status()
+            .map_err(|e| LoginError::Upstream(e.into()))?
+            .json()
+            .await
+            .map_err(|e| LoginError::Upstream(e.into()))?;
+
+        let mut roles = member.roles;
+        roles.push(self.config.guild_id.clone());
+        Ok(DiscordIdentity {
+            id: member.user.id,
+            username: member.user.username,
+            global_name: member.user.global_name,
+            roles,
+        })
+    }
+
+    /// Where to send the browser once `session_id` is open. The session
+    /// rides in the fragment, which browsers never send to a server.
+    pub fn app_redirect(&self, session_id: &str) -> Result<String> {
+        let mut url = Url::parse(&self.config.app_url)
+            .with_context(|| format!("Invalid app URL {}", self.config.app_url))?;
+        let fragment = url::form_urlencoded::Serializer::new(String::new())
+            .append_pair("session", session_id)
+            .append_pair("expires_in", &self.config.session_ttl.as_secs().to_string())
+            .finish();
+        url.set_fragment(Some(&fragment));
+        Ok(url.to_string())
+    }
+
+    /// The live session with this ID.
+    pub fn session(&self, session_id: &str) -> Option<DiscordSession> {
+        let session = self.sessions.get(session_id)?.clone();
+        if session.expires_at <= Instant::now() {
+            self.sessions.remove(session_id);
+            return None;
+        }
+        Some(session)
+    }
+
+    pub fn logout(&self, session_id: &str) -> bool {
+        self.sessions.remove(session_id).is_some()
+    }
+}
+
+/// A Discord stand-in for tests here and in `server`.
+#[cfg(test)]
+pub(crate) mod test_support {
+    use super::*;
+    use axum::extract::{Form, Path};
+    use axum::http::{HeaderMap, StatusCode};
+    use axum::routing::{get, post};
+    use axum::{Json, Router};
+    use serde_json::{json, Value};
+    use std::collections::HashMap;
+
+    pub const GUILD: &str = "900";
+    pub const APP_URL: &str = "http://app.test/login";
+
+    /// Serve a mock provider that accepts code "good" and knows one member,
+    /// `42` with role `r-ta`. Returns its base URL.
+    pub async fn mock_discord() -> String {
+        let app = Router::new()
+            .route(
+                "/oauth2/token",
+                post(|Form(form): Form<HashMap<String, String>>| async move {
+                    if form.get("code").map(String::as_str) != Some("good")
+                        || form.get("client_secret").map(String::as_str) != Some("secret")
+                    {
+                        return (
+                            StatusCode::BAD_REQUEST,
+                            Json(json!({"error": "invalid_grant"})),
+                        );
+                    }
+                    (
+                        StatusCode::OK,
+                        Json(json!({"access_token": "at-1", "token_type": "Bearer"})),
+                    )
+                }),
+            )
+            .route(
+                "/users/@me/guilds/:guild/member",
+                get(|Path(guild): Path<String>, headers: HeaderMap| async move {
+                    let auth = headers.get("authorization").and_then(|v| v.to_str().ok());
+                    if guild != GUILD || auth != Some("Bearer at-1") {
+                        return (StatusCode::NOT_FOUND, Json(Value::Null));
+                    }
+                    (
+                        StatusCode::OK,
+                        Json(json!({
+                            "user": {"id": "42", "username": "ada", "global_name": "Ada"},
+                            "roles": ["r-ta"]
+                        })),
+                    )
+                }),
+            );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+        format!("http://{}", addr)
+    }
+
+    pub fn config(api_url: String, guild_id: &str) -> DiscordAuthConfig {
+        DiscordAuthConfig {
+            client_id: "client".into(),
+            client_secret: "secret".into(),
+            redirect_uri: "http://relay.test/auth/discord/callback".into(),
+            guild_id: guild_id.into(),
+            app_url: APP_URL.into(),
+            api_url,
+            authorize_url: DEFAULT_AUTHORIZE_URL.into(),
+            session_ttl: Duration::from_secs(3600),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::test_support::{config, mock_discord, APP_URL, GUILD};
+    use super::*;
+    use serde_json::json;
+
+    fn auth(api_url: String, guild_id: &str) -> DiscordAuth {
+        DiscordAuth::new(config(api_url, guild_id))
+    }
+
+    fn state_of(url: &str) -> String {
+        Url::parse(url)
+            .unwrap()
+            .query_pairs()
+            .find(|(key, _)| key == "state")
+            .unwrap()
+            .1
+            .into_owned()
+    }
+
+    fn mapping() -> RoleMapping {
+        serde_json::from_value(json!({"roles": {
+            "r-ta": {"Lens Edu": "edit"},
+            GUILD: {"*": "view", "Lens Edu": "suggest"}
+        }}))
+        .unwrap()
+    }
+
+    #[test]
+    fn grants_add_up_across_roles() {
+        let roles = mapping().folder_roles(&["r-ta".into(), GUILD.into()]);
+        assert_eq!(roles["Lens Edu"], AclRole::Edit);
+        assert_eq!(roles["*"], AclRole::View);
+        let roles = mapping().folder_roles(&[GUILD.into()]);
+        assert_eq!(roles["Lens Edu"], AclRole::Suggest);
+    }
+
+    #[tokio::test]
+    async fn login_against_mock_provider() {
+        let auth = auth(mock_discord().await, GUILD);
+        let pending = auth.authorize_url().unwrap();
+        assert!(
+            pending.url.contains("scope=identify+guilds.members.read"),
+            "{}",
+            pending.url
+        );
+        let state = state_of(&pending.url);
+        assert_eq!(state, pending.state);
+
+        let (session_id, session) = auth
+            .login(&state, Some(&state), "good", &mapping())
+            .await
+            .unwrap();
+        assert_eq!(session.identity.user_id(), "discord:42");
+        assert_eq!(session.identity.global_name.as_deref(), Some("Ada"));
+        assert_eq!(
+            session.role_for_folder(&["Lens Edu", "uuid-1"]),
+            Some(AclRole::Edit)
+        );
+        assert_eq!(session.role_for_folder(&["Lens"]), Some(AclRole::View));
+        assert!(auth.session(&session_id).is_some());
+        assert_eq!(
+            auth.app_redirect(&session_id).unwrap(),
+            format!("{}#session={}&expires_in=3600", APP_URL, session_id)
+        );
+
+        assert!(auth.logout(&session_id));
+        assert!(auth.session(&session_id).is_none());
+    }
+
+    #[tokio::test]
+    async fn login_requires_the_browser_that_started_it() {
+        let auth = auth(mock_discord().await, GUILD);
+        let state = auth.authorize_url().unwrap().state;
+        let other = auth.authorize_url().unwrap().state;
+        for browser_state in [None, Some(other.as_str())] {
+            assert!(matches!(
+                auth.login(&state, browser_state, "good", &mapping()).await,
+                Err(LoginError::InvalidState)
+            ));
+        }
+        // A mismatch does not burn the state for the right browser.
+        assert!(auth
+            .login(&state, Some(&state), "good", &mapping())
+            .await
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn login_requires_a_state_this_relay_signed() {
+        let auth = auth(mock_discord().await, GUILD);
+        let state = auth.authorize_url().unwrap().state;
+        let (nonce, rest) = state.split_once('.').unwrap();
+        let (expires, _) = rest.split_once('.').unwrap();
+        let later: u64 = expires.parse::<u64>().unwrap() + 3600;
+        let forged = [
+            format!("{}.{}.{}", nonce, later, state.rsplit('.').next().unwrap()),
+            auth.sign_state(nonce, unix_now() - 1),
+            DiscordAuth::new(config("http://discord.test".into(), GUILD)).sign_state(nonce, later),
+            "not-a-state".to_string(),
+        ];
+        for state in forged {
+            assert!(matches!(
+                auth.login(&state, Some(&state), "good", &mapping()).await,
+                Err(LoginError::InvalidState)
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn a_flood_of_logins_does_not_lock_anyone_out() {
+        let auth = auth(mock_discord().await, GUILD);
+        let state = auth.authorize_url().unwrap().state;
+        for _ in 0..10_000 {
+            auth.authorize_url().unwrap();
+        }
+        let late = auth.authorize_url().unwrap().state;
+        for state in [state, late] {
+            assert!(auth
+                .login(&state, Some(&state), "good", &mapping())
+                .await
+                .is_ok());
+        }
+    }
+
+    #[tokio::test]
+    async fn login_rejects_bad_code_and_non_members() {
+        let api_url = mock_discord().await;
+        let auth_in_guild = auth(api_url.clone(), GUILD);
+        let state = auth_in_guild.authorize_url().unwrap().state;
+        assert!(matches!(
+            auth_in_guild
+                .login(&state, Some(&state), "stale", &mapping())
+                .await,
+            Err(LoginError::Rejected(_))
+        ));
+
+        let auth_elsewhere = auth(api_url, "901");
+        let state = auth_elsewhere.authorize_url().unwrap().state;
+        assert!(matches!(
+            auth_elsewhere
+                .login(&state, Some(&state), "good", &mapping())
+                .await,
+            Err(LoginError::NotMember)
+        ));
+    }
+}
@@ -0,0 +1,353 @@
+//! Export a folder as plain files: every markdown doc and blob in its
+//! `filemeta_v0`, at the same paths. Used by `relay export` (a directory
+//! tree, read straight from a store) and `GET /folder/:folder_uuid/export`
+//! (a tar archive streamed by the server).
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use serde::Deserialize;
+use std::path::Path;
+use y_sweet_core::{
+    doc_sync::doc_from_snapshot,
+    fsck::normalize_path,
+    link_indexer::{
+        extract_hash_from_filemeta_entry, extract_id_from_filemeta_entry,
+        extract_type_from_filemeta_entry, parse_doc_id,
+    },
+    store::Store,
+};
+use yrs::{Doc, GetString, Map, ReadTxn, Transact};
+
+use crate::mcp::tools::critic_markup;
+
+/// Which text of a markdown doc to export.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportView {
+    /// The text as stored, CriticMarkup included.
+    #[default]
+    Raw,
+    /// Pending suggestions rejected.
+    Base,
+    /// Pending suggestions accepted.
+    Accepted,
+}
+
+impl ExportView {
+    pub fn render(&self, raw: &str) -> String {
+        match self {
+            ExportView::Raw => raw.to_string(),
+            ExportView::Base => critic_markup::base_view(&critic_markup::parse(raw)),
+            ExportView::Accepted => critic_markup::accepted_view(&critic_markup::parse(raw)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExportItemKind {
+    Folder,
+    /// A Y.Doc whose `contents` text is the file; `markdown` docs get the
+    /// export view applied.
+    Text {
+        markdown: bool,
+    },
+    /// A blob at `files/{doc_id}/{hash}`.
+    Blob {
+        hash: String,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExportItem {
+    /// Path relative to the export root, without a leading slash.
+    pub path: String,
+    pub doc_id: String,
+    pub kind: ExportItemKind,
+}
+
+/// What exporting `folder_doc` writes, sorted by path so folders come before
+/// their contents. Entries without an id or with a path that has no safe
+/// spelling (see `fsck::normalize_path`) are skipped.
+pub fn plan_folder_export(folder_doc: &Doc, folder_doc_id: &str) -> Vec<ExportItem> {
+    let relay_id = parse_doc_id(folder_doc_id)
+        .map(|(relay_id, _)| relay_id)
+        .unwrap_or_default();
+    let txn = folder_doc.transact();
+    let Some(filemeta) = txn.get_map("filemeta_v0") else {
+        return Vec::new();
+    };
+    let mut items: Vec<ExportItem> = filemeta
+        .iter(&txn)
+        .filter_map(|(path, value)| {
+            let Some(normalized) = normalize_path(path) else {
+                tracing::warn!(path, "Skipping malformed filemeta path in export");
+                return None;
+            };
+            let id = extract_id_from_filemeta_entry(&value, &txn)?;
+            let entry_type = extract_type_from_filemeta_entry(&value, &txn);
+            let kind = match (
+                entry_type.as_deref(),
+                extract_hash_from_filemeta_entry(&value, &txn),
+            ) {
+                (Some("folder"), _) => ExportItemKind::Folder,
+                (_, Some(hash)) => ExportItemKind::Blob { hash },
+                (entry_type, None) => ExportItemKind::Text {
+                    markdown: entry_type == Some("markdown"),
+                },
+            };
+            Some(ExportItem {
+                path: normalized.trim_start_matches('/').to_string(),
+                doc_id: if relay_id.is_empty() {
+                    id
+                } else {
+                    format!("{}-{}", relay_id, id)
+                },
+                kind,
+            })
+        })
+        .collect();
+    items.sort_by(|a, b| a.path.cmp(&b.path));
+    items
+}
+
+/// Where exported docs and blobs are read from.
+#[async_trait]
+pub trait ExportSource: Send + Sync {
+    /// The `contents` text of a doc, or None when there is no such doc.
+    async fn doc_text(&self, doc_id: &str) -> Result<Option<String>>;
+    async fn blob(&self, doc_id: &str, hash: &str) -> Result<Option<Vec<u8>>>;
+}
+
+/// Reads persisted docs and blobs straight from a store.
+pub struct StoreExportSource<'a>(pub &'a dyn Store);
+
+#[async_trait]
+impl ExportSource for StoreExportSource<'_> {
+    async fn doc_text(&self, doc_id: &str) -> Result<Option<String>> {
+        let Some(snapshot) = self.0.get(&format!("{}/data.ysweet", doc_id)).await? else {
+            return Ok(None);
+        };
+        let doc = doc_from_snapshot(&snapshot)?;
+        let txn = doc.transact();
+        Ok(Some(
+            txn.get_text("contents")
+                .map(|text| text.get_string(&txn))
+                .unwrap_or_default(),
+        ))
+    }
+
+    async fn blob(&self, doc_id: &str, hash: &str) -> Result<Option<Vec<u8>>> {
+        Ok(self.0.get(&format!("files/{}/{}", doc_id, hash)).await?)
+    }
+}
+
+/// The bytes of one exported file. None for folders and for items missing
+/// from the source, which `relay fsck` reports as dangling.
+pub async fn read_item(
+    source: &dyn ExportSource,
+    item: &ExportItem,
+    view: ExportView,
+) -> Result<Option<Vec<u8>>> {
+    let data = match &item.kind {
+        ExportItemKind::Folder => None,
+        ExportItemKind::Text { markdown } => source.doc_text(&item.doc_id).await?.map(|text| {
+            if *markdown {
+                view.render(&text).into_bytes()
+            } else {
+                text.into_bytes()
+            }
+        }),
+        ExportItemKind::Blob { hash } => source.blob(&item.doc_id, hash).await?,
+    };
+    if data.is_none() && item.kind != ExportItemKind::Folder {
+        tracing::warn!(path = %item.path, doc_id = %item.doc_id, "Skipping missing export item");
+    }
+    Ok(data)
+}
+
+/// Write the export under `root`, creating directories as needed. Returns
+/// the number of files written.
+pub async fn export_to_dir(
+    source: &dyn ExportSource,
+    items: &[ExportItem],
+    view: ExportView,
+    root: &Path,
+) -> Result<usize> {
+    let mut written = 0;
+    for item in items {
+        let path = root.join(&item.path);
+        if item.kind == ExportItemKind::Folder {
+            std::fs::create_dir_all(&path)
+                .with_context(|| format!("Failed to create {}", path.display()))?;
+            continue;
+        }
+        let Some(data) = read_item(source, item, view).await? else {
+            continue;
+        };
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create {}", parent.display()))?;
+        }
+        std::fs::write(&path, data)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        written += 1;
+    }
+    Ok(written)
+}
+
+/// Append one export item to a tar archive. `data` is None for folders.
+pub fn append_tar_entry<W: std::io::Write>(
+    builder: &mut tar::Builder<W>,
+    item: &ExportItem,
+    data: Option<&[u8]>,
+    mtime: u64,
+) -> std::io::Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_mtime(mtime);
+    match data {
+        None => {
+            header.set_entry_type(tar::EntryType::Directory);
+            header.set_mode(0o755);
+            header.set_size(0);
+            builder.append_data(&mut header, format!("{}/", item.path), std::io::empty())
+        }
+        Some(data) => {
+            header.set_entry_type(tar::EntryType::Regular);
+            header.set_mode(0o644);
+            header.set_size(data.len() as u64);
+            builder.append_data(&mut header, &item.path, data)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stores::filesystem::FileSystemStore;
+    use tempfile::TempDir;
+    use yrs::{Any, StateVector, Text, WriteTxn};
+
+    const RELAY_ID: &str = "cb696037-0f72-4e93-8717-4e433129d789";
+
+    async fn put_doc(store: &TempDir, doc_id: &str, fill: impl FnOnce(&mut yrs::TransactionMut)) {
+        let doc = Doc::new();
+        fill(&mut doc.transact_mut());
+        let update = doc
+            .transact()
+            .encode_state_as_update_v1(&StateVector::default());
+        let store = FileSystemStore::new(store.path().to_path_buf()).unwrap();
+        crate::convert::convert(Box::new(store), &update, doc_id)
+            .await
+            .unwrap();
+    }
+
+    fn entry(id: &str, entry_type: &str, hash: Option<&str>) -> Any {
+        let mut meta = std::collections::HashMap::new();
+        meta.insert("id".to_string(), Any::from(id));
+        meta.insert("type".to_string(), Any::from(entry_type));
+        if let Some(hash) = hash {
+            meta.insert("hash".to_string(), Any::from(hash));
+        }
+        Any::Map(meta.into())
+    }
+
+    #[tokio::test]
+    async fn exports_docs_and_blobs_at_their_paths() {
+        let store_dir = TempDir::new().unwrap();
+        let folder_doc_id = format!("{}-b0000001-0000-4000-8000-000000000001", RELAY_ID);
+        put_doc(&store_dir, &folder_doc_id, |txn| {
+            let filemeta = txn.get_or_insert_map("filemeta_v0");
+            filemeta.insert(txn, "/Notes", entry("d1", "folder", None));
+            filemeta.insert(txn, "/Notes/a.md", entry("c1", "markdown", None));
+            filemeta.insert(txn, "/img.png", entry("b1", "image", Some("abc123")));
+            filemeta.insert(txn, "//bad.md", entry("c9", "markdown", None));
+            filemeta.insert(txn, "/Empty", entry("d2", "folder", None));
+        })
+        .await;
+        put_doc(&store_dir, &format!("{}-c1", RELAY_ID), |txn| {
+            txn.get_or_insert_text("contents")
+                .insert(txn, 0, "Hello {--old--}{++new++} world\n");
+        })
+        .await;
+        let store = FileSystemStore::new(store_dir.path().to_path_buf()).unwrap();
+        store
+            .set(&format!("files/{}-b1/abc123", RELAY_ID), vec![1, 2, 3])
+            .await
+            .unwrap();
+
+        let folder = y_sweet_core::doc_sync::doc_from_snapshot(
+            &store
+                .get(&format!("{}/data.ysweet", folder_doc_id))
+                .await
+                .unwrap()
+                .unwrap(),
+        )
+        .unwrap();
+        let items = plan_folder_export(&folder, &folder_doc_id);
+        assert_eq!(
+            items.iter().map(|i| i.path.as_str()).collect::<Vec<_>>(),
+            vec!["Empty", "Notes", "Notes/a.md", "bad.md", "img.png"]
+        );
+
+        let source = StoreExportSource(&store);
+        for (view, expected) in [
+            (ExportView::Raw, "Hello {--old--}{++new++} world\n"),
+            (ExportView::Base, "Hello old world\n"),
+            (ExportView::Accepted, "Hello new world\n"),
+        ] {
+            let out = TempDir::new().unwrap();
+            // bad.md's doc does not exist, so it is skipped.
+            let written = export_to_dir(&source, &items, view, out.path())
+                .await
+                .unwrap();
+            assert_eq!(written, 2);
+            assert_eq!(
+                std::fs::read_to_string(out.path().join("Notes/a.md")).unwrap(),
+                expected
+            );
+            assert_eq!(
+                std::fs::read(out.path().join("img.png")).unwrap(),
+                vec![1, 2, 3]
+            );
+            assert!(out.path().join("Empty").is_dir());
+        }
+    }
+
+    #[test]
+    fn tar_entries_round_trip() {
+        let mut builder = tar::Builder::new(Vec::new());
+        let folder = ExportItem {
+            path: "Notes".into(),
+            doc_id: "d1".into(),
+            kind: ExportItemKind::Folder,
+        };
+        let file = ExportItem {
+            path: "Notes/a.md".into(),
+            doc_id: "c1".into(),
+            kind: ExportItemKind::Text { markdown: true },
+        };
+        append_tar_entry(&mut builder, &folder, None, 0).unwrap();
+        append_tar_entry(&mut builder, &file, Some(b"# A\n"), 0).unwrap();
+        let bytes = builder.into_inner().unwrap();
+
+        let mut archive = tar::Archive::new(bytes.as_slice());
+        let entries: Vec<(String, Vec<u8>)> = archive
+            .entries()
+            .unwrap()
+            .map(|entry| {
+                let mut entry = entry.unwrap();
+                let path = entry.path().unwrap().to_string_lossy().into_owned();
+                let mut data = Vec::new();
+                std::io::Read::read_to_end(&mut entry, &mut data).unwrap();
+                (path, data)
+            })
+            .collect();
+        assert_eq!(
+            entries,
+            vec![
+                ("Notes/".to_string(), vec![]),
+                ("Notes/a.md".to_string(), b"# A\n".to_vec())
+            ]
+        );
+    }
+}
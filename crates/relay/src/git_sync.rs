@@ -0,0 +1,905 @@
+//! Native git sync: mirror selected folders into a local git repository and
+//! bring commits pushed to its remote back into the docs. Replaces the
+//! external git-sync container.
+//!
+//! Each synced folder is a directory of the repo named after the folder,
+//! laid out like `relay export` with the raw view (CriticMarkup included,
+//! so nothing is lost on the way back). A sync pass:
+//!
+//! 1. exports the folders and commits whatever changed, authored by the
+//!    users whose client IDs wrote the changes according to each doc's
+//!    `users` PermanentUserData map;
+//! 2. fetches the remote branch and merges it, the relay side winning
+//!    conflicting hunks (`-X ours`);
+//! 3. applies every markdown file the merge changed to its doc as a text
+//!    diff, so it arrives as an ordinary CRDT edit, and creates docs for new
+//!    markdown files;
+//! 4. pushes.
+//!
+//! Passes run once edits have settled for the debounce period, and at the
+//! pull interval when a remote is configured. The relay owns the mirror's
+//! working tree: commit to the remote, not to the mirror.
+
+use crate::folder_export::{self, ExportItemKind, ExportSource, ExportView};
+use crate::server::Server;
+use anyhow::{anyhow, bail, Context, Result};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
+use yrs::{Any, Array, Doc, Map, Out, ReadTxn, StateVector, Transact};
+
+/// How often the worker checks the synced folders for changes.
+const GIT_SYNC_TICK: Duration = Duration::from_secs(2);
+
+/// Files listed in a commit message body before it is cut short.
+const COMMIT_MESSAGE_MAX_PATHS: usize = 20;
+
+#[derive(Debug, Clone)]
+pub struct GitSyncConfig {
+    /// Working tree of the mirror; created (`git init`) if missing.
+    pub repo_path: PathBuf,
+    /// Display names of the folders to mirror.
+    pub folders: Vec<String>,
+    /// Remote to pull from and push to (`origin`). Without one the mirror
+    /// is write-only.
+    pub remote: Option<String>,
+    pub branch: String,
+    /// Quiet period after the last edit before committing.
+    pub debounce: Duration,
+    /// How often to pull from the remote when nothing changed locally.
+    pub pull_interval: Duration,
+    /// Commit emails are `{user}@{email_domain}`.
+    pub email_domain: String,
+}
+
+impl GitSyncConfig {
+    /// Read `RELAY_GIT_SYNC_DIR` and `RELAY_GIT_SYNC_FOLDERS` (comma
+    /// separated folder names), both required to enable sync, plus the
+    /// optional `RELAY_GIT_SYNC_REMOTE`, `RELAY_GIT_SYNC_BRANCH` (default
+    /// `main`), `RELAY_GIT_SYNC_DEBOUNCE_SECONDS` (default 30),
+    /// `RELAY_GIT_SYNC_PULL_SECONDS` (default 60) and
+    /// `RELAY_GIT_SYNC_EMAIL_DOMAIN` (default `relay.invalid`). Invalid
+    /// numbers are logged and ignored.
+    pub fn from_env() -> Option<Self> {
+        let repo_path = std::env::var("RELAY_GIT_SYNC_DIR").ok()?;
+        let folders: Vec<String> = std::env::var("RELAY_GIT_SYNC_FOLDERS")
+            .unwrap_or_default()
+            .split(',')
+            .map(|name| name.trim().to_string())
+            .filter(|name| !name.is_empty())
+            .collect();
+        if folders.is_empty() {
+            tracing::warn!(
+                "RELAY_GIT_SYNC_DIR is set but RELAY_GIT_SYNC_FOLDERS is empty; git sync disabled"
+            );
+            return None;
+        }
+        let seconds = |var: &str, default: u64| match std::env::var(var) {
+            Ok(value) => value.trim().parse::<u64>().unwrap_or_else(|e| {
+                tracing::warn!("Ignoring {}={}: {}", var, value, e);
+                default
+            }),
+            Err(_) => default,
+        };
+        Some(Self {
+            repo_path: PathBuf::from(repo_path),
+            folders,
+            remote: std::env::var("RELAY_GIT_SYNC_REMOTE")
+                .ok()
+                .filter(|remote| !remote.trim().is_empty()),
+            branch: std::env::var("RELAY_GIT_SYNC_BRANCH").unwrap_or_else(|_| "main".into()),
+            debounce: Duration::from_secs(seconds("RELAY_GIT_SYNC_DEBOUNCE_SECONDS", 30)),
+            pull_interval: Duration::from_secs(seconds("RELAY_GIT_SYNC_PULL_SECONDS", 60)),
+            email_domain: std::env::var("RELAY_GIT_SYNC_EMAIL_DOMAIN")
+                .unwrap_or_else(|_| "relay.invalid".into()),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct GitAuthor {
+    pub name: String,
+    pub email: String,
+}
+
+impl GitAuthor {
+    /// Author for a `users` map entry. The email is made up from the name,
+    /// since the map only carries display names.
+    pub fn for_user(name: &str, email_domain: &str) -> Self {
+        let local: String = name
+            .to_lowercase()
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+            .collect();
+        let local = local.trim_matches('-');
+        let local = if local.is_empty() { "user" } else { local };
+        Self {
+            name: name.to_string(),
+            email: format!("{}@{}", local, email_domain),
+        }
+    }
+
+    fn relay(email_domain: &str) -> Self {
+        Self {
+            name: "Relay".to_string(),
+            email: format!("relay@{}", email_domain),
+        }
+    }
+
+    fn to_git(&self) -> String {
+        format!("{} <{}>", self.name, self.email)
+    }
+}
+
+/// Names in `doc`'s `users` map whose client IDs wrote anything after
+/// `since`. Edits by clients that are not in the map (the server's own
+/// writes, for one) are not attributed to anyone.
+pub fn doc_authors(doc: &Doc, since: &StateVector) -> Vec<String> {
+    let txn = doc.transact();
+    let changed: HashSet<u64> = txn
+        .state_vector()
+        .iter()
+        .filter(|(client, clock)| since.get(client) < **clock)
+        .map(|(client, _)| *client)
+        .collect();
+    if changed.is_empty() {
+        return Vec::new();
+    }
+    let Some(users) = txn.get_map("users") else {
+        return Vec::new();
+    };
+    let mut names: Vec<String> = users
+        .iter(&txn)
+        .filter_map(|(name, user)| {
+            let Out::YMap(user) = user else {
+                return None;
+            };
+            let Some(Out::YArray(ids)) = user.get(&txn, "ids") else {
+                return None;
+            };
+            let wrote = ids.iter(&txn).any(|id| match id {
+                Out::Any(Any::Number(n)) => changed.contains(&(n as u64)),
+                Out::Any(Any::BigInt(n)) => changed.contains(&(n as u64)),
+                _ => false,
+            });
+            wrote.then(|| name.to_string())
+        })
+        .collect();
+    names.sort();
+    names
+}
+
+/// Commit message for a sync commit: a summary line, the changed paths and
+/// a `Co-authored-by` trailer for every author after the first.
+pub fn commit_message(paths: &[String], co_authors: &[GitAuthor]) -> String {
+    let mut message = match paths {
+        [path] => format!("Update {}", path),
+        _ => format!("Update {} files", paths.len()),
+    };
+    if paths.len() > 1 {
+        message.push_str("\n\n");
+        for path in paths.iter().take(COMMIT_MESSAGE_MAX_PATHS) {
+            message.push_str(&format!("- {}\n", path));
+        }
+        if paths.len() > COMMIT_MESSAGE_MAX_PATHS {
+            message.push_str(&format!(
+                "- ... and {} more\n",
+                paths.len() - COMMIT_MESSAGE_MAX_PATHS
+            ));
+        }
+    }
+    if !co_authors.is_empty() {
+        message.push_str(if paths.len() > 1 { "\n" } else { "\n\n" });
+        for author in co_authors {
+            message.push_str(&format!("Co-authored-by: {}\n", author.to_git()));
+        }
+    }
+    message
+}
+
+/// What one sync pass did.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct GitSyncReport {
+    /// A commit was made from relay changes.
+    pub committed: bool,
+    /// Docs updated from the remote.
+    pub docs_updated: usize,
+    /// Docs created for markdown files new on the remote.
+    pub docs_created: usize,
+    pub pushed: bool,
+}
+
+/// Thin wrapper around the `git` CLI, run in the mirror's working tree.
+struct GitRepo {
+    dir: PathBuf,
+    identity: GitAuthor,
+}
+
+impl GitRepo {
+    async fn output(&self, args: &[&str]) -> Result<std::process::Output> {
+        tokio::process::Command::new("git")
+            .args(args)
+            .current_dir(&self.dir)
+            .env("GIT_TERMINAL_PROMPT", "0")
+            .env("GIT_AUTHOR_NAME", &self.identity.name)
+            .env("GIT_AUTHOR_EMAIL", &self.identity.email)
+            .env("GIT_COMMITTER_NAME", &self.identity.name)
+            .env("GIT_COMMITTER_EMAIL", &self.identity.email)
+            .output()
+            .await
+            .with_context(|| format!("Failed to run git {}", args.join(" ")))
+    }
+
+    async fn run(&self, args: &[&str]) -> Result<String> {
+        let output = self.output(args).await?;
+        if !output.status.success() {
+            bail!(
+                "git {} failed: {}",
+                args.join(" "),
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+
+    /// Whether `rev` names a commit; false on an unborn branch.
+    async fn has_rev(&self, rev: &str) -> Result<bool> {
+        Ok(self
+            .output(&[
+                "rev-parse",
+                "-q",
+                "--verify",
+                &format!("{}^{{commit}}", rev),
+            ])
+            .await?
+            .status
+            .success())
+    }
+
+    async fn head(&self) -> Result<Option<String>> {
+        if !self.has_rev("HEAD").await? {
+            return Ok(None);
+        }
+        Ok(Some(
+            self.run(&["rev-parse", "HEAD"]).await?.trim().to_string(),
+        ))
+    }
+
+    /// NUL-separated path lists (`-z`) split into paths.
+    fn paths(output: &str) -> Vec<String> {
+        output
+            .split('\0')
+            .filter(|path| !path.is_empty())
+            .map(str::to_string)
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone)]
+struct ExportedDoc {
+    doc_id: String,
+    text: String,
+}
+
+pub struct GitSync {
+    config: GitSyncConfig,
+    repo: GitRepo,
+    /// State vectors of the synced docs as of the last commit.
+    committed: HashMap<String, StateVector>,
+    /// State vectors seen at the last check, for debouncing.
+    seen: HashMap<String, StateVector>,
+    last_change: Option<Instant>,
+    last_pass: Option<Instant>,
+    /// Markdown as last written to the working tree, keyed by repo path.
+    exported: HashMap<String, ExportedDoc>,
+}
+
+impl GitSync {
+    /// Open the mirror at `config.repo_path`, initialising it (and checking
+    /// out the remote branch, if there is one) on first use.
+    pub async fn open(config: GitSyncConfig) -> Result<Self> {
+        for folder in &config.folders {
+            if folder.contains('/') || folder.starts_with('.') {
+                bail!(
+                    "Cannot mirror folder '{}': not a usable directory name",
+                    folder
+                );
+            }
+        }
+        std::fs::create_dir_all(&config.repo_path)
+            .with_context(|| format!("Failed to create {}", config.repo_path.display()))?;
+        let repo = GitRepo {
+            dir: config.repo_path.clone(),
+            identity: GitAuthor::relay(&config.email_domain),
+        };
+        if !config.repo_path.join(".git").exists() {
+            repo.run(&["init", "-q", "-b", &config.branch]).await?;
+        }
+        if let Some(remote) = &config.remote {
+            if repo
+                .output(&["remote", "get-url", "origin"])
+                .await?
+                .status
+                .success()
+            {
+                repo.run(&["remote", "set-url", "origin", remote]).await?;
+            } else {
+                repo.run(&["remote", "add", "origin", remote]).await?;
+            }
+            repo.run(&["fetch", "-q", "origin"]).await?;
+            let upstream = format!("origin/{}", config.branch);
+            if !repo.has_rev("HEAD").await? && repo.has_rev(&upstream).await? {
+                repo.run(&["reset", "-q", "--hard", &upstream]).await?;
+            }
+        }
+        Ok(Self {
+            config,
+            repo,
+            committed: HashMap::new(),
+            seen: HashMap::new(),
+            last_change: None,
+            last_pass: None,
+            exported: HashMap::new(),
+        })
+    }
+
+    /// Run passes until `cancellation_token` fires.
+    pub async fn run(mut self, server: Arc<Server>, cancellation_token: CancellationToken) {
+        loop {
+            tokio::select! {
+                _ = tokio::time::sleep(GIT_SYNC_TICK) => {}
+                _ = cancellation_token.cancelled() => {
+                    tracing::info!("Git sync shutting down");
+                    return;
+                }
+            }
+            if !self.pass_due(&server, Instant::now()) {
+                continue;
+            }
+            match self.sync(&server).await {
+                Ok(report) if report != GitSyncReport::default() => {
+                    tracing::info!(?report, "Git sync pass")
+                }
+                Ok(_) => {}
+                Err(e) => tracing::error!("Git sync pass failed: {:?}", e),
+            }
+        }
+    }
+
+    /// Whether a pass should run now: the synced docs changed since the last
+    /// commit and have been quiet for the debounce period, or a pull is due.
+    fn pass_due(&mut self, server: &Server, now: Instant) -> bool {
+        let current = self.state_vectors(server);
+        if current != self.seen {
+            self.seen = current;
+            self.last_change = Some(now);
+        }
+        let dirty = self
+            .seen
+            .iter()
+            .any(|(doc_id, sv)| self.committed.get(doc_id) != Some(sv));
+        let settled = match self.last_change {
+            Some(changed) => now.duration_since(changed) >= self.config.debounce,
+            None => true,
+        };
+        let pull_due = self.config.remote.is_some()
+            && match self.last_pass {
+                Some(pass) => now.duration_since(pass) >= self.config.pull_interval,
+                None => true,
+            };
+        (dirty && settled) || pull_due
+    }
+
+    /// Folder doc IDs of the synced folders that exist, with their names.
+    fn folder_doc_ids(&self, server: &Server) -> Vec<(String, String)> {
+        self.config
+            .folders
+            .iter()
+            .filter_map(|name| {
+                let folder_doc_id = server.folder_doc_id_for_name(name);
+                if folder_doc_id.is_none() {
+                    tracing::debug!(folder = %name, "Git sync folder not loaded");
+                }
+                Some((name.clone(), folder_doc_id?))
+            })
+            .collect()
+    }
+
+    fn plan(server: &Server, folder_doc_id: &str) -> Vec<folder_export::ExportItem> {
+        let Some(awareness) = server.docs().get(folder_doc_id).map(|d| d.awareness()) else {
+            return Vec::new();
+        };
+        let guard = awareness.read().unwrap_or_else(|e| e.into_inner());
+        folder_export::plan_folder_export(&guard.doc, folder_doc_id)
+    }
+
+    /// State vectors of the loaded folder and content docs being synced.
+    /// Docs that are not loaded cannot have changed since they were.
+    fn state_vectors(&self, server: &Server) -> HashMap<String, StateVector> {
+        let mut svs = HashMap::new();
+        for (_, folder_doc_id) in self.folder_doc_ids(server) {
+            let doc_ids = std::iter::once(folder_doc_id.clone()).chain(
+                Self::plan(server, &folder_doc_id)
+                    .into_iter()
+                    .filter(|item| matches!(item.kind, ExportItemKind::Text { .. }))
+                    .map(|item| item.doc_id),
+            );
+            for doc_id in doc_ids {
+                let Some(awareness) = server.docs().get(&doc_id).map(|d| d.awareness()) else {
+                    continue;
+                };
+                let guard = awareness.read().unwrap_or_else(|e| e.into_inner());
+                let sv = guard.doc.transact().state_vector();
+                svs.insert(doc_id, sv);
+            }
+        }
+        svs
+    }
+
+    /// Run one pass now, regardless of debouncing.
+    pub async fn sync(&mut self, server: &Server) -> Result<GitSyncReport> {
+        let mut report = GitSyncReport {
+            committed: self.commit_relay_changes(server).await?,
+            ..Default::default()
+        };
+        self.last_pass = Some(Instant::now());
+        if self.config.remote.is_none() {
+            return Ok(report);
+        }
+        let (updated, created) = self.pull(server).await?;
+        report.docs_updated = updated;
+        report.docs_created = created;
+        if self.repo.has_rev("HEAD").await? {
+            let upstream = format!("origin/{}", self.config.branch);
+            let ahead = !self.repo.has_rev(&upstream).await?
+                || !self
+                    .repo
+                    .run(&["rev-list", "--count", &format!("{}..HEAD", upstream)])
+                    .await?
+                    .trim()
+                    .starts_with('0');
+            if ahead {
+                self.repo
+                    .run(&[
+                        "push",
+                        "-q",
+                        "origin",
+                        &format!("HEAD:refs/heads/{}", self.config.branch),
+                    ])
+                    .await?;
+                self.repo.run(&["fetch", "-q", "origin"]).await?;
+                report.pushed = true;
+            }
+        }
+        Ok(report)
+    }
+
+    /// Write the synced folders to the working tree and commit the result.
+    async fn commit_relay_changes(&mut self, server: &Server) -> Result<bool> {
+        let svs = self.state_vectors(server);
+        let mut authors = BTreeSet::new();
+        for (doc_id, sv) in &svs {
+            if self.committed.get(doc_id) == Some(sv) {
+                continue;
+            }
+            let Some(awareness) = server.docs().get(doc_id).map(|d| d.awareness()) else {
+                continue;
+            };
+            let since = self.committed.get(doc_id).cloned().unwrap_or_default();
+            let guard = awareness.read().unwrap_or_else(|e| e.into_inner());
+            authors.extend(doc_authors(&guard.doc, &since));
+        }
+
+        // Folders that are not loaded are left as they are in the repo.
+        let folders = self.folder_doc_ids(server);
+        if folders.is_empty() {
+            return Ok(false);
+        }
+        for (name, folder_doc_id) in &folders {
+            self.export_folder(server, name, folder_doc_id).await?;
+        }
+        let mut add = vec!["add", "-A", "--"];
+        add.extend(folders.iter().map(|(name, _)| name.as_str()));
+        self.repo.run(&add).await?;
+        let staged = GitRepo::paths(
+            &self
+                .repo
+                .run(&["diff", "--cached", "--name-only", "-z"])
+                .await?,
+        );
+        // Only mark the docs committed once their changes are in a commit,
+        // so a failed commit keeps their authors for the next attempt.
+        if staged.is_empty() {
+            self.committed.extend(svs);
+            return Ok(false);
+        }
+
+        let mut authors: Vec<GitAuthor> = authors
+            .iter()
+            .map(|name| GitAuthor::for_user(name, &self.config.email_domain))
+            .collect();
+        let author = if authors.is_empty() {
+            self.repo.identity.clone()
+        } else {
+            authors.remove(0)
+        };
+        let message = commit_message(&staged, &authors);
+        self.repo
+            .run(&["commit", "-q", "--author", &author.to_git(), "-m", &message])
+            .await?;
+        self.committed.extend(svs);
+        Ok(true)
+    }
+
+    /// Write one folder's export under `{repo}/{name}/` and delete files the
+    /// folder no longer has.
+    async fn export_folder(
+        &mut self,
+        server: &Server,
+        name: &str,
+        folder_doc_id: &str,
+    ) -> Result<()> {
+        let root = self.config.repo_path.join(name);
+        std::fs::create_dir_all(&root)
+            .with_context(|| format!("Failed to create {}", root.display()))?;
+        let mut written = HashSet::new();
+        for item in Self::plan(server, folder_doc_id) {
+            let path = root.join(&item.path);
+            if item.kind == ExportItemKind::Folder {
+                std::fs::create_dir_all(&path)
+                    .with_context(|| format!("Failed to create {}", path.display()))?;
+                continue;
+            }
+            let Some(data) = folder_export::read_item(server, &item, ExportView::Raw).await? else {
+                continue;
+            };
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)
+                    .with_context(|| format!("Failed to create {}", parent.display()))?;
+            }
+            if std::fs::read(&path).ok().as_deref() != Some(data.as_slice()) {
+                std::fs::write(&path, &data)
+                    .with_context(|| format!("Failed to write {}", path.display()))?;
+            }
+            if item.kind == (ExportItemKind::Text { markdown: true }) {
+                self.exported.insert(
+                    format!("{}/{}", name, item.path),
+                    ExportedDoc {
+                        doc_id: item.doc_id.clone(),
+                        text: String::from_utf8_lossy(&data).into_owned(),
+                    },
+                );
+            }
+            written.insert(path);
+        }
+        remove_unlisted_files(&root, &written)
+    }
+
+    /// Fetch and merge the remote branch, then bring the markdown files the
+    /// merge changed into their docs. Returns (docs updated, docs created).
+    async fn pull(&mut self, server: &Server) -> Result<(usize, usize)> {
+        self.repo.run(&["fetch", "-q", "origin"]).await?;
+        let upstream = format!("origin/{}", self.config.branch);
+        if !self.repo.has_rev(&upstream).await? {
+            return Ok((0, 0));
+        }
+        let before = self.repo.head().await?;
+        let merge = self
+            .repo
+            .output(&["merge", "-q", "--no-edit", "-X", "ours", &upstream])
+            .await?;
+        if !merge.status.success() {
+            let _ = self.repo.output(&["merge", "--abort"]).await;
+            bail!(
+                "Merging {} failed: {}",
+                upstream,
+                String::from_utf8_lossy(&merge.stderr).trim()
+            );
+        }
+        let changed = match &before {
+            Some(before) => GitRepo::paths(
+                &self
+                    .repo
+                    .run(&["diff", "--name-only", "-z", before, "HEAD"])
+                    .await?,
+            ),
+            None => GitRepo::paths(&self.repo.run(&["ls-files", "-z"]).await?),
+        };
+
+        let (mut updated, mut created) = (0, 0);
+        for repo_path in changed {
+            let Some((folder, in_folder)) = repo_path.split_once('/') else {
+                continue;
+            };
+            if !self.config.folders.iter().any(|name| name == folder) {
+                continue;
+            }
+            if !repo_path.ends_with(".md") {
+                tracing::debug!(path = %repo_path, "Git sync only pulls markdown; ignoring");
+                continue;
+            }
+            let Ok(merged) = std::fs::read_to_string(self.config.repo_path.join(&repo_path)) else {
+                // Deleted on the remote; the next export puts it back.
+                tracing::info!(path = %repo_path, "File removed in git; relay keeps the doc");
+                continue;
+            };
+            match self.exported.get(&repo_path).cloned() {
+                Some(exported) => {
+                    if self.apply_to_doc(server, &exported, &merged).await? {
+                        updated += 1;
+                    }
+                }
+                None => {
+                    match server
+                        .create_document_direct(folder, &format!("/{}", in_folder), &merged, None)
+                        .await
+                    {
+                        Ok(_) => created += 1,
+                        Err(e) => {
+                            tracing::warn!(path = %repo_path, "Git sync could not create doc: {}", e)
+                        }
+                    }
+                }
+            }
+        }
+        Ok((updated, created))
+    }
+
+    /// Bring `merged` into the doc as a diff. If the doc changed since it
+    /// was exported, the remote's changes are three-way merged into its
+    /// current text first, keeping the relay side on conflicts.
+    async fn apply_to_doc(
+        &self,
+        server: &Server,
+        exported: &ExportedDoc,
+        merged: &str,
+    ) -> Result<bool> {
+        let live = server
+            .doc_text(&exported.doc_id)
+            .await?
+            .ok_or_else(|| anyhow!("Document not found: {}", exported.doc_id))?;
+        let target = if live == exported.text {
+            merged.to_string()
+        } else {
+            self.merge_text(&live, &exported.text, merged).await?
+        };
+        if target == live {
+            return Ok(false);
+        }
+        let edits = server
+            .restore_doc_text(&exported.doc_id, &target, false, None)
+            .await
+            .map_err(|e| anyhow!("Failed to update {}: {:?}", exported.doc_id, e))?;
+        Ok(edits > 0)
+    }
+
+    /// `git merge-file --ours`: apply base → theirs onto ours.
+    async fn merge_text(&self, ours: &str, base: &str, theirs: &str) -> Result<String> {
+        let dir = tempfile::TempDir::new()?;
+        let paths: Vec<PathBuf> = [("ours", ours), ("base", base), ("theirs", theirs)]
+            .iter()
+            .map(|(name, text)| {
+                let path = dir.path().join(name);
+                std::fs::write(&path, text).map(|_| path)
+            })
+            .collect::<std::io::Result<_>>()?;
+        let args: Vec<&str> = paths.iter().filter_map(|path| path.to_str()).collect();
+        let output = self
+            .repo
+            .output(&["merge-file", "-p", "--ours", args[0], args[1], args[2]])
+            .await?;
+        // merge-file exits with the number of conflicts; only a negative
+        // status (killed, or no code at all) is an error.
+        if !matches!(output.status.code(), Some(code) if code >= 0) {
+            bail!(
+                "git merge-file failed: {}",
+                String::from_utf8_lossy(&output.stderr)
+            );
+        }
+        Ok(String::from_utf8(output.stdout)?)
+    }
+}
+
+/// Delete files under `root` that are not in `keep`.
+fn remove_unlisted_files(root: &Path, keep: &HashSet<PathBuf>) -> Result<()> {
+    for entry in
+        std::fs::read_dir(root).with_context(|| format!("Failed to read {}", root.display()))?
+    {
+        let path = entry?.path();
+        if path.is_dir() {
+            remove_unlisted_files(&path, keep)?;
+        } else if !keep.contains(&path) {
+            std::fs::remove_file(&path)
+                .with_context(|| format!("Failed to remove {}", path.display()))?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+    use yrs::{GetString, Text};
+
+    const RELAY_ID: &str = "cb696037-0f72-4e93-8717-4e433129d789";
+
+    fn git(dir: &Path, args: &[&str]) -> String {
+        let output = std::process::Command::new("git")
+            .args(args)
+            .current_dir(dir)
+            .env("GIT_AUTHOR_NAME", "Bob")
+            .env("GIT_AUTHOR_EMAIL", "bob@example.com")
+            .env("GIT_COMMITTER_NAME", "Bob")
+            .env("GIT_COMMITTER_EMAIL", "bob@example.com")
+            .output()
+            .unwrap();
+        assert!(
+            output.status.success(),
+            "git {:?}: {}",
+            args,
+            String::from_utf8_lossy(&output.stderr)
+        );
+        String::from_utf8(output.stdout).unwrap()
+    }
+
+    fn text(server: &Server, doc_id: &str) -> String {
+        let awareness = server.docs().get(doc_id).unwrap().awareness();
+        let guard = awareness.read().unwrap();
+        let txn = guard.doc.transact();
+        txn.get_text("contents").unwrap().get_string(&txn)
+    }
+
+    #[test]
+    fn authors_come_from_the_users_map() {
+        let doc = Doc::new();
+        crate::mcp::provenance::apply_attributed_edit(&doc, 7, "Alice", None, 0, |txn, text| {
+            text.insert(txn, 0, "hi")
+        })
+        .unwrap();
+        let after_alice = doc.transact().state_vector();
+        crate::mcp::provenance::apply_attributed_edit(&doc, 8, "Bob", None, 0, |txn, text| {
+            text.insert(txn, 2, "!")
+        })
+        .unwrap();
+
+        assert_eq!(
+            doc_authors(&doc, &StateVector::default()),
+            vec!["Alice", "Bob"]
+        );
+        assert_eq!(doc_authors(&doc, &after_alice), vec!["Bob"]);
+        assert!(doc_authors(&doc, &doc.transact().state_vector()).is_empty());
+        assert_eq!(
+            GitAuthor::for_user("Alice's AI", "relay.test").email,
+            "alice-s-ai@relay.test"
+        );
+    }
+
+    #[test]
+    fn commit_message_lists_paths_and_co_authors() {
+        assert_eq!(
+            commit_message(&["Lens/a.md".into()], &[]),
+            "Update Lens/a.md"
+        );
+        assert_eq!(
+            commit_message(
+                &["Lens/a.md".into(), "Lens/b.md".into()],
+                &[GitAuthor::for_user("Bob", "relay.test")]
+            ),
+            "Update 2 files\n\n- Lens/a.md\n- Lens/b.md\n\nCo-authored-by: Bob <bob@relay.test>\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn syncs_with_a_local_bare_repo() {
+        let bare = TempDir::new().unwrap();
+        git(bare.path(), &["init", "-q", "--bare", "-b", "main"]);
+        let mirror = TempDir::new().unwrap();
+        let vault = TempDir::new().unwrap();
+        std::fs::write(vault.path().join("Note.md"), "hello\n").unwrap();
+
+        let server = Arc::new(
+            Server::new_without_workers(
+                None,
+                Duration::from_secs(60),
+                None,
+                None,
+                vec![],
+                CancellationToken::new(),
+                false,
+                None,
+            )
+            .await
+            .unwrap(),
+        );
+        let report = server
+            .import_vault(vault.path(), "Lens", Some(RELAY_ID))
+            .await
+            .unwrap();
+        let roots = server.dump_doc(&report.folder_doc_id).await.unwrap();
+        let note_id = format!(
+            "{}-{}",
+            RELAY_ID,
+            roots["filemeta_v0"]["/Note.md"]["id"].as_str().unwrap()
+        );
+        {
+            let awareness = server.docs().get(&note_id).unwrap().awareness();
+            let guard = awareness.write().unwrap();
+            crate::mcp::provenance::apply_attributed_edit(
+                &guard.doc,
+                4242,
+                "Alice",
+                None,
+                0,
+                |txn, text| text.insert(txn, 5, ", world"),
+            )
+            .unwrap();
+        }
+
+        let mut sync = GitSync::open(GitSyncConfig {
+            repo_path: mirror.path().join("mirror"),
+            folders: vec!["Lens".into()],
+            remote: Some(bare.path().to_string_lossy().into_owned()),
+            branch: "main".into(),
+            debounce: Duration::from_secs(30),
+            pull_interval: Duration::from_secs(60),
+            email_domain: "relay.test".into(),
+        })
+        .await
+        .unwrap();
+
+        // A failed commit does not lose track of who made the changes.
+        let hooks = mirror.path().join("mirror/.git/hooks");
+        std::fs::create_dir_all(&hooks).unwrap();
+        let hook = hooks.join("pre-commit");
+        std::fs::write(&hook, "#!/bin/sh\nexit 1\n").unwrap();
+        std::fs::set_permissions(&hook, std::os::unix::fs::PermissionsExt::from_mode(0o755))
+            .unwrap();
+        assert!(sync.sync(&server).await.is_err());
+        std::fs::remove_file(&hook).unwrap();
+
+        let report = sync.sync(&server).await.unwrap();
+        assert!(report.committed && report.pushed);
+        assert_eq!(
+            git(bare.path(), &["log", "-1", "--format=%an <%ae>", "main"]).trim(),
+            "Alice <alice@relay.test>"
+        );
+        assert_eq!(
+            git(bare.path(), &["show", "main:Lens/Note.md"]),
+            "hello, world\n"
+        );
+
+        // Nothing changed: no commit, nothing to push.
+        assert_eq!(sync.sync(&server).await.unwrap(), GitSyncReport::default());
+
+        // Someone edits the repo and adds a note.
+        let clone = TempDir::new().unwrap();
+        git(
+            clone.path(),
+            &["clone", "-q", &bare.path().to_string_lossy(), "work"],
+        );
+        let work = clone.path().join("work");
+        std::fs::write(work.join("Lens/Note.md"), "hello, world\nfrom git\n").unwrap();
+        std::fs::write(work.join("Lens/New.md"), "new\n").unwrap();
+        git(&work, &["add", "-A"]);
+        git(&work, &["commit", "-q", "-m", "Edit in git"]);
+        git(&work, &["push", "-q", "origin", "main"]);
+
+        let report = sync.sync(&server).await.unwrap();
+        assert_eq!((report.docs_updated, report.docs_created), (1, 1));
+        assert_eq!(text(&server, &note_id), "hello, world\nfrom git\n");
+        let roots = server.dump_doc(&sync_folder_doc_id(&server)).await.unwrap();
+        assert_eq!(roots["filemeta_v0"]["/New.md"]["type"], "markdown");
+
+        // The pulled state re-exports unchanged.
+        let report = sync.sync(&server).await.unwrap();
+        assert!(!report.committed);
+    }
+
+    fn sync_folder_doc_id(server: &Server) -> String {
+        server.folder_doc_id_for_name("Lens").unwrap()
+    }
+}
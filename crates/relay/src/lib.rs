@@ -2,9 +2,15 @@
 
 pub mod cli;
 pub mod convert;
+pub mod discord_auth;
+pub mod folder_export;
+pub mod git_sync;
 pub mod mcp;
 pub mod server;
+pub mod store_admin;
 pub mod stores;
 pub mod supervisor;
+pub mod trash;
+pub mod vault_import;
 pub mod webhook;
 pub mod worker_status;
@@ -2,8 +2,12 @@ use anyhow::Context;
 use anyhow::Result;
 use axum::middleware;
 use clap::{Parser, Subcommand, ValueEnum};
-use relay::cli::{print_auth_message, sign_stdin, verify_stdin};
+use relay::cli::{
+    dump_from_store, print_auth_message, server_api_request, sign_stdin, verify_stdin,
+};
+use relay::folder_export::{self, ExportView};
 use relay::server::AllowedHost;
+use relay::store_admin;
 use relay::stores::filesystem::FileSystemStore;
 use serde_json::json;
 use std::{
@@ -156,6 +160,200 @@ enum ServSubcommand {
         #[clap(long)]
         file_hash: Option<String>,
     },
+
+    /// Named folder snapshots on a running server
+    Snapshot {
+        /// Base URL of the server
+        #[clap(
+            long,
+            env = "RELAY_SERVER_URL",
+            default_value = "http://localhost:8080"
+        )]
+        url: String,
+
+        /// Server token, required when the server has auth enabled
+        #[clap(long, env = "RELAY_SERVER_TOKEN")]
+        token: Option<String>,
+
+        #[clap(subcommand)]
+        cmd: SnapshotSubcommand,
+    },
+
+    /// Check every folder's filemeta_v0 on a running server for malformed
+    /// paths, duplicate ids, missing ancestor folders, dangling ids and
+    /// orphaned content docs
+    Fsck {
+        /// Base URL of the server
+        #[clap(
+            long,
+            env = "RELAY_SERVER_URL",
+            default_value = "http://localhost:8080"
+        )]
+        url: String,
+
+        /// Server token, required when the server has auth enabled
+        #[clap(long, env = "RELAY_SERVER_TOKEN")]
+        token: Option<String>,
+
+        /// Apply the repairs instead of only reporting
+        #[clap(long)]
+        repair: bool,
+    },
+
+    /// Print every root type of a Y.Doc (filemeta_v0, backlinks_v0,
+    /// folder_config, users, contents, ...) as JSON
+    Dump {
+        /// Doc ID, or a folder's display name with --folder
+        target: String,
+
+        /// Treat the target as a folder name and dump its folder doc
+        #[clap(long)]
+        folder: bool,
+
+        /// Read the doc straight from this store (a directory or s3:// URL)
+        /// instead of asking a running server
+        #[clap(long)]
+        store: Option<String>,
+
+        /// Base URL of the server
+        #[clap(
+            long,
+            env = "RELAY_SERVER_URL",
+            default_value = "http://localhost:8080"
+        )]
+        url: String,
+
+        /// Server token, required when the server has auth enabled
+        #[clap(long, env = "RELAY_SERVER_TOKEN")]
+        token: Option<String>,
+    },
+
+    /// Inspect and edit docs at rest, with the server stopped
+    Store {
+        /// Configuration file whose [store] section to use
+        #[clap(short = 'c', long = "config")]
+        config: Option<PathBuf>,
+
+        /// Store to use instead (a directory or s3:// URL)
+        #[clap(long)]
+        store: Option<String>,
+
+        #[clap(subcommand)]
+        cmd: StoreSubcommand,
+    },
+
+    /// Write a folder's docs and blobs to a directory tree, read from the
+    /// store. Edits a running server has not checkpointed yet are missed;
+    /// GET /folder/:folder_uuid/export on the server includes them.
+    Export {
+        /// Display name of the folder
+        folder: String,
+
+        /// Directory to write into; created if missing
+        out_dir: PathBuf,
+
+        /// Which text of markdown docs to write
+        #[clap(long, value_enum, default_value = "raw")]
+        view: ExportView,
+
+        /// Configuration file whose [store] section to use
+        #[clap(short = 'c', long = "config")]
+        config: Option<PathBuf>,
+
+        /// Store to use instead (a directory or s3:// URL)
+        #[clap(long)]
+        store: Option<String>,
+    },
+
+    /// Import an Obsidian vault directory into a folder on a running server.
+    /// Re-running it updates changed files and leaves the rest alone.
+    Import {
+        /// Vault directory; must be readable by the server process
+        vault_dir: PathBuf,
+
+        /// Display name of the folder to import into; created if missing
+        #[clap(long)]
+        folder: String,
+
+        /// Relay ID for a new folder. Defaults to that of an existing folder
+        #[clap(long)]
+        relay_id: Option<String>,
+
+        /// Base URL of the server
+        #[clap(
+            long,
+            env = "RELAY_SERVER_URL",
+            default_value = "http://localhost:8080"
+        )]
+        url: String,
+
+        /// Server token, required when the server has auth enabled
+        #[clap(long, env = "RELAY_SERVER_TOKEN")]
+        token: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum StoreSubcommand {
+    /// List doc IDs, or a folder's paths with --folder
+    Ls {
+        /// Display name of a folder whose filemeta_v0 to list
+        #[clap(long)]
+        folder: Option<String>,
+    },
+
+    /// Print a doc's markdown
+    Cat { doc_id: String },
+
+    /// Write markdown from a file (or stdin) into a doc, creating it if needed
+    Put {
+        doc_id: String,
+
+        /// Markdown file to read; stdin when omitted
+        file: Option<PathBuf>,
+    },
+
+    /// Delete a doc
+    Rm { doc_id: String },
+
+    /// Rename a file or folder path inside a folder
+    Mv {
+        /// Display name of the folder
+        folder: String,
+
+        /// Current path, e.g. /Drafts
+        from: String,
+
+        /// New path, e.g. /Archive/Drafts
+        to: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum SnapshotSubcommand {
+    /// List a folder's snapshots, newest first
+    List {
+        /// UUID of the folder
+        folder: String,
+    },
+
+    /// Snapshot a folder doc and every content doc in it
+    Create {
+        /// UUID of the folder
+        folder: String,
+
+        /// Snapshot name (letters, digits, '-' and '_')
+        name: String,
+    },
+
+    /// Roll a folder back to a snapshot
+    Restore {
+        /// UUID of the folder
+        folder: String,
+
+        /// Snapshot name
+        name: String,
+    },
 }
 
 #[derive(Subcommand)]
@@ -389,6 +587,24 @@ fn get_store_from_opts(store_path: &str) -> Result<Box<dyn Store>> {
     }
 }
 
+/// The store for offline subcommands: `--store` if given, else the
+/// `[store]` section of the config file.
+async fn get_admin_store(
+    config: Option<&std::path::Path>,
+    store: Option<&str>,
+) -> Result<Box<dyn Store>> {
+    let store = match store {
+        Some(store) => get_store_from_opts(store)?,
+        None => {
+            let config = Config::load(config)?;
+            get_store_from_config(&config.store)?
+                .ok_or_else(|| anyhow::anyhow!("No store configured; pass --store or --config"))?
+        }
+    };
+    store.init().await?;
+    Ok(store)
+}
+
 fn log_store_backend(store_config: &y_sweet_core::config::StoreConfig) {
     use y_sweet_core::config::StoreConfig;
 
@@ -990,6 +1206,156 @@ async fn main() -> Result<()> {
             let id = doc_id.as_deref().or(file_hash.as_deref());
             verify_stdin(&authenticator, id).await?;
         }
+        ServSubcommand::Snapshot { url, token, cmd } => {
+            let (method, path, body) = match cmd {
+                SnapshotSubcommand::List { folder } => (
+                    reqwest::Method::GET,
+                    format!("folder/{}/snapshots", folder),
+                    None,
+                ),
+                SnapshotSubcommand::Create { folder, name } => (
+                    reqwest::Method::POST,
+                    format!("folder/{}/snapshots", folder),
+                    Some(json!({ "name": name })),
+                ),
+                SnapshotSubcommand::Restore { folder, name } => (
+                    reqwest::Method::POST,
+                    format!("folder/{}/snapshots/{}/restore", folder, name),
+                    None,
+                ),
+            };
+            server_api_request(url, token.as_deref(), method, &path, body).await?;
+        }
+        ServSubcommand::Fsck { url, token, repair } => {
+            let (method, path) = if *repair {
+                (reqwest::Method::POST, "fsck/repair")
+            } else {
+                (reqwest::Method::GET, "fsck")
+            };
+            server_api_request(url, token.as_deref(), method, path, None).await?;
+        }
+        ServSubcommand::Dump {
+            target,
+            folder,
+            store,
+            url,
+            token,
+        } => {
+            if let Some(store) = store {
+                let store = get_store_from_opts(store)?;
+                store.init().await?;
+                dump_from_store(store.as_ref(), target, *folder).await?;
+            } else {
+                let param = if *folder { "folder" } else { "doc_id" };
+                let path = format!(
+                    "dump?{}={}",
+                    param,
+                    url::form_urlencoded::byte_serialize(target.as_bytes()).collect::<String>()
+                );
+                server_api_request(url, token.as_deref(), reqwest::Method::GET, &path, None)
+                    .await?;
+            }
+        }
+
+        ServSubcommand::Store { config, store, cmd } => {
+            let store = get_admin_store(config.as_deref(), store.as_deref()).await?;
+
+            match cmd {
+                StoreSubcommand::Ls { folder: None } => {
+                    for doc_id in store_admin::list_docs(store.as_ref()).await? {
+                        println!("{}", doc_id);
+                    }
+                }
+                StoreSubcommand::Ls {
+                    folder: Some(folder),
+                } => {
+                    for (path, (id, entry_type)) in
+                        store_admin::list_folder(store.as_ref(), folder).await?
+                    {
+                        println!("{}\t{}\t{}", path, entry_type, id);
+                    }
+                }
+                StoreSubcommand::Cat { doc_id } => {
+                    print!(
+                        "{}",
+                        store_admin::read_markdown(store.as_ref(), doc_id).await?
+                    );
+                }
+                StoreSubcommand::Put { doc_id, file } => {
+                    let markdown = match file {
+                        Some(file) => std::fs::read_to_string(file)
+                            .with_context(|| format!("Failed to read {}", file.display()))?,
+                        None => {
+                            let mut markdown = String::new();
+                            tokio::io::stdin().read_to_string(&mut markdown).await?;
+                            markdown
+                        }
+                    };
+                    let edits = store_admin::write_markdown(store, doc_id, &markdown).await?;
+                    eprintln!("Wrote {} ({} edits)", doc_id, edits);
+                }
+                StoreSubcommand::Rm { doc_id } => {
+                    store_admin::remove_doc(store.as_ref(), doc_id).await?;
+                    eprintln!("Removed {}", doc_id);
+                }
+                StoreSubcommand::Mv { folder, from, to } => {
+                    let moved = store_admin::move_path(store, folder, from, to).await?;
+                    eprintln!("Moved {} entries from {} to {}", moved, from, to);
+                }
+            }
+        }
+
+        ServSubcommand::Export {
+            folder,
+            out_dir,
+            view,
+            config,
+            store,
+        } => {
+            let store = get_admin_store(config.as_deref(), store.as_deref()).await?;
+            let store = store.as_ref();
+            let folder_doc_id = y_sweet_core::doc_dump::find_stored_folder(store, folder)
+                .await?
+                .ok_or_else(|| anyhow::anyhow!("Folder '{}' not found in the store", folder))?;
+            let snapshot = store
+                .get(&format!("{}/data.ysweet", folder_doc_id))
+                .await?
+                .ok_or_else(|| anyhow::anyhow!("Document not found: {}", folder_doc_id))?;
+            let folder_doc = y_sweet_core::doc_sync::doc_from_snapshot(&snapshot)?;
+            let items = folder_export::plan_folder_export(&folder_doc, &folder_doc_id);
+            let written = folder_export::export_to_dir(
+                &folder_export::StoreExportSource(store),
+                &items,
+                *view,
+                out_dir,
+            )
+            .await?;
+            eprintln!("Exported {} files to {}", written, out_dir.display());
+        }
+        ServSubcommand::Import {
+            vault_dir,
+            folder,
+            relay_id,
+            url,
+            token,
+        } => {
+            // The server reads the vault itself, so send an absolute path.
+            let vault_dir = std::fs::canonicalize(vault_dir)
+                .with_context(|| format!("Vault directory not found: {}", vault_dir.display()))?;
+            let body = serde_json::json!({
+                "path": vault_dir.to_string_lossy(),
+                "folder": folder,
+                "relay_id": relay_id,
+            });
+            server_api_request(
+                url,
+                token.as_deref(),
+                reqwest::Method::POST,
+                "import",
+                Some(body),
+            )
+            .await?;
+        }
 
         ServSubcommand::ServeDoc {
             port,
@@ -29,10 +29,15 @@ pub struct AiAttribution {
     pub actor: String,
     /// CriticMarkup author shown in the review UI: "AI" or "{name}'s AI".
     pub suggestion_author: String,
+    /// User the session's share token was issued to, reported as the `user`
+    /// of the resulting webhook events.
+    pub user: Option<String>,
 }
 
 /// Apply a text edit to `doc`'s "contents" Y.Text so the inserted items carry
 /// `ai_client_id`, registering `actor` in the "users" map in the same update.
+/// When `user` is given, the update's origin names them (see
+/// `y_sweet_core::event::user_origin`) so webhooks report who made the edit.
 ///
 /// The caller must hold whatever lock guards `doc` against concurrent writers
 /// for the duration of this call (the MCP edit path holds the awareness write
@@ -42,6 +47,7 @@ pub fn apply_attributed_edit<F>(
     doc: &Doc,
     ai_client_id: u64,
     actor: &str,
+    user: Option<&str>,
     now_ms: u64,
     mutate: F,
 ) -> Result<(), String>
@@ -82,7 +88,10 @@ where
     // Delta relative to the live doc, applied back like a remote update.
     let delta = scratch.transact().encode_state_as_update_v1(&sv);
     {
-        let mut txn = doc.transact_mut();
+        let mut txn = match user {
+            Some(user) => doc.transact_mut_with(y_sweet_core::event::user_origin(user).as_str()),
+            None => doc.transact_mut(),
+        };
         let update = Update::decode_v1(&delta)
             .map_err(|e| format!("provenance: failed to decode delta: {}", e))?;
         txn.apply_update(update);
@@ -165,7 +174,7 @@ mod tests {
     fn edit_applies_and_mints_items_under_ai_client_id() {
         let doc = doc_with_text("hello world");
 
-        apply_attributed_edit(&doc, AI_ID, ACTOR, 1000, |txn, text| {
+        apply_attributed_edit(&doc, AI_ID, ACTOR, None, 1000, |txn, text| {
             text.insert(txn, 5, " brave");
         })
         .unwrap();
@@ -176,10 +185,42 @@ mod tests {
         assert!(txn.state_vector().get(&AI_ID) > 0);
     }
 
+    #[test]
+    fn edit_for_user_names_them_in_the_origin() {
+        let doc = doc_with_text("hello");
+        let origins = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let captured = origins.clone();
+        let _sub = doc
+            .observe_update_v1(move |txn, _| {
+                captured.lock().unwrap().push(
+                    txn.origin()
+                        .and_then(|o| y_sweet_core::event::user_from_origin(o.as_ref())),
+                );
+            })
+            .unwrap();
+
+        apply_attributed_edit(
+            &doc,
+            AI_ID,
+            ACTOR,
+            Some("discord:1234"),
+            1000,
+            |txn, text| {
+                text.insert(txn, 5, "!");
+            },
+        )
+        .unwrap();
+
+        assert_eq!(
+            *origins.lock().unwrap(),
+            vec![Some("discord:1234".to_string())]
+        );
+    }
+
     #[test]
     fn edit_registers_actor_with_timestamp() {
         let doc = doc_with_text("abc");
-        apply_attributed_edit(&doc, AI_ID, ACTOR, 1234, |txn, text| {
+        apply_attributed_edit(&doc, AI_ID, ACTOR, None, 1234, |txn, text| {
             text.insert(txn, 3, "def");
         })
         .unwrap();
@@ -218,11 +259,11 @@ mod tests {
     #[test]
     fn second_edit_does_not_duplicate_registration() {
         let doc = doc_with_text("abc");
-        apply_attributed_edit(&doc, AI_ID, ACTOR, 1000, |txn, text| {
+        apply_attributed_edit(&doc, AI_ID, ACTOR, None, 1000, |txn, text| {
             text.insert(txn, 0, "x");
         })
         .unwrap();
-        apply_attributed_edit(&doc, AI_ID, ACTOR, 2000, |txn, text| {
+        apply_attributed_edit(&doc, AI_ID, ACTOR, None, 2000, |txn, text| {
             text.insert(txn, 0, "y");
         })
         .unwrap();
@@ -255,7 +296,7 @@ mod tests {
     #[test]
     fn deletions_propagate_to_live_doc() {
         let doc = doc_with_text("delete me please");
-        apply_attributed_edit(&doc, AI_ID, ACTOR, 1000, |txn, text| {
+        apply_attributed_edit(&doc, AI_ID, ACTOR, None, 1000, |txn, text| {
             text.remove_range(txn, 0, 10);
         })
         .unwrap();
@@ -267,7 +308,7 @@ mod tests {
         let doc = doc_with_text("human text ");
         let human_id = doc.client_id();
 
-        apply_attributed_edit(&doc, AI_ID, ACTOR, 1000, |txn, text| {
+        apply_attributed_edit(&doc, AI_ID, ACTOR, None, 1000, |txn, text| {
             let len = text.get_string(txn).len() as u32;
             text.insert(txn, len, "ai text");
         })
@@ -141,6 +141,7 @@ mod tests {
             folder_uuid: None,
             folder_name: None,
             raw_token: None,
+            user: None,
         }
     }
 
@@ -228,16 +229,19 @@ mod tests {
         let mut expected = vec![
             "create",
             "create_session",
+            "delete",
             "edit",
             "get_links",
             "get_url",
             "glob",
             "grep",
+            "history",
             "import_article",
             "import_status",
             "validate_content",
             "move",
             "read",
+            "restore",
             "search",
         ];
         expected.sort_unstable();
@@ -526,6 +530,7 @@ mod tests {
             folder_uuid: None,
             folder_name: None,
             raw_token: None,
+            user: None,
         };
 
         let req = make_request(json!(50), "tools/list", None);
@@ -543,6 +548,7 @@ mod tests {
         assert!(names.contains(&"grep"));
         assert!(names.contains(&"get_links"));
         assert!(names.contains(&"search"));
+        assert!(names.contains(&"history"));
         assert!(names.contains(&"create_session"));
 
         assert!(
@@ -560,6 +566,16 @@ mod tests {
             "read-only should not see move, got: {:?}",
             names
         );
+        assert!(
+            !names.contains(&"delete"),
+            "read-only should not see delete, got: {:?}",
+            names
+        );
+        assert!(
+            !names.contains(&"restore"),
+            "read-only should not see restore, got: {:?}",
+            names
+        );
     }
 
     #[tokio::test]
@@ -595,6 +611,7 @@ mod tests {
             folder_uuid: Some("bbbb0000-0000-0000-0000-000000000000".to_string()),
             folder_name: Some("Lens Edu".to_string()),
             raw_token: None,
+            user: None,
         };
         let sid = server
             .mcp_sessions
@@ -57,6 +57,10 @@ pub struct McpSession {
     /// Provenance actor key registered in each doc's "users" map:
     /// `ai:<model>:<behalf>` (or `ai:<model>` when no name was given).
     pub ai_actor: String,
+    /// User the session's share token was issued to (`access.user`). When
+    /// set it stands in for the self-reported name in `author_name` and
+    /// `ai_actor`, and names the user on webhooks for the session's edits.
+    pub user: Option<String>,
 }
 
 pub struct SessionManager {
@@ -80,6 +84,8 @@ impl SessionManager {
     /// `human_name` is the name of the human on whose behalf the AI is acting
     /// (e.g. "Chris"). When provided, suggestions are attributed to "{name}'s AI"
     /// instead of the generic "AI". Pass `None` to use the default "AI" label.
+    /// It is ignored when `access` carries a user: anyone can claim a name,
+    /// but the token's user was chosen by whoever issued it.
     ///
     /// Runs an opportunistic cleanup pass when the map is over
     /// `CLEANUP_THRESHOLD` entries, so memory stays bounded even between runs
@@ -95,7 +101,9 @@ impl SessionManager {
         }
         // Strip chars that would break the JSON author field in CriticMarkup
         // (format: {"author":"NAME","timestamp":...}@@)
-        let sanitized_name: Option<String> = human_name.map(|name| {
+        let user = access.user.clone();
+        let behalf = user.as_deref().or(human_name);
+        let sanitized_name: Option<String> = behalf.map(|name| {
             name.chars()
                 .filter(|c| *c != '"' && *c != '\\' && !c.is_control())
                 .collect::<String>()
@@ -118,6 +126,7 @@ impl SessionManager {
             author_name,
             ai_client_id: Self::fresh_ai_client_id(),
             ai_actor,
+            user,
         };
         self.sessions.insert(session_id.clone(), session);
         session_id
@@ -207,6 +216,7 @@ mod tests {
             folder_uuid: None,
             folder_name: None,
             raw_token: None,
+            user: None,
         }
     }
 
@@ -258,6 +268,21 @@ mod tests {
         assert_eq!(session.author_name, "Chris's AI");
     }
 
+    #[test]
+    fn create_session_token_user_overrides_name() {
+        let mgr = SessionManager::new();
+        let access = McpAccess {
+            user: Some("discord:1234".into()),
+            ..default_access()
+        };
+        let id = mgr.create_session(access, Some("Chris"), Some("fable-5"));
+        let session = mgr.get_session(&id).unwrap();
+        assert_eq!(session.user.as_deref(), Some("discord:1234"));
+        assert_eq!(session.author_name, "discord:1234's AI");
+        // Colons separate actor segments.
+        assert_eq!(session.ai_actor, "ai:fable-5:discord1234");
+    }
+
     #[test]
     fn two_sessions_have_different_ids() {
         let mgr = SessionManager::new();
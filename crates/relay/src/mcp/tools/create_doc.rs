@@ -32,6 +32,7 @@ pub async fn execute(
                 client_id: session.ai_client_id,
                 actor: session.ai_actor.clone(),
                 suggestion_author: session.author_name.clone(),
+                user: session.user.clone(),
             },
         )
     };
@@ -104,6 +104,102 @@ pub fn smart_critic_markup(old: &str, new: &str, meta_prefix: Option<&str>) -> S
     result
 }
 
+/// CriticMarkup delimiters, comments included. Text proposed as a suggestion
+/// must not already contain any of them, or the markup would nest.
+const MARKUP_DELIMITERS: &[&str] = &[
+    "{--", "--}", "{++", "++}", "{~~", "~~}", "{==", "==}", "{>>", "<<}",
+];
+
+/// One replacement in a Y.Text, in UTF-8 byte offsets.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextEdit {
+    pub offset: usize,
+    pub delete_len: usize,
+    pub insert: String,
+}
+
+/// Edits that turn `old` into `new`, from a word-level diff. They are in
+/// descending offset order, so applying them one after another never shifts
+/// an edit that is still to come.
+///
+/// With `meta_prefix` the change is proposed as CriticMarkup instead: removed
+/// text stays in place between inserted `{--`/`--}` markers, so its
+/// characters keep their CRDT identity and authorship, and new text is
+/// inserted as `{++...++}`. That fails when either text already contains
+/// CriticMarkup.
+pub fn text_edits(
+    old: &str,
+    new: &str,
+    meta_prefix: Option<&str>,
+) -> Result<Vec<TextEdit>, String> {
+    if meta_prefix.is_some() {
+        for (text, which) in [(old, "current"), (new, "target")] {
+            if let Some(delim) = MARKUP_DELIMITERS.iter().find(|d| text.contains(*d)) {
+                return Err(format!(
+                    "the {} text contains CriticMarkup '{}'; resolve pending suggestions and comments first",
+                    which, delim
+                ));
+            }
+        }
+    }
+
+    // (offset in old, deleted text, inserted text) per changed region
+    let mut regions: Vec<(usize, String, String)> = Vec::new();
+    let mut offset = 0;
+    let mut in_region = false;
+    for change in TextDiff::from_words(old, new).iter_all_changes() {
+        let value = change.value();
+        if change.tag() == ChangeTag::Equal {
+            in_region = false;
+            offset += value.len();
+            continue;
+        }
+        if !in_region {
+            regions.push((offset, String::new(), String::new()));
+            in_region = true;
+        }
+        let (_, deleted, inserted) = regions.last_mut().expect("region was just pushed");
+        if change.tag() == ChangeTag::Delete {
+            deleted.push_str(value);
+            offset += value.len();
+        } else {
+            inserted.push_str(value);
+        }
+    }
+
+    let mut edits = Vec::new();
+    for (start, deleted, inserted) in regions.into_iter().rev() {
+        let Some(meta) = meta_prefix else {
+            edits.push(TextEdit {
+                offset: start,
+                delete_len: deleted.len(),
+                insert: inserted,
+            });
+            continue;
+        };
+        let mut closing = String::new();
+        if !deleted.is_empty() {
+            closing.push_str("--}");
+        }
+        if !inserted.is_empty() {
+            closing.push_str(&format!("{{++{}{}++}}", meta, inserted));
+        }
+        edits.push(TextEdit {
+            offset: start + deleted.len(),
+            delete_len: 0,
+            insert: closing,
+        });
+        if !deleted.is_empty() {
+            edits.push(TextEdit {
+                offset: start,
+                delete_len: 0,
+                insert: format!("{{--{}", meta),
+            });
+        }
+    }
+    Ok(edits)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -235,4 +331,48 @@ mod tests {
             r#"hello {--{"author":"AI","timestamp":1707600000}@@beautiful --}world"#
         );
     }
+
+    fn apply(text: &str, edits: &[TextEdit]) -> String {
+        let mut text = text.to_string();
+        for edit in edits {
+            text.replace_range(edit.offset..edit.offset + edit.delete_len, &edit.insert);
+        }
+        text
+    }
+
+    #[test]
+    fn text_edits_turn_old_into_new() {
+        let old = "The quick brown fox jumps over the lazy dog.";
+        let new = "A quick red fox jumps over the dog. Woof.";
+        let edits = text_edits(old, new, None).unwrap();
+        assert!(edits.windows(2).all(|w| w[0].offset > w[1].offset));
+        assert_eq!(apply(old, &edits), new);
+
+        assert_eq!(apply("", &text_edits("", "fresh", None).unwrap()), "fresh");
+        assert_eq!(apply("gone", &text_edits("gone", "", None).unwrap()), "");
+        assert!(text_edits("same text", "same text", None)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn text_edits_as_suggestions_only_insert_markup() {
+        let meta = r#"{"author":"AI","timestamp":1707600000}@@"#;
+        let old = "I love cats and dogs";
+        let edits = text_edits(old, "I hate cats and dogs too", Some(meta)).unwrap();
+        assert!(edits.iter().all(|e| e.delete_len == 0));
+        assert_eq!(
+            apply(old, &edits),
+            r#"I {--{"author":"AI","timestamp":1707600000}@@love--}{++{"author":"AI","timestamp":1707600000}@@hate++} cats and dogs{++{"author":"AI","timestamp":1707600000}@@ too++}"#
+        );
+    }
+
+    #[test]
+    fn text_edits_as_suggestions_reject_existing_markup() {
+        let err = text_edits("a {++b++} c", "a c", Some("")).unwrap_err();
+        assert!(err.contains("current text"));
+        let err = text_edits("a c", "a {>>note<<} c", Some("")).unwrap_err();
+        assert!(err.contains("target text"));
+        assert!(text_edits("a {++b++} c", "a c", None).is_ok());
+    }
 }
@@ -845,7 +845,7 @@ fn truncate_side(text: &str) -> String {
 }
 
 /// Format a timestamp as relative time.
-fn format_relative_time(timestamp_ms: u64) -> String {
+pub(crate) fn format_relative_time(timestamp_ms: u64) -> String {
     let now = std::time::SystemTime::now()
         .duration_since(std::time::UNIX_EPOCH)
         .unwrap()
@@ -0,0 +1,119 @@
+use crate::server::Server;
+use serde_json::Value;
+use std::sync::Arc;
+
+/// Execute the `delete` tool: move a markdown document to its folder's
+/// trash, recorded as deleted by the session's AI.
+pub async fn execute(
+    server: &Arc<Server>,
+    session_id: &str,
+    arguments: &Value,
+) -> Result<String, String> {
+    let file_path = arguments
+        .get("file_path")
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| "Missing required parameter: file_path".to_string())?;
+
+    let author = server
+        .mcp_sessions
+        .get_session(session_id)
+        .map(|session| session.author_name.clone())
+        .ok_or_else(|| "Error: Session not found".to_string())?;
+
+    let result = server
+        .delete_path(file_path, Some(&author))
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(format!(
+        "Deleted {}{} ({} backlink entries removed)",
+        result.folder, result.path, result.backlinks_removed,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mcp::tools::test_helpers::{default_access, setup_session_no_reads};
+    use serde_json::json;
+    use std::collections::HashMap;
+    use y_sweet_core::doc_sync::DocWithSyncKv;
+    use yrs::{Any, Map, ReadTxn, Transact, WriteTxn};
+
+    const RELAY_ID: &str = "cb696037-0f72-4e93-8717-4e433129d789";
+    const FOLDER_UUID: &str = "b0000001-0000-4000-8000-000000000001";
+
+    async fn build_delete_server(entries: &[(&str, &str, &str)]) -> Arc<Server> {
+        let server = Server::new_for_test();
+        let folder_doc_id = format!("{}-{}", RELAY_ID, FOLDER_UUID);
+        let folder_doc = DocWithSyncKv::new(&folder_doc_id, None, || (), None)
+            .await
+            .unwrap();
+        {
+            let awareness = folder_doc.awareness();
+            let guard = awareness.write().unwrap();
+            let mut txn = guard.doc.transact_mut();
+            let config = txn.get_or_insert_map("folder_config");
+            config.insert(&mut txn, "name", Any::String("Lens".into()));
+            let filemeta = txn.get_or_insert_map("filemeta_v0");
+            for (path, uuid, entry_type) in entries {
+                let mut fields = HashMap::new();
+                fields.insert("id".to_string(), Any::String((*uuid).into()));
+                fields.insert("type".to_string(), Any::String((*entry_type).into()));
+                fields.insert("version".to_string(), Any::Number(0.0));
+                filemeta.insert(&mut txn, *path, Any::Map(fields.into()));
+            }
+        }
+        server.docs().insert(folder_doc_id, folder_doc);
+        server.doc_resolver().rebuild(server.docs());
+        server
+    }
+
+    #[tokio::test]
+    async fn delete_removes_document_from_folder() {
+        let server = build_delete_server(&[(
+            "/Old.md",
+            "11111111-1111-4111-8111-111111111111",
+            "markdown",
+        )])
+        .await;
+        let sid = server
+            .mcp_sessions
+            .create_session(default_access(), Some("Luc"), None);
+
+        let output = execute(&server, &sid, &json!({ "file_path": "Lens/Old.md" }))
+            .await
+            .unwrap();
+
+        assert!(output.contains("Deleted Lens/Old.md"));
+        let folder_doc_id = format!("{}-{}", RELAY_ID, FOLDER_UUID);
+        let doc_ref = server.docs().get(&folder_doc_id).unwrap();
+        let awareness = doc_ref.awareness();
+        let guard = awareness.read().unwrap();
+        let txn = guard.doc.transact();
+        let filemeta = txn.get_map("filemeta_v0").unwrap();
+        assert!(filemeta.get(&txn, "/Old.md").is_none());
+        let trashed = y_sweet_core::trash::list_trash(&txn);
+        assert_eq!(trashed.len(), 1);
+        assert_eq!(trashed[0].path, "/Old.md");
+        assert_eq!(trashed[0].deleted_by.as_deref(), Some("Luc's AI"));
+    }
+
+    #[tokio::test]
+    async fn delete_reports_unknown_paths_and_folders() {
+        let server =
+            build_delete_server(&[("/Notes", "22222222-2222-4222-8222-222222222222", "folder")])
+                .await;
+        let sid = setup_session_no_reads(&server);
+
+        let missing = execute(&server, &sid, &json!({ "file_path": "Lens/Missing.md" }))
+            .await
+            .unwrap_err();
+        assert!(missing.contains("Path not found"));
+
+        let folder = execute(&server, &sid, &json!({ "file_path": "Lens/Notes" }))
+            .await
+            .unwrap_err();
+        assert!(folder.contains("not a markdown document"));
+    }
+}
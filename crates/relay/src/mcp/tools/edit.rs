@@ -149,7 +149,7 @@ pub async fn execute(
     }
 
     // 3. Check read-before-edit: session must have read this document first
-    let (author, ai_client_id, ai_actor) = {
+    let (author, ai_client_id, ai_actor, user) = {
         let session = server
             .mcp_sessions
             .get_session(session_id)
@@ -164,6 +164,7 @@ pub async fn execute(
             session.author_name.clone(),
             session.ai_client_id,
             session.ai_actor.clone(),
+            session.user.clone(),
         )
         // Drop session guard before accessing Y.Doc
     };
@@ -182,6 +183,7 @@ pub async fn execute(
             new_string,
             ai_client_id,
             &ai_actor,
+            user.as_deref(),
         )
         .await;
     }
@@ -285,6 +287,7 @@ pub async fn execute(
             &guard.doc,
             ai_client_id,
             &ai_actor,
+            user.as_deref(),
             timestamp,
             |txn, text| {
                 text.remove_range(
@@ -327,6 +330,7 @@ async fn edit_raw_ytext_file(
     new_string: &str,
     ai_client_id: u64,
     ai_actor: &str,
+    user: Option<&str>,
 ) -> Result<String, String> {
     server
         .ensure_doc_loaded(&doc_info.doc_id)
@@ -376,6 +380,7 @@ async fn edit_raw_ytext_file(
             &guard.doc,
             ai_client_id,
             ai_actor,
+            user,
             timestamp,
             |txn, text| {
                 text.remove_range(txn, start, len);
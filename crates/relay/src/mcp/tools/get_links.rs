@@ -7,7 +7,11 @@ use y_sweet_core::link_parser;
 use yrs::{GetString, Map, ReadTxn, Transact};
 
 /// Execute the `get_links` tool: return backlinks and forward links for a document.
-pub async fn execute(server: &Arc<Server>, arguments: &Value) -> Result<String, String> {
+pub async fn execute(
+    server: &Arc<Server>,
+    user: Option<&str>,
+    arguments: &Value,
+) -> Result<String, String> {
     let file_path = arguments
         .get("file_path")
         .and_then(|v| v.as_str())
@@ -19,7 +23,12 @@ pub async fn execute(server: &Arc<Server>, arguments: &Value) -> Result<String,
         .ok_or_else(|| format!("Error: Document not found: {}", file_path))?;
 
     // --- Backlinks ---
-    let backlink_paths = read_backlinks(server, &doc_info.folder_doc_id, &doc_info.uuid).await;
+    let backlink_paths: Vec<String> =
+        read_backlinks(server, &doc_info.folder_doc_id, &doc_info.uuid)
+            .await
+            .into_iter()
+            .filter(|path| server.acl_can_read_path(path, user))
+            .collect();
 
     // --- Forward links ---
     let forward_link_paths = read_forward_links(server, &doc_info.doc_id).await;
@@ -1,6 +1,7 @@
 use crate::server::Server;
 use serde_json::Value;
 use std::sync::Arc;
+use y_sweet_core::sections::heading_slug;
 
 /// Length of the short UUID prefix used in editor URLs. Mirrors
 /// `SHORT_UUID_LENGTH` in `lens-editor/src/lib/url-utils.ts`.
@@ -12,7 +13,7 @@ const SHORT_UUID_LEN: usize = 8;
 /// where `prefix` is the first [`SHORT_UUID_LEN`] chars of the document's UUID.
 /// That prefix is a random id assigned at creation, so it can only be looked up,
 /// never derived — hence this tool, instead of clients hand-building (and
-/// breaking) URLs.
+/// breaking) URLs. An optional `heading` becomes a `#anchor` fragment.
 pub fn execute(server: &Arc<Server>, arguments: &Value) -> Result<String, String> {
     let file_path = arguments
         .get("file_path")
@@ -25,7 +26,26 @@ pub fn execute(server: &Arc<Server>, arguments: &Value) -> Result<String, String
         .ok_or_else(|| format!("Error: Document not found: {}", file_path))?;
 
     let base = std::env::var("EDITOR_BASE_URL").ok();
-    Ok(build_url(&doc_info.uuid, file_path, base.as_deref()))
+    let url = build_url(&doc_info.uuid, file_path, base.as_deref());
+    match arguments.get("heading").and_then(|v| v.as_str()) {
+        Some(heading) => Ok(format!("{}{}", url, heading_fragment(heading))),
+        None => Ok(url),
+    }
+}
+
+/// `#anchor` for a heading, matching the anchors returned by section search.
+/// Accepts heading text ("Calvin cycle"), a markdown heading line
+/// ("## Calvin cycle"), an anchor ("#calvin-cycle") or a heading path
+/// ("Photosynthesis > Calvin cycle", last segment wins). Empty when nothing
+/// sluggable remains.
+fn heading_fragment(heading: &str) -> String {
+    let last = heading.rsplit('>').next().unwrap_or(heading);
+    let slug = heading_slug(last.trim().trim_start_matches('#'));
+    if slug.is_empty() {
+        String::new()
+    } else {
+        format!("#{}", slug)
+    }
 }
 
 /// Build the editor URL from a document UUID and its vault path.
@@ -94,6 +114,18 @@ mod tests {
         );
     }
 
+    #[test]
+    fn heading_fragment_accepts_text_anchor_and_path() {
+        assert_eq!(heading_fragment("Calvin cycle"), "#calvin-cycle");
+        assert_eq!(heading_fragment("## Calvin cycle"), "#calvin-cycle");
+        assert_eq!(heading_fragment("#calvin-cycle"), "#calvin-cycle");
+        assert_eq!(
+            heading_fragment("Photosynthesis > Calvin cycle"),
+            "#calvin-cycle"
+        );
+        assert_eq!(heading_fragment("  "), "");
+    }
+
     #[test]
     fn short_uuid_shorter_than_prefix_len_does_not_panic() {
         assert_eq!(build_url("abcd", "Lens/Doc.md", None), "/abcd/Lens/Doc.md");
@@ -106,6 +138,20 @@ mod tests {
         assert!(result.is_err(), "missing doc should error, got: {result:?}");
     }
 
+    #[tokio::test]
+    async fn execute_appends_heading_anchor() {
+        let server = build_test_server(&[("/Doc.md", DOC_UUID, "# Intro\nHello")]).await;
+        let out = execute(
+            &server,
+            &json!({ "file_path": "Lens/Doc.md", "heading": "Intro" }),
+        )
+        .unwrap();
+        assert!(
+            out.ends_with("abcd1234/Lens/Doc.md#intro"),
+            "URL should end with the heading anchor, got: {out}"
+        );
+    }
+
     #[tokio::test]
     async fn execute_uses_real_resolved_prefix() {
         // The URL must embed the doc's real, resolved prefix.
@@ -4,7 +4,11 @@ use serde_json::Value;
 use std::sync::Arc;
 
 /// Execute the `glob` tool: pattern-match against document paths.
-pub fn execute(server: &Arc<Server>, arguments: &Value) -> Result<String, String> {
+pub fn execute(
+    server: &Arc<Server>,
+    user: Option<&str>,
+    arguments: &Value,
+) -> Result<String, String> {
     let pattern = arguments
         .get("pattern")
         .and_then(|v| v.as_str())
@@ -38,6 +42,7 @@ pub fn execute(server: &Arc<Server>, arguments: &Value) -> Result<String, String
                 glob_match(pattern, p)
             }
         })
+        .filter(|p| server.acl_can_read_path(p, user))
         .collect();
 
     matched.sort();
@@ -54,6 +59,7 @@ mod tests {
     use super::*;
     use serde_json::json;
     use std::collections::HashMap;
+    use y_sweet_core::acl::AclPolicy;
     use y_sweet_core::doc_resolver::DocumentResolver;
     use yrs::{Any, Doc, Map, Transact, WriteTxn};
 
@@ -97,7 +103,12 @@ mod tests {
         let server =
             build_test_server(&[("/subfolder/file.md", "uuid-1"), ("/other.md", "uuid-2")]);
 
-        let result = execute(&server, &json!({"pattern": "subfolder/*", "path": "Lens"})).unwrap();
+        let result = execute(
+            &server,
+            None,
+            &json!({"pattern": "subfolder/*", "path": "Lens"}),
+        )
+        .unwrap();
         assert!(
             result.contains("Lens/subfolder/file.md"),
             "Expected match for subfolder/file.md, got: {}",
@@ -115,13 +126,13 @@ mod tests {
         // Without path scope, pattern must match the full path
         let server = build_test_server(&[("/subfolder/file.md", "uuid-1")]);
 
-        let result = execute(&server, &json!({"pattern": "subfolder/*"})).unwrap();
+        let result = execute(&server, None, &json!({"pattern": "subfolder/*"})).unwrap();
         assert_eq!(
             result, "No matches found.",
             "Without scope, subfolder/* shouldn't match Lens/subfolder/file.md"
         );
 
-        let result = execute(&server, &json!({"pattern": "Lens/subfolder/*"})).unwrap();
+        let result = execute(&server, None, &json!({"pattern": "Lens/subfolder/*"})).unwrap();
         assert!(
             result.contains("Lens/subfolder/file.md"),
             "Full path pattern should match, got: {}",
@@ -134,7 +145,12 @@ mod tests {
         // Regression: **/*.md with scope should still work
         let server = build_test_server(&[("/subfolder/file.md", "uuid-1"), ("/top.md", "uuid-2")]);
 
-        let result = execute(&server, &json!({"pattern": "**/*.md", "path": "Lens"})).unwrap();
+        let result = execute(
+            &server,
+            None,
+            &json!({"pattern": "**/*.md", "path": "Lens"}),
+        )
+        .unwrap();
         assert!(result.contains("Lens/subfolder/file.md"), "got: {}", result);
         assert!(result.contains("Lens/top.md"), "got: {}", result);
     }
@@ -145,7 +161,7 @@ mod tests {
         let server =
             build_test_server(&[("/subfolder/nested.md", "uuid-1"), ("/top.md", "uuid-2")]);
 
-        let result = execute(&server, &json!({"pattern": "*.md", "path": "Lens"})).unwrap();
+        let result = execute(&server, None, &json!({"pattern": "*.md", "path": "Lens"})).unwrap();
         assert!(result.contains("Lens/top.md"), "got: {}", result);
         assert!(
             !result.contains("nested.md"),
@@ -153,4 +169,22 @@ mod tests {
             result
         );
     }
+
+    #[tokio::test]
+    async fn glob_hides_paths_the_acl_denies() {
+        let server = build_test_server(&[("/Drafts/a.md", "uuid-1"), ("/top.md", "uuid-2")]);
+        let policy: AclPolicy = serde_json::from_value(
+            json!({"folders": {"Lens": [{"path": "/Drafts/**", "role": "none"}]}}),
+        )
+        .unwrap();
+        server.set_acl_policy(policy).await.unwrap();
+
+        let result = execute(
+            &server,
+            None,
+            &json!({"pattern": "**/*.md", "path": "Lens"}),
+        )
+        .unwrap();
+        assert_eq!(result, "Lens/top.md");
+    }
 }
@@ -5,7 +5,11 @@ use std::sync::Arc;
 use yrs::{GetString, ReadTxn, Transact};
 
 /// Execute the `grep` tool: regex content search across Y.Docs.
-pub async fn execute(server: &Arc<Server>, arguments: &Value) -> Result<String, String> {
+pub async fn execute(
+    server: &Arc<Server>,
+    user: Option<&str>,
+    arguments: &Value,
+) -> Result<String, String> {
     let pattern = arguments
         .get("pattern")
         .and_then(|v| v.as_str())
@@ -60,6 +64,7 @@ pub async fn execute(server: &Arc<Server>, arguments: &Value) -> Result<String,
         };
         all_paths.retain(|p| p.starts_with(&prefix) || p == scope);
     }
+    all_paths.retain(|p| server.acl_can_read_path(p, user));
 
     let mut output_lines: Vec<String> = Vec::new();
     let mut file_count = 0;
@@ -315,6 +320,7 @@ mod tests {
 
         let result = execute(
             &server,
+            None,
             &json!({"pattern": "sunlight", "output_mode": "content"}),
         )
         .await
@@ -338,6 +344,7 @@ mod tests {
 
         let result = execute(
             &server,
+            None,
             &json!({"pattern": "hello", "-i": true, "output_mode": "content"}),
         )
         .await
@@ -371,6 +378,7 @@ mod tests {
 
         let result = execute(
             &server,
+            None,
             &json!({"pattern": "apple", "output_mode": "files_with_matches"}),
         )
         .await
@@ -390,6 +398,7 @@ mod tests {
 
         let result = execute(
             &server,
+            None,
             &json!({"pattern": "apple", "output_mode": "count"}),
         )
         .await
@@ -410,6 +419,7 @@ mod tests {
 
         let result = execute(
             &server,
+            None,
             &json!({"pattern": "MATCH", "output_mode": "content", "-C": 1}),
         )
         .await
@@ -443,6 +453,7 @@ mod tests {
 
         let result = execute(
             &server,
+            None,
             &json!({"pattern": "MATCH", "output_mode": "content", "-A": 2}),
         )
         .await
@@ -481,6 +492,7 @@ mod tests {
 
         let result = execute(
             &server,
+            None,
             &json!({"pattern": "MATCH", "output_mode": "content", "-B": 1}),
         )
         .await
@@ -543,6 +555,7 @@ mod tests {
         // Search scoped to Lens/ only
         let result = execute(
             &server,
+            None,
             &json!({"pattern": "target", "path": "Lens", "output_mode": "files_with_matches"}),
         )
         .await
@@ -566,6 +579,7 @@ mod tests {
 
         let result = execute(
             &server,
+            None,
             &json!({"pattern": "ZZZZNOTFOUND", "output_mode": "content"}),
         )
         .await
@@ -580,6 +594,7 @@ mod tests {
 
         let result = execute(
             &server,
+            None,
             &json!({"pattern": "[invalid", "output_mode": "content"}),
         )
         .await;
@@ -602,6 +617,7 @@ mod tests {
 
         let result = execute(
             &server,
+            None,
             &json!({"pattern": "target", "output_mode": "files_with_matches", "head_limit": 1}),
         )
         .await
@@ -626,6 +642,7 @@ mod tests {
 
         let result = execute(
             &server,
+            None,
             &json!({"pattern": "common", "output_mode": "files_with_matches"}),
         )
         .await
@@ -651,6 +668,7 @@ mod tests {
 
         let result = execute(
             &server,
+            None,
             &json!({"pattern": "found_me", "output_mode": "files_with_matches"}),
         )
         .await
@@ -675,6 +693,7 @@ mod tests {
 
         let result = execute(
             &server,
+            None,
             &json!({"pattern": "nonexistent", "output_mode": "content"}),
         )
         .await
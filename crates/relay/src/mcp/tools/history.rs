@@ -0,0 +1,324 @@
+use super::blob;
+use super::critic_markup::format_relative_time;
+use crate::mcp::provenance::AiAttribution;
+use crate::server::{unified_text_diff, Server};
+use serde_json::Value;
+use std::sync::Arc;
+
+/// Execute the `history` tool: list a document's stored versions, show the
+/// text of one version, or diff two versions (or a version against the
+/// current text).
+pub async fn execute(server: &Arc<Server>, arguments: &Value) -> Result<String, String> {
+    let file_path = arguments
+        .get("file_path")
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| "Missing required parameter: file_path".to_string())?;
+    let version = arguments.get("version").and_then(|v| v.as_str());
+    let from = arguments.get("from").and_then(|v| v.as_str());
+    let to = arguments.get("to").and_then(|v| v.as_str());
+
+    let doc_info = server
+        .doc_resolver()
+        .resolve_path(file_path)
+        .ok_or_else(|| format!("Error: Document not found: {}", file_path))?;
+
+    if blob::is_blob_file(file_path) {
+        return Err(format!(
+            "Error: history is only available for text documents, not {}",
+            file_path
+        ));
+    }
+
+    if let Some(version) = version {
+        if from.is_some() || to.is_some() {
+            return Err("Error: pass either version, or from (and optionally to)".to_string());
+        }
+        let text = version_text(server, &doc_info.doc_id, file_path, version).await?;
+        return Ok(format!("Version {} of {}:\n\n{}", version, file_path, text));
+    }
+
+    if let Some(from) = from {
+        let old_text = version_text(server, &doc_info.doc_id, file_path, from).await?;
+        let (to_label, new_text) = match to {
+            Some(to) => (
+                to,
+                version_text(server, &doc_info.doc_id, file_path, to).await?,
+            ),
+            None => (
+                "current",
+                server
+                    .doc_text(&doc_info.doc_id)
+                    .await
+                    .map_err(|e| format!("Error: Failed to load document {}: {}", file_path, e))?,
+            ),
+        };
+        let diff = unified_text_diff(
+            &old_text,
+            &new_text,
+            &format!("{}@{}", file_path, from),
+            &format!("{}@{}", file_path, to_label),
+        );
+        if diff.is_empty() {
+            return Ok(format!(
+                "No changes to {} between {} and {}.",
+                file_path, from, to_label
+            ));
+        }
+        return Ok(diff);
+    }
+
+    if to.is_some() {
+        return Err("Error: 'to' requires 'from'".to_string());
+    }
+
+    let versions = server
+        .doc_versions(&doc_info.doc_id)
+        .await
+        .map_err(|e| format!("Error: Failed to list versions of {}: {}", file_path, e))?;
+    if versions.is_empty() {
+        return Ok(format!("No stored versions of {}.", file_path));
+    }
+
+    let mut output = format!(
+        "{} version(s) of {}, newest first:\n",
+        versions.len(),
+        file_path
+    );
+    for v in &versions {
+        output.push_str(&format!(
+            "- {}  {}{}\n",
+            v.version_id,
+            format_relative_time(v.last_modified),
+            if v.is_latest { "  (latest)" } else { "" }
+        ));
+    }
+    output.push_str(
+        "\nShow one with history(version: <id>), or compare with history(from: <id>, to: <id>); omit 'to' to compare against the current text.",
+    );
+    Ok(output)
+}
+
+/// Execute the `restore` tool: roll a document back to a stored version. For
+/// markdown the rollback is proposed as CriticMarkup suggestions attributed
+/// to the session, like any other MCP edit; raw Y.Text files (.html) are
+/// restored directly.
+pub async fn restore(
+    server: &Arc<Server>,
+    session_id: &str,
+    arguments: &Value,
+) -> Result<String, String> {
+    let file_path = arguments
+        .get("file_path")
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| "Missing required parameter: file_path".to_string())?;
+    let version = arguments
+        .get("version")
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| "Missing required parameter: version".to_string())?;
+
+    let doc_info = server
+        .doc_resolver()
+        .resolve_path(file_path)
+        .ok_or_else(|| format!("Error: Document not found: {}", file_path))?;
+
+    if blob::is_blob_file(file_path) {
+        return Err(format!(
+            "Error: restore is only available for text documents, not {}",
+            file_path
+        ));
+    }
+
+    let attribution = {
+        let session = server
+            .mcp_sessions
+            .get_session(session_id)
+            .ok_or_else(|| "Error: Session not found".to_string())?;
+        AiAttribution {
+            client_id: session.ai_client_id,
+            actor: session.ai_actor.clone(),
+            suggestion_author: session.author_name.clone(),
+            user: session.user.clone(),
+        }
+    };
+    let as_suggestions = !blob::is_raw_ytext_file(file_path);
+
+    let edits = server
+        .restore_doc_version(
+            &doc_info.doc_id,
+            version,
+            as_suggestions,
+            Some(&attribution),
+        )
+        .await
+        .map_err(|e| format!("Error: {}", e.error))?;
+
+    Ok(if edits == 0 {
+        format!("{} already matches version {}.", file_path, version)
+    } else if as_suggestions {
+        format!(
+            "Proposed restoring {} to version {} as CriticMarkup suggestions for human review.",
+            file_path, version
+        )
+    } else {
+        format!("Restored {} to version {}.", file_path, version)
+    })
+}
+
+async fn version_text(
+    server: &Arc<Server>,
+    doc_id: &str,
+    file_path: &str,
+    version_id: &str,
+) -> Result<String, String> {
+    server
+        .doc_version_text(doc_id, version_id)
+        .await
+        .map_err(|e| {
+            format!(
+                "Error: Failed to read version {} of {}: {}",
+                version_id, file_path, e
+            )
+        })?
+        .ok_or_else(|| format!("Error: Version {} of {} not found", version_id, file_path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mcp::tools::test_helpers::*;
+    use serde_json::json;
+
+    const UUID: &str = "dddd0000-0000-4000-8000-000000000001";
+
+    async fn history_server() -> Arc<Server> {
+        build_versioned_test_server(
+            "/Notes.md",
+            UUID,
+            &["# Notes\nfirst draft\n", "# Notes\nsecond draft\n"],
+            "# Notes\nsecond draft\nwith an addition\n",
+        )
+        .await
+    }
+
+    #[tokio::test]
+    async fn lists_versions_newest_first() {
+        let server = history_server().await;
+        let result = execute(&server, &json!({ "file_path": "Lens/Notes.md" }))
+            .await
+            .unwrap();
+        assert!(result.starts_with("2 version(s) of Lens/Notes.md, newest first:"));
+        let v1 = result.find("- v1 ").expect("v1 listed");
+        let v0 = result.find("- v0 ").expect("v0 listed");
+        assert!(v1 < v0);
+        assert!(result.contains("(latest)"));
+    }
+
+    #[tokio::test]
+    async fn shows_the_text_of_a_version() {
+        let server = history_server().await;
+        let result = execute(
+            &server,
+            &json!({ "file_path": "Lens/Notes.md", "version": "v0" }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            result,
+            "Version v0 of Lens/Notes.md:\n\n# Notes\nfirst draft\n"
+        );
+
+        let err = execute(
+            &server,
+            &json!({ "file_path": "Lens/Notes.md", "version": "v7" }),
+        )
+        .await
+        .unwrap_err();
+        assert!(err.contains("Version v7 of Lens/Notes.md not found"));
+    }
+
+    #[tokio::test]
+    async fn diffs_versions_and_the_current_text() {
+        let server = history_server().await;
+        let result = execute(
+            &server,
+            &json!({ "file_path": "Lens/Notes.md", "from": "v0", "to": "v1" }),
+        )
+        .await
+        .unwrap();
+        assert!(result.starts_with("--- Lens/Notes.md@v0\n+++ Lens/Notes.md@v1\n"));
+        assert!(result.contains("-first draft\n+second draft\n"));
+
+        let result = execute(
+            &server,
+            &json!({ "file_path": "Lens/Notes.md", "from": "v1" }),
+        )
+        .await
+        .unwrap();
+        assert!(result.contains("+++ Lens/Notes.md@current\n"));
+        assert!(result.contains("+with an addition\n"));
+
+        let result = execute(
+            &server,
+            &json!({ "file_path": "Lens/Notes.md", "from": "v1", "to": "v1" }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(result, "No changes to Lens/Notes.md between v1 and v1.");
+    }
+
+    #[tokio::test]
+    async fn restore_proposes_the_old_text_as_suggestions() {
+        let server = history_server().await;
+        let sid = setup_session_no_reads(&server);
+        let result = restore(
+            &server,
+            &sid,
+            &json!({ "file_path": "Lens/Notes.md", "version": "v0" }),
+        )
+        .await
+        .unwrap();
+        assert!(result.starts_with("Proposed restoring Lens/Notes.md to version v0"));
+
+        let doc_id = format!("{}-{}", RELAY_ID, UUID);
+        let raw = read_doc_content(&server, &doc_id);
+        let spans = crate::mcp::tools::critic_markup::parse(&raw);
+        assert_eq!(
+            crate::mcp::tools::critic_markup::accepted_view(&spans),
+            "# Notes\nfirst draft\n"
+        );
+        assert_eq!(
+            crate::mcp::tools::critic_markup::base_view(&spans),
+            "# Notes\nsecond draft\nwith an addition\n"
+        );
+
+        // Pending suggestions block a second suggestion-mode restore.
+        let err = restore(
+            &server,
+            &sid,
+            &json!({ "file_path": "Lens/Notes.md", "version": "v1" }),
+        )
+        .await
+        .unwrap_err();
+        assert!(err.contains("CriticMarkup"));
+    }
+
+    #[tokio::test]
+    async fn rejects_conflicting_arguments() {
+        let server = history_server().await;
+        let err = execute(
+            &server,
+            &json!({ "file_path": "Lens/Notes.md", "version": "v0", "from": "v1" }),
+        )
+        .await
+        .unwrap_err();
+        assert!(err.contains("either version, or from"));
+
+        let err = execute(
+            &server,
+            &json!({ "file_path": "Lens/Notes.md", "to": "v1" }),
+        )
+        .await
+        .unwrap_err();
+        assert!(err.contains("'to' requires 'from'"));
+    }
+}
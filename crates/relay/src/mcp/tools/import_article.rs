@@ -169,6 +169,7 @@ mod tests {
             folder_uuid: None,
             folder_name: Some("Lens Edu".to_string()),
             raw_token: Some(token.to_string()),
+            user: None,
         }
     }
 
@@ -178,6 +179,7 @@ mod tests {
             folder_uuid: None,
             folder_name: None,
             raw_token: None,
+            user: None,
         }
     }
 
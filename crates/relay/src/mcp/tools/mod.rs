@@ -2,11 +2,13 @@ pub mod blob;
 pub mod create_doc;
 pub mod critic_diff;
 pub mod critic_markup;
+pub mod delete_doc;
 pub mod edit;
 pub mod get_links;
 pub mod get_url;
 pub mod glob;
 pub mod grep;
+pub mod history;
 pub mod import_article;
 pub mod move_doc;
 pub mod read;
@@ -19,10 +21,12 @@ pub mod validate_content;
 use crate::server::Server;
 use serde_json::{json, Value};
 use std::sync::Arc;
+use y_sweet_core::acl;
+use y_sweet_core::api_types::Authorization;
 use y_sweet_core::share_token::McpAccess;
 
 /// Return tool definitions for MCP tools/list response.
-/// When `writable` is false, write tools (edit, create, move) are excluded.
+/// When `writable` is false, write tools (edit, create, move, delete) are excluded.
 pub fn tool_definitions(writable: bool) -> Vec<Value> {
     let article_import_modes = import_article::ARTICLE_IMPORT_MODES;
     let mut tools = vec![
@@ -36,7 +40,7 @@ pub fn tool_definitions(writable: bool) -> Vec<Value> {
                 "properties": {
                     "name": {
                         "type": "string",
-                        "description": "User's first name; suggestions show as \"{name}'s AI\". Ask the user if you don't reliably know it. Omit if truly unavailable."
+                        "description": "User's first name; suggestions show as \"{name}'s AI\". Ask the user if you don't reliably know it. Omit if truly unavailable. Ignored when the server key already identifies the user."
                     },
                     "model": {
                         "type": "string",
@@ -126,6 +130,10 @@ pub fn tool_definitions(writable: bool) -> Vec<Value> {
                         "type": "string",
                         "description": "Path to the document (e.g. 'Lens/Photosynthesis.md')"
                     },
+                    "heading": {
+                        "type": "string",
+                        "description": "Optional heading to link to (e.g. 'Calvin cycle', or an anchor from a section search such as '#calvin-cycle'). Appended to the URL as a #anchor."
+                    },
                     "session_id": {
                         "type": "string",
                         "description": "Session ID returned by create_session. Required."
@@ -183,7 +191,7 @@ pub fn tool_definitions(writable: bool) -> Vec<Value> {
         }),
         json!({
             "name": "search",
-            "description": "Full-text search across the knowledge base using ranked relevance (BM25). Returns results sorted by relevance with snippets. Supports phrase search with quotes. Use this for natural-language queries; use grep for exact regex pattern matching.",
+            "description": "Full-text search across the knowledge base using ranked relevance (BM25). Returns results sorted by relevance (or by 'sort') with snippets, paged with 'offset'. Supports phrases, prefix (term*), fuzzy (term~), OR, exclusion (-term) and field queries. Use mode 'hybrid' for conceptual questions (e.g. 'how plants make energy') where documents may not contain your exact words; use grep for exact regex pattern matching.",
             "inputSchema": {
                 "type": "object",
                 "required": ["query", "session_id"],
@@ -191,12 +199,70 @@ pub fn tool_definitions(writable: bool) -> Vec<Value> {
                 "properties": {
                     "query": {
                         "type": "string",
-                        "description": "Search query. Supports multiple terms (AND semantics) and phrase search with quotes."
+                        "description": "Search query. Terms are AND-ed. Operators: \"exact phrase\" (\"a b\"~2 allows 2 words between), term* (prefix), term~ or term~2 (fuzzy, for typos), a OR b, -term (exclude). Field queries: title:, body:, heading:, and frontmatter tag:<tag>, type:<type>, source_url:\"<url>\" (e.g. 'tag:lens type:article -draft'). Parentheses are not supported; malformed queries return an error pointing at the bad part."
                     },
                     "limit": {
                         "type": "number",
                         "description": "Maximum number of results to return (default 20, max 100)."
                     },
+                    "offset": {
+                        "type": "number",
+                        "description": "Number of results to skip, for fetching the next page (default 0)."
+                    },
+                    "sort": {
+                        "type": "string",
+                        "enum": ["relevance", "modified", "title", "path"],
+                        "description": "Result order: 'relevance' (default), 'modified' (most recently edited first), 'title' or 'path'. Sorting other than by relevance requires mode 'bm25'."
+                    },
+                    "folder": {
+                        "type": "string",
+                        "description": "Folder to scope the search to (e.g. 'Lens', 'Lens Edu'). If not specified, searches all folders you have access to."
+                    },
+                    "path_prefix": {
+                        "type": "string",
+                        "description": "Only return documents at or under this path within the folder (e.g. '/Biology'). Matches whole path segments."
+                    },
+                    "mode": {
+                        "type": "string",
+                        "enum": ["bm25", "hybrid", "semantic"],
+                        "description": "Ranking mode: 'bm25' keyword relevance (default), 'hybrid' blends keyword relevance with semantic similarity, 'semantic' ranks by meaning only."
+                    },
+                    "granularity": {
+                        "type": "string",
+                        "enum": ["document", "section"],
+                        "description": "'document' (default) returns one hit per document; 'section' returns the best-matching heading sections with their heading path, line number and a #anchor for get_url. Section search requires mode 'bm25'."
+                    },
+                    "session_id": {
+                        "type": "string",
+                        "description": "Session ID returned by create_session. Required."
+                    }
+                }
+            }
+        }),
+        json!({
+            "name": "history",
+            "description": "Show how a document changed over time, from its stored versions. With only file_path, lists the versions (newest first). With 'version', returns the document text as of that version. With 'from' (and optionally 'to'), returns a unified diff between two versions; omit 'to' to diff against the current text.",
+            "inputSchema": {
+                "type": "object",
+                "required": ["file_path", "session_id"],
+                "additionalProperties": false,
+                "properties": {
+                    "file_path": {
+                        "type": "string",
+                        "description": "Path to the document (e.g. 'Lens/Photosynthesis.md')"
+                    },
+                    "version": {
+                        "type": "string",
+                        "description": "Version id from the version list, to show the document text at that version."
+                    },
+                    "from": {
+                        "type": "string",
+                        "description": "Version id to diff from."
+                    },
+                    "to": {
+                        "type": "string",
+                        "description": "Version id to diff to. Defaults to the current text."
+                    },
                     "session_id": {
                         "type": "string",
                         "description": "Session ID returned by create_session. Required."
@@ -356,6 +422,48 @@ pub fn tool_definitions(writable: bool) -> Vec<Value> {
                 }
             }
         }));
+        tools.push(json!({
+            "name": "delete",
+            "description": "Delete a markdown document. Moves it to its folder's trash, where it can be restored until the trash is purged, and removes it from the backlinks of other documents, and search. Links in other documents that point at it are left as they are. Cannot delete folders or non-markdown files.",
+            "inputSchema": {
+                "type": "object",
+                "required": ["file_path", "session_id"],
+                "additionalProperties": false,
+                "properties": {
+                    "file_path": {
+                        "type": "string",
+                        "description": "Path to the document (e.g. 'Lens/Photosynthesis.md')"
+                    },
+                    "session_id": {
+                        "type": "string",
+                        "description": "Session ID returned by create_session. Required."
+                    }
+                }
+            }
+        }));
+        tools.push(json!({
+            "name": "restore",
+            "description": "Roll a document back to a stored version (see the history tool for version ids). For markdown the rollback is proposed as CriticMarkup suggestions for human review, like edit; it fails while the document has pending suggestions or comments. Live editors keep working: the change merges like any other edit.",
+            "inputSchema": {
+                "type": "object",
+                "required": ["file_path", "version", "session_id"],
+                "additionalProperties": false,
+                "properties": {
+                    "file_path": {
+                        "type": "string",
+                        "description": "Path to the document (e.g. 'Lens/Photosynthesis.md')"
+                    },
+                    "version": {
+                        "type": "string",
+                        "description": "Version id to restore, from the history tool."
+                    },
+                    "session_id": {
+                        "type": "string",
+                        "description": "Session ID returned by create_session. Required."
+                    }
+                }
+            }
+        }));
     }
 
     tools
@@ -406,12 +514,35 @@ pub async fn dispatch_tool(
     if !access.writable
         && matches!(
             name,
-            "edit" | "create" | "move" | "import_article" | "import_status"
+            "edit" | "create" | "move" | "delete" | "restore" | "import_article" | "import_status"
         )
     {
         return tool_error("Access denied: read-only access. Cannot use write tools.");
     }
 
+    // Canonicalize path arguments the way the resolver keys paths, so the
+    // scope and ACL checks below see the same path the tool acts on.
+    let mut canonical_arguments = arguments.clone();
+    for key in ["file_path", "path", "new_path", "target_folder"] {
+        let Some(raw) = arguments.get(key).and_then(|v| v.as_str()) else {
+            continue;
+        };
+        let Some(path) = acl::canonical_path(raw) else {
+            return tool_error(&format!(
+                "Invalid {} '{}': '.' and '..' segments are not allowed.",
+                key, raw
+            ));
+        };
+        // `new_path` is relative to a folder and written with a leading `/`.
+        let path = if key == "new_path" {
+            format!("/{}", path)
+        } else {
+            path
+        };
+        canonical_arguments[key] = Value::String(path);
+    }
+    let arguments = &canonical_arguments;
+
     // Folder scope check: restrict tools to the allowed folder
     if let Some(ref allowed_folder) = access.folder_name {
         // Check file_path argument
@@ -447,6 +578,74 @@ pub async fn dispatch_tool(
         }
     }
 
+    // Path ACL check: the server's policy can narrow the key per path.
+    // Markdown edits land as CriticMarkup, so suggest access is enough for
+    // them; JSON, HTML and other raw files are edited in place.
+    let required = match name {
+        "edit" => {
+            let replaces_directly = arguments
+                .get("file_path")
+                .and_then(|v| v.as_str())
+                .is_some_and(|path| blob::is_blob_file(path) || blob::is_raw_ytext_file(path));
+            if replaces_directly {
+                Authorization::Full
+            } else {
+                Authorization::Suggest
+            }
+        }
+        "create" | "move" | "delete" | "restore" => Authorization::Full,
+        _ => Authorization::ReadOnly,
+    };
+    let mut acl_paths: Vec<String> = ["file_path", "path"]
+        .iter()
+        .filter_map(|key| arguments.get(*key).and_then(|v| v.as_str()))
+        .map(str::to_string)
+        .collect();
+    // A move writes its destination too: `new_path` inside `target_folder`,
+    // or inside the source's folder when no target is given.
+    if name == "move" {
+        if let Some(new_path) = arguments.get("new_path").and_then(|v| v.as_str()) {
+            let source_folder = acl_paths
+                .first()
+                .map(|path| path.trim_start_matches('/'))
+                .and_then(|path| path.split('/').next())
+                .unwrap_or_default()
+                .to_string();
+            let folder = arguments
+                .get("target_folder")
+                .and_then(|v| v.as_str())
+                .filter(|folder| !folder.is_empty())
+                .map(str::to_string)
+                .unwrap_or(source_folder);
+            acl_paths.push(format!("{}/{}", folder, new_path.trim_start_matches('/')));
+        }
+    }
+    for path in &acl_paths {
+        if let Some(role) = server.acl_role_for_path(path, access.user.as_deref()) {
+            if !role.allows(required) {
+                return tool_error(&format!(
+                    "Access denied: the folder ACL gives '{}' access to '{}'.",
+                    role.as_str(),
+                    path
+                ));
+            }
+        }
+    }
+    // Rules below a moved folder stay where they are, so the move must not
+    // carry a restricted subtree somewhere less restricted.
+    if let [source, .., destination] = acl_paths.as_slice() {
+        if name == "move" {
+            if let Some(reason) =
+                server.acl_move_loosens(source, destination, access.user.as_deref())
+            {
+                return tool_error(&format!(
+                    "Access denied: the move would loosen the folder ACL: {}.",
+                    reason
+                ));
+            }
+        }
+    }
+
     // Lazy rebuild: if the resolver has no entries but docs exist, trigger a rebuild.
     // This handles the case where docs were created after server startup (e.g. local dev).
     if server.doc_resolver().all_paths().is_empty() {
@@ -458,11 +657,11 @@ pub async fn dispatch_tool(
             Ok(text) => tool_success(&text),
             Err(msg) => tool_error(&msg),
         },
-        "glob" => match glob::execute(server, arguments) {
+        "glob" => match glob::execute(server, access.user.as_deref(), arguments) {
             Ok(text) => tool_success(&text),
             Err(msg) => tool_error(&msg),
         },
-        "get_links" => match get_links::execute(server, arguments).await {
+        "get_links" => match get_links::execute(server, access.user.as_deref(), arguments).await {
             Ok(text) => tool_success(&text),
             Err(msg) => tool_error(&msg),
         },
@@ -470,7 +669,7 @@ pub async fn dispatch_tool(
             Ok(text) => tool_success(&text),
             Err(msg) => tool_error(&msg),
         },
-        "grep" => match grep::execute(server, arguments).await {
+        "grep" => match grep::execute(server, access.user.as_deref(), arguments).await {
             Ok(text) => tool_success(&text),
             Err(msg) => tool_error(&msg),
         },
@@ -486,7 +685,19 @@ pub async fn dispatch_tool(
             Ok(text) => tool_success(&text),
             Err(msg) => tool_error(&msg),
         },
-        "search" => match search::execute(server, arguments).await {
+        "delete" => match delete_doc::execute(server, session_id, arguments).await {
+            Ok(text) => tool_success(&text),
+            Err(msg) => tool_error(&msg),
+        },
+        "history" => match history::execute(server, arguments).await {
+            Ok(text) => tool_success(&text),
+            Err(msg) => tool_error(&msg),
+        },
+        "restore" => match history::restore(server, session_id, arguments).await {
+            Ok(text) => tool_success(&text),
+            Err(msg) => tool_error(&msg),
+        },
+        "search" => match search::execute(server, access, arguments).await {
             Ok(text) => tool_success(&text),
             Err(msg) => tool_error(&msg),
         },
@@ -588,6 +799,7 @@ mod integration_tests {
             folder_uuid: None,
             folder_name: None,
             raw_token: None,
+            user: None,
         };
 
         // get_url must be advertised even for read-only sessions.
@@ -640,6 +852,250 @@ mod integration_tests {
         );
     }
 
+    #[tokio::test]
+    async fn dispatch_acl_covers_raw_edits_and_move_destinations() {
+        use y_sweet_core::acl::AclPolicy;
+        use y_sweet_core::share_token::McpAccess;
+
+        let server = build_blob_test_server_with_folder().await;
+        let access = McpAccess {
+            writable: true,
+            folder_uuid: None,
+            folder_name: None,
+            raw_token: None,
+            user: None,
+        };
+        let sess = super::dispatch_tool(
+            &server,
+            "create_session",
+            &json!({ "name": "Test" }),
+            &access,
+        )
+        .await;
+        let sid = sess["content"][0]["text"].as_str().unwrap().to_string();
+        for (file_path, content) in [("Lens/Doc.md", "hello"), ("Lens/data.json", "{}")] {
+            let created = super::dispatch_tool(
+                &server,
+                "create",
+                &json!({ "file_path": file_path, "content": content, "session_id": sid }),
+                &access,
+            )
+            .await;
+            assert_eq!(created["isError"], json!(false), "create failed: {created}");
+        }
+        let denied = |res: &serde_json::Value| {
+            res["content"][0]["text"]
+                .as_str()
+                .unwrap_or_default()
+                .contains("Access denied: the folder ACL")
+        };
+
+        // Suggest access covers markdown edits, which land as CriticMarkup,
+        // but not JSON edits, which replace text in place.
+        let policy: AclPolicy = serde_json::from_value(
+            json!({"folders": {"Lens": [{"path": "/**", "role": "suggest"}]}}),
+        )
+        .unwrap();
+        server.set_acl_policy(policy).await.unwrap();
+        let edit = |file_path: &str| {
+            json!({
+                "file_path": file_path,
+                "old_string": "hello",
+                "new_string": "hi",
+                "session_id": sid,
+            })
+        };
+        let res = super::dispatch_tool(&server, "edit", &edit("Lens/data.json"), &access).await;
+        assert!(denied(&res), "raw edit should need edit access: {res}");
+        let res = super::dispatch_tool(&server, "edit", &edit("Lens/Doc.md"), &access).await;
+        assert!(!denied(&res), "markdown edit should pass the ACL: {res}");
+
+        // A move is checked at its destination as well as its source.
+        let policy: AclPolicy = serde_json::from_value(
+            json!({"folders": {"Lens": [{"path": "/Locked/**", "role": "view"}]}}),
+        )
+        .unwrap();
+        server.set_acl_policy(policy).await.unwrap();
+        let res = super::dispatch_tool(
+            &server,
+            "move",
+            &json!({ "path": "Lens/Doc.md", "new_path": "/Locked/Doc.md", "session_id": sid }),
+            &access,
+        )
+        .await;
+        assert!(denied(&res), "move into a view-only path: {res}");
+        assert!(
+            res["content"][0]["text"]
+                .as_str()
+                .unwrap()
+                .contains("Lens/Locked/Doc.md"),
+            "{res}"
+        );
+        assert!(server.doc_resolver().resolve_path("Lens/Doc.md").is_some());
+    }
+
+    #[tokio::test]
+    async fn dispatch_acl_refuses_moves_that_carry_rules_away() {
+        use y_sweet_core::acl::AclPolicy;
+        use y_sweet_core::share_token::McpAccess;
+
+        let server = build_blob_test_server_with_folder().await;
+        let access = McpAccess {
+            writable: true,
+            folder_uuid: None,
+            folder_name: None,
+            raw_token: None,
+            user: None,
+        };
+        let sess = super::dispatch_tool(
+            &server,
+            "create_session",
+            &json!({ "name": "Test" }),
+            &access,
+        )
+        .await;
+        let sid = sess["content"][0]["text"].as_str().unwrap().to_string();
+        for file_path in ["Lens/Course/a.md", "Lens/Course/Private/x.md"] {
+            let created = super::dispatch_tool(
+                &server,
+                "create",
+                &json!({ "file_path": file_path, "content": "hi", "session_id": sid }),
+                &access,
+            )
+            .await;
+            assert_eq!(created["isError"], json!(false), "create failed: {created}");
+        }
+        let policy: AclPolicy = serde_json::from_value(json!({"folders": {"Lens": [
+            {"path": "/Course/Private/**", "role": "none"},
+            {"path": "/Course/Private/**", "user": "ta", "role": "edit"}
+        ]}}))
+        .unwrap();
+        server.set_acl_policy(policy).await.unwrap();
+
+        // The caller may edit /Course, but moving it would drop the rule on
+        // /Course/Private; the same goes for a user the rule lets in.
+        for user in [None, Some("ta".to_string())] {
+            let access = McpAccess {
+                user,
+                ..access.clone()
+            };
+            let res = super::dispatch_tool(
+                &server,
+                "move",
+                &json!({ "path": "Lens/Course", "new_path": "/Open", "session_id": sid }),
+                &access,
+            )
+            .await;
+            assert_eq!(res["isError"], json!(true), "{res}");
+            assert!(
+                res["content"][0]["text"]
+                    .as_str()
+                    .unwrap()
+                    .contains("would loosen the folder ACL"),
+                "{res}"
+            );
+        }
+        assert!(server
+            .doc_resolver()
+            .resolve_path("Lens/Course/Private/x.md")
+            .is_some());
+
+        // Moving a file out of the unrestricted part is unaffected.
+        let res = super::dispatch_tool(
+            &server,
+            "move",
+            &json!({ "path": "Lens/Course/a.md", "new_path": "/Open/a.md", "session_id": sid }),
+            &access,
+        )
+        .await;
+        assert_eq!(res["isError"], json!(false), "{res}");
+    }
+
+    #[tokio::test]
+    async fn dispatch_acl_checks_canonical_paths() {
+        use y_sweet_core::acl::AclPolicy;
+        use y_sweet_core::share_token::McpAccess;
+
+        let server = build_blob_test_server_with_folder().await;
+        let access = McpAccess {
+            writable: true,
+            folder_uuid: None,
+            folder_name: None,
+            raw_token: None,
+            user: None,
+        };
+        let sess = super::dispatch_tool(
+            &server,
+            "create_session",
+            &json!({ "name": "Test" }),
+            &access,
+        )
+        .await;
+        let sid = sess["content"][0]["text"].as_str().unwrap().to_string();
+        let created = super::dispatch_tool(
+            &server,
+            "create",
+            &json!({ "file_path": "Lens/Drafts/x.md", "content": "secret", "session_id": sid }),
+            &access,
+        )
+        .await;
+        assert_eq!(created["isError"], json!(false), "create failed: {created}");
+        let policy: AclPolicy = serde_json::from_value(
+            json!({"folders": {"Lens": [{"path": "/Drafts/**", "role": "none"}]}}),
+        )
+        .unwrap();
+        server.set_acl_policy(policy).await.unwrap();
+        let text = |res: &serde_json::Value| {
+            res["content"][0]["text"]
+                .as_str()
+                .unwrap_or_default()
+                .to_string()
+        };
+
+        for file_path in ["Lens//Drafts/x.md", "/Lens/Drafts//x.md"] {
+            let res = super::dispatch_tool(
+                &server,
+                "read",
+                &json!({ "file_path": file_path, "session_id": sid }),
+                &access,
+            )
+            .await;
+            assert_eq!(res["isError"], json!(true), "{file_path}: {res}");
+            assert!(
+                text(&res).contains("Access denied: the folder ACL"),
+                "{file_path}: {res}"
+            );
+        }
+        for file_path in ["Lens/./Drafts/x.md", "Lens/Notes/../Drafts/x.md"] {
+            let res = super::dispatch_tool(
+                &server,
+                "read",
+                &json!({ "file_path": file_path, "session_id": sid }),
+                &access,
+            )
+            .await;
+            assert!(
+                text(&res).starts_with("Invalid file_path"),
+                "{file_path}: {res}"
+            );
+            assert!(!text(&res).contains("secret"), "{file_path}: {res}");
+        }
+
+        // A folder-scoped key can't climb out of its folder either.
+        let scoped = McpAccess {
+            folder_name: Some("Lens".into()),
+            ..access.clone()
+        };
+        let res = super::dispatch_tool(
+            &server,
+            "glob",
+            &json!({ "pattern": "**/*.md", "path": "Lens/../Other", "session_id": sid }),
+            &scoped,
+        )
+        .await;
+        assert!(text(&res).starts_with("Invalid path"), "{res}");
+    }
+
     #[tokio::test]
     async fn json_file_create_read_edit_roundtrip() {
         let server = build_blob_test_server_with_folder().await;
@@ -665,6 +1121,7 @@ mod integration_tests {
         // 2. Glob finds it
         let glob_result = glob::execute(
             &server,
+            None,
             &json!({
                 "pattern": "**/*.json",
                 "session_id": sid,
@@ -738,6 +1195,7 @@ mod integration_tests {
         // 6. Grep finds content
         let grep_result = grep::execute(
             &server,
+            None,
             &json!({
                 "pattern": "test",
                 "session_id": sid,
@@ -465,6 +465,7 @@ mod tests {
                 folder_uuid: None,
                 folder_name: None,
                 raw_token: None,
+                user: None,
             },
             None,
             None,
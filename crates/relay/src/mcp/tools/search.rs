@@ -1,9 +1,23 @@
 use crate::server::Server;
 use serde_json::Value;
 use std::sync::Arc;
+use y_sweet_core::search_index::{SearchGranularity, SearchMode, SearchOptions, SearchSort};
+use y_sweet_core::search_query::QueryParseError;
+use y_sweet_core::share_token::McpAccess;
 
-/// Execute the `search` tool: full-text ranked search via Tantivy index.
-pub async fn execute(server: &Arc<Server>, arguments: &Value) -> Result<String, String> {
+/// Execute the `search` tool: full-text ranked search via Tantivy index,
+/// optionally blended with embedding similarity (`mode: hybrid|semantic`).
+/// `granularity: section` returns heading-level hits with a `#anchor`.
+/// `offset` pages through long result sets and `sort` orders them by
+/// relevance, modification time, title or path.
+///
+/// Folder-scoped tokens are always restricted to their folder, whether or
+/// not the caller passes `folder`.
+pub async fn execute(
+    server: &Arc<Server>,
+    access: &McpAccess,
+    arguments: &Value,
+) -> Result<String, String> {
     let query = arguments
         .get("query")
         .and_then(|v| v.as_str())
@@ -19,6 +33,72 @@ pub async fn execute(server: &Arc<Server>, arguments: &Value) -> Result<String,
         .and_then(|v| v.as_u64())
         .map(|v| (v as usize).min(100))
         .unwrap_or(20);
+    let offset = arguments
+        .get("offset")
+        .and_then(|v| v.as_u64())
+        .map(|v| v as usize)
+        .unwrap_or(0);
+
+    let mode = match arguments.get("mode").and_then(|v| v.as_str()) {
+        None => SearchMode::Bm25,
+        Some(name) => SearchMode::from_name(name).ok_or_else(|| {
+            format!(
+                "Invalid mode '{}'. Expected one of: bm25, hybrid, semantic.",
+                name
+            )
+        })?,
+    };
+    let granularity = match arguments.get("granularity").and_then(|v| v.as_str()) {
+        None => SearchGranularity::Document,
+        Some(name) => SearchGranularity::from_name(name).ok_or_else(|| {
+            format!(
+                "Invalid granularity '{}'. Expected one of: document, section.",
+                name
+            )
+        })?,
+    };
+    if granularity == SearchGranularity::Section && mode != SearchMode::Bm25 {
+        return Err("Section granularity is only supported with mode 'bm25'.".to_string());
+    }
+    let sort = match arguments.get("sort").and_then(|v| v.as_str()) {
+        None => SearchSort::Relevance,
+        Some(name) => SearchSort::from_name(name).ok_or_else(|| {
+            format!(
+                "Invalid sort '{}'. Expected one of: relevance, modified, title, path.",
+                name
+            )
+        })?,
+    };
+    if sort != SearchSort::Relevance && mode != SearchMode::Bm25 {
+        return Err(format!(
+            "Sorting by {} is only supported with mode 'bm25'.",
+            sort.as_str()
+        ));
+    }
+
+    let requested_folder = arguments
+        .get("folder")
+        .and_then(|v| v.as_str())
+        .map(str::trim)
+        .filter(|f| !f.is_empty());
+    let folder = match (&access.folder_uuid, requested_folder) {
+        (Some(allowed_uuid), Some(requested)) => {
+            let allowed_name = access.folder_name.as_deref();
+            if requested != allowed_uuid.as_str() && Some(requested) != allowed_name {
+                return Err(format!(
+                    "Access denied: this key only has access to '{}'.",
+                    allowed_name.unwrap_or(allowed_uuid.as_str())
+                ));
+            }
+            Some(allowed_uuid.clone())
+        }
+        (Some(allowed_uuid), None) => Some(allowed_uuid.clone()),
+        (None, requested) => requested.map(str::to_string),
+    };
+    let path_prefix = arguments
+        .get("path_prefix")
+        .and_then(|v| v.as_str())
+        .map(str::to_string);
 
     // Check search index availability
     let search_index = server
@@ -30,38 +110,114 @@ pub async fn execute(server: &Arc<Server>, arguments: &Value) -> Result<String,
         return Err("Search index is being built, please try again shortly.".to_string());
     }
 
+    if mode != SearchMode::Bm25 && search_index.embedding_provider_name().is_none() {
+        return Err(format!(
+            "{} search is not enabled on this server. Use mode 'bm25'.",
+            mode.as_str()
+        ));
+    }
+
+    let options = SearchOptions {
+        limit,
+        offset,
+        mode,
+        sort,
+        folder,
+        path_prefix,
+        granularity,
+        exclude_doc_ids: server.acl_hidden_doc_uuids(access.user.as_deref()),
+    };
+
     // Run search in blocking context (tantivy is sync)
-    let results = tokio::task::spawn_blocking(move || search_index.search(&query, limit))
-        .await
-        .map_err(|e| format!("Search task failed: {}", e))?
-        .map_err(|e| format!("Search error: {}", e))?;
+    let query_text = query.clone();
+    let (results, total) =
+        tokio::task::spawn_blocking(move || search_index.search_page(&query, &options))
+            .await
+            .map_err(|e| format!("Search task failed: {}", e))?
+            .map_err(|e| match e.downcast_ref::<QueryParseError>() {
+                Some(parse_error) => describe_parse_error(&query_text, parse_error),
+                None => format!("Search error: {}", e),
+            })?;
 
     if results.is_empty() {
+        if total > 0 {
+            return Ok(format!(
+                "No results at offset {} ({} total).",
+                offset, total
+            ));
+        }
         return Ok("No results found.".to_string());
     }
 
-    let total = results.len();
-    let mut output = Vec::with_capacity(total + 1);
-    output.push(format!(
-        "{} result{}:",
-        total,
-        if total == 1 { "" } else { "s" }
-    ));
+    let end = offset + results.len();
+    let mut output = Vec::with_capacity(results.len() + 2);
+    if offset == 0 && end == total {
+        output.push(format!(
+            "{} result{}:",
+            total,
+            if total == 1 { "" } else { "s" }
+        ));
+    } else {
+        output.push(format!("Results {}-{} of {}:", offset + 1, end, total));
+    }
 
     for r in &results {
         output.push(String::new());
-        output.push(format!("## {} ({})", r.title, r.folder));
+        let anchor = r.anchor.as_deref().unwrap_or("");
+        if r.path.is_empty() {
+            output.push(format!("## {} ({})", r.title, r.folder));
+        } else {
+            output.push(format!("## {} ({}{}{})", r.title, r.folder, r.path, anchor));
+        }
+        if let Some(line) = r.line {
+            if r.heading_path.is_empty() {
+                output.push(format!("Line {}", line));
+            } else {
+                output.push(format!("{} (line {})", r.heading_path.join(" > "), line));
+            }
+        }
+        if !r.tags.is_empty() || r.doc_type.is_some() {
+            let mut meta = Vec::new();
+            if let Some(doc_type) = &r.doc_type {
+                meta.push(format!("type: {}", doc_type));
+            }
+            if !r.tags.is_empty() {
+                meta.push(format!("tags: {}", r.tags.join(", ")));
+            }
+            output.push(format!("[{}]", meta.join("; ")));
+        }
         if !r.snippet.is_empty() {
             output.push(r.snippet.clone());
         }
     }
 
+    if end < total {
+        output.push(String::new());
+        output.push(format!("More results: search again with offset {}.", end));
+    }
+
     Ok(output.join("\n"))
 }
 
+/// One line per syntax error, quoting the offending part of the query so the
+/// caller can fix it without counting characters.
+fn describe_parse_error(query: &str, error: &QueryParseError) -> String {
+    let mut lines = vec!["Invalid search query:".to_string()];
+    for e in &error.errors {
+        let fragment: String = query.chars().skip(e.start).take(e.end - e.start).collect();
+        lines.push(format!("- {} (at `{}`)", e.message, fragment));
+    }
+    lines.push(
+        "Syntax: terms (AND), \"phrase\", term* (prefix), term~ (fuzzy), a OR b, -term (exclude), field:value (title, body, heading, tag, type, source_url)."
+            .to_string(),
+    );
+    lines.join("\n")
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::mcp::tools::test_helpers::default_access;
     use serde_json::json;
 
     #[tokio::test]
@@ -69,7 +225,12 @@ mod tests {
         // Server::new_for_test() has search_index: None
         let server = Server::new_for_test();
 
-        let result = execute(&server, &json!({"query": "photosynthesis"})).await;
+        let result = execute(
+            &server,
+            &default_access(),
+            &json!({"query": "photosynthesis"}),
+        )
+        .await;
 
         assert!(result.is_err());
         assert!(
@@ -82,7 +243,7 @@ mod tests {
     async fn search_empty_query_returns_no_results() {
         let server = Server::new_for_test();
 
-        let result = execute(&server, &json!({"query": "  "})).await;
+        let result = execute(&server, &default_access(), &json!({"query": "  "})).await;
 
         assert!(result.is_ok());
         assert_eq!(result.unwrap(), "No results found.");
@@ -106,9 +267,13 @@ mod tests {
 
         let server = Server::new_for_test_with_search(search_index.clone());
 
-        let result = execute(&server, &json!({"query": "photosynthesis"}))
-            .await
-            .unwrap();
+        let result = execute(
+            &server,
+            &default_access(),
+            &json!({"query": "photosynthesis"}),
+        )
+        .await
+        .unwrap();
 
         assert!(
             result.contains("1 result"),
@@ -147,9 +312,13 @@ mod tests {
 
         let server = Server::new_for_test_with_search(search_index.clone());
 
-        let result = execute(&server, &json!({"query": "biology", "limit": 2}))
-            .await
-            .unwrap();
+        let result = execute(
+            &server,
+            &default_access(),
+            &json!({"query": "biology", "limit": 2}),
+        )
+        .await
+        .unwrap();
 
         assert!(
             result.starts_with("2 result"),
@@ -157,4 +326,280 @@ mod tests {
             result
         );
     }
+
+    #[tokio::test]
+    async fn search_hybrid_mode_matches_concepts() {
+        use y_sweet_core::embedding::HashingEmbedder;
+
+        let search_index = Arc::new(
+            y_sweet_core::search_index::SearchIndex::new_in_memory()
+                .unwrap()
+                .with_embedding_provider(Arc::new(HashingEmbedder::default())),
+        );
+        search_index
+            .add_document(
+                "doc-1",
+                "Photosynthesis",
+                "Plants convert sunlight into chemical energy.",
+                "Lens",
+            )
+            .unwrap();
+        let server = Server::new_for_test_with_search(search_index);
+
+        let keyword = execute(
+            &server,
+            &default_access(),
+            &json!({"query": "how plants make energy"}),
+        )
+        .await
+        .unwrap();
+        assert_eq!(keyword, "No results found.");
+
+        let hybrid = execute(
+            &server,
+            &default_access(),
+            &json!({"query": "how plants make energy", "mode": "hybrid"}),
+        )
+        .await
+        .unwrap();
+        assert!(
+            hybrid.contains("Photosynthesis"),
+            "Expected hybrid hit, got: {}",
+            hybrid
+        );
+    }
+
+    #[tokio::test]
+    async fn search_rejects_invalid_mode() {
+        let search_index =
+            Arc::new(y_sweet_core::search_index::SearchIndex::new_in_memory().unwrap());
+        let server = Server::new_for_test_with_search(search_index);
+
+        let err = execute(
+            &server,
+            &default_access(),
+            &json!({"query": "x", "mode": "psychic"}),
+        )
+        .await
+        .unwrap_err();
+        assert!(err.contains("Invalid mode"), "got: {}", err);
+
+        // Valid mode, but this index has no embedding provider
+        let err = execute(
+            &server,
+            &default_access(),
+            &json!({"query": "x", "mode": "semantic"}),
+        )
+        .await
+        .unwrap_err();
+        assert!(err.contains("not enabled"), "got: {}", err);
+    }
+
+    #[tokio::test]
+    async fn search_folder_scoped_token_only_sees_its_folder() {
+        // Prevents: a folder-scoped share token reading search hits (titles
+        // and snippets) from folders it has no access to
+        use y_sweet_core::search_index::DocLocation;
+
+        let search_index =
+            Arc::new(y_sweet_core::search_index::SearchIndex::new_in_memory().unwrap());
+        for (doc_id, folder, folder_id) in [
+            ("doc-1", "Lens", "aaaa0000-0000-0000-0000-000000000000"),
+            ("doc-2", "Private", "bbbb0000-0000-0000-0000-000000000000"),
+        ] {
+            search_index
+                .add_document_at(
+                    doc_id,
+                    &format!("{} notes", folder),
+                    "photosynthesis",
+                    &DocLocation {
+                        folder: folder.to_string(),
+                        folder_id: folder_id.to_string(),
+                        path: "/Notes.md".to_string(),
+                    },
+                )
+                .unwrap();
+        }
+        let server = Server::new_for_test_with_search(search_index);
+        let access = McpAccess {
+            writable: false,
+            folder_uuid: Some("aaaa0000-0000-0000-0000-000000000000".to_string()),
+            folder_name: Some("Lens".to_string()),
+            raw_token: None,
+            user: None,
+        };
+
+        let result = execute(&server, &access, &json!({"query": "photosynthesis"}))
+            .await
+            .unwrap();
+        assert!(result.starts_with("1 result"), "got: {}", result);
+        assert!(result.contains("Lens/Notes.md"), "got: {}", result);
+        assert!(!result.contains("Private"), "got: {}", result);
+
+        let err = execute(
+            &server,
+            &access,
+            &json!({"query": "photosynthesis", "folder": "Private"}),
+        )
+        .await
+        .unwrap_err();
+        assert!(err.contains("Access denied"), "got: {}", err);
+
+        // Unscoped token can narrow explicitly, by name or path prefix
+        let result = execute(
+            &server,
+            &default_access(),
+            &json!({"query": "photosynthesis", "folder": "Private", "path_prefix": "/Notes.md"}),
+        )
+        .await
+        .unwrap();
+        assert!(result.starts_with("1 result"), "got: {}", result);
+        assert!(result.contains("Private/Notes.md"), "got: {}", result);
+    }
+
+    #[tokio::test]
+    async fn search_field_query_shows_frontmatter() {
+        let search_index =
+            Arc::new(y_sweet_core::search_index::SearchIndex::new_in_memory().unwrap());
+        search_index
+            .add_document(
+                "doc-1",
+                "Alignment Intro",
+                "---\ntype: article\ntags: [lens, intro]\n---\nAn introduction.",
+                "Lens",
+            )
+            .unwrap();
+        search_index
+            .add_document("doc-2", "Other", "An introduction.", "Lens")
+            .unwrap();
+        let server = Server::new_for_test_with_search(search_index);
+
+        let result = execute(
+            &server,
+            &default_access(),
+            &json!({"query": "type:article"}),
+        )
+        .await
+        .unwrap();
+        assert!(result.starts_with("1 result"), "got: {}", result);
+        assert!(
+            result.contains("[type: article; tags: lens, intro]"),
+            "got: {}",
+            result
+        );
+    }
+
+    #[tokio::test]
+    async fn search_section_granularity_shows_heading_and_anchor() {
+        let search_index =
+            Arc::new(y_sweet_core::search_index::SearchIndex::new_in_memory().unwrap());
+        search_index
+            .add_document_at(
+                "doc-1",
+                "Photosynthesis",
+                "# Light reactions\nChlorophyll absorbs light.\n# Calvin cycle\nCarbon fixation.\n",
+                &y_sweet_core::search_index::DocLocation {
+                    folder: "Lens".to_string(),
+                    folder_id: "aaaa0000-0000-0000-0000-000000000000".to_string(),
+                    path: "/Photosynthesis.md".to_string(),
+                },
+            )
+            .unwrap();
+        let server = Server::new_for_test_with_search(search_index);
+
+        let result = execute(
+            &server,
+            &default_access(),
+            &json!({"query": "carbon", "granularity": "section"}),
+        )
+        .await
+        .unwrap();
+        assert!(result.starts_with("1 result"), "got: {}", result);
+        assert!(
+            result.contains("(Lens/Photosynthesis.md#calvin-cycle)"),
+            "got: {}",
+            result
+        );
+        assert!(result.contains("Calvin cycle (line 3)"), "got: {}", result);
+
+        let err = execute(
+            &server,
+            &default_access(),
+            &json!({"query": "carbon", "granularity": "section", "mode": "hybrid"}),
+        )
+        .await
+        .unwrap_err();
+        assert!(err.contains("bm25"), "got: {}", err);
+    }
+
+    #[tokio::test]
+    async fn search_reports_query_syntax_errors() {
+        let search_index =
+            Arc::new(y_sweet_core::search_index::SearchIndex::new_in_memory().unwrap());
+        search_index
+            .add_document("doc-1", "Photosynthesis", "Chlorophyll.", "Lens")
+            .unwrap();
+        let server = Server::new_for_test_with_search(search_index);
+
+        let result = execute(&server, &default_access(), &json!({"query": "chloro*"}))
+            .await
+            .unwrap();
+        assert!(result.starts_with("1 result"), "got: {}", result);
+
+        let err = execute(
+            &server,
+            &default_access(),
+            &json!({"query": "photo author:me"}),
+        )
+        .await
+        .unwrap_err();
+        assert!(err.starts_with("Invalid search query:"), "got: {}", err);
+        assert!(
+            err.contains("Unknown field 'author'") && err.contains("(at `author`)"),
+            "got: {}",
+            err
+        );
+    }
+
+    #[tokio::test]
+    async fn search_pages_with_offset_and_sorts_by_title() {
+        let search_index =
+            Arc::new(y_sweet_core::search_index::SearchIndex::new_in_memory().unwrap());
+        for (id, title) in [("doc-1", "Beta"), ("doc-2", "Alpha"), ("doc-3", "Gamma")] {
+            search_index
+                .add_document(id, title, "Notes on energy.", "Lens")
+                .unwrap();
+        }
+        let server = Server::new_for_test_with_search(search_index);
+
+        let first = execute(
+            &server,
+            &default_access(),
+            &json!({"query": "energy", "limit": 2, "sort": "title"}),
+        )
+        .await
+        .unwrap();
+        assert!(first.starts_with("Results 1-2 of 3:"), "got: {}", first);
+        assert!(first.contains("## Alpha") && first.contains("## Beta"));
+        assert!(first.contains("offset 2"), "got: {}", first);
+
+        let second = execute(
+            &server,
+            &default_access(),
+            &json!({"query": "energy", "limit": 2, "offset": 2, "sort": "title"}),
+        )
+        .await
+        .unwrap();
+        assert!(second.starts_with("Results 3-3 of 3:"), "got: {}", second);
+        assert!(second.contains("## Gamma") && !second.contains("More results"));
+
+        let err = execute(
+            &server,
+            &default_access(),
+            &json!({"query": "energy", "sort": "size"}),
+        )
+        .await
+        .unwrap_err();
+        assert!(err.contains("Invalid sort"), "got: {}", err);
+    }
 }
@@ -127,6 +127,7 @@ mod tests {
             folder_uuid: Some(FOLDER0_UUID.to_string()),
             folder_name: Some("Lens".to_string()),
             raw_token: None,
+            user: None,
         }
     }
 
@@ -11,6 +11,7 @@ pub(crate) fn default_access() -> McpAccess {
         folder_uuid: None,
         folder_name: None,
         raw_token: None,
+        user: None,
     }
 }
 
@@ -310,6 +311,133 @@ pub(crate) async fn build_blob_test_server_with_folder() -> Arc<Server> {
     server
 }
 
+/// Build a test server whose store keeps every write as a new version, like
+/// a versioned S3 bucket. The doc at `path` is persisted once per entry of
+/// `versions` (ids `v0`, `v1`, ...) and then loaded with `current` as its
+/// live text.
+pub(crate) async fn build_versioned_test_server(
+    path: &str,
+    uuid: &str,
+    versions: &[&str],
+    current: &str,
+) -> Arc<Server> {
+    use async_trait::async_trait;
+    use std::sync::Mutex;
+    use std::time::Duration;
+    use tokio_util::sync::CancellationToken;
+    use y_sweet_core::store::Result as StoreResult;
+    use y_sweet_core::store::{Store, VersionInfo};
+
+    #[derive(Clone, Default)]
+    struct VersionedStore {
+        versions: Arc<Mutex<HashMap<String, Vec<Vec<u8>>>>>,
+    }
+
+    #[async_trait]
+    impl Store for VersionedStore {
+        async fn init(&self) -> StoreResult<()> {
+            Ok(())
+        }
+        async fn get(&self, key: &str) -> StoreResult<Option<Vec<u8>>> {
+            let versions = self.versions.lock().unwrap();
+            Ok(versions.get(key).and_then(|v| v.last().cloned()))
+        }
+        async fn set(&self, key: &str, value: Vec<u8>) -> StoreResult<()> {
+            let mut versions = self.versions.lock().unwrap();
+            versions.entry(key.to_string()).or_default().push(value);
+            Ok(())
+        }
+        async fn remove(&self, _key: &str) -> StoreResult<()> {
+            Ok(())
+        }
+        async fn exists(&self, key: &str) -> StoreResult<bool> {
+            Ok(self.versions.lock().unwrap().contains_key(key))
+        }
+        async fn list_versions(&self, key: &str) -> StoreResult<Vec<VersionInfo>> {
+            let versions = self.versions.lock().unwrap();
+            let count = versions.get(key).map_or(0, Vec::len);
+            Ok((0..count)
+                .rev()
+                .map(|i| VersionInfo {
+                    version_id: format!("v{}", i),
+                    last_modified: 0,
+                    is_latest: i + 1 == count,
+                })
+                .collect())
+        }
+        async fn get_version(&self, key: &str, version_id: &str) -> StoreResult<Option<Vec<u8>>> {
+            let versions = self.versions.lock().unwrap();
+            let index = version_id
+                .strip_prefix('v')
+                .and_then(|i| i.parse::<usize>().ok());
+            Ok(index.and_then(|i| versions.get(key)?.get(i).cloned()))
+        }
+    }
+
+    let store = VersionedStore::default();
+    let doc_id = format!("{}-{}", RELAY_ID, uuid);
+
+    let persisted = DocWithSyncKv::new(
+        &doc_id,
+        Some(Arc::new(Box::new(store.clone()))),
+        || (),
+        None,
+    )
+    .await
+    .expect("Failed to create versioned DocWithSyncKv");
+    for version in versions {
+        {
+            let awareness = persisted.awareness();
+            let guard = awareness.write().unwrap();
+            let mut txn = guard.doc.transact_mut();
+            let text = txn.get_or_insert_text("contents");
+            let len = text.len(&txn);
+            text.remove_range(&mut txn, 0, len);
+            text.insert(&mut txn, 0, version);
+        }
+        persisted
+            .sync_kv()
+            .persist()
+            .await
+            .expect("persist should succeed");
+    }
+
+    let server = Arc::new(
+        Server::new_without_workers(
+            Some(Box::new(store)),
+            Duration::from_secs(60),
+            None,
+            None,
+            Vec::new(),
+            CancellationToken::new(),
+            false,
+            None,
+        )
+        .await
+        .expect("server creation should succeed"),
+    );
+
+    let folder_doc = create_folder_doc(&[(path, uuid)]);
+    set_folder_name(&folder_doc, "Lens");
+    server
+        .doc_resolver()
+        .update_folder_from_doc(&folder0_id(), &folder_doc);
+
+    let live = DocWithSyncKv::new(&doc_id, None, || (), None)
+        .await
+        .expect("Failed to create test DocWithSyncKv");
+    {
+        let awareness = live.awareness();
+        let guard = awareness.write().unwrap();
+        let mut txn = guard.doc.transact_mut();
+        txn.get_or_insert_text("contents")
+            .insert(&mut txn, 0, current);
+    }
+    server.docs().insert(doc_id, live);
+
+    server
+}
+
 /// Read the Y.Doc content back for verification.
 pub(crate) fn read_doc_content(server: &Arc<Server>, doc_id: &str) -> String {
     let doc_ref = server.docs().get(doc_id).expect("doc should exist");
@@ -8,14 +8,14 @@ use axum::{
 use serde_json::Value;
 use std::sync::Arc;
 use tracing::debug;
-use y_sweet_core::share_token::{decode_mcp_key, McpAccess};
+use y_sweet_core::share_token::McpAccess;
 
 use super::jsonrpc::{self, parse_message, JsonRpcMessage, JsonRpcResponse, PARSE_ERROR};
 use super::router;
 use crate::server::Server;
 
 /// Middleware that validates Bearer token auth for MCP endpoints.
-/// Decodes the token via `decode_mcp_key()` and inserts `McpAccess` into request extensions.
+/// Decodes the token via `Server::decode_mcp_key()` and inserts `McpAccess` into request extensions.
 pub async fn mcp_auth_middleware(
     State(server): State<Arc<Server>>,
     mut req: axum::extract::Request,
@@ -29,11 +29,7 @@ pub async fn mcp_auth_middleware(
     match auth_header {
         Some(value) if value.starts_with("Bearer ") => {
             let token = &value["Bearer ".len()..];
-            match decode_mcp_key(
-                token,
-                server.share_token_secret.as_deref(),
-                server.mcp_api_key.as_deref(),
-            ) {
+            match server.decode_mcp_key(token) {
                 Some(access) => {
                     req.extensions_mut().insert(access);
                     next.run(req).await
@@ -141,12 +137,9 @@ pub async fn handle_mcp_delete() -> Response {
 
 /// Decode the API key from the URL path into McpAccess. Returns Err(401) on failure.
 fn decode_path_key(server: &Server, key: &str) -> Result<McpAccess, Response> {
-    decode_mcp_key(
-        key,
-        server.share_token_secret.as_deref(),
-        server.mcp_api_key.as_deref(),
-    )
-    .ok_or_else(|| StatusCode::UNAUTHORIZED.into_response())
+    server
+        .decode_mcp_key(key)
+        .ok_or_else(|| StatusCode::UNAUTHORIZED.into_response())
 }
 
 /// Handle POST /mcp/:key — same as handle_mcp_post but auth via URL path.
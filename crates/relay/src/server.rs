@@ -13,7 +13,7 @@ use axum::{
     },
     middleware::{self, Next},
     response::{IntoResponse, Response},
-    routing::{delete, get, head, post},
+    routing::{delete, get, head, post, put},
     Json, Router,
 };
 use axum_extra::typed_header::TypedHeader;
@@ -39,31 +39,54 @@ use tokio_util::{sync::CancellationToken, task::TaskTracker};
 use tracing::{span, Instrument, Level};
 use url::Url;
 use y_sweet_core::{
+    acl::{self, AclPolicy, AclRole},
     api_types::{
         validate_doc_name, validate_file_hash, AuthDocRequest, Authorization, ClientToken,
-        DocCreationRequest, DocumentVersionEntry, DocumentVersionResponse, FileDownloadUrlResponse,
-        FileHistoryEntry, FileHistoryResponse, FileUploadUrlResponse, NewDocResponse,
+        DocCreationRequest, DocumentRestoreResponse, DocumentVersionDiffResponse,
+        DocumentVersionEntry, DocumentVersionResponse, DocumentVersionTextResponse,
+        FileDownloadUrlResponse, FileHistoryEntry, FileHistoryResponse, FileUploadUrlResponse,
+        FolderSnapshotInfo, FolderSnapshotRequest, FolderSnapshotRestoreResponse, NewDocResponse,
+        RegressionRestoreResponse,
     },
     auth::{Authenticator, ExpirationTimeEpochMillis, Permission, DEFAULT_EXPIRATION_SECONDS},
+    content_regression::{RegressionConfig, RegressionDetector, RegressionEvent},
     critic_scanner,
     doc_connection::DocConnection,
+    doc_dump,
     doc_resolver::{DocInfo, DocumentResolver},
-    doc_sync::DocWithSyncKv,
+    doc_sync::{doc_from_snapshot, DocWithSyncKv},
+    embedding,
     event::{
         DebouncedSyncProtocolEventSender, DocumentUpdatedEvent, EventDispatcher, EventEnvelope,
         EventSender, SyncProtocolEventSender, UnifiedEventDispatcher, WebhookSender,
+        DOCUMENT_DELETED_EVENT_TYPE,
     },
+    filemeta_guard::{
+        FilemetaGuard, FilemetaGuardConfig, GuardAlert, GuardMode, FILEMETA_GUARD_EVENT_TYPE,
+    },
+    frontmatter,
+    fsck::{self, FolderFsckReport, FsckIssue, FsckIssueKind, FsckReport},
     link_indexer::{self, LinkIndexer},
     metrics::RelayMetrics,
-    search_index::SearchIndex,
-    store::Store,
+    search_index::{
+        DocLocation, SearchFacets, SearchGranularity, SearchIndex, SearchMode, SearchOptions,
+        SearchSort,
+    },
+    search_query::QueryParseError,
+    share_revocation::{self, token_fingerprint, RevocationList, RevokedFolder, RevokedToken},
+    share_token::{self, McpAccess},
+    store::{Store, VersionInfo},
     suggestions_index::SuggestionsIndex,
     sync::awareness::Awareness,
     sync_kv::SyncKv,
+    trash::{self, RestoreError, TrashItem, TRASH_ORIGIN},
     webhook::WebhookConfig,
 };
 use yrs::{GetString, Map, ReadTxn, Text, Transact, WriteTxn};
 
+use crate::folder_export::{self, ExportSource, ExportView, StoreExportSource};
+use crate::vault_import::{self, VaultEntryKind, VaultImportReport, VAULT_IMPORT_ORIGIN};
+
 const RELAY_SERVER_VERSION: &str = env!("GIT_VERSION");
 
 #[derive(Clone, Debug)]
@@ -72,7 +95,7 @@ pub struct AllowedHost {
     pub scheme: String, // "http" or "https"
 }
 
-fn current_time_epoch_millis() -> u64 {
+pub(crate) fn current_time_epoch_millis() -> u64 {
     let now = std::time::SystemTime::now();
     let duration_since_epoch = now.duration_since(std::time::UNIX_EPOCH).unwrap();
     duration_since_epoch.as_millis() as u64
@@ -120,7 +143,7 @@ fn validate_file_token(
         )
     })?;
 
-    let permission = authenticator
+    let mut permission = authenticator
         .verify_token_auto(token, current_time_epoch_millis())
         .map_err(|auth_error| {
             AppError::auth(
@@ -130,7 +153,7 @@ fn validate_file_token(
             )
         })?;
 
-    match &permission {
+    match &mut permission {
         Permission::File(file_permission) => {
             if file_permission.doc_id != doc_id {
                 return Err(AppError::auth(
@@ -139,6 +162,11 @@ fn validate_file_token(
                     "access_wrong_document",
                 ));
             }
+            file_permission.authorization = server_state.authorize_doc_acl(
+                doc_id,
+                file_permission.user.as_deref(),
+                file_permission.authorization,
+            )?;
         }
         _ => {
             return Err(AppError::auth(
@@ -295,6 +323,52 @@ pub struct CreateDocumentResult {
     pub in_folder_path: String,
 }
 
+/// Error type for `Server::delete_path()` that preserves HTTP status code semantics.
+#[derive(Debug)]
+pub enum DeleteDocumentError {
+    /// 400: the path is not a markdown document
+    BadRequest(String),
+    /// 404: path not found
+    NotFound(String),
+    /// 500: internal error
+    Internal(String),
+}
+
+impl std::fmt::Display for DeleteDocumentError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::BadRequest(msg) => write!(f, "{}", msg),
+            Self::NotFound(msg) => write!(f, "{}", msg),
+            Self::Internal(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl std::error::Error for DeleteDocumentError {}
+
+impl From<DeleteDocumentError> for AppError {
+    fn from(e: DeleteDocumentError) -> Self {
+        let status = match &e {
+            DeleteDocumentError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            DeleteDocumentError::NotFound(_) => StatusCode::NOT_FOUND,
+            DeleteDocumentError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        AppError::new(status, anyhow!("{}", e))
+    }
+}
+
+/// Result of a successful `Server::delete_path()` call.
+#[derive(Debug, Serialize)]
+pub struct DeleteDocumentResult {
+    pub uuid: String,
+    pub doc_id: String,
+    pub folder: String,
+    /// In-folder path the document had (e.g. "/Notes/Idea.md").
+    pub path: String,
+    /// Backlink arrays the document was removed from.
+    pub backlinks_removed: usize,
+}
+
 fn validate_file_path(path: &str) -> std::result::Result<(), &'static str> {
     if path.contains('"') {
         return Err("File names cannot contain double quotes");
@@ -332,15 +406,113 @@ struct SearchQuery {
     q: String,
     #[serde(default = "default_search_limit")]
     limit: usize,
+    /// Ranking mode: `bm25` (default), `hybrid` or `semantic`.
+    #[serde(default)]
+    mode: Option<String>,
+    /// Restrict to one folder, by name or UUID.
+    #[serde(default)]
+    folder: Option<String>,
+    /// Restrict to documents at or under this in-folder path, e.g. `/Biology`.
+    #[serde(default)]
+    path_prefix: Option<String>,
+    /// Hit unit: `document` (default) or `section` (per markdown heading).
+    #[serde(default)]
+    granularity: Option<String>,
+    /// Number of hits to skip.
+    #[serde(default)]
+    offset: Option<usize>,
+    /// `next_cursor` from a previous response; an alternative to `offset`.
+    #[serde(default)]
+    cursor: Option<String>,
+    /// Result order: `relevance` (default), `modified`, `title` or `path`.
+    #[serde(default)]
+    sort: Option<String>,
+    /// User the ACL policy is evaluated for; anonymous when absent. Only
+    /// server-token callers reach this route, so this is impersonation: a proxy
+    /// must set it from its own caller's credentials, never pass it through.
+    #[serde(default)]
+    user: Option<String>,
 }
 
 fn default_search_limit() -> usize {
     20
 }
 
+/// Deepest hit `/search` pages to. Sorted pages rank every match, so the
+/// cap bounds the work per request rather than the result set.
+const MAX_SEARCH_OFFSET: usize = 10_000;
+
+#[derive(Deserialize)]
+struct DocDiffQuery {
+    /// Version id to diff from.
+    from: String,
+    /// Version id to diff to; the live doc when omitted.
+    #[serde(default)]
+    to: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct RestoreVersionQuery {
+    /// Propose the rollback as CriticMarkup suggestions instead of applying it.
+    #[serde(default)]
+    as_suggestions: bool,
+}
+
+/// CriticMarkup author for suggestions proposed by a version restore that
+/// has no MCP session behind it.
+const RESTORE_SUGGESTION_AUTHOR: &str = "Restore";
+
+/// Store prefix for named folder snapshots. A snapshot is
+/// `.snapshots/{folder_doc_id}/{name}.json` (its manifest) plus one
+/// `.snapshots/{folder_doc_id}/{name}/{doc_id}.ysweet` per captured doc.
+/// None of these keys end in `/data.ysweet`, so they never show up as docs.
+const FOLDER_SNAPSHOT_PREFIX: &str = ".snapshots";
+
+/// Store prefix for client updates held back by the filemeta guard in
+/// quarantine mode: `.config/quarantine/{doc_id}/{epoch_ms}.update`, each the
+/// raw v1-encoded update.
+const FILEMETA_QUARANTINE_PREFIX: &str = ".config/quarantine";
+
+/// Store prefix for the pre-regression state of docs the regression detector
+/// flagged: `.config/regressions/{doc_id}/{event_id}.ysweet`, a copy of the
+/// doc's `data.ysweet` from the checkpoint before the regression.
+const REGRESSION_SNAPSHOT_PREFIX: &str = ".config/regressions";
+
+/// Folder doc maps a folder snapshot restore rolls back. `docs` is the
+/// legacy path map that moves keep in step with `filemeta_v0`.
+const FOLDER_SNAPSHOT_MAPS: [&str; 3] = ["filemeta_v0", "docs", "backlinks_v0"];
+
+#[derive(Deserialize)]
+struct FolderExportQuery {
+    #[serde(default)]
+    view: ExportView,
+}
+
+#[derive(Deserialize)]
+struct VaultImportRequest {
+    /// Vault directory on the server's filesystem.
+    path: String,
+    /// Display name of the folder to import into; created if missing.
+    folder: String,
+    /// Relay ID for a new folder doc. Defaults to that of an existing folder.
+    relay_id: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct DumpQuery {
+    doc_id: Option<String>,
+    /// Folder display name, resolved to its folder doc.
+    folder: Option<String>,
+}
+
 #[derive(Deserialize)]
 struct SuggestionsQuery {
     folder_id: String,
+    /// User the ACL policy is evaluated for; anonymous when absent. Only
+    /// server-token callers reach this route, so this is impersonation: a proxy
+    /// must set it from its own caller's credentials, never pass it through.
+    #[serde(default)]
+    user: Option<String>,
 }
 
 #[derive(Deserialize)]
@@ -357,6 +529,11 @@ struct MovePathRequest {
     target_folder: Option<String>,
 }
 
+#[derive(Deserialize)]
+struct DeletePathQuery {
+    path: String,
+}
+
 #[derive(Serialize)]
 struct MoveDocResponse {
     old_path: String,
@@ -477,7 +654,7 @@ async fn search_worker(
 ) {
     tracing::info!("Search index worker started");
 
-    // Cache of folder doc -> { uuid -> (path, title) } for detecting adds/removes
+    // Cache of folder doc -> { uuid -> path } for detecting adds/removes/moves
     let filemeta_cache: DashMap<String, std::collections::HashMap<String, String>> = DashMap::new();
 
     loop {
@@ -628,12 +805,12 @@ fn suggestions_fast_scan(
     true
 }
 
-/// Build uuid -> (title, folder_name) for every doc listed in any folder's
+/// Build uuid -> (title, location) for every doc listed in any folder's
 /// filemeta_v0, in one pass. The per-tick heavy lane uses this instead of
 /// scanning every folder doc per content doc.
 fn build_title_folder_cache(
     docs: &DashMap<String, DocWithSyncKv>,
-) -> std::collections::HashMap<String, (String, String)> {
+) -> std::collections::HashMap<String, (String, DocLocation)> {
     let mut cache = std::collections::HashMap::new();
     for folder_doc_id in link_indexer::find_all_folder_docs(docs) {
         let awareness = {
@@ -656,7 +833,7 @@ fn build_title_folder_cache(
                     id,
                     (
                         link_indexer::title_from_filemeta_path(&path),
-                        folder_name.clone(),
+                        search_doc_location(&folder_doc_id, &folder_name, &path),
                     ),
                 );
             }
@@ -686,7 +863,7 @@ fn search_handle_content_update_inner(
     docs: &DashMap<String, DocWithSyncKv>,
     search_index: &SearchIndex,
     suggestions_index: &SuggestionsIndex,
-    titles: Option<&std::collections::HashMap<String, (String, String)>>,
+    titles: Option<&std::collections::HashMap<String, (String, DocLocation)>>,
     update_suggestions: bool,
 ) {
     let Some((_relay_id, doc_uuid)) = link_indexer::parse_doc_id(doc_id) else {
@@ -723,26 +900,37 @@ fn search_handle_content_update_inner(
         body
     };
 
-    // Find which folder doc contains this UUID and extract title
-    let (title, folder_name) = match titles {
+    // Find which folder doc contains this UUID and extract title and location
+    let (title, location) = match titles {
         Some(cache) => cache
             .get(doc_uuid)
             .cloned()
-            .unwrap_or_else(|| (doc_uuid.to_string(), "Unknown".to_string())),
+            .unwrap_or_else(|| (doc_uuid.to_string(), DocLocation::in_folder("Unknown"))),
         None => search_find_title_and_folder(doc_uuid, docs),
     };
 
-    match search_index.add_document(doc_uuid, &title, &body, &folder_name) {
+    match search_index.add_document_at(doc_uuid, &title, &body, &location) {
         Ok(()) => tracing::debug!("Search indexed content doc: {} ({})", doc_uuid, title),
         Err(e) => tracing::error!("Search index failed for {}: {:?}", doc_uuid, e),
     }
 }
 
-/// Find the title and folder name for a content doc UUID by scanning all folder docs' filemeta_v0.
+/// Search-index location of a filemeta entry in the given folder doc.
+fn search_doc_location(folder_doc_id: &str, folder_name: &str, path: &str) -> DocLocation {
+    DocLocation {
+        folder: folder_name.to_string(),
+        folder_id: link_indexer::parse_doc_id(folder_doc_id)
+            .map(|(_, uuid)| uuid.to_string())
+            .unwrap_or_default(),
+        path: path.to_string(),
+    }
+}
+
+/// Find the title and location for a content doc UUID by scanning all folder docs' filemeta_v0.
 fn search_find_title_and_folder(
     doc_uuid: &str,
     docs: &DashMap<String, DocWithSyncKv>,
-) -> (String, String) {
+) -> (String, DocLocation) {
     let folder_doc_ids = link_indexer::find_all_folder_docs(docs);
 
     for folder_doc_id in &folder_doc_ids {
@@ -764,14 +952,17 @@ fn search_find_title_and_folder(
                     let title = link_indexer::title_from_filemeta_path(&path);
                     let folder_name =
                         y_sweet_core::doc_resolver::read_folder_name(&guard.doc, folder_doc_id);
-                    return (title, folder_name);
+                    return (
+                        title,
+                        search_doc_location(folder_doc_id, &folder_name, &path),
+                    );
                 }
             }
         }
     }
 
     // Not found in any folder doc — use UUID as title
-    (doc_uuid.to_string(), "Unknown".to_string())
+    (doc_uuid.to_string(), DocLocation::in_folder("Unknown"))
 }
 
 /// Handle folder doc update: detect added/removed UUIDs, update search index accordingly.
@@ -783,7 +974,7 @@ async fn search_handle_folder_update(
     filemeta_cache: &DashMap<String, std::collections::HashMap<String, String>>,
     suggestions_index: &SuggestionsIndex,
 ) {
-    // Build current uuid -> title map from filemeta
+    // Build current uuid -> path map from filemeta
     let (current_map, folder_name): (std::collections::HashMap<String, String>, String) = {
         let awareness = {
             let Some(doc_ref) = docs.get(folder_doc_id) else {
@@ -800,17 +991,25 @@ async fn search_handle_folder_update(
         let mut map = std::collections::HashMap::new();
         for (path, value) in filemeta.iter(&txn) {
             if let Some(id) = link_indexer::extract_id_from_filemeta_entry(&value, &txn) {
-                map.insert(id, link_indexer::title_from_filemeta_path(&path));
+                map.insert(id, path.to_string());
             }
         }
         let folder_name = y_sweet_core::doc_resolver::read_folder_name(&guard.doc, folder_doc_id);
         (map, folder_name)
     };
-    // Titles for this folder's docs, so the reindex calls below don't each
-    // rescan every folder doc.
-    let titles: std::collections::HashMap<String, (String, String)> = current_map
+    // Titles and locations for this folder's docs, so the reindex calls
+    // below don't each rescan every folder doc.
+    let titles: std::collections::HashMap<String, (String, DocLocation)> = current_map
         .iter()
-        .map(|(uuid, title)| (uuid.clone(), (title.clone(), folder_name.clone())))
+        .map(|(uuid, path)| {
+            (
+                uuid.clone(),
+                (
+                    link_indexer::title_from_filemeta_path(path),
+                    search_doc_location(folder_doc_id, &folder_name, path),
+                ),
+            )
+        })
         .collect();
 
     // Get old snapshot from cache
@@ -835,14 +1034,14 @@ async fn search_handle_folder_update(
             }
         }
 
-        // Detect added or renamed UUIDs — queue them for content indexing
+        // Detect added, renamed or moved UUIDs — queue them for content indexing
         let Some((relay_id, _)) = link_indexer::parse_doc_id(folder_doc_id) else {
             return;
         };
-        for (uuid, new_title) in &current_map {
-            let old_title = old_map.get(uuid);
-            if old_title.is_none() || old_title != Some(new_title) {
-                // New or renamed — reindex content
+        for (uuid, new_path) in &current_map {
+            let old_path = old_map.get(uuid);
+            if old_path.is_none() || old_path != Some(new_path) {
+                // New, renamed or moved — reindex content (title and path)
                 let content_id = format!("{}-{}", relay_id, uuid);
                 if docs.contains_key(&content_id) {
                     search_handle_content_update_inner(
@@ -910,6 +1109,93 @@ pub struct Server {
     last_successful_persist: Arc<AtomicU64>,
     /// Cross-channel state shared between worker supervisor and /ready endpoint.
     pub(crate) worker_status: Arc<crate::worker_status::WorkerStatusMap>,
+    /// How client updates to folder docs' filemeta_v0 are vetted.
+    filemeta_guard: FilemetaGuardConfig,
+    /// Watches content docs for wipes and reversions at each checkpoint.
+    regression_detector: Arc<RegressionDetector>,
+    /// Folders mirrored into a git repository, when enabled.
+    git_sync: Option<crate::git_sync::GitSyncConfig>,
+    /// How long trashed content is kept, and how often expired trash is purged.
+    trash: crate::trash::TrashConfig,
+    /// Path-level access rules, loaded from `.config/acl.json`.
+    acl: Arc<RwLock<AclPolicy>>,
+    /// Discord OAuth login, when configured.
+    discord: Option<Arc<crate::discord_auth::DiscordAuth>>,
+    /// Revoked share tokens, loaded from `.config/share_revocations.json`.
+    share_revocations: Arc<RwLock<RevocationList>>,
+    /// Held across a revocation update and its save, so concurrent updates
+    /// reach the store in the order they were applied.
+    share_revocations_update: Arc<tokio::sync::Mutex<()>>,
+}
+
+/// What a doc's persistence worker needs to run the regression detector
+/// before each checkpoint. The awareness is weak so the worker does not keep
+/// the doc from being GC'd.
+struct RegressionWatch {
+    detector: Arc<RegressionDetector>,
+    awareness: std::sync::Weak<RwLock<Awareness>>,
+    store: Option<Arc<Box<dyn Store>>>,
+    metrics: Arc<RelayMetrics>,
+}
+
+impl RegressionWatch {
+    /// Compare the doc's current text with earlier checkpoints. Must run
+    /// before the checkpoint is persisted: a flagged doc's `data.ysweet` is
+    /// then still the pre-regression state, and is copied aside.
+    async fn check(&self, doc_id: &str) {
+        let text = {
+            let Some(awareness) = self.awareness.upgrade() else {
+                return;
+            };
+            let awareness = awareness.read().unwrap_or_else(|e| e.into_inner());
+            let txn = awareness.doc().transact();
+            match txn.get_text("contents") {
+                Some(text) => text.get_string(&txn),
+                // Folder docs and other docs without markdown text.
+                None => return,
+            }
+        };
+        let Some(mut event) = self
+            .detector
+            .observe(doc_id, &text, current_time_epoch_millis())
+        else {
+            return;
+        };
+        self.metrics.record_content_regression(event.kind.as_str());
+
+        if self.detector.config().snapshots {
+            if let Some(store) = &self.store {
+                let key = format!(
+                    "{}/{}/{}.ysweet",
+                    REGRESSION_SNAPSHOT_PREFIX, doc_id, event.id
+                );
+                match store.get(&format!("{}/data.ysweet", doc_id)).await {
+                    Ok(Some(data)) => match store.set(&key, data).await {
+                        Ok(()) => event.snapshot_key = Some(key),
+                        Err(e) => {
+                            tracing::error!("Failed to save regression snapshot {}: {}", key, e)
+                        }
+                    },
+                    Ok(None) => {}
+                    Err(e) => tracing::error!(
+                        "Failed to read {} for a regression snapshot: {}",
+                        doc_id,
+                        e
+                    ),
+                }
+            }
+        }
+
+        tracing::warn!(
+            doc_id = %doc_id,
+            kind = event.kind.as_str(),
+            event_id = %event.id,
+            snapshot = ?event.snapshot_key,
+            "Content regression detected: {}",
+            event.detail
+        );
+        self.detector.record(event);
+    }
 }
 
 /// Holds channel receivers for background workers.
@@ -976,23 +1262,52 @@ impl Server {
         let (link_indexer, index_rx) = LinkIndexer::new();
         let link_indexer = Arc::new(link_indexer);
 
-        // Create SearchIndex with MmapDirectory in a fresh, instance-unique
-        // temp directory. The index is rebuilt from scratch on every start,
-        // and a unique path keeps parallel test Servers (and a restarting
-        // prod process) from deleting each other's live index directory.
+        // Create SearchIndex with MmapDirectory. RELAY_SEARCH_INDEX_DIR keeps
+        // the index across restarts, so startup only reindexes changed docs;
+        // the directory belongs to the index and is wiped on schema changes.
+        // Without it the index lives in a fresh, instance-unique temp
+        // directory and is rebuilt from scratch on every start; a unique path
+        // keeps parallel test Servers (and a restarting prod process) from
+        // deleting each other's live index directory.
         static SEARCH_INDEX_INSTANCE: std::sync::atomic::AtomicU64 =
             std::sync::atomic::AtomicU64::new(0);
-        let index_path = std::env::temp_dir().join(format!(
-            "lens-relay-search-index-{}-{}",
-            std::process::id(),
-            SEARCH_INDEX_INSTANCE.fetch_add(1, std::sync::atomic::Ordering::Relaxed)
-        ));
-        if index_path.exists() {
-            let _ = std::fs::remove_dir_all(&index_path);
-        }
+        let index_path = match std::env::var("RELAY_SEARCH_INDEX_DIR") {
+            Ok(dir) if !dir.trim().is_empty() => std::path::PathBuf::from(dir.trim()),
+            _ => {
+                let index_path = std::env::temp_dir().join(format!(
+                    "lens-relay-search-index-{}-{}",
+                    std::process::id(),
+                    SEARCH_INDEX_INSTANCE.fetch_add(1, std::sync::atomic::Ordering::Relaxed)
+                ));
+                if index_path.exists() {
+                    let _ = std::fs::remove_dir_all(&index_path);
+                }
+                index_path
+            }
+        };
         let search_index = match SearchIndex::new(&index_path) {
             Ok(si) => {
                 tracing::info!("SearchIndex created at {:?}", index_path);
+                // Embeddings for hybrid/semantic search. Off unless
+                // RELAY_SEARCH_EMBEDDING names a provider ("http" or "hashing").
+                let provider_name = std::env::var("RELAY_SEARCH_EMBEDDING").unwrap_or_default();
+                let si = match embedding::provider_by_name(&provider_name) {
+                    Ok(Some(provider)) => {
+                        tracing::info!("Search embeddings enabled ({})", provider.model_id());
+                        if provider.name() == "hashing" {
+                            tracing::warn!(
+                                "Hashing embeddings match shared words, not meaning; \
+                                 use RELAY_SEARCH_EMBEDDING=http for semantic search"
+                            );
+                        }
+                        si.with_embedding_provider(provider)
+                    }
+                    Ok(None) => si,
+                    Err(e) => {
+                        tracing::error!("{}; hybrid search disabled", e);
+                        si
+                    }
+                };
                 Some(Arc::new(si))
             }
             Err(e) => {
@@ -1027,6 +1342,58 @@ impl Server {
             );
         }
 
+        let filemeta_guard = FilemetaGuardConfig::from_env();
+        tracing::info!(
+            mode = filemeta_guard.mode.as_str(),
+            max_deletions = filemeta_guard.max_deletions,
+            "Filemeta guard configured"
+        );
+
+        let git_sync = crate::git_sync::GitSyncConfig::from_env();
+        if let Some(config) = &git_sync {
+            tracing::info!(
+                repo = %config.repo_path.display(),
+                folders = ?config.folders,
+                remote = ?config.remote,
+                "Git sync enabled"
+            );
+        }
+
+        let trash = crate::trash::TrashConfig::from_env();
+        tracing::info!(
+            retention_days = trash.retention.as_secs() / 86400,
+            "Trash configured"
+        );
+
+        // A policy that fails to load stops startup rather than leaving
+        // every path open.
+        let acl = match &store {
+            Some(store) => acl::load_policy(&**store).await?,
+            None => AclPolicy::default(),
+        };
+        tracing::info!(folders = acl.folders.len(), "ACL policy loaded");
+
+        // Like the ACL, a list that fails to load stops startup rather than
+        // letting revoked links back in.
+        let share_revocations = match &store {
+            Some(store) => share_revocation::load_revocations(&**store).await?,
+            None => RevocationList::default(),
+        };
+        tracing::info!(
+            tokens = share_revocations.tokens.len(),
+            folders = share_revocations.folders.len(),
+            "Share token revocations loaded"
+        );
+
+        let discord = crate::discord_auth::DiscordAuthConfig::from_env().map(|config| {
+            tracing::info!(
+                guild_id = %config.guild_id,
+                redirect_uri = %config.redirect_uri,
+                "Discord login enabled"
+            );
+            Arc::new(crate::discord_auth::DiscordAuth::new(config))
+        });
+
         let server = Self {
             docs,
             doc_worker_tracker: TaskTracker::new(),
@@ -1054,6 +1421,14 @@ impl Server {
             last_dirty_signal: Arc::new(AtomicU64::new(0)),
             last_successful_persist: Arc::new(AtomicU64::new(0)),
             worker_status: Arc::new(crate::worker_status::WorkerStatusMap::new()),
+            filemeta_guard,
+            regression_detector: Arc::new(RegressionDetector::new(RegressionConfig::from_env())),
+            git_sync,
+            trash,
+            acl: Arc::new(RwLock::new(acl)),
+            discord,
+            share_revocations: Arc::new(RwLock::new(share_revocations)),
+            share_revocations_update: Arc::new(tokio::sync::Mutex::new(())),
         };
 
         let receivers = WorkerReceivers {
@@ -1195,7 +1570,59 @@ impl Server {
             });
         }
 
-        tracing::info!("Background workers started (link indexer, search index)");
+        // Spawn supervised trash worker: trashes entries clients delete and
+        // purges expired trash.
+        {
+            let mut tracker = crate::trash::TrashTracker::new(self.trash.clone());
+            let server = self.clone();
+            let metrics_for_trash = self.metrics.clone();
+            let metrics_for_hook = self.metrics.clone();
+            let status_for_trash = self.worker_status.clone();
+            let status_for_hook = self.worker_status.clone();
+            let cancel_for_trash = self.cancellation_token.clone();
+            self.metrics.set_worker_alive("trash", true);
+            self.worker_status.register("trash");
+            tokio::spawn(async move {
+                let outcome = crate::supervisor::supervise(
+                    "trash",
+                    &mut tracker,
+                    |tracker| {
+                        let server = server.clone();
+                        let cancel = server.cancellation_token.clone();
+                        Box::pin(async move { tracker.run(server, cancel).await })
+                    },
+                    move |worker, msg, _attempt, _budget| {
+                        metrics_for_hook.record_worker_panic(worker);
+                        status_for_hook.record_panic("trash", msg);
+                        let _ = worker;
+                    },
+                )
+                .await;
+                handle_worker_outcome(
+                    "trash",
+                    outcome,
+                    &metrics_for_trash,
+                    &status_for_trash,
+                    cancel_for_trash,
+                )
+                .await;
+            });
+        }
+
+        // Spawn git sync. Opening the mirror may fetch from the remote, so
+        // it happens in the task rather than blocking startup.
+        if let Some(config) = self.git_sync.clone() {
+            let server = self.clone();
+            let cancel = self.cancellation_token.clone();
+            tokio::spawn(async move {
+                match crate::git_sync::GitSync::open(config).await {
+                    Ok(sync) => sync.run(server, cancel).await,
+                    Err(e) => tracing::error!("Git sync disabled: {:?}", e),
+                }
+            });
+        }
+
+        tracing::info!("Background workers started (link indexer, search index, trash)");
     }
 
     /// Periodically prune idle MCP app sessions. Runs every 5 minutes until
@@ -1321,6 +1748,324 @@ impl Server {
         None
     }
 
+    /// Full doc ID of the folder doc with the given folder UUID.
+    pub fn folder_doc_id_for_uuid(&self, folder_uuid: &str) -> Option<String> {
+        link_indexer::find_all_folder_docs(&self.docs)
+            .into_iter()
+            .find(|folder_doc_id| {
+                link_indexer::parse_doc_id(folder_doc_id).map(|(_, fid)| fid) == Some(folder_uuid)
+            })
+    }
+
+    /// Full doc ID of the loaded folder doc whose `folder_config.name` is
+    /// `name`.
+    pub fn folder_doc_id_for_name(&self, name: &str) -> Option<String> {
+        link_indexer::find_all_folder_docs(&self.docs)
+            .into_iter()
+            .find(|folder_doc_id| {
+                let Some(doc_ref) = self.docs.get(folder_doc_id) else {
+                    return false;
+                };
+                let awareness = doc_ref.awareness();
+                let guard = awareness.read().unwrap_or_else(|e| e.into_inner());
+                y_sweet_core::doc_resolver::read_folder_name(&guard.doc, folder_doc_id) == name
+            })
+    }
+
+    /// The ACL policy currently enforced.
+    pub fn acl_policy(&self) -> AclPolicy {
+        self.acl.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Replace the enforced ACL policy, saving it to the store when there
+    /// is one.
+    pub async fn set_acl_policy(&self, policy: AclPolicy) -> Result<()> {
+        policy.validate().map_err(anyhow::Error::msg)?;
+        if let Some(store) = &self.store {
+            acl::save_policy(&***store, &policy).await?;
+        }
+        *self.acl.write().unwrap_or_else(|e| e.into_inner()) = policy;
+        Ok(())
+    }
+
+    /// The role the ACL policy gives `user` on a user-facing path
+    /// ("Lens/Drafts/Week 1.md", or "Lens" for the folder itself). None
+    /// when no rule covers it.
+    pub fn acl_role_for_path(&self, path: &str, user: Option<&str>) -> Option<AclRole> {
+        let acl = self.acl.read().unwrap_or_else(|e| e.into_inner());
+        if acl.is_empty() {
+            return None;
+        }
+        // A path with `.` or `..` segments names nothing stored; deny it
+        // rather than guess which path it means.
+        let Some(path) = acl::canonical_path(path) else {
+            return Some(AclRole::None);
+        };
+        let (folder_name, in_folder) = path.split_once('/').unwrap_or((&path, ""));
+        let folder_doc_id = match self.doc_resolver.resolve_path(&path) {
+            Some(info) => Some(info.folder_doc_id),
+            None => self.folder_doc_id_for_name(folder_name),
+        };
+        let folder_uuid = folder_doc_id
+            .as_deref()
+            .and_then(link_indexer::parse_doc_id)
+            .map(|(_, uuid)| uuid.to_string());
+        let mut keys = vec![folder_name];
+        keys.extend(folder_uuid.as_deref());
+        acl.role_for(&keys, &format!("/{}", in_folder), user)
+    }
+
+    /// Why moving `source` to `destination` would loosen the ACL, if it
+    /// would. Rules don't move with a subtree, so each path under `source`
+    /// that a rule is rooted at is checked at both ends, for `user`,
+    /// everyone, and each user a rule there names: the move is refused when
+    /// the destination grants any of them more than the source did (no rule
+    /// counting as unrestricted).
+    pub fn acl_move_loosens(
+        &self,
+        source: &str,
+        destination: &str,
+        user: Option<&str>,
+    ) -> Option<String> {
+        let (Some(source), Some(destination)) = (
+            acl::canonical_path(source),
+            acl::canonical_path(destination),
+        ) else {
+            return Some("paths with '.' or '..' segments can't be moved".into());
+        };
+        let rules = {
+            let acl = self.acl.read().unwrap_or_else(|e| e.into_inner());
+            if acl.is_empty() {
+                return None;
+            }
+            let (folder_name, in_folder) = source.split_once('/').unwrap_or((&source, ""));
+            let folder_uuid = self
+                .folder_doc_id_for_name(folder_name)
+                .as_deref()
+                .and_then(link_indexer::parse_doc_id)
+                .map(|(_, uuid)| uuid.to_string());
+            let mut keys = vec![folder_name];
+            keys.extend(folder_uuid.as_deref());
+            let prefix = if in_folder.is_empty() {
+                String::new()
+            } else {
+                format!("/{}", in_folder)
+            };
+            acl.rules_under(&keys, &prefix)
+                .into_iter()
+                .map(|(path, rule_user)| (path[prefix.len()..].to_string(), rule_user))
+                .collect::<Vec<_>>()
+        };
+        let mut subjects: Vec<Option<&str>> = vec![user, None];
+        subjects.extend(rules.iter().map(|(_, rule_user)| rule_user.as_deref()));
+        subjects.sort();
+        subjects.dedup();
+        let effective = |path: &str, subject: Option<&str>| {
+            self.acl_role_for_path(path, subject)
+                .unwrap_or(AclRole::Edit)
+        };
+        rules.iter().find_map(|(rest, _)| {
+            let from = format!("{}{}", source, rest);
+            let to = format!("{}{}", destination, rest);
+            subjects.iter().find_map(|subject| {
+                let (before, after) = (effective(&from, *subject), effective(&to, *subject));
+                (after > before).then(|| {
+                    format!(
+                        "'{}' gives {} '{}' access but '{}' would give '{}'",
+                        from,
+                        subject.unwrap_or("everyone"),
+                        before.as_str(),
+                        to,
+                        after.as_str()
+                    )
+                })
+            })
+        })
+    }
+
+    /// The role the ACL policy gives `user` on a doc: content docs by their
+    /// path, folder docs as the folder root. None when no rule covers it or
+    /// the doc is in no known folder.
+    ///
+    /// A folder doc is one Y.Doc whose filemeta lists every path in the
+    /// folder, and sync can't filter it per path. So whoever may open the
+    /// folder root sees the names of files under paths denied to them, and
+    /// a role granted only below the root (edit on `/Sub/**`) does not let
+    /// them register new files there over sync.
+    pub fn acl_role_for_doc(&self, doc_id: &str, user: Option<&str>) -> Option<AclRole> {
+        if self
+            .acl
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .is_empty()
+        {
+            return None;
+        }
+        let (_, uuid) = link_indexer::parse_doc_id(doc_id)?;
+        if let Some(path) = self.doc_resolver.path_for_uuid(uuid) {
+            return self.acl_role_for_path(&path, user);
+        }
+        let (_, folder_name) = self.folder_of_doc(doc_id)?;
+        self.acl.read().unwrap_or_else(|e| e.into_inner()).role_for(
+            &[folder_name.as_str(), uuid],
+            "/",
+            user,
+        )
+    }
+
+    /// Doc ID and display name of the folder a content doc belongs to, or
+    /// of the folder doc itself. None for docs in no loaded folder.
+    pub fn folder_of_doc(&self, doc_id: &str) -> Option<(String, String)> {
+        let (_, uuid) = link_indexer::parse_doc_id(doc_id)?;
+        if let Some(info) = self
+            .doc_resolver
+            .path_for_uuid(uuid)
+            .and_then(|path| self.doc_resolver.resolve_path(&path))
+        {
+            return Some((info.folder_doc_id, info.folder_name));
+        }
+        let folder_doc_id = self.folder_doc_id_for_uuid(uuid)?;
+        let folder_name = {
+            let doc_ref = self.docs.get(&folder_doc_id)?;
+            let awareness = doc_ref.awareness();
+            let guard = awareness.read().unwrap_or_else(|e| e.into_inner());
+            y_sweet_core::doc_resolver::read_folder_name(&guard.doc, &folder_doc_id)
+        };
+        Some((folder_doc_id, folder_name))
+    }
+
+    /// Cap `authorization` on `doc_id` by the ACL policy, failing with 403
+    /// when the policy denies `user` the doc.
+    fn authorize_doc_acl(
+        &self,
+        doc_id: &str,
+        user: Option<&str>,
+        authorization: Authorization,
+    ) -> Result<Authorization, AppError> {
+        let Some(role) = self.acl_role_for_doc(doc_id, user) else {
+            return Ok(authorization);
+        };
+        role.cap(authorization).ok_or_else(|| {
+            AppError::auth(
+                StatusCode::FORBIDDEN,
+                anyhow!("Access to this document is denied by the folder ACL"),
+                "acl_denied",
+            )
+        })
+    }
+
+    /// Whether the ACL policy lets `user` read a user-facing path.
+    pub fn acl_can_read_path(&self, path: &str, user: Option<&str>) -> bool {
+        self.acl_role_for_path(path, user)
+            .map_or(true, |role| role.allows(Authorization::ReadOnly))
+    }
+
+    /// UUIDs of the content docs the ACL policy hides from `user`, for
+    /// leaving them out of search results.
+    pub fn acl_hidden_doc_uuids(&self, user: Option<&str>) -> Vec<String> {
+        if self
+            .acl
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .is_empty()
+        {
+            return Vec::new();
+        }
+        self.doc_resolver
+            .all_paths()
+            .into_iter()
+            .filter(|path| !self.acl_can_read_path(path, user))
+            .filter_map(|path| self.doc_resolver.resolve_path(&path))
+            .map(|info| info.uuid)
+            .collect()
+    }
+
+    /// Decode an MCP key (share token or legacy API key), refusing revoked
+    /// share tokens. This is the relay's only look at share tokens: doc tokens
+    /// lens-editor minted from a link before it was revoked are plain relay
+    /// tokens and stay valid until they expire.
+    pub(crate) fn decode_mcp_key(&self, token: &str) -> Option<McpAccess> {
+        let revocations = self
+            .share_revocations
+            .read()
+            .unwrap_or_else(|e| e.into_inner());
+        share_token::decode_mcp_key(
+            token,
+            self.share_token_secret.as_deref(),
+            self.mcp_api_key.as_deref(),
+            Some(&revocations),
+        )
+    }
+
+    /// The share token revocation list currently enforced.
+    pub fn share_revocations(&self) -> RevocationList {
+        self.share_revocations
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// Apply `change` to the revocation list, drop entries for tokens that
+    /// have expired anyway, and save the result to the store when there is
+    /// one. The change is enforced before the save completes; updates are
+    /// serialized so the store always ends up with the latest list.
+    pub async fn update_share_revocations(
+        &self,
+        change: impl FnOnce(&mut RevocationList),
+    ) -> Result<RevocationList> {
+        let _update = self.share_revocations_update.lock().await;
+        let list = {
+            let mut list = self
+                .share_revocations
+                .write()
+                .unwrap_or_else(|e| e.into_inner());
+            let mut updated = list.clone();
+            change(&mut updated);
+            updated.validate().map_err(anyhow::Error::msg)?;
+            updated.prune_expired(current_time_epoch_millis() / 1000);
+            *list = updated.clone();
+            updated
+        };
+        if let Some(store) = &self.store {
+            share_revocation::save_revocations(&***store, &list).await?;
+        }
+        Ok(list)
+    }
+
+    /// Discord login, or 404 when it is not configured.
+    fn discord_auth(&self) -> Result<&Arc<crate::discord_auth::DiscordAuth>, AppError> {
+        self.discord.as_ref().ok_or_else(|| {
+            AppError::new(
+                StatusCode::NOT_FOUND,
+                anyhow!("Discord login is not configured"),
+            )
+        })
+    }
+
+    /// A CWT doc token carrying `user`, valid for the default lifetime;
+    /// None when the server has no auth key.
+    fn mint_user_doc_token(
+        &self,
+        doc_id: &str,
+        authorization: Authorization,
+        user: &str,
+    ) -> Result<Option<String>, AppError> {
+        let Some(auth) = &self.authenticator else {
+            return Ok(None);
+        };
+        let expiration_time = ExpirationTimeEpochMillis(
+            current_time_epoch_millis() + DEFAULT_EXPIRATION_SECONDS * 1000,
+        );
+        auth.gen_doc_token_cwt(doc_id, authorization, expiration_time, Some(user), None)
+            .map(Some)
+            .map_err(|e| {
+                AppError::new(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    anyhow!("Failed to generate token: {}", e),
+                )
+            })
+    }
+
     /// Get the DashMap of all loaded documents.
     pub fn docs(&self) -> &Arc<DashMap<String, DocWithSyncKv>> {
         &self.docs
@@ -1498,6 +2243,7 @@ impl Server {
                     &guard.doc,
                     attr.client_id,
                     &attr.actor,
+                    attr.user.as_deref(),
                     timestamp,
                     |txn, text| text.insert(txn, 0, &wrapped),
                 )
@@ -1715,6 +2461,7 @@ impl Server {
                         &guard.doc,
                         attr.client_id,
                         &attr.actor,
+                        attr.user.as_deref(),
                         timestamp,
                         |txn, text| text.insert(txn, 0, content),
                     )
@@ -2995,17 +3742,365 @@ impl Server {
         })
     }
 
-    /// Convenience wrapper for tests: creates a Server and discards the WorkerReceivers.
-    /// Workers are not spawned, which is fine for tests that don't need background indexing.
-    #[cfg(test)]
-    pub async fn new_without_workers(
-        store: Option<Box<dyn Store>>,
-        checkpoint_freq: Duration,
-        authenticator: Option<Authenticator>,
-        url: Option<Url>,
-        allowed_hosts: Vec<AllowedHost>,
-        cancellation_token: CancellationToken,
-        doc_gc: bool,
+    /// Delete a markdown document by user-facing path ("Lens/Notes/Idea.md").
+    ///
+    /// Moves its `filemeta_v0` entry to the folder's trash (recording
+    /// `deleted_by`) and removes its legacy `docs` entry, drops it from
+    /// every folder's `backlinks_v0`, the search and suggestions indexes and
+    /// the doc resolver, then sends a `document.deleted` event on the doc's
+    /// channel. The content doc stays in the store until the trash is purged.
+    /// Shared by `DELETE /doc` and the MCP `delete` tool.
+    pub async fn delete_path(
+        &self,
+        path: &str,
+        deleted_by: Option<&str>,
+    ) -> std::result::Result<DeleteDocumentResult, DeleteDocumentError> {
+        // Same resolver-miss fallback as move_path: filemeta_v0 is the source
+        // of truth for freshly-created files.
+        let info = self
+            .doc_resolver()
+            .resolve_path(path)
+            .or_else(|| self.resolve_path_via_filemeta(path))
+            .ok_or_else(|| DeleteDocumentError::NotFound(format!("Path not found: {}", path)))?;
+        let in_folder_path = path
+            .strip_prefix(&format!("{}/", info.folder_name))
+            .map(|rest| format!("/{}", rest.trim_start_matches('/')))
+            .ok_or_else(|| DeleteDocumentError::NotFound(format!("Path not found: {}", path)))?;
+
+        // Clone the Arcs out and drop the shard guards before the blocking
+        // awareness locks (see the lock-ordering rule in AGENTS.md).
+        let folder_doc_ids = link_indexer::find_all_folder_docs(&self.docs);
+        let (folder_awareness, folder_sync_kvs): (Vec<_>, Vec<_>) = folder_doc_ids
+            .iter()
+            .filter_map(|id| {
+                let doc_ref = self.docs.get(id)?;
+                Some((doc_ref.awareness(), doc_ref.sync_kv()))
+            })
+            .unzip();
+        let source_awareness = self
+            .docs
+            .get(&info.folder_doc_id)
+            .map(|doc_ref| doc_ref.awareness())
+            .ok_or_else(|| DeleteDocumentError::Internal("Folder doc not loaded".into()))?;
+
+        {
+            let guard = source_awareness.write().unwrap_or_else(|e| e.into_inner());
+            let mut txn = guard
+                .doc
+                .transact_mut_with(link_indexer::LINK_INDEXER_ORIGIN);
+            let filemeta = txn.get_or_insert_map("filemeta_v0");
+            let value = filemeta.get(&txn, &in_folder_path).ok_or_else(|| {
+                DeleteDocumentError::NotFound(format!("Path not found: {}", path))
+            })?;
+            let entry_type = link_indexer::extract_type_from_filemeta_entry(&value, &txn)
+                .unwrap_or_else(|| "markdown".to_string());
+            if entry_type != "markdown" {
+                return Err(DeleteDocumentError::BadRequest(format!(
+                    "{} is a {}, not a markdown document",
+                    path, entry_type
+                )));
+            }
+            let meta = link_indexer::extract_filemeta_fields(&value, &txn);
+            let docs_map = txn.get_or_insert_map("docs");
+            filemeta.remove(&mut txn, &in_folder_path);
+            docs_map.remove(&mut txn, &in_folder_path);
+            trash::put_in_trash(
+                &mut txn,
+                &info.uuid,
+                &in_folder_path,
+                &meta,
+                current_time_epoch_millis(),
+                deleted_by,
+            );
+        }
+
+        let backlinks_removed = {
+            let folder_guards: Vec<_> = folder_awareness
+                .iter()
+                .map(|a| a.write().unwrap_or_else(|e| e.into_inner()))
+                .collect();
+            let folder_doc_refs: Vec<&yrs::Doc> = folder_guards.iter().map(|g| &g.doc).collect();
+            link_indexer::remove_doc_from_backlinks(&info.uuid, &folder_doc_refs)
+                .map_err(|e| DeleteDocumentError::Internal(e.to_string()))?
+        };
+
+        for sync_kv in &folder_sync_kvs {
+            if let Err(e) = sync_kv.persist().await {
+                tracing::error!("Failed to persist folder doc after delete: {:?}", e);
+            }
+        }
+
+        if let Some(ref search_index) = self.search_index {
+            if let Err(e) = search_index.remove_document(&info.uuid) {
+                tracing::error!("Search: failed to remove {}: {:?}", info.uuid, e);
+            }
+        }
+        self.suggestions_index.update(&info.uuid, Vec::new());
+        self.doc_resolver.remove_doc(&info.uuid);
+
+        // LINK_INDEXER_ORIGIN writes bypass the leased callback path, so
+        // queue explicitly (helper attaches the GC lease).
+        for folder_doc_id in &folder_doc_ids {
+            self.queue_derived_index_with_lease(folder_doc_id);
+        }
+
+        if let Some(dispatcher) = &self.event_dispatcher {
+            let mut event = DocumentUpdatedEvent::new(info.doc_id.clone());
+            event
+                .metadata
+                .insert("folder".to_string(), json!(info.folder_name));
+            event
+                .metadata
+                .insert("path".to_string(), json!(in_folder_path));
+            dispatcher.send_event(EventEnvelope::new_with_event_type(
+                info.doc_id.clone(),
+                DOCUMENT_DELETED_EVENT_TYPE,
+                event,
+            ));
+        }
+
+        tracing::info!(
+            "Document {} deleted: {}{} ({} backlink entries removed)",
+            info.uuid,
+            info.folder_name,
+            in_folder_path,
+            backlinks_removed,
+        );
+
+        Ok(DeleteDocumentResult {
+            uuid: info.uuid,
+            doc_id: info.doc_id,
+            folder: info.folder_name,
+            path: in_folder_path,
+            backlinks_removed,
+        })
+    }
+
+    /// Trashed entries of a folder, most recently deleted first.
+    pub fn list_trash(&self, folder_doc_id: &str) -> std::result::Result<Vec<TrashItem>, AppError> {
+        let awareness = self
+            .docs
+            .get(folder_doc_id)
+            .map(|doc_ref| doc_ref.awareness())
+            .ok_or_else(|| {
+                AppError::new(
+                    StatusCode::NOT_FOUND,
+                    anyhow!("Folder doc not loaded: {}", folder_doc_id),
+                )
+            })?;
+        let guard = awareness.read().unwrap_or_else(|e| e.into_inner());
+        let txn = guard.doc.transact();
+        Ok(trash::list_trash(&txn))
+    }
+
+    /// Put a trashed entry back at its old path, then re-index it: the
+    /// resolver learns the path again and a markdown doc's links go back
+    /// into `backlinks_v0` and search.
+    pub async fn restore_trash(
+        &self,
+        folder_doc_id: &str,
+        id: &str,
+    ) -> std::result::Result<TrashItem, AppError> {
+        let (awareness, sync_kv) = self
+            .docs
+            .get(folder_doc_id)
+            .map(|doc_ref| (doc_ref.awareness(), doc_ref.sync_kv()))
+            .ok_or_else(|| {
+                AppError::new(
+                    StatusCode::NOT_FOUND,
+                    anyhow!("Folder doc not loaded: {}", folder_doc_id),
+                )
+            })?;
+        let item = {
+            let guard = awareness.write().unwrap_or_else(|e| e.into_inner());
+            let mut txn = guard.doc.transact_mut_with(TRASH_ORIGIN);
+            trash::restore_from_trash(&mut txn, id).map_err(|e| {
+                let status = match e {
+                    RestoreError::NotFound(_) => StatusCode::NOT_FOUND,
+                    RestoreError::PathTaken(_) => StatusCode::CONFLICT,
+                };
+                AppError::new(status, e.into())
+            })?
+        }; // awareness write released
+        if let Err(e) = sync_kv.persist().await {
+            tracing::error!("Failed to persist folder doc {}: {:?}", folder_doc_id, e);
+        }
+        self.doc_resolver.rebuild(&self.docs);
+
+        if item.entry_type == "markdown" {
+            if let Some((relay_id, _)) = link_indexer::parse_doc_id(folder_doc_id) {
+                let doc_id = format!("{}-{}", relay_id, item.id);
+                if let Err(e) = self.ensure_doc_loaded(&doc_id).await {
+                    tracing::warn!("Failed to load restored doc {}: {:?}", doc_id, e);
+                } else {
+                    if let Some(ref indexer) = self.link_indexer {
+                        let folder_doc_ids = link_indexer::find_all_folder_docs(&self.docs);
+                        if let Err(e) = indexer.index_document(&doc_id, &self.docs, &folder_doc_ids)
+                        {
+                            tracing::warn!("Failed to index restored doc {}: {:?}", doc_id, e);
+                        }
+                    }
+                    if let Some(ref search_index) = self.search_index {
+                        search_handle_content_update(
+                            &doc_id,
+                            &self.docs,
+                            search_index,
+                            &self.suggestions_index,
+                        );
+                    }
+                }
+            }
+        }
+
+        tracing::info!(
+            folder_doc_id,
+            id,
+            path = %item.path,
+            "Restored entry from trash"
+        );
+        Ok(item)
+    }
+
+    /// Delete the content of entries that have been in the trash longer than
+    /// `retention`, and drop their records. Content docs that are loaded are
+    /// left for a later pass; entries that are live again in some folder
+    /// only lose their record. Returns the number of records dropped.
+    ///
+    /// `known_folders` are folder docs seen earlier that may have been
+    /// evicted since; they are loaded again so an entry live in any of them
+    /// is kept. If one of them can't be loaded, nothing is purged.
+    pub async fn purge_trash(&self, retention: Duration, known_folders: &[String]) -> usize {
+        let cutoff = current_time_epoch_millis().saturating_sub(retention.as_millis() as u64);
+        let mut folder_doc_ids = link_indexer::find_all_folder_docs(&self.docs);
+        for folder_doc_id in known_folders {
+            if self.docs.contains_key(folder_doc_id) {
+                continue;
+            }
+            // Removed from the store: nothing in it is live
+            if let Some(store) = &self.store {
+                let key = format!("{}/data.ysweet", folder_doc_id);
+                if matches!(store.exists(&key).await, Ok(false)) {
+                    continue;
+                }
+            }
+            if let Err(e) = self.ensure_doc_loaded(folder_doc_id).await {
+                tracing::warn!(
+                    "Skipping trash purge: failed to load folder doc {}: {:?}",
+                    folder_doc_id,
+                    e
+                );
+                return 0;
+            }
+            folder_doc_ids.push(folder_doc_id.clone());
+        }
+        let mut live = std::collections::HashSet::new();
+        let mut expired = Vec::new();
+        for folder_doc_id in &folder_doc_ids {
+            let Some(awareness) = self.docs.get(folder_doc_id).map(|d| d.awareness()) else {
+                continue;
+            };
+            let guard = awareness.read().unwrap_or_else(|e| e.into_inner());
+            let txn = guard.doc.transact();
+            live.extend(trash::filemeta_by_id(&txn).into_keys());
+            let items: Vec<TrashItem> = trash::list_trash(&txn)
+                .into_iter()
+                .filter(|item| item.deleted_at <= cutoff)
+                .collect();
+            if !items.is_empty() {
+                expired.push((folder_doc_id.clone(), items));
+            }
+        }
+
+        let mut purged_total = 0;
+        for (folder_doc_id, items) in expired {
+            let Some((relay_id, _)) = link_indexer::parse_doc_id(&folder_doc_id) else {
+                continue;
+            };
+            let mut purged = Vec::new();
+            for item in items {
+                if live.contains(&item.id) || self.purge_trashed_content(relay_id, &item).await {
+                    purged.push(item.id);
+                }
+            }
+            if purged.is_empty() {
+                continue;
+            }
+            let Some((awareness, sync_kv)) = self
+                .docs
+                .get(&folder_doc_id)
+                .map(|d| (d.awareness(), d.sync_kv()))
+            else {
+                continue;
+            };
+            {
+                let guard = awareness.write().unwrap_or_else(|e| e.into_inner());
+                let mut txn = guard.doc.transact_mut_with(TRASH_ORIGIN);
+                purged_total += trash::remove_from_trash(&mut txn, &purged);
+            } // awareness write released
+            if let Err(e) = sync_kv.persist().await {
+                tracing::error!("Failed to persist folder doc {}: {:?}", folder_doc_id, e);
+            }
+        }
+        purged_total
+    }
+
+    /// Remove a trashed entry's content doc or blob from the store. Returns
+    /// false when it should be tried again later.
+    async fn purge_trashed_content(&self, relay_id: &str, item: &TrashItem) -> bool {
+        let Some(store) = &self.store else {
+            return true;
+        };
+        let doc_id = format!("{}-{}", relay_id, item.id);
+        if item.entry_type == "folder" {
+            return true;
+        }
+        if let Some(hash) = item.hash() {
+            let prefix = format!("files/{}/", doc_id);
+            let keys: Vec<String> = match store.list(&prefix).await {
+                Ok(files) => files.into_iter().map(|file| file.key).collect(),
+                Err(_) => vec![hash.to_string()],
+            };
+            for key in keys {
+                let key = format!("{}{}", prefix, key);
+                if !matches!(store.exists(&key).await, Ok(false)) {
+                    if let Err(e) = store.remove(&key).await {
+                        tracing::error!("Failed to purge {}: {:?}", key, e);
+                        return false;
+                    }
+                }
+            }
+            return true;
+        }
+        if self.docs.contains_key(&doc_id) {
+            return false;
+        }
+        let key = format!("{}/data.ysweet", doc_id);
+        match store.exists(&key).await {
+            Ok(false) => true,
+            _ => match store.remove(&key).await {
+                Ok(()) => {
+                    tracing::info!(doc_id, path = %item.path, "Purged trashed doc");
+                    true
+                }
+                Err(e) => {
+                    tracing::error!("Failed to purge {}: {:?}", key, e);
+                    false
+                }
+            },
+        }
+    }
+
+    /// Convenience wrapper for tests: creates a Server and discards the WorkerReceivers.
+    /// Workers are not spawned, which is fine for tests that don't need background indexing.
+    #[cfg(test)]
+    pub async fn new_without_workers(
+        store: Option<Box<dyn Store>>,
+        checkpoint_freq: Duration,
+        authenticator: Option<Authenticator>,
+        url: Option<Url>,
+        allowed_hosts: Vec<AllowedHost>,
+        cancellation_token: CancellationToken,
+        doc_gc: bool,
         webhook_configs: Option<Vec<WebhookConfig>>,
     ) -> Result<Self> {
         let (server, _receivers) = Self::new(
@@ -3054,6 +4149,14 @@ impl Server {
             last_dirty_signal: Arc::new(AtomicU64::new(0)),
             last_successful_persist: Arc::new(AtomicU64::new(0)),
             worker_status: Arc::new(crate::worker_status::WorkerStatusMap::new()),
+            filemeta_guard: FilemetaGuardConfig::default(),
+            regression_detector: Arc::new(RegressionDetector::new(RegressionConfig::default())),
+            git_sync: None,
+            trash: crate::trash::TrashConfig::default(),
+            acl: Arc::new(RwLock::new(AclPolicy::default())),
+            discord: None,
+            share_revocations: Arc::new(RwLock::new(RevocationList::default())),
+            share_revocations_update: Arc::new(tokio::sync::Mutex::new(())),
         })
     }
 
@@ -3089,6 +4192,14 @@ impl Server {
             last_dirty_signal: Arc::new(AtomicU64::new(0)),
             last_successful_persist: Arc::new(AtomicU64::new(0)),
             worker_status: Arc::new(crate::worker_status::WorkerStatusMap::new()),
+            filemeta_guard: FilemetaGuardConfig::default(),
+            regression_detector: Arc::new(RegressionDetector::new(RegressionConfig::default())),
+            git_sync: None,
+            trash: crate::trash::TrashConfig::default(),
+            acl: Arc::new(RwLock::new(AclPolicy::default())),
+            discord: None,
+            share_revocations: Arc::new(RwLock::new(RevocationList::default())),
+            share_revocations_update: Arc::new(tokio::sync::Mutex::new(())),
         });
         server
     }
@@ -3155,1310 +4266,2626 @@ impl Server {
         ))
     }
 
-    fn validate_doc_id(doc_id: &str) -> Result<()> {
-        // Reject system configuration paths that are reserved for internal use
-        if doc_id.starts_with(".config/") || doc_id == ".config" {
-            return Err(anyhow::anyhow!(
-                "Document ID cannot access system configuration directory '.config'"
-            ));
-        }
-        Ok(())
-    }
-
-    pub fn load_doc<'a>(
-        &'a self,
-        doc_id: &'a str,
-        routing_channel: Option<String>,
-    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<()>> + Send + 'a>> {
-        Box::pin(self.load_doc_with_user(doc_id, routing_channel, None))
+    /// Content regressions flagged since startup, newest first.
+    pub fn regression_events(&self) -> Vec<RegressionEvent> {
+        self.regression_detector.events()
     }
 
-    /// Ensure a document is loaded into memory, reloading from storage if GC evicted it.
-    pub async fn ensure_doc_loaded(&self, doc_id: &str) -> Result<()> {
-        if !self.docs.contains_key(doc_id) {
-            self.load_doc(doc_id, None).await?;
+    /// Every root type of a doc as JSON ("relay dump"). A loaded doc is read
+    /// from memory; any other doc from its persisted snapshot, without
+    /// loading it.
+    pub async fn dump_doc(
+        &self,
+        doc_id: &str,
+    ) -> std::result::Result<std::collections::BTreeMap<String, Value>, AppError> {
+        if let Some(doc_ref) = self.docs.get(doc_id) {
+            let awareness = doc_ref.awareness();
+            drop(doc_ref);
+            let guard = awareness.read().unwrap_or_else(|e| e.into_inner());
+            return Ok(doc_dump::dump_doc(&guard.doc));
         }
-        Ok(())
+        let store = self.store.as_ref().ok_or_else(|| {
+            AppError::new(
+                StatusCode::NOT_FOUND,
+                anyhow!("Document not found: {}", doc_id),
+            )
+        })?;
+        doc_dump::dump_stored_doc(&***store, doc_id)
+            .await
+            .map_err(|e| AppError::new(StatusCode::INTERNAL_SERVER_ERROR, e))?
+            .ok_or_else(|| {
+                AppError::new(
+                    StatusCode::NOT_FOUND,
+                    anyhow!("Document not found: {}", doc_id),
+                )
+            })
     }
 
-    pub async fn load_doc_with_user(
+    /// Import an Obsidian vault directory into the folder named
+    /// `folder_name` ("relay import"). The folder doc is created when no
+    /// loaded folder has that name; `relay_id` is then required unless the
+    /// server already has a folder to take it from. Markdown files become
+    /// content docs and everything else blobs. Paths already in the folder
+    /// keep their IDs: docs get the diff to the vault text, blobs are
+    /// re-uploaded only when their hash changed, so re-running an import is
+    /// safe. Every imported markdown doc is then run through the link
+    /// indexer so backlinks are populated straight away.
+    pub async fn import_vault(
         &self,
-        doc_id: &str,
-        routing_channel: Option<String>,
-        user: Option<String>,
-    ) -> Result<()> {
-        Self::validate_doc_id(doc_id)?;
-        let (send, recv) = channel(1024);
-        let indexing_lease = Arc::new(std::sync::OnceLock::new());
+        vault_dir: &std::path::Path,
+        folder_name: &str,
+        relay_id: Option<&str>,
+    ) -> std::result::Result<VaultImportReport, AppError> {
+        let (entries, skipped) = vault_import::scan_vault(vault_dir)
+            .map_err(|e| AppError::new(StatusCode::BAD_REQUEST, e))?;
+        let mut report = VaultImportReport {
+            skipped,
+            ..Default::default()
+        };
 
-        // Determine routing channel: use provided channel or fallback to doc_id
-        let routing_channel_name = routing_channel
-            .clone()
-            .unwrap_or_else(|| doc_id.to_string());
+        let folder_doc_id = match self.folder_doc_id_for_name(folder_name) {
+            Some(folder_doc_id) => folder_doc_id,
+            None => {
+                let relay_id = match relay_id {
+                    Some(relay_id) => relay_id.to_string(),
+                    None => link_indexer::find_all_folder_docs(&self.docs)
+                        .iter()
+                        .find_map(|id| link_indexer::parse_doc_id(id).map(|(r, _)| r.to_string()))
+                        .ok_or_else(|| {
+                            AppError::new(
+                                StatusCode::BAD_REQUEST,
+                                anyhow!(
+                                    "Folder '{}' does not exist; pass relay_id to create it",
+                                    folder_name
+                                ),
+                            )
+                        })?,
+                };
+                if uuid::Uuid::parse_str(&relay_id).is_err() {
+                    return Err(AppError::new(
+                        StatusCode::BAD_REQUEST,
+                        anyhow!("Invalid relay ID '{}': expected a UUID", relay_id),
+                    ));
+                }
+                report.folder_created = true;
+                format!("{}-{}", relay_id, uuid::Uuid::new_v4())
+            }
+        };
+        let (relay_id, _) = link_indexer::parse_doc_id(&folder_doc_id).ok_or_else(|| {
+            AppError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                anyhow!("Invalid folder doc ID: {}", folder_doc_id),
+            )
+        })?;
+        let relay_id = relay_id.to_string();
+        let folder_awareness = self
+            .get_or_create_doc(&folder_doc_id)
+            .await
+            .map_err(|e| AppError::new(StatusCode::INTERNAL_SERVER_ERROR, e))?
+            .awareness();
+        report.folder_doc_id = folder_doc_id.clone();
 
-        // If this doc routes to a different channel (i.e., it's a subdoc),
-        // ensure the parent is loaded and hold a reference to prevent GC.
-        let parent_awareness_guard = if routing_channel_name != doc_id {
-            if !self.docs.contains_key(&routing_channel_name) {
-                self.load_doc(&routing_channel_name, None).await?;
+        // Path → (id, type, hash) of what the folder already has.
+        let existing: std::collections::HashMap<String, (String, String, Option<String>)> = {
+            let guard = folder_awareness.read().unwrap_or_else(|e| e.into_inner());
+            let txn = guard.doc.transact();
+            match txn.get_map("filemeta_v0") {
+                Some(filemeta) => filemeta
+                    .iter(&txn)
+                    .filter_map(|(path, value)| {
+                        let id = link_indexer::extract_id_from_filemeta_entry(&value, &txn)?;
+                        let entry_type =
+                            link_indexer::extract_type_from_filemeta_entry(&value, &txn)
+                                .unwrap_or_default();
+                        let hash = link_indexer::extract_hash_from_filemeta_entry(&value, &txn);
+                        Some((path.to_string(), (id, entry_type, hash)))
+                    })
+                    .collect(),
+                None => std::collections::HashMap::new(),
             }
-            self.docs
-                .get(&routing_channel_name)
-                .map(|parent| parent.awareness())
-        } else {
-            None
         };
 
-        // Create event callback with the determined routing channel and user
-        let event_callback = {
-            let event_dispatcher = self.event_dispatcher.clone();
-            let routing_channel_for_callback = routing_channel_name.clone();
-            let user_for_callback = user.clone();
-            let link_indexer_for_callback = self.link_indexer.clone();
-            let search_tx_for_callback = self.search_tx.clone();
-            let search_pending_for_callback = self.search_pending.clone();
-            let doc_key_for_indexer = doc_id.to_string();
-            let docs = self.docs.clone();
-            let indexing_lease_for_callback = indexing_lease.clone();
-            let doc_id_for_callback = doc_id.to_string();
-            // Capture parent awareness to keep it alive (prevents GC while subdoc exists)
-            let _parent_awareness = parent_awareness_guard;
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as f64;
+        // New or changed filemeta_v0 entries, written in one transaction at
+        // the end: (path, entry, also goes in the legacy docs map).
+        let mut folder_entries: Vec<(String, std::collections::HashMap<String, yrs::Any>, bool)> =
+            Vec::new();
+        let mut markdown_doc_ids = Vec::new();
+
+        for entry in &entries {
+            let existing_entry = existing.get(&entry.path);
+            let expected_type = match &entry.kind {
+                VaultEntryKind::Folder => "folder",
+                VaultEntryKind::Markdown => "markdown",
+                VaultEntryKind::Blob { file_type, .. } => *file_type,
+            };
+            if let Some((_, entry_type, _)) = existing_entry {
+                // Blob types are interchangeable: a PDF uploaded as "file"
+                // is still the same attachment.
+                let both_blobs = matches!(entry.kind, VaultEntryKind::Blob { .. })
+                    && !matches!(entry_type.as_str(), "folder" | "markdown" | "canvas");
+                if entry_type != expected_type && !both_blobs {
+                    report.conflicts.push(format!(
+                        "{}: already in the folder as {}",
+                        entry.path, entry_type
+                    ));
+                    continue;
+                }
+            }
+            let read_error = |e: std::io::Error| {
+                AppError::new(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    anyhow!("Failed to read {}: {}", entry.source.display(), e),
+                )
+            };
 
-            if let Some(dispatcher) = event_dispatcher {
-                Some(Arc::new(
-                    move |mut event: DocumentUpdatedEvent, suppress_derived_index: bool| {
-                        // Keep parent awareness alive by referencing it in the closure
-                        let _ = &_parent_awareness;
-
-                        // Update parent's subdoc state vector index
-                        if routing_channel_for_callback != doc_id_for_callback {
-                            if let Some(state_vector) = &event.state_vector {
-                                if let Some(parent) = docs.get(&routing_channel_for_callback) {
-                                    parent.update_subdoc_state_vector(
-                                        &doc_id_for_callback,
-                                        state_vector.clone(),
-                                    );
-                                }
-                            }
+            match &entry.kind {
+                VaultEntryKind::Folder => {
+                    if existing_entry.is_none() {
+                        let mut meta = std::collections::HashMap::new();
+                        meta.insert(
+                            "id".to_string(),
+                            yrs::Any::String(uuid::Uuid::new_v4().to_string().into()),
+                        );
+                        meta.insert("type".to_string(), yrs::Any::String("folder".into()));
+                        meta.insert("version".to_string(), yrs::Any::Number(0.0));
+                        folder_entries.push((entry.path.clone(), meta, true));
+                        report.folders_created += 1;
+                    }
+                }
+                VaultEntryKind::Markdown => {
+                    let bytes = std::fs::read(&entry.source).map_err(read_error)?;
+                    let Ok(markdown) = String::from_utf8(bytes) else {
+                        report
+                            .skipped
+                            .push(format!("{}: not valid UTF-8", entry.path));
+                        continue;
+                    };
+                    if let Some((id, _, _)) = existing_entry {
+                        let doc_id = format!("{}-{}", relay_id, id);
+                        if self
+                            .restore_doc_text(&doc_id, &markdown, false, None)
+                            .await?
+                            > 0
+                        {
+                            report.docs_updated += 1;
+                        } else {
+                            report.docs_unchanged += 1;
                         }
-
-                        // Add user to event if available
-                        if let Some(ref user) = user_for_callback {
-                            event.user = Some(user.clone());
+                        markdown_doc_ids.push(doc_id);
+                        continue;
+                    }
+                    let uuid = uuid::Uuid::new_v4().to_string();
+                    let doc_id = format!("{}-{}", relay_id, uuid);
+                    let (awareness, sync_kv) = {
+                        let doc_ref = self
+                            .get_or_create_doc(&doc_id)
+                            .await
+                            .map_err(|e| AppError::new(StatusCode::INTERNAL_SERVER_ERROR, e))?;
+                        (doc_ref.awareness(), doc_ref.sync_kv())
+                    }; // DashMap shard lock released
+                    {
+                        let guard = awareness.write().unwrap_or_else(|e| e.into_inner());
+                        let mut txn = guard.doc.transact_mut_with(VAULT_IMPORT_ORIGIN);
+                        let text = txn.get_or_insert_text("contents");
+                        text.insert(&mut txn, 0, &markdown);
+                    }
+                    if let Err(e) = sync_kv.persist().await {
+                        tracing::error!("Failed to persist imported doc {}: {:?}", doc_id, e);
+                    }
+                    if let Some(ref search_index) = self.search_index {
+                        search_handle_content_update(
+                            &doc_id,
+                            &self.docs,
+                            search_index,
+                            &self.suggestions_index,
+                        );
+                    }
+                    let mut meta = std::collections::HashMap::new();
+                    meta.insert("id".to_string(), yrs::Any::String(uuid.into()));
+                    meta.insert("type".to_string(), yrs::Any::String("markdown".into()));
+                    meta.insert("version".to_string(), yrs::Any::Number(0.0));
+                    folder_entries.push((entry.path.clone(), meta, true));
+                    markdown_doc_ids.push(doc_id);
+                    report.docs_created += 1;
+                }
+                VaultEntryKind::Blob {
+                    file_type,
+                    mimetype,
+                } => {
+                    let data = std::fs::read(&entry.source).map_err(read_error)?;
+                    let hash = crate::mcp::tools::blob::sha256_hex(&data);
+                    if let Some((_, _, Some(existing_hash))) = existing_entry {
+                        if *existing_hash == hash {
+                            report.blobs_unchanged += 1;
+                            continue;
                         }
+                    }
+                    let store = self.store.as_ref().ok_or_else(|| {
+                        AppError::new(
+                            StatusCode::INTERNAL_SERVER_ERROR,
+                            anyhow!("No store configured; cannot import {}", entry.path),
+                        )
+                    })?;
+                    let (uuid, file_type) = match existing_entry {
+                        Some((id, entry_type, _)) => (id.clone(), entry_type.as_str()),
+                        None => (uuid::Uuid::new_v4().to_string(), *file_type),
+                    };
+                    store
+                        .set(&format!("files/{}-{}/{}", relay_id, uuid, hash), data)
+                        .await
+                        .map_err(|e| AppError::new(StatusCode::INTERNAL_SERVER_ERROR, e.into()))?;
+                    let mut meta = std::collections::HashMap::new();
+                    meta.insert("id".to_string(), yrs::Any::String(uuid.into()));
+                    meta.insert("type".to_string(), yrs::Any::String(file_type.into()));
+                    meta.insert("version".to_string(), yrs::Any::Number(0.0));
+                    meta.insert("hash".to_string(), yrs::Any::String(hash.into()));
+                    meta.insert("mimetype".to_string(), yrs::Any::String((*mimetype).into()));
+                    meta.insert("synctime".to_string(), yrs::Any::Number(now));
+                    folder_entries.push((entry.path.clone(), meta, false));
+                    report.blobs_uploaded += 1;
+                }
+            }
+        }
 
-                        // Log the full event payload as JSON after user assignment
-                        match serde_json::to_string(&event) {
-                            Ok(json_str) => {
-                                tracing::debug!("Document updated event dispatched: {}", json_str);
-                            }
-                            Err(e) => {
-                                tracing::debug!(
-                                "Document updated event dispatched for doc_id: {} (JSON serialization failed: {})",
-                                event.doc_id, e
-                            );
-                            }
+        if report.folder_created || !folder_entries.is_empty() {
+            {
+                let guard = folder_awareness.write().unwrap_or_else(|e| e.into_inner());
+                let mut txn = guard.doc.transact_mut_with(VAULT_IMPORT_ORIGIN);
+                if report.folder_created {
+                    txn.get_or_insert_map("folder_config")
+                        .insert(&mut txn, "name", folder_name);
+                }
+                let filemeta = txn.get_or_insert_map("filemeta_v0");
+                let docs_map = txn.get_or_insert_map("docs");
+                for (path, meta, in_docs_map) in folder_entries {
+                    if in_docs_map {
+                        if let Some(yrs::Any::String(id)) = meta.get("id") {
+                            docs_map.insert(&mut txn, path.as_str(), yrs::Any::String(id.clone()));
                         }
+                    }
+                    filemeta.insert(&mut txn, path.as_str(), yrs::Any::Map(meta.into()));
+                }
+            } // awareness write released
+            let sync_kv = self
+                .docs
+                .get(&folder_doc_id)
+                .map(|doc_ref| doc_ref.sync_kv());
+            if let Some(sync_kv) = sync_kv {
+                if let Err(e) = sync_kv.persist().await {
+                    tracing::error!("Failed to persist folder doc {}: {:?}", folder_doc_id, e);
+                }
+            }
+            self.doc_resolver.rebuild(&self.docs);
+        }
 
-                        // Step 1: Create the envelope with predetermined routing channel
-                        let envelope =
-                            EventEnvelope::new(routing_channel_for_callback.clone(), event);
+        if let Some(ref indexer) = self.link_indexer {
+            let folder_doc_ids = link_indexer::find_all_folder_docs(&self.docs);
+            for doc_id in &markdown_doc_ids {
+                if let Err(e) = indexer.index_document(doc_id, &self.docs, &folder_doc_ids) {
+                    tracing::warn!("Failed to index imported doc {}: {:?}", doc_id, e);
+                }
+            }
+        }
 
-                        // Step 2: Send via dispatcher
-                        dispatcher.send_event(envelope);
+        tracing::info!(
+            folder = folder_name,
+            folder_doc_id = %folder_doc_id,
+            docs_created = report.docs_created,
+            docs_updated = report.docs_updated,
+            blobs_uploaded = report.blobs_uploaded,
+            conflicts = report.conflicts.len(),
+            "Imported vault {}",
+            vault_dir.display()
+        );
+        Ok(report)
+    }
 
-                        // Notify link indexer (if this update is not from the indexer itself)
-                        if !suppress_derived_index {
-                            // Upgrade the prewired weak self-reference. Never
-                            // access `docs` here: callback holds awareness WRITE,
-                            // while readers may hold a docs shard then wait for
-                            // awareness READ (AB/BA deadlock).
-                            let indexing_lease = indexing_lease_for_callback
-                                .get()
-                                .and_then(std::sync::Weak::upgrade);
-                            if indexing_lease.is_none() {
-                                // Unreachable by construction (load_doc sets the
-                                // weak before the doc is reachable); queueing
-                                // without a lease reopens the GC race.
-                                tracing::warn!(
-                                    doc_id = %doc_id_for_callback,
-                                    "Derived-index queue has no GC lease"
-                                );
-                            }
-                            if let Some(ref indexer) = link_indexer_for_callback {
-                                // Queue synchronously while this callback still
-                                // holds awareness write lock, closing the gap in
-                                // which GC could observe no external reference.
-                                indexer.queue_document_update_with_lease(
-                                    &doc_key_for_indexer,
-                                    indexing_lease.clone(),
-                                );
-                            }
+    /// Put the text a doc had before a flagged regression back, as a live
+    /// edit. Needs the event's snapshot.
+    pub async fn restore_regression(
+        &self,
+        event_id: &str,
+    ) -> std::result::Result<RegressionRestoreResponse, AppError> {
+        let event = self.regression_detector.event(event_id).ok_or_else(|| {
+            AppError::new(
+                StatusCode::NOT_FOUND,
+                anyhow!("Regression event not found: {}", event_id),
+            )
+        })?;
+        let snapshot_key = event.snapshot_key.as_deref().ok_or_else(|| {
+            AppError::new(
+                StatusCode::CONFLICT,
+                anyhow!("No snapshot was saved for regression event {}", event_id),
+            )
+        })?;
+        let store = self.store.as_ref().ok_or_else(|| {
+            AppError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                anyhow!("No store configured"),
+            )
+        })?;
+        let snapshot = store
+            .get(snapshot_key)
+            .await
+            .map_err(|e| AppError::new(StatusCode::INTERNAL_SERVER_ERROR, e.into()))?
+            .ok_or_else(|| {
+                AppError::new(
+                    StatusCode::NOT_FOUND,
+                    anyhow!("Regression snapshot missing: {}", snapshot_key),
+                )
+            })?;
+        let target = snapshot_text(&snapshot)
+            .map_err(|e| AppError::new(StatusCode::INTERNAL_SERVER_ERROR, e))?;
+        let edits = self
+            .restore_doc_text(&event.doc_id, &target, false, None)
+            .await?;
+        Ok(RegressionRestoreResponse {
+            event_id: event.id,
+            doc_id: event.doc_id,
+            edits,
+        })
+    }
 
-                            // ⚠️ This runs synchronously inside an awareness write lock.
-                            // Any lock acquired here must be LOWER than awareness in the
-                            // lock ordering. DashMap shard locks (via .entry()) are lower,
-                            // so this is safe — but code iterating this DashMap must NOT
-                            // hold shard locks while acquiring awareness locks.
+    /// The guard for a client connection to `doc_id`, or None when it is off.
+    /// When it trips, the guard counts each violation in
+    /// `relay_server_filemeta_guard_trips_total`, sends a `filemeta.guard`
+    /// event on the doc's channel and, in quarantine mode, saves the update
+    /// under `FILEMETA_QUARANTINE_PREFIX`.
+    fn filemeta_guard_for(&self, doc_id: &str) -> Option<FilemetaGuard> {
+        if self.filemeta_guard.mode == GuardMode::Off {
+            return None;
+        }
+        let doc_id = doc_id.to_string();
+        let metrics = self.metrics.clone();
+        let event_dispatcher = self.event_dispatcher.clone();
+        let store = self.store.clone();
+        Some(FilemetaGuard::new(
+            self.filemeta_guard,
+            move |alert: GuardAlert| {
+                let action = alert.mode.as_str();
+                for violation in &alert.violations {
+                    metrics.record_filemeta_guard_trip(violation.kind.as_str(), action);
+                }
+                tracing::warn!(
+                    doc_id = %doc_id,
+                    user = ?alert.user,
+                    action,
+                    violations = ?alert.violations,
+                    "Filemeta guard tripped"
+                );
 
-                            // Notify search index worker (with deduplication)
-                            if let Some(ref tx) = search_tx_for_callback {
-                                if let Some(ref pending) = search_pending_for_callback {
-                                    let is_new = link_indexer::upsert_pending_update(
-                                        pending,
-                                        &doc_key_for_indexer,
-                                        indexing_lease.clone(),
-                                    );
-                                    if is_new {
-                                        if let Err(e) = tx.try_send(doc_key_for_indexer.clone()) {
-                                            tracing::error!("Search index channel send failed (worker dead?): {e}");
-                                        }
-                                    }
-                                }
+                // Runs under the awareness write lock: the store write is
+                // spawned rather than awaited.
+                let quarantine_key = match (&store, alert.mode) {
+                    (Some(store), GuardMode::Quarantine) => {
+                        let key = format!(
+                            "{}/{}/{}.update",
+                            FILEMETA_QUARANTINE_PREFIX,
+                            doc_id,
+                            current_time_epoch_millis()
+                        );
+                        let store = store.clone();
+                        let store_key = key.clone();
+                        let update = alert.update.clone();
+                        tokio::spawn(async move {
+                            if let Err(e) = store.set(&store_key, update).await {
+                                tracing::error!(
+                                    "Failed to quarantine update at {}: {}",
+                                    store_key,
+                                    e
+                                );
                             }
-                        }
-                    },
-                ) as y_sweet_core::webhook::WebhookCallback)
-            } else {
-                // Server::new always constructs an event_dispatcher (with
-                // webhooks if configured, otherwise sync-protocol-only). The
-                // None case is reachable only via #[cfg(test)] helpers that
-                // bypass the doc-creation pipeline, so no callback is needed.
-                None
-            }
-        };
+                        });
+                        Some(key)
+                    }
+                    _ => None,
+                };
 
-        let last_dirty_for_callback = self.last_dirty_signal.clone();
-        let dwskv = DocWithSyncKv::new(
-            doc_id,
-            self.store.clone(),
-            move || {
-                let now_ms = std::time::SystemTime::now()
-                    .duration_since(std::time::UNIX_EPOCH)
-                    .unwrap_or_default()
-                    .as_millis() as u64;
-                last_dirty_for_callback.store(now_ms, AtomicOrdering::Relaxed);
-                if let Err(e) = send.try_send(()) {
-                    match e {
-                        tokio::sync::mpsc::error::TrySendError::Closed(_) => {
-                            tracing::error!(
-                                "Dirty signal channel closed — persistence worker is dead"
-                            );
-                        }
-                        tokio::sync::mpsc::error::TrySendError::Full(_) => {
-                            // Normal: signals coalesce when channel is full. The persistence
-                            // worker will pick up the dirty state on its next cycle.
-                        }
+                if let Some(dispatcher) = &event_dispatcher {
+                    let mut event = DocumentUpdatedEvent::new(doc_id.clone());
+                    event.user = alert.user.clone();
+                    event.metadata.insert("action".to_string(), json!(action));
+                    event.metadata.insert(
+                        "violations".to_string(),
+                        serde_json::to_value(&alert.violations).unwrap_or_default(),
+                    );
+                    if let Some(key) = quarantine_key {
+                        event
+                            .metadata
+                            .insert("quarantineKey".to_string(), json!(key));
                     }
+                    dispatcher.send_event(EventEnvelope::new_with_event_type(
+                        doc_id.clone(),
+                        FILEMETA_GUARD_EVENT_TYPE,
+                        event,
+                    ));
                 }
             },
-            event_callback,
-        )
-        .await?;
-
-        indexing_lease
-            .set(Arc::downgrade(&dwskv.awareness()))
-            .expect("indexing lease OnceLock is set exactly once per load_doc");
+        ))
+    }
 
-        // If channel is provided in token, store it in document metadata
-        if let Some(channel_name) = routing_channel {
-            dwskv.set_channel(&channel_name);
+    fn validate_doc_id(doc_id: &str) -> Result<()> {
+        // Reject system configuration paths that are reserved for internal use
+        if doc_id.starts_with(".config/") || doc_id == ".config" {
+            return Err(anyhow::anyhow!(
+                "Document ID cannot access system configuration directory '.config'"
+            ));
         }
-
-        dwskv
-            .sync_kv()
-            .persist()
-            .await
-            .map_err(|e| anyhow!("Error persisting: {:?}", e))?;
-
-        {
-            let sync_kv = dwskv.sync_kv();
-            let checkpoint_freq = self.checkpoint_freq;
-            let doc_id = doc_id.to_string();
-            let cancellation_token = self.cancellation_token.clone();
-
-            // Spawn a task to save the document to the store when it changes.
-            let last_persist = self.last_successful_persist.clone();
-            self.doc_worker_tracker.spawn(
-                Self::doc_persistence_worker(
-                    recv,
-                    sync_kv,
-                    checkpoint_freq,
-                    doc_id.clone(),
-                    cancellation_token.clone(),
-                    last_persist,
-                )
-                .instrument(span!(Level::INFO, "save_loop", doc_id=?doc_id)),
-            );
-
-            if self.doc_gc.load(std::sync::atomic::Ordering::Relaxed) {
-                self.doc_worker_tracker.spawn(
-                    Self::doc_gc_worker(
-                        self.docs.clone(),
-                        doc_id.clone(),
-                        checkpoint_freq,
-                        cancellation_token,
-                    )
-                    .instrument(span!(Level::INFO, "gc_loop", doc_id=?doc_id)),
-                );
-            }
+        if doc_id.starts_with(FOLDER_SNAPSHOT_PREFIX) {
+            return Err(anyhow::anyhow!(
+                "Document ID cannot access the folder snapshot directory '{}'",
+                FOLDER_SNAPSHOT_PREFIX
+            ));
         }
+        Ok(())
+    }
 
-        self.docs.insert(doc_id.to_string(), dwskv);
+    pub fn load_doc<'a>(
+        &'a self,
+        doc_id: &'a str,
+        routing_channel: Option<String>,
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<()>> + Send + 'a>> {
+        Box::pin(self.load_doc_with_user(doc_id, routing_channel, None))
+    }
 
+    /// Ensure a document is loaded into memory, reloading from storage if GC evicted it.
+    pub async fn ensure_doc_loaded(&self, doc_id: &str) -> Result<()> {
+        if !self.docs.contains_key(doc_id) {
+            self.load_doc(doc_id, None).await?;
+        }
         Ok(())
     }
 
-    /// Load all documents from storage into memory.
-    ///
-    /// Enumerates all doc IDs in the store and calls `load_doc()` for each.
-    /// Used on startup to populate the in-memory doc map before reindexing backlinks.
-    pub async fn load_all_docs(&self) -> Result<usize> {
+    /// Stored versions of a doc's snapshot, as listed by the store.
+    pub async fn doc_versions(&self, doc_id: &str) -> Result<Vec<VersionInfo>> {
         let store = self
             .store
             .as_ref()
-            .ok_or_else(|| anyhow!("No store configured — cannot load docs from storage"))?;
+            .ok_or_else(|| anyhow!("No store configured"))?;
+        Ok(store
+            .list_versions(&format!("{}/data.ysweet", doc_id))
+            .await?)
+    }
 
-        let doc_ids = store
-            .list_doc_ids()
-            .await
-            .map_err(|e| anyhow!("Failed to list doc IDs from storage: {:?}", e))?;
+    /// Markdown text of one stored version of a doc, or None when the store
+    /// has no such version.
+    pub async fn doc_version_text(&self, doc_id: &str, version_id: &str) -> Result<Option<String>> {
+        let store = self
+            .store
+            .as_ref()
+            .ok_or_else(|| anyhow!("No store configured"))?;
+        let key = format!("{}/data.ysweet", doc_id);
+        let Some(snapshot) = store.get_version(&key, version_id).await? else {
+            return Ok(None);
+        };
+        Ok(Some(snapshot_text(&snapshot)?))
+    }
 
-        let total = doc_ids.len();
-        tracing::info!("Loading {} documents from storage...", total);
+    /// Current markdown text of a doc, reloading it if GC evicted it.
+    pub async fn doc_text(&self, doc_id: &str) -> Result<String> {
+        self.ensure_doc_loaded(doc_id).await?;
+        let awareness = self
+            .docs
+            .get(doc_id)
+            .map(|doc_ref| doc_ref.awareness())
+            .ok_or_else(|| anyhow!("Document not loaded: {}", doc_id))?;
+        let guard = awareness.read().unwrap_or_else(|e| e.into_inner());
+        let txn = guard.doc.transact();
+        Ok(match txn.get_text("contents") {
+            Some(text) => text.get_string(&txn),
+            None => String::new(),
+        })
+    }
 
-        // Temporarily disable GC during bulk loading — all docs would be
-        // immediately GCed since no clients are connected yet.
-        let gc_was_enabled = self
-            .doc_gc
-            .swap(false, std::sync::atomic::Ordering::Relaxed);
+    /// Roll a doc's text back to a stored version. The difference to the live
+    /// text is applied as an ordinary edit, so connected editors converge on
+    /// the old text instead of being overwritten by an old snapshot. With
+    /// `as_suggestions` it is proposed as CriticMarkup for review instead.
+    /// Returns the number of Y.Text edits applied (0 when already equal).
+    pub async fn restore_doc_version(
+        &self,
+        doc_id: &str,
+        version_id: &str,
+        as_suggestions: bool,
+        attribution: Option<&crate::mcp::provenance::AiAttribution>,
+    ) -> std::result::Result<usize, AppError> {
+        let target = self
+            .doc_version_text(doc_id, version_id)
+            .await
+            .map_err(|e| AppError::new(StatusCode::INTERNAL_SERVER_ERROR, e))?
+            .ok_or_else(|| {
+                AppError::new(
+                    StatusCode::NOT_FOUND,
+                    anyhow!("Version {} of {} not found", version_id, doc_id),
+                )
+            })?;
+        let applied = self
+            .restore_doc_text(doc_id, &target, as_suggestions, attribution)
+            .await?;
+        if applied > 0 {
+            tracing::info!(
+                doc_id,
+                version_id,
+                as_suggestions,
+                edits = applied,
+                "Restored document version"
+            );
+        }
+        Ok(applied)
+    }
 
-        let loaded = std::sync::atomic::AtomicUsize::new(0);
-        let failed = std::sync::atomic::AtomicUsize::new(0);
-        let loaded_ref = &loaded;
-        let failed_ref = &failed;
+    /// Bring a doc's live text to `target` by applying the difference as an
+    /// ordinary (or, with `as_suggestions`, CriticMarkup) edit, then persist
+    /// and reindex it. Returns the number of Y.Text edits applied.
+    pub(crate) async fn restore_doc_text(
+        &self,
+        doc_id: &str,
+        target: &str,
+        as_suggestions: bool,
+        attribution: Option<&crate::mcp::provenance::AiAttribution>,
+    ) -> std::result::Result<usize, AppError> {
+        self.ensure_doc_loaded(doc_id)
+            .await
+            .map_err(|e| AppError::new(StatusCode::INTERNAL_SERVER_ERROR, e))?;
+        let awareness = self
+            .docs
+            .get(doc_id)
+            .map(|doc_ref| doc_ref.awareness())
+            .ok_or_else(|| {
+                AppError::new(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    anyhow!("Document not loaded: {}", doc_id),
+                )
+            })?;
 
-        // Load docs in parallel — each load_doc is an R2 round-trip (~200ms),
-        // so sequential loading of 300+ docs takes minutes. With concurrency
-        // of 32 it drops to seconds.
-        futures::stream::iter(doc_ids.iter().enumerate())
-            .for_each_concurrent(32, |(_, doc_id)| async move {
-                if self.docs.contains_key(doc_id) {
-                    loaded_ref.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
-                    return;
-                }
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        let meta_prefix = if as_suggestions {
+            let author = attribution
+                .map(|attr| attr.suggestion_author.as_str())
+                .unwrap_or(RESTORE_SUGGESTION_AUTHOR);
+            let author = serde_json::to_string(author)
+                .map_err(|e| AppError::new(StatusCode::INTERNAL_SERVER_ERROR, e.into()))?;
+            Some(format!(
+                "{{\"author\":{},\"timestamp\":{}}}@@",
+                author, timestamp
+            ))
+        } else {
+            None
+        };
 
-                match self.load_doc(doc_id, None).await {
-                    Ok(()) => {
-                        let n = loaded_ref.fetch_add(1, std::sync::atomic::Ordering::Relaxed) + 1;
-                        if n % 50 == 0 || n == total {
-                            tracing::info!("  Loaded {}/{} documents", n, total);
-                        }
-                    }
-                    Err(e) => {
-                        tracing::warn!("  Failed to load doc {}: {:?}", doc_id, e);
-                        failed_ref.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        // Diff and apply under one write guard, so the edits are computed
+        // against exactly the text they mutate.
+        let applied = {
+            let guard = awareness.write().unwrap_or_else(|e| e.into_inner());
+            let live = {
+                let txn = guard.doc.transact();
+                match txn.get_text("contents") {
+                    Some(text) => text.get_string(&txn),
+                    None => String::new(),
+                }
+            };
+            let edits =
+                crate::mcp::tools::critic_diff::text_edits(&live, target, meta_prefix.as_deref())
+                    .map_err(|e| {
+                    AppError::new(
+                        StatusCode::CONFLICT,
+                        anyhow!("Cannot restore as suggestions: {}", e),
+                    )
+                })?;
+            if !edits.is_empty() {
+                match attribution {
+                    Some(attr) => crate::mcp::provenance::apply_attributed_edit(
+                        &guard.doc,
+                        attr.client_id,
+                        &attr.actor,
+                        attr.user.as_deref(),
+                        timestamp,
+                        |txn, text| apply_text_edits(txn, text, &edits),
+                    )
+                    .map_err(|e| AppError::new(StatusCode::INTERNAL_SERVER_ERROR, anyhow!(e)))?,
+                    None => {
+                        let mut txn = guard.doc.transact_mut_with("restore");
+                        let text = txn.get_or_insert_text("contents");
+                        apply_text_edits(&mut txn, &text, &edits);
                     }
                 }
-            })
-            .await;
-
-        let loaded = loaded.load(std::sync::atomic::Ordering::Relaxed);
-        let failed = failed.load(std::sync::atomic::Ordering::Relaxed);
-
-        // Restore GC setting
-        self.doc_gc
-            .store(gc_was_enabled, std::sync::atomic::Ordering::Relaxed);
+            }
+            edits.len()
+        }; // awareness write released
+        if applied == 0 {
+            return Ok(0);
+        }
 
-        tracing::info!(
-            "Document loading complete: {} loaded, {} failed, {} total in storage",
-            loaded,
-            failed,
-            total
-        );
-        Ok(loaded)
+        let sync_kv = self.docs.get(doc_id).map(|doc_ref| doc_ref.sync_kv());
+        if let Some(sync_kv) = sync_kv {
+            if let Err(e) = sync_kv.persist().await {
+                tracing::error!("Failed to persist restored doc {}: {:?}", doc_id, e);
+            }
+        }
+        if let Some(ref search_index) = self.search_index {
+            search_handle_content_update(doc_id, &self.docs, search_index, &self.suggestions_index);
+        }
+        Ok(applied)
     }
 
-    /// Write folder display names from config into folder Y.Docs.
-    ///
-    /// For each configured folder, finds the folder doc whose doc_id ends with
-    /// the configured UUID, reads the current `folder_config.name` from the Y.Doc,
-    /// and writes the configured name if different. Persists changed docs to storage.
-    async fn apply_folder_names(
+    /// Take a named snapshot of a folder: the folder doc and every content
+    /// doc listed in its `filemeta_v0`, copied under `FOLDER_SNAPSHOT_PREFIX`.
+    /// Loaded docs are persisted first so the copy includes their latest
+    /// edits; docs that are not loaded are copied as stored.
+    pub async fn create_folder_snapshot(
         &self,
-        folders: &[y_sweet_core::config::FolderConfig],
-    ) -> Result<()> {
-        if folders.is_empty() {
-            return Ok(());
+        folder_doc_id: &str,
+        name: &str,
+    ) -> std::result::Result<FolderSnapshotInfo, AppError> {
+        if !validate_doc_name(name) {
+            return Err(AppError::new(
+                StatusCode::BAD_REQUEST,
+                anyhow!(
+                    "Invalid snapshot name '{}': use letters, digits, '-' and '_'",
+                    name
+                ),
+            ));
+        }
+        let store = self.snapshot_store()?;
+        let (relay_id, _) = link_indexer::parse_doc_id(folder_doc_id).ok_or_else(|| {
+            AppError::new(
+                StatusCode::BAD_REQUEST,
+                anyhow!("Invalid folder doc ID: {}", folder_doc_id),
+            )
+        })?;
+        let manifest_key = folder_snapshot_manifest_key(folder_doc_id, name);
+        if store.exists(&manifest_key).await.map_err(store_app_error)? {
+            return Err(AppError::new(
+                StatusCode::CONFLICT,
+                anyhow!("Snapshot '{}' already exists", name),
+            ));
         }
 
-        let folder_doc_ids = link_indexer::find_all_folder_docs(&self.docs);
-        let mut applied = 0;
+        self.ensure_doc_loaded(folder_doc_id).await.map_err(|e| {
+            AppError::new(StatusCode::NOT_FOUND, anyhow!("Folder not found: {}", e))
+        })?;
+        let entries = {
+            let awareness = self
+                .docs
+                .get(folder_doc_id)
+                .map(|doc_ref| doc_ref.awareness())
+                .ok_or_else(|| {
+                    AppError::new(StatusCode::NOT_FOUND, anyhow!("Folder doc not loaded"))
+                })?;
+            let guard = awareness.read().unwrap_or_else(|e| e.into_inner());
+            let txn = guard.doc.transact();
+            let filemeta = txn.get_map("filemeta_v0").ok_or_else(|| {
+                AppError::new(StatusCode::NOT_FOUND, anyhow!("Not a folder document"))
+            })?;
+            let mut entries = Vec::new();
+            for (path, value) in filemeta.iter(&txn) {
+                if let Some(id) = link_indexer::extract_id_from_filemeta_entry(&value, &txn) {
+                    entries.push((path.to_string(), format!("{}-{}", relay_id, id)));
+                }
+            }
+            entries
+        };
 
-        for folder_config in folders {
-            // Find the folder doc whose doc_id ends with this UUID
-            let Some(folder_doc_id) = folder_doc_ids.iter().find(|id| {
-                link_indexer::parse_doc_id(id)
-                    .map(|(_, uuid)| uuid == folder_config.uuid)
-                    .unwrap_or(false)
-            }) else {
-                tracing::warn!(
-                    "Folder config for '{}' (uuid={}) — no matching folder doc found",
-                    folder_config.name,
-                    folder_config.uuid
-                );
-                continue;
-            };
+        if !self
+            .snapshot_doc(store, folder_doc_id, name, folder_doc_id)
+            .await?
+        {
+            return Err(AppError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                anyhow!("Folder doc {} has not been persisted", folder_doc_id),
+            ));
+        }
+        let mut docs = std::collections::BTreeMap::new();
+        for (path, doc_id) in entries {
+            // Folders and blob files have filemeta IDs but no Y.Doc of their own.
+            if self
+                .snapshot_doc(store, folder_doc_id, name, &doc_id)
+                .await?
+            {
+                docs.insert(path, doc_id);
+            }
+        }
 
-            let (awareness, sync_kv) = {
-                let Some(doc_ref) = self.docs.get(folder_doc_id) else {
-                    continue;
-                };
-                (doc_ref.awareness(), doc_ref.sync_kv()) // Arc clones
-            }; // DashMap shard lock released
+        let info = FolderSnapshotInfo {
+            name: name.to_string(),
+            folder_doc_id: folder_doc_id.to_string(),
+            created_at: current_time_epoch_millis(),
+            docs,
+        };
+        // The manifest goes last, so an interrupted snapshot is never listed.
+        let manifest = serde_json::to_vec(&info)
+            .map_err(|e| AppError::new(StatusCode::INTERNAL_SERVER_ERROR, e.into()))?;
+        store
+            .set(&manifest_key, manifest)
+            .await
+            .map_err(store_app_error)?;
 
-            // Read current name and compare
-            let needs_update = {
-                let guard = awareness.read().unwrap_or_else(|e| e.into_inner());
-                let current_name =
-                    y_sweet_core::doc_resolver::read_folder_name(&guard.doc, folder_doc_id);
-                current_name != folder_config.name
-            };
+        tracing::info!(
+            folder_doc_id,
+            name,
+            docs = info.docs.len(),
+            "Created folder snapshot"
+        );
+        Ok(info)
+    }
 
-            if !needs_update {
-                tracing::debug!(
-                    "Folder '{}' already has correct name, skipping",
-                    folder_config.name
-                );
+    /// Named snapshots of a folder, newest first.
+    pub async fn list_folder_snapshots(
+        &self,
+        folder_doc_id: &str,
+    ) -> std::result::Result<Vec<FolderSnapshotInfo>, AppError> {
+        let store = self.snapshot_store()?;
+        let files = store
+            .list(&format!("{}/{}/", FOLDER_SNAPSHOT_PREFIX, folder_doc_id))
+            .await
+            .map_err(store_app_error)?;
+        let mut snapshots = Vec::new();
+        for file in files {
+            let Some(name) = file.key.strip_suffix(".json") else {
                 continue;
+            };
+            if let Some(info) = self.folder_snapshot(folder_doc_id, name).await? {
+                snapshots.push(info);
             }
+        }
+        snapshots.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+        Ok(snapshots)
+    }
 
-            // Write the folder name
-            {
-                let guard = awareness.read().unwrap_or_else(|e| e.into_inner());
-                let mut txn = guard.doc.transact_mut();
-                let config_map = txn.get_or_insert_map("folder_config");
-                config_map.insert(
-                    &mut txn,
-                    "name",
-                    yrs::Any::String(folder_config.name.clone().into()),
-                );
-            }
-
-            // Persist to storage
-            sync_kv.persist().await.map_err(|e| {
-                anyhow!(
-                    "Failed to persist folder name for '{}': {:?}",
-                    folder_config.name,
-                    e
+    /// Roll a folder back to a named snapshot. Content docs get the text
+    /// difference applied as an ordinary edit (see `restore_doc_text`), and
+    /// the folder doc's `FOLDER_SNAPSHOT_MAPS` get their entries re-set or
+    /// removed to match, so connected editors converge on the snapshot.
+    /// Docs created after the snapshot are dropped from the folder's maps;
+    /// the trash worker picks them up like any other deletion.
+    pub async fn restore_folder_snapshot(
+        &self,
+        folder_doc_id: &str,
+        name: &str,
+    ) -> std::result::Result<FolderSnapshotRestoreResponse, AppError> {
+        let store = self.snapshot_store()?;
+        let info = self
+            .folder_snapshot(folder_doc_id, name)
+            .await?
+            .ok_or_else(|| {
+                AppError::new(
+                    StatusCode::NOT_FOUND,
+                    anyhow!("Snapshot '{}' of {} not found", name, folder_doc_id),
                 )
             })?;
 
-            tracing::info!(
-                "Applied folder name '{}' to doc {}",
-                folder_config.name,
-                folder_doc_id
-            );
-            applied += 1;
+        let mut docs_restored = 0;
+        for doc_id in info.docs.values() {
+            let snapshot = self
+                .snapshot_bytes(store, folder_doc_id, name, doc_id)
+                .await?;
+            let target = snapshot_text(&snapshot)
+                .map_err(|e| AppError::new(StatusCode::INTERNAL_SERVER_ERROR, e))?;
+            if self.restore_doc_text(doc_id, &target, false, None).await? > 0 {
+                docs_restored += 1;
+            }
         }
 
-        if applied > 0 {
-            tracing::info!("Applied {} folder name(s) from config", applied);
+        let snapshot = self
+            .snapshot_bytes(store, folder_doc_id, name, folder_doc_id)
+            .await?;
+        let snapshot_doc = doc_from_snapshot(&snapshot)
+            .map_err(|e| AppError::new(StatusCode::INTERNAL_SERVER_ERROR, e))?;
+        self.ensure_doc_loaded(folder_doc_id)
+            .await
+            .map_err(|e| AppError::new(StatusCode::INTERNAL_SERVER_ERROR, e))?;
+        let (awareness, sync_kv) = self
+            .docs
+            .get(folder_doc_id)
+            .map(|doc_ref| (doc_ref.awareness(), doc_ref.sync_kv()))
+            .ok_or_else(|| {
+                AppError::new(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    anyhow!("Document not loaded: {}", folder_doc_id),
+                )
+            })?;
+        let folder_entries_restored = {
+            let guard = awareness.write().unwrap_or_else(|e| e.into_inner());
+            let mut txn = guard.doc.transact_mut_with("restore");
+            let mut changed = 0;
+            for map_name in FOLDER_SNAPSHOT_MAPS {
+                let target = folder_map_entries(&snapshot_doc, map_name);
+                let map = match txn.get_map(map_name) {
+                    Some(map) => map,
+                    None if target.is_empty() => continue,
+                    None => txn.get_or_insert_map(map_name),
+                };
+                let live: std::collections::HashMap<String, yrs::Any> = map
+                    .iter(&txn)
+                    .map(|(key, value)| (key.to_string(), value.to_json(&txn)))
+                    .collect();
+                for key in live.keys() {
+                    if !target.contains_key(key) {
+                        map.remove(&mut txn, key);
+                        changed += 1;
+                    }
+                }
+                for (key, value) in target {
+                    if live.get(&key) != Some(&value) {
+                        map.insert(&mut txn, key, value);
+                        changed += 1;
+                    }
+                }
+            }
+            changed
+        }; // awareness write released
+        if folder_entries_restored > 0 {
+            if let Err(e) = sync_kv.persist().await {
+                tracing::error!(
+                    "Failed to persist restored folder doc {}: {:?}",
+                    folder_doc_id,
+                    e
+                );
+            }
+            self.doc_resolver.rebuild(&self.docs);
         }
 
-        Ok(())
-    }
-
-    /// Scan every loaded content doc for CriticMarkup and (re)build the
-    /// suggestions index, then mark it ready. Called from `startup_reindex`
-    /// while all docs are in memory; incremental updates afterwards come from
-    /// the search worker.
-    pub(crate) fn rebuild_suggestions_index(&self) {
-        // Snapshot keys first: iterating the DashMap while acquiring awareness
-        // locks would violate the lock ordering (shard < awareness), see the
-        // search_worker comment.
-        let doc_ids: Vec<String> = self.docs.iter().map(|e| e.key().clone()).collect();
-        let mut indexed = 0;
-        for doc_id in &doc_ids {
-            let Some((_relay_id, doc_uuid)) = link_indexer::parse_doc_id(doc_id) else {
-                continue;
-            };
-            let awareness = {
-                let Some(doc_ref) = self.docs.get(doc_id) else {
-                    continue;
-                };
-                doc_ref.awareness() // Arc clone
-            }; // DashMap shard lock released
-            let content = {
-                let guard = awareness.read().unwrap_or_else(|e| e.into_inner());
-                let txn = guard.doc.transact();
-                match txn.get_text("contents") {
-                    Some(text) => text.get_string(&txn),
-                    // Folder docs and blobs have no "contents" text
-                    None => continue,
-                }
-            };
-            let suggestions = critic_scanner::scan_suggestions(&content);
-            if !suggestions.is_empty() {
-                indexed += 1;
-            }
-            self.suggestions_index.update(doc_uuid, suggestions);
-        }
-        self.suggestions_ready
-            .store(true, std::sync::atomic::Ordering::Release);
         tracing::info!(
-            "Suggestions index built: {} of {} docs have suggestions",
-            indexed,
-            doc_ids.len()
+            folder_doc_id,
+            name,
+            docs_restored,
+            folder_entries_restored,
+            "Restored folder snapshot"
         );
+        Ok(FolderSnapshotRestoreResponse {
+            name: name.to_string(),
+            docs_restored,
+            folder_entries_restored,
+        })
     }
 
-    /// Load all documents from storage and reindex all backlinks.
-    ///
-    /// Called once on startup, before accepting connections.
-    /// No-op if no store is configured (in-memory mode).
-    pub async fn startup_reindex(
-        &self,
-        folders: &[y_sweet_core::config::FolderConfig],
-    ) -> Result<()> {
-        if self.store.is_none() {
-            tracing::info!("No store configured, skipping startup reindex");
-            // Even without a store, mark search as ready (empty index)
-            self.search_ready
-                .store(true, std::sync::atomic::Ordering::Release);
-            self.suggestions_ready
-                .store(true, std::sync::atomic::Ordering::Release);
-            return Ok(());
-        }
-
-        let loaded = self.load_all_docs().await?;
-        tracing::info!("Loaded {} documents, now reindexing backlinks...", loaded);
+    fn snapshot_store(&self) -> std::result::Result<&dyn Store, AppError> {
+        self.store.as_ref().map(|store| &***store).ok_or_else(|| {
+            AppError::new(
+                StatusCode::SERVICE_UNAVAILABLE,
+                anyhow!("Folder snapshots need a store"),
+            )
+        })
+    }
 
-        // Apply folder names from config before reindexing
-        self.apply_folder_names(folders).await?;
+    async fn folder_snapshot(
+        &self,
+        folder_doc_id: &str,
+        name: &str,
+    ) -> std::result::Result<Option<FolderSnapshotInfo>, AppError> {
+        let store = self.snapshot_store()?;
+        let Some(manifest) = store
+            .get(&folder_snapshot_manifest_key(folder_doc_id, name))
+            .await
+            .map_err(store_app_error)?
+        else {
+            return Ok(None);
+        };
+        serde_json::from_slice(&manifest)
+            .map(Some)
+            .map_err(|e| AppError::new(StatusCode::INTERNAL_SERVER_ERROR, e.into()))
+    }
 
-        if let Some(ref indexer) = self.link_indexer {
-            indexer.reindex_all_backlinks(&self.docs)?;
+    /// Copy one doc's persisted snapshot into a folder snapshot. Returns
+    /// false when the store has nothing for the doc.
+    async fn snapshot_doc(
+        &self,
+        store: &dyn Store,
+        folder_doc_id: &str,
+        name: &str,
+        doc_id: &str,
+    ) -> std::result::Result<bool, AppError> {
+        let sync_kv = self.docs.get(doc_id).map(|doc_ref| doc_ref.sync_kv());
+        if let Some(sync_kv) = sync_kv {
+            sync_kv.persist().await.map_err(|e| {
+                AppError::new(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    anyhow!("Failed to persist {}: {:?}", doc_id, e),
+                )
+            })?;
         }
+        let Some(data) = store
+            .get(&format!("{}/data.ysweet", doc_id))
+            .await
+            .map_err(store_app_error)?
+        else {
+            return Ok(false);
+        };
+        store
+            .set(&folder_snapshot_doc_key(folder_doc_id, name, doc_id), data)
+            .await
+            .map_err(store_app_error)?;
+        Ok(true)
+    }
 
-        // Build document resolver (bidirectional path <-> UUID mapping)
-        self.doc_resolver.rebuild(&self.docs);
-        tracing::info!(
-            "Document resolver built: {} documents",
-            self.doc_resolver.all_paths().len()
-        );
-
-        // Build the suggestions index while everything is in memory
-        self.rebuild_suggestions_index();
-
-        // Build search index from all loaded documents
-        if let Some(ref search_index) = self.search_index {
-            tracing::info!("Building search index from loaded documents...");
-            let mut indexed = 0;
+    async fn snapshot_bytes(
+        &self,
+        store: &dyn Store,
+        folder_doc_id: &str,
+        name: &str,
+        doc_id: &str,
+    ) -> std::result::Result<Vec<u8>, AppError> {
+        store
+            .get(&folder_snapshot_doc_key(folder_doc_id, name, doc_id))
+            .await
+            .map_err(store_app_error)?
+            .ok_or_else(|| {
+                AppError::new(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    anyhow!("Snapshot '{}' is missing {}", name, doc_id),
+                )
+            })
+    }
 
-            // Find all folder docs and build uuid -> (title, folder_name) map
-            let folder_doc_ids = link_indexer::find_all_folder_docs(&self.docs);
-            let mut uuid_metadata: std::collections::HashMap<String, (String, String)> =
-                std::collections::HashMap::new();
+    /// Check every loaded folder doc's filemeta_v0 (see `fsck::check_folder`),
+    /// then, when there is a store, look for entries whose content doc or
+    /// blob is missing and for content docs no folder lists. With `repair`,
+    /// structural fixes are applied as live edits and persisted.
+    pub async fn fsck(&self, repair: bool) -> Result<FsckReport> {
+        let folder_doc_ids = link_indexer::find_all_folder_docs(&self.docs);
+        let mut report = FsckReport {
+            repair,
+            ..Default::default()
+        };
+        let mut referenced = std::collections::HashSet::new();
+        let mut relay_ids = std::collections::HashSet::new();
+        let mut repaired_any = false;
 
-            for folder_doc_id in &folder_doc_ids {
-                let awareness = {
-                    let Some(doc_ref) = self.docs.get(folder_doc_id) else {
-                        continue;
-                    };
-                    doc_ref.awareness() // Arc clone
-                }; // DashMap shard lock released
+        for folder_doc_id in &folder_doc_ids {
+            let Some((relay_id, _)) = link_indexer::parse_doc_id(folder_doc_id) else {
+                continue;
+            };
+            relay_ids.insert(relay_id.to_string());
+            let Some((awareness, sync_kv)) = self
+                .docs
+                .get(folder_doc_id)
+                .map(|doc_ref| (doc_ref.awareness(), doc_ref.sync_kv()))
+            else {
+                continue;
+            };
+            let (check, folder_name) = if repair {
+                let guard = awareness.write().unwrap_or_else(|e| e.into_inner());
+                (
+                    fsck::check_folder(&guard.doc, true),
+                    y_sweet_core::doc_resolver::read_folder_name(&guard.doc, folder_doc_id),
+                )
+            } else {
                 let guard = awareness.read().unwrap_or_else(|e| e.into_inner());
-                let folder_name =
-                    y_sweet_core::doc_resolver::read_folder_name(&guard.doc, folder_doc_id);
-                let txn = guard.doc.transact();
-                let Some(filemeta) = txn.get_map("filemeta_v0") else {
+                (
+                    fsck::check_folder(&guard.doc, false),
+                    y_sweet_core::doc_resolver::read_folder_name(&guard.doc, folder_doc_id),
+                )
+            };
+            if repair && !check.issues.is_empty() {
+                repaired_any = true;
+                if let Err(e) = sync_kv.persist().await {
+                    tracing::error!(
+                        "Failed to persist repaired folder doc {}: {:?}",
+                        folder_doc_id,
+                        e
+                    );
+                }
+            }
+
+            let mut issues = check.issues;
+            for entry in &check.entries {
+                let Some(id) = &entry.id else {
                     continue;
                 };
-
-                for (path, value) in filemeta.iter(&txn) {
-                    if let Some(uuid) = link_indexer::extract_id_from_filemeta_entry(&value, &txn) {
-                        // Extract title: strip leading "/" and trailing ".md", take basename
-                        let title = path
-                            .strip_prefix('/')
-                            .unwrap_or(&path)
-                            .strip_suffix(".md")
-                            .unwrap_or(&path)
-                            .rsplit('/')
-                            .next()
-                            .unwrap_or(&path)
-                            .to_string();
-                        uuid_metadata.insert(uuid, (title, folder_name.clone()));
-                    }
+                let doc_id = format!("{}-{}", relay_id, id);
+                referenced.insert(doc_id.clone());
+                if entry.is_folder() || self.docs.contains_key(&doc_id) {
+                    continue;
+                }
+                let Some(store) = &self.store else {
+                    continue;
+                };
+                let (key, what) = match &entry.hash {
+                    Some(hash) => (format!("files/{}/{}", doc_id, hash), "blob"),
+                    None => (format!("{}/data.ysweet", doc_id), "content doc"),
+                };
+                if !store.exists(&key).await? {
+                    issues.push(FsckIssue {
+                        kind: FsckIssueKind::DanglingId,
+                        path: Some(entry.path.clone()),
+                        id: Some(id.clone()),
+                        detail: format!("no {} in the store", what),
+                        repaired: false,
+                    });
                 }
             }
+            report.folders.push(FolderFsckReport {
+                folder_doc_id: folder_doc_id.clone(),
+                folder_name,
+                entries: check.entries.len(),
+                issues,
+            });
+        }
 
-            tracing::info!(
-                "Found {} documents in {} folder doc(s) for search indexing",
-                uuid_metadata.len(),
-                folder_doc_ids.len()
-            );
-
-            // For each UUID in the metadata map, find the content doc and index it
-            for (uuid, (title, folder_name)) in &uuid_metadata {
-                // Try to find the content doc — it might be under any relay_id prefix
-                // Search through all loaded docs for one ending with this UUID.
-                // Clone the awareness Arc out of the DashMap iter to avoid holding
-                // shard locks across the awareness read lock.
-                let awareness = {
-                    let mut found = None;
-                    for entry in self.docs.iter() {
-                        if let Some((_relay_id, doc_uuid)) = link_indexer::parse_doc_id(entry.key())
+        if let Some(store) = &self.store {
+            match store.list_doc_ids().await {
+                Ok(mut doc_ids) => {
+                    doc_ids.sort();
+                    for doc_id in doc_ids {
+                        let Some((relay_id, _)) = link_indexer::parse_doc_id(&doc_id) else {
+                            continue;
+                        };
+                        if !relay_ids.contains(relay_id)
+                            || referenced.contains(&doc_id)
+                            || folder_doc_ids.contains(&doc_id)
                         {
-                            if doc_uuid == uuid {
-                                found = Some(entry.value().awareness());
-                                break;
-                            }
+                            continue;
                         }
-                    }
-                    found
-                }; // DashMap iter / shard locks released
-                let mut body = String::new();
-                if let Some(awareness) = awareness {
-                    let guard = awareness.read().unwrap_or_else(|e| e.into_inner());
-                    let txn = guard.doc.transact();
-                    if let Some(text) = txn.get_text("contents") {
-                        body = text.get_string(&txn);
-                    }
-                }
-
-                match search_index.add_document_buffered(uuid, title, &body, folder_name) {
-                    Ok(()) => indexed += 1,
-                    Err(e) => {
-                        tracing::error!("Failed to index doc {} into search: {:?}", uuid, e);
+                        report.orphaned_docs.push(FsckIssue {
+                            kind: FsckIssueKind::OrphanedDoc,
+                            path: None,
+                            id: Some(doc_id),
+                            detail: "no folder's filemeta_v0 lists this doc".to_string(),
+                            repaired: false,
+                        });
                     }
                 }
+                Err(e) => tracing::warn!("fsck: cannot list docs, skipping orphan check: {}", e),
             }
+        }
 
-            if let Err(e) = search_index.flush() {
-                tracing::error!("Failed to flush search index: {}", e);
-            }
-            tracing::info!("Search index built: {} documents indexed", indexed);
+        if repaired_any {
+            self.doc_resolver.rebuild(&self.docs);
         }
+        tracing::info!(
+            repair,
+            folders = report.folders.len(),
+            issues = report.issue_count(),
+            "fsck finished"
+        );
+        Ok(report)
+    }
 
-        // Mark search as ready after indexing is complete
-        self.search_ready
-            .store(true, std::sync::atomic::Ordering::Release);
-        tracing::info!("Search index is now ready for queries");
+    pub async fn load_doc_with_user(
+        &self,
+        doc_id: &str,
+        routing_channel: Option<String>,
+        user: Option<String>,
+    ) -> Result<()> {
+        Self::validate_doc_id(doc_id)?;
+        let (send, recv) = channel(1024);
+        let indexing_lease = Arc::new(std::sync::OnceLock::new());
 
-        Ok(())
-    }
+        // Determine routing channel: use provided channel or fallback to doc_id
+        let routing_channel_name = routing_channel
+            .clone()
+            .unwrap_or_else(|| doc_id.to_string());
 
-    /// GC step for a single doc: remove it from the docs map, then compact
-    /// its PermanentUserData and shut down its persistence. Removal is
-    /// declined — the doc stays fully alive — when its awareness gained a
-    /// holder (derived-index lease, MCP handler, new connection) after the
-    /// GC worker's last checkpoint sample. Returns true when the doc was
-    /// removed (or was already gone).
-    ///
-    /// Removing first means compaction only ever runs on a doomed doc no
-    /// new holder can reach, and the extracted value keeps the update
-    /// subscription and SyncKv alive so the compacted state still persists
-    /// (the persistence worker holds its own SyncKv Arc). Shutdown comes
-    /// last: mark_dirty is a no-op post-shutdown, so shutting down earlier
-    /// could silently drop the compaction from persistence.
-    ///
-    /// Blocking — compaction takes the awareness write lock and can run
-    /// long on bloated PUD; call via spawn_blocking from async context. The
-    /// remove_if predicate is a single atomic load; no shard guard is ever
-    /// held across a blocking lock (2026-07-31/08-02 prod hangs;
-    /// regression test: search_deadlock.rs Test 7).
-    pub fn gc_compact_and_remove(docs: &DashMap<String, DocWithSyncKv>, doc_id: &str) -> bool {
-        // The checkpoints merely sample refs, so a final edit (e.g. via
-        // MCP) can slip in after the last sample and queue a leased index
-        // entry; the predicate re-checks at commit time. (GC is the only
-        // path that removes docs, so the entry can't have been swapped
-        // since the samples — if other removers appear, compare identity
-        // first so a reinserted doc isn't dropped uncompacted.)
-        let Some((_, doc)) = docs.remove_if(doc_id, |_, doc| !doc.has_external_refs()) else {
-            // None: either declined (doc re-referenced — leave it alive) or
-            // already gone.
-            return !docs.contains_key(doc_id);
+        // If this doc routes to a different channel (i.e., it's a subdoc),
+        // ensure the parent is loaded and hold a reference to prevent GC.
+        let parent_awareness_guard = if routing_channel_name != doc_id {
+            if !self.docs.contains_key(&routing_channel_name) {
+                self.load_doc(&routing_channel_name, None).await?;
+            }
+            self.docs
+                .get(&routing_channel_name)
+                .map(|parent| parent.awareness())
+        } else {
+            None
         };
 
-        // Compact PUD: dedup ids, clear ds. The mutations create tombstones
-        // which yrs GC will clean up, and the update observer (owned by the
-        // extracted value) marks SyncKv dirty so the compacted state gets
-        // persisted.
-        let started = std::time::Instant::now();
-        let result = y_sweet_core::permanent_user_data::compact_user_data_locked(&doc.awareness());
-        if !result.is_empty() {
-            tracing::info!(
-                ids_removed = result.ids_removed,
-                ds_removed = result.ds_removed,
-                elapsed_ms = started.elapsed().as_millis() as u64,
-                "Compacted PermanentUserData"
-            );
-        }
-        doc.sync_kv().shutdown();
-        true
-    }
-
-    async fn doc_gc_worker(
-        docs: Arc<DashMap<String, DocWithSyncKv>>,
-        doc_id: String,
-        checkpoint_freq: Duration,
-        cancellation_token: CancellationToken,
-    ) {
-        let mut checkpoints_without_refs = 0;
+        // Create event callback with the determined routing channel and user
+        let event_callback = {
+            let event_dispatcher = self.event_dispatcher.clone();
+            let routing_channel_for_callback = routing_channel_name.clone();
+            let user_for_callback = user.clone();
+            let link_indexer_for_callback = self.link_indexer.clone();
+            let search_tx_for_callback = self.search_tx.clone();
+            let search_pending_for_callback = self.search_pending.clone();
+            let doc_key_for_indexer = doc_id.to_string();
+            let docs = self.docs.clone();
+            let indexing_lease_for_callback = indexing_lease.clone();
+            let doc_id_for_callback = doc_id.to_string();
+            // Capture parent awareness to keep it alive (prevents GC while subdoc exists)
+            let _parent_awareness = parent_awareness_guard;
 
-        loop {
-            tokio::select! {
-                _ = tokio::time::sleep(checkpoint_freq) => {
-                    if let Some(doc) = docs.get(&doc_id) {
-                        if doc.has_external_refs() {
-                            checkpoints_without_refs = 0;
-                            tracing::debug!("doc is still alive - it has external references");
-                        } else {
-                            checkpoints_without_refs += 1;
-                            tracing::info!("doc has only one reference, candidate for GC. checkpoints_without_refs: {}", checkpoints_without_refs);
+            if let Some(dispatcher) = event_dispatcher {
+                Some(Arc::new(
+                    move |mut event: DocumentUpdatedEvent, suppress_derived_index: bool| {
+                        // Keep parent awareness alive by referencing it in the closure
+                        let _ = &_parent_awareness;
+
+                        // Update parent's subdoc state vector index
+                        if routing_channel_for_callback != doc_id_for_callback {
+                            if let Some(state_vector) = &event.state_vector {
+                                if let Some(parent) = docs.get(&routing_channel_for_callback) {
+                                    parent.update_subdoc_state_vector(
+                                        &doc_id_for_callback,
+                                        state_vector.clone(),
+                                    );
+                                }
+                            }
                         }
-                    } else {
-                        break;
-                    }
 
-                    if checkpoints_without_refs >= 2 {
-                        tracing::info!("GCing doc");
-                        // Off the async runtime: the GC step takes blocking
-                        // std locks and compaction can run long, which would
-                        // otherwise pin one of the few worker threads.
-                        let docs = Arc::clone(&docs);
-                        let doc_id = doc_id.clone();
-                        match tokio::task::spawn_blocking(move || {
-                            Self::gc_compact_and_remove(&docs, &doc_id)
-                        })
-                        .await
-                        {
-                            Ok(true) => break,
-                            Ok(false) => {
-                                // A holder appeared after the checkpoint
-                                // samples (index lease, MCP handler, new
-                                // connection). Start the count over.
-                                tracing::info!("GC deferred: doc re-referenced before removal");
-                                checkpoints_without_refs = 0;
+                        // Add the loading user unless the write named its own
+                        // (server-side edits made for a user, see user_origin)
+                        if event.user.is_none() {
+                            event.user = user_for_callback.clone();
+                        }
+
+                        // Log the full event payload as JSON after user assignment
+                        match serde_json::to_string(&event) {
+                            Ok(json_str) => {
+                                tracing::debug!("Document updated event dispatched: {}", json_str);
                             }
                             Err(e) => {
-                                tracing::error!(?e, "GC compact task panicked");
-                                break;
+                                tracing::debug!(
+                                "Document updated event dispatched for doc_id: {} (JSON serialization failed: {})",
+                                event.doc_id, e
+                            );
                             }
                         }
-                    }
-                }
-                _ = cancellation_token.cancelled() => {
-                    break;
-                }
-            };
-        }
-        tracing::info!("Exiting gc_loop");
-    }
 
-    async fn doc_persistence_worker(
-        mut recv: Receiver<()>,
-        sync_kv: Arc<SyncKv>,
-        checkpoint_freq: Duration,
-        doc_id: String,
-        cancellation_token: CancellationToken,
-        last_successful_persist: Arc<AtomicU64>,
-    ) {
-        let mut last_save = std::time::Instant::now();
-        let mut consecutive_failures: u32 = 0;
+                        // Step 1: Create the envelope with predetermined routing channel
+                        let envelope =
+                            EventEnvelope::new(routing_channel_for_callback.clone(), event);
 
-        loop {
-            let is_done = tokio::select! {
-                v = recv.recv() => v.is_none(),
-                _ = cancellation_token.cancelled() => true,
-                _ = tokio::time::sleep(checkpoint_freq) => {
-                    sync_kv.is_shutdown()
-                }
-            };
+                        // Step 2: Send via dispatcher
+                        dispatcher.send_event(envelope);
 
-            tracing::debug!("Received signal. done: {}", is_done);
-            let now = std::time::Instant::now();
-            if !is_done && now - last_save < checkpoint_freq {
-                let sleep = tokio::time::sleep(checkpoint_freq - (now - last_save));
-                tokio::pin!(sleep);
-                tracing::info!("Throttling.");
+                        // Notify link indexer (if this update is not from the indexer itself)
+                        if !suppress_derived_index {
+                            // Upgrade the prewired weak self-reference. Never
+                            // access `docs` here: callback holds awareness WRITE,
+                            // while readers may hold a docs shard then wait for
+                            // awareness READ (AB/BA deadlock).
+                            let indexing_lease = indexing_lease_for_callback
+                                .get()
+                                .and_then(std::sync::Weak::upgrade);
+                            if indexing_lease.is_none() {
+                                // Unreachable by construction (load_doc sets the
+                                // weak before the doc is reachable); queueing
+                                // without a lease reopens the GC race.
+                                tracing::warn!(
+                                    doc_id = %doc_id_for_callback,
+                                    "Derived-index queue has no GC lease"
+                                );
+                            }
+                            if let Some(ref indexer) = link_indexer_for_callback {
+                                // Queue synchronously while this callback still
+                                // holds awareness write lock, closing the gap in
+                                // which GC could observe no external reference.
+                                indexer.queue_document_update_with_lease(
+                                    &doc_key_for_indexer,
+                                    indexing_lease.clone(),
+                                );
+                            }
 
-                loop {
-                    tokio::select! {
-                        _ = &mut sleep => {
-                            break;
-                        }
-                        v = recv.recv() => {
-                            tracing::info!("Received dirty while throttling.");
-                            if v.is_none() {
-                                break;
+                            // ⚠️ This runs synchronously inside an awareness write lock.
+                            // Any lock acquired here must be LOWER than awareness in the
+                            // lock ordering. DashMap shard locks (via .entry()) are lower,
+                            // so this is safe — but code iterating this DashMap must NOT
+                            // hold shard locks while acquiring awareness locks.
+
+                            // Notify search index worker (with deduplication)
+                            if let Some(ref tx) = search_tx_for_callback {
+                                if let Some(ref pending) = search_pending_for_callback {
+                                    let is_new = link_indexer::upsert_pending_update(
+                                        pending,
+                                        &doc_key_for_indexer,
+                                        indexing_lease.clone(),
+                                    );
+                                    if is_new {
+                                        if let Err(e) = tx.try_send(doc_key_for_indexer.clone()) {
+                                            tracing::error!("Search index channel send failed (worker dead?): {e}");
+                                        }
+                                    }
+                                }
                             }
                         }
-                        _ = cancellation_token.cancelled() => {
-                            tracing::info!("Received cancellation while throttling.");
-                            break;
-                        }
-
-                    }
-                    tracing::info!("Done throttling.");
-                }
-            }
-            tracing::debug!("Persisting.");
-            if let Err(e) = sync_kv.persist().await {
-                consecutive_failures += 1;
-                if consecutive_failures >= 10 {
-                    tracing::error!(
-                        ?e,
-                        consecutive_failures,
-                        "Persist failing repeatedly for {}",
-                        doc_id
-                    );
-                } else {
-                    tracing::error!(?e, "Error persisting.");
-                }
+                    },
+                ) as y_sweet_core::webhook::WebhookCallback)
             } else {
-                if consecutive_failures > 0 {
-                    tracing::info!(
-                        "Persist succeeded after {} consecutive failures",
-                        consecutive_failures
-                    );
-                }
-                consecutive_failures = 0;
+                // Server::new always constructs an event_dispatcher (with
+                // webhooks if configured, otherwise sync-protocol-only). The
+                // None case is reachable only via #[cfg(test)] helpers that
+                // bypass the doc-creation pipeline, so no callback is needed.
+                None
+            }
+        };
+
+        let last_dirty_for_callback = self.last_dirty_signal.clone();
+        let dwskv = DocWithSyncKv::new(
+            doc_id,
+            self.store.clone(),
+            move || {
                 let now_ms = std::time::SystemTime::now()
                     .duration_since(std::time::UNIX_EPOCH)
                     .unwrap_or_default()
                     .as_millis() as u64;
-                last_successful_persist.store(now_ms, AtomicOrdering::Relaxed);
-                tracing::debug!("Done persisting.");
-            }
-            last_save = std::time::Instant::now();
+                last_dirty_for_callback.store(now_ms, AtomicOrdering::Relaxed);
+                if let Err(e) = send.try_send(()) {
+                    match e {
+                        tokio::sync::mpsc::error::TrySendError::Closed(_) => {
+                            tracing::error!(
+                                "Dirty signal channel closed — persistence worker is dead"
+                            );
+                        }
+                        tokio::sync::mpsc::error::TrySendError::Full(_) => {
+                            // Normal: signals coalesce when channel is full. The persistence
+                            // worker will pick up the dirty state on its next cycle.
+                        }
+                    }
+                }
+            },
+            event_callback,
+        )
+        .await?;
 
-            if is_done {
-                break;
-            }
-        }
-        tracing::info!("Terminating loop for {}", doc_id);
-    }
+        indexing_lease
+            .set(Arc::downgrade(&dwskv.awareness()))
+            .expect("indexing lease OnceLock is set exactly once per load_doc");
 
-    pub async fn get_or_create_doc(
-        &self,
-        doc_id: &str,
-    ) -> Result<MappedRef<'_, String, DocWithSyncKv, DocWithSyncKv>> {
-        if !self.docs.contains_key(doc_id) {
-            tracing::info!(doc_id=?doc_id, "Loading doc");
-            self.load_doc(doc_id, None).await?;
+        // If channel is provided in token, store it in document metadata
+        if let Some(channel_name) = routing_channel {
+            dwskv.set_channel(&channel_name);
         }
 
-        Ok(self
-            .docs
-            .get(doc_id)
-            .ok_or_else(|| anyhow!("Failed to get-or-create doc"))?
-            .map(|d| d))
-    }
-
-    pub async fn get_or_create_doc_with_channel(
-        &self,
-        doc_id: &str,
-        routing_channel: Option<String>,
-    ) -> Result<MappedRef<'_, String, DocWithSyncKv, DocWithSyncKv>> {
-        self.get_or_create_doc_with_channel_and_user(doc_id, routing_channel, None)
+        dwskv
+            .sync_kv()
+            .persist()
             .await
-    }
+            .map_err(|e| anyhow!("Error persisting: {:?}", e))?;
 
-    pub async fn get_or_create_doc_with_channel_and_user(
-        &self,
-        doc_id: &str,
-        routing_channel: Option<String>,
-        user: Option<String>,
-    ) -> Result<MappedRef<'_, String, DocWithSyncKv, DocWithSyncKv>> {
-        if !self.docs.contains_key(doc_id) {
-            tracing::info!(doc_id=?doc_id, channel=?routing_channel, user=?user, "Loading doc with channel and user");
-            self.load_doc_with_user(doc_id, routing_channel, user)
-                .await?;
-        }
+        {
+            let sync_kv = dwskv.sync_kv();
+            let checkpoint_freq = self.checkpoint_freq;
+            let doc_id = doc_id.to_string();
+            let cancellation_token = self.cancellation_token.clone();
 
-        Ok(self
-            .docs
-            .get(doc_id)
-            .ok_or_else(|| anyhow!("Failed to get-or-create doc"))?
-            .map(|d| d))
-    }
+            // Spawn a task to save the document to the store when it changes.
+            let last_persist = self.last_successful_persist.clone();
+            let regression_watch = RegressionWatch {
+                detector: self.regression_detector.clone(),
+                awareness: Arc::downgrade(&dwskv.awareness()),
+                store: self.store.clone(),
+                metrics: self.metrics.clone(),
+            };
+            self.doc_worker_tracker.spawn(
+                Self::doc_persistence_worker(
+                    recv,
+                    sync_kv,
+                    checkpoint_freq,
+                    doc_id.clone(),
+                    cancellation_token.clone(),
+                    last_persist,
+                    regression_watch,
+                )
+                .instrument(span!(Level::INFO, "save_loop", doc_id=?doc_id)),
+            );
 
-    pub fn check_auth(
-        &self,
-        auth_header: Option<TypedHeader<headers::Authorization<headers::authorization::Bearer>>>,
-    ) -> Result<(), AppError> {
-        if let Some(auth) = &self.authenticator {
-            if let Some(TypedHeader(headers::Authorization(bearer))) = auth_header {
-                if let Ok(()) =
-                    auth.verify_server_token(bearer.token(), current_time_epoch_millis())
-                {
-                    return Ok(());
-                }
-                return Err(AppError::auth(
-                    StatusCode::UNAUTHORIZED,
-                    anyhow!("Unauthorized."),
-                    "invalid_server_token",
-                ));
+            if self.doc_gc.load(std::sync::atomic::Ordering::Relaxed) {
+                self.doc_worker_tracker.spawn(
+                    Self::doc_gc_worker(
+                        self.docs.clone(),
+                        doc_id.clone(),
+                        checkpoint_freq,
+                        cancellation_token,
+                    )
+                    .instrument(span!(Level::INFO, "gc_loop", doc_id=?doc_id)),
+                );
             }
-            Err(AppError::auth(
-                StatusCode::UNAUTHORIZED,
-                anyhow!("Unauthorized."),
-                "missing_token",
-            ))
-        } else {
-            Ok(())
         }
-    }
 
-    pub async fn redact_error_middleware(req: Request, next: Next) -> impl IntoResponse {
-        let resp = next.run(req).await;
-        if resp.status().is_server_error() || resp.status().is_client_error() {
-            // If we should redact errors, copy over only the status code and
-            // not the response body.
-            return resp.status().into_response();
-        }
-        resp
-    }
+        self.docs.insert(doc_id.to_string(), dwskv);
 
-    pub async fn version_header_middleware(req: Request, next: Next) -> impl IntoResponse {
-        let mut resp = next.run(req).await;
-        resp.headers_mut().insert(
-            HeaderName::from_static("relay-server-version"),
-            HeaderValue::from_static(RELAY_SERVER_VERSION),
-        );
-        resp
+        Ok(())
     }
 
-    pub fn routes_with_metrics(self: &Arc<Self>) -> Router {
-        self.routes().layer(middleware::from_fn_with_state(
-            self.clone(),
-            auth_metrics_middleware,
-        ))
+    /// Load all documents from storage into memory.
+    ///
+    /// Enumerates all doc IDs in the store and calls `load_doc()` for each.
+    /// Used on startup to populate the in-memory doc map before reindexing backlinks.
+    pub async fn load_all_docs(&self) -> Result<usize> {
+        let doc_ids = self.list_stored_doc_ids().await?;
+        Ok(self.load_docs(&doc_ids).await)
     }
 
-    pub fn single_doc_routes_with_metrics(self: &Arc<Self>) -> Router {
-        self.single_doc_routes()
-            .layer(middleware::from_fn_with_state(
-                self.clone(),
-                auth_metrics_middleware,
-            ))
-    }
+    /// Ids of every doc in the store.
+    async fn list_stored_doc_ids(&self) -> Result<Vec<String>> {
+        let store = self
+            .store
+            .as_ref()
+            .ok_or_else(|| anyhow!("No store configured — cannot load docs from storage"))?;
 
-    pub fn routes(self: &Arc<Self>) -> Router {
-        let mut router = Router::new()
-            .route("/ready", get(ready))
-            .route("/check_store", post(check_store))
-            .route("/check_store", get(check_store_deprecated))
-            .route("/doc/ws/:doc_id", get(handle_socket_upgrade_deprecated))
-            .route("/doc/new", post(new_doc))
-            .route("/doc/:doc_id/auth", post(auth_doc))
-            .route("/doc/:doc_id/folder", get(get_doc_folder))
-            .route("/doc/resolve/:prefix", get(resolve_doc))
-            .route("/doc/:doc_id/as-update", get(get_doc_as_update_deprecated))
-            .route("/doc/:doc_id/update", post(update_doc_deprecated))
-            .route("/d/:doc_id/as-update", get(get_doc_as_update))
-            .route("/d/:doc_id/update", post(update_doc))
-            .route("/d/:doc_id/versions", get(handle_doc_versions))
-            .route(
-                "/d/:doc_id/ws/:doc_id2",
-                get(handle_socket_upgrade_full_path),
-            )
-            .route("/webhook/reload", post(reload_webhook_config_endpoint))
-            .route("/search", get(handle_search))
-            .route("/folder/:folder_uuid/name", get(handle_folder_name))
-            .route("/move", post(handle_move_path))
-            .route("/doc/move", post(handle_move_document))
-            .route("/doc/upsert", post(handle_upsert_document))
-            .route("/doc/check", post(handle_check_documents))
-            .route("/doc/check-video-ids", post(handle_check_video_ids))
-            .route("/doc/check-source-urls", post(handle_check_source_urls))
-            .route(
-                "/doc/attachment",
-                post(handle_upsert_attachment).layer(DefaultBodyLimit::max(30 * 1024 * 1024)),
-            )
-            .route("/open/*path", get(handle_open_by_path))
-            .route("/debug/resolve", get(handle_debug_resolve))
-            .route("/suggestions", get(handle_suggestions))
-            .route("/suggestions/apply", post(handle_apply_suggestions));
+        store
+            .list_doc_ids()
+            .await
+            .map_err(|e| anyhow!("Failed to list doc IDs from storage: {:?}", e))
+    }
 
-        // Register /mcp if MCP_API_KEY or SHARE_TOKEN_SECRET is set
-        if self.mcp_api_key.is_some() || self.share_token_secret.is_some() {
-            // Bearer auth: POST/GET/DELETE /mcp (for Claude Code / .mcp.json)
-            let bearer_routes = Router::new()
-                .route(
-                    "/",
-                    post(crate::mcp::transport::handle_mcp_post)
-                        .get(crate::mcp::transport::handle_mcp_get)
-                        .delete(crate::mcp::transport::handle_mcp_delete),
-                )
-                .layer(middleware::from_fn_with_state(
-                    self.clone(),
-                    crate::mcp::transport::mcp_auth_middleware,
-                ));
+    /// Load the given docs from storage unless already in memory, returning
+    /// how many are loaded afterwards.
+    async fn load_docs(&self, doc_ids: &[String]) -> usize {
+        let total = doc_ids.len();
+        tracing::info!("Loading {} documents from storage...", total);
 
-            // Path-key auth: POST/GET/DELETE /mcp/:key (for claude.ai connectors)
-            let path_key_routes = Router::new().route(
-                "/:key",
-                post(crate::mcp::transport::handle_mcp_post_with_key)
-                    .get(crate::mcp::transport::handle_mcp_get_with_key)
-                    .delete(crate::mcp::transport::handle_mcp_delete_with_key),
-            );
+        // Temporarily disable GC during bulk loading — all docs would be
+        // immediately GCed since no clients are connected yet.
+        let gc_was_enabled = self
+            .doc_gc
+            .swap(false, std::sync::atomic::Ordering::Relaxed);
 
-            let mcp_routes = bearer_routes
-                .merge(path_key_routes)
-                .with_state(self.clone());
-            router = router.nest("/mcp", mcp_routes);
-        }
+        let loaded = std::sync::atomic::AtomicUsize::new(0);
+        let failed = std::sync::atomic::AtomicUsize::new(0);
+        let loaded_ref = &loaded;
+        let failed_ref = &failed;
 
-        // Only add file endpoints if a store is configured
-        if let Some(store) = &self.store {
-            // Add presigned URL endpoints for all stores
-            router = router
-                .route("/f/:doc_id/upload-url", post(handle_file_upload_url))
-                .route("/f/:doc_id/download-url", get(handle_file_download_url));
+        // Load docs in parallel — each load_doc is an R2 round-trip (~200ms),
+        // so sequential loading of 300+ docs takes minutes. With concurrency
+        // of 32 it drops to seconds.
+        futures::stream::iter(doc_ids.iter().enumerate())
+            .for_each_concurrent(32, |(_, doc_id)| async move {
+                if self.docs.contains_key(doc_id) {
+                    loaded_ref.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                    return;
+                }
 
-            // Add file operations that work with any store
-            router = router
-                .route("/f/:doc_id/history", get(handle_file_history))
-                .route("/f/:doc_id", delete(handle_file_delete))
-                .route("/f/:doc_id/:hash", delete(handle_file_delete_by_hash))
-                .route("/f/:doc_id", head(handle_file_head));
+                match self.load_doc(doc_id, None).await {
+                    Ok(()) => {
+                        let n = loaded_ref.fetch_add(1, std::sync::atomic::Ordering::Relaxed) + 1;
+                        if n % 50 == 0 || n == total {
+                            tracing::info!("  Loaded {}/{} documents", n, total);
+                        }
+                    }
+                    Err(e) => {
+                        tracing::warn!("  Failed to load doc {}: {:?}", doc_id, e);
+                        failed_ref.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                    }
+                }
+            })
+            .await;
 
-            // Only add direct upload/download endpoints if store supports direct uploads
-            if store.supports_direct_uploads() {
-                let upload_routes = Router::new()
-                    .route(
-                        "/f/:doc_id/upload",
-                        post(handle_file_upload)
-                            .put(handle_file_upload_raw)
-                            .layer(DefaultBodyLimit::max(100 * 1024 * 1024)), // 100MB for file uploads
-                    )
-                    .route("/f/:doc_id/download", get(handle_file_download))
-                    .layer(DefaultBodyLimit::max(250 * 1024 * 1024));
-                router = router.merge(upload_routes);
-            }
-        }
+        let loaded = loaded.load(std::sync::atomic::Ordering::Relaxed);
+        let failed = failed.load(std::sync::atomic::Ordering::Relaxed);
 
-        // Unauthenticated blob read — local dev only (no auth key configured)
-        if self.authenticator.is_none() {
-            if let Some(_store) = &self.store {
-                router = router.route("/blob/:doc_id/:hash", get(handle_blob_read));
-            }
-        }
+        // Restore GC setting
+        self.doc_gc
+            .store(gc_was_enabled, std::sync::atomic::Ordering::Relaxed);
 
-        router
-            .layer(DefaultBodyLimit::max(10 * 1024 * 1024)) // 10MB default
-            .with_state(self.clone())
+        tracing::info!(
+            "Document loading complete: {} loaded, {} failed, {} requested",
+            loaded,
+            failed,
+            total
+        );
+        loaded
     }
 
-    pub fn single_doc_routes(self: &Arc<Self>) -> Router {
-        Router::new()
-            .route("/ws/:doc_id", get(handle_socket_upgrade_single))
-            .route("/as-update", get(get_doc_as_update_single))
-            .route("/update", post(update_doc_single))
-            .with_state(self.clone())
-    }
+    /// Write folder display names from config into folder Y.Docs.
+    ///
+    /// For each configured folder, finds the folder doc whose doc_id ends with
+    /// the configured UUID, reads the current `folder_config.name` from the Y.Doc,
+    /// and writes the configured name if different. Persists changed docs to storage.
+    async fn apply_folder_names(
+        &self,
+        folders: &[y_sweet_core::config::FolderConfig],
+    ) -> Result<()> {
+        if folders.is_empty() {
+            return Ok(());
+        }
 
-    pub fn metrics_routes(self: &Arc<Self>) -> Router {
-        Router::new()
-            .route("/metrics", get(metrics_endpoint))
-            .with_state(self.clone())
-    }
+        let folder_doc_ids = link_indexer::find_all_folder_docs(&self.docs);
+        let mut applied = 0;
 
-    async fn serve_internal(
-        self: Arc<Self>,
-        listener: TcpListener,
-        redact_errors: bool,
-        routes: Router,
-    ) -> Result<()> {
-        let token = self.cancellation_token.clone();
+        for folder_config in folders {
+            // Find the folder doc whose doc_id ends with this UUID
+            let Some(folder_doc_id) = folder_doc_ids.iter().find(|id| {
+                link_indexer::parse_doc_id(id)
+                    .map(|(_, uuid)| uuid == folder_config.uuid)
+                    .unwrap_or(false)
+            }) else {
+                tracing::warn!(
+                    "Folder config for '{}' (uuid={}) — no matching folder doc found",
+                    folder_config.name,
+                    folder_config.uuid
+                );
+                continue;
+            };
 
-        let app = routes.layer(middleware::from_fn(Self::version_header_middleware));
-        let app = if redact_errors {
-            app.layer(middleware::from_fn(Self::redact_error_middleware))
-        } else {
-            app
-        };
+            let (awareness, sync_kv) = {
+                let Some(doc_ref) = self.docs.get(folder_doc_id) else {
+                    continue;
+                };
+                (doc_ref.awareness(), doc_ref.sync_kv()) // Arc clones
+            }; // DashMap shard lock released
 
-        tracing::info!("Starting HTTP server...");
-        axum::serve(listener, app.into_make_service())
-            .with_graceful_shutdown(async move {
-                tracing::info!("Waiting for cancellation token...");
-                token.cancelled().await;
-                tracing::info!("Cancellation token triggered, starting graceful shutdown");
-            })
-            .await?;
+            // Read current name and compare
+            let needs_update = {
+                let guard = awareness.read().unwrap_or_else(|e| e.into_inner());
+                let current_name =
+                    y_sweet_core::doc_resolver::read_folder_name(&guard.doc, folder_doc_id);
+                current_name != folder_config.name
+            };
 
-        tracing::info!("HTTP server stopped, shutting down event dispatcher...");
+            if !needs_update {
+                tracing::debug!(
+                    "Folder '{}' already has correct name, skipping",
+                    folder_config.name
+                );
+                continue;
+            }
 
-        // Explicitly shutdown event dispatcher before waiting on doc workers
-        if let Some(event_dispatcher) = &self.event_dispatcher {
-            tracing::info!("Shutting down event dispatcher...");
-            event_dispatcher.shutdown();
-            tracing::info!("Event dispatcher shutdown complete");
+            // Write the folder name
+            {
+                let guard = awareness.read().unwrap_or_else(|e| e.into_inner());
+                let mut txn = guard.doc.transact_mut();
+                let config_map = txn.get_or_insert_map("folder_config");
+                config_map.insert(
+                    &mut txn,
+                    "name",
+                    yrs::Any::String(folder_config.name.clone().into()),
+                );
+            }
+
+            // Persist to storage
+            sync_kv.persist().await.map_err(|e| {
+                anyhow!(
+                    "Failed to persist folder name for '{}': {:?}",
+                    folder_config.name,
+                    e
+                )
+            })?;
+
+            tracing::info!(
+                "Applied folder name '{}' to doc {}",
+                folder_config.name,
+                folder_doc_id
+            );
+            applied += 1;
         }
 
-        tracing::info!("Closing doc worker tracker...");
-        self.doc_worker_tracker.close();
-        tracing::info!("Waiting for doc workers to finish...");
-        self.doc_worker_tracker.wait().await;
-        tracing::info!("All doc workers stopped");
+        if applied > 0 {
+            tracing::info!("Applied {} folder name(s) from config", applied);
+        }
 
         Ok(())
     }
 
-    pub async fn serve(self, listener: TcpListener, redact_errors: bool) -> Result<()> {
-        let s = Arc::new(self);
-        let routes = s.routes_with_metrics();
-        s.serve_internal(listener, redact_errors, routes).await
-    }
-
-    pub async fn serve_doc(self, listener: TcpListener, redact_errors: bool) -> Result<()> {
-        let s = Arc::new(self);
-        let routes = s.single_doc_routes_with_metrics();
-        s.serve_internal(listener, redact_errors, routes).await
+    /// Scan every loaded content doc for CriticMarkup and (re)build the
+    /// suggestions index, then mark it ready. Called from `startup_reindex`
+    /// while all docs are in memory; incremental updates afterwards come from
+    /// the search worker.
+    pub(crate) fn rebuild_suggestions_index(&self) {
+        // Snapshot keys first: iterating the DashMap while acquiring awareness
+        // locks would violate the lock ordering (shard < awareness), see the
+        // search_worker comment.
+        let doc_ids: Vec<String> = self.docs.iter().map(|e| e.key().clone()).collect();
+        let mut indexed = 0;
+        for doc_id in &doc_ids {
+            let Some((_relay_id, doc_uuid)) = link_indexer::parse_doc_id(doc_id) else {
+                continue;
+            };
+            let awareness = {
+                let Some(doc_ref) = self.docs.get(doc_id) else {
+                    continue;
+                };
+                doc_ref.awareness() // Arc clone
+            }; // DashMap shard lock released
+            let content = {
+                let guard = awareness.read().unwrap_or_else(|e| e.into_inner());
+                let txn = guard.doc.transact();
+                match txn.get_text("contents") {
+                    Some(text) => text.get_string(&txn),
+                    // Folder docs and blobs have no "contents" text
+                    None => continue,
+                }
+            };
+            let suggestions = critic_scanner::scan_suggestions(&content);
+            if !suggestions.is_empty() {
+                indexed += 1;
+            }
+            self.suggestions_index.update(doc_uuid, suggestions);
+        }
+        self.suggestions_ready
+            .store(true, std::sync::atomic::Ordering::Release);
+        tracing::info!(
+            "Suggestions index built: {} of {} docs have suggestions",
+            indexed,
+            doc_ids.len()
+        );
     }
 
-    pub async fn serve_metrics(self, listener: TcpListener) -> Result<()> {
-        let s = Arc::new(self);
-        let routes = s.metrics_routes();
-        s.serve_internal(listener, false, routes).await
+    /// Last-modified time (ms since epoch) of the given docs' stored
+    /// snapshots, keyed by doc id, used to skip unchanged docs at startup
+    /// and to seed the search index's "recently modified" sort. Docs the
+    /// store cannot list are left out.
+    async fn stored_modified_times(
+        &self,
+        doc_ids: Vec<String>,
+    ) -> std::collections::HashMap<String, u64> {
+        let Some(store) = self.store.as_ref() else {
+            return std::collections::HashMap::new();
+        };
+        futures::stream::iter(doc_ids)
+            .map(|doc_id| async move {
+                let files = store.list(&format!("{}/", doc_id)).await.ok()?;
+                let modified = files
+                    .iter()
+                    .filter(|file| file.key.ends_with("data.ysweet"))
+                    .map(|file| file.last_modified)
+                    .max()?;
+                Some((doc_id, modified))
+            })
+            .buffer_unordered(32)
+            .filter_map(|entry| async move { entry })
+            .collect()
+            .await
     }
 
-    async fn ensure_socket_doc_access(
+    /// uuid -> (title, location) of every filemeta entry in the given loaded
+    /// folder docs. On duplicate uuids the last folder wins.
+    fn search_entries_in_folders(
         &self,
-        doc_id: &str,
-        authorization: Authorization,
-    ) -> Result<(), AppError> {
-        if !matches!(authorization, Authorization::Full) && !self.doc_exists(doc_id).await {
-            return Err(AppError::new(
-                StatusCode::NOT_FOUND,
-                anyhow!("Doc {} not found", doc_id),
-            ));
-        }
+        folder_doc_ids: &[String],
+    ) -> std::collections::HashMap<String, (String, DocLocation)> {
+        let mut entries = std::collections::HashMap::new();
+        for folder_doc_id in folder_doc_ids {
+            let awareness = {
+                let Some(doc_ref) = self.docs.get(folder_doc_id) else {
+                    continue;
+                };
+                doc_ref.awareness() // Arc clone
+            }; // DashMap shard lock released
+            let guard = awareness.read().unwrap_or_else(|e| e.into_inner());
+            let folder_name =
+                y_sweet_core::doc_resolver::read_folder_name(&guard.doc, folder_doc_id);
+            let txn = guard.doc.transact();
+            let Some(filemeta) = txn.get_map("filemeta_v0") else {
+                continue;
+            };
 
-        Ok(())
+            for (path, value) in filemeta.iter(&txn) {
+                if let Some(uuid) = link_indexer::extract_id_from_filemeta_entry(&value, &txn) {
+                    // Extract title: strip leading "/" and trailing ".md", take basename
+                    let title = path
+                        .strip_prefix('/')
+                        .unwrap_or(&path)
+                        .strip_suffix(".md")
+                        .unwrap_or(&path)
+                        .rsplit('/')
+                        .next()
+                        .unwrap_or(&path)
+                        .to_string();
+                    let location = search_doc_location(folder_doc_id, &folder_name, &path);
+                    entries.insert(uuid, (title, location));
+                }
+            }
+        }
+        entries
     }
 
-    fn verify_doc_token(&self, token: Option<&str>, doc: &str) -> Result<Authorization, AppError> {
-        if let Some(authenticator) = &self.authenticator {
-            if let Some(token) = token {
-                let authorization = authenticator
-                    .verify_doc_token(token, doc, current_time_epoch_millis())
-                    .map_err(|e| {
-                        AppError::auth(StatusCode::UNAUTHORIZED, e.into(), "invalid_doc_token")
-                    })?;
-                Ok(authorization)
+    /// Bring the search entries for `entries` up to date, buffered (call
+    /// `flush()` afterwards). A doc whose stored snapshot is older than its
+    /// search entry is skipped without being loaded; the others are loaded
+    /// and reindexed unless their content hash matches. An edit that was
+    /// indexed but lost before its checkpoint (a crash) therefore keeps its
+    /// entry until the doc changes again. `doc_ids` maps content uuids to
+    /// doc ids in the store. Returns (reindexed, skipped).
+    async fn reindex_search_entries(
+        &self,
+        search_index: &SearchIndex,
+        entries: &std::collections::HashMap<String, (String, DocLocation)>,
+        doc_ids: &std::collections::HashMap<&str, &str>,
+    ) -> (usize, usize) {
+        let modified_times = self
+            .stored_modified_times(
+                entries
+                    .keys()
+                    .filter_map(|uuid| doc_ids.get(uuid.as_str()).map(|id| id.to_string()))
+                    .collect(),
+            )
+            .await;
+
+        let mut skipped = 0;
+        let mut to_check = Vec::new();
+        for (uuid, (title, location)) in entries {
+            let doc_id = doc_ids.get(uuid.as_str()).copied();
+            let modified = doc_id.and_then(|doc_id| modified_times.get(doc_id).copied());
+            let unchanged = match modified {
+                Some(modified) => search_index
+                    .unchanged_since(uuid, title, location, modified)
+                    .unwrap_or_else(|e| {
+                        tracing::warn!("Failed to check search entry for {}: {:?}", uuid, e);
+                        false
+                    }),
+                None => false,
+            };
+            if unchanged {
+                skipped += 1;
             } else {
-                Err(AppError::auth(
-                    StatusCode::UNAUTHORIZED,
-                    anyhow!("No token provided."),
-                    "missing_token",
-                ))
+                to_check.push((uuid, doc_id, modified, title, location));
             }
-        } else {
-            Ok(Authorization::Full)
         }
-    }
 
-    fn get_single_doc_id(&self) -> Result<String, AppError> {
-        self.docs
+        let to_load: Vec<String> = to_check
             .iter()
-            .next()
-            .map(|entry| entry.key().clone())
-            .ok_or_else(|| AppError::new(StatusCode::NOT_FOUND, anyhow!("No document found")))
-    }
-}
+            .filter_map(|(_, doc_id, ..)| *doc_id)
+            .filter(|doc_id| !self.docs.contains_key(*doc_id))
+            .map(str::to_string)
+            .collect();
+        if !to_load.is_empty() {
+            self.load_docs(&to_load).await;
+        }
 
-#[derive(Deserialize)]
-struct HandlerParams {
-    token: Option<String>,
-}
-
-async fn get_doc_as_update(
-    State(server_state): State<Arc<Server>>,
-    Path(doc_id): Path<String>,
-    auth_header: Option<TypedHeader<headers::Authorization<headers::authorization::Bearer>>>,
-) -> Result<Response, AppError> {
-    // All authorization types allow reading the document.
-    let token = get_token_from_header(auth_header);
-    let _ = server_state.verify_doc_token(token.as_deref(), &doc_id)?;
+        let mut indexed = 0;
+        for (uuid, doc_id, modified, title, location) in to_check {
+            let awareness = doc_id.and_then(|doc_id| self.docs.get(doc_id).map(|d| d.awareness()));
+            let body = match awareness {
+                Some(awareness) => {
+                    let guard = awareness.read().unwrap_or_else(|e| e.into_inner());
+                    let txn = guard.doc.transact();
+                    txn.get_text("contents")
+                        .map(|text| text.get_string(&txn))
+                        .unwrap_or_default()
+                }
+                None => String::new(),
+            };
 
-    let dwskv = server_state
-        .get_or_create_doc(&doc_id)
-        .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+            match search_index.skip_if_unchanged(uuid, title, &body, location) {
+                Ok(true) => {
+                    skipped += 1;
+                    // Only the snapshot was rewritten since the entry (a
+                    // checkpoint after the last index update): record that,
+                    // so the next start skips the doc without loading it
+                    if modified.is_some() {
+                        if let Err(e) = search_index.refresh_buffered(uuid, title, &body, location)
+                        {
+                            tracing::warn!("Failed to refresh search entry for {}: {:?}", uuid, e);
+                        }
+                    }
+                    continue;
+                }
+                Ok(false) => {}
+                Err(e) => {
+                    tracing::warn!("Failed to check search entry for {}: {:?}", uuid, e);
+                }
+            }
+            match search_index.add_document_buffered_at(uuid, title, &body, location, modified) {
+                Ok(()) => indexed += 1,
+                Err(e) => {
+                    tracing::error!("Failed to index doc {} into search: {:?}", uuid, e);
+                }
+            }
+        }
+        (indexed, skipped)
+    }
 
-    let update = dwskv.as_update();
-    tracing::debug!("update: {:?}", update);
-    Ok(update.into_response())
-}
+    /// Load documents from storage and rebuild the derived indexes.
+    ///
+    /// Search goes first and loads only what it needs: the folder docs
+    /// (configured ones and those the persisted index refers to) and the
+    /// content docs whose stored snapshot is newer than their search entry.
+    /// Search is marked ready after that pass; then every doc is loaded to
+    /// rebuild backlinks, the document resolver and suggestions, and
+    /// folders the search pass didn't know are indexed.
+    ///
+    /// Called once on startup, before accepting connections.
+    /// No-op if no store is configured (in-memory mode).
+    pub async fn startup_reindex(
+        &self,
+        folders: &[y_sweet_core::config::FolderConfig],
+    ) -> Result<()> {
+        if self.store.is_none() {
+            tracing::info!("No store configured, skipping startup reindex");
+            // Even without a store, mark search as ready (empty index)
+            self.search_ready
+                .store(true, std::sync::atomic::Ordering::Release);
+            self.suggestions_ready
+                .store(true, std::sync::atomic::Ordering::Release);
+            return Ok(());
+        }
 
-async fn get_doc_as_update_deprecated(
-    Path(doc_id): Path<String>,
-    State(server_state): State<Arc<Server>>,
-    auth_header: Option<TypedHeader<headers::Authorization<headers::authorization::Bearer>>>,
-) -> Result<Response, AppError> {
-    tracing::warn!("/doc/:doc_id/as-update is deprecated; call /doc/:doc_id/auth instead and then call as-update on the returned base URL.");
-    get_doc_as_update(State(server_state), Path(doc_id), auth_header).await
-}
+        let doc_ids = self.list_stored_doc_ids().await?;
+        // Content uuid -> doc id. A uuid stored under several relay ids
+        // resolves to the first one listed.
+        let mut uuid_doc_ids = std::collections::HashMap::new();
+        for doc_id in &doc_ids {
+            if let Some((_relay_id, uuid)) = link_indexer::parse_doc_id(doc_id) {
+                uuid_doc_ids.entry(uuid).or_insert(doc_id.as_str());
+            }
+        }
 
-async fn update_doc_deprecated(
-    Path(doc_id): Path<String>,
-    State(server_state): State<Arc<Server>>,
-    auth_header: Option<TypedHeader<headers::Authorization<headers::authorization::Bearer>>>,
-    body: Bytes,
-) -> Result<Response, AppError> {
-    tracing::warn!("/doc/:doc_id/update is deprecated; call /doc/:doc_id/auth instead and then call update on the returned base URL.");
-    update_doc(Path(doc_id), State(server_state), auth_header, body).await
-}
+        // Folder docs first: folder names, and the search pass, need them
+        let mut folder_uuids: std::collections::HashSet<String> =
+            folders.iter().map(|folder| folder.uuid.clone()).collect();
+        if let Some(ref search_index) = self.search_index {
+            match search_index.indexed_folder_ids() {
+                Ok(ids) => folder_uuids.extend(ids),
+                Err(e) => tracing::error!("Failed to read persisted search index: {:?}", e),
+            }
+        }
+        let folder_candidates: Vec<String> = doc_ids
+            .iter()
+            .filter(|doc_id| {
+                link_indexer::parse_doc_id(doc_id)
+                    .map(|(_, uuid)| folder_uuids.contains(uuid))
+                    .unwrap_or(false)
+            })
+            .cloned()
+            .collect();
+        self.load_docs(&folder_candidates).await;
 
-async fn get_doc_as_update_single(
-    State(server_state): State<Arc<Server>>,
-    auth_header: Option<TypedHeader<headers::Authorization<headers::authorization::Bearer>>>,
-) -> Result<Response, AppError> {
-    let doc_id = server_state.get_single_doc_id()?;
-    get_doc_as_update(State(server_state), Path(doc_id), auth_header).await
-}
+        // Apply folder names from config before indexing
+        self.apply_folder_names(folders).await?;
+        let searched_folders = link_indexer::find_all_folder_docs(&self.docs);
 
-async fn update_doc(
-    Path(doc_id): Path<String>,
-    State(server_state): State<Arc<Server>>,
-    auth_header: Option<TypedHeader<headers::Authorization<headers::authorization::Bearer>>>,
-    body: Bytes,
-) -> Result<Response, AppError> {
-    let token = get_token_from_header(auth_header);
-    let authorization = server_state.verify_doc_token(token.as_deref(), &doc_id)?;
-    update_doc_inner(doc_id, server_state, authorization, body).await
-}
+        let mut reindexed = 0;
+        let mut skipped = 0;
+        if let Some(ref search_index) = self.search_index {
+            tracing::info!("Updating persisted search index...");
+            let previously_indexed = search_index.indexed_doc_ids().unwrap_or_else(|e| {
+                tracing::error!("Failed to read persisted search index: {:?}", e);
+                std::collections::HashSet::new()
+            });
+            let entries = self.search_entries_in_folders(&searched_folders);
+            tracing::info!(
+                "Found {} documents in {} folder doc(s) for search indexing",
+                entries.len(),
+                searched_folders.len()
+            );
+            (reindexed, skipped) = self
+                .reindex_search_entries(search_index, &entries, &uuid_doc_ids)
+                .await;
 
-async fn update_doc_inner(
-    doc_id: String,
-    server_state: Arc<Server>,
-    authorization: Authorization,
-    body: Bytes,
-) -> Result<Response, AppError> {
-    if !matches!(authorization, Authorization::Full) {
-        return Err(AppError::auth(
-            StatusCode::FORBIDDEN,
-            anyhow!("Unauthorized."),
-            "insufficient_permissions",
-        ));
-    }
+            if let Err(e) = search_index.flush() {
+                tracing::error!("Failed to flush search index: {}", e);
+            }
 
-    let dwskv = server_state
-        .get_or_create_doc(&doc_id)
-        .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+            // Docs deleted while the relay was down. A doc moved into a
+            // folder this pass didn't load comes back below.
+            let mut removed = 0;
+            for uuid in previously_indexed
+                .iter()
+                .filter(|uuid| !entries.contains_key(*uuid))
+            {
+                match search_index.remove_document(uuid) {
+                    Ok(()) => removed += 1,
+                    Err(e) => tracing::error!("Failed to remove {} from search: {:?}", uuid, e),
+                }
+            }
 
-    if let Err(err) = dwskv.apply_update(&body) {
-        tracing::error!(?err, "Failed to apply update");
-        return Err(AppError::new(StatusCode::INTERNAL_SERVER_ERROR, err));
-    }
+            self.metrics.set_search_startup_docs("skipped", skipped);
+            self.metrics.set_search_startup_docs("reindexed", reindexed);
+            self.metrics.set_search_startup_docs("removed", removed);
+            tracing::info!(
+                "Search index updated: {} documents indexed, {} unchanged, {} removed",
+                reindexed,
+                skipped,
+                removed
+            );
+        }
 
-    Ok(StatusCode::OK.into_response())
-}
+        // The persisted index has been checked against the store
+        self.search_ready
+            .store(true, std::sync::atomic::Ordering::Release);
+        tracing::info!("Search index is now ready for queries");
 
-async fn update_doc_single(
-    State(server_state): State<Arc<Server>>,
-    auth_header: Option<TypedHeader<headers::Authorization<headers::authorization::Bearer>>>,
-    body: Bytes,
-) -> Result<Response, AppError> {
-    let doc_id = server_state.get_single_doc_id()?;
-    let token = get_token_from_header(auth_header);
-    let authorization = server_state.verify_doc_token(token.as_deref(), &doc_id)?;
-    update_doc_inner(doc_id, server_state, authorization, body).await
-}
+        let loaded = self.load_docs(&doc_ids).await;
+        tracing::info!("Loaded {} documents, now reindexing backlinks...", loaded);
 
-async fn handle_socket_upgrade(
-    ws: WebSocketUpgrade,
-    Path(doc_id): Path<String>,
-    authorization: Authorization,
-    State(server_state): State<Arc<Server>>,
-) -> Result<Response, AppError> {
-    handle_socket_upgrade_with_channel(ws, Path(doc_id), authorization, None, State(server_state))
-        .await
-}
+        if let Some(ref indexer) = self.link_indexer {
+            indexer.reindex_all_backlinks(&self.docs)?;
+        }
 
-async fn handle_socket_upgrade_with_channel(
-    ws: WebSocketUpgrade,
-    Path(doc_id): Path<String>,
-    authorization: Authorization,
-    routing_channel: Option<String>,
-    State(server_state): State<Arc<Server>>,
-) -> Result<Response, AppError> {
-    handle_socket_upgrade_with_channel_and_user(
-        ws,
-        Path(doc_id),
-        authorization,
-        routing_channel,
-        None,
-        None, // No token available at this level
-        State(server_state),
-    )
-    .await
-}
+        // Build document resolver (bidirectional path <-> UUID mapping)
+        self.doc_resolver.rebuild(&self.docs);
+        tracing::info!(
+            "Document resolver built: {} documents",
+            self.doc_resolver.all_paths().len()
+        );
 
-async fn handle_socket_upgrade_with_channel_and_user(
-    ws: WebSocketUpgrade,
-    Path(doc_id): Path<String>,
-    authorization: Authorization,
-    routing_channel: Option<String>,
-    user: Option<String>,
-    token: Option<String>,
-    State(server_state): State<Arc<Server>>,
-) -> Result<Response, AppError> {
-    server_state
-        .ensure_socket_doc_access(&doc_id, authorization)
-        .await?;
+        // Build the suggestions index while everything is in memory
+        self.rebuild_suggestions_index();
 
-    // Extract expiration time from token
-    let expiration_time = if let Some(authenticator) = &server_state.authenticator {
-        if let Some(token_str) = token.as_deref() {
-            authenticator
-                .decode_token(token_str)
-                .ok()
-                .and_then(|payload| payload.expiration_millis)
-                .map(|exp| exp.0)
-        } else {
-            None
+        // Folders neither configured nor in the persisted index, e.g. shared
+        // while the relay was down
+        if let Some(ref search_index) = self.search_index {
+            let new_folders: Vec<String> = link_indexer::find_all_folder_docs(&self.docs)
+                .into_iter()
+                .filter(|folder_doc_id| !searched_folders.contains(folder_doc_id))
+                .collect();
+            if !new_folders.is_empty() {
+                let entries = self.search_entries_in_folders(&new_folders);
+                let (new_reindexed, new_skipped) = self
+                    .reindex_search_entries(search_index, &entries, &uuid_doc_ids)
+                    .await;
+                if let Err(e) = search_index.flush() {
+                    tracing::error!("Failed to flush search index: {}", e);
+                }
+                self.metrics
+                    .set_search_startup_docs("skipped", skipped + new_skipped);
+                self.metrics
+                    .set_search_startup_docs("reindexed", reindexed + new_reindexed);
+                tracing::info!(
+                    "Search index: {} documents indexed, {} unchanged in {} new folder doc(s)",
+                    new_reindexed,
+                    new_skipped,
+                    new_folders.len()
+                );
+            }
         }
-    } else {
-        None
-    };
-
-    let user_for_pud = user.clone();
-    let dwskv = server_state
-        .get_or_create_doc_with_channel_and_user(&doc_id, routing_channel, user)
-        .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
-    let awareness = dwskv.awareness();
-    let sync_kv = dwskv.sync_kv();
-    let cancellation_token = server_state.cancellation_token.clone();
-    let sync_protocol_event_sender = server_state.sync_protocol_event_sender.clone();
-    let metrics = server_state.metrics.clone();
-    let doc_id_clone = doc_id.clone();
 
-    Ok(ws.on_upgrade(move |socket| {
-        handle_socket(
-            socket,
-            awareness,
-            sync_kv,
-            authorization,
-            expiration_time,
-            user_for_pud,
-            cancellation_token,
-            sync_protocol_event_sender,
-            doc_id_clone,
-            metrics,
-        )
-    }))
-}
+        Ok(())
+    }
 
-fn verify_socket_token(
-    server_state: &Arc<Server>,
+    /// GC step for a single doc: remove it from the docs map, then compact
+    /// its PermanentUserData and shut down its persistence. Removal is
+    /// declined — the doc stays fully alive — when its awareness gained a
+    /// holder (derived-index lease, MCP handler, new connection) after the
+    /// GC worker's last checkpoint sample. Returns true when the doc was
+    /// removed (or was already gone).
+    ///
+    /// Removing first means compaction only ever runs on a doomed doc no
+    /// new holder can reach, and the extracted value keeps the update
+    /// subscription and SyncKv alive so the compacted state still persists
+    /// (the persistence worker holds its own SyncKv Arc). Shutdown comes
+    /// last: mark_dirty is a no-op post-shutdown, so shutting down earlier
+    /// could silently drop the compaction from persistence.
+    ///
+    /// Blocking — compaction takes the awareness write lock and can run
+    /// long on bloated PUD; call via spawn_blocking from async context. The
+    /// remove_if predicate is a single atomic load; no shard guard is ever
+    /// held across a blocking lock (2026-07-31/08-02 prod hangs;
+    /// regression test: search_deadlock.rs Test 7).
+    pub fn gc_compact_and_remove(docs: &DashMap<String, DocWithSyncKv>, doc_id: &str) -> bool {
+        // The checkpoints merely sample refs, so a final edit (e.g. via
+        // MCP) can slip in after the last sample and queue a leased index
+        // entry; the predicate re-checks at commit time. (GC is the only
+        // path that removes docs, so the entry can't have been swapped
+        // since the samples — if other removers appear, compare identity
+        // first so a reinserted doc isn't dropped uncompacted.)
+        let Some((_, doc)) = docs.remove_if(doc_id, |_, doc| !doc.has_external_refs()) else {
+            // None: either declined (doc re-referenced — leave it alive) or
+            // already gone.
+            return !docs.contains_key(doc_id);
+        };
+
+        // Compact PUD: dedup ids, clear ds. The mutations create tombstones
+        // which yrs GC will clean up, and the update observer (owned by the
+        // extracted value) marks SyncKv dirty so the compacted state gets
+        // persisted.
+        let started = std::time::Instant::now();
+        let result = y_sweet_core::permanent_user_data::compact_user_data_locked(&doc.awareness());
+        if !result.is_empty() {
+            tracing::info!(
+                ids_removed = result.ids_removed,
+                ds_removed = result.ds_removed,
+                elapsed_ms = started.elapsed().as_millis() as u64,
+                "Compacted PermanentUserData"
+            );
+        }
+        doc.sync_kv().shutdown();
+        true
+    }
+
+    async fn doc_gc_worker(
+        docs: Arc<DashMap<String, DocWithSyncKv>>,
+        doc_id: String,
+        checkpoint_freq: Duration,
+        cancellation_token: CancellationToken,
+    ) {
+        let mut checkpoints_without_refs = 0;
+
+        loop {
+            tokio::select! {
+                _ = tokio::time::sleep(checkpoint_freq) => {
+                    if let Some(doc) = docs.get(&doc_id) {
+                        if doc.has_external_refs() {
+                            checkpoints_without_refs = 0;
+                            tracing::debug!("doc is still alive - it has external references");
+                        } else {
+                            checkpoints_without_refs += 1;
+                            tracing::info!("doc has only one reference, candidate for GC. checkpoints_without_refs: {}", checkpoints_without_refs);
+                        }
+                    } else {
+                        break;
+                    }
+
+                    if checkpoints_without_refs >= 2 {
+                        tracing::info!("GCing doc");
+                        // Off the async runtime: the GC step takes blocking
+                        // std locks and compaction can run long, which would
+                        // otherwise pin one of the few worker threads.
+                        let docs = Arc::clone(&docs);
+                        let doc_id = doc_id.clone();
+                        match tokio::task::spawn_blocking(move || {
+                            Self::gc_compact_and_remove(&docs, &doc_id)
+                        })
+                        .await
+                        {
+                            Ok(true) => break,
+                            Ok(false) => {
+                                // A holder appeared after the checkpoint
+                                // samples (index lease, MCP handler, new
+                                // connection). Start the count over.
+                                tracing::info!("GC deferred: doc re-referenced before removal");
+                                checkpoints_without_refs = 0;
+                            }
+                            Err(e) => {
+                                tracing::error!(?e, "GC compact task panicked");
+                                break;
+                            }
+                        }
+                    }
+                }
+                _ = cancellation_token.cancelled() => {
+                    break;
+                }
+            };
+        }
+        tracing::info!("Exiting gc_loop");
+    }
+
+    async fn doc_persistence_worker(
+        mut recv: Receiver<()>,
+        sync_kv: Arc<SyncKv>,
+        checkpoint_freq: Duration,
+        doc_id: String,
+        cancellation_token: CancellationToken,
+        last_successful_persist: Arc<AtomicU64>,
+        regression_watch: RegressionWatch,
+    ) {
+        let mut last_save = std::time::Instant::now();
+        let mut consecutive_failures: u32 = 0;
+        // Baseline for the first checkpoint.
+        regression_watch.check(&doc_id).await;
+
+        loop {
+            let is_done = tokio::select! {
+                v = recv.recv() => v.is_none(),
+                _ = cancellation_token.cancelled() => true,
+                _ = tokio::time::sleep(checkpoint_freq) => {
+                    sync_kv.is_shutdown()
+                }
+            };
+
+            tracing::debug!("Received signal. done: {}", is_done);
+            let now = std::time::Instant::now();
+            if !is_done && now - last_save < checkpoint_freq {
+                let sleep = tokio::time::sleep(checkpoint_freq - (now - last_save));
+                tokio::pin!(sleep);
+                tracing::info!("Throttling.");
+
+                loop {
+                    tokio::select! {
+                        _ = &mut sleep => {
+                            break;
+                        }
+                        v = recv.recv() => {
+                            tracing::info!("Received dirty while throttling.");
+                            if v.is_none() {
+                                break;
+                            }
+                        }
+                        _ = cancellation_token.cancelled() => {
+                            tracing::info!("Received cancellation while throttling.");
+                            break;
+                        }
+
+                    }
+                    tracing::info!("Done throttling.");
+                }
+            }
+            tracing::debug!("Persisting.");
+            regression_watch.check(&doc_id).await;
+            if let Err(e) = sync_kv.persist().await {
+                consecutive_failures += 1;
+                if consecutive_failures >= 10 {
+                    tracing::error!(
+                        ?e,
+                        consecutive_failures,
+                        "Persist failing repeatedly for {}",
+                        doc_id
+                    );
+                } else {
+                    tracing::error!(?e, "Error persisting.");
+                }
+            } else {
+                if consecutive_failures > 0 {
+                    tracing::info!(
+                        "Persist succeeded after {} consecutive failures",
+                        consecutive_failures
+                    );
+                }
+                consecutive_failures = 0;
+                let now_ms = std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_millis() as u64;
+                last_successful_persist.store(now_ms, AtomicOrdering::Relaxed);
+                tracing::debug!("Done persisting.");
+            }
+            last_save = std::time::Instant::now();
+
+            if is_done {
+                break;
+            }
+        }
+        tracing::info!("Terminating loop for {}", doc_id);
+    }
+
+    pub async fn get_or_create_doc(
+        &self,
+        doc_id: &str,
+    ) -> Result<MappedRef<'_, String, DocWithSyncKv, DocWithSyncKv>> {
+        if !self.docs.contains_key(doc_id) {
+            tracing::info!(doc_id=?doc_id, "Loading doc");
+            self.load_doc(doc_id, None).await?;
+        }
+
+        Ok(self
+            .docs
+            .get(doc_id)
+            .ok_or_else(|| anyhow!("Failed to get-or-create doc"))?
+            .map(|d| d))
+    }
+
+    pub async fn get_or_create_doc_with_channel(
+        &self,
+        doc_id: &str,
+        routing_channel: Option<String>,
+    ) -> Result<MappedRef<'_, String, DocWithSyncKv, DocWithSyncKv>> {
+        self.get_or_create_doc_with_channel_and_user(doc_id, routing_channel, None)
+            .await
+    }
+
+    pub async fn get_or_create_doc_with_channel_and_user(
+        &self,
+        doc_id: &str,
+        routing_channel: Option<String>,
+        user: Option<String>,
+    ) -> Result<MappedRef<'_, String, DocWithSyncKv, DocWithSyncKv>> {
+        if !self.docs.contains_key(doc_id) {
+            tracing::info!(doc_id=?doc_id, channel=?routing_channel, user=?user, "Loading doc with channel and user");
+            self.load_doc_with_user(doc_id, routing_channel, user)
+                .await?;
+        }
+
+        Ok(self
+            .docs
+            .get(doc_id)
+            .ok_or_else(|| anyhow!("Failed to get-or-create doc"))?
+            .map(|d| d))
+    }
+
+    pub fn check_auth(
+        &self,
+        auth_header: Option<TypedHeader<headers::Authorization<headers::authorization::Bearer>>>,
+    ) -> Result<(), AppError> {
+        if let Some(auth) = &self.authenticator {
+            if let Some(TypedHeader(headers::Authorization(bearer))) = auth_header {
+                if let Ok(()) =
+                    auth.verify_server_token(bearer.token(), current_time_epoch_millis())
+                {
+                    return Ok(());
+                }
+                return Err(AppError::auth(
+                    StatusCode::UNAUTHORIZED,
+                    anyhow!("Unauthorized."),
+                    "invalid_server_token",
+                ));
+            }
+            Err(AppError::auth(
+                StatusCode::UNAUTHORIZED,
+                anyhow!("Unauthorized."),
+                "missing_token",
+            ))
+        } else {
+            Ok(())
+        }
+    }
+
+    pub async fn redact_error_middleware(req: Request, next: Next) -> impl IntoResponse {
+        let resp = next.run(req).await;
+        if resp.status().is_server_error() || resp.status().is_client_error() {
+            // If we should redact errors, copy over only the status code and
+            // not the response body.
+            return resp.status().into_response();
+        }
+        resp
+    }
+
+    pub async fn version_header_middleware(req: Request, next: Next) -> impl IntoResponse {
+        let mut resp = next.run(req).await;
+        resp.headers_mut().insert(
+            HeaderName::from_static("relay-server-version"),
+            HeaderValue::from_static(RELAY_SERVER_VERSION),
+        );
+        resp
+    }
+
+    pub fn routes_with_metrics(self: &Arc<Self>) -> Router {
+        self.routes().layer(middleware::from_fn_with_state(
+            self.clone(),
+            auth_metrics_middleware,
+        ))
+    }
+
+    pub fn single_doc_routes_with_metrics(self: &Arc<Self>) -> Router {
+        self.single_doc_routes()
+            .layer(middleware::from_fn_with_state(
+                self.clone(),
+                auth_metrics_middleware,
+            ))
+    }
+
+    pub fn routes(self: &Arc<Self>) -> Router {
+        let mut router = Router::new()
+            .route("/ready", get(ready))
+            .route("/check_store", post(check_store))
+            .route("/check_store", get(check_store_deprecated))
+            .route("/doc/ws/:doc_id", get(handle_socket_upgrade_deprecated))
+            .route("/doc/new", post(new_doc))
+            .route("/doc/:doc_id/auth", post(auth_doc))
+            .route("/doc/:doc_id/folder", get(get_doc_folder))
+            .route("/doc/resolve/:prefix", get(resolve_doc))
+            .route("/doc/:doc_id/as-update", get(get_doc_as_update_deprecated))
+            .route("/doc/:doc_id/update", post(update_doc_deprecated))
+            .route("/d/:doc_id/as-update", get(get_doc_as_update))
+            .route("/d/:doc_id/update", post(update_doc))
+            .route("/d/:doc_id/versions", get(handle_doc_versions))
+            .route(
+                "/d/:doc_id/versions/:version_id",
+                get(handle_doc_version_text),
+            )
+            .route(
+                "/d/:doc_id/versions/:version_id/restore",
+                post(handle_restore_doc_version),
+            )
+            .route("/d/:doc_id/diff", get(handle_doc_diff))
+            .route(
+                "/d/:doc_id/ws/:doc_id2",
+                get(handle_socket_upgrade_full_path),
+            )
+            .route("/webhook/reload", post(reload_webhook_config_endpoint))
+            .route("/search", get(handle_search))
+            .route("/folder/:folder_uuid/name", get(handle_folder_name))
+            .route(
+                "/folder/:folder_uuid/snapshots",
+                get(handle_list_folder_snapshots).post(handle_create_folder_snapshot),
+            )
+            .route(
+                "/folder/:folder_uuid/snapshots/:name/restore",
+                post(handle_restore_folder_snapshot),
+            )
+            .route("/folder/:folder_uuid/trash", get(handle_list_trash))
+            .route(
+                "/folder/:folder_uuid/trash/:id/restore",
+                post(handle_restore_trash),
+            )
+            .route("/folder/:folder_uuid/export", get(handle_export_folder))
+            .route("/fsck", get(handle_fsck))
+            .route("/fsck/repair", post(handle_fsck_repair))
+            .route("/dump", get(handle_dump_doc))
+            .route("/import", post(handle_import_vault))
+            .route("/acl", get(handle_get_acl).put(handle_put_acl))
+            .route(
+                "/share-tokens/revocations",
+                get(handle_get_share_revocations).put(handle_put_share_revocations),
+            )
+            .route("/share-tokens/revoke", post(handle_revoke_share_token))
+            .route("/regressions", get(handle_list_regressions))
+            .route(
+                "/regressions/:event_id/restore",
+                post(handle_restore_regression),
+            )
+            .route("/move", post(handle_move_path))
+            .route("/doc/move", post(handle_move_document))
+            .route("/doc", delete(handle_delete_path))
+            .route("/doc/upsert", post(handle_upsert_document))
+            .route("/doc/check", post(handle_check_documents))
+            .route("/doc/check-video-ids", post(handle_check_video_ids))
+            .route("/doc/check-source-urls", post(handle_check_source_urls))
+            .route(
+                "/doc/attachment",
+                post(handle_upsert_attachment).layer(DefaultBodyLimit::max(30 * 1024 * 1024)),
+            )
+            .route("/open/*path", get(handle_open_by_path))
+            .route("/debug/resolve", get(handle_debug_resolve))
+            .route("/suggestions", get(handle_suggestions))
+            .route("/suggestions/apply", post(handle_apply_suggestions));
+
+        // Register /mcp if MCP_API_KEY or SHARE_TOKEN_SECRET is set
+        if self.mcp_api_key.is_some() || self.share_token_secret.is_some() {
+            // Bearer auth: POST/GET/DELETE /mcp (for Claude Code / .mcp.json)
+            let bearer_routes = Router::new()
+                .route(
+                    "/",
+                    post(crate::mcp::transport::handle_mcp_post)
+                        .get(crate::mcp::transport::handle_mcp_get)
+                        .delete(crate::mcp::transport::handle_mcp_delete),
+                )
+                .layer(middleware::from_fn_with_state(
+                    self.clone(),
+                    crate::mcp::transport::mcp_auth_middleware,
+                ));
+
+            // Path-key auth: POST/GET/DELETE /mcp/:key (for claude.ai connectors)
+            let path_key_routes = Router::new().route(
+                "/:key",
+                post(crate::mcp::transport::handle_mcp_post_with_key)
+                    .get(crate::mcp::transport::handle_mcp_get_with_key)
+                    .delete(crate::mcp::transport::handle_mcp_delete_with_key),
+            );
+
+            let mcp_routes = bearer_routes
+                .merge(path_key_routes)
+                .with_state(self.clone());
+            router = router.nest("/mcp", mcp_routes);
+        }
+
+        // Only add file endpoints if a store is configured
+        if let Some(store) = &self.store {
+            // Add presigned URL endpoints for all stores
+            router = router
+                .route("/f/:doc_id/upload-url", post(handle_file_upload_url))
+                .route("/f/:doc_id/download-url", get(handle_file_download_url));
+
+            // Add file operations that work with any store
+            router = router
+                .route("/f/:doc_id/history", get(handle_file_history))
+                .route("/f/:doc_id", delete(handle_file_delete))
+                .route("/f/:doc_id/:hash", delete(handle_file_delete_by_hash))
+                .route("/f/:doc_id", head(handle_file_head));
+
+            // Only add direct upload/download endpoints if store supports direct uploads
+            if store.supports_direct_uploads() {
+                let upload_routes = Router::new()
+                    .route(
+                        "/f/:doc_id/upload",
+                        post(handle_file_upload)
+                            .put(handle_file_upload_raw)
+                            .layer(DefaultBodyLimit::max(100 * 1024 * 1024)), // 100MB for file uploads
+                    )
+                    .route("/f/:doc_id/download", get(handle_file_download))
+                    .layer(DefaultBodyLimit::max(250 * 1024 * 1024));
+                router = router.merge(upload_routes);
+            }
+        }
+
+        // Unauthenticated blob read — local dev only (no auth key configured)
+        if self.authenticator.is_none() {
+            if let Some(_store) = &self.store {
+                router = router.route("/blob/:doc_id/:hash", get(handle_blob_read));
+            }
+        }
+
+        if self.discord.is_some() {
+            router = router
+                .route("/auth/discord/login", get(handle_discord_login))
+                .route("/auth/discord/callback", get(handle_discord_callback))
+                .route("/auth/discord/session", get(handle_discord_session))
+                .route("/auth/discord/doc/:doc_id", post(handle_discord_doc_token))
+                .route("/auth/discord/logout", post(handle_discord_logout));
+        }
+
+        router
+            .layer(DefaultBodyLimit::max(10 * 1024 * 1024)) // 10MB default
+            .with_state(self.clone())
+    }
+
+    pub fn single_doc_routes(self: &Arc<Self>) -> Router {
+        Router::new()
+            .route("/ws/:doc_id", get(handle_socket_upgrade_single))
+            .route("/as-update", get(get_doc_as_update_single))
+            .route("/update", post(update_doc_single))
+            .with_state(self.clone())
+    }
+
+    pub fn metrics_routes(self: &Arc<Self>) -> Router {
+        Router::new()
+            .route("/metrics", get(metrics_endpoint))
+            .with_state(self.clone())
+    }
+
+    async fn serve_internal(
+        self: Arc<Self>,
+        listener: TcpListener,
+        redact_errors: bool,
+        routes: Router,
+    ) -> Result<()> {
+        let token = self.cancellation_token.clone();
+
+        let app = routes.layer(middleware::from_fn(Self::version_header_middleware));
+        let app = if redact_errors {
+            app.layer(middleware::from_fn(Self::redact_error_middleware))
+        } else {
+            app
+        };
+
+        tracing::info!("Starting HTTP server...");
+        axum::serve(listener, app.into_make_service())
+            .with_graceful_shutdown(async move {
+                tracing::info!("Waiting for cancellation token...");
+                token.cancelled().await;
+                tracing::info!("Cancellation token triggered, starting graceful shutdown");
+            })
+            .await?;
+
+        tracing::info!("HTTP server stopped, shutting down event dispatcher...");
+
+        // Explicitly shutdown event dispatcher before waiting on doc workers
+        if let Some(event_dispatcher) = &self.event_dispatcher {
+            tracing::info!("Shutting down event dispatcher...");
+            event_dispatcher.shutdown();
+            tracing::info!("Event dispatcher shutdown complete");
+        }
+
+        tracing::info!("Closing doc worker tracker...");
+        self.doc_worker_tracker.close();
+        tracing::info!("Waiting for doc workers to finish...");
+        self.doc_worker_tracker.wait().await;
+        tracing::info!("All doc workers stopped");
+
+        Ok(())
+    }
+
+    pub async fn serve(self, listener: TcpListener, redact_errors: bool) -> Result<()> {
+        let s = Arc::new(self);
+        let routes = s.routes_with_metrics();
+        s.serve_internal(listener, redact_errors, routes).await
+    }
+
+    pub async fn serve_doc(self, listener: TcpListener, redact_errors: bool) -> Result<()> {
+        let s = Arc::new(self);
+        let routes = s.single_doc_routes_with_metrics();
+        s.serve_internal(listener, redact_errors, routes).await
+    }
+
+    pub async fn serve_metrics(self, listener: TcpListener) -> Result<()> {
+        let s = Arc::new(self);
+        let routes = s.metrics_routes();
+        s.serve_internal(listener, false, routes).await
+    }
+
+    async fn ensure_socket_doc_access(
+        &self,
+        doc_id: &str,
+        authorization: Authorization,
+    ) -> Result<(), AppError> {
+        if !matches!(authorization, Authorization::Full) && !self.doc_exists(doc_id).await {
+            return Err(AppError::new(
+                StatusCode::NOT_FOUND,
+                anyhow!("Doc {} not found", doc_id),
+            ));
+        }
+
+        Ok(())
+    }
+
+    fn verify_doc_token(&self, token: Option<&str>, doc: &str) -> Result<Authorization, AppError> {
+        if let Some(authenticator) = &self.authenticator {
+            if let Some(token) = token {
+                let authorization = authenticator
+                    .verify_doc_token(token, doc, current_time_epoch_millis())
+                    .map_err(|e| {
+                        AppError::auth(StatusCode::UNAUTHORIZED, e.into(), "invalid_doc_token")
+                    })?;
+                let user = authenticator.extract_user_from_token(token).ok().flatten();
+                self.authorize_doc_acl(doc, user.as_deref(), authorization)
+            } else {
+                Err(AppError::auth(
+                    StatusCode::UNAUTHORIZED,
+                    anyhow!("No token provided."),
+                    "missing_token",
+                ))
+            }
+        } else {
+            Ok(Authorization::Full)
+        }
+    }
+
+    fn get_single_doc_id(&self) -> Result<String, AppError> {
+        self.docs
+            .iter()
+            .next()
+            .map(|entry| entry.key().clone())
+            .ok_or_else(|| AppError::new(StatusCode::NOT_FOUND, anyhow!("No document found")))
+    }
+}
+
+#[derive(Deserialize)]
+struct HandlerParams {
+    token: Option<String>,
+}
+
+async fn get_doc_as_update(
+    State(server_state): State<Arc<Server>>,
+    Path(doc_id): Path<String>,
+    auth_header: Option<TypedHeader<headers::Authorization<headers::authorization::Bearer>>>,
+) -> Result<Response, AppError> {
+    // All authorization types allow reading the document.
+    let token = get_token_from_header(auth_header);
+    let _ = server_state.verify_doc_token(token.as_deref(), &doc_id)?;
+
+    let dwskv = server_state
+        .get_or_create_doc(&doc_id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    let update = dwskv.as_update();
+    tracing::debug!("update: {:?}", update);
+    Ok(update.into_response())
+}
+
+async fn get_doc_as_update_deprecated(
+    Path(doc_id): Path<String>,
+    State(server_state): State<Arc<Server>>,
+    auth_header: Option<TypedHeader<headers::Authorization<headers::authorization::Bearer>>>,
+) -> Result<Response, AppError> {
+    tracing::warn!("/doc/:doc_id/as-update is deprecated; call /doc/:doc_id/auth instead and then call as-update on the returned base URL.");
+    get_doc_as_update(State(server_state), Path(doc_id), auth_header).await
+}
+
+async fn update_doc_deprecated(
+    Path(doc_id): Path<String>,
+    State(server_state): State<Arc<Server>>,
+    auth_header: Option<TypedHeader<headers::Authorization<headers::authorization::Bearer>>>,
+    body: Bytes,
+) -> Result<Response, AppError> {
+    tracing::warn!("/doc/:doc_id/update is deprecated; call /doc/:doc_id/auth instead and then call update on the returned base URL.");
+    update_doc(Path(doc_id), State(server_state), auth_header, body).await
+}
+
+async fn get_doc_as_update_single(
+    State(server_state): State<Arc<Server>>,
+    auth_header: Option<TypedHeader<headers::Authorization<headers::authorization::Bearer>>>,
+) -> Result<Response, AppError> {
+    let doc_id = server_state.get_single_doc_id()?;
+    get_doc_as_update(State(server_state), Path(doc_id), auth_header).await
+}
+
+async fn update_doc(
+    Path(doc_id): Path<String>,
+    State(server_state): State<Arc<Server>>,
+    auth_header: Option<TypedHeader<headers::Authorization<headers::authorization::Bearer>>>,
+    body: Bytes,
+) -> Result<Response, AppError> {
+    let token = get_token_from_header(auth_header);
+    let authorization = server_state.verify_doc_token(token.as_deref(), &doc_id)?;
+    update_doc_inner(doc_id, server_state, authorization, body).await
+}
+
+async fn update_doc_inner(
+    doc_id: String,
+    server_state: Arc<Server>,
+    authorization: Authorization,
+    body: Bytes,
+) -> Result<Response, AppError> {
+    if !matches!(authorization, Authorization::Full) {
+        return Err(AppError::auth(
+            StatusCode::FORBIDDEN,
+            anyhow!("Unauthorized."),
+            "insufficient_permissions",
+        ));
+    }
+
+    let dwskv = server_state
+        .get_or_create_doc(&doc_id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    if let Err(err) = dwskv.apply_update(&body) {
+        tracing::error!(?err, "Failed to apply update");
+        return Err(AppError::new(StatusCode::INTERNAL_SERVER_ERROR, err));
+    }
+
+    Ok(StatusCode::OK.into_response())
+}
+
+async fn update_doc_single(
+    State(server_state): State<Arc<Server>>,
+    auth_header: Option<TypedHeader<headers::Authorization<headers::authorization::Bearer>>>,
+    body: Bytes,
+) -> Result<Response, AppError> {
+    let doc_id = server_state.get_single_doc_id()?;
+    let token = get_token_from_header(auth_header);
+    let authorization = server_state.verify_doc_token(token.as_deref(), &doc_id)?;
+    update_doc_inner(doc_id, server_state, authorization, body).await
+}
+
+async fn handle_socket_upgrade(
+    ws: WebSocketUpgrade,
+    Path(doc_id): Path<String>,
+    authorization: Authorization,
+    State(server_state): State<Arc<Server>>,
+) -> Result<Response, AppError> {
+    handle_socket_upgrade_with_channel(ws, Path(doc_id), authorization, None, State(server_state))
+        .await
+}
+
+async fn handle_socket_upgrade_with_channel(
+    ws: WebSocketUpgrade,
+    Path(doc_id): Path<String>,
+    authorization: Authorization,
+    routing_channel: Option<String>,
+    State(server_state): State<Arc<Server>>,
+) -> Result<Response, AppError> {
+    handle_socket_upgrade_with_channel_and_user(
+        ws,
+        Path(doc_id),
+        authorization,
+        routing_channel,
+        None,
+        None, // No token available at this level
+        State(server_state),
+    )
+    .await
+}
+
+async fn handle_socket_upgrade_with_channel_and_user(
+    ws: WebSocketUpgrade,
+    Path(doc_id): Path<String>,
+    authorization: Authorization,
+    routing_channel: Option<String>,
+    user: Option<String>,
+    token: Option<String>,
+    State(server_state): State<Arc<Server>>,
+) -> Result<Response, AppError> {
+    server_state
+        .ensure_socket_doc_access(&doc_id, authorization)
+        .await?;
+
+    // Extract expiration time from token
+    let expiration_time = if let Some(authenticator) = &server_state.authenticator {
+        if let Some(token_str) = token.as_deref() {
+            authenticator
+                .decode_token(token_str)
+                .ok()
+                .and_then(|payload| payload.expiration_millis)
+                .map(|exp| exp.0)
+        } else {
+            None
+        }
+    } else {
+        None
+    };
+
+    let user_for_pud = user.clone();
+    let dwskv = server_state
+        .get_or_create_doc_with_channel_and_user(&doc_id, routing_channel, user)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    let awareness = dwskv.awareness();
+    let sync_kv = dwskv.sync_kv();
+    let cancellation_token = server_state.cancellation_token.clone();
+    let sync_protocol_event_sender = server_state.sync_protocol_event_sender.clone();
+    let metrics = server_state.metrics.clone();
+    let filemeta_guard = server_state.filemeta_guard_for(&doc_id);
+    let doc_id_clone = doc_id.clone();
+
+    Ok(ws.on_upgrade(move |socket| {
+        handle_socket(
+            socket,
+            awareness,
+            sync_kv,
+            authorization,
+            expiration_time,
+            user_for_pud,
+            cancellation_token,
+            sync_protocol_event_sender,
+            doc_id_clone,
+            metrics,
+            filemeta_guard,
+        )
+    }))
+}
+
+fn verify_socket_token(
+    server_state: &Arc<Server>,
     doc_id: &str,
     token: Option<&str>,
 ) -> Result<(Authorization, Option<String>, Option<String>), AppError> {
@@ -4471,1767 +6898,3422 @@ fn verify_socket_token(
             )
         })?;
 
-        authenticator
-            .verify_token_with_channel(token, current_time_epoch_millis())
-            .map_err(|e| {
-                tracing::debug!("Token verification failed: {:?}", e);
-                AppError::auth(StatusCode::UNAUTHORIZED, e.into(), "invalid_token")
-            })?
+        authenticator
+            .verify_token_with_channel(token, current_time_epoch_millis())
+            .map_err(|e| {
+                tracing::debug!("Token verification failed: {:?}", e);
+                AppError::auth(StatusCode::UNAUTHORIZED, e.into(), "invalid_token")
+            })?
+    } else {
+        (Permission::Server, None)
+    };
+
+    let (authorization, user) = match permission {
+        Permission::Doc(doc_perm) => {
+            if doc_perm.doc_id != doc_id {
+                return Err(AppError::auth(
+                    StatusCode::FORBIDDEN,
+                    anyhow!("Token not valid for this document"),
+                    "access_wrong_document",
+                ));
+            }
+            (doc_perm.authorization, doc_perm.user)
+        }
+        Permission::Server => (Authorization::Full, None),
+        Permission::Prefix(prefix_perm) => {
+            if !doc_id.starts_with(&prefix_perm.prefix) {
+                return Err(AppError::auth(
+                    StatusCode::FORBIDDEN,
+                    anyhow!("Token not valid for this document"),
+                    "prefix_mismatch",
+                ));
+            }
+            (prefix_perm.authorization, prefix_perm.user)
+        }
+        Permission::File(_) => {
+            return Err(AppError::auth(
+                StatusCode::FORBIDDEN,
+                anyhow!("File token not valid for document access"),
+                "wrong_token_type",
+            ));
+        }
+    };
+    let authorization = server_state.authorize_doc_acl(doc_id, user.as_deref(), authorization)?;
+
+    Ok((authorization, channel, user))
+}
+
+async fn handle_socket_upgrade_deprecated(
+    ws: WebSocketUpgrade,
+    Path(doc_id): Path<String>,
+    Query(params): Query<HandlerParams>,
+    State(server_state): State<Arc<Server>>,
+) -> Result<Response, AppError> {
+    tracing::warn!(
+        "/doc/ws/:doc_id is deprecated; call /doc/:doc_id/auth instead and use the returned URL."
+    );
+    let (authorization, channel, user) =
+        verify_socket_token(&server_state, &doc_id, params.token.as_deref())?;
+
+    handle_socket_upgrade_with_channel_and_user(
+        ws,
+        Path(doc_id),
+        authorization,
+        channel,
+        user,
+        params.token.clone(), // Pass the token from query params
+        State(server_state),
+    )
+    .await
+}
+
+async fn handle_socket_upgrade_full_path(
+    ws: WebSocketUpgrade,
+    Path((doc_id, doc_id2)): Path<(String, String)>,
+    Query(params): Query<HandlerParams>,
+    State(server_state): State<Arc<Server>>,
+) -> Result<Response, AppError> {
+    tracing::debug!("WebSocket upgrade request for doc: {}", doc_id);
+
+    if doc_id != doc_id2 {
+        tracing::debug!("Doc ID mismatch: {} != {}", doc_id, doc_id2);
+        return Err(AppError::new(
+            StatusCode::BAD_REQUEST,
+            anyhow!("For Yjs compatibility, the doc_id appears twice in the URL. It must be the same in both places, but we got {} and {}.", doc_id, doc_id2),
+        ));
+    }
+
+    let (authorization, channel, user) =
+        verify_socket_token(&server_state, &doc_id, params.token.as_deref())?;
+
+    handle_socket_upgrade_with_channel_and_user(
+        ws,
+        Path(doc_id),
+        authorization,
+        channel,
+        user,
+        params.token.clone(), // Pass the token from query params
+        State(server_state),
+    )
+    .await
+}
+
+async fn handle_socket_upgrade_single(
+    ws: WebSocketUpgrade,
+    Path(doc_id): Path<String>,
+    auth_header: Option<TypedHeader<headers::Authorization<headers::authorization::Bearer>>>,
+    State(server_state): State<Arc<Server>>,
+) -> Result<Response, AppError> {
+    let single_doc_id = server_state.get_single_doc_id()?;
+    if doc_id != single_doc_id {
+        return Err(AppError::new(
+            StatusCode::NOT_FOUND,
+            anyhow!("Document not found"),
+        ));
+    }
+
+    let token = get_token_from_header(auth_header);
+    let authorization = server_state.verify_doc_token(token.as_deref(), &doc_id)?;
+    handle_socket_upgrade(ws, Path(single_doc_id), authorization, State(server_state)).await
+}
+
+async fn handle_socket(
+    socket: WebSocket,
+    awareness: Arc<RwLock<Awareness>>,
+    sync_kv: Arc<SyncKv>,
+    authorization: Authorization,
+    expiration_time: Option<u64>,
+    user: Option<String>,
+    cancellation_token: CancellationToken,
+    sync_protocol_event_sender: Arc<SyncProtocolEventSender>,
+    doc_id: String,
+    metrics: Arc<RelayMetrics>,
+    filemeta_guard: Option<FilemetaGuard>,
+) {
+    let (mut sink, mut stream) = socket.split();
+    let (send, mut recv) = channel(1024);
+
+    tokio::spawn(async move {
+        while let Some(msg) = recv.recv().await {
+            let _ = sink.send(msg).await;
+        }
+    });
+
+    let send_clone = send.clone();
+    let mut conn = DocConnection::new_with_expiration(
+        awareness,
+        authorization,
+        expiration_time,
+        move |bytes| {
+            if let Err(e) = send_clone.try_send(Message::Binary(bytes.to_vec())) {
+                tracing::warn!(?e, "Error sending message");
+            }
+        },
+    );
+    conn.set_sync_kv(sync_kv);
+    if let Some(user) = user {
+        conn.set_user(user);
+    }
+    if let Some(guard) = filemeta_guard {
+        conn.set_filemeta_guard(guard);
+    }
+    let connection = Arc::new(conn);
+
+    // Register the connection with the sync protocol event sender
+    sync_protocol_event_sender.register_doc_connection(doc_id.clone(), Arc::downgrade(&connection));
+
+    loop {
+        tokio::select! {
+            Some(msg) = stream.next() => {
+                let msg = match msg {
+                    Ok(Message::Binary(bytes)) => bytes,
+                    Ok(Message::Close(_)) => break,
+                    Err(_e) => {
+                        // The stream will complain about things like
+                        // connections being lost without handshake.
+                        continue;
+                    }
+                    msg => {
+                        tracing::warn!(?msg, "Received non-binary message");
+                        continue;
+                    }
+                };
+
+                match connection.send(&msg).await {
+                    Ok(_) => {},
+                    Err(e) if e.to_string().contains("Token expired") => {
+                        metrics.record_http_auth_error(
+                            "expired",
+                            "1008",
+                            "websocket_connection",
+                            "WS",
+                        );
+                        tracing::warn!(
+                            doc_id = %doc_id,
+                            "Closing connection due to token expiration"
+                        );
+                        let _ = send.try_send(Message::Close(Some(CloseFrame {
+                            code: 1008, // Policy Violation - indicates a policy violation
+                            reason: "Token expired".into(),
+                        })));
+                        break;
+                    }
+                    Err(e) => {
+                        tracing::warn!(?e, "Error handling message");
+                    }
+                }
+            }
+            _ = cancellation_token.cancelled() => {
+                tracing::debug!("Closing doc connection due to server cancel...");
+                break;
+            }
+        }
+    }
+}
+
+async fn check_store(
+    auth_header: Option<TypedHeader<headers::Authorization<headers::authorization::Bearer>>>,
+    State(server_state): State<Arc<Server>>,
+) -> Result<Json<Value>, AppError> {
+    server_state.check_auth(auth_header)?;
+
+    if server_state.store.is_none() {
+        return Ok(Json(json!({"ok": false, "error": "No store set."})));
+    };
+
+    // The check_store endpoint for the native server is kind of moot, since
+    // the server will not start if store is not ok.
+    Ok(Json(json!({"ok": true})))
+}
+
+async fn check_store_deprecated(
+    auth_header: Option<TypedHeader<headers::Authorization<headers::authorization::Bearer>>>,
+    State(server_state): State<Arc<Server>>,
+) -> Result<Json<Value>, AppError> {
+    tracing::warn!(
+        "GET check_store is deprecated, use POST check_store with an empty body instead."
+    );
+    check_store(auth_header, State(server_state)).await
+}
+
+#[derive(serde::Serialize)]
+struct ReadyResponse {
+    ok: bool,
+    workers: Vec<WorkerReadiness>,
+}
+
+#[derive(serde::Serialize)]
+struct WorkerReadiness {
+    name: String,
+    alive: bool,
+    panics_in_window: u32,
+}
+
+/// Always returns 200 OK as long as the process is listening. The body
+/// reports per-worker liveness so operators can distinguish "process up
+/// but a worker has died" from "fully healthy". `ok` is true iff every
+/// registered worker is still alive; an empty worker set is vacuously OK.
+async fn ready(State(server): State<Arc<Server>>) -> Json<ReadyResponse> {
+    let workers: Vec<WorkerReadiness> = server
+        .worker_status
+        .snapshot()
+        .into_iter()
+        .map(|(name, alive, panics_in_window)| WorkerReadiness {
+            name: name.to_string(),
+            alive,
+            panics_in_window,
+        })
+        .collect();
+    let ok = workers.iter().all(|w| w.alive);
+    Json(ReadyResponse { ok, workers })
+}
+
+async fn handle_open_by_path(
+    State(server_state): State<Arc<Server>>,
+    Path(path): Path<String>,
+    request: Request,
+) -> Result<Response, AppError> {
+    // Fall back to filemeta_v0 (the source of truth) when the eventually-
+    // consistent resolver doesn't know the path yet — e.g. a just-created file.
+    let resolved = server_state.doc_resolver().resolve_path(&path).or_else(|| {
+        let info = server_state.resolve_path_via_filemeta(&path)?;
+        tracing::warn!(
+            requested_path = %path,
+            uuid = %info.uuid,
+            folder = %info.folder_name,
+            "open: doc_resolver stale — path missing from resolver but present in filemeta_v0; using filemeta fallback"
+        );
+        Some(info)
+    });
+    match resolved {
+        Some(info) => {
+            let short_uuid = &info.uuid[..8.min(info.uuid.len())];
+            let encoded_path = path.replace(' ', "-");
+            let mut redirect_url = format!("/{}/{}", short_uuid, encoded_path);
+            if let Some(query) = request.uri().query() {
+                redirect_url.push('?');
+                redirect_url.push_str(query);
+            }
+            Ok(axum::response::Redirect::temporary(&redirect_url).into_response())
+        }
+        None => Err(AppError::new(
+            StatusCode::NOT_FOUND,
+            anyhow!("No document found at path '{}'", path),
+        )),
+    }
+}
+
+/// 503 until the given index has finished its startup build.
+fn require_index_ready(ready: &std::sync::atomic::AtomicBool, what: &str) -> Result<(), AppError> {
+    if ready.load(std::sync::atomic::Ordering::Acquire) {
+        Ok(())
+    } else {
+        Err(AppError::new(
+            StatusCode::SERVICE_UNAVAILABLE,
+            anyhow!("{} is being built, please try again shortly", what),
+        ))
+    }
+}
+
+async fn handle_search(
+    auth_header: Option<TypedHeader<headers::Authorization<headers::authorization::Bearer>>>,
+    State(server_state): State<Arc<Server>>,
+    Query(params): Query<SearchQuery>,
+) -> Result<Response, AppError> {
+    server_state.check_auth(auth_header)?;
+    require_index_ready(&server_state.search_ready, "Search index")?;
+
+    let limit = params.limit.min(100); // Cap at 100
+    let q = params.q.trim().to_string();
+    let mode = match params.mode.as_deref() {
+        None => SearchMode::Bm25,
+        Some(name) => SearchMode::from_name(name).ok_or_else(|| {
+            AppError::new(
+                StatusCode::BAD_REQUEST,
+                anyhow!(
+                    "Invalid search mode '{}' (expected bm25, hybrid or semantic)",
+                    name
+                ),
+            )
+        })?,
+    };
+    let granularity = match params.granularity.as_deref() {
+        None => SearchGranularity::Document,
+        Some(name) => SearchGranularity::from_name(name).ok_or_else(|| {
+            AppError::new(
+                StatusCode::BAD_REQUEST,
+                anyhow!(
+                    "Invalid granularity '{}' (expected document or section)",
+                    name
+                ),
+            )
+        })?,
+    };
+    if granularity == SearchGranularity::Section && mode != SearchMode::Bm25 {
+        return Err(AppError::new(
+            StatusCode::BAD_REQUEST,
+            anyhow!("Section granularity is only supported in bm25 mode"),
+        ));
+    }
+    let sort = match params.sort.as_deref() {
+        None => SearchSort::Relevance,
+        Some(name) => SearchSort::from_name(name).ok_or_else(|| {
+            AppError::new(
+                StatusCode::BAD_REQUEST,
+                anyhow!(
+                    "Invalid sort '{}' (expected relevance, modified, title or path)",
+                    name
+                ),
+            )
+        })?,
+    };
+    if sort != SearchSort::Relevance && mode != SearchMode::Bm25 {
+        return Err(AppError::new(
+            StatusCode::BAD_REQUEST,
+            anyhow!(
+                "Sorting by {} is only supported in bm25 mode",
+                sort.as_str()
+            ),
+        ));
+    }
+    // The cursor is the next offset; clients should treat it as opaque
+    let offset = match (params.offset, params.cursor.as_deref()) {
+        (Some(_), Some(_)) => {
+            return Err(AppError::new(
+                StatusCode::BAD_REQUEST,
+                anyhow!("Pass either offset or cursor, not both"),
+            ))
+        }
+        (_, Some(cursor)) => cursor.parse::<usize>().map_err(|_| {
+            AppError::new(
+                StatusCode::BAD_REQUEST,
+                anyhow!("Invalid cursor '{}'", cursor),
+            )
+        })?,
+        (offset, None) => offset.unwrap_or(0),
+    };
+    if offset > MAX_SEARCH_OFFSET {
+        return Err(AppError::new(
+            StatusCode::BAD_REQUEST,
+            anyhow!(
+                "Offset {} exceeds the maximum of {}",
+                offset,
+                MAX_SEARCH_OFFSET
+            ),
+        ));
+    }
+
+    if q.is_empty() {
+        return Ok(Json(json!({
+            "results": [],
+            "total_hits": 0,
+            "offset": offset,
+            "next_cursor": null,
+            "query": "",
+            "mode": mode,
+            "granularity": granularity,
+            "sort": sort,
+            "facets": SearchFacets::default()
+        }))
+        .into_response());
+    }
+
+    let search_index = server_state.search_index.clone().ok_or_else(|| {
+        AppError::new(
+            StatusCode::SERVICE_UNAVAILABLE,
+            anyhow!("Search index not available"),
+        )
+    })?;
+
+    if mode != SearchMode::Bm25 && search_index.embedding_provider_name().is_none() {
+        return Err(AppError::new(
+            StatusCode::BAD_REQUEST,
+            anyhow!("{} search is not enabled on this server", mode.as_str()),
+        ));
+    }
+
+    let options = SearchOptions {
+        limit,
+        offset,
+        mode,
+        sort,
+        folder: params.folder.clone(),
+        path_prefix: params.path_prefix.clone(),
+        granularity,
+        exclude_doc_ids: server_state.acl_hidden_doc_uuids(params.user.as_deref()),
+    };
+
+    // Run search in blocking context (tantivy is sync)
+    let response =
+        match tokio::task::spawn_blocking(move || search_index.search_with_facets(&q, &options))
+            .await
+            .map_err(|e| AppError::new(StatusCode::INTERNAL_SERVER_ERROR, e.into()))?
+        {
+            Ok(response) => response,
+            // Malformed queries are the caller's to fix: say where, as JSON
+            Err(e) => match e.downcast_ref::<QueryParseError>() {
+                Some(parse_error) => {
+                    return Ok((
+                        StatusCode::BAD_REQUEST,
+                        Json(json!({
+                            "error": parse_error.to_string(),
+                            "query": params.q,
+                            "errors": parse_error.errors
+                        })),
+                    )
+                        .into_response())
+                }
+                None => return Err(AppError::new(StatusCode::INTERNAL_SERVER_ERROR, e)),
+            },
+        };
+
+    let next_offset = offset + response.results.len();
+    let next_cursor =
+        (limit > 0 && next_offset < response.total_hits && next_offset <= MAX_SEARCH_OFFSET)
+            .then(|| next_offset.to_string());
+    Ok(Json(json!({
+        "results": response.results,
+        "total_hits": response.total_hits,
+        "offset": offset,
+        "next_cursor": next_cursor,
+        "query": params.q,
+        "mode": mode,
+        "granularity": granularity,
+        "sort": sort,
+        "facets": response.facets
+    }))
+    .into_response())
+}
+
+async fn handle_folder_name(
+    auth_header: Option<TypedHeader<headers::Authorization<headers::authorization::Bearer>>>,
+    State(server_state): State<Arc<Server>>,
+    Path(folder_uuid): Path<String>,
+) -> Result<Json<Value>, AppError> {
+    server_state.check_auth(auth_header)?;
+    match server_state.folder_name_for_uuid(&folder_uuid) {
+        Some(name) => Ok(Json(json!({ "name": name }))),
+        None => Err(AppError::new(
+            StatusCode::NOT_FOUND,
+            anyhow!("Folder '{}' not found", folder_uuid),
+        )),
+    }
+}
+
+/// Named snapshots of a folder doc and its content docs.
+///
+/// GET  /folder/:folder_uuid/snapshots                  → newest-first manifests
+/// POST /folder/:folder_uuid/snapshots { "name": "..." } → take a snapshot
+/// POST /folder/:folder_uuid/snapshots/:name/restore    → roll the folder back
+async fn handle_list_folder_snapshots(
+    auth_header: Option<TypedHeader<headers::Authorization<headers::authorization::Bearer>>>,
+    State(server_state): State<Arc<Server>>,
+    Path(folder_uuid): Path<String>,
+) -> Result<Json<Vec<FolderSnapshotInfo>>, AppError> {
+    server_state.check_auth(auth_header)?;
+    let folder_doc_id = folder_doc_id_or_404(&server_state, &folder_uuid)?;
+    Ok(Json(
+        server_state.list_folder_snapshots(&folder_doc_id).await?,
+    ))
+}
+
+async fn handle_create_folder_snapshot(
+    auth_header: Option<TypedHeader<headers::Authorization<headers::authorization::Bearer>>>,
+    State(server_state): State<Arc<Server>>,
+    Path(folder_uuid): Path<String>,
+    Json(body): Json<FolderSnapshotRequest>,
+) -> Result<Json<FolderSnapshotInfo>, AppError> {
+    server_state.check_auth(auth_header)?;
+    let folder_doc_id = folder_doc_id_or_404(&server_state, &folder_uuid)?;
+    Ok(Json(
+        server_state
+            .create_folder_snapshot(&folder_doc_id, &body.name)
+            .await?,
+    ))
+}
+
+async fn handle_restore_folder_snapshot(
+    auth_header: Option<TypedHeader<headers::Authorization<headers::authorization::Bearer>>>,
+    State(server_state): State<Arc<Server>>,
+    Path((folder_uuid, name)): Path<(String, String)>,
+) -> Result<Json<FolderSnapshotRestoreResponse>, AppError> {
+    server_state.check_auth(auth_header)?;
+    let folder_doc_id = folder_doc_id_or_404(&server_state, &folder_uuid)?;
+    Ok(Json(
+        server_state
+            .restore_folder_snapshot(&folder_doc_id, &name)
+            .await?,
+    ))
+}
+
+/// A folder's trash: entries deleted from its filemeta_v0 whose content is
+/// still in the store.
+///
+/// GET  /folder/:folder_uuid/trash              → newest-first trashed entries
+/// POST /folder/:folder_uuid/trash/:id/restore  → put an entry back (404 when
+///                                                 not trashed, 409 when its
+///                                                 path is in use)
+async fn handle_list_trash(
+    auth_header: Option<TypedHeader<headers::Authorization<headers::authorization::Bearer>>>,
+    State(server_state): State<Arc<Server>>,
+    Path(folder_uuid): Path<String>,
+) -> Result<Json<Vec<TrashItem>>, AppError> {
+    server_state.check_auth(auth_header)?;
+    let folder_doc_id = folder_doc_id_or_404(&server_state, &folder_uuid)?;
+    Ok(Json(server_state.list_trash(&folder_doc_id)?))
+}
+
+async fn handle_restore_trash(
+    auth_header: Option<TypedHeader<headers::Authorization<headers::authorization::Bearer>>>,
+    State(server_state): State<Arc<Server>>,
+    Path((folder_uuid, id)): Path<(String, String)>,
+) -> Result<Json<TrashItem>, AppError> {
+    server_state.check_auth(auth_header)?;
+    let folder_doc_id = folder_doc_id_or_404(&server_state, &folder_uuid)?;
+    Ok(Json(server_state.restore_trash(&folder_doc_id, &id).await?))
+}
+
+/// Stream a folder as a tar archive of plain files ("relay export"): every
+/// doc and blob in its filemeta_v0 at the same path.
+///
+/// GET /folder/:folder_uuid/export?view=raw|base|accepted
+///
+/// Each file is sent as soon as it is read, so a large folder never sits in
+/// memory whole. A read error mid-stream aborts the response, which leaves
+/// the client with a truncated archive rather than a wrong one.
+async fn handle_export_folder(
+    auth_header: Option<TypedHeader<headers::Authorization<headers::authorization::Bearer>>>,
+    State(server_state): State<Arc<Server>>,
+    Path(folder_uuid): Path<String>,
+    Query(params): Query<FolderExportQuery>,
+) -> Result<Response, AppError> {
+    server_state.check_auth(auth_header)?;
+    let folder_doc_id = folder_doc_id_or_404(&server_state, &folder_uuid)?;
+    let awareness = server_state
+        .docs
+        .get(&folder_doc_id)
+        .map(|doc_ref| doc_ref.awareness())
+        .ok_or_else(|| {
+            AppError::new(
+                StatusCode::NOT_FOUND,
+                anyhow!("Folder '{}' not found", folder_uuid),
+            )
+        })?;
+    let (items, folder_name) = {
+        let guard = awareness.read().unwrap_or_else(|e| e.into_inner());
+        (
+            folder_export::plan_folder_export(&guard.doc, &folder_doc_id),
+            y_sweet_core::doc_resolver::read_folder_name(&guard.doc, &folder_doc_id),
+        )
+    };
+
+    let (tx, rx) = tokio::sync::mpsc::channel::<std::io::Result<Vec<u8>>>(4);
+    let server = server_state.clone();
+    let view = params.view;
+    tokio::spawn(async move {
+        let mtime = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let mut builder = tar::Builder::new(Vec::new());
+        for item in items {
+            let data = match folder_export::read_item(server.as_ref(), &item, view).await {
+                Ok(data) => data,
+                Err(e) => {
+                    tracing::error!(path = %item.path, "Folder export failed: {:?}", e);
+                    let _ = tx.send(Err(std::io::Error::other(e.to_string()))).await;
+                    return;
+                }
+            };
+            if data.is_none() && item.kind != folder_export::ExportItemKind::Folder {
+                continue;
+            }
+            if let Err(e) =
+                folder_export::append_tar_entry(&mut builder, &item, data.as_deref(), mtime)
+            {
+                let _ = tx.send(Err(e)).await;
+                return;
+            }
+            let chunk = std::mem::take(builder.get_mut());
+            if tx.send(Ok(chunk)).await.is_err() {
+                // Client went away.
+                return;
+            }
+        }
+        let _ = tx.send(builder.into_inner()).await;
+    });
+
+    let filename: String = folder_name
+        .chars()
+        .map(|c| if c == '"' || c.is_control() { '_' } else { c })
+        .collect();
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("content-type", "application/x-tar")
+        .header(
+            "content-disposition",
+            format!("attachment; filename=\"{}.tar\"", filename),
+        )
+        .body(axum::body::Body::from_stream(
+            tokio_stream::wrappers::ReceiverStream::new(rx),
+        ))
+        .map_err(|e| AppError::new(StatusCode::INTERNAL_SERVER_ERROR, e.into()))
+}
+
+fn folder_doc_id_or_404(server_state: &Server, folder_uuid: &str) -> Result<String, AppError> {
+    server_state
+        .folder_doc_id_for_uuid(folder_uuid)
+        .ok_or_else(|| {
+            AppError::new(
+                StatusCode::NOT_FOUND,
+                anyhow!("Folder '{}' not found", folder_uuid),
+            )
+        })
+}
+
+/// Check every folder's filemeta_v0 ("relay fsck").
+///
+/// GET  /fsck        → dry-run report
+/// POST /fsck/repair → apply the structural repairs, then report
+async fn handle_fsck(
+    auth_header: Option<TypedHeader<headers::Authorization<headers::authorization::Bearer>>>,
+    State(server_state): State<Arc<Server>>,
+) -> Result<Json<FsckReport>, AppError> {
+    server_state.check_auth(auth_header)?;
+    let report = server_state
+        .fsck(false)
+        .await
+        .map_err(|e| AppError::new(StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    Ok(Json(report))
+}
+
+async fn handle_fsck_repair(
+    auth_header: Option<TypedHeader<headers::Authorization<headers::authorization::Bearer>>>,
+    State(server_state): State<Arc<Server>>,
+) -> Result<Json<FsckReport>, AppError> {
+    server_state.check_auth(auth_header)?;
+    let report = server_state
+        .fsck(true)
+        .await
+        .map_err(|e| AppError::new(StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    Ok(Json(report))
+}
+
+/// Dump every root type of a doc ("relay dump").
+///
+/// GET /dump?doc_id=...      → { "docId": "...", "roots": { "filemeta_v0": {...}, ... } }
+/// GET /dump?folder=Lens     → the same for the folder doc named "Lens"
+async fn handle_dump_doc(
+    auth_header: Option<TypedHeader<headers::Authorization<headers::authorization::Bearer>>>,
+    State(server_state): State<Arc<Server>>,
+    Query(params): Query<DumpQuery>,
+) -> Result<Json<Value>, AppError> {
+    server_state.check_auth(auth_header)?;
+    let doc_id = match (params.doc_id, params.folder) {
+        (Some(doc_id), None) => doc_id,
+        (None, Some(folder)) => server_state
+            .folder_doc_id_for_name(&folder)
+            .ok_or_else(|| {
+                AppError::new(
+                    StatusCode::NOT_FOUND,
+                    anyhow!("Folder '{}' not found", folder),
+                )
+            })?,
+        _ => {
+            return Err(AppError::new(
+                StatusCode::BAD_REQUEST,
+                anyhow!("Pass exactly one of doc_id or folder"),
+            ))
+        }
+    };
+    let roots = server_state.dump_doc(&doc_id).await?;
+    Ok(Json(json!({ "docId": doc_id, "roots": roots })))
+}
+
+/// Import an Obsidian vault directory into a folder ("relay import").
+///
+/// POST /import {"path": "/srv/vault", "folder": "Lens"} → VaultImportReport
+async fn handle_import_vault(
+    auth_header: Option<TypedHeader<headers::Authorization<headers::authorization::Bearer>>>,
+    State(server_state): State<Arc<Server>>,
+    Json(body): Json<VaultImportRequest>,
+) -> Result<Json<VaultImportReport>, AppError> {
+    server_state.check_auth(auth_header)?;
+    let report = server_state
+        .import_vault(
+            std::path::Path::new(&body.path),
+            &body.folder,
+            body.relay_id.as_deref(),
+        )
+        .await?;
+    Ok(Json(report))
+}
+
+/// The ACL policy in force.
+///
+/// GET /acl
+/// Response: { "folders": { "<folder name or uuid>": [{ "path", "user"?, "role" }] } }
+async fn handle_get_acl(
+    auth_header: Option<TypedHeader<headers::Authorization<headers::authorization::Bearer>>>,
+    State(server_state): State<Arc<Server>>,
+) -> Result<Json<AclPolicy>, AppError> {
+    server_state.check_auth(auth_header)?;
+    Ok(Json(server_state.acl_policy()))
+}
+
+/// Replace the ACL policy and save it to `.config/acl.json`. Takes effect
+/// for new requests and connections; open websockets keep the access they
+/// were granted.
+///
+/// PUT /acl
+/// Body: the same shape GET /acl returns.
+async fn handle_put_acl(
+    auth_header: Option<TypedHeader<headers::Authorization<headers::authorization::Bearer>>>,
+    State(server_state): State<Arc<Server>>,
+    Json(policy): Json<AclPolicy>,
+) -> Result<Json<AclPolicy>, AppError> {
+    server_state.check_auth(auth_header)?;
+    policy
+        .validate()
+        .map_err(|e| AppError::new(StatusCode::BAD_REQUEST, anyhow!(e)))?;
+    server_state
+        .set_acl_policy(policy)
+        .await
+        .map_err(|e| AppError::new(StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    Ok(Json(server_state.acl_policy()))
+}
+
+/// Share token revocations in force.
+///
+/// GET /share-tokens/revocations
+/// Response: { tokens: [{ fingerprint, expiry?, revoked_at, note? }],
+///             folders: [{ folder, issued_before, note? }] }
+async fn handle_get_share_revocations(
+    auth_header: Option<TypedHeader<headers::Authorization<headers::authorization::Bearer>>>,
+    State(server_state): State<Arc<Server>>,
+) -> Result<Json<RevocationList>, AppError> {
+    server_state.check_auth(auth_header)?;
+    Ok(Json(server_state.share_revocations()))
+}
+
+/// Replace the revocation list, e.g. to lift a folder cutoff.
+///
+/// PUT /share-tokens/revocations
+/// Body: the same shape GET returns.
+async fn handle_put_share_revocations(
+    auth_header: Option<TypedHeader<headers::Authorization<headers::authorization::Bearer>>>,
+    State(server_state): State<Arc<Server>>,
+    Json(list): Json<RevocationList>,
+) -> Result<Json<RevocationList>, AppError> {
+    server_state.check_auth(auth_header)?;
+    list.validate()
+        .map_err(|e| AppError::new(StatusCode::BAD_REQUEST, anyhow!(e)))?;
+    let list = server_state
+        .update_share_revocations(|current| *current = list)
+        .await
+        .map_err(|e| AppError::new(StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    Ok(Json(list))
+}
+
+#[derive(Deserialize)]
+struct RevokeShareTokenRequest {
+    /// The leaked token itself; only its fingerprint is stored.
+    #[serde(default)]
+    token: Option<String>,
+    /// A fingerprint from a previous revocation or from logs.
+    #[serde(default)]
+    fingerprint: Option<String>,
+    /// Folder UUID whose tokens to revoke, with `issued_before`.
+    #[serde(default)]
+    folder: Option<String>,
+    /// Cutoff for `folder`, unix seconds; defaults to now.
+    #[serde(default)]
+    issued_before: Option<u64>,
+    #[serde(default)]
+    note: Option<String>,
+}
+
+/// Revoke one share token, or every token for a folder issued before a
+/// cutoff. Cutoffs only cover tokens that record their issue time (v2);
+/// v1 tokens are revoked one by one. Takes effect on the next request the
+/// token is used for.
+///
+/// POST /share-tokens/revoke
+/// Body: { token } | { fingerprint } | { folder, issued_before? }, plus an optional note
+/// Response: the updated list, as GET /share-tokens/revocations.
+async fn handle_revoke_share_token(
+    auth_header: Option<TypedHeader<headers::Authorization<headers::authorization::Bearer>>>,
+    State(server_state): State<Arc<Server>>,
+    Json(req): Json<RevokeShareTokenRequest>,
+) -> Result<Json<RevocationList>, AppError> {
+    server_state.check_auth(auth_header)?;
+    let now = current_time_epoch_millis() / 1000;
+
+    let change: Box<dyn FnOnce(&mut RevocationList) + Send> =
+        match (req.token, req.fingerprint, req.folder) {
+            (Some(token), None, None) => {
+                // Tokens that no longer verify can't be used anyway; refusing
+                // them catches pasting the wrong thing.
+                let expiry = match server_state.share_token_secret.as_deref() {
+                    Some(secret) => Some(
+                        share_token::verify_share_token(token.trim(), secret)
+                            .ok_or_else(|| {
+                                AppError::new(
+                                    StatusCode::BAD_REQUEST,
+                                    anyhow!("Not a valid, unexpired share token"),
+                                )
+                            })?
+                            .expiry,
+                    ),
+                    None => None,
+                };
+                let entry = RevokedToken {
+                    fingerprint: token_fingerprint(&token),
+                    expiry,
+                    revoked_at: now,
+                    note: req.note,
+                };
+                tracing::info!(fingerprint = %entry.fingerprint, "Share token revoked");
+                Box::new(move |list| {
+                    list.revoke_token(entry);
+                })
+            }
+            (None, Some(fingerprint), None) => {
+                let entry = RevokedToken {
+                    fingerprint: fingerprint.trim().to_ascii_lowercase(),
+                    expiry: None,
+                    revoked_at: now,
+                    note: req.note,
+                };
+                RevocationList {
+                    tokens: vec![entry.clone()],
+                    folders: Vec::new(),
+                }
+                .validate()
+                .map_err(|e| AppError::new(StatusCode::BAD_REQUEST, anyhow!(e)))?;
+                tracing::info!(fingerprint = %entry.fingerprint, "Share token revoked");
+                Box::new(move |list| {
+                    list.revoke_token(entry);
+                })
+            }
+            (None, None, Some(folder)) => {
+                let rule = RevokedFolder {
+                    folder: folder.trim().to_ascii_lowercase(),
+                    issued_before: req.issued_before.unwrap_or(now),
+                    note: req.note,
+                };
+                tracing::info!(
+                    folder = %rule.folder,
+                    issued_before = rule.issued_before,
+                    "Share tokens revoked for folder"
+                );
+                Box::new(move |list| list.revoke_folder(rule))
+            }
+            _ => {
+                return Err(AppError::new(
+                    StatusCode::BAD_REQUEST,
+                    anyhow!("Pass exactly one of token, fingerprint or folder"),
+                ))
+            }
+        };
+
+    let list = server_state
+        .update_share_revocations(change)
+        .await
+        .map_err(|e| AppError::new(StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    Ok(Json(list))
+}
+
+async fn handle_list_regressions(
+    auth_header: Option<TypedHeader<headers::Authorization<headers::authorization::Bearer>>>,
+    State(server_state): State<Arc<Server>>,
+) -> Result<Json<Value>, AppError> {
+    server_state.check_auth(auth_header)?;
+    Ok(Json(json!({ "events": server_state.regression_events() })))
+}
+
+async fn handle_restore_regression(
+    auth_header: Option<TypedHeader<headers::Authorization<headers::authorization::Bearer>>>,
+    State(server_state): State<Arc<Server>>,
+    Path(event_id): Path<String>,
+) -> Result<Json<RegressionRestoreResponse>, AppError> {
+    server_state.check_auth(auth_header)?;
+    Ok(Json(server_state.restore_regression(&event_id).await?))
+}
+
+/// List CriticMarkup suggestions for all documents in a folder.
+///
+/// GET /suggestions?folder_id=...
+/// Response: { "files": [{ "path": "...", "doc_id": "...", "suggestions": [...] }] }
+///
+/// Answers from the in-memory suggestions index — it must NOT load content
+/// docs on demand. The previous per-request full-folder scan loaded every doc
+/// from storage and triggered the 2026-07-02 prod hang (see
+/// docs/plans/2026-07-02-suggestions-index.md).
+async fn handle_suggestions(
+    auth_header: Option<TypedHeader<headers::Authorization<headers::authorization::Bearer>>>,
+    State(server_state): State<Arc<Server>>,
+    Query(params): Query<SuggestionsQuery>,
+) -> Result<Json<Value>, AppError> {
+    server_state.check_auth(auth_header)?;
+    require_index_ready(&server_state.suggestions_ready, "Suggestions index")?;
+
+    let folder_id = &params.folder_id;
+
+    // Load the folder doc and get content UUIDs from filemeta_v0
+    server_state
+        .ensure_doc_loaded(folder_id)
+        .await
+        .map_err(|e| AppError::new(StatusCode::NOT_FOUND, anyhow!("Folder not found: {}", e)))?;
+
+    let content_uuids = link_indexer::is_folder_doc(folder_id, &server_state.docs)
+        .ok_or_else(|| AppError::new(StatusCode::NOT_FOUND, anyhow!("Not a folder document")))?;
+
+    // Get path mapping from filemeta_v0
+    let path_map = {
+        let doc_ref = server_state.docs.get(folder_id).ok_or_else(|| {
+            AppError::new(StatusCode::NOT_FOUND, anyhow!("Folder doc not loaded"))
+        })?;
+        let awareness = doc_ref.awareness();
+        let guard = awareness.read().unwrap_or_else(|e| e.into_inner());
+        let txn = guard.doc.transact();
+        let filemeta = txn
+            .get_map("filemeta_v0")
+            .ok_or_else(|| AppError::new(StatusCode::NOT_FOUND, anyhow!("No filemeta_v0")))?;
+        let mut map = std::collections::HashMap::new();
+        for (path, value) in filemeta.iter(&txn) {
+            if let Some(id) = link_indexer::extract_id_from_filemeta_entry(&value, &txn) {
+                map.insert(id, path.to_string());
+            }
+        }
+        map
+    };
+
+    // relay_id = first 36 chars of compound folder_id
+    if folder_id.len() < 36 {
+        return Err(AppError::new(
+            StatusCode::BAD_REQUEST,
+            anyhow!("Invalid folder_id"),
+        ));
+    }
+    let relay_id = &folder_id[..36];
+
+    let mut files = Vec::new();
+
+    // Stale index entries for docs deleted from the folder are filtered
+    // naturally: only UUIDs currently in filemeta_v0 are consulted.
+    for content_uuid in &content_uuids {
+        let Some(suggestions) = server_state.suggestions_index.get(content_uuid) else {
+            continue;
+        };
+        let doc_id = format!("{}-{}", relay_id, content_uuid);
+        if !server_state
+            .acl_role_for_doc(&doc_id, params.user.as_deref())
+            .map_or(true, |role| role.allows(Authorization::ReadOnly))
+        {
+            continue;
+        }
+        let path = path_map
+            .get(content_uuid)
+            .cloned()
+            .unwrap_or_else(|| content_uuid.clone());
+
+        files.push(serde_json::json!({
+            "path": path,
+            "doc_id": doc_id,
+            "suggestions": suggestions,
+        }));
+    }
+
+    Ok(Json(serde_json::json!({ "files": files })))
+}
+
+#[derive(serde::Deserialize)]
+struct ApplySuggestionsRequest {
+    doc_id: String,
+    action: String,
+    /// Wire format shared with the GET /suggestions response; only
+    /// `raw_markup`, `type`, `content`, `old_content`, `new_content` are
+    /// consulted (the rest default).
+    suggestions: Vec<critic_scanner::Suggestion>,
+}
+
+/// Apply accept/reject to a batch of suggestions in one document, server-side.
+///
+/// POST /suggestions/apply?folder_id=<compound folder doc id>
+/// Body: { doc_id, action: "accept"|"reject", suggestions: [...] }
+/// Response: { applied: [idx], failed: [{index, reason}], remaining_suggestions }
+///
+/// This replaces the review page's per-file client-side flow (browser opening
+/// every Y.Doc over websocket — unusable at thousands of suggestions across
+/// hundreds of files). folder_id rides in the query so the editor proxy can
+/// scope-check folder tokens without buffering the body; the doc must belong
+/// to that folder. All ops for one doc commit in a single transaction,
+/// deletions are surgical (kept payload keeps its original authorship), and
+/// the suggestions index is updated under the same awareness write lock the
+/// edit commits under, so a racing worker scan can never overwrite it with
+/// pre-apply state and the next refresh is read-your-writes.
+async fn handle_apply_suggestions(
+    auth_header: Option<TypedHeader<headers::Authorization<headers::authorization::Bearer>>>,
+    State(server_state): State<Arc<Server>>,
+    Query(params): Query<SuggestionsQuery>,
+    Json(req): Json<ApplySuggestionsRequest>,
+) -> Result<Json<Value>, AppError> {
+    use y_sweet_core::critic_surgical::{plan_batch, SuggestionAction};
+
+    server_state.check_auth(auth_header)?;
+    require_index_ready(&server_state.suggestions_ready, "Suggestions index")?;
+
+    let action = match req.action.as_str() {
+        "accept" => SuggestionAction::Accept,
+        "reject" => SuggestionAction::Reject,
+        other => {
+            return Err(AppError::new(
+                StatusCode::BAD_REQUEST,
+                anyhow!("Invalid action: {}", other),
+            ))
+        }
+    };
+
+    let Some((_relay_id, doc_uuid)) = link_indexer::parse_doc_id(&req.doc_id) else {
+        return Err(AppError::new(
+            StatusCode::BAD_REQUEST,
+            anyhow!("Invalid doc_id"),
+        ));
+    };
+    let doc_uuid = doc_uuid.to_string();
+
+    // ACL: the doc must belong to the given folder (folder-scoped share
+    // tokens are checked against the folder_id query by the editor proxy;
+    // this makes the pair internally consistent).
+    server_state
+        .ensure_doc_loaded(&params.folder_id)
+        .await
+        .map_err(|e| AppError::new(StatusCode::NOT_FOUND, anyhow!("Folder not found: {}", e)))?;
+    let folder_members = link_indexer::is_folder_doc(&params.folder_id, &server_state.docs)
+        .ok_or_else(|| AppError::new(StatusCode::NOT_FOUND, anyhow!("Not a folder document")))?;
+    if !folder_members.contains(&doc_uuid) {
+        return Err(AppError::new(
+            StatusCode::FORBIDDEN,
+            anyhow!("Document is not part of the given folder"),
+        ));
+    }
+    // Resolving a suggestion edits the doc, so the path ACL must allow edit.
+    if server_state.authorize_doc_acl(&req.doc_id, params.user.as_deref(), Authorization::Full)?
+        != Authorization::Full
+    {
+        return Err(AppError::auth(
+            StatusCode::FORBIDDEN,
+            anyhow!("The folder ACL does not allow editing this document"),
+            "acl_denied",
+        ));
+    }
+
+    server_state
+        .ensure_doc_loaded(&req.doc_id)
+        .await
+        .map_err(|e| AppError::new(StatusCode::NOT_FOUND, anyhow!("Doc not found: {}", e)))?;
+
+    let awareness = {
+        let Some(doc_ref) = server_state.docs.get(&req.doc_id) else {
+            return Err(AppError::new(
+                StatusCode::NOT_FOUND,
+                anyhow!("Doc not loaded"),
+            ));
+        };
+        doc_ref.awareness() // Arc clone
+    }; // DashMap shard lock released
+
+    // Plan + apply + rescan under one awareness write guard: the plan is
+    // computed against the exact body the transaction mutates, and the index
+    // update commits before any other writer can read the doc. Everything in
+    // here is O(body + items) — see critic_surgical::plan_batch.
+    let (applied, failed, remaining_suggestions) = {
+        let guard = awareness.write().unwrap_or_else(|e| e.into_inner());
+        let body = {
+            let txn = guard.doc.transact();
+            match txn.get_text("contents") {
+                Some(text) => text.get_string(&txn),
+                None => String::new(),
+            }
+        };
+
+        let (ops, failures) = plan_batch(&body, &req.suggestions, action);
+        let applied: Vec<usize> = {
+            let mut idx: Vec<usize> = ops.iter().map(|op| op.index).collect();
+            idx.sort_unstable();
+            idx
+        };
+        let failed: Vec<Value> = failures
+            .iter()
+            .map(|f| serde_json::json!({"index": f.index, "reason": f.reason}))
+            .collect();
+
+        {
+            let mut txn = guard.doc.transact_mut();
+            let text = txn.get_or_insert_text("contents");
+            for op in &ops {
+                debug_assert!(
+                    op.deletions.windows(2).all(|w| w[0].from >= w[1].to),
+                    "plan_batch must yield descending, non-overlapping deletions"
+                );
+                for d in &op.deletions {
+                    text.remove_range(&mut txn, d.from as u32, (d.to - d.from) as u32);
+                }
+                if let Some(insert) = &op.fallback_insert {
+                    if !insert.is_empty() {
+                        text.insert(&mut txn, op.span_start as u32, insert);
+                    }
+                }
+            }
+        }
+
+        let new_body = {
+            let txn = guard.doc.transact();
+            match txn.get_text("contents") {
+                Some(text) => text.get_string(&txn),
+                None => String::new(),
+            }
+        };
+        let suggestions = critic_scanner::scan_suggestions(&new_body);
+        let remaining = suggestions.len();
+        server_state
+            .suggestions_index
+            .update(&doc_uuid, suggestions);
+        (applied, failed, remaining)
+    }; // awareness write released
+
+    // Heavy derived state (tantivy, backlinks) flows through the normal
+    // worker queues via the update callback that just fired. Persist
+    // explicitly for immediate durability (same policy as the MCP edit tool).
+    let sync_kv = server_state
+        .docs
+        .get(&req.doc_id)
+        .map(|doc_ref| doc_ref.sync_kv());
+    if let Some(sync_kv) = sync_kv {
+        if let Err(e) = sync_kv.persist().await {
+            tracing::error!(doc_id = %req.doc_id, "Persist after suggestion apply failed: {:?}", e);
+        }
+    }
+
+    tracing::info!(
+        doc_id = %req.doc_id,
+        applied = applied.len(),
+        failed = failed.len(),
+        remaining = remaining_suggestions,
+        "Applied suggestion batch"
+    );
+
+    Ok(Json(serde_json::json!({
+        "applied": applied,
+        "failed": failed,
+        "remaining_suggestions": remaining_suggestions,
+    })))
+}
+
+/// Move a document to a new path within or across folders.
+///
+/// POST /doc/move
+/// Body: { "uuid": "...", "new_path": "/Biology/Photosynthesis.md", "target_folder": "Lens Edu" }
+/// Response: { "old_path", "new_path", "old_folder", "new_folder", "links_rewritten" }
+async fn handle_move_document(
+    auth_header: Option<TypedHeader<headers::Authorization<headers::authorization::Bearer>>>,
+    State(server_state): State<Arc<Server>>,
+    Json(body): Json<MoveDocRequest>,
+) -> Result<Json<MoveDocResponse>, AppError> {
+    server_state.check_auth(auth_header)?;
+    let result = server_state
+        .move_document(&body.uuid, &body.new_path, body.target_folder.as_deref())
+        .await
+        .map_err(AppError::from)?;
+
+    Ok(Json(MoveDocResponse {
+        old_path: result.old_path,
+        new_path: result.new_path,
+        old_folder: result.old_folder_name,
+        new_folder: result.new_folder_name,
+        links_rewritten: result.links_rewritten,
+    }))
+}
+
+/// Move a document or rename a folder by user-facing path.
+///
+/// POST /move
+/// Body: { "path": "Lens/Old.md", "new_path": "/New.md", "target_folder": "Lens Edu" }
+/// Response: { "old_path", "new_path", "old_folder", "new_folder", "links_rewritten" }
+async fn handle_move_path(
+    auth_header: Option<TypedHeader<headers::Authorization<headers::authorization::Bearer>>>,
+    State(server_state): State<Arc<Server>>,
+    Json(body): Json<MovePathRequest>,
+) -> Result<Json<MoveDocResponse>, AppError> {
+    server_state.check_auth(auth_header)?;
+    let result = server_state
+        .move_path(&body.path, &body.new_path, body.target_folder.as_deref())
+        .await
+        .map_err(|e| {
+            // Move failures are returned to the client with an empty body, so
+            // without this they are invisible in the logs.
+            tracing::warn!(
+                path = %body.path,
+                new_path = %body.new_path,
+                target_folder = ?body.target_folder,
+                error = ?e,
+                "move_path request failed"
+            );
+            AppError::from(e)
+        })?;
+
+    Ok(Json(MoveDocResponse {
+        old_path: result.old_path,
+        new_path: result.new_path,
+        old_folder: result.old_folder_name,
+        new_folder: result.new_folder_name,
+        links_rewritten: result.links_rewritten,
+    }))
+}
+
+/// Delete a markdown document by user-facing path.
+///
+/// DELETE /doc?path=Lens/Old.md
+/// Response: { "uuid", "doc_id", "folder", "path", "backlinks_removed" }
+async fn handle_delete_path(
+    auth_header: Option<TypedHeader<headers::Authorization<headers::authorization::Bearer>>>,
+    State(server_state): State<Arc<Server>>,
+    Query(params): Query<DeletePathQuery>,
+) -> Result<Json<DeleteDocumentResult>, AppError> {
+    server_state.check_auth(auth_header)?;
+    let result = server_state
+        .delete_path(&params.path, Some("api"))
+        .await
+        .map_err(|e| {
+            tracing::warn!(path = %params.path, error = ?e, "delete_path request failed");
+            AppError::from(e)
+        })?;
+    Ok(Json(result))
+}
+
+/// POST /doc/upsert
+/// Creates a document if it doesn't exist, or replaces its content if it does.
+/// No CriticMarkup wrapping — content is written directly to Y.Text.
+/// Accepts any file extension; uses "file" type for non-.md, "markdown" for .md.
+#[derive(Deserialize)]
+struct UpsertDocRequest {
+    folder: String,
+    path: String,
+    content: String,
+}
+
+#[derive(Serialize)]
+struct UpsertDocResponse {
+    doc_id: String,
+    path: String,
+    created: bool,
+}
+
+async fn handle_upsert_document(
+    auth_header: Option<TypedHeader<headers::Authorization<headers::authorization::Bearer>>>,
+    State(server_state): State<Arc<Server>>,
+    Json(body): Json<UpsertDocRequest>,
+) -> Result<Json<UpsertDocResponse>, AppError> {
+    server_state.check_auth(auth_header)?;
+
+    // Ensure path starts with /
+    let path = if body.path.starts_with('/') {
+        body.path.clone()
     } else {
-        (Permission::Server, None)
+        format!("/{}", body.path)
     };
 
-    let (authorization, user) = match permission {
-        Permission::Doc(doc_perm) => {
-            if doc_perm.doc_id != doc_id {
-                return Err(AppError::auth(
-                    StatusCode::FORBIDDEN,
-                    anyhow!("Token not valid for this document"),
-                    "access_wrong_document",
-                ));
+    let is_blob = path.to_ascii_lowercase().ends_with(".json");
+
+    if is_blob {
+        // JSON files → blob storage (create-only, no updates)
+        match server_state
+            .create_blob_file(
+                &body.folder,
+                &path,
+                body.content.as_bytes(),
+                "application/json",
+            )
+            .await
+        {
+            Ok(result) => Ok(Json(UpsertDocResponse {
+                doc_id: result.full_doc_id,
+                path: format!("{}{}", body.folder, path),
+                created: true,
+            })),
+            Err(CreateDocumentError::Conflict(msg)) => {
+                Err(AppError::new(StatusCode::CONFLICT, anyhow!("{}", msg)))
             }
-            (doc_perm.authorization, doc_perm.user)
+            Err(CreateDocumentError::NotFound(msg)) => {
+                Err(AppError::new(StatusCode::NOT_FOUND, anyhow!("{}", msg)))
+            }
+            Err(CreateDocumentError::BadRequest(msg)) => {
+                Err(AppError::new(StatusCode::BAD_REQUEST, anyhow!("{}", msg)))
+            }
+            Err(e) => Err(AppError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                anyhow!("{}", e),
+            )),
         }
-        Permission::Server => (Authorization::Full, None),
-        Permission::Prefix(prefix_perm) => {
-            if !doc_id.starts_with(&prefix_perm.prefix) {
-                return Err(AppError::auth(
-                    StatusCode::FORBIDDEN,
-                    anyhow!("Token not valid for this document"),
-                    "prefix_mismatch",
-                ));
+    } else {
+        // Markdown/other → Y.Doc (existing behavior with upsert semantics)
+        match server_state
+            .create_document_direct(&body.folder, &path, &body.content, None)
+            .await
+        {
+            Ok(result) => Ok(Json(UpsertDocResponse {
+                doc_id: result.full_doc_id,
+                path: format!("{}{}", body.folder, path),
+                created: true,
+            })),
+            Err(CreateDocumentError::Conflict(_)) => {
+                // Already exists — update content instead
+                server_state
+                    .write_document_content(&body.folder, &path, &body.content)
+                    .await
+                    .map_err(|e| {
+                        AppError::new(StatusCode::INTERNAL_SERVER_ERROR, anyhow!("{}", e))
+                    })?;
+                Ok(Json(UpsertDocResponse {
+                    doc_id: String::new(),
+                    path: format!("{}{}", body.folder, path),
+                    created: false,
+                }))
             }
-            (prefix_perm.authorization, prefix_perm.user)
+            Err(CreateDocumentError::NotFound(msg)) => {
+                Err(AppError::new(StatusCode::NOT_FOUND, anyhow!("{}", msg)))
+            }
+            Err(CreateDocumentError::BadRequest(msg)) => {
+                Err(AppError::new(StatusCode::BAD_REQUEST, anyhow!("{}", msg)))
+            }
+            Err(e) => Err(AppError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                anyhow!("{}", e),
+            )),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct CheckDocsRequest {
+    folder: String,
+    paths: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct CheckDocsResponse {
+    exists: std::collections::HashMap<String, bool>,
+}
+
+async fn handle_check_documents(
+    auth_header: Option<TypedHeader<headers::Authorization<headers::authorization::Bearer>>>,
+    State(server_state): State<Arc<Server>>,
+    Json(body): Json<CheckDocsRequest>,
+) -> Result<Json<CheckDocsResponse>, AppError> {
+    server_state.check_auth(auth_header)?;
+
+    let docs = server_state.docs();
+    let folder_doc_ids = link_indexer::find_all_folder_docs(docs);
+
+    // Find the matching folder doc
+    let mut folder_doc_id: Option<String> = None;
+    for fid in &folder_doc_ids {
+        let awareness = {
+            let Some(doc_ref) = docs.get(fid) else {
+                continue;
+            };
+            doc_ref.awareness()
+        };
+        let guard = awareness.read().unwrap_or_else(|e| e.into_inner());
+        let name = y_sweet_core::doc_resolver::read_folder_name(&guard.doc, fid);
+        if name == body.folder {
+            folder_doc_id = Some(fid.clone());
+            break;
         }
-        Permission::File(_) => {
-            return Err(AppError::auth(
-                StatusCode::FORBIDDEN,
-                anyhow!("File token not valid for document access"),
-                "wrong_token_type",
+    }
+
+    let Some(folder_doc_id) = folder_doc_id else {
+        return Err(AppError::new(
+            StatusCode::NOT_FOUND,
+            anyhow!("Unknown folder '{}'", body.folder),
+        ));
+    };
+
+    // Read filemeta_v0 once, check all paths
+    let awareness = {
+        let Some(doc_ref) = docs.get(&folder_doc_id) else {
+            return Err(AppError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                anyhow!("Folder doc not loaded"),
             ));
-        }
+        };
+        doc_ref.awareness()
     };
+    let guard = awareness.read().unwrap_or_else(|e| e.into_inner());
+    let txn = guard.doc.transact();
 
-    Ok((authorization, channel, user))
+    let mut exists = std::collections::HashMap::new();
+    let filemeta = txn.get_map("filemeta_v0");
+    for path in &body.paths {
+        let normalized = if path.starts_with('/') {
+            path.clone()
+        } else {
+            format!("/{}", path)
+        };
+        let found = filemeta
+            .as_ref()
+            .map(|fm| fm.get(&txn, normalized.as_str()).is_some())
+            .unwrap_or(false);
+        exists.insert(path.clone(), found);
+    }
+
+    Ok(Json(CheckDocsResponse { exists }))
 }
 
-async fn handle_socket_upgrade_deprecated(
-    ws: WebSocketUpgrade,
-    Path(doc_id): Path<String>,
-    Query(params): Query<HandlerParams>,
-    State(server_state): State<Arc<Server>>,
-) -> Result<Response, AppError> {
-    tracing::warn!(
-        "/doc/ws/:doc_id is deprecated; call /doc/:doc_id/auth instead and use the returned URL."
-    );
-    let (authorization, channel, user) =
-        verify_socket_token(&server_state, &doc_id, params.token.as_deref())?;
+#[derive(Deserialize)]
+struct CheckVideoIdsRequest {
+    folder: String,
+    subfolder: Option<String>,
+    video_ids: Vec<String>,
+}
 
-    handle_socket_upgrade_with_channel_and_user(
-        ws,
-        Path(doc_id),
-        authorization,
-        channel,
-        user,
-        params.token.clone(), // Pass the token from query params
-        State(server_state),
-    )
-    .await
+#[derive(Serialize)]
+struct CheckVideoIdsResponse {
+    found: std::collections::HashMap<String, Option<String>>,
 }
 
-async fn handle_socket_upgrade_full_path(
-    ws: WebSocketUpgrade,
-    Path((doc_id, doc_id2)): Path<(String, String)>,
-    Query(params): Query<HandlerParams>,
+async fn handle_check_video_ids(
+    auth_header: Option<TypedHeader<headers::Authorization<headers::authorization::Bearer>>>,
     State(server_state): State<Arc<Server>>,
-) -> Result<Response, AppError> {
-    tracing::debug!("WebSocket upgrade request for doc: {}", doc_id);
+    Json(body): Json<CheckVideoIdsRequest>,
+) -> Result<Json<CheckVideoIdsResponse>, AppError> {
+    server_state.check_auth(auth_header)?;
 
-    if doc_id != doc_id2 {
-        tracing::debug!("Doc ID mismatch: {} != {}", doc_id, doc_id2);
-        return Err(AppError::new(
-            StatusCode::BAD_REQUEST,
-            anyhow!("For Yjs compatibility, the doc_id appears twice in the URL. It must be the same in both places, but we got {} and {}.", doc_id, doc_id2),
-        ));
-    }
+    let prefix = match &body.subfolder {
+        Some(sub) => format!("{}/{}/", body.folder, sub),
+        None => format!("{}/", body.folder),
+    };
 
-    let (authorization, channel, user) =
-        verify_socket_token(&server_state, &doc_id, params.token.as_deref())?;
+    let all_paths = server_state.doc_resolver().all_paths();
+    let matching_paths: Vec<String> = all_paths
+        .into_iter()
+        .filter(|p| p.starts_with(&prefix) && p.ends_with(".md"))
+        .collect();
 
-    handle_socket_upgrade_with_channel_and_user(
-        ws,
-        Path(doc_id),
-        authorization,
-        channel,
-        user,
-        params.token.clone(), // Pass the token from query params
-        State(server_state),
-    )
-    .await
+    let mut found: std::collections::HashMap<String, Option<String>> =
+        body.video_ids.iter().map(|id| (id.clone(), None)).collect();
+
+    for path in &matching_paths {
+        if found.values().all(|v| v.is_some()) {
+            break;
+        }
+
+        let doc_id = match server_state.doc_resolver().resolve_path(path) {
+            Some(doc_info) => doc_info.doc_id,
+            None => continue,
+        };
+
+        if server_state.ensure_doc_loaded(&doc_id).await.is_err() {
+            continue;
+        }
+
+        let content = match read_doc_head(&server_state, &doc_id, 500) {
+            Some(c) => c,
+            None => continue,
+        };
+
+        let rel_path = format!("/{}", &path[body.folder.len()..].trim_start_matches('/'));
+
+        for (video_id, slot) in found.iter_mut() {
+            if slot.is_none()
+                && (content.contains(&format!("watch?v={}", video_id))
+                    || content.contains(&format!("/shorts/{}", video_id)))
+            {
+                *slot = Some(rel_path.clone());
+            }
+        }
+    }
+
+    Ok(Json(CheckVideoIdsResponse { found }))
 }
 
-async fn handle_socket_upgrade_single(
-    ws: WebSocketUpgrade,
-    Path(doc_id): Path<String>,
-    auth_header: Option<TypedHeader<headers::Authorization<headers::authorization::Bearer>>>,
-    State(server_state): State<Arc<Server>>,
-) -> Result<Response, AppError> {
-    let single_doc_id = server_state.get_single_doc_id()?;
-    if doc_id != single_doc_id {
-        return Err(AppError::new(
-            StatusCode::NOT_FOUND,
-            anyhow!("Document not found"),
-        ));
+/// Read up to `max_bytes` (rounded down to a UTF-8 char boundary) of a loaded
+/// doc's `contents` Y.Text. The caller must have ensured the doc is loaded.
+/// Returns None when the doc is not present in the store.
+fn read_doc_head(server_state: &Arc<Server>, doc_id: &str, max_bytes: usize) -> Option<String> {
+    let doc_ref = server_state.docs().get(doc_id)?;
+    let awareness = doc_ref.awareness();
+    drop(doc_ref);
+    let guard = awareness.read().unwrap_or_else(|e| e.into_inner());
+    let txn = guard.doc.transact();
+    let full = txn
+        .get_text("contents")
+        .map(|t| t.get_string(&txn))
+        .unwrap_or_default();
+    if full.len() <= max_bytes {
+        return Some(full);
     }
+    let mut end = max_bytes;
+    while end > 0 && !full.is_char_boundary(end) {
+        end -= 1;
+    }
+    Some(full[..end].to_string())
+}
 
-    let token = get_token_from_header(auth_header);
-    let authorization = server_state.verify_doc_token(token.as_deref(), &doc_id)?;
-    handle_socket_upgrade(ws, Path(single_doc_id), authorization, State(server_state)).await
+/// Normalize a source URL for dedup comparison: trim whitespace and a trailing
+/// slash (the common variant). Intentionally conservative — scheme/host case and
+/// query strings are left intact so genuinely different URLs aren't merged.
+fn normalize_source_url(url: &str) -> String {
+    url.trim().trim_end_matches('/').to_string()
 }
 
-async fn handle_socket(
-    socket: WebSocket,
-    awareness: Arc<RwLock<Awareness>>,
-    sync_kv: Arc<SyncKv>,
-    authorization: Authorization,
-    expiration_time: Option<u64>,
-    user: Option<String>,
-    cancellation_token: CancellationToken,
-    sync_protocol_event_sender: Arc<SyncProtocolEventSender>,
-    doc_id: String,
-    metrics: Arc<RelayMetrics>,
-) {
-    let (mut sink, mut stream) = socket.split();
-    let (send, mut recv) = channel(1024);
+#[derive(Deserialize)]
+struct CheckSourceUrlsRequest {
+    folder: String,
+    subfolder: Option<String>,
+    source_urls: Vec<String>,
+}
 
-    tokio::spawn(async move {
-        while let Some(msg) = recv.recv().await {
-            let _ = sink.send(msg).await;
-        }
-    });
+#[derive(Serialize)]
+struct CheckSourceUrlsResponse {
+    found: std::collections::HashMap<String, Option<String>>,
+    /// Full content is returned only for lightweight article stubs. This lets
+    /// the importer preserve discussion notes during promotion without sending
+    /// already-imported article bodies over the duplicate-check endpoint.
+    stubs: std::collections::HashMap<String, Option<ArticleStubMatch>>,
+}
 
-    let send_clone = send.clone();
-    let mut conn = DocConnection::new_with_expiration(
-        awareness,
-        authorization,
-        expiration_time,
-        move |bytes| {
-            if let Err(e) = send_clone.try_send(Message::Binary(bytes.to_vec())) {
-                tracing::warn!(?e, "Error sending message");
-            }
-        },
-    );
-    conn.set_sync_kv(sync_kv);
-    if let Some(user) = user {
-        conn.set_user(user);
-    }
-    let connection = Arc::new(conn);
+#[derive(Clone, Serialize)]
+struct ArticleStubMatch {
+    path: String,
+    content: String,
+}
+
+/// For each given source URL, find an existing article doc whose `source_url`
+/// frontmatter matches (normalized) — the URL-based duplicate check the
+/// add-article importer uses. Mirrors `handle_check_video_ids`, but matches the
+/// frontmatter field precisely rather than substring-scanning the body.
+async fn handle_check_source_urls(
+    auth_header: Option<TypedHeader<headers::Authorization<headers::authorization::Bearer>>>,
+    State(server_state): State<Arc<Server>>,
+    Json(body): Json<CheckSourceUrlsRequest>,
+) -> Result<Json<CheckSourceUrlsResponse>, AppError> {
+    server_state.check_auth(auth_header)?;
+
+    let prefix = match &body.subfolder {
+        Some(sub) => format!("{}/{}/", body.folder, sub),
+        None => format!("{}/", body.folder),
+    };
 
-    // Register the connection with the sync protocol event sender
-    sync_protocol_event_sender.register_doc_connection(doc_id.clone(), Arc::downgrade(&connection));
+    let matching_paths: Vec<String> = server_state
+        .doc_resolver()
+        .all_paths()
+        .into_iter()
+        .filter(|p| p.starts_with(&prefix) && p.ends_with(".md"))
+        .collect();
 
-    loop {
-        tokio::select! {
-            Some(msg) = stream.next() => {
-                let msg = match msg {
-                    Ok(Message::Binary(bytes)) => bytes,
-                    Ok(Message::Close(_)) => break,
-                    Err(_e) => {
-                        // The stream will complain about things like
-                        // connections being lost without handshake.
-                        continue;
-                    }
-                    msg => {
-                        tracing::warn!(?msg, "Received non-binary message");
-                        continue;
-                    }
-                };
+    let queries: Vec<(String, String)> = body
+        .source_urls
+        .iter()
+        .map(|u| (u.clone(), normalize_source_url(u)))
+        .collect();
+    let mut found: std::collections::HashMap<String, Option<String>> =
+        body.source_urls.iter().map(|u| (u.clone(), None)).collect();
+    let mut stubs: std::collections::HashMap<String, Option<ArticleStubMatch>> =
+        body.source_urls.iter().map(|u| (u.clone(), None)).collect();
 
-                match connection.send(&msg).await {
-                    Ok(_) => {},
-                    Err(e) if e.to_string().contains("Token expired") => {
-                        metrics.record_http_auth_error(
-                            "expired",
-                            "1008",
-                            "websocket_connection",
-                            "WS",
-                        );
-                        tracing::warn!(
-                            doc_id = %doc_id,
-                            "Closing connection due to token expiration"
-                        );
-                        let _ = send.try_send(Message::Close(Some(CloseFrame {
-                            code: 1008, // Policy Violation - indicates a policy violation
-                            reason: "Token expired".into(),
-                        })));
-                        break;
-                    }
-                    Err(e) => {
-                        tracing::warn!(?e, "Error handling message");
+    for path in &matching_paths {
+        if found.values().all(|v| v.is_some()) {
+            break;
+        }
+        let doc_id = match server_state.doc_resolver().resolve_path(path) {
+            Some(info) => info.doc_id,
+            None => continue,
+        };
+        if server_state.ensure_doc_loaded(&doc_id).await.is_err() {
+            continue;
+        }
+        let head = match read_doc_head(&server_state, &doc_id, 4000) {
+            Some(h) => h,
+            None => continue,
+        };
+        let head_frontmatter = frontmatter::parse(&head);
+        let stored = match &head_frontmatter.source_url {
+            Some(s) => normalize_source_url(s),
+            None => continue,
+        };
+        let rel_path = format!("/{}", &path[body.folder.len()..].trim_start_matches('/'));
+        let stub_content = if head_frontmatter.has_tag("article-stub") {
+            read_doc_head(&server_state, &doc_id, usize::MAX)
+        } else {
+            None
+        };
+        for (orig, norm) in &queries {
+            if *norm == stored {
+                if let Some(slot) = found.get_mut(orig) {
+                    if slot.is_none() {
+                        *slot = Some(rel_path.clone());
+                        if let Some(content) = &stub_content {
+                            stubs.insert(
+                                orig.clone(),
+                                Some(ArticleStubMatch {
+                                    path: rel_path.clone(),
+                                    content: content.clone(),
+                                }),
+                            );
+                        }
                     }
                 }
             }
-            _ = cancellation_token.cancelled() => {
-                tracing::debug!("Closing doc connection due to server cancel...");
-                break;
-            }
         }
     }
+
+    Ok(Json(CheckSourceUrlsResponse { found, stubs }))
 }
 
-async fn check_store(
+#[derive(Deserialize)]
+struct AttachmentQuery {
+    folder: String,
+    path: String,
+    mimetype: Option<String>,
+}
+
+/// Create a binary attachment (e.g. an image extracted from an imported PDF) as
+/// a relay blob + `filemeta_v0` entry, so markdown can reference it
+/// (`![[/attachments/x.png]]`). Raw bytes in the body; folder/path/mimetype in
+/// the query. Create-only: an existing path is treated as already-hosted.
+async fn handle_upsert_attachment(
     auth_header: Option<TypedHeader<headers::Authorization<headers::authorization::Bearer>>>,
     State(server_state): State<Arc<Server>>,
-) -> Result<Json<Value>, AppError> {
+    Query(query): Query<AttachmentQuery>,
+    body: axum::body::Bytes,
+) -> Result<Json<UpsertDocResponse>, AppError> {
     server_state.check_auth(auth_header)?;
 
-    if server_state.store.is_none() {
-        return Ok(Json(json!({"ok": false, "error": "No store set."})));
+    let path = if query.path.starts_with('/') {
+        query.path.clone()
+    } else {
+        format!("/{}", query.path)
     };
+    let mimetype = query
+        .mimetype
+        .as_deref()
+        .unwrap_or("application/octet-stream");
 
-    // The check_store endpoint for the native server is kind of moot, since
-    // the server will not start if store is not ok.
-    Ok(Json(json!({"ok": true})))
+    match server_state
+        .create_blob_file(&query.folder, &path, &body, mimetype)
+        .await
+    {
+        Ok(result) => Ok(Json(UpsertDocResponse {
+            doc_id: result.full_doc_id,
+            path: format!("{}{}", query.folder, path),
+            created: true,
+        })),
+        // Already hosted at this path — idempotent success.
+        Err(CreateDocumentError::Conflict(_)) => Ok(Json(UpsertDocResponse {
+            doc_id: String::new(),
+            path: format!("{}{}", query.folder, path),
+            created: false,
+        })),
+        Err(CreateDocumentError::NotFound(msg)) => {
+            Err(AppError::new(StatusCode::NOT_FOUND, anyhow!("{}", msg)))
+        }
+        Err(CreateDocumentError::BadRequest(msg)) => {
+            Err(AppError::new(StatusCode::BAD_REQUEST, anyhow!("{}", msg)))
+        }
+        Err(e) => Err(AppError::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            anyhow!("{:?}", e),
+        )),
+    }
 }
 
-async fn check_store_deprecated(
+async fn new_doc(
     auth_header: Option<TypedHeader<headers::Authorization<headers::authorization::Bearer>>>,
     State(server_state): State<Arc<Server>>,
-) -> Result<Json<Value>, AppError> {
-    tracing::warn!(
-        "GET check_store is deprecated, use POST check_store with an empty body instead."
-    );
-    check_store(auth_header, State(server_state)).await
-}
-
-#[derive(serde::Serialize)]
-struct ReadyResponse {
-    ok: bool,
-    workers: Vec<WorkerReadiness>,
-}
-
-#[derive(serde::Serialize)]
-struct WorkerReadiness {
-    name: String,
-    alive: bool,
-    panics_in_window: u32,
-}
+    Json(body): Json<DocCreationRequest>,
+) -> Result<Json<NewDocResponse>, AppError> {
+    let token = get_token_from_header(auth_header);
 
-/// Always returns 200 OK as long as the process is listening. The body
-/// reports per-worker liveness so operators can distinguish "process up
-/// but a worker has died" from "fully healthy". `ok` is true iff every
-/// registered worker is still alive; an empty worker set is vacuously OK.
-async fn ready(State(server): State<Arc<Server>>) -> Json<ReadyResponse> {
-    let workers: Vec<WorkerReadiness> = server
-        .worker_status
-        .snapshot()
-        .into_iter()
-        .map(|(name, alive, panics_in_window)| WorkerReadiness {
-            name: name.to_string(),
-            alive,
-            panics_in_window,
-        })
-        .collect();
-    let ok = workers.iter().all(|w| w.alive);
-    Json(ReadyResponse { ok, workers })
-}
+    if let Some(authenticator) = &server_state.authenticator {
+        if let Some(token) = token.as_deref() {
+            // First try server token
+            if authenticator
+                .verify_server_token(token, current_time_epoch_millis())
+                .is_ok()
+            {
+                // Server token allows creating any document
+            } else {
+                // Try prefix token - we need to check if the doc_id matches the prefix
+                if let Some(doc_id) = &body.doc_id {
+                    let permission = authenticator
+                        .verify_token_auto(token, current_time_epoch_millis())
+                        .map_err(|auth_error| {
+                            AppError::auth(
+                                StatusCode::UNAUTHORIZED,
+                                anyhow!("Invalid token: {}", auth_error),
+                                auth_error.to_metric_label(),
+                            )
+                        })?;
 
-async fn handle_open_by_path(
-    State(server_state): State<Arc<Server>>,
-    Path(path): Path<String>,
-    request: Request,
-) -> Result<Response, AppError> {
-    // Fall back to filemeta_v0 (the source of truth) when the eventually-
-    // consistent resolver doesn't know the path yet — e.g. a just-created file.
-    let resolved = server_state.doc_resolver().resolve_path(&path).or_else(|| {
-        let info = server_state.resolve_path_via_filemeta(&path)?;
-        tracing::warn!(
-            requested_path = %path,
-            uuid = %info.uuid,
-            folder = %info.folder_name,
-            "open: doc_resolver stale — path missing from resolver but present in filemeta_v0; using filemeta fallback"
-        );
-        Some(info)
-    });
-    match resolved {
-        Some(info) => {
-            let short_uuid = &info.uuid[..8.min(info.uuid.len())];
-            let encoded_path = path.replace(' ', "-");
-            let mut redirect_url = format!("/{}/{}", short_uuid, encoded_path);
-            if let Some(query) = request.uri().query() {
-                redirect_url.push('?');
-                redirect_url.push_str(query);
+                    match permission {
+                        Permission::Prefix(prefix_perm) => {
+                            // Check if the document ID starts with the prefix
+                            if !doc_id.starts_with(&prefix_perm.prefix) {
+                                return Err(AppError::auth(
+                                    StatusCode::FORBIDDEN,
+                                    anyhow!(
+                                        "Document ID '{}' does not match prefix '{}'",
+                                        doc_id,
+                                        prefix_perm.prefix
+                                    ),
+                                    "prefix_mismatch",
+                                ));
+                            }
+                            // Check if we have Full permissions (needed for creation)
+                            if prefix_perm.authorization != Authorization::Full {
+                                return Err(AppError::auth(
+                                    StatusCode::FORBIDDEN,
+                                    anyhow!("Prefix token requires Full authorization to create documents"),
+                                    "insufficient_permissions",
+                                ));
+                            }
+                        }
+                        _ => {
+                            return Err(AppError::auth(
+                                StatusCode::FORBIDDEN,
+                                anyhow!("Only server or prefix tokens can create documents"),
+                                "wrong_token_type",
+                            ));
+                        }
+                    }
+                } else {
+                    // No doc_id provided - only server tokens can create with auto-generated ID
+                    return Err(AppError::auth(
+                        StatusCode::FORBIDDEN,
+                        anyhow!("Prefix tokens must specify a docId that matches their prefix"),
+                        "wrong_token_type",
+                    ));
+                }
             }
-            Ok(axum::response::Redirect::temporary(&redirect_url).into_response())
+        } else {
+            return Err(AppError::auth(
+                StatusCode::UNAUTHORIZED,
+                anyhow!("No token provided"),
+                "missing_token",
+            ));
         }
-        None => Err(AppError::new(
-            StatusCode::NOT_FOUND,
-            anyhow!("No document found at path '{}'", path),
-        )),
     }
-}
 
-/// 503 until the given index has finished its startup build.
-fn require_index_ready(ready: &std::sync::atomic::AtomicBool, what: &str) -> Result<(), AppError> {
-    if ready.load(std::sync::atomic::Ordering::Acquire) {
-        Ok(())
+    let doc_id = if let Some(doc_id) = body.doc_id {
+        if !validate_doc_name(doc_id.as_str()) {
+            Err((StatusCode::BAD_REQUEST, anyhow!("Invalid document name")))?
+        }
+
+        server_state
+            .get_or_create_doc(doc_id.as_str())
+            .await
+            .map_err(|e| {
+                tracing::error!(?e, "Failed to create doc");
+                (StatusCode::INTERNAL_SERVER_ERROR, e)
+            })?;
+
+        doc_id
     } else {
-        Err(AppError::new(
-            StatusCode::SERVICE_UNAVAILABLE,
-            anyhow!("{} is being built, please try again shortly", what),
-        ))
-    }
+        server_state.create_doc().await.map_err(|d| {
+            tracing::error!(?d, "Failed to create doc");
+            (StatusCode::INTERNAL_SERVER_ERROR, d)
+        })?
+    };
+
+    Ok(Json(NewDocResponse { doc_id }))
 }
 
-async fn handle_search(
-    auth_header: Option<TypedHeader<headers::Authorization<headers::authorization::Bearer>>>,
-    State(server_state): State<Arc<Server>>,
-    Query(params): Query<SearchQuery>,
-) -> Result<Json<Value>, AppError> {
-    server_state.check_auth(auth_header)?;
-    require_index_ready(&server_state.search_ready, "Search index")?;
+fn generate_base_url(
+    url: &Option<Url>,
+    allowed_hosts: &[AllowedHost],
+    request_host: &str,
+) -> Result<String, AppError> {
+    // Priority 1: Explicit URL prefix
+    if let Some(prefix) = url {
+        return Ok(prefix.as_str().trim_end_matches('/').to_string());
+    }
 
-    let limit = params.limit.min(100); // Cap at 100
-    let q = params.q.trim().to_string();
+    // Priority 2: Context-derived URL from Host header
+    if let Some(allowed) = allowed_hosts.iter().find(|h| h.host == request_host) {
+        return Ok(format!("{}://{}", allowed.scheme, request_host));
+    }
 
-    if q.is_empty() {
-        return Ok(Json(json!({
-            "results": [],
-            "total_hits": 0,
-            "query": ""
-        })));
+    // Priority 3: Fallback to old behavior for backward compatibility
+    if allowed_hosts.is_empty() {
+        return Ok(format!("http://{}", request_host));
     }
 
-    let search_index = server_state.search_index.clone().ok_or_else(|| {
-        AppError::new(
-            StatusCode::SERVICE_UNAVAILABLE,
-            anyhow!("Search index not available"),
-        )
-    })?;
+    // Reject unknown hosts when allowed_hosts is configured
+    Err(AppError::new(
+        StatusCode::BAD_REQUEST,
+        anyhow!("Host '{}' not in allowed hosts list", request_host),
+    ))
+}
 
-    // Run search in blocking context (tantivy is sync)
-    let results = tokio::task::spawn_blocking(move || search_index.search(&q, limit))
-        .await
-        .map_err(|e| AppError::new(StatusCode::INTERNAL_SERVER_ERROR, e.into()))?
-        .map_err(|e| AppError::new(StatusCode::INTERNAL_SERVER_ERROR, e))?;
+fn generate_context_aware_urls(
+    url: &Option<Url>,
+    allowed_hosts: &[AllowedHost],
+    request_host: &str,
+    doc_id: &str,
+) -> Result<(String, String), AppError> {
+    // Priority 1: Explicit URL prefix
+    if let Some(prefix) = url {
+        let ws_scheme = if prefix.scheme() == "https" {
+            "wss"
+        } else {
+            "ws"
+        };
+        let mut ws_url = prefix.clone();
+        ws_url.set_scheme(ws_scheme).unwrap();
+        let ws_url = ws_url
+            .join(&format!("/d/{}/ws", doc_id))
+            .unwrap()
+            .to_string();
 
-    let total_hits = results.len();
-    Ok(Json(json!({
-        "results": results,
-        "total_hits": total_hits,
-        "query": params.q
-    })))
-}
+        let base_url = format!("{}/d/{}", prefix.as_str().trim_end_matches('/'), doc_id);
+        return Ok((ws_url, base_url));
+    }
 
-async fn handle_folder_name(
-    auth_header: Option<TypedHeader<headers::Authorization<headers::authorization::Bearer>>>,
-    State(server_state): State<Arc<Server>>,
-    Path(folder_uuid): Path<String>,
-) -> Result<Json<Value>, AppError> {
-    server_state.check_auth(auth_header)?;
-    match server_state.folder_name_for_uuid(&folder_uuid) {
-        Some(name) => Ok(Json(json!({ "name": name }))),
-        None => Err(AppError::new(
-            StatusCode::NOT_FOUND,
-            anyhow!("Folder '{}' not found", folder_uuid),
-        )),
+    // Priority 2: Context-derived URL from Host header
+    if let Some(allowed) = allowed_hosts.iter().find(|h| h.host == request_host) {
+        let ws_scheme = if allowed.scheme == "https" {
+            "wss"
+        } else {
+            "ws"
+        };
+        let ws_url = format!("{}://{}/d/{}/ws", ws_scheme, request_host, doc_id);
+        let base_url = format!("{}://{}/d/{}", allowed.scheme, request_host, doc_id);
+        return Ok((ws_url, base_url));
     }
+
+    // Priority 3: Fallback to old behavior for backward compatibility
+    // This handles the case where no URL prefix and no allowed hosts are set
+    if allowed_hosts.is_empty() {
+        let ws_url = format!("ws://{}/d/{}/ws", request_host, doc_id);
+        let base_url = format!("http://{}/d/{}", request_host, doc_id);
+        return Ok((ws_url, base_url));
+    }
+
+    // Reject unknown hosts when allowed_hosts is configured
+    Err(AppError::new(
+        StatusCode::BAD_REQUEST,
+        anyhow!("Host '{}' not in allowed hosts list", request_host),
+    ))
 }
 
-/// List CriticMarkup suggestions for all documents in a folder.
-///
-/// GET /suggestions?folder_id=...
-/// Response: { "files": [{ "path": "...", "doc_id": "...", "suggestions": [...] }] }
-///
-/// Answers from the in-memory suggestions index — it must NOT load content
-/// docs on demand. The previous per-request full-folder scan loaded every doc
-/// from storage and triggered the 2026-07-02 prod hang (see
-/// docs/plans/2026-07-02-suggestions-index.md).
-async fn handle_suggestions(
+async fn auth_doc(
     auth_header: Option<TypedHeader<headers::Authorization<headers::authorization::Bearer>>>,
+    TypedHeader(host): TypedHeader<headers::Host>,
     State(server_state): State<Arc<Server>>,
-    Query(params): Query<SuggestionsQuery>,
-) -> Result<Json<Value>, AppError> {
+    Path(doc_id): Path<String>,
+    body: Option<Json<AuthDocRequest>>,
+) -> Result<Json<ClientToken>, AppError> {
     server_state.check_auth(auth_header)?;
-    require_index_ready(&server_state.suggestions_ready, "Suggestions index")?;
 
-    let folder_id = &params.folder_id;
+    let Json(AuthDocRequest {
+        authorization,
+        user_id,
+        valid_for_seconds,
+    }) = body.unwrap_or_default();
 
-    // Load the folder doc and get content UUIDs from filemeta_v0
-    server_state
-        .ensure_doc_loaded(folder_id)
-        .await
-        .map_err(|e| AppError::new(StatusCode::NOT_FOUND, anyhow!("Folder not found: {}", e)))?;
+    if !server_state.doc_exists(&doc_id).await {
+        Err((StatusCode::NOT_FOUND, anyhow!("Doc {} not found", doc_id)))?;
+    }
+    let authorization =
+        server_state.authorize_doc_acl(&doc_id, user_id.as_deref(), authorization)?;
 
-    let content_uuids = link_indexer::is_folder_doc(folder_id, &server_state.docs)
-        .ok_or_else(|| AppError::new(StatusCode::NOT_FOUND, anyhow!("Not a folder document")))?;
+    let valid_for_seconds = valid_for_seconds.unwrap_or(DEFAULT_EXPIRATION_SECONDS);
+    let expiration_time =
+        ExpirationTimeEpochMillis(current_time_epoch_millis() + valid_for_seconds * 1000);
 
-    // Get path mapping from filemeta_v0
-    let path_map = {
-        let doc_ref = server_state.docs.get(folder_id).ok_or_else(|| {
-            AppError::new(StatusCode::NOT_FOUND, anyhow!("Folder doc not loaded"))
-        })?;
-        let awareness = doc_ref.awareness();
-        let guard = awareness.read().unwrap_or_else(|e| e.into_inner());
-        let txn = guard.doc.transact();
-        let filemeta = txn
-            .get_map("filemeta_v0")
-            .ok_or_else(|| AppError::new(StatusCode::NOT_FOUND, anyhow!("No filemeta_v0")))?;
-        let mut map = std::collections::HashMap::new();
-        for (path, value) in filemeta.iter(&txn) {
-            if let Some(id) = link_indexer::extract_id_from_filemeta_entry(&value, &txn) {
-                map.insert(id, path.to_string());
-            }
-        }
-        map
+    let token = if let Some(auth) = &server_state.authenticator {
+        let token = auth
+            .gen_doc_token_auto(&doc_id, authorization, expiration_time, None)
+            .map_err(|e| {
+                AppError::new(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    anyhow!("Failed to generate token: {}", e),
+                )
+            })?;
+        Some(token)
+    } else {
+        None
     };
 
-    // relay_id = first 36 chars of compound folder_id
-    if folder_id.len() < 36 {
-        return Err(AppError::new(
-            StatusCode::BAD_REQUEST,
-            anyhow!("Invalid folder_id"),
-        ));
-    }
-    let relay_id = &folder_id[..36];
+    let (url, base_url) = generate_context_aware_urls(
+        &server_state.url,
+        &server_state.allowed_hosts,
+        &host.to_string(),
+        &doc_id,
+    )?;
+
+    Ok(Json(ClientToken {
+        url,
+        base_url: Some(base_url),
+        doc_id,
+        token,
+        authorization,
+    }))
+}
+
+#[derive(Deserialize)]
+struct DiscordCallbackQuery {
+    code: Option<String>,
+    state: Option<String>,
+    /// Set by Discord when the user declines.
+    error: Option<String>,
+}
 
-    let mut files = Vec::new();
+/// HTTP status for a failed Discord login.
+fn discord_login_error(e: crate::discord_auth::LoginError) -> AppError {
+    use crate::discord_auth::LoginError;
 
-    // Stale index entries for docs deleted from the folder are filtered
-    // naturally: only UUIDs currently in filemeta_v0 are consulted.
-    for content_uuid in &content_uuids {
-        let Some(suggestions) = server_state.suggestions_index.get(content_uuid) else {
-            continue;
-        };
-        let doc_id = format!("{}-{}", relay_id, content_uuid);
-        let path = path_map
-            .get(content_uuid)
-            .cloned()
-            .unwrap_or_else(|| content_uuid.clone());
+    let status = match &e {
+        LoginError::InvalidState => StatusCode::BAD_REQUEST,
+        LoginError::Rejected(_) => StatusCode::UNAUTHORIZED,
+        LoginError::NotMember => StatusCode::FORBIDDEN,
+        LoginError::Upstream(_) => StatusCode::BAD_GATEWAY,
+    };
+    AppError::new(status, anyhow!(e.to_string()))
+}
 
-        files.push(serde_json::json!({
-            "path": path,
-            "doc_id": doc_id,
-            "suggestions": suggestions,
-        }));
-    }
+/// `Set-Cookie` value for the login state cookie; an empty `state` clears it.
+fn discord_state_cookie(discord: &crate::discord_auth::DiscordAuth, state: &str) -> String {
+    use crate::discord_auth::{STATE_COOKIE, STATE_TTL};
 
-    Ok(Json(serde_json::json!({ "files": files })))
+    let max_age = if state.is_empty() {
+        0
+    } else {
+        STATE_TTL.as_secs()
+    };
+    let secure = if discord.config().redirect_uri.starts_with("https://") {
+        "; Secure"
+    } else {
+        ""
+    };
+    format!(
+        "{}={}; Path=/auth/discord; Max-Age={}; HttpOnly; SameSite=Lax{}",
+        STATE_COOKIE, state, max_age, secure
+    )
 }
 
-#[derive(serde::Deserialize)]
-struct ApplySuggestionsRequest {
-    doc_id: String,
-    action: String,
-    /// Wire format shared with the GET /suggestions response; only
-    /// `raw_markup`, `type`, `content`, `old_content`, `new_content` are
-    /// consulted (the rest default).
-    suggestions: Vec<critic_scanner::Suggestion>,
+/// The live Discord session named by the bearer header.
+fn discord_session_from_header(
+    server_state: &Server,
+    auth_header: Option<TypedHeader<headers::Authorization<headers::authorization::Bearer>>>,
+) -> Result<crate::discord_auth::DiscordSession, AppError> {
+    let discord = server_state.discord_auth()?;
+    auth_header
+        .and_then(|TypedHeader(header)| discord.session(header.token()))
+        .ok_or_else(|| {
+            AppError::auth(
+                StatusCode::UNAUTHORIZED,
+                anyhow!("Missing or expired Discord session"),
+                "invalid_session",
+            )
+        })
 }
 
-/// Apply accept/reject to a batch of suggestions in one document, server-side.
-///
-/// POST /suggestions/apply?folder_id=<compound folder doc id>
-/// Body: { doc_id, action: "accept"|"reject", suggestions: [...] }
-/// Response: { applied: [idx], failed: [{index, reason}], remaining_suggestions }
+/// Start a Discord login.
 ///
-/// This replaces the review page's per-file client-side flow (browser opening
-/// every Y.Doc over websocket — unusable at thousands of suggestions across
-/// hundreds of files). folder_id rides in the query so the editor proxy can
-/// scope-check folder tokens without buffering the body; the doc must belong
-/// to that folder. All ops for one doc commit in a single transaction,
-/// deletions are surgical (kept payload keeps its original authorship), and
-/// the suggestions index is updated under the same awareness write lock the
-/// edit commits under, so a racing worker scan can never overwrite it with
-/// pre-apply state and the next refresh is read-your-writes.
-async fn handle_apply_suggestions(
-    auth_header: Option<TypedHeader<headers::Authorization<headers::authorization::Bearer>>>,
+/// GET /auth/discord/login
+/// Sets the state cookie and redirects to Discord's consent page, which
+/// redirects back to /auth/discord/callback.
+async fn handle_discord_login(
     State(server_state): State<Arc<Server>>,
-    Query(params): Query<SuggestionsQuery>,
-    Json(req): Json<ApplySuggestionsRequest>,
-) -> Result<Json<Value>, AppError> {
-    use y_sweet_core::critic_surgical::{plan_batch, SuggestionAction};
-
-    server_state.check_auth(auth_header)?;
-    require_index_ready(&server_state.suggestions_ready, "Suggestions index")?;
-
-    let action = match req.action.as_str() {
-        "accept" => SuggestionAction::Accept,
-        "reject" => SuggestionAction::Reject,
-        other => {
-            return Err(AppError::new(
-                StatusCode::BAD_REQUEST,
-                anyhow!("Invalid action: {}", other),
-            ))
-        }
-    };
+) -> Result<Response, AppError> {
+    let discord = server_state.discord_auth()?;
+    let pending = discord.authorize_url().map_err(discord_login_error)?;
+    Ok((
+        [(
+            axum::http::header::SET_COOKIE,
+            discord_state_cookie(discord, &pending.state),
+        )],
+        axum::response::Redirect::temporary(&pending.url),
+    )
+        .into_response())
+}
 
-    let Some((_relay_id, doc_uuid)) = link_indexer::parse_doc_id(&req.doc_id) else {
+/// Finish a Discord login: check the state against the browser's cookie,
+/// exchange the code and send the browser to the app with the session.
+///
+/// GET /auth/discord/callback?code=...&state=...
+/// Redirects to RELAY_DISCORD_APP_URL#session=...&expires_in=...
+async fn handle_discord_callback(
+    cookies: Option<TypedHeader<headers::Cookie>>,
+    State(server_state): State<Arc<Server>>,
+    Query(params): Query<DiscordCallbackQuery>,
+) -> Result<Response, AppError> {
+    let discord = server_state.discord_auth()?;
+    if let Some(error) = params.error {
+        return Err(AppError::new(
+            StatusCode::UNAUTHORIZED,
+            anyhow!("Discord login was cancelled: {}", error),
+        ));
+    }
+    let (Some(code), Some(state)) = (params.code, params.state) else {
         return Err(AppError::new(
             StatusCode::BAD_REQUEST,
-            anyhow!("Invalid doc_id"),
+            anyhow!("Missing code or state"),
         ));
     };
-    let doc_uuid = doc_uuid.to_string();
-
-    // ACL: the doc must belong to the given folder (folder-scoped share
-    // tokens are checked against the folder_id query by the editor proxy;
-    // this makes the pair internally consistent).
-    server_state
-        .ensure_doc_loaded(&params.folder_id)
+    let browser_state = cookies
+        .as_ref()
+        .and_then(|TypedHeader(cookies)| cookies.get(crate::discord_auth::STATE_COOKIE));
+    let mapping = match &server_state.store {
+        Some(store) => crate::discord_auth::load_role_mapping(&***store)
+            .await
+            .map_err(|e| AppError::new(StatusCode::INTERNAL_SERVER_ERROR, e))?,
+        None => Default::default(),
+    };
+    let (session_id, session) = discord
+        .login(&state, browser_state, &code, &mapping)
         .await
-        .map_err(|e| AppError::new(StatusCode::NOT_FOUND, anyhow!("Folder not found: {}", e)))?;
-    let folder_members = link_indexer::is_folder_doc(&params.folder_id, &server_state.docs)
-        .ok_or_else(|| AppError::new(StatusCode::NOT_FOUND, anyhow!("Not a folder document")))?;
-    if !folder_members.contains(&doc_uuid) {
-        return Err(AppError::new(
-            StatusCode::FORBIDDEN,
-            anyhow!("Document is not part of the given folder"),
-        ));
-    }
+        .map_err(discord_login_error)?;
+    tracing::info!(
+        user = %session.identity.user_id(),
+        username = %session.identity.username,
+        "Discord login"
+    );
 
-    server_state
-        .ensure_doc_loaded(&req.doc_id)
-        .await
-        .map_err(|e| AppError::new(StatusCode::NOT_FOUND, anyhow!("Doc not found: {}", e)))?;
+    let location = discord
+        .app_redirect(&session_id)
+        .map_err(|e| AppError::new(StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    Ok((
+        [(
+            axum::http::header::SET_COOKIE,
+            discord_state_cookie(discord, ""),
+        )],
+        axum::response::Redirect::to(&location),
+    )
+        .into_response())
+}
 
-    let awareness = {
-        let Some(doc_ref) = server_state.docs.get(&req.doc_id) else {
-            return Err(AppError::new(
-                StatusCode::NOT_FOUND,
-                anyhow!("Doc not loaded"),
-            ));
+/// The signed-in Discord member and a doc token for each folder doc they
+/// can open.
+///
+/// GET /auth/discord/session (Authorization: Bearer <session>)
+/// Response: { expires_in, user: { id, username, global_name, user_id },
+///             folders: [{ folder, folder_id, role, authorization, token }] }
+async fn handle_discord_session(
+    auth_header: Option<TypedHeader<headers::Authorization<headers::authorization::Bearer>>>,
+    State(server_state): State<Arc<Server>>,
+) -> Result<Json<Value>, AppError> {
+    let session = discord_session_from_header(&server_state, auth_header)?;
+    let user_id = session.identity.user_id();
+    let mut folders = Vec::new();
+    for folder_doc_id in link_indexer::find_all_folder_docs(&server_state.docs) {
+        let Some((_, folder_name)) = server_state.folder_of_doc(&folder_doc_id) else {
+            continue;
         };
-        doc_ref.awareness() // Arc clone
-    }; // DashMap shard lock released
-
-    // Plan + apply + rescan under one awareness write guard: the plan is
-    // computed against the exact body the transaction mutates, and the index
-    // update commits before any other writer can read the doc. Everything in
-    // here is O(body + items) — see critic_surgical::plan_batch.
-    let (applied, failed, remaining_suggestions) = {
-        let guard = awareness.write().unwrap_or_else(|e| e.into_inner());
-        let body = {
-            let txn = guard.doc.transact();
-            match txn.get_text("contents") {
-                Some(text) => text.get_string(&txn),
-                None => String::new(),
-            }
+        let Some((_, folder_uuid)) = link_indexer::parse_doc_id(&folder_doc_id) else {
+            continue;
         };
-
-        let (ops, failures) = plan_batch(&body, &req.suggestions, action);
-        let applied: Vec<usize> = {
-            let mut idx: Vec<usize> = ops.iter().map(|op| op.index).collect();
-            idx.sort_unstable();
-            idx
+        let Some(role) = session.role_for_folder(&[folder_name.as_str(), folder_uuid]) else {
+            continue;
         };
-        let failed: Vec<Value> = failures
-            .iter()
-            .map(|f| serde_json::json!({"index": f.index, "reason": f.reason}))
-            .collect();
+        let Some(authorization) = role.cap(Authorization::Full) else {
+            continue;
+        };
+        let Ok(authorization) =
+            server_state.authorize_doc_acl(&folder_doc_id, Some(&user_id), authorization)
+        else {
+            continue;
+        };
+        let token = server_state.mint_user_doc_token(&folder_doc_id, authorization, &user_id)?;
+        folders.push(json!({
+            "folder": folder_name,
+            "folder_id": folder_doc_id,
+            "role": role,
+            "authorization": authorization,
+            "token": token,
+        }));
+    }
 
-        {
-            let mut txn = guard.doc.transact_mut();
-            let text = txn.get_or_insert_text("contents");
-            for op in &ops {
-                debug_assert!(
-                    op.deletions.windows(2).all(|w| w[0].from >= w[1].to),
-                    "plan_batch must yield descending, non-overlapping deletions"
-                );
-                for d in &op.deletions {
-                    text.remove_range(&mut txn, d.from as u32, (d.to - d.from) as u32);
-                }
-                if let Some(insert) = &op.fallback_insert {
-                    if !insert.is_empty() {
-                        text.insert(&mut txn, op.span_start as u32, insert);
-                    }
-                }
-            }
-        }
+    Ok(Json(json!({
+        "expires_in": session
+            .expires_at
+            .saturating_duration_since(std::time::Instant::now())
+            .as_secs(),
+        "user": {
+            "id": session.identity.id,
+            "username": session.identity.username,
+            "global_name": session.identity.global_name,
+            "user_id": user_id,
+        },
+        "folders": folders,
+    })))
+}
 
-        let new_body = {
-            let txn = guard.doc.transact();
-            match txn.get_text("contents") {
-                Some(text) => text.get_string(&txn),
-                None => String::new(),
-            }
-        };
-        let suggestions = critic_scanner::scan_suggestions(&new_body);
-        let remaining = suggestions.len();
-        server_state
-            .suggestions_index
-            .update(&doc_uuid, suggestions);
-        (applied, failed, remaining)
-    }; // awareness write released
+/// Mint a doc token for a signed-in Discord member, at the role their guild
+/// roles give them in the doc's folder, capped by the ACL.
+///
+/// POST /auth/discord/doc/:doc_id (Authorization: Bearer <session>)
+/// Response: the same shape as POST /doc/:doc_id/auth.
+async fn handle_discord_doc_token(
+    auth_header: Option<TypedHeader<headers::Authorization<headers::authorization::Bearer>>>,
+    TypedHeader(host): TypedHeader<headers::Host>,
+    State(server_state): State<Arc<Server>>,
+    Path(doc_id): Path<String>,
+) -> Result<Json<ClientToken>, AppError> {
+    let session = discord_session_from_header(&server_state, auth_header)?;
+    let user_id = session.identity.user_id();
 
-    // Heavy derived state (tantivy, backlinks) flows through the normal
-    // worker queues via the update callback that just fired. Persist
-    // explicitly for immediate durability (same policy as the MCP edit tool).
-    let sync_kv = server_state
-        .docs
-        .get(&req.doc_id)
-        .map(|doc_ref| doc_ref.sync_kv());
-    if let Some(sync_kv) = sync_kv {
-        if let Err(e) = sync_kv.persist().await {
-            tracing::error!(doc_id = %req.doc_id, "Persist after suggestion apply failed: {:?}", e);
-        }
-    }
+    let (folder_doc_id, folder_name) = server_state.folder_of_doc(&doc_id).ok_or_else(|| {
+        AppError::new(
+            StatusCode::NOT_FOUND,
+            anyhow!("Doc {} is not in a known folder", doc_id),
+        )
+    })?;
+    let folder_uuid = link_indexer::parse_doc_id(&folder_doc_id)
+        .map(|(_, uuid)| uuid)
+        .unwrap_or_default();
+    let authorization = session
+        .role_for_folder(&[folder_name.as_str(), folder_uuid])
+        .and_then(|role| role.cap(Authorization::Full))
+        .ok_or_else(|| {
+            AppError::auth(
+                StatusCode::FORBIDDEN,
+                anyhow!("Your Discord roles give no access to '{}'", folder_name),
+                "no_folder_role",
+            )
+        })?;
+    let authorization = server_state.authorize_doc_acl(&doc_id, Some(&user_id), authorization)?;
+    let token = server_state.mint_user_doc_token(&doc_id, authorization, &user_id)?;
 
-    tracing::info!(
-        doc_id = %req.doc_id,
-        applied = applied.len(),
-        failed = failed.len(),
-        remaining = remaining_suggestions,
-        "Applied suggestion batch"
-    );
+    let (url, base_url) = generate_context_aware_urls(
+        &server_state.url,
+        &server_state.allowed_hosts,
+        &host.to_string(),
+        &doc_id,
+    )?;
 
-    Ok(Json(serde_json::json!({
-        "applied": applied,
-        "failed": failed,
-        "remaining_suggestions": remaining_suggestions,
-    })))
+    Ok(Json(ClientToken {
+        url,
+        base_url: Some(base_url),
+        doc_id,
+        token,
+        authorization,
+    }))
 }
 
-/// Move a document to a new path within or across folders.
+/// End a Discord session. Tokens already minted stay valid until they
+/// expire.
 ///
-/// POST /doc/move
-/// Body: { "uuid": "...", "new_path": "/Biology/Photosynthesis.md", "target_folder": "Lens Edu" }
-/// Response: { "old_path", "new_path", "old_folder", "new_folder", "links_rewritten" }
-async fn handle_move_document(
+/// POST /auth/discord/logout (Authorization: Bearer <session>)
+async fn handle_discord_logout(
     auth_header: Option<TypedHeader<headers::Authorization<headers::authorization::Bearer>>>,
     State(server_state): State<Arc<Server>>,
-    Json(body): Json<MoveDocRequest>,
-) -> Result<Json<MoveDocResponse>, AppError> {
+) -> Result<StatusCode, AppError> {
+    let discord = server_state.discord_auth()?;
+    if let Some(TypedHeader(header)) = auth_header {
+        discord.logout(header.token());
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn resolve_doc(
+    auth_header: Option<TypedHeader<headers::Authorization<headers::authorization::Bearer>>>,
+    State(server_state): State<Arc<Server>>,
+    Path(prefix): Path<String>,
+) -> Result<Json<serde_json::Value>, AppError> {
     server_state.check_auth(auth_header)?;
-    let result = server_state
-        .move_document(&body.uuid, &body.new_path, body.target_folder.as_deref())
-        .await
-        .map_err(AppError::from)?;
 
-    Ok(Json(MoveDocResponse {
-        old_path: result.old_path,
-        new_path: result.new_path,
-        old_folder: result.old_folder_name,
-        new_folder: result.new_folder_name,
-        links_rewritten: result.links_rewritten,
-    }))
+    match server_state.resolve_doc_id(&prefix).await {
+        Some(doc_id) => Ok(Json(serde_json::json!({ "docId": doc_id }))),
+        None => Err(AppError::new(
+            StatusCode::NOT_FOUND,
+            anyhow!("No unique doc matching prefix '{}'", prefix),
+        )),
+    }
 }
 
-/// Move a document or rename a folder by user-facing path.
-///
-/// POST /move
-/// Body: { "path": "Lens/Old.md", "new_path": "/New.md", "target_folder": "Lens Edu" }
-/// Response: { "old_path", "new_path", "old_folder", "new_folder", "links_rewritten" }
-async fn handle_move_path(
+async fn get_doc_folder(
     auth_header: Option<TypedHeader<headers::Authorization<headers::authorization::Bearer>>>,
     State(server_state): State<Arc<Server>>,
-    Json(body): Json<MovePathRequest>,
-) -> Result<Json<MoveDocResponse>, AppError> {
+    Path(doc_id): Path<String>,
+) -> Result<Json<serde_json::Value>, AppError> {
     server_state.check_auth(auth_header)?;
-    let result = server_state
-        .move_path(&body.path, &body.new_path, body.target_folder.as_deref())
-        .await
-        .map_err(|e| {
-            // Move failures are returned to the client with an empty body, so
-            // without this they are invisible in the logs.
-            tracing::warn!(
-                path = %body.path,
-                new_path = %body.new_path,
-                target_folder = ?body.target_folder,
-                error = ?e,
-                "move_path request failed"
-            );
-            AppError::from(e)
-        })?;
 
-    Ok(Json(MoveDocResponse {
-        old_path: result.old_path,
-        new_path: result.new_path,
-        old_folder: result.old_folder_name,
-        new_folder: result.new_folder_name,
-        links_rewritten: result.links_rewritten,
-    }))
-}
+    // Extract the UUID portion from compound doc_id (relay_id-uuid)
+    let uuid = link_indexer::parse_doc_id(&doc_id)
+        .map(|(_, uuid)| uuid)
+        .ok_or_else(|| AppError::new(StatusCode::BAD_REQUEST, anyhow!("Invalid doc_id format")))?;
 
-/// POST /doc/upsert
-/// Creates a document if it doesn't exist, or replaces its content if it does.
-/// No CriticMarkup wrapping — content is written directly to Y.Text.
-/// Accepts any file extension; uses "file" type for non-.md, "markdown" for .md.
-#[derive(Deserialize)]
-struct UpsertDocRequest {
-    folder: String,
-    path: String,
-    content: String,
+    match server_state.doc_resolver().folder_uuid_for_doc(uuid) {
+        Some(folder_uuid) => Ok(Json(serde_json::json!({ "folderUuid": folder_uuid }))),
+        None => Err(AppError::new(
+            StatusCode::NOT_FOUND,
+            anyhow!("Document not found in any folder"),
+        )),
+    }
 }
 
-#[derive(Serialize)]
-struct UpsertDocResponse {
-    doc_id: String,
+#[derive(Deserialize)]
+struct DebugResolveQuery {
     path: String,
-    created: bool,
 }
 
-async fn handle_upsert_document(
+/// GET /debug/resolve?path=<folder>/<subpath>/<name>.md
+///
+/// Read-only diagnostic comparing the eventually-consistent `doc_resolver`
+/// against `filemeta_v0` (the source of truth) for a single path. `stale: true`
+/// means the file exists in filemeta but the resolver cannot resolve it at this
+/// path — the condition that makes a rename fail with "Move failed: 400".
+/// `resolver_path_for_uuid` shows where the resolver currently thinks the doc
+/// lives (None = missing entirely; a different path = stale entry).
+async fn handle_debug_resolve(
     auth_header: Option<TypedHeader<headers::Authorization<headers::authorization::Bearer>>>,
     State(server_state): State<Arc<Server>>,
-    Json(body): Json<UpsertDocRequest>,
-) -> Result<Json<UpsertDocResponse>, AppError> {
+    Query(params): Query<DebugResolveQuery>,
+) -> Result<Json<serde_json::Value>, AppError> {
     server_state.check_auth(auth_header)?;
 
-    // Ensure path starts with /
-    let path = if body.path.starts_with('/') {
-        body.path.clone()
-    } else {
-        format!("/{}", body.path)
-    };
+    let path = params.path;
+    let resolver_hit = server_state.doc_resolver().resolve_path(&path);
+    let filemeta_hit = server_state.resolve_path_via_filemeta(&path);
+    let resolver_path_for_uuid = filemeta_hit
+        .as_ref()
+        .and_then(|i| server_state.doc_resolver().path_for_uuid(&i.uuid));
 
-    let is_blob = path.to_ascii_lowercase().ends_with(".json");
+    Ok(Json(serde_json::json!({
+        "path": path,
+        "resolver_hit": resolver_hit.is_some(),
+        "resolver_uuid": resolver_hit.as_ref().map(|i| i.uuid.clone()),
+        "in_filemeta": filemeta_hit.is_some(),
+        "filemeta_uuid": filemeta_hit.as_ref().map(|i| i.uuid.clone()),
+        "filemeta_folder": filemeta_hit.as_ref().map(|i| i.folder_name.clone()),
+        "resolver_path_for_uuid": resolver_path_for_uuid,
+        "stale": filemeta_hit.is_some() && resolver_hit.is_none(),
+    })))
+}
 
-    if is_blob {
-        // JSON files → blob storage (create-only, no updates)
-        match server_state
-            .create_blob_file(
-                &body.folder,
-                &path,
-                body.content.as_bytes(),
-                "application/json",
-            )
-            .await
-        {
-            Ok(result) => Ok(Json(UpsertDocResponse {
-                doc_id: result.full_doc_id,
-                path: format!("{}{}", body.folder, path),
-                created: true,
-            })),
-            Err(CreateDocumentError::Conflict(msg)) => {
-                Err(AppError::new(StatusCode::CONFLICT, anyhow!("{}", msg)))
-            }
-            Err(CreateDocumentError::NotFound(msg)) => {
-                Err(AppError::new(StatusCode::NOT_FOUND, anyhow!("{}", msg)))
-            }
-            Err(CreateDocumentError::BadRequest(msg)) => {
-                Err(AppError::new(StatusCode::BAD_REQUEST, anyhow!("{}", msg)))
-            }
-            Err(e) => Err(AppError::new(
-                StatusCode::INTERNAL_SERVER_ERROR,
-                anyhow!("{}", e),
-            )),
-        }
+fn get_token_from_header(
+    auth_header: Option<TypedHeader<headers::Authorization<headers::authorization::Bearer>>>,
+) -> Option<String> {
+    if let Some(TypedHeader(headers::Authorization(bearer))) = auth_header {
+        Some(bearer.token().to_string())
     } else {
-        // Markdown/other → Y.Doc (existing behavior with upsert semantics)
-        match server_state
-            .create_document_direct(&body.folder, &path, &body.content, None)
-            .await
-        {
-            Ok(result) => Ok(Json(UpsertDocResponse {
-                doc_id: result.full_doc_id,
-                path: format!("{}{}", body.folder, path),
-                created: true,
-            })),
-            Err(CreateDocumentError::Conflict(_)) => {
-                // Already exists — update content instead
-                server_state
-                    .write_document_content(&body.folder, &path, &body.content)
-                    .await
-                    .map_err(|e| {
-                        AppError::new(StatusCode::INTERNAL_SERVER_ERROR, anyhow!("{}", e))
-                    })?;
-                Ok(Json(UpsertDocResponse {
-                    doc_id: String::new(),
-                    path: format!("{}{}", body.folder, path),
-                    created: false,
-                }))
-            }
-            Err(CreateDocumentError::NotFound(msg)) => {
-                Err(AppError::new(StatusCode::NOT_FOUND, anyhow!("{}", msg)))
-            }
-            Err(CreateDocumentError::BadRequest(msg)) => {
-                Err(AppError::new(StatusCode::BAD_REQUEST, anyhow!("{}", msg)))
-            }
-            Err(e) => Err(AppError::new(
-                StatusCode::INTERNAL_SERVER_ERROR,
-                anyhow!("{}", e),
-            )),
-        }
+        None
     }
 }
 
-#[derive(Deserialize)]
-struct CheckDocsRequest {
-    folder: String,
-    paths: Vec<String>,
-}
-
-#[derive(Serialize)]
-struct CheckDocsResponse {
-    exists: std::collections::HashMap<String, bool>,
-}
-
-async fn handle_check_documents(
-    auth_header: Option<TypedHeader<headers::Authorization<headers::authorization::Bearer>>>,
+async fn handle_file_upload_url(
     State(server_state): State<Arc<Server>>,
-    Json(body): Json<CheckDocsRequest>,
-) -> Result<Json<CheckDocsResponse>, AppError> {
-    server_state.check_auth(auth_header)?;
+    Path(doc_id): Path<String>,
+    TypedHeader(host): TypedHeader<headers::Host>,
+    Query(params): Query<FileUploadQueryParams>,
+    auth_header: Option<TypedHeader<headers::Authorization<headers::authorization::Bearer>>>,
+) -> Result<Json<FileUploadUrlResponse>, AppError> {
+    tracing::info!(doc_id = %doc_id, "Generating file upload URL");
 
-    let docs = server_state.docs();
-    let folder_doc_ids = link_indexer::find_all_folder_docs(docs);
+    let token = get_token_from_header(auth_header);
 
-    // Find the matching folder doc
-    let mut folder_doc_id: Option<String> = None;
-    for fid in &folder_doc_ids {
-        let awareness = {
-            let Some(doc_ref) = docs.get(fid) else {
-                continue;
-            };
-            doc_ref.awareness()
-        };
-        let guard = awareness.read().unwrap_or_else(|e| e.into_inner());
-        let name = y_sweet_core::doc_resolver::read_folder_name(&guard.doc, fid);
-        if name == body.folder {
-            folder_doc_id = Some(fid.clone());
-            break;
+    // Local dev fast path: no authenticator configured → accept any upload, validate by hash only.
+    // Mirrors the unauthenticated blob-read route registered when authenticator.is_none().
+    if server_state.authenticator.is_none() {
+        let hash = params.hash.ok_or_else(|| {
+            AppError::new(
+                StatusCode::BAD_REQUEST,
+                anyhow!("hash query parameter required"),
+            )
+        })?;
+        if !validate_file_hash(&hash) {
+            return Err(AppError::new(
+                StatusCode::BAD_REQUEST,
+                anyhow!("Invalid file hash format"),
+            ));
         }
-    }
-
-    let Some(folder_doc_id) = folder_doc_id else {
-        return Err(AppError::new(
-            StatusCode::NOT_FOUND,
-            anyhow!("Unknown folder '{}'", body.folder),
-        ));
-    };
-
-    // Read filemeta_v0 once, check all paths
-    let awareness = {
-        let Some(doc_ref) = docs.get(&folder_doc_id) else {
+        let store = server_state.store.as_ref().ok_or_else(|| {
+            AppError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                anyhow!("No store configured for file uploads"),
+            )
+        })?;
+        let key = format!("files/{}/{}", doc_id, hash);
+        let upload_url = store
+            .generate_upload_url(&key, params.content_type.as_deref(), params.content_length)
+            .await
+            .map_err(|e| AppError::new(StatusCode::INTERNAL_SERVER_ERROR, e.into()))?;
+        let Some(url) = upload_url else {
             return Err(AppError::new(
                 StatusCode::INTERNAL_SERVER_ERROR,
-                anyhow!("Folder doc not loaded"),
+                anyhow!("Failed to generate upload URL"),
             ));
         };
-        doc_ref.awareness()
-    };
-    let guard = awareness.read().unwrap_or_else(|e| e.into_inner());
-    let txn = guard.doc.transact();
-
-    let mut exists = std::collections::HashMap::new();
-    let filemeta = txn.get_map("filemeta_v0");
-    for path in &body.paths {
-        let normalized = if path.starts_with('/') {
-            path.clone()
+        // For local filesystem store the URL is a relative path (/f/{doc}/upload).
+        // Return it as-is so the caller can route it through their own proxy without
+        // constructing an absolute http:// URL (which would cause mixed-content issues
+        // when the client is on https).
+        let response_url = if url.starts_with("http") {
+            url
         } else {
-            format!("/{}", path)
+            format!("{}?hash={}", url, hash)
         };
-        let found = filemeta
-            .as_ref()
-            .map(|fm| fm.get(&txn, normalized.as_str()).is_some())
-            .unwrap_or(false);
-        exists.insert(path.clone(), found);
+        return Ok(Json(FileUploadUrlResponse {
+            upload_url: response_url,
+        }));
     }
 
-    Ok(Json(CheckDocsResponse { exists }))
-}
-
-#[derive(Deserialize)]
-struct CheckVideoIdsRequest {
-    folder: String,
-    subfolder: Option<String>,
-    video_ids: Vec<String>,
-}
-
-#[derive(Serialize)]
-struct CheckVideoIdsResponse {
-    found: std::collections::HashMap<String, Option<String>>,
-}
-
-async fn handle_check_video_ids(
-    auth_header: Option<TypedHeader<headers::Authorization<headers::authorization::Bearer>>>,
-    State(server_state): State<Arc<Server>>,
-    Json(body): Json<CheckVideoIdsRequest>,
-) -> Result<Json<CheckVideoIdsResponse>, AppError> {
-    server_state.check_auth(auth_header)?;
+    let Some(authenticator) = &server_state.authenticator else {
+        unreachable!("authenticator checked above")
+    };
 
-    let prefix = match &body.subfolder {
-        Some(sub) => format!("{}/{}/", body.folder, sub),
-        None => format!("{}/", body.folder),
+    let Some(token) = token.as_deref() else {
+        return Err(AppError::auth(
+            StatusCode::UNAUTHORIZED,
+            anyhow!("No token provided"),
+            "missing_token",
+        ));
     };
 
-    let all_paths = server_state.doc_resolver().all_paths();
-    let matching_paths: Vec<String> = all_paths
-        .into_iter()
-        .filter(|p| p.starts_with(&prefix) && p.ends_with(".md"))
-        .collect();
+    let permission = authenticator
+        .verify_token_auto(token, current_time_epoch_millis())
+        .map_err(|_| {
+            AppError::auth(
+                StatusCode::UNAUTHORIZED,
+                anyhow!("Invalid token"),
+                "invalid_token",
+            )
+        })?;
 
-    let mut found: std::collections::HashMap<String, Option<String>> =
-        body.video_ids.iter().map(|id| (id.clone(), None)).collect();
+    // upload_token is the token that will be appended to the local upload URL.
+    // For file tokens we reuse the original; for server tokens we mint a new file token.
+    enum UploadTokenSource {
+        Original,
+        MintNew {
+            content_type: Option<String>,
+            content_length: Option<u64>,
+        },
+    }
 
-    for path in &matching_paths {
-        if found.values().all(|v| v.is_some()) {
-            break;
+    let (file_hash, content_type_owned, content_length, token_source, user) = match permission {
+        Permission::File(file_permission) => {
+            // File token must be for this doc and have Full permission
+            if file_permission.doc_id != doc_id {
+                return Err(AppError::auth(
+                    StatusCode::UNAUTHORIZED,
+                    anyhow!("Token not valid for this document"),
+                    "access_wrong_document",
+                ));
+            }
+            if !matches!(file_permission.authorization, Authorization::Full) {
+                return Err(AppError::auth(
+                    StatusCode::FORBIDDEN,
+                    anyhow!("Insufficient permissions to upload files"),
+                    "insufficient_permissions",
+                ));
+            }
+            (
+                file_permission.file_hash,
+                file_permission.content_type,
+                file_permission.content_length,
+                UploadTokenSource::Original,
+                file_permission.user,
+            )
         }
-
-        let doc_id = match server_state.doc_resolver().resolve_path(path) {
-            Some(doc_info) => doc_info.doc_id,
-            None => continue,
-        };
-
-        if server_state.ensure_doc_loaded(&doc_id).await.is_err() {
-            continue;
+        Permission::Server => {
+            // Server token: hash (and optional metadata) must come from query params
+            let hash = params.hash.ok_or_else(|| {
+                AppError::new(
+                    StatusCode::BAD_REQUEST,
+                    anyhow!("Hash query parameter required when using server token"),
+                )
+            })?;
+            let ct = params.content_type.clone();
+            let cl = params.content_length;
+            (
+                hash,
+                params.content_type,
+                params.content_length,
+                UploadTokenSource::MintNew {
+                    content_type: ct,
+                    content_length: cl,
+                },
+                None,
+            )
+        }
+        _ => {
+            return Err(AppError::new(
+                StatusCode::BAD_REQUEST,
+                anyhow!("Token type cannot be used for file uploads"),
+            ));
         }
+    };
 
-        let content = match read_doc_head(&server_state, &doc_id, 500) {
-            Some(c) => c,
-            None => continue,
-        };
+    if server_state.authorize_doc_acl(&doc_id, user.as_deref(), Authorization::Full)?
+        != Authorization::Full
+    {
+        return Err(AppError::auth(
+            StatusCode::FORBIDDEN,
+            anyhow!("The folder ACL does not allow uploads to this document"),
+            "acl_denied",
+        ));
+    }
 
-        let rel_path = format!("/{}", &path[body.folder.len()..].trim_start_matches('/'));
+    // Validate the file hash
+    if !validate_file_hash(&file_hash) {
+        return Err(AppError::new(
+            StatusCode::BAD_REQUEST,
+            anyhow!("Invalid file hash format"),
+        ));
+    }
 
-        for (video_id, slot) in found.iter_mut() {
-            if slot.is_none()
-                && (content.contains(&format!("watch?v={}", video_id))
-                    || content.contains(&format!("/shorts/{}", video_id)))
-            {
-                *slot = Some(rel_path.clone());
-            }
-        }
+    // Check if we have a store configured
+    if server_state.store.is_none() {
+        return Err(AppError::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            anyhow!("No store configured for file uploads"),
+        ));
     }
 
-    Ok(Json(CheckVideoIdsResponse { found }))
-}
+    let key = format!("files/{}/{}", doc_id, file_hash);
+    let upload_url = server_state
+        .store
+        .as_ref()
+        .unwrap()
+        .generate_upload_url(&key, content_type_owned.as_deref(), content_length)
+        .await
+        .map_err(|e| AppError::new(StatusCode::INTERNAL_SERVER_ERROR, e.into()))?;
 
-/// Read up to `max_bytes` (rounded down to a UTF-8 char boundary) of a loaded
-/// doc's `contents` Y.Text. The caller must have ensured the doc is loaded.
-/// Returns None when the doc is not present in the store.
-fn read_doc_head(server_state: &Arc<Server>, doc_id: &str, max_bytes: usize) -> Option<String> {
-    let doc_ref = server_state.docs().get(doc_id)?;
-    let awareness = doc_ref.awareness();
-    drop(doc_ref);
-    let guard = awareness.read().unwrap_or_else(|e| e.into_inner());
-    let txn = guard.doc.transact();
-    let full = txn
-        .get_text("contents")
-        .map(|t| t.get_string(&txn))
-        .unwrap_or_default();
-    if full.len() <= max_bytes {
-        return Some(full);
-    }
-    let mut end = max_bytes;
-    while end > 0 && !full.is_char_boundary(end) {
-        end -= 1;
-    }
-    Some(full[..end].to_string())
-}
+    let Some(url) = upload_url else {
+        return Err(AppError::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            anyhow!("Failed to generate upload URL"),
+        ));
+    };
 
-/// Extract the `source_url` value from a markdown doc's YAML frontmatter head
-/// (the block between the leading `---` fences). Returns None if absent.
-fn extract_frontmatter_source_url(head: &str) -> Option<String> {
-    let mut in_frontmatter = false;
-    for line in head.lines() {
-        let trimmed = line.trim_end();
-        if trimmed == "---" {
-            if in_frontmatter {
-                break; // closing fence — not in frontmatter
-            }
-            in_frontmatter = true;
-            continue;
-        }
-        if in_frontmatter {
-            if let Some(rest) = trimmed.trim_start().strip_prefix("source_url:") {
-                let value = rest.trim().trim_matches('"').trim();
-                return (!value.is_empty()).then(|| value.to_string());
+    if !url.starts_with("http") {
+        let base_url = generate_base_url(
+            &server_state.url,
+            &server_state.allowed_hosts,
+            &host.to_string(),
+        )?;
+        let upload_token = match token_source {
+            UploadTokenSource::Original => token.to_string(),
+            UploadTokenSource::MintNew {
+                content_type: ct,
+                content_length: cl,
+            } => {
+                let expiration_time = ExpirationTimeEpochMillis(
+                    current_time_epoch_millis() + DEFAULT_EXPIRATION_SECONDS * 1000,
+                );
+                authenticator
+                    .gen_file_token_auto(
+                        &file_hash,
+                        &doc_id,
+                        Authorization::Full,
+                        expiration_time,
+                        ct.as_deref(),
+                        cl,
+                        None,
+                    )
+                    .map_err(|e| {
+                        AppError::new(
+                            StatusCode::INTERNAL_SERVER_ERROR,
+                            anyhow!("Failed to generate file token: {}", e),
+                        )
+                    })?
             }
-        }
+        };
+        let full_url = format!("{}{}?token={}", base_url, url, upload_token);
+        Ok(Json(FileUploadUrlResponse {
+            upload_url: full_url,
+        }))
+    } else {
+        // S3/cloud storage URL - return as-is
+        Ok(Json(FileUploadUrlResponse { upload_url: url }))
     }
-    None
 }
 
-/// Return whether a YAML frontmatter `tags` field contains the exact tag.
-/// Supports the block-list form written by the article importer and common
-/// inline lists such as `tags: [article-stub, validator-ignore]`.
-fn frontmatter_has_tag(head: &str, wanted: &str) -> bool {
-    let mut in_frontmatter = false;
-    let mut in_tags = false;
-    for line in head.lines() {
-        let trimmed_end = line.trim_end();
-        if trimmed_end == "---" {
-            if in_frontmatter {
-                break;
-            }
-            in_frontmatter = true;
-            continue;
-        }
-        if !in_frontmatter {
-            continue;
-        }
+async fn handle_file_download_url(
+    State(server_state): State<Arc<Server>>,
+    Path(doc_id): Path<String>,
+    TypedHeader(host): TypedHeader<headers::Host>,
+    Query(params): Query<FileDownloadQueryParams>,
+    auth_header: Option<TypedHeader<headers::Authorization<headers::authorization::Bearer>>>,
+) -> Result<Json<FileDownloadUrlResponse>, AppError> {
+    tracing::info!(doc_id = %doc_id, hash = ?params.hash, "Generating file download URL");
 
-        let is_top_level = !line.starts_with(' ') && !line.starts_with('\t');
-        if is_top_level {
-            in_tags = false;
-            if let Some(rest) = trimmed_end.strip_prefix("tags:") {
-                in_tags = true;
-                if rest
-                    .split(|c: char| c == ',' || c == '[' || c == ']')
-                    .map(|part| part.trim().trim_matches(['"', '\'']))
-                    .any(|tag| tag == wanted)
-                {
-                    return true;
-                }
-            }
-            continue;
-        }
+    // Get token
+    let token = get_token_from_header(auth_header);
 
-        if in_tags
-            && trimmed_end
-                .trim()
-                .strip_prefix('-')
-                .map(|tag| tag.trim().trim_matches(['"', '\'']) == wanted)
-                .unwrap_or(false)
-        {
-            return true;
+    // Local dev fast path: no authenticator configured → accept any download, validate hash only.
+    if server_state.authenticator.is_none() {
+        let hash = params.hash.ok_or_else(|| {
+            AppError::new(
+                StatusCode::BAD_REQUEST,
+                anyhow!("hash query parameter required"),
+            )
+        })?;
+        if !validate_file_hash(&hash) {
+            return Err(AppError::new(
+                StatusCode::BAD_REQUEST,
+                anyhow!("Invalid file hash format"),
+            ));
         }
+        let Json(response) =
+            generate_file_download_url(&server_state, &doc_id, &hash, &host.to_string()).await?;
+        return Ok(Json(response));
     }
-    false
-}
 
-/// Normalize a source URL for dedup comparison: trim whitespace and a trailing
-/// slash (the common variant). Intentionally conservative — scheme/host case and
-/// query strings are left intact so genuinely different URLs aren't merged.
-fn normalize_source_url(url: &str) -> String {
-    url.trim().trim_end_matches('/').to_string()
-}
+    // Check if we have authentication configured
+    if let Some(authenticator) = &server_state.authenticator {
+        if let Some(token) = token.as_deref() {
+            // Extract hash from query parameter if present
+            let query_hash = params.hash;
 
-#[derive(Deserialize)]
-struct CheckSourceUrlsRequest {
-    folder: String,
-    subfolder: Option<String>,
-    source_urls: Vec<String>,
-}
+            // Verify the token and determine its type
+            let permission = authenticator
+                .verify_token_auto(token, current_time_epoch_millis())
+                .map_err(|_| {
+                    AppError::auth(
+                        StatusCode::UNAUTHORIZED,
+                        anyhow!("Invalid token"),
+                        "invalid_token",
+                    )
+                })?;
 
-#[derive(Serialize)]
-struct CheckSourceUrlsResponse {
-    found: std::collections::HashMap<String, Option<String>>,
-    /// Full content is returned only for lightweight article stubs. This lets
-    /// the importer preserve discussion notes during promotion without sending
-    /// already-imported article bodies over the duplicate-check endpoint.
-    stubs: std::collections::HashMap<String, Option<ArticleStubMatch>>,
-}
+            let user = match &permission {
+                Permission::File(file_permission) => file_permission.user.as_deref(),
+                Permission::Prefix(prefix_perm) => prefix_perm.user.as_deref(),
+                Permission::Doc(_) | Permission::Server => None,
+            };
+            server_state.authorize_doc_acl(&doc_id, user, Authorization::ReadOnly)?;
 
-#[derive(Clone, Serialize)]
-struct ArticleStubMatch {
-    path: String,
-    content: String,
-}
+            match permission {
+                Permission::File(file_permission) => {
+                    // Check if file token is for this doc_id
+                    if file_permission.doc_id != doc_id {
+                        return Err(AppError::auth(
+                            StatusCode::UNAUTHORIZED,
+                            anyhow!("Token not valid for this document"),
+                            "access_wrong_document",
+                        ));
+                    }
 
-/// For each given source URL, find an existing article doc whose `source_url`
-/// frontmatter matches (normalized) — the URL-based duplicate check the
-/// add-article importer uses. Mirrors `handle_check_video_ids`, but matches the
-/// frontmatter field precisely rather than substring-scanning the body.
-async fn handle_check_source_urls(
-    auth_header: Option<TypedHeader<headers::Authorization<headers::authorization::Bearer>>>,
-    State(server_state): State<Arc<Server>>,
-    Json(body): Json<CheckSourceUrlsRequest>,
-) -> Result<Json<CheckSourceUrlsResponse>, AppError> {
-    server_state.check_auth(auth_header)?;
+                    // ReadOnly, Suggest and Full can download files
+                    if !matches!(
+                        file_permission.authorization,
+                        Authorization::ReadOnly | Authorization::Suggest | Authorization::Full
+                    ) {
+                        return Err(AppError::auth(
+                            StatusCode::FORBIDDEN,
+                            anyhow!("Insufficient permissions to download file"),
+                            "insufficient_permissions",
+                        ));
+                    }
 
-    let prefix = match &body.subfolder {
-        Some(sub) => format!("{}/{}/", body.folder, sub),
-        None => format!("{}/", body.folder),
-    };
+                    let file_hash = file_permission.file_hash;
 
-    let matching_paths: Vec<String> = server_state
-        .doc_resolver()
-        .all_paths()
-        .into_iter()
-        .filter(|p| p.starts_with(&prefix) && p.ends_with(".md"))
-        .collect();
+                    // Validate the file hash
+                    if !validate_file_hash(&file_hash) {
+                        return Err(AppError::new(
+                            StatusCode::BAD_REQUEST,
+                            anyhow!("Invalid file hash format in token"),
+                        ));
+                    }
 
-    let queries: Vec<(String, String)> = body
-        .source_urls
-        .iter()
-        .map(|u| (u.clone(), normalize_source_url(u)))
-        .collect();
-    let mut found: std::collections::HashMap<String, Option<String>> =
-        body.source_urls.iter().map(|u| (u.clone(), None)).collect();
-    let mut stubs: std::collections::HashMap<String, Option<ArticleStubMatch>> =
-        body.source_urls.iter().map(|u| (u.clone(), None)).collect();
+                    // Generate download URL using hash from token
+                    let Json(download_response) = generate_file_download_url(
+                        &server_state,
+                        &doc_id,
+                        &file_hash,
+                        &host.to_string(),
+                    )
+                    .await?;
+                    // Add token to the URL
+                    let mut download_url = download_response.download_url;
+                    if !download_url.starts_with("http") || download_url.contains("/f/") {
+                        // This is our local endpoint, add token
+                        let separator = if download_url.contains('?') { "&" } else { "?" };
+                        download_url = format!("{}{}token={}", download_url, separator, token);
+                    }
+                    return Ok(Json(FileDownloadUrlResponse { download_url }));
+                }
+                Permission::Server => {
+                    // Server token is valid, use hash from query parameter
+                    if let Some(hash) = query_hash {
+                        // Validate the file hash from query parameter
+                        if !validate_file_hash(&hash) {
+                            return Err(AppError::new(
+                                StatusCode::BAD_REQUEST,
+                                anyhow!("Invalid file hash format in query parameter"),
+                            ));
+                        }
 
-    for path in &matching_paths {
-        if found.values().all(|v| v.is_some()) {
-            break;
-        }
-        let doc_id = match server_state.doc_resolver().resolve_path(path) {
-            Some(info) => info.doc_id,
-            None => continue,
-        };
-        if server_state.ensure_doc_loaded(&doc_id).await.is_err() {
-            continue;
-        }
-        let head = match read_doc_head(&server_state, &doc_id, 4000) {
-            Some(h) => h,
-            None => continue,
-        };
-        let stored = match extract_frontmatter_source_url(&head) {
-            Some(s) => normalize_source_url(&s),
-            None => continue,
-        };
-        let rel_path = format!("/{}", &path[body.folder.len()..].trim_start_matches('/'));
-        let stub_content = if frontmatter_has_tag(&head, "article-stub") {
-            read_doc_head(&server_state, &doc_id, usize::MAX)
-        } else {
-            None
-        };
-        for (orig, norm) in &queries {
-            if *norm == stored {
-                if let Some(slot) = found.get_mut(orig) {
-                    if slot.is_none() {
-                        *slot = Some(rel_path.clone());
-                        if let Some(content) = &stub_content {
-                            stubs.insert(
-                                orig.clone(),
-                                Some(ArticleStubMatch {
-                                    path: rel_path.clone(),
-                                    content: content.clone(),
-                                }),
+                        // Generate download URL using hash from query parameter
+                        let Json(download_response) = generate_file_download_url(
+                            &server_state,
+                            &doc_id,
+                            &hash,
+                            &host.to_string(),
+                        )
+                        .await?;
+                        // Add file token to the URL (not the server token)
+                        let mut download_url = download_response.download_url;
+                        if !download_url.starts_with("http") || download_url.contains("/f/") {
+                            // This is our local endpoint, generate a proper file token
+                            let expiration_time = ExpirationTimeEpochMillis(
+                                current_time_epoch_millis() + DEFAULT_EXPIRATION_SECONDS * 1000,
                             );
+                            let file_token = authenticator
+                                .gen_file_token_auto(
+                                    &hash,
+                                    &doc_id,
+                                    Authorization::Full,
+                                    expiration_time,
+                                    None,
+                                    None,
+                                    None,
+                                )
+                                .map_err(|e| {
+                                    AppError::new(
+                                        StatusCode::INTERNAL_SERVER_ERROR,
+                                        anyhow!("Failed to generate file token: {}", e),
+                                    )
+                                })?;
+                            let separator = if download_url.contains('?') { "&" } else { "?" };
+                            download_url =
+                                format!("{}{}token={}", download_url, separator, file_token);
                         }
+                        return Ok(Json(FileDownloadUrlResponse { download_url }));
+                    } else {
+                        return Err(AppError::new(
+                            StatusCode::BAD_REQUEST,
+                            anyhow!("Hash query parameter required when using server token"),
+                        ));
                     }
                 }
-            }
-        }
-    }
-
-    Ok(Json(CheckSourceUrlsResponse { found, stubs }))
-}
-
-#[derive(Deserialize)]
-struct AttachmentQuery {
-    folder: String,
-    path: String,
-    mimetype: Option<String>,
-}
-
-/// Create a binary attachment (e.g. an image extracted from an imported PDF) as
-/// a relay blob + `filemeta_v0` entry, so markdown can reference it
-/// (`![[/attachments/x.png]]`). Raw bytes in the body; folder/path/mimetype in
-/// the query. Create-only: an existing path is treated as already-hosted.
-async fn handle_upsert_attachment(
-    auth_header: Option<TypedHeader<headers::Authorization<headers::authorization::Bearer>>>,
-    State(server_state): State<Arc<Server>>,
-    Query(query): Query<AttachmentQuery>,
-    body: axum::body::Bytes,
-) -> Result<Json<UpsertDocResponse>, AppError> {
-    server_state.check_auth(auth_header)?;
-
-    let path = if query.path.starts_with('/') {
-        query.path.clone()
-    } else {
-        format!("/{}", query.path)
-    };
-    let mimetype = query
-        .mimetype
-        .as_deref()
-        .unwrap_or("application/octet-stream");
-
-    match server_state
-        .create_blob_file(&query.folder, &path, &body, mimetype)
-        .await
-    {
-        Ok(result) => Ok(Json(UpsertDocResponse {
-            doc_id: result.full_doc_id,
-            path: format!("{}{}", query.folder, path),
-            created: true,
-        })),
-        // Already hosted at this path — idempotent success.
-        Err(CreateDocumentError::Conflict(_)) => Ok(Json(UpsertDocResponse {
-            doc_id: String::new(),
-            path: format!("{}{}", query.folder, path),
-            created: false,
-        })),
-        Err(CreateDocumentError::NotFound(msg)) => {
-            Err(AppError::new(StatusCode::NOT_FOUND, anyhow!("{}", msg)))
-        }
-        Err(CreateDocumentError::BadRequest(msg)) => {
-            Err(AppError::new(StatusCode::BAD_REQUEST, anyhow!("{}", msg)))
-        }
-        Err(e) => Err(AppError::new(
-            StatusCode::INTERNAL_SERVER_ERROR,
-            anyhow!("{:?}", e),
-        )),
-    }
-}
-
-async fn new_doc(
-    auth_header: Option<TypedHeader<headers::Authorization<headers::authorization::Bearer>>>,
-    State(server_state): State<Arc<Server>>,
-    Json(body): Json<DocCreationRequest>,
-) -> Result<Json<NewDocResponse>, AppError> {
-    let token = get_token_from_header(auth_header);
+                Permission::Doc(_) => {
+                    return Err(AppError::new(
+                        StatusCode::BAD_REQUEST,
+                        anyhow!("Document tokens cannot be used for file operations"),
+                    ));
+                }
+                Permission::Prefix(prefix_perm) => {
+                    // Check if doc_id matches the prefix
+                    if !doc_id.starts_with(&prefix_perm.prefix) {
+                        return Err(AppError::auth(
+                            StatusCode::FORBIDDEN,
+                            anyhow!("Token not valid for this document"),
+                            "prefix_mismatch",
+                        ));
+                    }
 
-    if let Some(authenticator) = &server_state.authenticator {
-        if let Some(token) = token.as_deref() {
-            // First try server token
-            if authenticator
-                .verify_server_token(token, current_time_epoch_millis())
-                .is_ok()
-            {
-                // Server token allows creating any document
-            } else {
-                // Try prefix token - we need to check if the doc_id matches the prefix
-                if let Some(doc_id) = &body.doc_id {
-                    let permission = authenticator
-                        .verify_token_auto(token, current_time_epoch_millis())
-                        .map_err(|auth_error| {
-                            AppError::auth(
-                                StatusCode::UNAUTHORIZED,
-                                anyhow!("Invalid token: {}", auth_error),
-                                auth_error.to_metric_label(),
-                            )
-                        })?;
+                    // ReadOnly, Suggest and Full can download files
+                    if !matches!(
+                        prefix_perm.authorization,
+                        Authorization::ReadOnly | Authorization::Suggest | Authorization::Full
+                    ) {
+                        return Err(AppError::auth(
+                            StatusCode::FORBIDDEN,
+                            anyhow!("Insufficient permissions to download file"),
+                            "insufficient_permissions",
+                        ));
+                    }
 
-                    match permission {
-                        Permission::Prefix(prefix_perm) => {
-                            // Check if the document ID starts with the prefix
-                            if !doc_id.starts_with(&prefix_perm.prefix) {
-                                return Err(AppError::auth(
-                                    StatusCode::FORBIDDEN,
-                                    anyhow!(
-                                        "Document ID '{}' does not match prefix '{}'",
-                                        doc_id,
-                                        prefix_perm.prefix
-                                    ),
-                                    "prefix_mismatch",
-                                ));
-                            }
-                            // Check if we have Full permissions (needed for creation)
-                            if prefix_perm.authorization != Authorization::Full {
-                                return Err(AppError::auth(
-                                    StatusCode::FORBIDDEN,
-                                    anyhow!("Prefix token requires Full authorization to create documents"),
-                                    "insufficient_permissions",
-                                ));
-                            }
-                        }
-                        _ => {
-                            return Err(AppError::auth(
-                                StatusCode::FORBIDDEN,
-                                anyhow!("Only server or prefix tokens can create documents"),
-                                "wrong_token_type",
+                    // Use hash from query parameter for prefix tokens
+                    if let Some(hash) = query_hash {
+                        // Validate the file hash from query parameter
+                        if !validate_file_hash(&hash) {
+                            return Err(AppError::new(
+                                StatusCode::BAD_REQUEST,
+                                anyhow!("Invalid file hash format in query parameter"),
                             ));
                         }
+
+                        // Generate download URL using hash from query parameter
+                        let Json(download_response) = generate_file_download_url(
+                            &server_state,
+                            &doc_id,
+                            &hash,
+                            &host.to_string(),
+                        )
+                        .await?;
+                        // Add file token to the URL (not the prefix token)
+                        let mut download_url = download_response.download_url;
+                        if !download_url.starts_with("http") || download_url.contains("/f/") {
+                            // This is our local endpoint, generate a proper file token
+                            let expiration_time = ExpirationTimeEpochMillis(
+                                current_time_epoch_millis() + DEFAULT_EXPIRATION_SECONDS * 1000,
+                            );
+                            let file_token = authenticator
+                                .gen_file_token_auto(
+                                    &hash,
+                                    &doc_id,
+                                    prefix_perm.authorization,
+                                    expiration_time,
+                                    None,
+                                    None,
+                                    None,
+                                )
+                                .map_err(|e| {
+                                    AppError::new(
+                                        StatusCode::INTERNAL_SERVER_ERROR,
+                                        anyhow!("Failed to generate file token: {}", e),
+                                    )
+                                })?;
+                            let separator = if download_url.contains('?') { "&" } else { "?" };
+                            download_url =
+                                format!("{}{}token={}", download_url, separator, file_token);
+                        }
+                        return Ok(Json(FileDownloadUrlResponse { download_url }));
+                    } else {
+                        return Err(AppError::new(
+                            StatusCode::BAD_REQUEST,
+                            anyhow!("Hash query parameter required when using prefix token"),
+                        ));
                     }
-                } else {
-                    // No doc_id provided - only server tokens can create with auto-generated ID
-                    return Err(AppError::auth(
-                        StatusCode::FORBIDDEN,
-                        anyhow!("Prefix tokens must specify a docId that matches their prefix"),
-                        "wrong_token_type",
-                    ));
                 }
             }
         } else {
-            return Err(AppError::auth(
-                StatusCode::UNAUTHORIZED,
-                anyhow!("No token provided"),
-                "missing_token",
-            ));
-        }
-    }
-
-    let doc_id = if let Some(doc_id) = body.doc_id {
-        if !validate_doc_name(doc_id.as_str()) {
-            Err((StatusCode::BAD_REQUEST, anyhow!("Invalid document name")))?
-        }
-
-        server_state
-            .get_or_create_doc(doc_id.as_str())
-            .await
-            .map_err(|e| {
-                tracing::error!(?e, "Failed to create doc");
-                (StatusCode::INTERNAL_SERVER_ERROR, e)
-            })?;
-
-        doc_id
-    } else {
-        server_state.create_doc().await.map_err(|d| {
-            tracing::error!(?d, "Failed to create doc");
-            (StatusCode::INTERNAL_SERVER_ERROR, d)
-        })?
-    };
-
-    Ok(Json(NewDocResponse { doc_id }))
-}
-
-fn generate_base_url(
-    url: &Option<Url>,
-    allowed_hosts: &[AllowedHost],
-    request_host: &str,
-) -> Result<String, AppError> {
-    // Priority 1: Explicit URL prefix
-    if let Some(prefix) = url {
-        return Ok(prefix.as_str().trim_end_matches('/').to_string());
-    }
-
-    // Priority 2: Context-derived URL from Host header
-    if let Some(allowed) = allowed_hosts.iter().find(|h| h.host == request_host) {
-        return Ok(format!("{}://{}", allowed.scheme, request_host));
-    }
-
-    // Priority 3: Fallback to old behavior for backward compatibility
-    if allowed_hosts.is_empty() {
-        return Ok(format!("http://{}", request_host));
+            return Err(AppError::auth(
+                StatusCode::UNAUTHORIZED,
+                anyhow!("No token provided"),
+                "missing_token",
+            ));
+        }
+    } else {
+        // No auth configured
+        return Err(AppError::auth(
+            StatusCode::UNAUTHORIZED,
+            anyhow!("Authentication is required for file operations"),
+            "no_authenticator",
+        ));
     }
-
-    // Reject unknown hosts when allowed_hosts is configured
-    Err(AppError::new(
-        StatusCode::BAD_REQUEST,
-        anyhow!("Host '{}' not in allowed hosts list", request_host),
-    ))
 }
 
-fn generate_context_aware_urls(
-    url: &Option<Url>,
-    allowed_hosts: &[AllowedHost],
-    request_host: &str,
+async fn generate_file_download_url(
+    server_state: &Arc<Server>,
     doc_id: &str,
-) -> Result<(String, String), AppError> {
-    // Priority 1: Explicit URL prefix
-    if let Some(prefix) = url {
-        let ws_scheme = if prefix.scheme() == "https" {
-            "wss"
-        } else {
-            "ws"
-        };
-        let mut ws_url = prefix.clone();
-        ws_url.set_scheme(ws_scheme).unwrap();
-        let ws_url = ws_url
-            .join(&format!("/d/{}/ws", doc_id))
-            .unwrap()
-            .to_string();
-
-        let base_url = format!("{}/d/{}", prefix.as_str().trim_end_matches('/'), doc_id);
-        return Ok((ws_url, base_url));
+    file_hash: &str,
+    host: &str,
+) -> Result<Json<FileDownloadUrlResponse>, AppError> {
+    // Check if we have a store configured
+    if server_state.store.is_none() {
+        return Err(AppError::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            anyhow!("No store configured for file downloads"),
+        ));
     }
 
-    // Priority 2: Context-derived URL from Host header
-    if let Some(allowed) = allowed_hosts.iter().find(|h| h.host == request_host) {
-        let ws_scheme = if allowed.scheme == "https" {
-            "wss"
-        } else {
-            "ws"
-        };
-        let ws_url = format!("{}://{}/d/{}/ws", ws_scheme, request_host, doc_id);
-        let base_url = format!("{}://{}/d/{}", allowed.scheme, request_host, doc_id);
-        return Ok((ws_url, base_url));
-    }
+    // Generate the download URL - using doc_id/file_hash path structure
+    let key = format!("files/{}/{}", doc_id, file_hash);
+    let download_url = server_state
+        .store
+        .as_ref()
+        .unwrap()
+        .generate_download_url(&key)
+        .await
+        .map_err(|e| AppError::new(StatusCode::INTERNAL_SERVER_ERROR, e.into()))?;
 
-    // Priority 3: Fallback to old behavior for backward compatibility
-    // This handles the case where no URL prefix and no allowed hosts are set
-    if allowed_hosts.is_empty() {
-        let ws_url = format!("ws://{}/d/{}/ws", request_host, doc_id);
-        let base_url = format!("http://{}/d/{}", request_host, doc_id);
-        return Ok((ws_url, base_url));
+    if let Some(url) = download_url {
+        // Check if this is a local endpoint (relative path) and convert to full URL
+        if !url.starts_with("http") {
+            let base_url = generate_base_url(&server_state.url, &server_state.allowed_hosts, host)?;
+            let full_url = format!("{}{}", base_url, url);
+            Ok(Json(FileDownloadUrlResponse {
+                download_url: full_url,
+            }))
+        } else {
+            // S3/cloud storage URL - return as-is
+            Ok(Json(FileDownloadUrlResponse { download_url: url }))
+        }
+    } else {
+        Err(AppError::new(
+            StatusCode::NOT_FOUND,
+            anyhow!("File not found"),
+        ))
     }
-
-    // Reject unknown hosts when allowed_hosts is configured
-    Err(AppError::new(
-        StatusCode::BAD_REQUEST,
-        anyhow!("Host '{}' not in allowed hosts list", request_host),
-    ))
 }
 
-async fn auth_doc(
-    auth_header: Option<TypedHeader<headers::Authorization<headers::authorization::Bearer>>>,
-    TypedHeader(host): TypedHeader<headers::Host>,
+/// Delete all files for a document
+///
+/// This endpoint accepts either:
+/// - A file token with the doc_id (hash not required)
+/// - A doc token with the doc_id
+/// - A server token
+///
+/// Returns 204 No Content on success
+async fn handle_file_delete(
     State(server_state): State<Arc<Server>>,
     Path(doc_id): Path<String>,
-    body: Option<Json<AuthDocRequest>>,
-) -> Result<Json<ClientToken>, AppError> {
-    server_state.check_auth(auth_header)?;
-
-    let Json(AuthDocRequest {
-        authorization,
-        valid_for_seconds,
-        ..
-    }) = body.unwrap_or_default();
+    auth_header: Option<TypedHeader<headers::Authorization<headers::authorization::Bearer>>>,
+) -> Result<StatusCode, AppError> {
+    // Get token
+    let token = get_token_from_header(auth_header);
 
-    if !server_state.doc_exists(&doc_id).await {
-        Err((StatusCode::NOT_FOUND, anyhow!("Doc {} not found", doc_id)))?;
-    }
+    // Verify token is for this doc_id and has required permission
+    if let Some(authenticator) = &server_state.authenticator {
+        if let Some(token) = token.as_deref() {
+            // Verify token is for this doc_id
+            let auth = authenticator
+                .verify_file_token_for_doc(token, &doc_id, current_time_epoch_millis())
+                .map_err(|e| {
+                    AppError::auth(
+                        StatusCode::UNAUTHORIZED,
+                        anyhow!("Invalid token: {}", e),
+                        "invalid_token",
+                    )
+                })?;
 
-    let valid_for_seconds = valid_for_seconds.unwrap_or(DEFAULT_EXPIRATION_SECONDS);
-    let expiration_time =
-        ExpirationTimeEpochMillis(current_time_epoch_millis() + valid_for_seconds * 1000);
+            // Only Full permission can delete files
+            if !matches!(auth, Authorization::Full) {
+                return Err(AppError::auth(
+                    StatusCode::FORBIDDEN,
+                    anyhow!("Insufficient permissions to delete files"),
+                    "insufficient_permissions",
+                ));
+            }
 
-    let token = if let Some(auth) = &server_state.authenticator {
-        let token = auth
-            .gen_doc_token_auto(&doc_id, authorization, expiration_time, None)
-            .map_err(|e| {
-                AppError::new(
+            // Check if we have a store configured
+            if server_state.store.is_none() {
+                return Err(AppError::new(
                     StatusCode::INTERNAL_SERVER_ERROR,
-                    anyhow!("Failed to generate token: {}", e),
-                )
-            })?;
-        Some(token)
-    } else {
-        None
-    };
+                    anyhow!("No store configured for file operations"),
+                ));
+            }
 
-    let (url, base_url) = generate_context_aware_urls(
-        &server_state.url,
-        &server_state.allowed_hosts,
-        &host.to_string(),
-        &doc_id,
-    )?;
+            // List all files in the document's directory
+            let prefix = format!("files/{}/", doc_id);
+            let store = server_state.store.as_ref().unwrap();
 
-    Ok(Json(ClientToken {
-        url,
-        base_url: Some(base_url),
-        doc_id,
-        token,
-        authorization,
-    }))
-}
+            let file_infos = store
+                .list(&prefix)
+                .await
+                .map_err(|e| AppError::new(StatusCode::INTERNAL_SERVER_ERROR, e.into()))?;
 
-async fn resolve_doc(
-    auth_header: Option<TypedHeader<headers::Authorization<headers::authorization::Bearer>>>,
-    State(server_state): State<Arc<Server>>,
-    Path(prefix): Path<String>,
-) -> Result<Json<serde_json::Value>, AppError> {
-    server_state.check_auth(auth_header)?;
+            if file_infos.is_empty() {
+                tracing::info!("No files to delete for document: {}", doc_id);
+                return Ok(StatusCode::NO_CONTENT);
+            }
 
-    match server_state.resolve_doc_id(&prefix).await {
-        Some(doc_id) => Ok(Json(serde_json::json!({ "docId": doc_id }))),
-        None => Err(AppError::new(
-            StatusCode::NOT_FOUND,
-            anyhow!("No unique doc matching prefix '{}'", prefix),
-        )),
+            // Delete each file
+            let mut deleted_count = 0;
+            for file_info in file_infos {
+                let key = file_info.key;
+                if let Err(e) = store.remove(&format!("files/{}/{}", doc_id, key)).await {
+                    tracing::error!("Failed to delete file {}/{}: {}", doc_id, key, e);
+                    continue;
+                }
+                deleted_count += 1;
+            }
+
+            tracing::info!("Deleted {} files for document: {}", deleted_count, doc_id);
+            return Ok(StatusCode::NO_CONTENT);
+        } else {
+            return Err(AppError::auth(
+                StatusCode::UNAUTHORIZED,
+                anyhow!("No token provided"),
+                "missing_token",
+            ));
+        }
+    } else {
+        // No auth configured
+        return Err(AppError::auth(
+            StatusCode::UNAUTHORIZED,
+            anyhow!("Authentication is required for file operations"),
+            "no_authenticator",
+        ));
     }
 }
 
-async fn get_doc_folder(
-    auth_header: Option<TypedHeader<headers::Authorization<headers::authorization::Bearer>>>,
-    State(server_state): State<Arc<Server>>,
-    Path(doc_id): Path<String>,
-) -> Result<Json<serde_json::Value>, AppError> {
-    server_state.check_auth(auth_header)?;
+/// Delete a specific file by hash
+///
+/// This endpoint accepts either:
+/// - A file token with the doc_id (hash not required)
+/// - A doc token with the doc_id
+/// - A server token
+///
+/// The hash to delete is specified in the URL path.
+/// Returns 204 No Content on success, 404 if file not found
+async fn handle_file_delete_by_hash(
+    State(server_state): State<Arc<Server>>,
+    Path((doc_id, file_hash)): Path<(String, String)>,
+    auth_header: Option<TypedHeader<headers::Authorization<headers::authorization::Bearer>>>,
+) -> Result<StatusCode, AppError> {
+    // Get token
+    let token = get_token_from_header(auth_header);
+
+    // Verify token is for this doc_id and has required permission
+    if let Some(authenticator) = &server_state.authenticator {
+        if let Some(token) = token.as_deref() {
+            // Verify token is for this doc_id
+            let auth = authenticator
+                .verify_file_token_for_doc(token, &doc_id, current_time_epoch_millis())
+                .map_err(|e| {
+                    AppError::auth(
+                        StatusCode::UNAUTHORIZED,
+                        anyhow!("Invalid token: {}", e),
+                        "invalid_token",
+                    )
+                })?;
+
+            // Only Full permission can delete files
+            if !matches!(auth, Authorization::Full) {
+                return Err(AppError::auth(
+                    StatusCode::FORBIDDEN,
+                    anyhow!("Insufficient permissions to delete file"),
+                    "insufficient_permissions",
+                ));
+            }
 
-    // Extract the UUID portion from compound doc_id (relay_id-uuid)
-    let uuid = link_indexer::parse_doc_id(&doc_id)
-        .map(|(_, uuid)| uuid)
-        .ok_or_else(|| AppError::new(StatusCode::BAD_REQUEST, anyhow!("Invalid doc_id format")))?;
+            // Validate the file hash format
+            if !validate_file_hash(&file_hash) {
+                return Err(AppError::new(
+                    StatusCode::BAD_REQUEST,
+                    anyhow!("Invalid file hash format"),
+                ));
+            }
 
-    match server_state.doc_resolver().folder_uuid_for_doc(uuid) {
-        Some(folder_uuid) => Ok(Json(serde_json::json!({ "folderUuid": folder_uuid }))),
-        None => Err(AppError::new(
-            StatusCode::NOT_FOUND,
-            anyhow!("Document not found in any folder"),
-        )),
-    }
-}
+            // Check if we have a store configured
+            if server_state.store.is_none() {
+                return Err(AppError::new(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    anyhow!("No store configured for file operations"),
+                ));
+            }
 
-#[derive(Deserialize)]
-struct DebugResolveQuery {
-    path: String,
-}
+            // Construct the file path
+            let key = format!("files/{}/{}", doc_id, file_hash);
 
-/// GET /debug/resolve?path=<folder>/<subpath>/<name>.md
-///
-/// Read-only diagnostic comparing the eventually-consistent `doc_resolver`
-/// against `filemeta_v0` (the source of truth) for a single path. `stale: true`
-/// means the file exists in filemeta but the resolver cannot resolve it at this
-/// path — the condition that makes a rename fail with "Move failed: 400".
-/// `resolver_path_for_uuid` shows where the resolver currently thinks the doc
-/// lives (None = missing entirely; a different path = stale entry).
-async fn handle_debug_resolve(
-    auth_header: Option<TypedHeader<headers::Authorization<headers::authorization::Bearer>>>,
-    State(server_state): State<Arc<Server>>,
-    Query(params): Query<DebugResolveQuery>,
-) -> Result<Json<serde_json::Value>, AppError> {
-    server_state.check_auth(auth_header)?;
+            // Check if the file exists before trying to delete it
+            let exists = server_state
+                .store
+                .as_ref()
+                .unwrap()
+                .exists(&key)
+                .await
+                .map_err(|e| AppError::new(StatusCode::INTERNAL_SERVER_ERROR, e.into()))?;
 
-    let path = params.path;
-    let resolver_hit = server_state.doc_resolver().resolve_path(&path);
-    let filemeta_hit = server_state.resolve_path_via_filemeta(&path);
-    let resolver_path_for_uuid = filemeta_hit
-        .as_ref()
-        .and_then(|i| server_state.doc_resolver().path_for_uuid(&i.uuid));
+            if !exists {
+                // If the file is already gone, return 204 No Content since DELETE is idempotent
+                tracing::debug!("File already deleted: {}/{}", doc_id, file_hash);
+                return Ok(StatusCode::NO_CONTENT);
+            }
 
-    Ok(Json(serde_json::json!({
-        "path": path,
-        "resolver_hit": resolver_hit.is_some(),
-        "resolver_uuid": resolver_hit.as_ref().map(|i| i.uuid.clone()),
-        "in_filemeta": filemeta_hit.is_some(),
-        "filemeta_uuid": filemeta_hit.as_ref().map(|i| i.uuid.clone()),
-        "filemeta_folder": filemeta_hit.as_ref().map(|i| i.folder_name.clone()),
-        "resolver_path_for_uuid": resolver_path_for_uuid,
-        "stale": filemeta_hit.is_some() && resolver_hit.is_none(),
-    })))
-}
+            // Delete the file
+            server_state
+                .store
+                .as_ref()
+                .unwrap()
+                .remove(&key)
+                .await
+                .map_err(|e| AppError::new(StatusCode::INTERNAL_SERVER_ERROR, e.into()))?;
 
-fn get_token_from_header(
-    auth_header: Option<TypedHeader<headers::Authorization<headers::authorization::Bearer>>>,
-) -> Option<String> {
-    if let Some(TypedHeader(headers::Authorization(bearer))) = auth_header {
-        Some(bearer.token().to_string())
+            tracing::info!("Deleted file: {}/{}", doc_id, file_hash);
+            return Ok(StatusCode::NO_CONTENT);
+        } else {
+            return Err(AppError::auth(
+                StatusCode::UNAUTHORIZED,
+                anyhow!("No token provided"),
+                "missing_token",
+            ));
+        }
     } else {
-        None
+        // No auth configured
+        return Err(AppError::auth(
+            StatusCode::UNAUTHORIZED,
+            anyhow!("Authentication is required for file operations"),
+            "no_authenticator",
+        ));
     }
 }
 
-async fn handle_file_upload_url(
+/// Handle HEAD request to check if a file exists in S3 storage
+///
+/// Returns:
+/// - 200 OK if the file exists
+/// - 404 Not Found if the file doesn't exist
+/// - Other status codes for authentication/authorization errors
+
+/// Get the history of all files for a document
+///
+/// This endpoint accepts either:
+/// - A file token with the doc_id (hash not required)
+/// - A doc token with the doc_id
+/// - A server token
+async fn handle_file_history(
     State(server_state): State<Arc<Server>>,
     Path(doc_id): Path<String>,
-    TypedHeader(host): TypedHeader<headers::Host>,
-    Query(params): Query<FileUploadQueryParams>,
     auth_header: Option<TypedHeader<headers::Authorization<headers::authorization::Bearer>>>,
-) -> Result<Json<FileUploadUrlResponse>, AppError> {
-    tracing::info!(doc_id = %doc_id, "Generating file upload URL");
-
+) -> Result<Json<FileHistoryResponse>, AppError> {
+    // Get token
     let token = get_token_from_header(auth_header);
 
-    // Local dev fast path: no authenticator configured → accept any upload, validate by hash only.
-    // Mirrors the unauthenticated blob-read route registered when authenticator.is_none().
-    if server_state.authenticator.is_none() {
-        let hash = params.hash.ok_or_else(|| {
-            AppError::new(
-                StatusCode::BAD_REQUEST,
-                anyhow!("hash query parameter required"),
-            )
-        })?;
-        if !validate_file_hash(&hash) {
-            return Err(AppError::new(
-                StatusCode::BAD_REQUEST,
-                anyhow!("Invalid file hash format"),
+    // Verify token is for this doc_id
+    if let Some(authenticator) = &server_state.authenticator {
+        if let Some(token) = token.as_deref() {
+            // Verify token is for this doc_id - this now accepts both doc and file tokens
+            let auth = authenticator
+                .verify_file_token_for_doc(token, &doc_id, current_time_epoch_millis())
+                .map_err(|e| {
+                    AppError::auth(
+                        StatusCode::UNAUTHORIZED,
+                        anyhow!("Invalid token: {}", e),
+                        "invalid_token",
+                    )
+                })?;
+
+            // ReadOnly, Suggest and Full can view file history
+            if !matches!(
+                auth,
+                Authorization::ReadOnly | Authorization::Suggest | Authorization::Full
+            ) {
+                return Err(AppError::auth(
+                    StatusCode::FORBIDDEN,
+                    anyhow!("Insufficient permissions to view file history"),
+                    "insufficient_permissions",
+                ));
+            }
+        } else {
+            return Err(AppError::auth(
+                StatusCode::UNAUTHORIZED,
+                anyhow!("No token provided"),
+                "missing_token",
             ));
         }
-        let store = server_state.store.as_ref().ok_or_else(|| {
-            AppError::new(
-                StatusCode::INTERNAL_SERVER_ERROR,
-                anyhow!("No store configured for file uploads"),
-            )
-        })?;
-        let key = format!("files/{}/{}", doc_id, hash);
-        let upload_url = store
-            .generate_upload_url(&key, params.content_type.as_deref(), params.content_length)
-            .await
-            .map_err(|e| AppError::new(StatusCode::INTERNAL_SERVER_ERROR, e.into()))?;
-        let Some(url) = upload_url else {
-            return Err(AppError::new(
-                StatusCode::INTERNAL_SERVER_ERROR,
-                anyhow!("Failed to generate upload URL"),
-            ));
-        };
-        // For local filesystem store the URL is a relative path (/f/{doc}/upload).
-        // Return it as-is so the caller can route it through their own proxy without
-        // constructing an absolute http:// URL (which would cause mixed-content issues
-        // when the client is on https).
-        let response_url = if url.starts_with("http") {
-            url
-        } else {
-            format!("{}?hash={}", url, hash)
-        };
-        return Ok(Json(FileUploadUrlResponse {
-            upload_url: response_url,
-        }));
     }
 
-    let Some(authenticator) = &server_state.authenticator else {
-        unreachable!("authenticator checked above")
-    };
-
-    let Some(token) = token.as_deref() else {
-        return Err(AppError::auth(
-            StatusCode::UNAUTHORIZED,
-            anyhow!("No token provided"),
-            "missing_token",
+    // Check if we have a store configured
+    if server_state.store.is_none() {
+        return Err(AppError::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            anyhow!("No store configured for file operations"),
         ));
-    };
+    }
 
-    let permission = authenticator
-        .verify_token_auto(token, current_time_epoch_millis())
-        .map_err(|_| {
-            AppError::auth(
-                StatusCode::UNAUTHORIZED,
-                anyhow!("Invalid token"),
-                "invalid_token",
-            )
-        })?;
+    // List files in the document's directory
+    let prefix = format!("files/{}/", doc_id);
+    let store = server_state.store.as_ref().unwrap();
+
+    let file_infos = store
+        .list(&prefix)
+        .await
+        .map_err(|e| AppError::new(StatusCode::INTERNAL_SERVER_ERROR, e.into()))?;
+
+    // Convert the raw file info into the API response format
+    let files = file_infos
+        .into_iter()
+        .map(|info| FileHistoryEntry {
+            hash: info.key,
+            size: info.size,
+            created_at: info.last_modified,
+        })
+        .collect();
 
-    // upload_token is the token that will be appended to the local upload URL.
-    // For file tokens we reuse the original; for server tokens we mint a new file token.
-    enum UploadTokenSource {
-        Original,
-        MintNew {
-            content_type: Option<String>,
-            content_length: Option<u64>,
-        },
-    }
+    Ok(Json(FileHistoryResponse { files }))
+}
 
-    let (file_hash, content_type_owned, content_length, token_source) = match permission {
-        Permission::File(file_permission) => {
-            // File token must be for this doc and have Full permission
-            if file_permission.doc_id != doc_id {
+/// Require a doc token with read access (full access when `write`) when auth
+/// is enabled. Guards the endpoints that expose or restore a doc's stored
+/// versions.
+fn authorize_doc_history(
+    server_state: &Server,
+    doc_id: &str,
+    token: Option<&str>,
+    write: bool,
+) -> Result<(), AppError> {
+    if let Some(authenticator) = &server_state.authenticator {
+        if let Some(token) = token {
+            let auth = authenticator
+                .verify_doc_token(token, doc_id, current_time_epoch_millis())
+                .map_err(|e| {
+                    AppError::auth(
+                        StatusCode::UNAUTHORIZED,
+                        anyhow!("Invalid token: {}", e),
+                        "invalid_token",
+                    )
+                })?;
+
+            if write && !matches!(auth, Authorization::Full) {
                 return Err(AppError::auth(
-                    StatusCode::UNAUTHORIZED,
-                    anyhow!("Token not valid for this document"),
-                    "access_wrong_document",
+                    StatusCode::FORBIDDEN,
+                    anyhow!("Insufficient permissions to restore document versions"),
+                    "insufficient_permissions",
                 ));
             }
-            if !matches!(file_permission.authorization, Authorization::Full) {
+            if !matches!(
+                auth,
+                Authorization::ReadOnly | Authorization::Suggest | Authorization::Full
+            ) {
                 return Err(AppError::auth(
                     StatusCode::FORBIDDEN,
-                    anyhow!("Insufficient permissions to upload files"),
+                    anyhow!("Insufficient permissions to view document versions"),
                     "insufficient_permissions",
                 ));
             }
-            (
-                file_permission.file_hash,
-                file_permission.content_type,
-                file_permission.content_length,
-                UploadTokenSource::Original,
-            )
-        }
-        Permission::Server => {
-            // Server token: hash (and optional metadata) must come from query params
-            let hash = params.hash.ok_or_else(|| {
-                AppError::new(
-                    StatusCode::BAD_REQUEST,
-                    anyhow!("Hash query parameter required when using server token"),
-                )
-            })?;
-            let ct = params.content_type.clone();
-            let cl = params.content_length;
-            (
-                hash,
-                params.content_type,
-                params.content_length,
-                UploadTokenSource::MintNew {
-                    content_type: ct,
-                    content_length: cl,
-                },
-            )
-        }
-        _ => {
-            return Err(AppError::new(
-                StatusCode::BAD_REQUEST,
-                anyhow!("Token type cannot be used for file uploads"),
+        } else {
+            return Err(AppError::auth(
+                StatusCode::UNAUTHORIZED,
+                anyhow!("No token provided"),
+                "missing_token",
             ));
         }
-    };
-
-    // Validate the file hash
-    if !validate_file_hash(&file_hash) {
-        return Err(AppError::new(
-            StatusCode::BAD_REQUEST,
-            anyhow!("Invalid file hash format"),
-        ));
     }
+    Ok(())
+}
 
-    // Check if we have a store configured
-    if server_state.store.is_none() {
-        return Err(AppError::new(
-            StatusCode::INTERNAL_SERVER_ERROR,
-            anyhow!("No store configured for file uploads"),
-        ));
-    }
+async fn handle_doc_versions(
+    State(server_state): State<Arc<Server>>,
+    Path(doc_id): Path<String>,
+    auth_header: Option<TypedHeader<headers::Authorization<headers::authorization::Bearer>>>,
+) -> Result<Json<DocumentVersionResponse>, AppError> {
+    let token = get_token_from_header(auth_header);
+    authorize_doc_history(&server_state, &doc_id, token.as_deref(), false)?;
 
-    let key = format!("files/{}/{}", doc_id, file_hash);
-    let upload_url = server_state
-        .store
-        .as_ref()
-        .unwrap()
-        .generate_upload_url(&key, content_type_owned.as_deref(), content_length)
+    let store = match &server_state.store {
+        Some(s) => s,
+        None => {
+            return Err(AppError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                anyhow!("No store configured for operations"),
+            ))
+        }
+    };
+
+    let key = format!("{}/data.ysweet", doc_id);
+    let versions = store
+        .list_versions(&key)
         .await
         .map_err(|e| AppError::new(StatusCode::INTERNAL_SERVER_ERROR, e.into()))?;
 
-    let Some(url) = upload_url else {
-        return Err(AppError::new(
-            StatusCode::INTERNAL_SERVER_ERROR,
-            anyhow!("Failed to generate upload URL"),
-        ));
-    };
+    let entries = versions
+        .into_iter()
+        .map(|v| DocumentVersionEntry {
+            version_id: v.version_id,
+            created_at: v.last_modified,
+            is_latest: v.is_latest,
+        })
+        .collect();
 
-    if !url.starts_with("http") {
-        let base_url = generate_base_url(
-            &server_state.url,
-            &server_state.allowed_hosts,
-            &host.to_string(),
-        )?;
-        let upload_token = match token_source {
-            UploadTokenSource::Original => token.to_string(),
-            UploadTokenSource::MintNew {
-                content_type: ct,
-                content_length: cl,
-            } => {
-                let expiration_time = ExpirationTimeEpochMillis(
-                    current_time_epoch_millis() + DEFAULT_EXPIRATION_SECONDS * 1000,
-                );
-                authenticator
-                    .gen_file_token_auto(
-                        &file_hash,
-                        &doc_id,
-                        Authorization::Full,
-                        expiration_time,
-                        ct.as_deref(),
-                        cl,
-                        None,
-                    )
-                    .map_err(|e| {
-                        AppError::new(
-                            StatusCode::INTERNAL_SERVER_ERROR,
-                            anyhow!("Failed to generate file token: {}", e),
-                        )
-                    })?
-            }
-        };
-        let full_url = format!("{}{}?token={}", base_url, url, upload_token);
-        Ok(Json(FileUploadUrlResponse {
-            upload_url: full_url,
-        }))
-    } else {
-        // S3/cloud storage URL - return as-is
-        Ok(Json(FileUploadUrlResponse { upload_url: url }))
-    }
+    Ok(Json(DocumentVersionResponse { versions: entries }))
 }
 
-async fn handle_file_download_url(
+async fn handle_doc_version_text(
+    State(server_state): State<Arc<Server>>,
+    Path((doc_id, version_id)): Path<(String, String)>,
+    auth_header: Option<TypedHeader<headers::Authorization<headers::authorization::Bearer>>>,
+) -> Result<Json<DocumentVersionTextResponse>, AppError> {
+    let token = get_token_from_header(auth_header);
+    authorize_doc_history(&server_state, &doc_id, token.as_deref(), false)?;
+
+    let text = stored_version_text(&server_state, &doc_id, &version_id).await?;
+    Ok(Json(DocumentVersionTextResponse { version_id, text }))
+}
+
+async fn handle_doc_diff(
     State(server_state): State<Arc<Server>>,
     Path(doc_id): Path<String>,
-    TypedHeader(host): TypedHeader<headers::Host>,
-    Query(params): Query<FileDownloadQueryParams>,
+    Query(query): Query<DocDiffQuery>,
     auth_header: Option<TypedHeader<headers::Authorization<headers::authorization::Bearer>>>,
-) -> Result<Json<FileDownloadUrlResponse>, AppError> {
-    tracing::info!(doc_id = %doc_id, hash = ?params.hash, "Generating file download URL");
+) -> Result<Json<DocumentVersionDiffResponse>, AppError> {
+    let token = get_token_from_header(auth_header);
+    authorize_doc_history(&server_state, &doc_id, token.as_deref(), false)?;
 
-    // Get token
+    let old_text = stored_version_text(&server_state, &doc_id, &query.from).await?;
+    let (to, new_text) = match query.to {
+        Some(to) => {
+            let text = stored_version_text(&server_state, &doc_id, &to).await?;
+            (to, text)
+        }
+        None => {
+            let text = server_state
+                .doc_text(&doc_id)
+                .await
+                .map_err(|e| AppError::new(StatusCode::INTERNAL_SERVER_ERROR, e))?;
+            ("current".to_string(), text)
+        }
+    };
+
+    let diff = unified_text_diff(
+        &old_text,
+        &new_text,
+        &format!("{}@{}", doc_id, query.from),
+        &format!("{}@{}", doc_id, to),
+    );
+    Ok(Json(DocumentVersionDiffResponse {
+        from: query.from,
+        to,
+        diff,
+    }))
+}
+
+async fn handle_restore_doc_version(
+    State(server_state): State<Arc<Server>>,
+    Path((doc_id, version_id)): Path<(String, String)>,
+    Query(query): Query<RestoreVersionQuery>,
+    auth_header: Option<TypedHeader<headers::Authorization<headers::authorization::Bearer>>>,
+) -> Result<Json<DocumentRestoreResponse>, AppError> {
     let token = get_token_from_header(auth_header);
+    authorize_doc_history(&server_state, &doc_id, token.as_deref(), true)?;
 
-    // Local dev fast path: no authenticator configured → accept any download, validate hash only.
-    if server_state.authenticator.is_none() {
-        let hash = params.hash.ok_or_else(|| {
+    let edits = server_state
+        .restore_doc_version(&doc_id, &version_id, query.as_suggestions, None)
+        .await?;
+    Ok(Json(DocumentRestoreResponse {
+        version_id,
+        edits,
+        as_suggestions: query.as_suggestions,
+    }))
+}
+
+/// Text of a stored version of a doc; 404 when the store has no such version.
+async fn stored_version_text(
+    server_state: &Server,
+    doc_id: &str,
+    version_id: &str,
+) -> Result<String, AppError> {
+    server_state
+        .doc_version_text(doc_id, version_id)
+        .await
+        .map_err(|e| AppError::new(StatusCode::INTERNAL_SERVER_ERROR, e))?
+        .ok_or_else(|| {
             AppError::new(
-                StatusCode::BAD_REQUEST,
-                anyhow!("hash query parameter required"),
+                StatusCode::NOT_FOUND,
+                anyhow!("Version {} of {} not found", version_id, doc_id),
             )
-        })?;
-        if !validate_file_hash(&hash) {
-            return Err(AppError::new(
-                StatusCode::BAD_REQUEST,
-                anyhow!("Invalid file hash format"),
+        })
+}
+
+/// Markdown text (the `contents` Y.Text) of a persisted doc snapshot.
+fn snapshot_text(snapshot: &[u8]) -> Result<String> {
+    let doc = doc_from_snapshot(snapshot)?;
+    let txn = doc.transact();
+    Ok(match txn.get_text("contents") {
+        Some(text) => text.get_string(&txn),
+        None => String::new(),
+    })
+}
+
+/// Entries of one root map of a doc, with nested shared types flattened to
+/// plain values so they compare and re-insert like `Any::Map` filemeta.
+fn folder_map_entries(
+    doc: &yrs::Doc,
+    map_name: &str,
+) -> std::collections::HashMap<String, yrs::Any> {
+    let txn = doc.transact();
+    match txn.get_map(map_name) {
+        Some(map) => map
+            .iter(&txn)
+            .map(|(key, value)| (key.to_string(), value.to_json(&txn)))
+            .collect(),
+        None => std::collections::HashMap::new(),
+    }
+}
+
+fn folder_snapshot_manifest_key(folder_doc_id: &str, name: &str) -> String {
+    format!("{}/{}/{}.json", FOLDER_SNAPSHOT_PREFIX, folder_doc_id, name)
+}
+
+fn folder_snapshot_doc_key(folder_doc_id: &str, name: &str, doc_id: &str) -> String {
+    format!(
+        "{}/{}/{}/{}.ysweet",
+        FOLDER_SNAPSHOT_PREFIX, folder_doc_id, name, doc_id
+    )
+}
+
+fn store_app_error(e: y_sweet_core::store::StoreError) -> AppError {
+    AppError::new(StatusCode::INTERNAL_SERVER_ERROR, e.into())
+}
+
+/// Folder exports read loaded docs from memory, so they include edits not
+/// yet persisted, and everything else from the store without loading it.
+#[async_trait::async_trait]
+impl ExportSource for Server {
+    async fn doc_text(&self, doc_id: &str) -> Result<Option<String>> {
+        let awareness = self.docs.get(doc_id).map(|doc_ref| doc_ref.awareness());
+        if let Some(awareness) = awareness {
+            let guard = awareness.read().unwrap_or_else(|e| e.into_inner());
+            let txn = guard.doc.transact();
+            return Ok(Some(
+                txn.get_text("contents")
+                    .map(|text| text.get_string(&txn))
+                    .unwrap_or_default(),
             ));
         }
-        let Json(response) =
-            generate_file_download_url(&server_state, &doc_id, &hash, &host.to_string()).await?;
-        return Ok(Json(response));
+        match &self.store {
+            Some(store) => StoreExportSource(&***store).doc_text(doc_id).await,
+            None => Ok(None),
+        }
+    }
+
+    async fn blob(&self, doc_id: &str, hash: &str) -> Result<Option<Vec<u8>>> {
+        match &self.store {
+            Some(store) => StoreExportSource(&***store).blob(doc_id, hash).await,
+            None => Ok(None),
+        }
+    }
+}
+
+/// Apply edits from `critic_diff::text_edits` (descending offsets) to a Y.Text.
+pub(crate) fn apply_text_edits(
+    txn: &mut yrs::TransactionMut,
+    text: &yrs::TextRef,
+    edits: &[crate::mcp::tools::critic_diff::TextEdit],
+) {
+    for edit in edits {
+        if edit.delete_len > 0 {
+            text.remove_range(txn, edit.offset as u32, edit.delete_len as u32);
+        }
+        if !edit.insert.is_empty() {
+            text.insert(txn, edit.offset as u32, &edit.insert);
+        }
     }
+}
+
+/// Unified line diff between two texts, with `old_label` and `new_label` in
+/// the `---`/`+++` header. Empty when the texts are identical.
+pub fn unified_text_diff(old: &str, new: &str, old_label: &str, new_label: &str) -> String {
+    similar::TextDiff::from_lines(old, new)
+        .unified_diff()
+        .header(old_label, new_label)
+        .to_string()
+}
+
+async fn handle_file_head(
+    State(server_state): State<Arc<Server>>,
+    Path(doc_id): Path<String>,
+    auth_header: Option<TypedHeader<headers::Authorization<headers::authorization::Bearer>>>,
+) -> Result<StatusCode, AppError> {
+    // Get token
+    let token = get_token_from_header(auth_header);
 
-    // Check if we have authentication configured
+    // Verify token is for this doc_id
     if let Some(authenticator) = &server_state.authenticator {
         if let Some(token) = token.as_deref() {
-            // Extract hash from query parameter if present
-            let query_hash = params.hash;
+            // Verify token is for this doc_id
+            let auth = authenticator
+                .verify_file_token_for_doc(token, &doc_id, current_time_epoch_millis())
+                .map_err(|e| {
+                    AppError::auth(
+                        StatusCode::UNAUTHORIZED,
+                        anyhow!("Invalid token: {}", e),
+                        "invalid_token",
+                    )
+                })?;
 
-            // Verify the token and determine its type
+            // ReadOnly, Suggest and Full can check if a file exists
+            if !matches!(
+                auth,
+                Authorization::ReadOnly | Authorization::Suggest | Authorization::Full
+            ) {
+                return Err(AppError::auth(
+                    StatusCode::FORBIDDEN,
+                    anyhow!("Insufficient permissions to access file"),
+                    "insufficient_permissions",
+                ));
+            }
+
+            // Verify the token and get the file hash
             let permission = authenticator
                 .verify_token_auto(token, current_time_epoch_millis())
                 .map_err(|_| {
@@ -6242,191 +10324,52 @@ async fn handle_file_download_url(
                     )
                 })?;
 
-            match permission {
-                Permission::File(file_permission) => {
-                    // Check if file token is for this doc_id
-                    if file_permission.doc_id != doc_id {
-                        return Err(AppError::auth(
-                            StatusCode::UNAUTHORIZED,
-                            anyhow!("Token not valid for this document"),
-                            "access_wrong_document",
-                        ));
-                    }
-
-                    // Both ReadOnly and Full can download files
-                    if !matches!(
-                        file_permission.authorization,
-                        Authorization::ReadOnly | Authorization::Full
-                    ) {
-                        return Err(AppError::auth(
-                            StatusCode::FORBIDDEN,
-                            anyhow!("Insufficient permissions to download file"),
-                            "insufficient_permissions",
-                        ));
-                    }
-
-                    let file_hash = file_permission.file_hash;
-
-                    // Validate the file hash
-                    if !validate_file_hash(&file_hash) {
-                        return Err(AppError::new(
-                            StatusCode::BAD_REQUEST,
-                            anyhow!("Invalid file hash format in token"),
-                        ));
-                    }
+            if let Permission::File(file_permission) = permission {
+                let file_hash = file_permission.file_hash;
 
-                    // Generate download URL using hash from token
-                    let Json(download_response) = generate_file_download_url(
-                        &server_state,
-                        &doc_id,
-                        &file_hash,
-                        &host.to_string(),
-                    )
-                    .await?;
-                    // Add token to the URL
-                    let mut download_url = download_response.download_url;
-                    if !download_url.starts_with("http") || download_url.contains("/f/") {
-                        // This is our local endpoint, add token
-                        let separator = if download_url.contains('?') { "&" } else { "?" };
-                        download_url = format!("{}{}token={}", download_url, separator, token);
-                    }
-                    return Ok(Json(FileDownloadUrlResponse { download_url }));
+                // Validate the file hash
+                if !validate_file_hash(&file_hash) {
+                    return Err(AppError::new(
+                        StatusCode::BAD_REQUEST,
+                        anyhow!("Invalid file hash format in token"),
+                    ));
                 }
-                Permission::Server => {
-                    // Server token is valid, use hash from query parameter
-                    if let Some(hash) = query_hash {
-                        // Validate the file hash from query parameter
-                        if !validate_file_hash(&hash) {
-                            return Err(AppError::new(
-                                StatusCode::BAD_REQUEST,
-                                anyhow!("Invalid file hash format in query parameter"),
-                            ));
-                        }
 
-                        // Generate download URL using hash from query parameter
-                        let Json(download_response) = generate_file_download_url(
-                            &server_state,
-                            &doc_id,
-                            &hash,
-                            &host.to_string(),
-                        )
-                        .await?;
-                        // Add file token to the URL (not the server token)
-                        let mut download_url = download_response.download_url;
-                        if !download_url.starts_with("http") || download_url.contains("/f/") {
-                            // This is our local endpoint, generate a proper file token
-                            let expiration_time = ExpirationTimeEpochMillis(
-                                current_time_epoch_millis() + DEFAULT_EXPIRATION_SECONDS * 1000,
-                            );
-                            let file_token = authenticator
-                                .gen_file_token_auto(
-                                    &hash,
-                                    &doc_id,
-                                    Authorization::Full,
-                                    expiration_time,
-                                    None,
-                                    None,
-                                    None,
-                                )
-                                .map_err(|e| {
-                                    AppError::new(
-                                        StatusCode::INTERNAL_SERVER_ERROR,
-                                        anyhow!("Failed to generate file token: {}", e),
-                                    )
-                                })?;
-                            let separator = if download_url.contains('?') { "&" } else { "?" };
-                            download_url =
-                                format!("{}{}token={}", download_url, separator, file_token);
-                        }
-                        return Ok(Json(FileDownloadUrlResponse { download_url }));
-                    } else {
-                        return Err(AppError::new(
-                            StatusCode::BAD_REQUEST,
-                            anyhow!("Hash query parameter required when using server token"),
-                        ));
-                    }
-                }
-                Permission::Doc(_) => {
+                // Check if we have a store configured
+                if server_state.store.is_none() {
                     return Err(AppError::new(
-                        StatusCode::BAD_REQUEST,
-                        anyhow!("Document tokens cannot be used for file operations"),
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        anyhow!("No store configured for file operations"),
                     ));
                 }
-                Permission::Prefix(prefix_perm) => {
-                    // Check if doc_id matches the prefix
-                    if !doc_id.starts_with(&prefix_perm.prefix) {
-                        return Err(AppError::auth(
-                            StatusCode::FORBIDDEN,
-                            anyhow!("Token not valid for this document"),
-                            "prefix_mismatch",
-                        ));
-                    }
 
-                    // Both ReadOnly and Full can download files
-                    if !matches!(
-                        prefix_perm.authorization,
-                        Authorization::ReadOnly | Authorization::Full
-                    ) {
-                        return Err(AppError::auth(
-                            StatusCode::FORBIDDEN,
-                            anyhow!("Insufficient permissions to download file"),
-                            "insufficient_permissions",
-                        ));
-                    }
+                // Construct the file path with proper format - using doc_id/file_hash
+                let key = format!("files/{}/{}", doc_id, file_hash);
 
-                    // Use hash from query parameter for prefix tokens
-                    if let Some(hash) = query_hash {
-                        // Validate the file hash from query parameter
-                        if !validate_file_hash(&hash) {
-                            return Err(AppError::new(
-                                StatusCode::BAD_REQUEST,
-                                anyhow!("Invalid file hash format in query parameter"),
-                            ));
-                        }
+                // Check if the file exists with a direct call to S3
+                let exists = server_state
+                    .store
+                    .as_ref()
+                    .unwrap()
+                    .exists(&key)
+                    .await
+                    .map_err(|e| AppError::new(StatusCode::INTERNAL_SERVER_ERROR, e.into()))?;
 
-                        // Generate download URL using hash from query parameter
-                        let Json(download_response) = generate_file_download_url(
-                            &server_state,
-                            &doc_id,
-                            &hash,
-                            &host.to_string(),
-                        )
-                        .await?;
-                        // Add file token to the URL (not the prefix token)
-                        let mut download_url = download_response.download_url;
-                        if !download_url.starts_with("http") || download_url.contains("/f/") {
-                            // This is our local endpoint, generate a proper file token
-                            let expiration_time = ExpirationTimeEpochMillis(
-                                current_time_epoch_millis() + DEFAULT_EXPIRATION_SECONDS * 1000,
-                            );
-                            let file_token = authenticator
-                                .gen_file_token_auto(
-                                    &hash,
-                                    &doc_id,
-                                    prefix_perm.authorization,
-                                    expiration_time,
-                                    None,
-                                    None,
-                                    None,
-                                )
-                                .map_err(|e| {
-                                    AppError::new(
-                                        StatusCode::INTERNAL_SERVER_ERROR,
-                                        anyhow!("Failed to generate file token: {}", e),
-                                    )
-                                })?;
-                            let separator = if download_url.contains('?') { "&" } else { "?" };
-                            download_url =
-                                format!("{}{}token={}", download_url, separator, file_token);
-                        }
-                        return Ok(Json(FileDownloadUrlResponse { download_url }));
-                    } else {
-                        return Err(AppError::new(
-                            StatusCode::BAD_REQUEST,
-                            anyhow!("Hash query parameter required when using prefix token"),
-                        ));
-                    }
+                if exists {
+                    tracing::debug!("File exists: {}/{}", doc_id, file_hash);
+                    return Ok(StatusCode::OK);
+                } else {
+                    tracing::debug!("File not found: {}/{}", doc_id, file_hash);
+                    return Err(AppError::new(
+                        StatusCode::NOT_FOUND,
+                        anyhow!("File not found"),
+                    ));
                 }
+            } else {
+                return Err(AppError::new(
+                    StatusCode::BAD_REQUEST,
+                    anyhow!("Token is not a file token"),
+                ));
             }
         } else {
             return Err(AppError::auth(
@@ -6435,1787 +10378,2989 @@ async fn handle_file_download_url(
                 "missing_token",
             ));
         }
-    } else {
-        // No auth configured
-        return Err(AppError::auth(
-            StatusCode::UNAUTHORIZED,
-            anyhow!("Authentication is required for file operations"),
-            "no_authenticator",
-        ));
+    } else {
+        // No auth configured
+        return Err(AppError::auth(
+            StatusCode::UNAUTHORIZED,
+            anyhow!("Authentication is required for file operations"),
+            "no_authenticator",
+        ));
+    }
+}
+
+async fn reload_webhook_config_endpoint(
+    State(server_state): State<Arc<Server>>,
+    auth_header: Option<TypedHeader<headers::Authorization<headers::authorization::Bearer>>>,
+) -> Result<Json<Value>, AppError> {
+    // Get token
+    let token = get_token_from_header(auth_header);
+
+    // Verify token is server token (for server admin operations)
+    if let Some(authenticator) = &server_state.authenticator {
+        if let Some(token) = token.as_deref() {
+            // Verify this is a server admin token
+            authenticator
+                .verify_server_token(token, current_time_epoch_millis())
+                .map_err(|e| {
+                    AppError::auth(
+                        StatusCode::UNAUTHORIZED,
+                        anyhow!("Invalid token: {}", e),
+                        "invalid_token",
+                    )
+                })?;
+        } else {
+            return Err(AppError::auth(
+                StatusCode::UNAUTHORIZED,
+                anyhow!("No token provided"),
+                "missing_token",
+            ));
+        }
+    }
+
+    // Reload webhook configuration
+    match server_state.reload_webhook_config().await {
+        Ok(status) => Ok(Json(json!({
+            "status": "success",
+            "message": status
+        }))),
+        Err(e) => {
+            tracing::error!("Failed to reload webhook config: {}", e);
+            Err(AppError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                anyhow!("Failed to reload webhook configuration: {}", e),
+            ))
+        }
     }
 }
 
-async fn generate_file_download_url(
-    server_state: &Arc<Server>,
-    doc_id: &str,
-    file_hash: &str,
-    host: &str,
-) -> Result<Json<FileDownloadUrlResponse>, AppError> {
-    // Check if we have a store configured
-    if server_state.store.is_none() {
-        return Err(AppError::new(
+async fn metrics_endpoint(State(_server_state): State<Arc<Server>>) -> Result<String, AppError> {
+    use prometheus::{Encoder, TextEncoder};
+
+    let encoder = TextEncoder::new();
+    let metric_families = prometheus::gather();
+    let mut buffer = Vec::new();
+
+    encoder.encode(&metric_families, &mut buffer).map_err(|e| {
+        (
             StatusCode::INTERNAL_SERVER_ERROR,
-            anyhow!("No store configured for file downloads"),
-        ));
-    }
+            anyhow!("Failed to encode metrics: {}", e),
+        )
+    })?;
 
-    // Generate the download URL - using doc_id/file_hash path structure
-    let key = format!("files/{}/{}", doc_id, file_hash);
-    let download_url = server_state
-        .store
-        .as_ref()
-        .unwrap()
-        .generate_download_url(&key)
-        .await
-        .map_err(|e| AppError::new(StatusCode::INTERNAL_SERVER_ERROR, e.into()))?;
+    Ok(String::from_utf8(buffer).map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            anyhow!("Failed to convert metrics to string: {}", e),
+        )
+    })?)
+}
 
-    if let Some(url) = download_url {
-        // Check if this is a local endpoint (relative path) and convert to full URL
-        if !url.starts_with("http") {
-            let base_url = generate_base_url(&server_state.url, &server_state.allowed_hosts, host)?;
-            let full_url = format!("{}{}", base_url, url);
-            Ok(Json(FileDownloadUrlResponse {
-                download_url: full_url,
-            }))
-        } else {
-            // S3/cloud storage URL - return as-is
-            Ok(Json(FileDownloadUrlResponse { download_url: url }))
+#[cfg(test)]
+mod test {
+    use super::*;
+    use axum::body::{to_bytes, Body};
+    use axum::http::{Method, Request};
+    use serde_json::Value as JsonValue;
+    use tower::util::ServiceExt;
+    use y_sweet_core::api_types::Authorization;
+    use y_sweet_core::auth::ExpirationTimeEpochMillis;
+    use y_sweet_core::critic_scanner::scan_suggestions;
+    use y_sweet_core::doc_sync::DocWithSyncKv;
+    use yrs::GetString;
+    use yrs::{Any, Map, ReadTxn, Text, Transact, WriteTxn};
+
+    const TEST_RELAY_ID: &str = "cb696037-0f72-4e93-8717-4e433129d789";
+    const TEST_FOLDER_UUID: &str = "b0000001-0000-4000-8000-000000000001";
+
+    async fn insert_test_folder_doc(
+        server: &Arc<Server>,
+        folder_name: &str,
+        entries: &[(&str, &str, &str)],
+    ) -> String {
+        let folder_doc_id = format!("{}-{}", TEST_RELAY_ID, TEST_FOLDER_UUID);
+        let dwskv = DocWithSyncKv::new(&folder_doc_id, None, || (), None)
+            .await
+            .unwrap();
+        {
+            let awareness = dwskv.awareness();
+            let guard = awareness.write().unwrap();
+            let mut txn = guard.doc.transact_mut();
+            let config = txn.get_or_insert_map("folder_config");
+            config.insert(&mut txn, "name", Any::String(folder_name.into()));
+            let filemeta = txn.get_or_insert_map("filemeta_v0");
+            let docs_map = txn.get_or_insert_map("docs");
+            for (path, uuid, entry_type) in entries {
+                let mut map = std::collections::HashMap::new();
+                map.insert("id".to_string(), Any::String((*uuid).into()));
+                map.insert("type".to_string(), Any::String((*entry_type).into()));
+                map.insert("version".to_string(), Any::Number(0.0));
+                filemeta.insert(&mut txn, *path, Any::Map(map.into()));
+                docs_map.insert(&mut txn, *path, Any::String((*uuid).into()));
+            }
         }
-    } else {
-        Err(AppError::new(
-            StatusCode::NOT_FOUND,
-            anyhow!("File not found"),
-        ))
+        server.docs().insert(folder_doc_id.clone(), dwskv);
+        server.doc_resolver().rebuild(server.docs());
+        folder_doc_id
+    }
+
+    async fn insert_test_content_doc(server: &Arc<Server>, uuid: &str, content: &str) {
+        let doc_id = format!("{}-{}", TEST_RELAY_ID, uuid);
+        let dwskv = DocWithSyncKv::new(&doc_id, None, || (), None)
+            .await
+            .unwrap();
+        {
+            let awareness = dwskv.awareness();
+            let guard = awareness.write().unwrap();
+            let mut txn = guard.doc.transact_mut();
+            let text = txn.get_or_insert_text("contents");
+            text.insert(&mut txn, 0, content);
+        }
+        server.docs().insert(doc_id, dwskv);
+    }
+
+    fn insert_backlink(
+        server: &Arc<Server>,
+        folder_doc_id: &str,
+        target_uuid: &str,
+        backlinker_uuid: &str,
+    ) {
+        let doc_ref = server.docs().get(folder_doc_id).unwrap();
+        let awareness = doc_ref.awareness();
+        let guard = awareness.write().unwrap();
+        let mut txn = guard.doc.transact_mut();
+        let backlinks = txn.get_or_insert_map("backlinks_v0");
+        backlinks.insert(
+            &mut txn,
+            target_uuid,
+            vec![Any::String(backlinker_uuid.into())],
+        );
+    }
+
+    fn filemeta_has(server: &Arc<Server>, folder_doc_id: &str, path: &str) -> bool {
+        let doc_ref = server.docs().get(folder_doc_id).unwrap();
+        let awareness = doc_ref.awareness();
+        let guard = awareness.read().unwrap();
+        let txn = guard.doc.transact();
+        txn.get_map("filemeta_v0")
+            .and_then(|m| m.get(&txn, path))
+            .is_some()
+    }
+
+    fn legacy_docs_value(server: &Arc<Server>, folder_doc_id: &str, path: &str) -> Option<String> {
+        let doc_ref = server.docs().get(folder_doc_id).unwrap();
+        let awareness = doc_ref.awareness();
+        let guard = awareness.read().unwrap();
+        let txn = guard.doc.transact();
+        txn.get_map("docs").and_then(|m| match m.get(&txn, path) {
+            Some(yrs::Out::Any(Any::String(value))) => Some(value.to_string()),
+            _ => None,
+        })
+    }
+
+    fn content_text(server: &Arc<Server>, uuid: &str) -> String {
+        let doc_id = format!("{}-{}", TEST_RELAY_ID, uuid);
+        let doc_ref = server.docs().get(&doc_id).unwrap();
+        let awareness = doc_ref.awareness();
+        let guard = awareness.read().unwrap();
+        let txn = guard.doc.transact();
+        txn.get_text("contents")
+            .map(|text| text.get_string(&txn))
+            .unwrap_or_default()
+    }
+
+    async fn post_move(server: &Arc<Server>, body: JsonValue) -> (StatusCode, JsonValue) {
+        let response = server
+            .routes()
+            .oneshot(
+                Request::builder()
+                    .method(Method::POST)
+                    .uri("/move")
+                    .header("content-type", "application/json")
+                    .body(Body::from(body.to_string()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let status = response.status();
+        let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body = serde_json::from_slice(&bytes).unwrap_or_else(|_| json!({}));
+        (status, body)
+    }
+
+    async fn post_request(
+        server: &Arc<Server>,
+        uri: &str,
+        content_type: &str,
+        body: Body,
+    ) -> (StatusCode, String) {
+        let response = server
+            .routes()
+            .oneshot(
+                Request::builder()
+                    .method(Method::POST)
+                    .uri(uri)
+                    .header("content-type", content_type)
+                    .body(body)
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let status = response.status();
+        let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, String::from_utf8_lossy(&bytes).into_owned())
+    }
+
+    async fn post_check_video_ids(
+        server: &Arc<Server>,
+        body: JsonValue,
+    ) -> (StatusCode, JsonValue) {
+        let response = server
+            .routes()
+            .oneshot(
+                Request::builder()
+                    .method(Method::POST)
+                    .uri("/doc/check-video-ids")
+                    .header("content-type", "application/json")
+                    .body(Body::from(body.to_string()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let status = response.status();
+        let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body = serde_json::from_slice(&bytes).unwrap_or_else(|_| json!({}));
+        (status, body)
     }
-}
 
-/// Delete all files for a document
-///
-/// This endpoint accepts either:
-/// - A file token with the doc_id (hash not required)
-/// - A doc token with the doc_id
-/// - A server token
-///
-/// Returns 204 No Content on success
-async fn handle_file_delete(
-    State(server_state): State<Arc<Server>>,
-    Path(doc_id): Path<String>,
-    auth_header: Option<TypedHeader<headers::Authorization<headers::authorization::Bearer>>>,
-) -> Result<StatusCode, AppError> {
-    // Get token
-    let token = get_token_from_header(auth_header);
+    #[tokio::test]
+    async fn move_path_file_moves_existing_file() {
+        let server = Server::new_for_test();
+        let folder_doc_id = insert_test_folder_doc(
+            &server,
+            "Relay Folder 1",
+            &[(
+                "/Old.md",
+                "11111111-1111-4111-8111-111111111111",
+                "markdown",
+            )],
+        )
+        .await;
+        insert_test_content_doc(&server, "11111111-1111-4111-8111-111111111111", "Old").await;
 
-    // Verify token is for this doc_id and has required permission
-    if let Some(authenticator) = &server_state.authenticator {
-        if let Some(token) = token.as_deref() {
-            // Verify token is for this doc_id
-            let auth = authenticator
-                .verify_file_token_for_doc(token, &doc_id, current_time_epoch_millis())
-                .map_err(|e| {
-                    AppError::auth(
-                        StatusCode::UNAUTHORIZED,
-                        anyhow!("Invalid token: {}", e),
-                        "invalid_token",
-                    )
-                })?;
+        let result = server
+            .move_path("Relay Folder 1/Old.md", "/New.md", None)
+            .await
+            .unwrap();
 
-            // Only Full permission can delete files
-            if !matches!(auth, Authorization::Full) {
-                return Err(AppError::auth(
-                    StatusCode::FORBIDDEN,
-                    anyhow!("Insufficient permissions to delete files"),
-                    "insufficient_permissions",
-                ));
-            }
+        assert_eq!(result.old_path, "/Old.md");
+        assert_eq!(result.new_path, "/New.md");
+        assert!(!filemeta_has(&server, &folder_doc_id, "/Old.md"));
+        assert!(filemeta_has(&server, &folder_doc_id, "/New.md"));
+    }
 
-            // Check if we have a store configured
-            if server_state.store.is_none() {
-                return Err(AppError::new(
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    anyhow!("No store configured for file operations"),
-                ));
-            }
+    #[tokio::test]
+    async fn move_path_rejects_double_quotes_without_mutation() {
+        let server = Server::new_for_test();
+        let folder_doc_id = insert_test_folder_doc(
+            &server,
+            "Relay Folder 1",
+            &[(
+                "/Old.md",
+                "11111111-1111-4111-8111-111111111111",
+                "markdown",
+            )],
+        )
+        .await;
+        insert_test_content_doc(&server, "11111111-1111-4111-8111-111111111111", "Old").await;
 
-            // List all files in the document's directory
-            let prefix = format!("files/{}/", doc_id);
-            let store = server_state.store.as_ref().unwrap();
+        let result = server
+            .move_path("Relay Folder 1/Old.md", "/Bad \"Name\".md", None)
+            .await;
 
-            let file_infos = store
-                .list(&prefix)
-                .await
-                .map_err(|e| AppError::new(StatusCode::INTERNAL_SERVER_ERROR, e.into()))?;
+        assert!(matches!(result, Err(MoveDocumentError::BadRequest(_))));
+        assert!(filemeta_has(&server, &folder_doc_id, "/Old.md"));
+        assert!(!filemeta_has(&server, &folder_doc_id, "/Bad \"Name\".md"));
+    }
 
-            if file_infos.is_empty() {
-                tracing::info!("No files to delete for document: {}", doc_id);
-                return Ok(StatusCode::NO_CONTENT);
-            }
+    #[tokio::test]
+    async fn create_document_rejects_double_quotes_without_mutation() {
+        let server = Server::new_for_test();
+        let folder_doc_id = insert_test_folder_doc(&server, "Relay Folder 1", &[]).await;
 
-            // Delete each file
-            let mut deleted_count = 0;
-            for file_info in file_infos {
-                let key = file_info.key;
-                if let Err(e) = store.remove(&format!("files/{}/{}", doc_id, key)).await {
-                    tracing::error!("Failed to delete file {}/{}: {}", doc_id, key, e);
-                    continue;
-                }
-                deleted_count += 1;
-            }
+        let result = server
+            .create_document("Relay Folder 1", "/Bad \"Name\".md", "content", None)
+            .await;
 
-            tracing::info!("Deleted {} files for document: {}", deleted_count, doc_id);
-            return Ok(StatusCode::NO_CONTENT);
-        } else {
-            return Err(AppError::auth(
-                StatusCode::UNAUTHORIZED,
-                anyhow!("No token provided"),
-                "missing_token",
-            ));
-        }
-    } else {
-        // No auth configured
-        return Err(AppError::auth(
-            StatusCode::UNAUTHORIZED,
-            anyhow!("Authentication is required for file operations"),
-            "no_authenticator",
-        ));
+        assert!(matches!(result, Err(CreateDocumentError::BadRequest(_))));
+        assert!(!filemeta_has(&server, &folder_doc_id, "/Bad \"Name\".md"));
     }
-}
 
-/// Delete a specific file by hash
-///
-/// This endpoint accepts either:
-/// - A file token with the doc_id (hash not required)
-/// - A doc token with the doc_id
-/// - A server token
-///
-/// The hash to delete is specified in the URL path.
-/// Returns 204 No Content on success, 404 if file not found
-async fn handle_file_delete_by_hash(
-    State(server_state): State<Arc<Server>>,
-    Path((doc_id, file_hash)): Path<(String, String)>,
-    auth_header: Option<TypedHeader<headers::Authorization<headers::authorization::Bearer>>>,
-) -> Result<StatusCode, AppError> {
-    // Get token
-    let token = get_token_from_header(auth_header);
+    #[tokio::test]
+    async fn upsert_document_rejects_double_quotes_as_bad_request() {
+        let server = Server::new_for_test();
+        insert_test_folder_doc(&server, "Relay Folder 1", &[]).await;
+        let request = json!({
+            "folder": "Relay Folder 1",
+            "path": "/Bad \"Name\".md",
+            "content": "content"
+        });
 
-    // Verify token is for this doc_id and has required permission
-    if let Some(authenticator) = &server_state.authenticator {
-        if let Some(token) = token.as_deref() {
-            // Verify token is for this doc_id
-            let auth = authenticator
-                .verify_file_token_for_doc(token, &doc_id, current_time_epoch_millis())
-                .map_err(|e| {
-                    AppError::auth(
-                        StatusCode::UNAUTHORIZED,
-                        anyhow!("Invalid token: {}", e),
-                        "invalid_token",
-                    )
-                })?;
+        let (status, body) = post_request(
+            &server,
+            "/doc/upsert",
+            "application/json",
+            Body::from(request.to_string()),
+        )
+        .await;
 
-            // Only Full permission can delete files
-            if !matches!(auth, Authorization::Full) {
-                return Err(AppError::auth(
-                    StatusCode::FORBIDDEN,
-                    anyhow!("Insufficient permissions to delete file"),
-                    "insufficient_permissions",
-                ));
-            }
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body.contains("File names cannot contain double quotes"));
+    }
 
-            // Validate the file hash format
-            if !validate_file_hash(&file_hash) {
-                return Err(AppError::new(
-                    StatusCode::BAD_REQUEST,
-                    anyhow!("Invalid file hash format"),
-                ));
-            }
+    #[tokio::test]
+    async fn upsert_blob_rejects_double_quotes_as_bad_request() {
+        let server = Server::new_for_test();
+        insert_test_folder_doc(&server, "Relay Folder 1", &[]).await;
+        let request = json!({
+            "folder": "Relay Folder 1",
+            "path": "/Bad \"Name\".json",
+            "content": "{}"
+        });
 
-            // Check if we have a store configured
-            if server_state.store.is_none() {
-                return Err(AppError::new(
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    anyhow!("No store configured for file operations"),
-                ));
-            }
+        let (status, body) = post_request(
+            &server,
+            "/doc/upsert",
+            "application/json",
+            Body::from(request.to_string()),
+        )
+        .await;
 
-            // Construct the file path
-            let key = format!("files/{}/{}", doc_id, file_hash);
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body.contains("File names cannot contain double quotes"));
+    }
 
-            // Check if the file exists before trying to delete it
-            let exists = server_state
-                .store
-                .as_ref()
-                .unwrap()
-                .exists(&key)
-                .await
-                .map_err(|e| AppError::new(StatusCode::INTERNAL_SERVER_ERROR, e.into()))?;
+    #[tokio::test]
+    async fn upsert_attachment_rejects_double_quotes_as_bad_request() {
+        let server = Server::new_for_test();
+        insert_test_folder_doc(&server, "Relay Folder 1", &[]).await;
 
-            if !exists {
-                // If the file is already gone, return 204 No Content since DELETE is idempotent
-                tracing::debug!("File already deleted: {}/{}", doc_id, file_hash);
-                return Ok(StatusCode::NO_CONTENT);
-            }
+        let (status, body) = post_request(
+            &server,
+            "/doc/attachment?folder=Relay%20Folder%201&path=%2FBad%20%22Name%22.png",
+            "application/octet-stream",
+            Body::from(vec![1, 2, 3]),
+        )
+        .await;
 
-            // Delete the file
-            server_state
-                .store
-                .as_ref()
-                .unwrap()
-                .remove(&key)
-                .await
-                .map_err(|e| AppError::new(StatusCode::INTERNAL_SERVER_ERROR, e.into()))?;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body.contains("File names cannot contain double quotes"));
+    }
 
-            tracing::info!("Deleted file: {}/{}", doc_id, file_hash);
-            return Ok(StatusCode::NO_CONTENT);
-        } else {
-            return Err(AppError::auth(
-                StatusCode::UNAUTHORIZED,
-                anyhow!("No token provided"),
-                "missing_token",
-            ));
+    /// Add a filemeta + legacy-docs entry to an already-loaded folder doc WITHOUT
+    /// refreshing the resolver. Simulates a freshly-created file that the client
+    /// wrote to the folder Y.Doc but the link-indexer worker has not yet (or never,
+    /// if stalled) registered in the in-memory resolver.
+    fn add_filemeta_entry_without_resolver(
+        server: &Arc<Server>,
+        folder_doc_id: &str,
+        path: &str,
+        uuid: &str,
+        entry_type: &str,
+    ) {
+        let doc_ref = server.docs().get(folder_doc_id).unwrap();
+        let awareness = doc_ref.awareness();
+        let guard = awareness.write().unwrap();
+        let mut txn = guard.doc.transact_mut();
+        let filemeta = txn.get_or_insert_map("filemeta_v0");
+        let docs_map = txn.get_or_insert_map("docs");
+        let mut map = std::collections::HashMap::new();
+        map.insert("id".to_string(), Any::String(uuid.into()));
+        map.insert("type".to_string(), Any::String(entry_type.into()));
+        map.insert("version".to_string(), Any::Number(0.0));
+        filemeta.insert(&mut txn, path, Any::Map(map.into()));
+        if entry_type == "markdown" {
+            docs_map.insert(&mut txn, path, Any::String(uuid.into()));
         }
-    } else {
-        // No auth configured
-        return Err(AppError::auth(
-            StatusCode::UNAUTHORIZED,
-            anyhow!("Authentication is required for file operations"),
-            "no_authenticator",
-        ));
     }
-}
 
-/// Handle HEAD request to check if a file exists in S3 storage
-///
-/// Returns:
-/// - 200 OK if the file exists
-/// - 404 Not Found if the file doesn't exist
-/// - Other status codes for authentication/authorization errors
+    // Prevents: "Move failed: 400" when renaming a freshly created markdown file
+    // while the resolver is stale (link-indexer worker lagging or dead). The file
+    // IS in filemeta_v0 (source of truth) but NOT in the resolver, so without the
+    // filemeta fallback resolve_path() misses, move_path() falls through to the
+    // folder-move branch, and the ".md" destination is rejected with BadRequest.
+    #[tokio::test]
+    async fn move_path_renames_file_missing_from_stale_resolver() {
+        let server = Server::new_for_test();
+        // Folder doc loaded; resolver rebuilt while the folder had only old files.
+        let folder_doc_id = insert_test_folder_doc(
+            &server,
+            "Relay Folder 1",
+            &[(
+                "/Old.md",
+                "00000000-0000-4000-8000-000000000001",
+                "markdown",
+            )],
+        )
+        .await;
 
-/// Get the history of all files for a document
-///
-/// This endpoint accepts either:
-/// - A file token with the doc_id (hash not required)
-/// - A doc token with the doc_id
-/// - A server token
-async fn handle_file_history(
-    State(server_state): State<Arc<Server>>,
-    Path(doc_id): Path<String>,
-    auth_header: Option<TypedHeader<headers::Authorization<headers::authorization::Bearer>>>,
-) -> Result<Json<FileHistoryResponse>, AppError> {
-    // Get token
-    let token = get_token_from_header(auth_header);
+        // Freshly-created file: written to filemeta, but resolver never learned it.
+        let uuid = "11111111-1111-4111-8111-111111111111";
+        add_filemeta_entry_without_resolver(&server, &folder_doc_id, "/New.md", uuid, "markdown");
+        insert_test_content_doc(&server, uuid, "New").await;
 
-    // Verify token is for this doc_id
-    if let Some(authenticator) = &server_state.authenticator {
-        if let Some(token) = token.as_deref() {
-            // Verify token is for this doc_id - this now accepts both doc and file tokens
-            let auth = authenticator
-                .verify_file_token_for_doc(token, &doc_id, current_time_epoch_millis())
-                .map_err(|e| {
-                    AppError::auth(
-                        StatusCode::UNAUTHORIZED,
-                        anyhow!("Invalid token: {}", e),
-                        "invalid_token",
-                    )
-                })?;
+        // Precondition: the resolver really is stale for this path.
+        assert!(
+            server
+                .doc_resolver()
+                .resolve_path("Relay Folder 1/New.md")
+                .is_none(),
+            "test setup: resolver should not know the new file"
+        );
 
-            // Both ReadOnly and Full can view file history
-            if !matches!(auth, Authorization::ReadOnly | Authorization::Full) {
-                return Err(AppError::auth(
-                    StatusCode::FORBIDDEN,
-                    anyhow!("Insufficient permissions to view file history"),
-                    "insufficient_permissions",
-                ));
-            }
-        } else {
-            return Err(AppError::auth(
-                StatusCode::UNAUTHORIZED,
-                anyhow!("No token provided"),
-                "missing_token",
-            ));
-        }
+        // The user's exact action.
+        let result = server
+            .move_path("Relay Folder 1/New.md", "/Renamed.md", None)
+            .await;
+
+        assert!(
+            result.is_ok(),
+            "renaming a file that is in filemeta but missing from a stale resolver should succeed, got: {:?}",
+            result.err()
+        );
+        assert!(!filemeta_has(&server, &folder_doc_id, "/New.md"));
+        assert!(filemeta_has(&server, &folder_doc_id, "/Renamed.md"));
     }
 
-    // Check if we have a store configured
-    if server_state.store.is_none() {
-        return Err(AppError::new(
-            StatusCode::INTERNAL_SERVER_ERROR,
-            anyhow!("No store configured for file operations"),
-        ));
+    #[tokio::test]
+    async fn check_video_ids_detects_full_youtube_urls() {
+        let server = Server::new_for_test();
+        insert_test_folder_doc(
+            &server,
+            "Lens Edu",
+            &[
+                (
+                    "/video_transcripts/Short.md",
+                    "11111111-1111-4111-8111-111111111111",
+                    "markdown",
+                ),
+                (
+                    "/video_transcripts/Normal.md",
+                    "22222222-2222-4222-8222-222222222222",
+                    "markdown",
+                ),
+            ],
+        )
+        .await;
+        insert_test_content_doc(
+            &server,
+            "11111111-1111-4111-8111-111111111111",
+            "---\nurl: \"https://www.youtube.com/shorts/GMTDrG3hYJ0\"\n---\n",
+        )
+        .await;
+        insert_test_content_doc(
+            &server,
+            "22222222-2222-4222-8222-222222222222",
+            "---\nurl: \"https://www.youtube.com/watch?v=Nl7-bRFSZBs\"\n---\n",
+        )
+        .await;
+
+        let (status, body) = post_check_video_ids(
+            &server,
+            json!({
+                "folder": "Lens Edu",
+                "subfolder": "video_transcripts",
+                "video_ids": ["GMTDrG3hYJ0", "Nl7-bRFSZBs"]
+            }),
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["found"]["GMTDrG3hYJ0"], "/video_transcripts/Short.md");
+        assert_eq!(body["found"]["Nl7-bRFSZBs"], "/video_transcripts/Normal.md");
     }
 
-    // List files in the document's directory
-    let prefix = format!("files/{}/", doc_id);
-    let store = server_state.store.as_ref().unwrap();
+    #[tokio::test]
+    async fn check_video_ids_ignores_compact_youtube_paths() {
+        let server = Server::new_for_test();
+        insert_test_folder_doc(
+            &server,
+            "Lens Edu",
+            &[(
+                "/video_transcripts/Legacy.md",
+                "11111111-1111-4111-8111-111111111111",
+                "markdown",
+            )],
+        )
+        .await;
+        insert_test_content_doc(
+            &server,
+            "11111111-1111-4111-8111-111111111111",
+            "---\nurl: \"/GMTDrG3hYJ0\"\n---\n",
+        )
+        .await;
 
-    let file_infos = store
-        .list(&prefix)
-        .await
-        .map_err(|e| AppError::new(StatusCode::INTERNAL_SERVER_ERROR, e.into()))?;
+        let (status, body) = post_check_video_ids(
+            &server,
+            json!({
+                "folder": "Lens Edu",
+                "subfolder": "video_transcripts",
+                "video_ids": ["GMTDrG3hYJ0"]
+            }),
+        )
+        .await;
 
-    // Convert the raw file info into the API response format
-    let files = file_infos
-        .into_iter()
-        .map(|info| FileHistoryEntry {
-            hash: info.key,
-            size: info.size,
-            created_at: info.last_modified,
-        })
-        .collect();
+        assert_eq!(status, StatusCode::OK);
+        assert!(body["found"]["GMTDrG3hYJ0"].is_null());
+    }
 
-    Ok(Json(FileHistoryResponse { files }))
-}
+    async fn post_check_source_urls(
+        server: &Arc<Server>,
+        body: JsonValue,
+    ) -> (StatusCode, JsonValue) {
+        let response = server
+            .routes()
+            .oneshot(
+                Request::builder()
+                    .method(Method::POST)
+                    .uri("/doc/check-source-urls")
+                    .header("content-type", "application/json")
+                    .body(Body::from(body.to_string()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let status = response.status();
+        let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body = serde_json::from_slice(&bytes).unwrap_or_else(|_| json!({}));
+        (status, body)
+    }
 
-async fn handle_doc_versions(
-    State(server_state): State<Arc<Server>>,
-    Path(doc_id): Path<String>,
-    auth_header: Option<TypedHeader<headers::Authorization<headers::authorization::Bearer>>>,
-) -> Result<Json<DocumentVersionResponse>, AppError> {
-    let token = get_token_from_header(auth_header);
+    #[tokio::test]
+    async fn check_source_urls_matches_frontmatter_and_normalizes_trailing_slash() {
+        let server = Server::new_for_test();
+        insert_test_folder_doc(
+            &server,
+            "Lens Edu",
+            &[
+                (
+                    "/articles/a.md",
+                    "11111111-1111-4111-8111-111111111111",
+                    "markdown",
+                ),
+                (
+                    "/articles/b.md",
+                    "22222222-2222-4222-8222-222222222222",
+                    "markdown",
+                ),
+            ],
+        )
+        .await;
+        insert_test_content_doc(
+            &server,
+            "11111111-1111-4111-8111-111111111111",
+            "---\ntitle: \"A\"\nsource_url: \"https://ai-safety-atlas.com/chapters/v1/risks/introduction\"\n---\n\nBody.",
+        )
+        .await;
+        insert_test_content_doc(
+            &server,
+            "22222222-2222-4222-8222-222222222222",
+            "---\ntitle: \"B\"\nsource_url: \"https://example.com/post\"\ntags:\n  - article-stub\n  - validator-ignore\n---\n\n%%\nA discussion note.\n%%",
+        )
+        .await;
 
-    if let Some(authenticator) = &server_state.authenticator {
-        if let Some(token) = token.as_deref() {
-            let auth = authenticator
-                .verify_doc_token(token, &doc_id, current_time_epoch_millis())
-                .map_err(|e| {
-                    AppError::auth(
-                        StatusCode::UNAUTHORIZED,
-                        anyhow!("Invalid token: {}", e),
-                        "invalid_token",
-                    )
-                })?;
+        let (status, body) = post_check_source_urls(
+            &server,
+            json!({
+                "folder": "Lens Edu",
+                "subfolder": "articles",
+                "source_urls": [
+                    // trailing slash on the query must still match the stored URL
+                    "https://ai-safety-atlas.com/chapters/v1/risks/introduction/",
+                    "https://example.com/post",
+                    "https://unimported.example/x"
+                ]
+            }),
+        )
+        .await;
 
-            if !matches!(auth, Authorization::ReadOnly | Authorization::Full) {
-                return Err(AppError::auth(
-                    StatusCode::FORBIDDEN,
-                    anyhow!("Insufficient permissions to view document versions"),
-                    "insufficient_permissions",
-                ));
-            }
-        } else {
-            return Err(AppError::auth(
-                StatusCode::UNAUTHORIZED,
-                anyhow!("No token provided"),
-                "missing_token",
-            ));
-        }
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            body["found"]["https://ai-safety-atlas.com/chapters/v1/risks/introduction/"],
+            "/articles/a.md"
+        );
+        assert_eq!(body["found"]["https://example.com/post"], "/articles/b.md");
+        assert_eq!(
+            body["stubs"]["https://example.com/post"]["path"],
+            "/articles/b.md"
+        );
+        assert!(body["stubs"]["https://example.com/post"]["content"]
+            .as_str()
+            .unwrap()
+            .contains("A discussion note."));
+        // Full articles remain lightweight: their bodies are not returned.
+        assert!(
+            body["stubs"]["https://ai-safety-atlas.com/chapters/v1/risks/introduction/"].is_null()
+        );
+        assert!(body["found"]["https://unimported.example/x"].is_null());
     }
 
-    let store = match &server_state.store {
-        Some(s) => s,
-        None => {
-            return Err(AppError::new(
-                StatusCode::INTERNAL_SERVER_ERROR,
-                anyhow!("No store configured for operations"),
-            ))
-        }
-    };
-
-    let key = format!("{}/data.ysweet", doc_id);
-    let versions = store
-        .list_versions(&key)
-        .await
-        .map_err(|e| AppError::new(StatusCode::INTERNAL_SERVER_ERROR, e.into()))?;
+    // Frontmatter-precise: a URL that appears only in the body must NOT match
+    // (unlike a naive substring scan).
+    #[tokio::test]
+    async fn check_source_urls_ignores_url_in_body_only() {
+        let server = Server::new_for_test();
+        insert_test_folder_doc(
+            &server,
+            "Lens Edu",
+            &[(
+                "/articles/a.md",
+                "11111111-1111-4111-8111-111111111111",
+                "markdown",
+            )],
+        )
+        .await;
+        insert_test_content_doc(
+            &server,
+            "11111111-1111-4111-8111-111111111111",
+            "---\ntitle: \"A\"\nsource_url: \"https://example.com/real\"\n---\n\nSee https://example.com/body-only for more.",
+        )
+        .await;
 
-    let entries = versions
-        .into_iter()
-        .map(|v| DocumentVersionEntry {
-            version_id: v.version_id,
-            created_at: v.last_modified,
-            is_latest: v.is_latest,
-        })
-        .collect();
+        let (status, body) = post_check_source_urls(
+            &server,
+            json!({
+                "folder": "Lens Edu",
+                "subfolder": "articles",
+                "source_urls": ["https://example.com/body-only"]
+            }),
+        )
+        .await;
 
-    Ok(Json(DocumentVersionResponse { versions: entries }))
-}
+        assert_eq!(status, StatusCode::OK);
+        assert!(body["found"]["https://example.com/body-only"].is_null());
+    }
 
-async fn handle_file_head(
-    State(server_state): State<Arc<Server>>,
-    Path(doc_id): Path<String>,
-    auth_header: Option<TypedHeader<headers::Authorization<headers::authorization::Bearer>>>,
-) -> Result<StatusCode, AppError> {
-    // Get token
-    let token = get_token_from_header(auth_header);
+    #[tokio::test]
+    async fn move_path_renames_non_markdown_file_metadata() {
+        let server = Server::new_for_test();
+        let folder_doc_id = insert_test_folder_doc(
+            &server,
+            "Relay Folder 1",
+            &[(
+                "/source.timestamps.json",
+                "22222222-2222-4222-8222-222222222222",
+                "file",
+            )],
+        )
+        .await;
 
-    // Verify token is for this doc_id
-    if let Some(authenticator) = &server_state.authenticator {
-        if let Some(token) = token.as_deref() {
-            // Verify token is for this doc_id
-            let auth = authenticator
-                .verify_file_token_for_doc(token, &doc_id, current_time_epoch_millis())
-                .map_err(|e| {
-                    AppError::auth(
-                        StatusCode::UNAUTHORIZED,
-                        anyhow!("Invalid token: {}", e),
-                        "invalid_token",
-                    )
-                })?;
+        let result = server
+            .move_path(
+                "Relay Folder 1/source.timestamps.json",
+                "/renamed.timestamps.json",
+                None,
+            )
+            .await
+            .unwrap();
 
-            // Both ReadOnly and Full can check if a file exists
-            if !matches!(auth, Authorization::ReadOnly | Authorization::Full) {
-                return Err(AppError::auth(
-                    StatusCode::FORBIDDEN,
-                    anyhow!("Insufficient permissions to access file"),
-                    "insufficient_permissions",
-                ));
-            }
+        assert_eq!(result.old_path, "/source.timestamps.json");
+        assert_eq!(result.new_path, "/renamed.timestamps.json");
+        assert!(!filemeta_has(
+            &server,
+            &folder_doc_id,
+            "/source.timestamps.json"
+        ));
+        assert!(filemeta_has(
+            &server,
+            &folder_doc_id,
+            "/renamed.timestamps.json"
+        ));
+        assert_eq!(
+            legacy_docs_value(&server, &folder_doc_id, "/renamed.timestamps.json"),
+            None
+        );
+    }
 
-            // Verify the token and get the file hash
-            let permission = authenticator
-                .verify_token_auto(token, current_time_epoch_millis())
-                .map_err(|_| {
-                    AppError::auth(
-                        StatusCode::UNAUTHORIZED,
-                        anyhow!("Invalid token"),
-                        "invalid_token",
-                    )
-                })?;
+    #[tokio::test]
+    async fn folder_doc_acl_uses_the_folder_root_role() {
+        let server = Server::new_for_test();
+        let folder_doc_id = insert_test_folder_doc(
+            &server,
+            "Lens",
+            &[
+                ("/Drafts", "22222222-2222-4222-8222-222222222222", "folder"),
+                (
+                    "/Drafts/Plan.md",
+                    "33333333-3333-4333-8333-333333333333",
+                    "markdown",
+                ),
+                ("/Sub", "44444444-4444-4444-8444-444444444444", "folder"),
+                (
+                    "/Sub/Notes.md",
+                    "55555555-5555-4555-8555-555555555555",
+                    "markdown",
+                ),
+            ],
+        )
+        .await;
+        let policy: AclPolicy = serde_json::from_value(json!({"folders": {"Lens": [
+            {"path": "/**", "role": "view"},
+            {"path": "/Drafts/**", "role": "none"},
+            {"path": "/Sub/**", "role": "edit"},
+        ]}}))
+        .unwrap();
+        server.set_acl_policy(policy).await.unwrap();
+        let doc = |uuid: &str| format!("{}-{}", TEST_RELAY_ID, uuid);
 
-            if let Permission::File(file_permission) = permission {
-                let file_hash = file_permission.file_hash;
+        assert!(server
+            .authorize_doc_acl(
+                &doc("33333333-3333-4333-8333-333333333333"),
+                None,
+                Authorization::Full
+            )
+            .is_err());
+        assert_eq!(
+            server
+                .authorize_doc_acl(
+                    &doc("55555555-5555-4555-8555-555555555555"),
+                    None,
+                    Authorization::Full
+                )
+                .ok(),
+            Some(Authorization::Full)
+        );
+        // Known limitation: the folder doc goes by the root rule alone. Its
+        // filemeta still names /Drafts/Plan.md, and the edit rule on /Sub/**
+        // does not make it writable.
+        assert_eq!(
+            server
+                .authorize_doc_acl(&folder_doc_id, None, Authorization::Full)
+                .ok(),
+            Some(Authorization::ReadOnly)
+        );
+        assert!(filemeta_has(&server, &folder_doc_id, "/Drafts/Plan.md"));
+    }
 
-                // Validate the file hash
-                if !validate_file_hash(&file_hash) {
-                    return Err(AppError::new(
-                        StatusCode::BAD_REQUEST,
-                        anyhow!("Invalid file hash format in token"),
-                    ));
-                }
+    /// A server with Discord login against the mock provider, a role
+    /// mapping in its store and one folder, "Lens Edu", holding DISCORD_DOC.
+    async fn discord_test_server() -> Arc<Server> {
+        use crate::discord_auth::test_support::{config, mock_discord, GUILD};
+        use async_trait::async_trait;
+        use std::collections::HashMap;
+        use std::sync::Mutex;
+        use y_sweet_core::store::Result as StoreResult;
 
-                // Check if we have a store configured
-                if server_state.store.is_none() {
-                    return Err(AppError::new(
-                        StatusCode::INTERNAL_SERVER_ERROR,
-                        anyhow!("No store configured for file operations"),
-                    ));
-                }
+        #[derive(Clone, Default)]
+        struct MemoryStore {
+            data: Arc<Mutex<HashMap<String, Vec<u8>>>>,
+        }
 
-                // Construct the file path with proper format - using doc_id/file_hash
-                let key = format!("files/{}/{}", doc_id, file_hash);
+        #[async_trait]
+        impl Store for MemoryStore {
+            async fn init(&self) -> StoreResult<()> {
+                Ok(())
+            }
 
-                // Check if the file exists with a direct call to S3
-                let exists = server_state
-                    .store
-                    .as_ref()
-                    .unwrap()
-                    .exists(&key)
-                    .await
-                    .map_err(|e| AppError::new(StatusCode::INTERNAL_SERVER_ERROR, e.into()))?;
+            async fn get(&self, key: &str) -> StoreResult<Option<Vec<u8>>> {
+                Ok(self.data.lock().unwrap().get(key).cloned())
+            }
 
-                if exists {
-                    tracing::debug!("File exists: {}/{}", doc_id, file_hash);
-                    return Ok(StatusCode::OK);
-                } else {
-                    tracing::debug!("File not found: {}/{}", doc_id, file_hash);
-                    return Err(AppError::new(
-                        StatusCode::NOT_FOUND,
-                        anyhow!("File not found"),
-                    ));
-                }
-            } else {
-                return Err(AppError::new(
-                    StatusCode::BAD_REQUEST,
-                    anyhow!("Token is not a file token"),
-                ));
+            async fn set(&self, key: &str, value: Vec<u8>) -> StoreResult<()> {
+                self.data.lock().unwrap().insert(key.to_string(), value);
+                Ok(())
             }
-        } else {
-            return Err(AppError::auth(
-                StatusCode::UNAUTHORIZED,
-                anyhow!("No token provided"),
-                "missing_token",
-            ));
-        }
-    } else {
-        // No auth configured
-        return Err(AppError::auth(
-            StatusCode::UNAUTHORIZED,
-            anyhow!("Authentication is required for file operations"),
-            "no_authenticator",
-        ));
-    }
-}
 
-async fn reload_webhook_config_endpoint(
-    State(server_state): State<Arc<Server>>,
-    auth_header: Option<TypedHeader<headers::Authorization<headers::authorization::Bearer>>>,
-) -> Result<Json<Value>, AppError> {
-    // Get token
-    let token = get_token_from_header(auth_header);
+            async fn remove(&self, key: &str) -> StoreResult<()> {
+                self.data.lock().unwrap().remove(key);
+                Ok(())
+            }
 
-    // Verify token is server token (for server admin operations)
-    if let Some(authenticator) = &server_state.authenticator {
-        if let Some(token) = token.as_deref() {
-            // Verify this is a server admin token
-            authenticator
-                .verify_server_token(token, current_time_epoch_millis())
-                .map_err(|e| {
-                    AppError::auth(
-                        StatusCode::UNAUTHORIZED,
-                        anyhow!("Invalid token: {}", e),
-                        "invalid_token",
-                    )
-                })?;
-        } else {
-            return Err(AppError::auth(
-                StatusCode::UNAUTHORIZED,
-                anyhow!("No token provided"),
-                "missing_token",
-            ));
+            async fn exists(&self, key: &str) -> StoreResult<bool> {
+                Ok(self.data.lock().unwrap().contains_key(key))
+            }
         }
+
+        let store = MemoryStore::default();
+        store
+            .set(
+                crate::discord_auth::ROLE_MAPPING_KEY,
+                serde_json::to_vec(&json!({"roles": {
+                    "r-ta": {"Lens Edu": "suggest"},
+                    GUILD: {"Other": "view"}
+                }}))
+                .unwrap(),
+            )
+            .await
+            .unwrap();
+        let mut server = Server::new_without_workers(
+            Some(Box::new(store)),
+            Duration::from_secs(60),
+            Some(y_sweet_core::auth::Authenticator::gen_key().unwrap()),
+            None,
+            vec![],
+            CancellationToken::new(),
+            false,
+            None,
+        )
+        .await
+        .unwrap();
+        server.discord = Some(Arc::new(crate::discord_auth::DiscordAuth::new(config(
+            mock_discord().await,
+            GUILD,
+        ))));
+        let server = Arc::new(server);
+        insert_test_folder_doc(&server, "Lens Edu", &[("/Doc.md", DISCORD_DOC, "markdown")]).await;
+        server
     }
 
-    // Reload webhook configuration
-    match server_state.reload_webhook_config().await {
-        Ok(status) => Ok(Json(json!({
-            "status": "success",
-            "message": status
-        }))),
-        Err(e) => {
-            tracing::error!("Failed to reload webhook config: {}", e);
-            Err(AppError::new(
-                StatusCode::INTERNAL_SERVER_ERROR,
-                anyhow!("Failed to reload webhook configuration: {}", e),
-            ))
+    const DISCORD_DOC: &str = "66666666-6666-4666-8666-666666666666";
+
+    async fn discord_request(
+        server: &Arc<Server>,
+        method: Method,
+        uri: &str,
+        headers: &[(&str, &str)],
+    ) -> axum::response::Response {
+        let mut request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header("host", "relay.test");
+        for (name, value) in headers {
+            request = request.header(*name, *value);
         }
+        server
+            .routes()
+            .oneshot(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap()
     }
-}
-
-async fn metrics_endpoint(State(_server_state): State<Arc<Server>>) -> Result<String, AppError> {
-    use prometheus::{Encoder, TextEncoder};
 
-    let encoder = TextEncoder::new();
-    let metric_families = prometheus::gather();
-    let mut buffer = Vec::new();
+    fn response_header(response: &axum::response::Response, name: &str) -> String {
+        response
+            .headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default()
+            .to_string()
+    }
+
+    /// Run the login flow the way a browser does; returns the session ID
+    /// the app receives.
+    async fn discord_login(server: &Arc<Server>) -> String {
+        let response = discord_request(server, Method::GET, "/auth/discord/login", &[]).await;
+        assert_eq!(response.status(), StatusCode::TEMPORARY_REDIRECT);
+        let cookie = response_header(&response, "set-cookie");
+        let (cookie_pair, _) = cookie.split_once(';').unwrap();
+        let state = cookie_pair
+            .strip_prefix("relay_discord_state=")
+            .unwrap()
+            .to_string();
+        assert!(response_header(&response, "location").contains(&format!("state={}", state)));
 
-    encoder.encode(&metric_families, &mut buffer).map_err(|e| {
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            anyhow!("Failed to encode metrics: {}", e),
+        let response = discord_request(
+            server,
+            Method::GET,
+            &format!("/auth/discord/callback?code=good&state={}", state),
+            &[("cookie", cookie_pair)],
         )
-    })?;
+        .await;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        let location = response_header(&response, "location");
+        let fragment = location
+            .strip_prefix(&format!("{}#", crate::discord_auth::test_support::APP_URL))
+            .unwrap_or_else(|| panic!("unexpected redirect {}", location));
+        url::form_urlencoded::parse(fragment.as_bytes())
+            .find(|(key, _)| key == "session")
+            .map(|(_, value)| value.into_owned())
+            .unwrap()
+    }
 
-    Ok(String::from_utf8(buffer).map_err(|e| {
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            anyhow!("Failed to convert metrics to string: {}", e),
+    #[tokio::test]
+    async fn handle_discord_callback_binds_state_to_the_browser() {
+        let server = discord_test_server().await;
+        let response = discord_request(&server, Method::GET, "/auth/discord/login", &[]).await;
+        let cookie = response_header(&response, "set-cookie");
+        assert!(cookie.contains("HttpOnly"), "{}", cookie);
+        assert!(cookie.contains("SameSite=Lax"), "{}", cookie);
+        let (cookie_pair, _) = cookie.split_once(';').unwrap();
+        let state = cookie_pair.strip_prefix("relay_discord_state=").unwrap();
+        let callback = format!("/auth/discord/callback?code=good&state={}", state);
+
+        // A callback link opened in another browser (no cookie, or the
+        // cookie of a login it started itself) does not sign it in.
+        let response = discord_request(&server, Method::GET, &callback, &[]).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let response = discord_request(
+            &server,
+            Method::GET,
+            &callback,
+            &[("cookie", "relay_discord_state=someone-else")],
         )
-    })?)
-}
+        .await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
 
-#[cfg(test)]
-mod test {
-    use super::*;
-    use axum::body::{to_bytes, Body};
-    use axum::http::{Method, Request};
-    use serde_json::Value as JsonValue;
-    use tower::util::ServiceExt;
-    use y_sweet_core::api_types::Authorization;
-    use y_sweet_core::auth::ExpirationTimeEpochMillis;
-    use y_sweet_core::critic_scanner::scan_suggestions;
-    use y_sweet_core::doc_sync::DocWithSyncKv;
-    use yrs::GetString;
-    use yrs::{Any, Map, ReadTxn, Text, Transact, WriteTxn};
+        // The browser that started it is sent on to the app, with the
+        // session in the fragment and nothing in the body.
+        let response =
+            discord_request(&server, Method::GET, &callback, &[("cookie", cookie_pair)]).await;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        assert!(
+            response_header(&response, "location").starts_with("http://app.test/login#session=")
+        );
+        assert!(response_header(&response, "set-cookie").contains("Max-Age=0"));
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert!(body.is_empty());
 
-    const TEST_RELAY_ID: &str = "cb696037-0f72-4e93-8717-4e433129d789";
-    const TEST_FOLDER_UUID: &str = "b0000001-0000-4000-8000-000000000001";
+        let session = discord_login(&server).await;
+        assert!(server.discord.as_ref().unwrap().session(&session).is_some());
+    }
 
-    async fn insert_test_folder_doc(
-        server: &Arc<Server>,
-        folder_name: &str,
-        entries: &[(&str, &str, &str)],
-    ) -> String {
+    #[tokio::test]
+    async fn handle_discord_login_floods_do_not_block_other_browsers() {
+        let server = discord_test_server().await;
+        let app = server.routes();
+        for _ in 0..2_000 {
+            let response = app
+                .clone()
+                .oneshot(
+                    Request::builder()
+                        .uri("/auth/discord/login")
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::TEMPORARY_REDIRECT);
+        }
+        let session = discord_login(&server).await;
+        assert!(server.discord.as_ref().unwrap().session(&session).is_some());
+    }
+
+    #[tokio::test]
+    async fn handle_discord_session_and_doc_token_mint_user_tokens() {
+        let server = discord_test_server().await;
+        let session = discord_login(&server).await;
+        let bearer = format!("Bearer {}", session);
         let folder_doc_id = format!("{}-{}", TEST_RELAY_ID, TEST_FOLDER_UUID);
-        let dwskv = DocWithSyncKv::new(&folder_doc_id, None, || (), None)
+
+        let response = discord_request(
+            &server,
+            Method::GET,
+            "/auth/discord/session",
+            &[("authorization", &bearer)],
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body: JsonValue =
+            serde_json::from_slice(&to_bytes(response.into_body(), usize::MAX).await.unwrap())
+                .unwrap();
+        assert_eq!(body["user"]["user_id"], "discord:42");
+        let folders = body["folders"].as_array().unwrap();
+        assert_eq!(folders.len(), 1, "{}", body);
+        assert_eq!(folders[0]["folder"], "Lens Edu");
+        assert_eq!(folders[0]["folder_id"], folder_doc_id.as_str());
+        assert_eq!(folders[0]["authorization"], "suggest");
+        let (authorization, _, user) =
+            verify_socket_token(&server, &folder_doc_id, folders[0]["token"].as_str()).unwrap();
+        assert_eq!(authorization, Authorization::Suggest);
+        assert_eq!(user.as_deref(), Some("discord:42"));
+
+        let doc_id = format!("{}-{}", TEST_RELAY_ID, DISCORD_DOC);
+        let uri = format!("/auth/discord/doc/{}", doc_id);
+        let response =
+            discord_request(&server, Method::POST, &uri, &[("authorization", &bearer)]).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let token: JsonValue =
+            serde_json::from_slice(&to_bytes(response.into_body(), usize::MAX).await.unwrap())
+                .unwrap();
+        assert_eq!(token["authorization"], "suggest");
+        let (_, _, user) = verify_socket_token(&server, &doc_id, token["token"].as_str()).unwrap();
+        assert_eq!(user.as_deref(), Some("discord:42"));
+
+        for headers in [vec![], vec![("authorization", "Bearer not-a-session")]] {
+            let response = discord_request(&server, Method::POST, &uri, &headers).await;
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        }
+        let response = discord_request(
+            &server,
+            Method::POST,
+            "/auth/discord/logout",
+            &[("authorization", &bearer)],
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        let response =
+            discord_request(&server, Method::POST, &uri, &[("authorization", &bearer)]).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn folder_rename_moves_metadata() {
+        let server = Server::new_for_test();
+        let folder_doc_id = insert_test_folder_doc(
+            &server,
+            "Relay Folder 1",
+            &[
+                ("/Old", "22222222-2222-4222-8222-222222222222", "folder"),
+                (
+                    "/Old/Child.md",
+                    "33333333-3333-4333-8333-333333333333",
+                    "markdown",
+                ),
+                (
+                    "/Old/Page.html",
+                    "44444444-4444-4444-8444-444444444444",
+                    "file",
+                ),
+            ],
+        )
+        .await;
+        insert_test_content_doc(&server, "33333333-3333-4333-8333-333333333333", "Child").await;
+
+        server
+            .move_path("Relay Folder 1/Old", "/New", None)
             .await
             .unwrap();
-        {
-            let awareness = dwskv.awareness();
-            let guard = awareness.write().unwrap();
-            let mut txn = guard.doc.transact_mut();
-            let config = txn.get_or_insert_map("folder_config");
-            config.insert(&mut txn, "name", Any::String(folder_name.into()));
-            let filemeta = txn.get_or_insert_map("filemeta_v0");
-            let docs_map = txn.get_or_insert_map("docs");
-            for (path, uuid, entry_type) in entries {
-                let mut map = std::collections::HashMap::new();
-                map.insert("id".to_string(), Any::String((*uuid).into()));
-                map.insert("type".to_string(), Any::String((*entry_type).into()));
-                map.insert("version".to_string(), Any::Number(0.0));
-                filemeta.insert(&mut txn, *path, Any::Map(map.into()));
-                docs_map.insert(&mut txn, *path, Any::String((*uuid).into()));
-            }
-        }
-        server.docs().insert(folder_doc_id.clone(), dwskv);
-        server.doc_resolver().rebuild(server.docs());
-        folder_doc_id
+
+        assert!(!filemeta_has(&server, &folder_doc_id, "/Old"));
+        assert!(!filemeta_has(&server, &folder_doc_id, "/Old/Child.md"));
+        assert!(!filemeta_has(&server, &folder_doc_id, "/Old/Page.html"));
+        assert!(filemeta_has(&server, &folder_doc_id, "/New"));
+        assert!(filemeta_has(&server, &folder_doc_id, "/New/Child.md"));
+        assert!(filemeta_has(&server, &folder_doc_id, "/New/Page.html"));
+        assert_eq!(
+            legacy_docs_value(&server, &folder_doc_id, "/New"),
+            Some("22222222-2222-4222-8222-222222222222".to_string())
+        );
+        assert_eq!(
+            legacy_docs_value(&server, &folder_doc_id, "/New/Child.md"),
+            Some("33333333-3333-4333-8333-333333333333".to_string())
+        );
+        assert_eq!(
+            legacy_docs_value(&server, &folder_doc_id, "/New/Page.html"),
+            None
+        );
+        assert_eq!(legacy_docs_value(&server, &folder_doc_id, "/Old"), None);
+        assert_eq!(
+            legacy_docs_value(&server, &folder_doc_id, "/Old/Child.md"),
+            None
+        );
+        assert_eq!(
+            legacy_docs_value(&server, &folder_doc_id, "/Old/Page.html"),
+            None
+        );
     }
 
-    async fn insert_test_content_doc(server: &Arc<Server>, uuid: &str, content: &str) {
-        let doc_id = format!("{}-{}", TEST_RELAY_ID, uuid);
-        let dwskv = DocWithSyncKv::new(&doc_id, None, || (), None)
+    #[tokio::test]
+    async fn folder_rename_moves_synthetic_ancestor_folder() {
+        let server = Server::new_for_test();
+        let folder_doc_id = insert_test_folder_doc(
+            &server,
+            "Relay Folder 1",
+            &[(
+                "/articles/Article.md",
+                "33333333-3333-4333-8333-333333333333",
+                "markdown",
+            )],
+        )
+        .await;
+        insert_test_content_doc(&server, "33333333-3333-4333-8333-333333333333", "Article").await;
+
+        let result = server
+            .move_path("Relay Folder 1/articles", "/renamed", None)
             .await
             .unwrap();
-        {
-            let awareness = dwskv.awareness();
-            let guard = awareness.write().unwrap();
-            let mut txn = guard.doc.transact_mut();
-            let text = txn.get_or_insert_text("contents");
-            text.insert(&mut txn, 0, content);
-        }
-        server.docs().insert(doc_id, dwskv);
-    }
 
-    fn insert_backlink(
-        server: &Arc<Server>,
-        folder_doc_id: &str,
-        target_uuid: &str,
-        backlinker_uuid: &str,
-    ) {
-        let doc_ref = server.docs().get(folder_doc_id).unwrap();
-        let awareness = doc_ref.awareness();
-        let guard = awareness.write().unwrap();
-        let mut txn = guard.doc.transact_mut();
-        let backlinks = txn.get_or_insert_map("backlinks_v0");
-        backlinks.insert(
-            &mut txn,
-            target_uuid,
-            vec![Any::String(backlinker_uuid.into())],
+        assert_eq!(result.old_path, "/articles");
+        assert_eq!(result.new_path, "/renamed");
+        assert!(!filemeta_has(
+            &server,
+            &folder_doc_id,
+            "/articles/Article.md"
+        ));
+        assert!(filemeta_has(&server, &folder_doc_id, "/renamed/Article.md"));
+        assert_eq!(
+            legacy_docs_value(&server, &folder_doc_id, "/articles/Article.md"),
+            None
+        );
+        assert_eq!(
+            legacy_docs_value(&server, &folder_doc_id, "/renamed/Article.md"),
+            Some("33333333-3333-4333-8333-333333333333".to_string())
         );
     }
 
-    fn filemeta_has(server: &Arc<Server>, folder_doc_id: &str, path: &str) -> bool {
-        let doc_ref = server.docs().get(folder_doc_id).unwrap();
-        let awareness = doc_ref.awareness();
-        let guard = awareness.read().unwrap();
-        let txn = guard.doc.transact();
-        txn.get_map("filemeta_v0")
-            .and_then(|m| m.get(&txn, path))
-            .is_some()
-    }
+    #[tokio::test]
+    async fn folder_rename_rejects_synthetic_destination_collision_without_mutation() {
+        let server = Server::new_for_test();
+        let folder_doc_id = insert_test_folder_doc(
+            &server,
+            "Relay Folder 1",
+            &[
+                (
+                    "/articles/Article.md",
+                    "33333333-3333-4333-8333-333333333333",
+                    "markdown",
+                ),
+                (
+                    "/renamed/Article.md",
+                    "44444444-4444-4444-8444-444444444444",
+                    "markdown",
+                ),
+            ],
+        )
+        .await;
+        insert_test_content_doc(&server, "33333333-3333-4333-8333-333333333333", "Article").await;
+        insert_test_content_doc(&server, "44444444-4444-4444-8444-444444444444", "Existing").await;
 
-    fn legacy_docs_value(server: &Arc<Server>, folder_doc_id: &str, path: &str) -> Option<String> {
-        let doc_ref = server.docs().get(folder_doc_id).unwrap();
-        let awareness = doc_ref.awareness();
-        let guard = awareness.read().unwrap();
-        let txn = guard.doc.transact();
-        txn.get_map("docs").and_then(|m| match m.get(&txn, path) {
-            Some(yrs::Out::Any(Any::String(value))) => Some(value.to_string()),
-            _ => None,
-        })
-    }
+        let result = server
+            .move_path("Relay Folder 1/articles", "/renamed", None)
+            .await;
 
-    fn content_text(server: &Arc<Server>, uuid: &str) -> String {
-        let doc_id = format!("{}-{}", TEST_RELAY_ID, uuid);
-        let doc_ref = server.docs().get(&doc_id).unwrap();
-        let awareness = doc_ref.awareness();
-        let guard = awareness.read().unwrap();
-        let txn = guard.doc.transact();
-        txn.get_text("contents")
-            .map(|text| text.get_string(&txn))
-            .unwrap_or_default()
+        assert!(matches!(result, Err(MoveDocumentError::Conflict(_))));
+        assert!(filemeta_has(
+            &server,
+            &folder_doc_id,
+            "/articles/Article.md"
+        ));
+        assert!(filemeta_has(&server, &folder_doc_id, "/renamed/Article.md"));
     }
 
-    async fn post_move(server: &Arc<Server>, body: JsonValue) -> (StatusCode, JsonValue) {
-        let response = server
-            .routes()
-            .oneshot(
-                Request::builder()
-                    .method(Method::POST)
-                    .uri("/move")
-                    .header("content-type", "application/json")
-                    .body(Body::from(body.to_string()))
-                    .unwrap(),
-            )
-            .await
-            .unwrap();
-        let status = response.status();
-        let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
-        let body = serde_json::from_slice(&bytes).unwrap_or_else(|_| json!({}));
-        (status, body)
-    }
+    #[tokio::test]
+    async fn folder_rename_rewrites_synthetic_descendant_backlinks() {
+        let server = Server::new_for_test();
+        let folder_doc_id = insert_test_folder_doc(
+            &server,
+            "Relay Folder 1",
+            &[
+                (
+                    "/articles/Article.md",
+                    "33333333-3333-4333-8333-333333333333",
+                    "markdown",
+                ),
+                (
+                    "/Backlinker.md",
+                    "44444444-4444-4444-8444-444444444444",
+                    "markdown",
+                ),
+            ],
+        )
+        .await;
+        insert_test_content_doc(&server, "33333333-3333-4333-8333-333333333333", "Article").await;
+        insert_test_content_doc(
+            &server,
+            "44444444-4444-4444-8444-444444444444",
+            "See [[articles/Article]]",
+        )
+        .await;
+        insert_backlink(
+            &server,
+            &folder_doc_id,
+            "33333333-3333-4333-8333-333333333333",
+            "44444444-4444-4444-8444-444444444444",
+        );
 
-    async fn post_request(
-        server: &Arc<Server>,
-        uri: &str,
-        content_type: &str,
-        body: Body,
-    ) -> (StatusCode, String) {
-        let response = server
-            .routes()
-            .oneshot(
-                Request::builder()
-                    .method(Method::POST)
-                    .uri(uri)
-                    .header("content-type", content_type)
-                    .body(body)
-                    .unwrap(),
-            )
+        let result = server
+            .move_path("Relay Folder 1/articles", "/renamed", None)
             .await
             .unwrap();
-        let status = response.status();
-        let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
-        (status, String::from_utf8_lossy(&bytes).into_owned())
+
+        assert_eq!(result.links_rewritten, 1);
+        assert_eq!(
+            content_text(&server, "44444444-4444-4444-8444-444444444444"),
+            "See [[renamed/Article]]"
+        );
     }
 
-    async fn post_check_video_ids(
-        server: &Arc<Server>,
-        body: JsonValue,
-    ) -> (StatusCode, JsonValue) {
-        let response = server
-            .routes()
-            .oneshot(
-                Request::builder()
-                    .method(Method::POST)
-                    .uri("/doc/check-video-ids")
-                    .header("content-type", "application/json")
-                    .body(Body::from(body.to_string()))
-                    .unwrap(),
-            )
+    #[tokio::test]
+    async fn folder_rename_rewrites_descendant_backlinks() {
+        let server = Server::new_for_test();
+        let folder_doc_id = insert_test_folder_doc(
+            &server,
+            "Relay Folder 1",
+            &[
+                ("/Old", "22222222-2222-4222-8222-222222222222", "folder"),
+                (
+                    "/Old/Child.md",
+                    "33333333-3333-4333-8333-333333333333",
+                    "markdown",
+                ),
+                (
+                    "/Backlinker.md",
+                    "44444444-4444-4444-8444-444444444444",
+                    "markdown",
+                ),
+            ],
+        )
+        .await;
+        insert_test_content_doc(&server, "33333333-3333-4333-8333-333333333333", "Child").await;
+        insert_test_content_doc(
+            &server,
+            "44444444-4444-4444-8444-444444444444",
+            "See [[Old/Child]]",
+        )
+        .await;
+        insert_backlink(
+            &server,
+            &folder_doc_id,
+            "33333333-3333-4333-8333-333333333333",
+            "44444444-4444-4444-8444-444444444444",
+        );
+
+        let result = server
+            .move_path("Relay Folder 1/Old", "/New", None)
             .await
             .unwrap();
-        let status = response.status();
-        let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
-        let body = serde_json::from_slice(&bytes).unwrap_or_else(|_| json!({}));
-        (status, body)
+
+        assert_eq!(result.links_rewritten, 1);
+        assert_eq!(
+            content_text(&server, "44444444-4444-4444-8444-444444444444"),
+            "See [[New/Child]]"
+        );
     }
 
     #[tokio::test]
-    async fn move_path_file_moves_existing_file() {
+    async fn folder_rename_without_backlinkers_reports_zero_rewrites() {
         let server = Server::new_for_test();
-        let folder_doc_id = insert_test_folder_doc(
+        insert_test_folder_doc(
             &server,
             "Relay Folder 1",
-            &[(
-                "/Old.md",
-                "11111111-1111-4111-8111-111111111111",
-                "markdown",
-            )],
+            &[
+                ("/Old", "22222222-2222-4222-8222-222222222222", "folder"),
+                (
+                    "/Old/Child.md",
+                    "33333333-3333-4333-8333-333333333333",
+                    "markdown",
+                ),
+            ],
         )
         .await;
-        insert_test_content_doc(&server, "11111111-1111-4111-8111-111111111111", "Old").await;
+        insert_test_content_doc(&server, "33333333-3333-4333-8333-333333333333", "Child").await;
 
         let result = server
-            .move_path("Relay Folder 1/Old.md", "/New.md", None)
+            .move_path("Relay Folder 1/Old", "/New", None)
             .await
             .unwrap();
 
-        assert_eq!(result.old_path, "/Old.md");
-        assert_eq!(result.new_path, "/New.md");
-        assert!(!filemeta_has(&server, &folder_doc_id, "/Old.md"));
-        assert!(filemeta_has(&server, &folder_doc_id, "/New.md"));
+        assert_eq!(result.links_rewritten, 0);
     }
 
     #[tokio::test]
-    async fn move_path_rejects_double_quotes_without_mutation() {
+    async fn move_path_rejects_folder_destination_collision_without_mutation() {
         let server = Server::new_for_test();
         let folder_doc_id = insert_test_folder_doc(
             &server,
             "Relay Folder 1",
-            &[(
-                "/Old.md",
-                "11111111-1111-4111-8111-111111111111",
-                "markdown",
-            )],
+            &[
+                ("/Old", "22222222-2222-4222-8222-222222222222", "folder"),
+                (
+                    "/Existing",
+                    "55555555-5555-4555-8555-555555555555",
+                    "folder",
+                ),
+            ],
         )
         .await;
-        insert_test_content_doc(&server, "11111111-1111-4111-8111-111111111111", "Old").await;
 
         let result = server
-            .move_path("Relay Folder 1/Old.md", "/Bad \"Name\".md", None)
+            .move_path("Relay Folder 1/Old", "/Existing", None)
             .await;
 
-        assert!(matches!(result, Err(MoveDocumentError::BadRequest(_))));
-        assert!(filemeta_has(&server, &folder_doc_id, "/Old.md"));
-        assert!(!filemeta_has(&server, &folder_doc_id, "/Bad \"Name\".md"));
+        assert!(matches!(result, Err(MoveDocumentError::Conflict(_))));
+        assert!(filemeta_has(&server, &folder_doc_id, "/Old"));
+        assert!(filemeta_has(&server, &folder_doc_id, "/Existing"));
     }
 
     #[tokio::test]
-    async fn create_document_rejects_double_quotes_without_mutation() {
+    async fn move_path_rejects_descendant_destination_collision_without_mutation() {
         let server = Server::new_for_test();
-        let folder_doc_id = insert_test_folder_doc(&server, "Relay Folder 1", &[]).await;
+        let folder_doc_id = insert_test_folder_doc(
+            &server,
+            "Relay Folder 1",
+            &[
+                ("/Old", "22222222-2222-4222-8222-222222222222", "folder"),
+                (
+                    "/Old/Child.md",
+                    "33333333-3333-4333-8333-333333333333",
+                    "markdown",
+                ),
+                (
+                    "/New/Child.md",
+                    "44444444-4444-4444-8444-444444444444",
+                    "markdown",
+                ),
+            ],
+        )
+        .await;
+        insert_test_content_doc(&server, "33333333-3333-4333-8333-333333333333", "Child").await;
+        insert_test_content_doc(&server, "44444444-4444-4444-8444-444444444444", "Existing").await;
 
-        let result = server
-            .create_document("Relay Folder 1", "/Bad \"Name\".md", "content", None)
+        let result = server.move_path("Relay Folder 1/Old", "/New", None).await;
+
+        assert!(matches!(result, Err(MoveDocumentError::Conflict(_))));
+        assert!(filemeta_has(&server, &folder_doc_id, "/Old"));
+        assert!(filemeta_has(&server, &folder_doc_id, "/Old/Child.md"));
+        assert!(filemeta_has(&server, &folder_doc_id, "/New/Child.md"));
+        assert!(!filemeta_has(&server, &folder_doc_id, "/New"));
+    }
+
+    #[tokio::test]
+    async fn move_path_rejects_invalid_folder_destinations_without_mutation() {
+        let invalid_destinations = ["/", "/New/", "/New//Child", "/.", "/..", "/New/../Child"];
+        for new_path in invalid_destinations {
+            let server = Server::new_for_test();
+            let folder_doc_id = insert_test_folder_doc(
+                &server,
+                "Relay Folder 1",
+                &[("/Old", "22222222-2222-4222-8222-222222222222", "folder")],
+            )
             .await;
 
-        assert!(matches!(result, Err(CreateDocumentError::BadRequest(_))));
-        assert!(!filemeta_has(&server, &folder_doc_id, "/Bad \"Name\".md"));
+            let result = server.move_path("Relay Folder 1/Old", new_path, None).await;
+
+            assert!(
+                matches!(result, Err(MoveDocumentError::BadRequest(_))),
+                "expected BadRequest for {new_path}"
+            );
+            assert!(filemeta_has(&server, &folder_doc_id, "/Old"));
+            assert!(!filemeta_has(&server, &folder_doc_id, new_path));
+        }
     }
 
     #[tokio::test]
-    async fn upsert_document_rejects_double_quotes_as_bad_request() {
+    async fn move_path_rejects_folder_cross_folder_move() {
         let server = Server::new_for_test();
-        insert_test_folder_doc(&server, "Relay Folder 1", &[]).await;
-        let request = json!({
-            "folder": "Relay Folder 1",
-            "path": "/Bad \"Name\".md",
-            "content": "content"
-        });
-
-        let (status, body) = post_request(
+        let folder_doc_id = insert_test_folder_doc(
             &server,
-            "/doc/upsert",
-            "application/json",
-            Body::from(request.to_string()),
+            "Relay Folder 1",
+            &[("/Old", "22222222-2222-4222-8222-222222222222", "folder")],
         )
         .await;
 
-        assert_eq!(status, StatusCode::BAD_REQUEST);
-        assert!(body.contains("File names cannot contain double quotes"));
+        let result = server
+            .move_path("Relay Folder 1/Old", "/New", Some("Relay Folder 2"))
+            .await;
+
+        assert!(matches!(result, Err(MoveDocumentError::BadRequest(_))));
+        assert!(filemeta_has(&server, &folder_doc_id, "/Old"));
     }
 
     #[tokio::test]
-    async fn upsert_blob_rejects_double_quotes_as_bad_request() {
+    async fn move_path_rejects_folder_to_markdown_destination() {
         let server = Server::new_for_test();
-        insert_test_folder_doc(&server, "Relay Folder 1", &[]).await;
-        let request = json!({
-            "folder": "Relay Folder 1",
-            "path": "/Bad \"Name\".json",
-            "content": "{}"
-        });
-
-        let (status, body) = post_request(
+        let folder_doc_id = insert_test_folder_doc(
             &server,
-            "/doc/upsert",
-            "application/json",
-            Body::from(request.to_string()),
+            "Relay Folder 1",
+            &[("/Old", "22222222-2222-4222-8222-222222222222", "folder")],
         )
         .await;
 
-        assert_eq!(status, StatusCode::BAD_REQUEST);
-        assert!(body.contains("File names cannot contain double quotes"));
+        let result = server
+            .move_path("Relay Folder 1/Old", "/Old.md", None)
+            .await;
+
+        assert!(matches!(result, Err(MoveDocumentError::BadRequest(_))));
+        assert!(filemeta_has(&server, &folder_doc_id, "/Old"));
+        assert!(!filemeta_has(&server, &folder_doc_id, "/Old.md"));
     }
 
     #[tokio::test]
-    async fn upsert_attachment_rejects_double_quotes_as_bad_request() {
+    async fn move_path_rejects_folder_descendant_destination() {
         let server = Server::new_for_test();
-        insert_test_folder_doc(&server, "Relay Folder 1", &[]).await;
-
-        let (status, body) = post_request(
+        let folder_doc_id = insert_test_folder_doc(
             &server,
-            "/doc/attachment?folder=Relay%20Folder%201&path=%2FBad%20%22Name%22.png",
-            "application/octet-stream",
-            Body::from(vec![1, 2, 3]),
+            "Relay Folder 1",
+            &[("/Old", "22222222-2222-4222-8222-222222222222", "folder")],
         )
         .await;
 
-        assert_eq!(status, StatusCode::BAD_REQUEST);
-        assert!(body.contains("File names cannot contain double quotes"));
-    }
+        let result = server
+            .move_path("Relay Folder 1/Old", "/Old/Sub", None)
+            .await;
 
-    /// Add a filemeta + legacy-docs entry to an already-loaded folder doc WITHOUT
-    /// refreshing the resolver. Simulates a freshly-created file that the client
-    /// wrote to the folder Y.Doc but the link-indexer worker has not yet (or never,
-    /// if stalled) registered in the in-memory resolver.
-    fn add_filemeta_entry_without_resolver(
-        server: &Arc<Server>,
-        folder_doc_id: &str,
-        path: &str,
-        uuid: &str,
-        entry_type: &str,
-    ) {
-        let doc_ref = server.docs().get(folder_doc_id).unwrap();
-        let awareness = doc_ref.awareness();
-        let guard = awareness.write().unwrap();
-        let mut txn = guard.doc.transact_mut();
-        let filemeta = txn.get_or_insert_map("filemeta_v0");
-        let docs_map = txn.get_or_insert_map("docs");
-        let mut map = std::collections::HashMap::new();
-        map.insert("id".to_string(), Any::String(uuid.into()));
-        map.insert("type".to_string(), Any::String(entry_type.into()));
-        map.insert("version".to_string(), Any::Number(0.0));
-        filemeta.insert(&mut txn, path, Any::Map(map.into()));
-        if entry_type == "markdown" {
-            docs_map.insert(&mut txn, path, Any::String(uuid.into()));
-        }
+        assert!(matches!(result, Err(MoveDocumentError::BadRequest(_))));
+        assert!(filemeta_has(&server, &folder_doc_id, "/Old"));
+        assert!(!filemeta_has(&server, &folder_doc_id, "/Old/Sub"));
     }
 
-    // Prevents: "Move failed: 400" when renaming a freshly created markdown file
-    // while the resolver is stale (link-indexer worker lagging or dead). The file
-    // IS in filemeta_v0 (source of truth) but NOT in the resolver, so without the
-    // filemeta fallback resolve_path() misses, move_path() falls through to the
-    // folder-move branch, and the ".md" destination is rejected with BadRequest.
     #[tokio::test]
-    async fn move_path_renames_file_missing_from_stale_resolver() {
+    async fn handle_move_path_route_moves_file() {
         let server = Server::new_for_test();
-        // Folder doc loaded; resolver rebuilt while the folder had only old files.
         let folder_doc_id = insert_test_folder_doc(
             &server,
             "Relay Folder 1",
             &[(
                 "/Old.md",
-                "00000000-0000-4000-8000-000000000001",
+                "11111111-1111-4111-8111-111111111111",
                 "markdown",
             )],
         )
         .await;
+        insert_test_content_doc(&server, "11111111-1111-4111-8111-111111111111", "Old").await;
+
+        let (status, body) = post_move(
+            &server,
+            json!({ "path": "Relay Folder 1/Old.md", "new_path": "/New.md" }),
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["old_path"], "/Old.md");
+        assert_eq!(body["new_path"], "/New.md");
+        assert_eq!(body["old_folder"], "Relay Folder 1");
+        assert_eq!(body["new_folder"], "Relay Folder 1");
+        assert!(filemeta_has(&server, &folder_doc_id, "/New.md"));
+    }
+
+    #[tokio::test]
+    async fn handle_move_path_route_renames_folder() {
+        let server = Server::new_for_test();
+        let folder_doc_id = insert_test_folder_doc(
+            &server,
+            "Relay Folder 1",
+            &[("/Old", "22222222-2222-4222-8222-222222222222", "folder")],
+        )
+        .await;
 
-        // Freshly-created file: written to filemeta, but resolver never learned it.
-        let uuid = "11111111-1111-4111-8111-111111111111";
-        add_filemeta_entry_without_resolver(&server, &folder_doc_id, "/New.md", uuid, "markdown");
-        insert_test_content_doc(&server, uuid, "New").await;
-
-        // Precondition: the resolver really is stale for this path.
-        assert!(
-            server
-                .doc_resolver()
-                .resolve_path("Relay Folder 1/New.md")
-                .is_none(),
-            "test setup: resolver should not know the new file"
-        );
-
-        // The user's exact action.
-        let result = server
-            .move_path("Relay Folder 1/New.md", "/Renamed.md", None)
-            .await;
+        let (status, body) = post_move(
+            &server,
+            json!({ "path": "Relay Folder 1/Old", "new_path": "/New" }),
+        )
+        .await;
 
-        assert!(
-            result.is_ok(),
-            "renaming a file that is in filemeta but missing from a stale resolver should succeed, got: {:?}",
-            result.err()
-        );
-        assert!(!filemeta_has(&server, &folder_doc_id, "/New.md"));
-        assert!(filemeta_has(&server, &folder_doc_id, "/Renamed.md"));
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["old_path"], "/Old");
+        assert_eq!(body["new_path"], "/New");
+        assert!(filemeta_has(&server, &folder_doc_id, "/New"));
     }
 
     #[tokio::test]
-    async fn check_video_ids_detects_full_youtube_urls() {
+    async fn handle_move_path_route_returns_conflict_for_collision() {
         let server = Server::new_for_test();
         insert_test_folder_doc(
             &server,
-            "Lens Edu",
+            "Relay Folder 1",
             &[
+                ("/Old", "22222222-2222-4222-8222-222222222222", "folder"),
                 (
-                    "/video_transcripts/Short.md",
-                    "11111111-1111-4111-8111-111111111111",
-                    "markdown",
-                ),
-                (
-                    "/video_transcripts/Normal.md",
-                    "22222222-2222-4222-8222-222222222222",
-                    "markdown",
+                    "/Existing",
+                    "55555555-5555-4555-8555-555555555555",
+                    "folder",
                 ),
             ],
         )
         .await;
-        insert_test_content_doc(
+
+        let (status, _) = post_move(
             &server,
-            "11111111-1111-4111-8111-111111111111",
-            "---\nurl: \"https://www.youtube.com/shorts/GMTDrG3hYJ0\"\n---\n",
+            json!({ "path": "Relay Folder 1/Old", "new_path": "/Existing" }),
         )
         .await;
-        insert_test_content_doc(
+
+        assert_eq!(status, StatusCode::CONFLICT);
+    }
+
+    #[tokio::test]
+    async fn handle_move_path_route_returns_bad_request_for_folder_cross_folder_move() {
+        let server = Server::new_for_test();
+        insert_test_folder_doc(
             &server,
-            "22222222-2222-4222-8222-222222222222",
-            "---\nurl: \"https://www.youtube.com/watch?v=Nl7-bRFSZBs\"\n---\n",
+            "Relay Folder 1",
+            &[("/Old", "22222222-2222-4222-8222-222222222222", "folder")],
         )
         .await;
 
-        let (status, body) = post_check_video_ids(
+        let (status, _) = post_move(
             &server,
             json!({
-                "folder": "Lens Edu",
-                "subfolder": "video_transcripts",
-                "video_ids": ["GMTDrG3hYJ0", "Nl7-bRFSZBs"]
+                "path": "Relay Folder 1/Old",
+                "new_path": "/New",
+                "target_folder": "Relay Folder 2"
             }),
         )
         .await;
 
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    async fn delete_path_request(server: &Arc<Server>, path: &str) -> (StatusCode, JsonValue) {
+        let uri = format!("/doc?path={}", path.replace(' ', "%20"));
+        let response = server
+            .routes()
+            .oneshot(
+                Request::builder()
+                    .method(Method::DELETE)
+                    .uri(uri)
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let status = response.status();
+        let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body = serde_json::from_slice(&bytes).unwrap_or_else(|_| json!({}));
+        (status, body)
+    }
+
+    #[tokio::test]
+    async fn handle_delete_path_route_removes_doc_and_index_entries() {
+        const TARGET_UUID: &str = "11111111-1111-4111-8111-111111111111";
+        const DELETED_UUID: &str = "22222222-2222-4222-8222-222222222222";
+        let server = Server::new_for_test();
+        let folder_doc_id = insert_test_folder_doc(
+            &server,
+            "Relay Folder 1",
+            &[
+                ("/Target.md", TARGET_UUID, "markdown"),
+                ("/Notes", "33333333-3333-4333-8333-333333333333", "folder"),
+                ("/Notes/Gone.md", DELETED_UUID, "markdown"),
+            ],
+        )
+        .await;
+        insert_test_content_doc(&server, DELETED_UUID, "See [[Target]] {++new++}").await;
+        {
+            let doc_ref = server.docs().get(&folder_doc_id).unwrap();
+            let awareness = doc_ref.awareness();
+            let guard = awareness.write().unwrap();
+            let mut txn = guard.doc.transact_mut();
+            let backlinks = txn.get_or_insert_map("backlinks_v0");
+            backlinks.insert(
+                &mut txn,
+                TARGET_UUID,
+                Any::from(vec![Any::String(DELETED_UUID.into())]),
+            );
+        }
+        server
+            .suggestions_index
+            .update(DELETED_UUID, scan_suggestions("See [[Target]] {++new++}"));
+
+        let (status, body) = delete_path_request(&server, "Relay Folder 1/Notes/Gone.md").await;
+
         assert_eq!(status, StatusCode::OK);
-        assert_eq!(body["found"]["GMTDrG3hYJ0"], "/video_transcripts/Short.md");
-        assert_eq!(body["found"]["Nl7-bRFSZBs"], "/video_transcripts/Normal.md");
+        assert_eq!(body["uuid"], DELETED_UUID);
+        assert_eq!(body["folder"], "Relay Folder 1");
+        assert_eq!(body["path"], "/Notes/Gone.md");
+        assert_eq!(body["backlinks_removed"], 1);
+        assert!(!filemeta_has(&server, &folder_doc_id, "/Notes/Gone.md"));
+        assert_eq!(
+            legacy_docs_value(&server, &folder_doc_id, "/Notes/Gone.md"),
+            None
+        );
+        assert!(filemeta_has(&server, &folder_doc_id, "/Notes"));
+        {
+            let doc_ref = server.docs().get(&folder_doc_id).unwrap();
+            let awareness = doc_ref.awareness();
+            let guard = awareness.read().unwrap();
+            let txn = guard.doc.transact();
+            let backlinks = txn.get_map("backlinks_v0").unwrap();
+            assert!(backlinks.get(&txn, TARGET_UUID).is_none());
+        }
+        assert!(server.suggestions_index.get(DELETED_UUID).is_none());
+        assert!(server
+            .doc_resolver()
+            .resolve_path("Relay Folder 1/Notes/Gone.md")
+            .is_none());
+        // The content doc is left for the store; the folder keeps a trash
+        // record of it.
+        assert!(server
+            .docs()
+            .contains_key(&format!("{}-{}", TEST_RELAY_ID, DELETED_UUID)));
+        let trashed = server.list_trash(&folder_doc_id).unwrap();
+        assert_eq!(trashed.len(), 1);
+        assert_eq!(trashed[0].id, DELETED_UUID);
+        assert_eq!(trashed[0].path, "/Notes/Gone.md");
+        assert_eq!(trashed[0].deleted_by.as_deref(), Some("api"));
+
+        let (status, _) = delete_path_request(&server, "Relay Folder 1/Notes/Gone.md").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
     }
 
     #[tokio::test]
-    async fn check_video_ids_ignores_compact_youtube_paths() {
+    async fn handle_delete_path_route_rejects_folders_and_files() {
         let server = Server::new_for_test();
-        insert_test_folder_doc(
+        let folder_doc_id = insert_test_folder_doc(
             &server,
-            "Lens Edu",
-            &[(
-                "/video_transcripts/Legacy.md",
-                "11111111-1111-4111-8111-111111111111",
-                "markdown",
-            )],
+            "Relay Folder 1",
+            &[
+                ("/Notes", "33333333-3333-4333-8333-333333333333", "folder"),
+                ("/img.png", "44444444-4444-4444-8444-444444444444", "image"),
+            ],
         )
         .await;
-        insert_test_content_doc(
+
+        let (status, _) = delete_path_request(&server, "Relay Folder 1/Notes").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let (status, _) = delete_path_request(&server, "Relay Folder 1/img.png").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(filemeta_has(&server, &folder_doc_id, "/Notes"));
+        assert!(filemeta_has(&server, &folder_doc_id, "/img.png"));
+    }
+
+    #[tokio::test]
+    async fn trash_routes_list_and_restore_deleted_docs() {
+        const TARGET_UUID: &str = "11111111-1111-4111-8111-111111111111";
+        const DELETED_UUID: &str = "22222222-2222-4222-8222-222222222222";
+        let server = Server::new_for_test();
+        let folder_doc_id = insert_test_folder_doc(
             &server,
-            "11111111-1111-4111-8111-111111111111",
-            "---\nurl: \"/GMTDrG3hYJ0\"\n---\n",
+            "Relay Folder 1",
+            &[
+                ("/Target.md", TARGET_UUID, "markdown"),
+                ("/Notes", "33333333-3333-4333-8333-333333333333", "folder"),
+                ("/Notes/Gone.md", DELETED_UUID, "markdown"),
+            ],
         )
         .await;
+        insert_test_content_doc(&server, DELETED_UUID, "See [[Target]]").await;
+        server
+            .delete_path("Relay Folder 1/Notes/Gone.md", Some("Alice"))
+            .await
+            .unwrap();
 
-        let (status, body) = post_check_video_ids(
+        let trash_uri = format!("/folder/{}/trash", TEST_FOLDER_UUID);
+        let response = server
+            .routes()
+            .oneshot(
+                Request::builder()
+                    .uri(&trash_uri)
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let listed: JsonValue = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(listed[0]["id"], DELETED_UUID);
+        assert_eq!(listed[0]["path"], "/Notes/Gone.md");
+        assert_eq!(listed[0]["type"], "markdown");
+        assert_eq!(listed[0]["deletedBy"], "Alice");
+
+        let restore = |server: Arc<Server>| async move {
+            server
+                .routes()
+                .oneshot(
+                    Request::builder()
+                        .method(Method::POST)
+                        .uri(format!(
+                            "/folder/{}/trash/{}/restore",
+                            TEST_FOLDER_UUID, DELETED_UUID
+                        ))
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap()
+                .status()
+        };
+        assert_eq!(restore(server.clone()).await, StatusCode::OK);
+        assert!(filemeta_has(&server, &folder_doc_id, "/Notes/Gone.md"));
+        assert_eq!(
+            legacy_docs_value(&server, &folder_doc_id, "/Notes/Gone.md").as_deref(),
+            Some(DELETED_UUID)
+        );
+        assert!(server
+            .doc_resolver()
+            .resolve_path("Relay Folder 1/Notes/Gone.md")
+            .is_some());
+        assert!(server.list_trash(&folder_doc_id).unwrap().is_empty());
+
+        assert_eq!(restore(server.clone()).await, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn trash_tracker_records_client_deletions() {
+        const MOVED_UUID: &str = "44444444-4444-4444-8444-444444444444";
+        const GONE_UUID: &str = "55555555-5555-4555-8555-555555555555";
+        let server = Server::new_for_test();
+        let folder_doc_id = insert_test_folder_doc(
             &server,
-            json!({
-                "folder": "Lens Edu",
-                "subfolder": "video_transcripts",
-                "video_ids": ["GMTDrG3hYJ0"]
-            }),
+            "Relay Folder 1",
+            &[
+                ("/Moved.md", MOVED_UUID, "markdown"),
+                ("/Gone.md", GONE_UUID, "markdown"),
+            ],
         )
         .await;
+        let mut tracker = crate::trash::TrashTracker::new(crate::trash::TrashConfig::default());
+        assert_eq!(tracker.scan(&server).await, 0);
+
+        // A client renames one file and deletes the other.
+        let client_edit = |edit: &dyn Fn(&mut yrs::TransactionMut, &yrs::MapRef)| {
+            let doc_ref = server.docs().get(&folder_doc_id).unwrap();
+            let awareness = doc_ref.awareness();
+            let guard = awareness.write().unwrap();
+            let mut txn = guard.doc.transact_mut();
+            let filemeta = txn.get_or_insert_map("filemeta_v0");
+            edit(&mut txn, &filemeta);
+        };
+        client_edit(&|txn, filemeta| {
+            let moved = filemeta.get(&*txn, "/Moved.md").unwrap().to_json(&*txn);
+            filemeta.remove(txn, "/Moved.md");
+            filemeta.insert(txn, "/Renamed.md", moved);
+            filemeta.remove(txn, "/Gone.md");
+        });
+        assert_eq!(tracker.scan(&server).await, 1);
+        let trashed = server.list_trash(&folder_doc_id).unwrap();
+        assert_eq!(trashed.len(), 1);
+        assert_eq!(trashed[0].id, GONE_UUID);
+        assert_eq!(trashed[0].path, "/Gone.md");
+        // No users map, so nobody to attribute it to.
+        assert_eq!(trashed[0].deleted_by, None);
+
+        // Undoing the deletion takes it back out of the trash.
+        client_edit(&|txn, filemeta| {
+            let mut meta = std::collections::HashMap::new();
+            meta.insert("id".to_string(), Any::String(GONE_UUID.into()));
+            meta.insert("type".to_string(), Any::String("markdown".into()));
+            filemeta.insert(txn, "/Gone.md", Any::Map(meta.into()));
+        });
+        assert_eq!(tracker.scan(&server).await, 0);
+        assert!(server.list_trash(&folder_doc_id).unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn purge_trash_removes_expired_content_from_the_store() {
+        use async_trait::async_trait;
+        use std::collections::HashMap;
+        use std::sync::Mutex;
+        use y_sweet_core::store::Result as StoreResult;
+
+        #[derive(Clone, Default)]
+        struct MemoryStore {
+            data: Arc<Mutex<HashMap<String, Vec<u8>>>>,
+        }
+
+        #[async_trait]
+        impl Store for MemoryStore {
+            async fn init(&self) -> StoreResult<()> {
+                Ok(())
+            }
+
+            async fn get(&self, key: &str) -> StoreResult<Option<Vec<u8>>> {
+                Ok(self.data.lock().unwrap().get(key).cloned())
+            }
+
+            async fn set(&self, key: &str, value: Vec<u8>) -> StoreResult<()> {
+                self.data.lock().unwrap().insert(key.to_string(), value);
+                Ok(())
+            }
+
+            async fn remove(&self, key: &str) -> StoreResult<()> {
+                self.data.lock().unwrap().remove(key);
+                Ok(())
+            }
+
+            async fn exists(&self, key: &str) -> StoreResult<bool> {
+                Ok(self.data.lock().unwrap().contains_key(key))
+            }
+        }
+
+        const OLD_DOC: &str = "66666666-6666-4666-8666-666666666666";
+        const OLD_BLOB: &str = "77777777-7777-4777-8777-777777777777";
+        const RECENT_DOC: &str = "88888888-8888-4888-8888-888888888888";
+        let store = MemoryStore::default();
+        let server = Arc::new(
+            Server::new_without_workers(
+                Some(Box::new(store.clone())),
+                Duration::from_secs(60),
+                None,
+                None,
+                vec![],
+                CancellationToken::new(),
+                false,
+                None,
+            )
+            .await
+            .unwrap(),
+        );
+        let folder_doc_id = insert_test_folder_doc(&server, "Relay Folder 1", &[]).await;
+        let keys = [
+            format!("{}-{}/data.ysweet", TEST_RELAY_ID, OLD_DOC),
+            format!("files/{}-{}/abc123", TEST_RELAY_ID, OLD_BLOB),
+            format!("{}-{}/data.ysweet", TEST_RELAY_ID, RECENT_DOC),
+        ];
+        for key in &keys {
+            store.set(key, vec![1]).await.unwrap();
+        }
+        {
+            let doc_ref = server.docs().get(&folder_doc_id).unwrap();
+            let awareness = doc_ref.awareness();
+            let guard = awareness.write().unwrap();
+            let mut txn = guard.doc.transact_mut();
+            let meta = |id: &str, entry_type: &str| {
+                let mut meta = HashMap::new();
+                meta.insert("id".to_string(), Any::String(id.into()));
+                meta.insert("type".to_string(), Any::String(entry_type.into()));
+                meta
+            };
+            let mut blob = meta(OLD_BLOB, "image");
+            blob.insert("hash".to_string(), Any::String("abc123".into()));
+            let now = current_time_epoch_millis();
+            trash::put_in_trash(
+                &mut txn,
+                OLD_DOC,
+                "/Old.md",
+                &meta(OLD_DOC, "markdown"),
+                1_000,
+                None,
+            );
+            trash::put_in_trash(&mut txn, OLD_BLOB, "/old.png", &blob, 1_000, None);
+            trash::put_in_trash(
+                &mut txn,
+                RECENT_DOC,
+                "/Recent.md",
+                &meta(RECENT_DOC, "markdown"),
+                now,
+                None,
+            );
+        }
 
-        assert_eq!(status, StatusCode::OK);
-        assert!(body["found"]["GMTDrG3hYJ0"].is_null());
+        assert_eq!(server.purge_trash(Duration::from_secs(3600), &[]).await, 2);
+        let data = store.data.lock().unwrap().clone();
+        assert!(!data.contains_key(&keys[0]));
+        assert!(!data.contains_key(&keys[1]));
+        assert!(data.contains_key(&keys[2]));
+        let left: Vec<String> = server
+            .list_trash(&folder_doc_id)
+            .unwrap()
+            .into_iter()
+            .map(|item| item.id)
+            .collect();
+        assert_eq!(left, vec![RECENT_DOC.to_string()]);
     }
 
-    async fn post_check_source_urls(
-        server: &Arc<Server>,
-        body: JsonValue,
-    ) -> (StatusCode, JsonValue) {
-        let response = server
-            .routes()
-            .oneshot(
-                Request::builder()
-                    .method(Method::POST)
-                    .uri("/doc/check-source-urls")
-                    .header("content-type", "application/json")
-                    .body(Body::from(body.to_string()))
-                    .unwrap(),
+    #[tokio::test]
+    async fn purge_trash_keeps_content_live_in_an_evicted_folder() {
+        use async_trait::async_trait;
+        use std::collections::HashMap;
+        use std::sync::Mutex;
+        use y_sweet_core::store::Result as StoreResult;
+
+        #[derive(Clone, Default)]
+        struct MemoryStore {
+            data: Arc<Mutex<HashMap<String, Vec<u8>>>>,
+        }
+
+        #[async_trait]
+        impl Store for MemoryStore {
+            async fn init(&self) -> StoreResult<()> {
+                Ok(())
+            }
+
+            async fn get(&self, key: &str) -> StoreResult<Option<Vec<u8>>> {
+                Ok(self.data.lock().unwrap().get(key).cloned())
+            }
+
+            async fn set(&self, key: &str, value: Vec<u8>) -> StoreResult<()> {
+                self.data.lock().unwrap().insert(key.to_string(), value);
+                Ok(())
+            }
+
+            async fn remove(&self, key: &str) -> StoreResult<()> {
+                self.data.lock().unwrap().remove(key);
+                Ok(())
+            }
+
+            async fn exists(&self, key: &str) -> StoreResult<bool> {
+                Ok(self.data.lock().unwrap().contains_key(key))
+            }
+        }
+
+        // Trashed in the loaded folder, re-added in another one that GC
+        // has evicted since the trash tracker saw it.
+        const MOVED_DOC: &str = "99999999-9999-4999-8999-999999999999";
+        let store = MemoryStore::default();
+        let server = Arc::new(
+            Server::new_without_workers(
+                Some(Box::new(store.clone())),
+                Duration::from_secs(60),
+                None,
+                None,
+                vec![],
+                CancellationToken::new(),
+                false,
+                None,
             )
+            .await
+            .unwrap(),
+        );
+        let folder_doc_id = insert_test_folder_doc(&server, "Relay Folder 1", &[]).await;
+        let content_key = format!("{}-{}/data.ysweet", TEST_RELAY_ID, MOVED_DOC);
+        store.set(&content_key, vec![1]).await.unwrap();
+        let meta = || {
+            let mut meta = HashMap::new();
+            meta.insert("id".to_string(), Any::String(MOVED_DOC.into()));
+            meta.insert("type".to_string(), Any::String("markdown".into()));
+            meta
+        };
+        {
+            let doc_ref = server.docs().get(&folder_doc_id).unwrap();
+            let awareness = doc_ref.awareness();
+            let guard = awareness.write().unwrap();
+            let mut txn = guard.doc.transact_mut();
+            trash::put_in_trash(&mut txn, MOVED_DOC, "/Moved.md", &meta(), 1_000, None);
+        }
+        let evicted_folder_id = format!("{}-b0000002-0000-4000-8000-000000000002", TEST_RELAY_ID);
+        let doc_store: Arc<Box<dyn Store>> = Arc::new(Box::new(store.clone()));
+        let evicted = DocWithSyncKv::new(&evicted_folder_id, Some(doc_store), || (), None)
             .await
             .unwrap();
-        let status = response.status();
-        let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
-        let body = serde_json::from_slice(&bytes).unwrap_or_else(|_| json!({}));
-        (status, body)
+        {
+            let awareness = evicted.awareness();
+            let guard = awareness.write().unwrap();
+            let mut txn = guard.doc.transact_mut();
+            txn.get_or_insert_map("filemeta_v0").insert(
+                &mut txn,
+                "/Moved.md",
+                Any::Map(meta().into()),
+            );
+        }
+        evicted.sync_kv().persist().await.unwrap();
+        drop(evicted);
+
+        assert_eq!(
+            server
+                .purge_trash(Duration::from_secs(3600), &[evicted_folder_id.clone()])
+                .await,
+            1
+        );
+        assert!(store.data.lock().unwrap().contains_key(&content_key));
+        assert!(server.list_trash(&folder_doc_id).unwrap().is_empty());
+        assert!(server.docs().contains_key(&evicted_folder_id));
     }
 
     #[tokio::test]
-    async fn check_source_urls_matches_frontmatter_and_normalizes_trailing_slash() {
-        let server = Server::new_for_test();
-        insert_test_folder_doc(
-            &server,
-            "Lens Edu",
-            &[
-                (
-                    "/articles/a.md",
-                    "11111111-1111-4111-8111-111111111111",
-                    "markdown",
-                ),
-                (
-                    "/articles/b.md",
-                    "22222222-2222-4222-8222-222222222222",
-                    "markdown",
-                ),
-            ],
-        )
-        .await;
-        insert_test_content_doc(
-            &server,
-            "11111111-1111-4111-8111-111111111111",
-            "---\ntitle: \"A\"\nsource_url: \"https://ai-safety-atlas.com/chapters/v1/risks/introduction\"\n---\n\nBody.",
-        )
-        .await;
-        insert_test_content_doc(
-            &server,
-            "22222222-2222-4222-8222-222222222222",
-            "---\ntitle: \"B\"\nsource_url: \"https://example.com/post\"\ntags:\n  - article-stub\n  - validator-ignore\n---\n\n%%\nA discussion note.\n%%",
+    async fn test_auth_doc() {
+        let server_state = Server::new_without_workers(
+            None,
+            Duration::from_secs(60),
+            None,
+            None,
+            vec![],
+            CancellationToken::new(),
+            true,
+            None,
         )
-        .await;
+        .await
+        .unwrap();
 
-        let (status, body) = post_check_source_urls(
-            &server,
-            json!({
-                "folder": "Lens Edu",
-                "subfolder": "articles",
-                "source_urls": [
-                    // trailing slash on the query must still match the stored URL
-                    "https://ai-safety-atlas.com/chapters/v1/risks/introduction/",
-                    "https://example.com/post",
-                    "https://unimported.example/x"
-                ]
-            }),
+        let doc_id = server_state.create_doc().await.unwrap();
+
+        let token = auth_doc(
+            None,
+            TypedHeader(headers::Host::from(http::uri::Authority::from_static(
+                "localhost",
+            ))),
+            State(Arc::new(server_state)),
+            Path(doc_id.clone()),
+            Some(Json(AuthDocRequest {
+                authorization: Authorization::Full,
+                user_id: None,
+                valid_for_seconds: None,
+            })),
         )
-        .await;
+        .await
+        .unwrap();
 
-        assert_eq!(status, StatusCode::OK);
-        assert_eq!(
-            body["found"]["https://ai-safety-atlas.com/chapters/v1/risks/introduction/"],
-            "/articles/a.md"
-        );
-        assert_eq!(body["found"]["https://example.com/post"], "/articles/b.md");
-        assert_eq!(
-            body["stubs"]["https://example.com/post"]["path"],
-            "/articles/b.md"
-        );
-        assert!(body["stubs"]["https://example.com/post"]["content"]
-            .as_str()
-            .unwrap()
-            .contains("A discussion note."));
-        // Full articles remain lightweight: their bodies are not returned.
-        assert!(
-            body["stubs"]["https://ai-safety-atlas.com/chapters/v1/risks/introduction/"].is_null()
-        );
-        assert!(body["found"]["https://unimported.example/x"].is_null());
+        let expected_url = format!("ws://localhost/d/{doc_id}/ws");
+        assert_eq!(token.url, expected_url);
+        assert_eq!(token.doc_id, doc_id);
+        assert!(token.token.is_none());
     }
 
-    // Frontmatter-precise: a URL that appears only in the body must NOT match
-    // (unlike a naive substring scan).
     #[tokio::test]
-    async fn check_source_urls_ignores_url_in_body_only() {
-        let server = Server::new_for_test();
-        insert_test_folder_doc(
-            &server,
-            "Lens Edu",
-            &[(
-                "/articles/a.md",
-                "11111111-1111-4111-8111-111111111111",
-                "markdown",
-            )],
-        )
-        .await;
-        insert_test_content_doc(
-            &server,
-            "11111111-1111-4111-8111-111111111111",
-            "---\ntitle: \"A\"\nsource_url: \"https://example.com/real\"\n---\n\nSee https://example.com/body-only for more.",
+    async fn test_auth_doc_with_prefix() {
+        let prefix: Url = "https://foo.bar".parse().unwrap();
+        let server_state = Server::new_without_workers(
+            None,
+            Duration::from_secs(60),
+            None,
+            Some(prefix),
+            vec![],
+            CancellationToken::new(),
+            true,
+            None,
         )
-        .await;
+        .await
+        .unwrap();
 
-        let (status, body) = post_check_source_urls(
-            &server,
-            json!({
-                "folder": "Lens Edu",
-                "subfolder": "articles",
-                "source_urls": ["https://example.com/body-only"]
-            }),
+        let doc_id = server_state.create_doc().await.unwrap();
+
+        let token = auth_doc(
+            None,
+            TypedHeader(headers::Host::from(http::uri::Authority::from_static(
+                "localhost",
+            ))),
+            State(Arc::new(server_state)),
+            Path(doc_id.clone()),
+            None,
         )
-        .await;
+        .await
+        .unwrap();
 
-        assert_eq!(status, StatusCode::OK);
-        assert!(body["found"]["https://example.com/body-only"].is_null());
+        let expected_url = format!("wss://foo.bar/d/{doc_id}/ws");
+        assert_eq!(token.url, expected_url);
+        assert_eq!(token.doc_id, doc_id);
+        assert!(token.token.is_none());
     }
 
     #[tokio::test]
-    async fn move_path_renames_non_markdown_file_metadata() {
-        let server = Server::new_for_test();
-        let folder_doc_id = insert_test_folder_doc(
-            &server,
-            "Relay Folder 1",
-            &[(
-                "/source.timestamps.json",
-                "22222222-2222-4222-8222-222222222222",
-                "file",
-            )],
-        )
-        .await;
+    async fn test_websocket_auth_rejects_missing_token_when_auth_configured() {
+        let authenticator = y_sweet_core::auth::Authenticator::gen_key().unwrap();
+        let server_state = Arc::new(
+            Server::new_without_workers(
+                None,
+                Duration::from_secs(60),
+                Some(authenticator),
+                None,
+                vec![],
+                CancellationToken::new(),
+                true,
+                None,
+            )
+            .await
+            .unwrap(),
+        );
+
+        let err = verify_socket_token(&server_state, "test-doc", None).unwrap_err();
 
-        let result = server
-            .move_path(
-                "Relay Folder 1/source.timestamps.json",
-                "/renamed.timestamps.json",
+        assert_eq!(err.status, StatusCode::UNAUTHORIZED);
+        assert_eq!(err.auth_error_type, Some("missing_token"));
+    }
+
+    #[tokio::test]
+    async fn test_websocket_auth_allows_missing_token_without_authenticator() {
+        let server_state = Arc::new(
+            Server::new_without_workers(
+                None,
+                Duration::from_secs(60),
+                None,
+                None,
+                vec![],
+                CancellationToken::new(),
+                true,
                 None,
             )
             .await
-            .unwrap();
-
-        assert_eq!(result.old_path, "/source.timestamps.json");
-        assert_eq!(result.new_path, "/renamed.timestamps.json");
-        assert!(!filemeta_has(
-            &server,
-            &folder_doc_id,
-            "/source.timestamps.json"
-        ));
-        assert!(filemeta_has(
-            &server,
-            &folder_doc_id,
-            "/renamed.timestamps.json"
-        ));
-        assert_eq!(
-            legacy_docs_value(&server, &folder_doc_id, "/renamed.timestamps.json"),
-            None
+            .unwrap(),
         );
+
+        let (authorization, channel, user) =
+            verify_socket_token(&server_state, "test-doc", None).unwrap();
+
+        assert_eq!(authorization, Authorization::Full);
+        assert_eq!(channel, None);
+        assert_eq!(user, None);
     }
 
     #[tokio::test]
-    async fn folder_rename_moves_metadata() {
-        let server = Server::new_for_test();
-        let folder_doc_id = insert_test_folder_doc(
-            &server,
-            "Relay Folder 1",
-            &[
-                ("/Old", "22222222-2222-4222-8222-222222222222", "folder"),
-                (
-                    "/Old/Child.md",
-                    "33333333-3333-4333-8333-333333333333",
-                    "markdown",
-                ),
-                (
-                    "/Old/Page.html",
-                    "44444444-4444-4444-8444-444444444444",
-                    "file",
-                ),
-            ],
+    async fn test_read_only_socket_access_allows_persisted_unloaded_doc() {
+        use async_trait::async_trait;
+        use std::collections::HashSet;
+        use y_sweet_core::store::Result as StoreResult;
+
+        struct ExistingDocStore {
+            existing_keys: HashSet<String>,
+        }
+
+        #[async_trait]
+        impl Store for ExistingDocStore {
+            async fn init(&self) -> StoreResult<()> {
+                Ok(())
+            }
+
+            async fn get(&self, _key: &str) -> StoreResult<Option<Vec<u8>>> {
+                Ok(None)
+            }
+
+            async fn set(&self, _key: &str, _value: Vec<u8>) -> StoreResult<()> {
+                Ok(())
+            }
+
+            async fn remove(&self, _key: &str) -> StoreResult<()> {
+                Ok(())
+            }
+
+            async fn exists(&self, key: &str) -> StoreResult<bool> {
+                Ok(self.existing_keys.contains(key))
+            }
+        }
+
+        let doc_id = "persisted-doc";
+        let store = ExistingDocStore {
+            existing_keys: HashSet::from([format!("{}/data.ysweet", doc_id)]),
+        };
+        let server = Server::new_without_workers(
+            Some(Box::new(store)),
+            Duration::from_secs(60),
+            None,
+            None,
+            vec![],
+            CancellationToken::new(),
+            true,
+            None,
         )
-        .await;
-        insert_test_content_doc(&server, "33333333-3333-4333-8333-333333333333", "Child").await;
+        .await
+        .unwrap();
 
+        assert!(!server.docs.contains_key(doc_id));
         server
-            .move_path("Relay Folder 1/Old", "/New", None)
+            .ensure_socket_doc_access(doc_id, Authorization::ReadOnly)
             .await
             .unwrap();
 
-        assert!(!filemeta_has(&server, &folder_doc_id, "/Old"));
-        assert!(!filemeta_has(&server, &folder_doc_id, "/Old/Child.md"));
-        assert!(!filemeta_has(&server, &folder_doc_id, "/Old/Page.html"));
-        assert!(filemeta_has(&server, &folder_doc_id, "/New"));
-        assert!(filemeta_has(&server, &folder_doc_id, "/New/Child.md"));
-        assert!(filemeta_has(&server, &folder_doc_id, "/New/Page.html"));
-        assert_eq!(
-            legacy_docs_value(&server, &folder_doc_id, "/New"),
-            Some("22222222-2222-4222-8222-222222222222".to_string())
-        );
-        assert_eq!(
-            legacy_docs_value(&server, &folder_doc_id, "/New/Child.md"),
-            Some("33333333-3333-4333-8333-333333333333".to_string())
-        );
-        assert_eq!(
-            legacy_docs_value(&server, &folder_doc_id, "/New/Page.html"),
-            None
-        );
-        assert_eq!(legacy_docs_value(&server, &folder_doc_id, "/Old"), None);
-        assert_eq!(
-            legacy_docs_value(&server, &folder_doc_id, "/Old/Child.md"),
-            None
-        );
-        assert_eq!(
-            legacy_docs_value(&server, &folder_doc_id, "/Old/Page.html"),
-            None
-        );
-    }
-
-    #[tokio::test]
-    async fn folder_rename_moves_synthetic_ancestor_folder() {
-        let server = Server::new_for_test();
-        let folder_doc_id = insert_test_folder_doc(
-            &server,
-            "Relay Folder 1",
-            &[(
-                "/articles/Article.md",
-                "33333333-3333-4333-8333-333333333333",
-                "markdown",
-            )],
-        )
-        .await;
-        insert_test_content_doc(&server, "33333333-3333-4333-8333-333333333333", "Article").await;
+        let err = server
+            .ensure_socket_doc_access("missing-doc", Authorization::ReadOnly)
+            .await
+            .unwrap_err();
+        assert_eq!(err.status, StatusCode::NOT_FOUND);
 
-        let result = server
-            .move_path("Relay Folder 1/articles", "/renamed", None)
+        server
+            .ensure_socket_doc_access("new-doc", Authorization::Full)
             .await
             .unwrap();
-
-        assert_eq!(result.old_path, "/articles");
-        assert_eq!(result.new_path, "/renamed");
-        assert!(!filemeta_has(
-            &server,
-            &folder_doc_id,
-            "/articles/Article.md"
-        ));
-        assert!(filemeta_has(&server, &folder_doc_id, "/renamed/Article.md"));
-        assert_eq!(
-            legacy_docs_value(&server, &folder_doc_id, "/articles/Article.md"),
-            None
-        );
-        assert_eq!(
-            legacy_docs_value(&server, &folder_doc_id, "/renamed/Article.md"),
-            Some("33333333-3333-4333-8333-333333333333".to_string())
-        );
     }
 
     #[tokio::test]
-    async fn folder_rename_rejects_synthetic_destination_collision_without_mutation() {
-        let server = Server::new_for_test();
-        let folder_doc_id = insert_test_folder_doc(
-            &server,
-            "Relay Folder 1",
-            &[
-                (
-                    "/articles/Article.md",
-                    "33333333-3333-4333-8333-333333333333",
-                    "markdown",
-                ),
-                (
-                    "/renamed/Article.md",
-                    "44444444-4444-4444-8444-444444444444",
-                    "markdown",
-                ),
-            ],
-        )
-        .await;
-        insert_test_content_doc(&server, "33333333-3333-4333-8333-333333333333", "Article").await;
-        insert_test_content_doc(&server, "44444444-4444-4444-8444-444444444444", "Existing").await;
+    async fn doc_version_text_and_diff_endpoints() {
+        use async_trait::async_trait;
+        use std::collections::HashMap;
+        use std::sync::Mutex;
+        use y_sweet_core::store::Result as StoreResult;
 
-        let result = server
-            .move_path("Relay Folder 1/articles", "/renamed", None)
-            .await;
+        /// Keeps every write as a new version, like a versioned S3 bucket.
+        #[derive(Clone, Default)]
+        struct VersionedStore {
+            versions: Arc<Mutex<HashMap<String, Vec<Vec<u8>>>>>,
+        }
+
+        #[async_trait]
+        impl Store for VersionedStore {
+            async fn init(&self) -> StoreResult<()> {
+                Ok(())
+            }
+
+            async fn get(&self, key: &str) -> StoreResult<Option<Vec<u8>>> {
+                let versions = self.versions.lock().unwrap();
+                Ok(versions.get(key).and_then(|v| v.last().cloned()))
+            }
+
+            async fn set(&self, key: &str, value: Vec<u8>) -> StoreResult<()> {
+                let mut versions = self.versions.lock().unwrap();
+                versions.entry(key.to_string()).or_default().push(value);
+                Ok(())
+            }
+
+            async fn remove(&self, _key: &str) -> StoreResult<()> {
+                Ok(())
+            }
+
+            async fn exists(&self, key: &str) -> StoreResult<bool> {
+                Ok(self.versions.lock().unwrap().contains_key(key))
+            }
+
+            async fn list_versions(&self, key: &str) -> StoreResult<Vec<VersionInfo>> {
+                let versions = self.versions.lock().unwrap();
+                let count = versions.get(key).map_or(0, Vec::len);
+                Ok((0..count)
+                    .rev()
+                    .map(|i| VersionInfo {
+                        version_id: format!("v{}", i),
+                        last_modified: i as u64,
+                        is_latest: i + 1 == count,
+                    })
+                    .collect())
+            }
+
+            async fn get_version(
+                &self,
+                key: &str,
+                version_id: &str,
+            ) -> StoreResult<Option<Vec<u8>>> {
+                let versions = self.versions.lock().unwrap();
+                let index = version_id
+                    .strip_prefix('v')
+                    .and_then(|i| i.parse::<usize>().ok());
+                Ok(index.and_then(|i| versions.get(key)?.get(i).cloned()))
+            }
+        }
+
+        let store = VersionedStore::default();
+        let doc_id = "history-doc";
+        let persisted =
+            DocWithSyncKv::new(doc_id, Some(Arc::new(Box::new(store.clone()))), || (), None)
+                .await
+                .unwrap();
+        for line in ["line one\n", "line two\n"] {
+            {
+                let awareness = persisted.awareness();
+                let guard = awareness.write().unwrap();
+                let mut txn = guard.doc.transact_mut();
+                let text = txn.get_or_insert_text("contents");
+                let end = text.len(&txn);
+                text.insert(&mut txn, end, line);
+            }
+            persisted.sync_kv().persist().await.unwrap();
+        }
+
+        let server_state = Arc::new(
+            Server::new_without_workers(
+                Some(Box::new(store)),
+                Duration::from_secs(60),
+                None,
+                None,
+                vec![],
+                CancellationToken::new(),
+                false,
+                None,
+            )
+            .await
+            .unwrap(),
+        );
+        let live = DocWithSyncKv::new(doc_id, None, || (), None).await.unwrap();
+        {
+            let awareness = live.awareness();
+            let guard = awareness.write().unwrap();
+            let mut txn = guard.doc.transact_mut();
+            txn.get_or_insert_text("contents").insert(
+                &mut txn,
+                0,
+                "line one\nline two\nline three\n",
+            );
+        }
+        server_state.docs().insert(doc_id.to_string(), live);
 
-        assert!(matches!(result, Err(MoveDocumentError::Conflict(_))));
-        assert!(filemeta_has(
-            &server,
-            &folder_doc_id,
-            "/articles/Article.md"
-        ));
-        assert!(filemeta_has(&server, &folder_doc_id, "/renamed/Article.md"));
-    }
+        let Json(listed) =
+            handle_doc_versions(State(server_state.clone()), Path(doc_id.to_string()), None)
+                .await
+                .unwrap();
+        let ids: Vec<&str> = listed
+            .versions
+            .iter()
+            .map(|v| v.version_id.as_str())
+            .collect();
+        assert_eq!(ids, vec!["v1", "v0"]);
 
-    #[tokio::test]
-    async fn folder_rename_rewrites_synthetic_descendant_backlinks() {
-        let server = Server::new_for_test();
-        let folder_doc_id = insert_test_folder_doc(
-            &server,
-            "Relay Folder 1",
-            &[
-                (
-                    "/articles/Article.md",
-                    "33333333-3333-4333-8333-333333333333",
-                    "markdown",
-                ),
-                (
-                    "/Backlinker.md",
-                    "44444444-4444-4444-8444-444444444444",
-                    "markdown",
-                ),
-            ],
+        let Json(version) = handle_doc_version_text(
+            State(server_state.clone()),
+            Path((doc_id.to_string(), "v0".to_string())),
+            None,
         )
-        .await;
-        insert_test_content_doc(&server, "33333333-3333-4333-8333-333333333333", "Article").await;
-        insert_test_content_doc(
-            &server,
-            "44444444-4444-4444-8444-444444444444",
-            "See [[articles/Article]]",
+        .await
+        .unwrap();
+        assert_eq!(version.text, "line one\n");
+
+        let Json(diff) = handle_doc_diff(
+            State(server_state.clone()),
+            Path(doc_id.to_string()),
+            Query(DocDiffQuery {
+                from: "v0".to_string(),
+                to: Some("v1".to_string()),
+            }),
+            None,
         )
-        .await;
-        insert_backlink(
-            &server,
-            &folder_doc_id,
-            "33333333-3333-4333-8333-333333333333",
-            "44444444-4444-4444-8444-444444444444",
+        .await
+        .unwrap();
+        assert_eq!(
+            diff.diff,
+            "--- history-doc@v0\n+++ history-doc@v1\n@@ -1 +1,2 @@\n line one\n+line two\n"
         );
 
-        let result = server
-            .move_path("Relay Folder 1/articles", "/renamed", None)
-            .await
-            .unwrap();
+        let Json(diff) = handle_doc_diff(
+            State(server_state.clone()),
+            Path(doc_id.to_string()),
+            Query(DocDiffQuery {
+                from: "v1".to_string(),
+                to: None,
+            }),
+            None,
+        )
+        .await
+        .unwrap();
+        assert_eq!(diff.to, "current");
+        assert!(diff.diff.contains("+line three\n"));
+        assert!(!diff.diff.contains("+line two"));
 
-        assert_eq!(result.links_rewritten, 1);
-        assert_eq!(
-            content_text(&server, "44444444-4444-4444-8444-444444444444"),
-            "See [[renamed/Article]]"
-        );
+        let Err(err) = handle_doc_version_text(
+            State(server_state.clone()),
+            Path((doc_id.to_string(), "v9".to_string())),
+            None,
+        )
+        .await
+        else {
+            panic!("unknown version should fail");
+        };
+        assert_eq!(err.status, StatusCode::NOT_FOUND);
     }
 
     #[tokio::test]
-    async fn folder_rename_rewrites_descendant_backlinks() {
-        let server = Server::new_for_test();
-        let folder_doc_id = insert_test_folder_doc(
-            &server,
-            "Relay Folder 1",
-            &[
-                ("/Old", "22222222-2222-4222-8222-222222222222", "folder"),
-                (
-                    "/Old/Child.md",
-                    "33333333-3333-4333-8333-333333333333",
-                    "markdown",
-                ),
-                (
-                    "/Backlinker.md",
-                    "44444444-4444-4444-8444-444444444444",
-                    "markdown",
-                ),
-            ],
-        )
-        .await;
-        insert_test_content_doc(&server, "33333333-3333-4333-8333-333333333333", "Child").await;
-        insert_test_content_doc(
-            &server,
-            "44444444-4444-4444-8444-444444444444",
-            "See [[Old/Child]]",
+    async fn restore_endpoint_merges_with_concurrent_client_edits() {
+        use crate::mcp::tools::test_helpers::{build_versioned_test_server, RELAY_ID};
+        use yrs::updates::decoder::Decode;
+        use yrs::updates::encoder::Encode;
+
+        let uuid = "eeee0000-0000-4000-8000-000000000001";
+        let doc_id = format!("{}-{}", RELAY_ID, uuid);
+        let server_state = build_versioned_test_server(
+            "/Notes.md",
+            uuid,
+            &["alpha beta\n", "alpha beta gamma\n"],
+            "alpha beta gamma delta\n",
         )
         .await;
-        insert_backlink(
-            &server,
-            &folder_doc_id,
-            "33333333-3333-4333-8333-333333333333",
-            "44444444-4444-4444-8444-444444444444",
-        );
+        let awareness = server_state.docs().get(&doc_id).unwrap().awareness();
 
-        let result = server
-            .move_path("Relay Folder 1/Old", "/New", None)
-            .await
-            .unwrap();
-
-        assert_eq!(result.links_rewritten, 1);
-        assert_eq!(
-            content_text(&server, "44444444-4444-4444-8444-444444444444"),
-            "See [[New/Child]]"
-        );
-    }
+        // A connected editor with an edit the server has not seen yet.
+        let client = yrs::Doc::new();
+        {
+            let guard = awareness.read().unwrap();
+            let update = guard
+                .doc
+                .transact()
+                .encode_state_as_update_v1(&yrs::StateVector::default());
+            client
+                .transact_mut()
+                .apply_update(yrs::Update::decode_v1(&update).unwrap());
+        }
+        {
+            let mut txn = client.transact_mut();
+            txn.get_or_insert_text("contents").insert(&mut txn, 0, "!");
+        }
 
-    #[tokio::test]
-    async fn folder_rename_without_backlinkers_reports_zero_rewrites() {
-        let server = Server::new_for_test();
-        insert_test_folder_doc(
-            &server,
-            "Relay Folder 1",
-            &[
-                ("/Old", "22222222-2222-4222-8222-222222222222", "folder"),
-                (
-                    "/Old/Child.md",
-                    "33333333-3333-4333-8333-333333333333",
-                    "markdown",
-                ),
-            ],
+        let Json(restored) = handle_restore_doc_version(
+            State(server_state.clone()),
+            Path((doc_id.clone(), "v0".to_string())),
+            Query(RestoreVersionQuery {
+                as_suggestions: false,
+            }),
+            None,
         )
-        .await;
-        insert_test_content_doc(&server, "33333333-3333-4333-8333-333333333333", "Child").await;
+        .await
+        .unwrap();
+        assert!(restored.edits > 0);
 
-        let result = server
-            .move_path("Relay Folder 1/Old", "/New", None)
-            .await
-            .unwrap();
+        // Exchange updates both ways, as the sync protocol would.
+        let guard = awareness.read().unwrap();
+        let to_client = guard
+            .doc
+            .transact()
+            .encode_state_as_update_v1(&client.transact().state_vector());
+        let to_server = client
+            .transact()
+            .encode_state_as_update_v1(&guard.doc.transact().state_vector());
+        client
+            .transact_mut()
+            .apply_update(yrs::Update::decode_v1(&to_client).unwrap());
+        guard
+            .doc
+            .transact_mut()
+            .apply_update(yrs::Update::decode_v1(&to_server).unwrap());
+
+        let text_of = |doc: &yrs::Doc| {
+            let txn = doc.transact();
+            txn.get_text("contents").unwrap().get_string(&txn)
+        };
+        assert_eq!(text_of(&client), "!alpha beta\n");
+        assert_eq!(text_of(&guard.doc), "!alpha beta\n");
+        drop(guard);
 
-        assert_eq!(result.links_rewritten, 0);
+        let Err(err) = handle_restore_doc_version(
+            State(server_state.clone()),
+            Path((doc_id.clone(), "v5".to_string())),
+            Query(RestoreVersionQuery {
+                as_suggestions: true,
+            }),
+            None,
+        )
+        .await
+        else {
+            panic!("unknown version should fail");
+        };
+        assert_eq!(err.status, StatusCode::NOT_FOUND);
     }
 
     #[tokio::test]
-    async fn move_path_rejects_folder_destination_collision_without_mutation() {
-        let server = Server::new_for_test();
-        let folder_doc_id = insert_test_folder_doc(
-            &server,
-            "Relay Folder 1",
-            &[
-                ("/Old", "22222222-2222-4222-8222-222222222222", "folder"),
-                (
-                    "/Existing",
-                    "55555555-5555-4555-8555-555555555555",
-                    "folder",
-                ),
-            ],
-        )
-        .await;
+    async fn folder_snapshot_round_trip_undoes_a_restructure() {
+        use async_trait::async_trait;
+        use std::collections::HashMap;
+        use std::sync::Mutex;
+        use y_sweet_core::store::{FileInfo, Result as StoreResult};
 
-        let result = server
-            .move_path("Relay Folder 1/Old", "/Existing", None)
-            .await;
+        #[derive(Clone, Default)]
+        struct MemoryStore {
+            data: Arc<Mutex<HashMap<String, Vec<u8>>>>,
+        }
 
-        assert!(matches!(result, Err(MoveDocumentError::Conflict(_))));
-        assert!(filemeta_has(&server, &folder_doc_id, "/Old"));
-        assert!(filemeta_has(&server, &folder_doc_id, "/Existing"));
-    }
+        #[async_trait]
+        impl Store for MemoryStore {
+            async fn init(&self) -> StoreResult<()> {
+                Ok(())
+            }
 
-    #[tokio::test]
-    async fn move_path_rejects_descendant_destination_collision_without_mutation() {
-        let server = Server::new_for_test();
-        let folder_doc_id = insert_test_folder_doc(
-            &server,
-            "Relay Folder 1",
-            &[
-                ("/Old", "22222222-2222-4222-8222-222222222222", "folder"),
-                (
-                    "/Old/Child.md",
-                    "33333333-3333-4333-8333-333333333333",
-                    "markdown",
-                ),
-                (
-                    "/New/Child.md",
-                    "44444444-4444-4444-8444-444444444444",
-                    "markdown",
-                ),
-            ],
-        )
-        .await;
-        insert_test_content_doc(&server, "33333333-3333-4333-8333-333333333333", "Child").await;
-        insert_test_content_doc(&server, "44444444-4444-4444-8444-444444444444", "Existing").await;
+            async fn get(&self, key: &str) -> StoreResult<Option<Vec<u8>>> {
+                Ok(self.data.lock().unwrap().get(key).cloned())
+            }
 
-        let result = server.move_path("Relay Folder 1/Old", "/New", None).await;
+            async fn set(&self, key: &str, value: Vec<u8>) -> StoreResult<()> {
+                self.data.lock().unwrap().insert(key.to_string(), value);
+                Ok(())
+            }
 
-        assert!(matches!(result, Err(MoveDocumentError::Conflict(_))));
-        assert!(filemeta_has(&server, &folder_doc_id, "/Old"));
-        assert!(filemeta_has(&server, &folder_doc_id, "/Old/Child.md"));
-        assert!(filemeta_has(&server, &folder_doc_id, "/New/Child.md"));
-        assert!(!filemeta_has(&server, &folder_doc_id, "/New"));
-    }
+            async fn remove(&self, key: &str) -> StoreResult<()> {
+                self.data.lock().unwrap().remove(key);
+                Ok(())
+            }
 
-    #[tokio::test]
-    async fn move_path_rejects_invalid_folder_destinations_without_mutation() {
-        let invalid_destinations = ["/", "/New/", "/New//Child", "/.", "/..", "/New/../Child"];
-        for new_path in invalid_destinations {
-            let server = Server::new_for_test();
-            let folder_doc_id = insert_test_folder_doc(
-                &server,
-                "Relay Folder 1",
-                &[("/Old", "22222222-2222-4222-8222-222222222222", "folder")],
-            )
-            .await;
+            async fn exists(&self, key: &str) -> StoreResult<bool> {
+                Ok(self.data.lock().unwrap().contains_key(key))
+            }
 
-            let result = server.move_path("Relay Folder 1/Old", new_path, None).await;
+            // Like FileSystemStore: file names directly under the prefix.
+            async fn list(&self, prefix: &str) -> StoreResult<Vec<FileInfo>> {
+                let data = self.data.lock().unwrap();
+                Ok(data
+                    .iter()
+                    .filter_map(|(key, value)| {
+                        let name = key.strip_prefix(prefix)?;
+                        (!name.contains('/')).then(|| FileInfo {
+                            key: name.to_string(),
+                            size: value.len() as u64,
+                            last_modified: 0,
+                        })
+                    })
+                    .collect())
+            }
+        }
+
+        let store = MemoryStore::default();
+        let server = Arc::new(
+            Server::new_without_workers(
+                Some(Box::new(store.clone())),
+                Duration::from_secs(60),
+                None,
+                None,
+                vec![],
+                CancellationToken::new(),
+                false,
+                None,
+            )
+            .await
+            .unwrap(),
+        );
+        let doc_store: Arc<Box<dyn Store>> = Arc::new(Box::new(store.clone()));
+        let uuid = "11111111-1111-4111-8111-111111111111";
+        let folder_doc_id = format!("{}-{}", TEST_RELAY_ID, TEST_FOLDER_UUID);
+        let content_doc_id = format!("{}-{}", TEST_RELAY_ID, uuid);
 
-            assert!(
-                matches!(result, Err(MoveDocumentError::BadRequest(_))),
-                "expected BadRequest for {new_path}"
-            );
-            assert!(filemeta_has(&server, &folder_doc_id, "/Old"));
-            assert!(!filemeta_has(&server, &folder_doc_id, new_path));
+        let folder = DocWithSyncKv::new(&folder_doc_id, Some(doc_store.clone()), || (), None)
+            .await
+            .unwrap();
+        {
+            let awareness = folder.awareness();
+            let guard = awareness.write().unwrap();
+            let mut txn = guard.doc.transact_mut();
+            let config = txn.get_or_insert_map("folder_config");
+            config.insert(&mut txn, "name", Any::String("Lens".into()));
+            let mut map = HashMap::new();
+            map.insert("id".to_string(), Any::String(uuid.into()));
+            map.insert("type".to_string(), Any::String("markdown".into()));
+            txn.get_or_insert_map("filemeta_v0")
+                .insert(&mut txn, "/A.md", Any::Map(map.into()));
+            txn.get_or_insert_map("docs")
+                .insert(&mut txn, "/A.md", Any::String(uuid.into()));
+        }
+        server.docs().insert(folder_doc_id.clone(), folder);
+        let content = DocWithSyncKv::new(&content_doc_id, Some(doc_store), || (), None)
+            .await
+            .unwrap();
+        {
+            let awareness = content.awareness();
+            let guard = awareness.write().unwrap();
+            let mut txn = guard.doc.transact_mut();
+            txn.get_or_insert_text("contents")
+                .insert(&mut txn, 0, "alpha\n");
         }
-    }
+        server.docs().insert(content_doc_id.clone(), content);
+        server.doc_resolver().rebuild(server.docs());
 
-    #[tokio::test]
-    async fn move_path_rejects_folder_cross_folder_move() {
-        let server = Server::new_for_test();
-        let folder_doc_id = insert_test_folder_doc(
+        let snapshots_uri = format!("/folder/{}/snapshots", TEST_FOLDER_UUID);
+        let (status, body) = post_request(
             &server,
-            "Relay Folder 1",
-            &[("/Old", "22222222-2222-4222-8222-222222222222", "folder")],
+            &snapshots_uri,
+            "application/json",
+            Body::from(json!({ "name": "before-move" }).to_string()),
         )
         .await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        let created: JsonValue = serde_json::from_str(&body).unwrap();
+        assert_eq!(created["docs"]["/A.md"], content_doc_id.as_str());
 
-        let result = server
-            .move_path("Relay Folder 1/Old", "/New", Some("Relay Folder 2"))
-            .await;
-
-        assert!(matches!(result, Err(MoveDocumentError::BadRequest(_))));
-        assert!(filemeta_has(&server, &folder_doc_id, "/Old"));
-    }
-
-    #[tokio::test]
-    async fn move_path_rejects_folder_to_markdown_destination() {
-        let server = Server::new_for_test();
-        let folder_doc_id = insert_test_folder_doc(
+        let (status, _) = post_request(
             &server,
-            "Relay Folder 1",
-            &[("/Old", "22222222-2222-4222-8222-222222222222", "folder")],
+            &snapshots_uri,
+            "application/json",
+            Body::from(json!({ "name": "before-move" }).to_string()),
         )
         .await;
-
-        let result = server
-            .move_path("Relay Folder 1/Old", "/Old.md", None)
-            .await;
-
-        assert!(matches!(result, Err(MoveDocumentError::BadRequest(_))));
-        assert!(filemeta_has(&server, &folder_doc_id, "/Old"));
-        assert!(!filemeta_has(&server, &folder_doc_id, "/Old.md"));
-    }
-
-    #[tokio::test]
-    async fn move_path_rejects_folder_descendant_destination() {
-        let server = Server::new_for_test();
-        let folder_doc_id = insert_test_folder_doc(
+        assert_eq!(status, StatusCode::CONFLICT);
+        let (status, _) = post_request(
             &server,
-            "Relay Folder 1",
-            &[("/Old", "22222222-2222-4222-8222-222222222222", "folder")],
+            &snapshots_uri,
+            "application/json",
+            Body::from(json!({ "name": "../escape" }).to_string()),
         )
         .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
 
-        let result = server
-            .move_path("Relay Folder 1/Old", "/Old/Sub", None)
-            .await;
+        // The restructure: rename the file, edit it and add a backlink.
+        {
+            let awareness = server.docs().get(&folder_doc_id).unwrap().awareness();
+            let guard = awareness.write().unwrap();
+            let mut txn = guard.doc.transact_mut();
+            let filemeta = txn.get_or_insert_map("filemeta_v0");
+            let entry = filemeta.get(&txn, "/A.md").unwrap().to_json(&txn);
+            filemeta.remove(&mut txn, "/A.md");
+            filemeta.insert(&mut txn, "/B.md", entry);
+            let docs_map = txn.get_or_insert_map("docs");
+            docs_map.remove(&mut txn, "/A.md");
+            docs_map.insert(&mut txn, "/B.md", Any::String(uuid.into()));
+        }
+        insert_backlink(&server, &folder_doc_id, uuid, "other-uuid");
+        {
+            let awareness = server.docs().get(&content_doc_id).unwrap().awareness();
+            let guard = awareness.write().unwrap();
+            let mut txn = guard.doc.transact_mut();
+            txn.get_or_insert_text("contents")
+                .insert(&mut txn, 5, " changed");
+        }
 
-        assert!(matches!(result, Err(MoveDocumentError::BadRequest(_))));
-        assert!(filemeta_has(&server, &folder_doc_id, "/Old"));
-        assert!(!filemeta_has(&server, &folder_doc_id, "/Old/Sub"));
-    }
+        let response = server
+            .routes()
+            .oneshot(
+                Request::builder()
+                    .uri(snapshots_uri.as_str())
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let listed: JsonValue = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(listed.as_array().unwrap().len(), 1);
+        assert_eq!(listed[0]["name"], "before-move");
 
-    #[tokio::test]
-    async fn handle_move_path_route_moves_file() {
-        let server = Server::new_for_test();
-        let folder_doc_id = insert_test_folder_doc(
+        let (status, body) = post_request(
             &server,
-            "Relay Folder 1",
-            &[(
-                "/Old.md",
-                "11111111-1111-4111-8111-111111111111",
-                "markdown",
-            )],
+            &format!("{}/before-move/restore", snapshots_uri),
+            "application/json",
+            Body::empty(),
         )
         .await;
-        insert_test_content_doc(&server, "11111111-1111-4111-8111-111111111111", "Old").await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        let restored: JsonValue = serde_json::from_str(&body).unwrap();
+        assert_eq!(restored["docsRestored"], 1);
+        // /B.md removed and /A.md re-added in both maps, plus the backlink.
+        assert_eq!(restored["folderEntriesRestored"], 5);
+
+        assert_eq!(content_text(&server, uuid), "alpha\n");
+        assert!(filemeta_has(&server, &folder_doc_id, "/A.md"));
+        assert!(!filemeta_has(&server, &folder_doc_id, "/B.md"));
+        assert_eq!(
+            legacy_docs_value(&server, &folder_doc_id, "/A.md").as_deref(),
+            Some(uuid)
+        );
+        let backlinks_len = {
+            let awareness = server.docs().get(&folder_doc_id).unwrap().awareness();
+            let guard = awareness.read().unwrap();
+            let txn = guard.doc.transact();
+            txn.get_map("backlinks_v0").map_or(0, |m| m.len(&txn))
+        };
+        assert_eq!(backlinks_len, 0);
 
-        let (status, body) = post_move(
+        let (status, _) = post_request(
             &server,
-            json!({ "path": "Relay Folder 1/Old.md", "new_path": "/New.md" }),
+            &format!("{}/missing/restore", snapshots_uri),
+            "application/json",
+            Body::empty(),
         )
         .await;
-
-        assert_eq!(status, StatusCode::OK);
-        assert_eq!(body["old_path"], "/Old.md");
-        assert_eq!(body["new_path"], "/New.md");
-        assert_eq!(body["old_folder"], "Relay Folder 1");
-        assert_eq!(body["new_folder"], "Relay Folder 1");
-        assert!(filemeta_has(&server, &folder_doc_id, "/New.md"));
+        assert_eq!(status, StatusCode::NOT_FOUND);
     }
 
     #[tokio::test]
-    async fn handle_move_path_route_renames_folder() {
-        let server = Server::new_for_test();
-        let folder_doc_id = insert_test_folder_doc(
-            &server,
-            "Relay Folder 1",
-            &[("/Old", "22222222-2222-4222-8222-222222222222", "folder")],
-        )
-        .await;
+    async fn fsck_reports_then_repairs_folder_metadata() {
+        use async_trait::async_trait;
+        use std::collections::HashSet;
+        use y_sweet_core::store::Result as StoreResult;
 
-        let (status, body) = post_move(
-            &server,
-            json!({ "path": "Relay Folder 1/Old", "new_path": "/New" }),
-        )
-        .await;
+        /// Knows which docs exist, nothing more.
+        struct DocListStore {
+            doc_ids: HashSet<String>,
+        }
 
-        assert_eq!(status, StatusCode::OK);
-        assert_eq!(body["old_path"], "/Old");
-        assert_eq!(body["new_path"], "/New");
-        assert!(filemeta_has(&server, &folder_doc_id, "/New"));
-    }
+        #[async_trait]
+        impl Store for DocListStore {
+            async fn init(&self) -> StoreResult<()> {
+                Ok(())
+            }
 
-    #[tokio::test]
-    async fn handle_move_path_route_returns_conflict_for_collision() {
-        let server = Server::new_for_test();
-        insert_test_folder_doc(
+            async fn get(&self, _key: &str) -> StoreResult<Option<Vec<u8>>> {
+                Ok(None)
+            }
+
+            async fn set(&self, _key: &str, _value: Vec<u8>) -> StoreResult<()> {
+                Ok(())
+            }
+
+            async fn remove(&self, _key: &str) -> StoreResult<()> {
+                Ok(())
+            }
+
+            async fn exists(&self, key: &str) -> StoreResult<bool> {
+                Ok(key
+                    .strip_suffix("/data.ysweet")
+                    .is_some_and(|doc_id| self.doc_ids.contains(doc_id)))
+            }
+
+            async fn list_doc_ids(&self) -> StoreResult<Vec<String>> {
+                Ok(self.doc_ids.iter().cloned().collect())
+            }
+        }
+
+        let present = "aaaaaaaa-0000-4000-8000-000000000001";
+        let gone = "aaaaaaaa-0000-4000-8000-000000000002";
+        let nested = "aaaaaaaa-0000-4000-8000-000000000003";
+        let orphan = "aaaaaaaa-0000-4000-8000-000000000004";
+        let store = DocListStore {
+            doc_ids: [present, nested, orphan]
+                .iter()
+                .map(|uuid| format!("{}-{}", TEST_RELAY_ID, uuid))
+                .collect(),
+        };
+        let server = Arc::new(
+            Server::new_without_workers(
+                Some(Box::new(store)),
+                Duration::from_secs(60),
+                None,
+                None,
+                vec![],
+                CancellationToken::new(),
+                false,
+                None,
+            )
+            .await
+            .unwrap(),
+        );
+        let folder_doc_id = insert_test_folder_doc(
             &server,
-            "Relay Folder 1",
+            "Lens",
             &[
-                ("/Old", "22222222-2222-4222-8222-222222222222", "folder"),
-                (
-                    "/Existing",
-                    "55555555-5555-4555-8555-555555555555",
-                    "folder",
-                ),
+                ("/a.md", present, "markdown"),
+                ("//a.md", present, "markdown"),
+                ("/gone.md", gone, "markdown"),
+                ("/Sub/x.md", nested, "markdown"),
             ],
         )
         .await;
 
-        let (status, _) = post_move(
-            &server,
-            json!({ "path": "Relay Folder 1/Old", "new_path": "/Existing" }),
-        )
-        .await;
+        let fsck = |method: Method, uri: &'static str| {
+            let server = server.clone();
+            async move {
+                let response = server
+                    .routes()
+                    .oneshot(
+                        Request::builder()
+                            .method(method)
+                            .uri(uri)
+                            .body(Body::empty())
+                            .unwrap(),
+                    )
+                    .await
+                    .unwrap();
+                assert_eq!(response.status(), StatusCode::OK);
+                let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+                serde_json::from_slice::<JsonValue>(&bytes).unwrap()
+            }
+        };
+        let issues = |report: &JsonValue| -> Vec<(String, String)> {
+            report["folders"][0]["issues"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|issue| {
+                    (
+                        issue["kind"].as_str().unwrap().to_string(),
+                        issue["path"].as_str().unwrap().to_string(),
+                    )
+                })
+                .collect()
+        };
 
-        assert_eq!(status, StatusCode::CONFLICT);
+        let report = fsck(Method::GET, "/fsck").await;
+        assert_eq!(
+            issues(&report),
+            vec![
+                ("malformed_path".to_string(), "//a.md".to_string()),
+                ("missing_ancestor".to_string(), "/Sub".to_string()),
+                ("dangling_id".to_string(), "/gone.md".to_string()),
+            ]
+        );
+        assert_eq!(
+            report["orphaned_docs"][0]["id"],
+            format!("{}-{}", TEST_RELAY_ID, orphan)
+        );
+        assert!(filemeta_has(&server, &folder_doc_id, "//a.md"));
+
+        let report = fsck(Method::POST, "/fsck/repair").await;
+        assert_eq!(report["folders"][0]["issues"][0]["repaired"], true);
+        assert!(!filemeta_has(&server, &folder_doc_id, "//a.md"));
+        assert!(filemeta_has(&server, &folder_doc_id, "/Sub"));
+
+        // Dangling ids and orphans are left for a human to resolve.
+        let report = fsck(Method::GET, "/fsck").await;
+        assert_eq!(
+            issues(&report),
+            vec![("dangling_id".to_string(), "/gone.md".to_string())]
+        );
+        assert_eq!(report["orphaned_docs"].as_array().unwrap().len(), 1);
     }
 
     #[tokio::test]
-    async fn handle_move_path_route_returns_bad_request_for_folder_cross_folder_move() {
-        let server = Server::new_for_test();
-        insert_test_folder_doc(
-            &server,
-            "Relay Folder 1",
-            &[("/Old", "22222222-2222-4222-8222-222222222222", "folder")],
-        )
-        .await;
+    async fn filemeta_guard_quarantines_corrupting_updates() {
+        use async_trait::async_trait;
+        use std::collections::HashMap;
+        use std::sync::Mutex;
+        use y_sweet_core::store::Result as StoreResult;
+        use yrs::StateVector;
 
-        let (status, _) = post_move(
-            &server,
-            json!({
-                "path": "Relay Folder 1/Old",
-                "new_path": "/New",
-                "target_folder": "Relay Folder 2"
-            }),
-        )
-        .await;
+        #[derive(Clone, Default)]
+        struct MemoryStore {
+            data: Arc<Mutex<HashMap<String, Vec<u8>>>>,
+        }
 
-        assert_eq!(status, StatusCode::BAD_REQUEST);
-    }
+        #[async_trait]
+        impl Store for MemoryStore {
+            async fn init(&self) -> StoreResult<()> {
+                Ok(())
+            }
+
+            async fn get(&self, key: &str) -> StoreResult<Option<Vec<u8>>> {
+                Ok(self.data.lock().unwrap().get(key).cloned())
+            }
+
+            async fn set(&self, key: &str, value: Vec<u8>) -> StoreResult<()> {
+                self.data.lock().unwrap().insert(key.to_string(), value);
+                Ok(())
+            }
+
+            async fn remove(&self, key: &str) -> StoreResult<()> {
+                self.data.lock().unwrap().remove(key);
+                Ok(())
+            }
+
+            async fn exists(&self, key: &str) -> StoreResult<bool> {
+                Ok(self.data.lock().unwrap().contains_key(key))
+            }
+        }
 
-    #[tokio::test]
-    async fn test_auth_doc() {
-        let server_state = Server::new_without_workers(
-            None,
+        let store = MemoryStore::default();
+        let mut server = Server::new_without_workers(
+            Some(Box::new(store.clone())),
             Duration::from_secs(60),
             None,
             None,
             vec![],
             CancellationToken::new(),
-            true,
+            false,
             None,
         )
         .await
         .unwrap();
+        server.filemeta_guard = FilemetaGuardConfig {
+            mode: GuardMode::Quarantine,
+            max_deletions: 50,
+        };
+        let folder_doc_id = format!("{}-{}", TEST_RELAY_ID, TEST_FOLDER_UUID);
+        let guard = server.filemeta_guard_for(&folder_doc_id).unwrap();
 
-        let doc_id = server_state.create_doc().await.unwrap();
+        let doc = yrs::Doc::new();
+        doc.get_or_insert_map("filemeta_v0");
+        let client = yrs::Doc::new();
+        {
+            let mut txn = client.transact_mut();
+            let filemeta = txn.get_or_insert_map("filemeta_v0");
+            filemeta.insert(&mut txn, "/", yrs::Any::String("bogus".into()));
+        }
+        let update = client
+            .transact()
+            .encode_state_as_update_v1(&StateVector::default());
 
-        let token = auth_doc(
-            None,
-            TypedHeader(headers::Host::from(http::uri::Authority::from_static(
-                "localhost",
-            ))),
-            State(Arc::new(server_state)),
-            Path(doc_id.clone()),
-            Some(Json(AuthDocRequest {
-                authorization: Authorization::Full,
-                user_id: None,
-                valid_for_seconds: None,
-            })),
-        )
-        .await
-        .unwrap();
+        let violations = guard.admit(&doc, &update, Some("alice")).unwrap_err();
+        assert_eq!(violations.len(), 1);
+        assert!(
+            server
+                .metrics
+                .filemeta_guard_trips_total
+                .with_label_values(&["invalid_path", "quarantine"])
+                .get()
+                >= 1.0
+        );
 
-        let expected_url = format!("ws://localhost/d/{doc_id}/ws");
-        assert_eq!(token.url, expected_url);
-        assert_eq!(token.doc_id, doc_id);
-        assert!(token.token.is_none());
+        // The quarantined copy is written by a spawned task.
+        let prefix = format!(".config/quarantine/{}/", folder_doc_id);
+        let mut quarantined = None;
+        for _ in 0..50 {
+            quarantined = store
+                .data
+                .lock()
+                .unwrap()
+                .iter()
+                .find(|(key, _)| key.starts_with(&prefix))
+                .map(|(_, value)| value.clone());
+            if quarantined.is_some() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(quarantined, Some(update));
     }
 
     #[tokio::test]
-    async fn test_auth_doc_with_prefix() {
-        let prefix: Url = "https://foo.bar".parse().unwrap();
-        let server_state = Server::new_without_workers(
-            None,
-            Duration::from_secs(60),
-            None,
-            Some(prefix),
-            vec![],
-            CancellationToken::new(),
-            true,
-            None,
-        )
-        .await
-        .unwrap();
+    async fn regression_detector_snapshots_and_restores_a_wiped_doc() {
+        use async_trait::async_trait;
+        use std::collections::HashMap;
+        use std::sync::Mutex;
+        use y_sweet_core::store::Result as StoreResult;
 
-        let doc_id = server_state.create_doc().await.unwrap();
+        #[derive(Clone, Default)]
+        struct MemoryStore {
+            data: Arc<Mutex<HashMap<String, Vec<u8>>>>,
+        }
 
-        let token = auth_doc(
-            None,
-            TypedHeader(headers::Host::from(http::uri::Authority::from_static(
-                "localhost",
-            ))),
-            State(Arc::new(server_state)),
-            Path(doc_id.clone()),
-            None,
-        )
-        .await
-        .unwrap();
+        #[async_trait]
+        impl Store for MemoryStore {
+            async fn init(&self) -> StoreResult<()> {
+                Ok(())
+            }
 
-        let expected_url = format!("wss://foo.bar/d/{doc_id}/ws");
-        assert_eq!(token.url, expected_url);
-        assert_eq!(token.doc_id, doc_id);
-        assert!(token.token.is_none());
-    }
+            async fn get(&self, key: &str) -> StoreResult<Option<Vec<u8>>> {
+                Ok(self.data.lock().unwrap().get(key).cloned())
+            }
 
-    #[tokio::test]
-    async fn test_websocket_auth_rejects_missing_token_when_auth_configured() {
-        let authenticator = y_sweet_core::auth::Authenticator::gen_key().unwrap();
-        let server_state = Arc::new(
+            async fn set(&self, key: &str, value: Vec<u8>) -> StoreResult<()> {
+                self.data.lock().unwrap().insert(key.to_string(), value);
+                Ok(())
+            }
+
+            async fn remove(&self, key: &str) -> StoreResult<()> {
+                self.data.lock().unwrap().remove(key);
+                Ok(())
+            }
+
+            async fn exists(&self, key: &str) -> StoreResult<bool> {
+                Ok(self.data.lock().unwrap().contains_key(key))
+            }
+        }
+
+        let store = MemoryStore::default();
+        let server = Arc::new(
             Server::new_without_workers(
-                None,
+                Some(Box::new(store.clone())),
                 Duration::from_secs(60),
-                Some(authenticator),
+                None,
                 None,
                 vec![],
                 CancellationToken::new(),
-                true,
+                false,
                 None,
             )
             .await
             .unwrap(),
         );
+        let doc_store: Arc<Box<dyn Store>> = Arc::new(Box::new(store.clone()));
+        let uuid = "33333333-3333-4333-8333-333333333333";
+        let doc_id = format!("{}-{}", TEST_RELAY_ID, uuid);
+        let original = "# Notes\nA paragraph that took a while to write.\n".repeat(3);
 
-        let err = verify_socket_token(&server_state, "test-doc", None).unwrap_err();
+        let content = DocWithSyncKv::new(&doc_id, Some(doc_store.clone()), || (), None)
+            .await
+            .unwrap();
+        {
+            let awareness = content.awareness();
+            let guard = awareness.write().unwrap();
+            let mut txn = guard.doc.transact_mut();
+            txn.get_or_insert_text("contents")
+                .insert(&mut txn, 0, &original);
+        }
+        let watch = RegressionWatch {
+            detector: server.regression_detector.clone(),
+            awareness: Arc::downgrade(&content.awareness()),
+            store: Some(doc_store),
+            metrics: server.metrics.clone(),
+        };
+        watch.check(&doc_id).await;
+        content.sync_kv().persist().await.unwrap();
+        server.docs().insert(doc_id.clone(), content);
 
-        assert_eq!(err.status, StatusCode::UNAUTHORIZED);
-        assert_eq!(err.auth_error_type, Some("missing_token"));
+        // A client wipes the doc; the next checkpoint flags it.
+        {
+            let awareness = server.docs().get(&doc_id).unwrap().awareness();
+            let guard = awareness.write().unwrap();
+            let mut txn = guard.doc.transact_mut();
+            let text = txn.get_or_insert_text("contents");
+            let len = text.len(&txn);
+            text.remove_range(&mut txn, 0, len);
+        }
+        watch.check(&doc_id).await;
+        assert_eq!(content_text(&server, uuid), "");
+
+        let response = server
+            .routes()
+            .oneshot(
+                Request::builder()
+                    .uri("/regressions")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let listed: JsonValue = serde_json::from_slice(&body).unwrap();
+        let event = &listed["events"][0];
+        assert_eq!(event["docId"], doc_id.as_str());
+        assert_eq!(event["kind"], "wipe");
+        assert_eq!(event["currentBytes"], 0);
+        let event_id = event["id"].as_str().unwrap();
+        assert_eq!(
+            event["snapshotKey"],
+            format!(".config/regressions/{}/{}.ysweet", doc_id, event_id)
+        );
+
+        let (status, body) = post_request(
+            &server,
+            &format!("/regressions/{}/restore", event_id),
+            "application/json",
+            Body::empty(),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert_eq!(content_text(&server, uuid), original);
+
+        let (status, _) = post_request(
+            &server,
+            "/regressions/reg_missing/restore",
+            "application/json",
+            Body::empty(),
+        )
+        .await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
     }
 
     #[tokio::test]
-    async fn test_websocket_auth_allows_missing_token_without_authenticator() {
-        let server_state = Arc::new(
+    async fn export_endpoint_streams_folder_as_tar() {
+        let server = Arc::new(
             Server::new_without_workers(
                 None,
                 Duration::from_secs(60),
@@ -8223,87 +13368,244 @@ mod test {
                 None,
                 vec![],
                 CancellationToken::new(),
-                true,
+                false,
                 None,
             )
             .await
             .unwrap(),
         );
+        let uuid = "55555555-5555-4555-8555-555555555555";
+        insert_test_folder_doc(
+            &server,
+            "Lens",
+            &[
+                ("/Notes", "66666666-6666-4666-8666-666666666666", "folder"),
+                ("/Notes/a.md", uuid, "markdown"),
+            ],
+        )
+        .await;
+        insert_test_content_doc(&server, uuid, "Hello {--old--}{++new++} world\n").await;
 
-        let (authorization, channel, user) =
-            verify_socket_token(&server_state, "test-doc", None).unwrap();
+        let response = server
+            .routes()
+            .oneshot(
+                Request::builder()
+                    .uri(format!("/folder/{}/export?view=accepted", TEST_FOLDER_UUID))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers()["content-disposition"],
+            "attachment; filename=\"Lens.tar\""
+        );
+        let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let mut archive = tar::Archive::new(bytes.as_ref());
+        let entries: Vec<(String, String)> = archive
+            .entries()
+            .unwrap()
+            .map(|entry| {
+                let mut entry = entry.unwrap();
+                let path = entry.path().unwrap().to_string_lossy().into_owned();
+                let mut data = String::new();
+                std::io::Read::read_to_string(&mut entry, &mut data).unwrap();
+                (path, data)
+            })
+            .collect();
+        assert_eq!(
+            entries,
+            vec![
+                ("Notes/".to_string(), String::new()),
+                ("Notes/a.md".to_string(), "Hello new world\n".to_string()),
+            ]
+        );
 
-        assert_eq!(authorization, Authorization::Full);
-        assert_eq!(channel, None);
-        assert_eq!(user, None);
+        let response = server
+            .routes()
+            .oneshot(
+                Request::builder()
+                    .uri("/folder/missing/export")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
     }
 
     #[tokio::test]
-    async fn test_read_only_socket_access_allows_persisted_unloaded_doc() {
-        use async_trait::async_trait;
-        use std::collections::HashSet;
-        use y_sweet_core::store::Result as StoreResult;
+    async fn import_endpoint_imports_vault_and_is_idempotent() {
+        use crate::stores::filesystem::FileSystemStore;
+        use tempfile::TempDir;
 
-        struct ExistingDocStore {
-            existing_keys: HashSet<String>,
-        }
+        let store_dir = TempDir::new().unwrap();
+        let store = FileSystemStore::new(store_dir.path().to_path_buf()).unwrap();
+        let server = Arc::new(
+            Server::new_without_workers(
+                Some(Box::new(store)),
+                Duration::from_secs(60),
+                None,
+                None,
+                vec![],
+                CancellationToken::new(),
+                false,
+                None,
+            )
+            .await
+            .unwrap(),
+        );
 
-        #[async_trait]
-        impl Store for ExistingDocStore {
-            async fn init(&self) -> StoreResult<()> {
-                Ok(())
-            }
+        let vault = TempDir::new().unwrap();
+        let root = vault.path();
+        std::fs::create_dir_all(root.join(".obsidian")).unwrap();
+        std::fs::write(root.join(".obsidian/app.json"), "{}").unwrap();
+        std::fs::create_dir_all(root.join("Notes")).unwrap();
+        std::fs::write(root.join("Notes/Idea.md"), "See [[Target]]\n").unwrap();
+        std::fs::write(root.join("Target.md"), "target\n").unwrap();
+        std::fs::create_dir_all(root.join("attachments")).unwrap();
+        std::fs::write(root.join("attachments/fig.png"), [1u8, 2, 3]).unwrap();
+        std::fs::write(root.join("Board.canvas"), "{}").unwrap();
+
+        let body = json!({
+            "path": root.to_string_lossy(),
+            "folder": "Vault",
+            "relay_id": TEST_RELAY_ID,
+        })
+        .to_string();
+        async fn import(server: &Arc<Server>, body: &str) -> JsonValue {
+            let (status, text) = post_request(
+                server,
+                "/import",
+                "application/json",
+                Body::from(body.to_string()),
+            )
+            .await;
+            assert_eq!(status, StatusCode::OK, "{}", text);
+            serde_json::from_str(&text).unwrap()
+        }
 
-            async fn get(&self, _key: &str) -> StoreResult<Option<Vec<u8>>> {
-                Ok(None)
-            }
+        let report = import(&server, &body).await;
+        assert_eq!(report["folderCreated"], true);
+        assert_eq!(report["foldersCreated"], 2);
+        assert_eq!(report["docsCreated"], 2);
+        assert_eq!(report["blobsUploaded"], 1);
+        assert_eq!(
+            report["skipped"],
+            json!(["/Board.canvas: unsupported file type"])
+        );
+        let folder_doc_id = report["folderDocId"].as_str().unwrap().to_string();
+        assert_eq!(
+            server.folder_doc_id_for_name("Vault"),
+            Some(folder_doc_id.clone())
+        );
 
-            async fn set(&self, _key: &str, _value: Vec<u8>) -> StoreResult<()> {
-                Ok(())
-            }
+        let roots = server.dump_doc(&folder_doc_id).await.unwrap();
+        let filemeta = &roots["filemeta_v0"];
+        assert_eq!(filemeta["/Notes"]["type"], "folder");
+        assert_eq!(filemeta["/Notes/Idea.md"]["type"], "markdown");
+        let fig = &filemeta["/attachments/fig.png"];
+        assert_eq!(fig["type"], "image");
+        assert_eq!(fig["mimetype"], "image/png");
+        let hash = crate::mcp::tools::blob::sha256_hex(&[1, 2, 3]);
+        assert_eq!(fig["hash"], hash.as_str());
+        let blob_key = format!(
+            "files/{}-{}/{}",
+            TEST_RELAY_ID,
+            fig["id"].as_str().unwrap(),
+            hash
+        );
+        assert!(server
+            .store()
+            .as_ref()
+            .unwrap()
+            .exists(&blob_key)
+            .await
+            .unwrap());
 
-            async fn remove(&self, _key: &str) -> StoreResult<()> {
-                Ok(())
-            }
+        let idea_id = filemeta["/Notes/Idea.md"]["id"]
+            .as_str()
+            .unwrap()
+            .to_string();
+        let target_id = filemeta["/Target.md"]["id"].as_str().unwrap().to_string();
+        assert_eq!(roots["docs"]["/Target.md"], target_id.as_str());
+        assert_eq!(roots["backlinks_v0"][&target_id], json!([idea_id]));
+        assert_eq!(content_text(&server, &target_id), "target\n");
+
+        // Re-running changes nothing.
+        let report = import(&server, &body).await;
+        assert_eq!(report["folderCreated"], false);
+        assert_eq!(report["foldersCreated"], 0);
+        assert_eq!(report["docsCreated"], 0);
+        assert_eq!(report["docsUnchanged"], 2);
+        assert_eq!(report["blobsUnchanged"], 1);
+
+        // An edited file updates the existing doc in place.
+        std::fs::write(root.join("Target.md"), "target, revised\n").unwrap();
+        let report = import(&server, &body).await;
+        assert_eq!(report["docsUpdated"], 1);
+        assert_eq!(report["docsUnchanged"], 1);
+        let roots = server.dump_doc(&folder_doc_id).await.unwrap();
+        assert_eq!(roots["filemeta_v0"]["/Target.md"]["id"], target_id.as_str());
+        assert_eq!(content_text(&server, &target_id), "target, revised\n");
+    }
 
-            async fn exists(&self, key: &str) -> StoreResult<bool> {
-                Ok(self.existing_keys.contains(key))
+    #[tokio::test]
+    async fn dump_endpoint_prints_every_root() {
+        let server = Arc::new(
+            Server::new_without_workers(
+                None,
+                Duration::from_secs(60),
+                None,
+                None,
+                vec![],
+                CancellationToken::new(),
+                false,
+                None,
+            )
+            .await
+            .unwrap(),
+        );
+        let uuid = "44444444-4444-4444-8444-444444444444";
+        let folder_doc_id =
+            insert_test_folder_doc(&server, "Lens", &[("/Notes.md", uuid, "markdown")]).await;
+        insert_test_content_doc(&server, uuid, "# Notes\n").await;
+
+        let dump = |uri: String| {
+            let server = server.clone();
+            async move {
+                let response = server
+                    .routes()
+                    .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+                    .await
+                    .unwrap();
+                let status = response.status();
+                let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+                (
+                    status,
+                    serde_json::from_slice::<JsonValue>(&bytes).unwrap_or(JsonValue::Null),
+                )
             }
-        }
-
-        let doc_id = "persisted-doc";
-        let store = ExistingDocStore {
-            existing_keys: HashSet::from([format!("{}/data.ysweet", doc_id)]),
         };
-        let server = Server::new_without_workers(
-            Some(Box::new(store)),
-            Duration::from_secs(60),
-            None,
-            None,
-            vec![],
-            CancellationToken::new(),
-            true,
-            None,
-        )
-        .await
-        .unwrap();
 
-        assert!(!server.docs.contains_key(doc_id));
-        server
-            .ensure_socket_doc_access(doc_id, Authorization::ReadOnly)
-            .await
-            .unwrap();
+        let (status, body) = dump("/dump?folder=Lens".to_string()).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["docId"], folder_doc_id.as_str());
+        assert_eq!(body["roots"]["folder_config"], json!({ "name": "Lens" }));
+        assert_eq!(body["roots"]["filemeta_v0"]["/Notes.md"]["id"], uuid);
+        assert_eq!(body["roots"]["docs"]["/Notes.md"], uuid);
 
-        let err = server
-            .ensure_socket_doc_access("missing-doc", Authorization::ReadOnly)
-            .await
-            .unwrap_err();
-        assert_eq!(err.status, StatusCode::NOT_FOUND);
+        let (status, body) = dump(format!("/dump?doc_id={}-{}", TEST_RELAY_ID, uuid)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["roots"], json!({ "contents": "# Notes\n" }));
 
-        server
-            .ensure_socket_doc_access("new-doc", Authorization::Full)
-            .await
-            .unwrap();
+        let (status, _) = dump("/dump?folder=Missing".to_string()).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let (status, _) = dump("/dump?doc_id=missing".to_string()).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let (status, _) = dump("/dump".to_string()).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
     }
 
     #[tokio::test]
@@ -9303,6 +14605,296 @@ mod test {
         assert!(server.suggestions_index.get(SUGG_UUID).is_none());
     }
 
+    /// GET /search with a raw query string (caller URL-encodes values).
+    async fn get_search(server: &Arc<Server>, query_string: &str) -> (StatusCode, JsonValue) {
+        let response = server
+            .routes()
+            .oneshot(
+                Request::builder()
+                    .method(Method::GET)
+                    .uri(format!("/search?{}", query_string))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let status = response.status();
+        let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body = serde_json::from_slice(&bytes).unwrap_or_else(|_| json!({}));
+        (status, body)
+    }
+
+    fn hybrid_search_index() -> Arc<SearchIndex> {
+        Arc::new(
+            SearchIndex::new_in_memory()
+                .expect("in-memory search index")
+                .with_embedding_provider(Arc::new(embedding::HashingEmbedder::default())),
+        )
+    }
+
+    #[tokio::test]
+    async fn search_endpoint_hybrid_mode_finds_conceptual_match() {
+        // Prevents: `mode` being ignored so conceptual queries fall back to
+        // strict BM25 AND-matching and return nothing
+        let search_index = hybrid_search_index();
+        search_index
+            .add_document(
+                "doc-1",
+                "Photosynthesis",
+                "Plants convert sunlight into chemical energy.",
+                "Lens",
+            )
+            .unwrap();
+        let server = Server::new_for_test_with_search(search_index);
+
+        let (status, body) = get_search(&server, "q=how+plants+make+energy").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["mode"], "bm25");
+        assert_eq!(body["total_hits"], 0);
+
+        let (status, body) = get_search(&server, "q=how+plants+make+energy&mode=hybrid").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["mode"], "hybrid");
+        assert_eq!(body["results"][0]["doc_id"], "doc-1");
+    }
+
+    #[tokio::test]
+    async fn search_endpoint_scopes_by_folder_and_path_prefix() {
+        // Prevents: folder/path filters being dropped, or the worker indexing
+        // docs without their filemeta path (so prefix filters match nothing)
+        let search_index = Arc::new(SearchIndex::new_in_memory().expect("in-memory search index"));
+        let server = Server::new_for_test_with_search(search_index.clone());
+        let bio = "33333333-3333-4333-8333-333333333331";
+        let chem = "33333333-3333-4333-8333-333333333332";
+        insert_test_folder_doc(
+            &server,
+            "Relay Folder 1",
+            &[
+                ("/Biology/Cells.md", bio, "markdown"),
+                ("/Chemistry/Bonds.md", chem, "markdown"),
+            ],
+        )
+        .await;
+        insert_test_content_doc(&server, bio, "Energy in cells").await;
+        insert_test_content_doc(&server, chem, "Energy in bonds").await;
+        for uuid in [bio, chem] {
+            search_handle_content_update(
+                &format!("{}-{}", TEST_RELAY_ID, uuid),
+                &server.docs,
+                &search_index,
+                &server.suggestions_index,
+            );
+        }
+
+        let (status, body) = get_search(&server, "q=energy&path_prefix=%2FBiology").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["total_hits"], 1);
+        assert_eq!(body["results"][0]["doc_id"], bio);
+        assert_eq!(body["results"][0]["path"], "/Biology/Cells.md");
+
+        let (_, body) = get_search(&server, &format!("q=energy&folder={}", TEST_FOLDER_UUID)).await;
+        assert_eq!(body["total_hits"], 2);
+        let (_, body) = get_search(&server, "q=energy&folder=Relay+Folder+1").await;
+        assert_eq!(body["total_hits"], 2);
+        let (_, body) = get_search(&server, "q=energy&folder=Other").await;
+        assert_eq!(body["total_hits"], 0);
+    }
+
+    #[tokio::test]
+    async fn search_endpoint_supports_frontmatter_fields_and_facets() {
+        let search_index = Arc::new(SearchIndex::new_in_memory().expect("in-memory search index"));
+        let server = Server::new_for_test_with_search(search_index.clone());
+        let article = "33333333-3333-4333-8333-333333333341";
+        let lens = "33333333-3333-4333-8333-333333333342";
+        insert_test_folder_doc(
+            &server,
+            "Relay Folder 1",
+            &[
+                ("/Article.md", article, "markdown"),
+                ("/Lens.md", lens, "markdown"),
+            ],
+        )
+        .await;
+        insert_test_content_doc(
+            &server,
+            article,
+            "---\ntype: article\ntags: [lens, ai-safety]\n---\nAbout alignment",
+        )
+        .await;
+        insert_test_content_doc(
+            &server,
+            lens,
+            "---\ntype: lens\ntags:\n  - lens\n---\nAlignment lens",
+        )
+        .await;
+        for uuid in [article, lens] {
+            search_handle_content_update(
+                &format!("{}-{}", TEST_RELAY_ID, uuid),
+                &server.docs,
+                &search_index,
+                &server.suggestions_index,
+            );
+        }
+
+        let (status, body) = get_search(&server, "q=tag%3Alens+type%3Aarticle").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["total_hits"], 1);
+        assert_eq!(body["results"][0]["doc_id"], article);
+        assert_eq!(body["results"][0]["type"], "article");
+        assert_eq!(body["results"][0]["tags"], json!(["lens", "ai-safety"]));
+
+        let (_, body) = get_search(&server, "q=alignment").await;
+        assert_eq!(body["total_hits"], 2);
+        assert_eq!(
+            body["facets"],
+            json!({
+                "tags": { "ai-safety": 1, "lens": 2 },
+                "types": { "article": 1, "lens": 1 }
+            })
+        );
+
+        let (_, body) = get_search(&server, "q=").await;
+        assert_eq!(body["facets"], json!({ "tags": {}, "types": {} }));
+    }
+
+    #[tokio::test]
+    async fn search_endpoint_section_granularity_returns_anchors() {
+        let search_index = Arc::new(SearchIndex::new_in_memory().expect("in-memory search index"));
+        let server = Server::new_for_test_with_search(search_index.clone());
+        let uuid = "33333333-3333-4333-8333-333333333351";
+        insert_test_folder_doc(&server, "Relay Folder 1", &[("/Lens.md", uuid, "markdown")]).await;
+        insert_test_content_doc(
+            &server,
+            uuid,
+            "Intro\n# Background\nHistory\n## Inner Alignment\nMesa optimizers\n",
+        )
+        .await;
+        search_handle_content_update(
+            &format!("{}-{}", TEST_RELAY_ID, uuid),
+            &server.docs,
+            &search_index,
+            &server.suggestions_index,
+        );
+
+        let (status, body) = get_search(&server, "q=mesa&granularity=section").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["granularity"], "section");
+        assert_eq!(body["total_hits"], 1);
+        let hit = &body["results"][0];
+        assert_eq!(hit["doc_id"], uuid);
+        assert_eq!(hit["path"], "/Lens.md");
+        assert_eq!(
+            hit["heading_path"],
+            json!(["Background", "Inner Alignment"])
+        );
+        assert_eq!(hit["line"], 4);
+        assert_eq!(hit["anchor"], "#inner-alignment");
+
+        // Document hits carry no section fields
+        let (_, body) = get_search(&server, "q=mesa").await;
+        assert_eq!(body["granularity"], "document");
+        assert!(body["results"][0].get("anchor").is_none());
+
+        let (status, _) = get_search(&server, "q=mesa&granularity=paragraph").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn search_endpoint_reports_query_syntax_errors() {
+        let search_index = Arc::new(SearchIndex::new_in_memory().expect("in-memory search index"));
+        search_index
+            .add_document("doc1", "Photosynthesis", "Chlorophyll and light", "Lens")
+            .unwrap();
+        let server = Server::new_for_test_with_search(search_index);
+
+        let (status, body) = get_search(&server, "q=chloro*+OR+light+-dark").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["total_hits"], 1);
+
+        let (status, body) = get_search(&server, "q=light+%22unclosed+author%3Ame").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["query"], "light \"unclosed author:me");
+        let errors = body["errors"].as_array().expect("structured errors");
+        assert_eq!(errors.len(), 1, "errors: {:?}", errors);
+        assert_eq!(errors[0]["start"], 6);
+        assert_eq!(errors[0]["end"], 25);
+        assert!(errors[0]["message"]
+            .as_str()
+            .unwrap()
+            .contains("Unterminated phrase"));
+    }
+
+    #[tokio::test]
+    async fn search_endpoint_pages_with_cursor_and_sorts() {
+        let search_index = Arc::new(SearchIndex::new_in_memory().expect("in-memory search index"));
+        for (id, title, modified) in [
+            ("doc1", "Beta", 2_000),
+            ("doc2", "Alpha", 3_000),
+            ("doc3", "Gamma", 1_000),
+        ] {
+            search_index
+                .add_document_buffered_at(
+                    id,
+                    title,
+                    "Notes on energy.",
+                    &DocLocation::in_folder("Lens"),
+                    Some(modified),
+                )
+                .unwrap();
+        }
+        search_index.flush().unwrap();
+        let server = Server::new_for_test_with_search(search_index);
+
+        let (status, body) = get_search(&server, "q=energy&limit=2&sort=modified").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["total_hits"], 3);
+        assert_eq!(body["sort"], "modified");
+        assert_eq!(body["results"][0]["doc_id"], "doc2");
+        assert_eq!(body["results"][0]["modified"], 3_000);
+        assert_eq!(body["results"][1]["doc_id"], "doc1");
+        let cursor = body["next_cursor"]
+            .as_str()
+            .expect("more results")
+            .to_string();
+
+        let (status, body) = get_search(
+            &server,
+            &format!("q=energy&limit=2&sort=modified&cursor={}", cursor),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["offset"], 2);
+        assert_eq!(body["results"].as_array().unwrap().len(), 1);
+        assert_eq!(body["results"][0]["doc_id"], "doc3");
+        assert!(body["next_cursor"].is_null());
+
+        let (_, body) = get_search(&server, "q=energy&sort=title&offset=1&limit=1").await;
+        assert_eq!(body["results"][0]["title"], "Beta");
+
+        for bad in [
+            "q=energy&sort=size",
+            "q=energy&offset=1&cursor=1",
+            "q=energy&cursor=abc",
+            "q=energy&offset=20000",
+        ] {
+            let (status, _) = get_search(&server, bad).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST, "{}", bad);
+        }
+    }
+
+    #[tokio::test]
+    async fn search_endpoint_rejects_unknown_or_unavailable_mode() {
+        let server = Server::new_for_test_with_search(hybrid_search_index());
+        let (status, _) = get_search(&server, "q=plants&mode=telepathic").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        // No embedding provider attached: hybrid is a client error, not a 500
+        let plain = Arc::new(SearchIndex::new_in_memory().expect("in-memory search index"));
+        let server = Server::new_for_test_with_search(plain);
+        let (status, _) = get_search(&server, "q=plants&mode=hybrid").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
     #[tokio::test]
     async fn missing_content_doc_retains_last_known_index_without_hot_retry() {
         // Absence from the in-memory map says nothing about persisted content.
@@ -9324,6 +14916,168 @@ mod test {
         assert!(server.suggestions_index.get(SUGG_UUID).is_some());
     }
 
+    #[tokio::test]
+    async fn startup_reindex_skips_docs_older_than_their_search_entry_without_loading() {
+        use async_trait::async_trait;
+        use std::collections::HashMap;
+        use std::sync::Mutex;
+        use y_sweet_core::store::{FileInfo, Result as StoreResult};
+
+        #[derive(Clone, Default)]
+        struct MemoryStore {
+            data: Arc<Mutex<HashMap<String, Vec<u8>>>>,
+            modified: Arc<Mutex<HashMap<String, u64>>>,
+        }
+
+        #[async_trait]
+        impl Store for MemoryStore {
+            async fn init(&self) -> StoreResult<()> {
+                Ok(())
+            }
+
+            async fn get(&self, key: &str) -> StoreResult<Option<Vec<u8>>> {
+                Ok(self.data.lock().unwrap().get(key).cloned())
+            }
+
+            async fn set(&self, key: &str, value: Vec<u8>) -> StoreResult<()> {
+                self.data.lock().unwrap().insert(key.to_string(), value);
+                self.modified
+                    .lock()
+                    .unwrap()
+                    .insert(key.to_string(), current_time_epoch_millis());
+                Ok(())
+            }
+
+            async fn remove(&self, key: &str) -> StoreResult<()> {
+                self.data.lock().unwrap().remove(key);
+                Ok(())
+            }
+
+            async fn exists(&self, key: &str) -> StoreResult<bool> {
+                Ok(self.data.lock().unwrap().contains_key(key))
+            }
+
+            async fn list(&self, prefix: &str) -> StoreResult<Vec<FileInfo>> {
+                let modified = self.modified.lock().unwrap();
+                Ok(self
+                    .data
+                    .lock()
+                    .unwrap()
+                    .iter()
+                    .filter_map(|(key, value)| {
+                        let name = key.strip_prefix(prefix)?;
+                        (!name.contains('/')).then(|| FileInfo {
+                            key: name.to_string(),
+                            size: value.len() as u64,
+                            last_modified: modified.get(key).copied().unwrap_or(0),
+                        })
+                    })
+                    .collect())
+            }
+
+            async fn list_doc_ids(&self) -> StoreResult<Vec<String>> {
+                Ok(self
+                    .data
+                    .lock()
+                    .unwrap()
+                    .keys()
+                    .filter_map(|key| key.strip_suffix("/data.ysweet"))
+                    .map(str::to_string)
+                    .collect())
+            }
+        }
+
+        let store = MemoryStore::default();
+        let doc_store: Arc<Box<dyn Store>> = Arc::new(Box::new(store.clone()));
+        let old_uuid = "11111111-1111-4111-8111-111111111111";
+        let new_uuid = "22222222-2222-4222-8222-222222222222";
+        let folder_doc_id = format!("{}-{}", TEST_RELAY_ID, TEST_FOLDER_UUID);
+
+        let folder = DocWithSyncKv::new(&folder_doc_id, Some(doc_store.clone()), || (), None)
+            .await
+            .unwrap();
+        {
+            let awareness = folder.awareness();
+            let guard = awareness.write().unwrap();
+            let mut txn = guard.doc.transact_mut();
+            txn.get_or_insert_map("folder_config").insert(
+                &mut txn,
+                "name",
+                Any::String("Lens".into()),
+            );
+            let filemeta = txn.get_or_insert_map("filemeta_v0");
+            for (path, uuid) in [("/Old.md", old_uuid), ("/New.md", new_uuid)] {
+                let mut map = HashMap::new();
+                map.insert("id".to_string(), Any::String(uuid.into()));
+                map.insert("type".to_string(), Any::String("markdown".into()));
+                filemeta.insert(&mut txn, path, Any::Map(map.into()));
+            }
+        }
+        folder.sync_kv().persist().await.unwrap();
+        for uuid in [old_uuid, new_uuid] {
+            let content_doc_id = format!("{}-{}", TEST_RELAY_ID, uuid);
+            let content = DocWithSyncKv::new(&content_doc_id, Some(doc_store.clone()), || (), None)
+                .await
+                .unwrap();
+            {
+                let awareness = content.awareness();
+                let guard = awareness.write().unwrap();
+                let mut txn = guard.doc.transact_mut();
+                txn.get_or_insert_text("contents")
+                    .insert(&mut txn, 0, "fresh words");
+            }
+            content.sync_kv().persist().await.unwrap();
+        }
+
+        let server = Server::new_without_workers(
+            Some(Box::new(store.clone())),
+            Duration::from_secs(60),
+            None,
+            None,
+            vec![],
+            CancellationToken::new(),
+            false,
+            None,
+        )
+        .await
+        .unwrap();
+        // Entries left by a previous run, both with an outdated body. Only
+        // the old doc's snapshot predates its entry.
+        let search_index = server.search_index().clone().unwrap();
+        for (uuid, path) in [(old_uuid, "/Old.md"), (new_uuid, "/New.md")] {
+            let location = search_doc_location(&folder_doc_id, "Lens", path);
+            let title = path.trim_start_matches('/').trim_end_matches(".md");
+            search_index
+                .add_document_at(uuid, title, "stale words", &location)
+                .unwrap();
+        }
+        store
+            .modified
+            .lock()
+            .unwrap()
+            .insert(format!("{}-{}/data.ysweet", TEST_RELAY_ID, old_uuid), 0);
+
+        server.startup_reindex(&[]).await.unwrap();
+
+        assert!(server.search_is_ready());
+        let stale: Vec<_> = search_index
+            .search("stale", 10)
+            .unwrap()
+            .into_iter()
+            .map(|result| result.doc_id)
+            .collect();
+        assert_eq!(stale, vec![old_uuid.to_string()]);
+        let fresh: Vec<_> = search_index
+            .search("fresh", 10)
+            .unwrap()
+            .into_iter()
+            .map(|result| result.doc_id)
+            .collect();
+        assert_eq!(fresh, vec![new_uuid.to_string()]);
+        // Loaded afterwards for backlinks and suggestions all the same
+        assert_eq!(server.docs().len(), 3);
+    }
+
     #[tokio::test]
     async fn pending_search_lease_keeps_doc_alive_until_refresh() {
         // Regression for the production sequence: final edit queued a
@@ -9522,6 +15276,7 @@ mod test {
         .unwrap();
         let params = SuggestionsQuery {
             folder_id: folder_doc_id.to_string(),
+            user: None,
         };
         handle_apply_suggestions(None, State(server.clone()), Query(params), Json(req))
             .await
@@ -9699,6 +15454,40 @@ mod test {
             .suggestions_ready
             .load(std::sync::atomic::Ordering::Acquire));
     }
+
+    #[tokio::test]
+    async fn revoked_share_token_stops_decoding() {
+        let mut server = Server::new_for_test();
+        Arc::get_mut(&mut server).unwrap().share_token_secret = Some("secret".into());
+        let token = share_token::sign_share_token(
+            &share_token::ShareTokenPayload {
+                purpose: share_token::SharePurpose::Share,
+                role: share_token::ShareRole::Edit,
+                folder: "fbd5eb54-73cc-41b0-ac28-2b93d3b4244e".into(),
+                expiry: u32::MAX,
+                user: None,
+                issued_at: None,
+            },
+            "secret",
+        )
+        .unwrap();
+        assert!(server.decode_mcp_key(&token).is_some());
+
+        server
+            .update_share_revocations(|list| {
+                list.revoke_token(RevokedToken {
+                    fingerprint: token_fingerprint(&token),
+                    expiry: Some(u32::MAX),
+                    revoked_at: 0,
+                    note: None,
+                });
+            })
+            .await
+            .unwrap();
+
+        assert!(server.decode_mcp_key(&token).is_none());
+        assert_eq!(server.share_revocations().tokens.len(), 1);
+    }
 }
 
 async fn handle_file_upload(
@@ -9962,10 +15751,10 @@ async fn handle_file_download(
     let permission = validate_file_token(&server_state, &params.token, &doc_id)?;
 
     if let Permission::File(file_permission) = permission {
-        // Both ReadOnly and Full can download files
+        // ReadOnly, Suggest and Full can download files
         if !matches!(
             file_permission.authorization,
-            Authorization::ReadOnly | Authorization::Full
+            Authorization::ReadOnly | Authorization::Suggest | Authorization::Full
         ) {
             return Err(AppError::auth(
                 StatusCode::FORBIDDEN,
@@ -0,0 +1,311 @@
+//! Offline store administration ("relay store ..."): inspect and repair docs
+//! at rest while the server is down. Every write goes through
+//! `convert::convert` as a Yjs update on top of the stored doc, so the
+//! result merges cleanly with clients that still hold the old state.
+
+use crate::convert::convert;
+use anyhow::{anyhow, bail, Context, Result};
+use std::collections::BTreeMap;
+use y_sweet_core::{
+    doc_dump::find_stored_folder,
+    doc_sync::doc_from_snapshot,
+    fsck::normalize_path,
+    link_indexer::{ensure_ancestor_folders, extract_id_from_filemeta_entry},
+    store::Store,
+};
+use yrs::{Doc, GetString, Map, ReadTxn, StateVector, Text, Transact, WriteTxn};
+
+/// Transaction origin for offline edits, so they can be told apart in
+/// update observers once the server loads the doc again.
+pub const STORE_ADMIN_ORIGIN: &str = "store-admin";
+
+fn doc_key(doc_id: &str) -> String {
+    format!("{}/data.ysweet", doc_id)
+}
+
+async fn load_doc(store: &dyn Store, doc_id: &str) -> Result<Option<Doc>> {
+    let Some(snapshot) = store.get(&doc_key(doc_id)).await? else {
+        return Ok(None);
+    };
+    let doc = doc_from_snapshot(&snapshot)
+        .with_context(|| format!("Failed to load {} from the store", doc_id))?;
+    Ok(Some(doc))
+}
+
+/// Doc IDs in the store, sorted.
+pub async fn list_docs(store: &dyn Store) -> Result<Vec<String>> {
+    let mut doc_ids = store.list_doc_ids().await?;
+    doc_ids.sort();
+    Ok(doc_ids)
+}
+
+/// `filemeta_v0` of the folder named `folder_name`: path → (id, type).
+pub async fn list_folder(
+    store: &dyn Store,
+    folder_name: &str,
+) -> Result<BTreeMap<String, (String, String)>> {
+    let (_, doc) = load_folder(store, folder_name).await?;
+    let txn = doc.transact();
+    let Some(filemeta) = txn.get_map("filemeta_v0") else {
+        return Ok(BTreeMap::new());
+    };
+    Ok(filemeta
+        .iter(&txn)
+        .map(|(path, value)| {
+            let id = extract_id_from_filemeta_entry(&value, &txn).unwrap_or_default();
+            let entry_type =
+                y_sweet_core::link_indexer::extract_type_from_filemeta_entry(&value, &txn)
+                    .unwrap_or_default();
+            (path.to_string(), (id, entry_type))
+        })
+        .collect())
+}
+
+/// The markdown (`contents` text) of a stored doc.
+pub async fn read_markdown(store: &dyn Store, doc_id: &str) -> Result<String> {
+    let doc = load_doc(store, doc_id)
+        .await?
+        .ok_or_else(|| anyhow!("Document not found in the store: {}", doc_id))?;
+    let txn = doc.transact();
+    Ok(txn
+        .get_text("contents")
+        .map(|text| text.get_string(&txn))
+        .unwrap_or_default())
+}
+
+/// Make `markdown` the `contents` of `doc_id`, creating the doc if needed.
+/// An existing doc gets the word-level edits from its current text, not a
+/// replacement, so unchanged passages keep their CRDT identity. Returns the
+/// number of edits.
+pub async fn write_markdown(store: Box<dyn Store>, doc_id: &str, markdown: &str) -> Result<usize> {
+    let doc = load_doc(store.as_ref(), doc_id)
+        .await?
+        .unwrap_or_else(Doc::new);
+    let before = doc.transact().state_vector();
+    let edits = {
+        let mut txn = doc.transact_mut_with(STORE_ADMIN_ORIGIN);
+        let text = txn.get_or_insert_text("contents");
+        let live = text.get_string(&txn);
+        let edits = crate::mcp::tools::critic_diff::text_edits(&live, markdown, None)
+            .map_err(|e| anyhow!(e))?;
+        crate::server::apply_text_edits(&mut txn, &text, &edits);
+        edits.len()
+    };
+    if edits > 0 || before == StateVector::default() {
+        let update = doc.transact().encode_state_as_update_v1(&before);
+        convert(store, &update, doc_id).await?;
+    }
+    Ok(edits)
+}
+
+/// Delete a stored doc. Folder entries pointing at it are left alone; run
+/// `relay fsck` against the server afterwards to find them.
+pub async fn remove_doc(store: &dyn Store, doc_id: &str) -> Result<()> {
+    let key = doc_key(doc_id);
+    if !store.exists(&key).await? {
+        bail!("Document not found in the store: {}", doc_id);
+    }
+    store.remove(&key).await?;
+    Ok(())
+}
+
+/// Rename `from` to `to` in the `filemeta_v0` and legacy `docs` maps of the
+/// folder named `folder_name`. A folder path moves with everything under
+/// it; missing ancestors of `to` are created. Wikilinks pointing at the
+/// moved docs are not rewritten. Returns the number of entries moved.
+pub async fn move_path(
+    store: Box<dyn Store>,
+    folder_name: &str,
+    from: &str,
+    to: &str,
+) -> Result<usize> {
+    let from = normalize_path(from).ok_or_else(|| anyhow!("Invalid path: {}", from))?;
+    let to = normalize_path(to).ok_or_else(|| anyhow!("Invalid path: {}", to))?;
+    if to == from || to.starts_with(&format!("{}/", from)) {
+        bail!("Cannot move {} to itself or one of its descendants", from);
+    }
+    let (folder_doc_id, doc) = load_folder(store.as_ref(), folder_name).await?;
+
+    let before = doc.transact().state_vector();
+    let moved = {
+        let mut txn = doc.transact_mut_with(STORE_ADMIN_ORIGIN);
+        let filemeta = txn.get_or_insert_map("filemeta_v0");
+        let docs_map = txn.get_or_insert_map("docs");
+
+        let from_prefix = format!("{}/", from);
+        let moves: Vec<(String, String)> = filemeta
+            .keys(&txn)
+            .filter(|path| *path == from || path.starts_with(&from_prefix))
+            .map(|path| (path.to_string(), format!("{}{}", to, &path[from.len()..])))
+            .collect();
+        if moves.is_empty() {
+            bail!("Path not found in folder '{}': {}", folder_name, from);
+        }
+        if let Some((_, taken)) = moves
+            .iter()
+            .find(|(_, destination)| filemeta.get(&txn, destination).is_some())
+        {
+            bail!("Path already exists in folder '{}': {}", folder_name, taken);
+        }
+
+        let entries: Vec<_> = moves
+            .iter()
+            .map(|(source, destination)| {
+                let meta = filemeta.get(&txn, source).map(|value| value.to_json(&txn));
+                let legacy = docs_map.get(&txn, source).map(|value| value.to_json(&txn));
+                (source, destination, meta, legacy)
+            })
+            .collect();
+        for (source, destination, meta, legacy) in entries {
+            filemeta.remove(&mut txn, source);
+            docs_map.remove(&mut txn, source);
+            if let Some(meta) = meta {
+                filemeta.insert(&mut txn, destination.as_str(), meta);
+            }
+            if let Some(legacy) = legacy {
+                docs_map.insert(&mut txn, destination.as_str(), legacy);
+            }
+        }
+        ensure_ancestor_folders(&filemeta, &docs_map, &mut txn, &to);
+        moves.len()
+    };
+
+    let update = doc.transact().encode_state_as_update_v1(&before);
+    convert(store, &update, &folder_doc_id).await?;
+    Ok(moved)
+}
+
+async fn load_folder(store: &dyn Store, folder_name: &str) -> Result<(String, Doc)> {
+    let folder_doc_id = find_stored_folder(store, folder_name)
+        .await?
+        .ok_or_else(|| anyhow!("Folder '{}' not found in the store", folder_name))?;
+    let doc = load_doc(store, &folder_doc_id)
+        .await?
+        .ok_or_else(|| anyhow!("Document not found in the store: {}", folder_doc_id))?;
+    Ok((folder_doc_id, doc))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stores::filesystem::FileSystemStore;
+    use tempfile::TempDir;
+    use yrs::Any;
+
+    fn store(dir: &TempDir) -> Box<dyn Store> {
+        Box::new(FileSystemStore::new(dir.path().to_path_buf()).unwrap())
+    }
+
+    async fn write_folder(dir: &TempDir, doc_id: &str, name: &str, paths: &[(&str, &str, &str)]) {
+        let doc = Doc::new();
+        {
+            let mut txn = doc.transact_mut();
+            txn.get_or_insert_map("folder_config")
+                .insert(&mut txn, "name", name);
+            let filemeta = txn.get_or_insert_map("filemeta_v0");
+            let docs_map = txn.get_or_insert_map("docs");
+            for (path, id, entry_type) in paths {
+                let mut meta = std::collections::HashMap::new();
+                meta.insert("id".to_string(), Any::from(*id));
+                meta.insert("type".to_string(), Any::from(*entry_type));
+                meta.insert("version".to_string(), Any::Number(0.0));
+                filemeta.insert(&mut txn, *path, Any::Map(meta.into()));
+                docs_map.insert(&mut txn, *path, *id);
+            }
+        }
+        let update = doc
+            .transact()
+            .encode_state_as_update_v1(&StateVector::default());
+        convert(store(dir), &update, doc_id).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn put_cat_and_rm_round_trip() {
+        let dir = TempDir::new().unwrap();
+        assert_eq!(
+            write_markdown(store(&dir), "doc-1", "# Notes\nfirst\n")
+                .await
+                .unwrap(),
+            1
+        );
+        assert_eq!(
+            read_markdown(store(&dir).as_ref(), "doc-1").await.unwrap(),
+            "# Notes\nfirst\n"
+        );
+
+        // Rewriting edits the stored doc rather than appending a second copy.
+        write_markdown(store(&dir), "doc-1", "# Notes\nsecond\n")
+            .await
+            .unwrap();
+        assert_eq!(
+            read_markdown(store(&dir).as_ref(), "doc-1").await.unwrap(),
+            "# Notes\nsecond\n"
+        );
+        assert_eq!(
+            list_docs(store(&dir).as_ref()).await.unwrap(),
+            vec!["doc-1"]
+        );
+
+        remove_doc(store(&dir).as_ref(), "doc-1").await.unwrap();
+        assert!(list_docs(store(&dir).as_ref()).await.unwrap().is_empty());
+        assert!(read_markdown(store(&dir).as_ref(), "doc-1").await.is_err());
+        assert!(remove_doc(store(&dir).as_ref(), "doc-1").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn mv_renames_a_folder_and_its_children() {
+        let dir = TempDir::new().unwrap();
+        write_folder(
+            &dir,
+            "relay-folder",
+            "Lens",
+            &[
+                ("/Drafts", "f1", "folder"),
+                ("/Drafts/a.md", "c1", "markdown"),
+                ("/Drafts/Old/b.md", "c2", "markdown"),
+                ("/Top.md", "c3", "markdown"),
+            ],
+        )
+        .await;
+
+        let moved = move_path(store(&dir), "Lens", "/Drafts", "/Archive/2024")
+            .await
+            .unwrap();
+        assert_eq!(moved, 3);
+
+        let entries = list_folder(store(&dir).as_ref(), "Lens").await.unwrap();
+        assert_eq!(
+            entries.keys().map(String::as_str).collect::<Vec<_>>(),
+            vec![
+                "/Archive",
+                "/Archive/2024",
+                "/Archive/2024/Old/b.md",
+                "/Archive/2024/a.md",
+                "/Top.md"
+            ]
+        );
+        assert_eq!(
+            entries["/Archive/2024/a.md"],
+            ("c1".into(), "markdown".into())
+        );
+
+        let dump = y_sweet_core::doc_dump::dump_stored_doc(store(&dir).as_ref(), "relay-folder")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(dump["docs"]["/Archive/2024/Old/b.md"], "c2");
+        assert!(dump["docs"].get("/Drafts").is_none());
+
+        assert!(
+            move_path(store(&dir), "Lens", "/Top.md", "/Archive/2024/a.md")
+                .await
+                .is_err()
+        );
+        assert!(move_path(store(&dir), "Lens", "/Missing.md", "/x.md")
+            .await
+            .is_err());
+        assert!(move_path(store(&dir), "Nope", "/Top.md", "/x.md")
+            .await
+            .is_err());
+    }
+}
@@ -0,0 +1,260 @@
+//! Trash worker: record entries that clients delete from a folder's
+//! `filemeta_v0` in the folder's trash, and purge trashed content once it
+//! has been there for the retention period.
+//!
+//! Deletions made through the relay (`Server::delete_path`) are trashed in
+//! the same transaction. Clients delete by removing the filemeta entry over
+//! sync, so the worker compares each folder's filemeta with what it saw at
+//! the previous scan. An entry that left one folder but is live in another
+//! was moved, not deleted; a trashed entry that shows up live again (a
+//! client undo, a snapshot restore) is dropped from the trash.
+
+use crate::git_sync;
+use crate::server::{current_time_epoch_millis, Server};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
+use y_sweet_core::link_indexer;
+use y_sweet_core::trash::{self, FilemetaById, TRASH_ORIGIN};
+use yrs::{ReadTxn, StateVector, Transact};
+
+/// How often the worker checks the folders for deletions.
+const TRASH_TICK: Duration = Duration::from_secs(2);
+
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+#[derive(Debug, Clone)]
+pub struct TrashConfig {
+    /// How long trashed content is kept before it is purged from the store.
+    pub retention: Duration,
+    /// How often to look for expired trash.
+    pub purge_interval: Duration,
+}
+
+impl Default for TrashConfig {
+    fn default() -> Self {
+        Self {
+            retention: 30 * DAY,
+            purge_interval: Duration::from_secs(3600),
+        }
+    }
+}
+
+impl TrashConfig {
+    /// Read `RELAY_TRASH_RETENTION_DAYS` (default 30) and
+    /// `RELAY_TRASH_PURGE_INTERVAL_SECONDS` (default 3600). Invalid numbers
+    /// are logged and ignored.
+    pub fn from_env() -> Self {
+        let number = |var: &str, default: u64| match std::env::var(var) {
+            Ok(value) => value.trim().parse::<u64>().unwrap_or_else(|e| {
+                tracing::warn!("Ignoring {}={}: {}", var, value, e);
+                default
+            }),
+            Err(_) => default,
+        };
+        Self {
+            retention: Duration::from_secs(
+                number("RELAY_TRASH_RETENTION_DAYS", 30).saturating_mul(DAY.as_secs()),
+            ),
+            purge_interval: Duration::from_secs(number("RELAY_TRASH_PURGE_INTERVAL_SECONDS", 3600)),
+        }
+    }
+}
+
+/// A folder doc as of the last scan.
+struct FolderSnapshot {
+    state_vector: StateVector,
+    filemeta: FilemetaById,
+    trashed: HashSet<String>,
+}
+
+/// What a scan found for one folder doc.
+struct FolderScan {
+    snapshot: FolderSnapshot,
+    /// IDs that left the folder since the last scan.
+    removed: Vec<String>,
+    /// Who edited the folder since the last scan.
+    authors: Vec<String>,
+}
+
+pub struct TrashTracker {
+    config: TrashConfig,
+    folders: HashMap<String, FolderSnapshot>,
+    last_purge: Option<Instant>,
+}
+
+impl TrashTracker {
+    pub fn new(config: TrashConfig) -> Self {
+        Self {
+            config,
+            folders: HashMap::new(),
+            last_purge: None,
+        }
+    }
+
+    /// Scan and purge until `cancellation_token` fires.
+    pub async fn run(&mut self, server: Arc<Server>, cancellation_token: CancellationToken) {
+        loop {
+            tokio::select! {
+                _ = tokio::time::sleep(TRASH_TICK) => {}
+                _ = cancellation_token.cancelled() => {
+                    tracing::info!("Trash worker shutting down");
+                    return;
+                }
+            }
+            self.scan(&server).await;
+            let purge_due = match self.last_purge {
+                Some(purge) => purge.elapsed() >= self.config.purge_interval,
+                None => true,
+            };
+            if purge_due {
+                self.last_purge = Some(Instant::now());
+                let known: Vec<String> = self.folders.keys().cloned().collect();
+                let purged = server.purge_trash(self.config.retention, &known).await;
+                if purged > 0 {
+                    tracing::info!(purged, "Purged expired trash");
+                }
+            }
+        }
+    }
+
+    /// Trash the entries deleted from any folder since the last scan, and
+    /// drop trashed entries that are live again. A folder seen for the first
+    /// time is only recorded. Returns the number of entries trashed.
+    pub async fn scan(&mut self, server: &Server) -> usize {
+        let folder_doc_ids = link_indexer::find_all_folder_docs(server.docs());
+        let mut scans: HashMap<String, FolderScan> = HashMap::new();
+        for folder_doc_id in &folder_doc_ids {
+            let Some(awareness) = server.docs().get(folder_doc_id).map(|d| d.awareness()) else {
+                continue;
+            };
+            let guard = awareness.read().unwrap_or_else(|e| e.into_inner());
+            let txn = guard.doc.transact();
+            let state_vector = txn.state_vector();
+            let previous = self.folders.get(folder_doc_id);
+            if previous.is_some_and(|p| p.state_vector == state_vector) {
+                continue;
+            }
+            let filemeta = trash::filemeta_by_id(&txn);
+            let trashed = trash::list_trash(&txn)
+                .into_iter()
+                .map(|item| item.id)
+                .collect();
+            let removed: Vec<String> = previous
+                .map(|p| {
+                    p.filemeta
+                        .keys()
+                        .filter(|id| !filemeta.contains_key(*id))
+                        .cloned()
+                        .collect()
+                })
+                .unwrap_or_default();
+            drop(txn);
+            let authors = match previous {
+                Some(p) if !removed.is_empty() => {
+                    git_sync::doc_authors(&guard.doc, &p.state_vector)
+                }
+                _ => Vec::new(),
+            };
+            scans.insert(
+                folder_doc_id.clone(),
+                FolderScan {
+                    snapshot: FolderSnapshot {
+                        state_vector,
+                        filemeta,
+                        trashed,
+                    },
+                    removed,
+                    authors,
+                },
+            );
+        }
+        if scans.is_empty() {
+            return 0;
+        }
+
+        // Folders that were not scanned (unchanged, or unloaded) still count
+        // towards what is live.
+        let previous = std::mem::take(&mut self.folders);
+        let mut previous_by_folder = HashMap::new();
+        for (folder_doc_id, snapshot) in previous {
+            if scans.contains_key(&folder_doc_id) {
+                previous_by_folder.insert(folder_doc_id, snapshot);
+            } else {
+                self.folders.insert(folder_doc_id, snapshot);
+            }
+        }
+        let live: HashSet<String> = self
+            .folders
+            .values()
+            .chain(scans.values().map(|scan| &scan.snapshot))
+            .flat_map(|snapshot| snapshot.filemeta.keys().cloned())
+            .collect();
+
+        let now = current_time_epoch_millis();
+        let mut trashed_total = 0;
+        let all_folders: Vec<String> = self.folders.keys().chain(scans.keys()).cloned().collect();
+        for folder_doc_id in all_folders {
+            let (removed, authors, previous) = match scans.get(&folder_doc_id) {
+                Some(scan) => (
+                    scan.removed
+                        .iter()
+                        .filter(|id| !live.contains(*id))
+                        .cloned()
+                        .collect::<Vec<_>>(),
+                    scan.authors.join(", "),
+                    previous_by_folder.get(&folder_doc_id),
+                ),
+                None => (Vec::new(), String::new(), None),
+            };
+            let revived: Vec<String> = scans
+                .get(&folder_doc_id)
+                .map(|scan| &scan.snapshot)
+                .or_else(|| self.folders.get(&folder_doc_id))
+                .map(|snapshot| snapshot.trashed.intersection(&live).cloned().collect())
+                .unwrap_or_default();
+            if removed.is_empty() && revived.is_empty() {
+                continue;
+            }
+            let Some((awareness, sync_kv)) = server
+                .docs()
+                .get(&folder_doc_id)
+                .map(|d| (d.awareness(), d.sync_kv()))
+            else {
+                continue;
+            };
+            {
+                let guard = awareness.write().unwrap_or_else(|e| e.into_inner());
+                let mut txn = guard.doc.transact_mut_with(TRASH_ORIGIN);
+                for id in &removed {
+                    let Some((path, meta)) = previous.and_then(|p| p.filemeta.get(id)) else {
+                        continue;
+                    };
+                    let deleted_by = (!authors.is_empty()).then_some(authors.as_str());
+                    if trash::put_in_trash(&mut txn, id, path, meta, now, deleted_by) {
+                        trashed_total += 1;
+                        tracing::info!(
+                            folder_doc_id = %folder_doc_id,
+                            id = %id,
+                            path = %path,
+                            deleted_by = ?deleted_by,
+                            "Moved deleted entry to trash"
+                        );
+                    }
+                }
+                trash::remove_from_trash(&mut txn, &revived);
+            } // awareness write released
+            if let Err(e) = sync_kv.persist().await {
+                tracing::error!("Failed to persist trash of {}: {:?}", folder_doc_id, e);
+            }
+        }
+        // Our own writes change the state vectors, so the folders written to
+        // are scanned again next time, against the filemeta seen now.
+        for (folder_doc_id, scan) in scans {
+            self.folders.insert(folder_doc_id, scan.snapshot);
+        }
+        trashed_total
+    }
+}
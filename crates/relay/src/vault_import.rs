@@ -0,0 +1,216 @@
+//! Import an Obsidian vault directory into a relay folder ("relay import").
+//!
+//! This module only walks the vault and decides what each file becomes;
+//! `Server::import_vault` writes the folder doc, content docs and blobs.
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+/// Transaction origin for imported content and folder entries.
+pub const VAULT_IMPORT_ORIGIN: &str = "import";
+
+/// What a vault file becomes in the folder's `filemeta_v0`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VaultEntryKind {
+    Folder,
+    /// A `.md` file, imported as a content doc.
+    Markdown,
+    /// Any other file, uploaded as a blob under its SHA-256 hash.
+    Blob {
+        file_type: &'static str,
+        mimetype: &'static str,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VaultEntry {
+    /// In-folder path, with a leading slash ("/Notes/Idea.md").
+    pub path: String,
+    pub source: PathBuf,
+    pub kind: VaultEntryKind,
+}
+
+/// Outcome of an import. Every count is per file, so re-importing an
+/// unchanged vault reports only `*_unchanged` counts.
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VaultImportReport {
+    pub folder_doc_id: String,
+    /// The folder doc did not exist and was created by this import.
+    pub folder_created: bool,
+    pub folders_created: usize,
+    pub docs_created: usize,
+    pub docs_updated: usize,
+    pub docs_unchanged: usize,
+    pub blobs_uploaded: usize,
+    pub blobs_unchanged: usize,
+    /// Vault files that were not imported, with the reason.
+    pub skipped: Vec<String>,
+    /// Paths already in the folder as a different type; left untouched.
+    pub conflicts: Vec<String>,
+}
+
+/// Extension → (filemeta type, mimetype) for blobs, matching the types the
+/// Relay plugin assigns.
+const BLOB_TYPES: &[(&str, &str, &str)] = &[
+    ("png", "image", "image/png"),
+    ("jpg", "image", "image/jpeg"),
+    ("jpeg", "image", "image/jpeg"),
+    ("gif", "image", "image/gif"),
+    ("webp", "image", "image/webp"),
+    ("svg", "image", "image/svg+xml"),
+    ("bmp", "image", "image/bmp"),
+    ("avif", "image", "image/avif"),
+    ("pdf", "pdf", "application/pdf"),
+    ("mp3", "audio", "audio/mpeg"),
+    ("wav", "audio", "audio/wav"),
+    ("m4a", "audio", "audio/mp4"),
+    ("ogg", "audio", "audio/ogg"),
+    ("flac", "audio", "audio/flac"),
+    ("mp4", "video", "video/mp4"),
+    ("mov", "video", "video/quicktime"),
+    ("mkv", "video", "video/x-matroska"),
+    ("webm", "video", "video/webm"),
+];
+
+/// Classify a vault file by extension. None for files the relay cannot
+/// represent yet (canvases, whose JSON the plugin keeps in its own format).
+pub fn classify(file_name: &str) -> Option<VaultEntryKind> {
+    let extension = file_name
+        .rsplit_once('.')
+        .map(|(_, ext)| ext.to_ascii_lowercase())
+        .unwrap_or_default();
+    match extension.as_str() {
+        "md" => Some(VaultEntryKind::Markdown),
+        "canvas" => None,
+        ext => {
+            let (file_type, mimetype) = BLOB_TYPES
+                .iter()
+                .find(|(known, _, _)| *known == ext)
+                .map(|(_, file_type, mimetype)| (*file_type, *mimetype))
+                .unwrap_or(("file", "application/octet-stream"));
+            Some(VaultEntryKind::Blob {
+                file_type,
+                mimetype,
+            })
+        }
+    }
+}
+
+/// Walk `root` and list what to import, sorted by path so every folder
+/// comes before its contents. Dot files and directories (`.obsidian`,
+/// `.trash`, `.git`) are ignored; files that cannot be imported are
+/// returned as skip messages.
+pub fn scan_vault(root: &Path) -> Result<(Vec<VaultEntry>, Vec<String>)> {
+    if !root.is_dir() {
+        anyhow::bail!("Not a directory: {}", root.display());
+    }
+    let mut entries = Vec::new();
+    let mut skipped = Vec::new();
+    scan_dir(root, "", &mut entries, &mut skipped)?;
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+    skipped.sort();
+    Ok((entries, skipped))
+}
+
+fn scan_dir(
+    dir: &Path,
+    prefix: &str,
+    entries: &mut Vec<VaultEntry>,
+    skipped: &mut Vec<String>,
+) -> Result<()> {
+    let listing =
+        std::fs::read_dir(dir).with_context(|| format!("Failed to read {}", dir.display()))?;
+    for dir_entry in listing {
+        let dir_entry = dir_entry?;
+        let source = dir_entry.path();
+        let Some(name) = dir_entry.file_name().to_str().map(str::to_string) else {
+            skipped.push(format!("{}: file name is not UTF-8", source.display()));
+            continue;
+        };
+        if name.starts_with('.') {
+            continue;
+        }
+        let path = format!("{}/{}", prefix, name);
+        if name.contains('"') {
+            skipped.push(format!("{}: file names cannot contain double quotes", path));
+            continue;
+        }
+        let file_type = dir_entry.file_type()?;
+        if file_type.is_dir() {
+            entries.push(VaultEntry {
+                path: path.clone(),
+                source: source.clone(),
+                kind: VaultEntryKind::Folder,
+            });
+            scan_dir(&source, &path, entries, skipped)?;
+        } else if file_type.is_file() {
+            match classify(&name) {
+                Some(kind) => entries.push(VaultEntry { path, source, kind }),
+                None => skipped.push(format!("{}: unsupported file type", path)),
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn classifies_by_extension() {
+        assert_eq!(classify("Note.md"), Some(VaultEntryKind::Markdown));
+        assert_eq!(
+            classify("Photo.JPG"),
+            Some(VaultEntryKind::Blob {
+                file_type: "image",
+                mimetype: "image/jpeg"
+            })
+        );
+        assert_eq!(
+            classify("paper.pdf"),
+            Some(VaultEntryKind::Blob {
+                file_type: "pdf",
+                mimetype: "application/pdf"
+            })
+        );
+        assert_eq!(
+            classify("data.csv"),
+            Some(VaultEntryKind::Blob {
+                file_type: "file",
+                mimetype: "application/octet-stream"
+            })
+        );
+        assert_eq!(classify("Board.canvas"), None);
+    }
+
+    #[test]
+    fn scan_skips_dot_dirs_and_sorts_parents_first() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path();
+        std::fs::create_dir_all(root.join(".obsidian")).unwrap();
+        std::fs::write(root.join(".obsidian/app.json"), "{}").unwrap();
+        std::fs::create_dir_all(root.join("Notes/Deep")).unwrap();
+        std::fs::write(root.join("Notes/Deep/b.md"), "b").unwrap();
+        std::fs::write(root.join("Notes/a.md"), "a").unwrap();
+        std::fs::write(root.join("img.png"), [1u8, 2]).unwrap();
+        std::fs::write(root.join("Board.canvas"), "{}").unwrap();
+
+        let (entries, skipped) = scan_vault(root).unwrap();
+        assert_eq!(
+            entries.iter().map(|e| e.path.as_str()).collect::<Vec<_>>(),
+            vec![
+                "/Notes",
+                "/Notes/Deep",
+                "/Notes/Deep/b.md",
+                "/Notes/a.md",
+                "/img.png"
+            ]
+        );
+        assert_eq!(skipped, vec!["/Board.canvas: unsupported file type"]);
+        assert!(scan_vault(&root.join("missing")).is_err());
+    }
+}
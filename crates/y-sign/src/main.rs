@@ -171,9 +171,10 @@ async fn sign_stdin(
 
     let authorization = match auth_str {
         "read" | "read-only" => Authorization::ReadOnly,
+        "suggest" => Authorization::Suggest,
         "full" => Authorization::Full,
         other => anyhow::bail!(
-            "Invalid authorization: {}. Must be 'read', 'read-only', or 'full'",
+            "Invalid authorization: {}. Must be 'read', 'read-only', 'suggest', or 'full'",
             other
         ),
     };
@@ -211,6 +212,7 @@ async fn sign_stdin(
 
             let auth_value = match authorization {
                 Authorization::ReadOnly => "read-only",
+                Authorization::Suggest => "suggest",
                 Authorization::Full => "full",
             };
             output.insert(
@@ -278,6 +280,7 @@ async fn sign_stdin(
 
             let auth_value = match authorization {
                 Authorization::ReadOnly => "read-only",
+                Authorization::Suggest => "suggest",
                 Authorization::Full => "full",
             };
             output.insert(
@@ -333,6 +336,7 @@ async fn sign_stdin(
 
             let auth_value = match authorization {
                 Authorization::ReadOnly => "read-only",
+                Authorization::Suggest => "suggest",
                 Authorization::Full => "full",
             };
             output.insert(
@@ -403,6 +407,7 @@ async fn verify_stdin(auth: &Authenticator, id: Option<&str>, key_type: &str) ->
                     // Add authorization
                     let auth_str = match doc_permission.authorization {
                         Authorization::ReadOnly => "read-only",
+                        Authorization::Suggest => "suggest",
                         Authorization::Full => "full",
                     };
                     verification.insert(
@@ -436,6 +441,7 @@ async fn verify_stdin(auth: &Authenticator, id: Option<&str>, key_type: &str) ->
                     // Add authorization
                     let auth_str = match file_permission.authorization {
                         Authorization::ReadOnly => "read-only",
+                        Authorization::Suggest => "suggest",
                         Authorization::Full => "full",
                     };
                     verification.insert(
@@ -478,6 +484,7 @@ async fn verify_stdin(auth: &Authenticator, id: Option<&str>, key_type: &str) ->
                     // Add authorization
                     let auth_str = match prefix_permission.authorization {
                         Authorization::ReadOnly => "read-only",
+                        Authorization::Suggest => "suggest",
                         Authorization::Full => "full",
                     };
                     verification.insert(
@@ -601,6 +608,7 @@ async fn verify_stdin(auth: &Authenticator, id: Option<&str>, key_type: &str) ->
                     Ok(authorization) => {
                         let auth_str = match authorization {
                             Authorization::ReadOnly => "read-only",
+                            Authorization::Suggest => "suggest",
                             Authorization::Full => "full",
                         };
 
@@ -696,6 +704,7 @@ async fn verify_stdin(auth: &Authenticator, id: Option<&str>, key_type: &str) ->
                     if let Ok(authorization) = auth_result {
                         let auth_str = match authorization {
                             Authorization::ReadOnly => "read-only",
+                            Authorization::Suggest => "suggest",
                             Authorization::Full => "full",
                         };
 
@@ -0,0 +1,450 @@
+//! Folder- and path-level access control lists.
+//!
+//! Tokens grant a doc, a doc ID prefix or a whole folder. The ACL policy,
+//! kept in the store at `.config/acl.json`, narrows that per path: each
+//! folder (keyed by UUID or display name, `*` for every folder) lists rules
+//! like `/Drafts/** -> view`, optionally for one user. A path inherits the
+//! rule of its nearest ancestor, so a rule on `/**` covers the whole folder
+//! until a deeper one overrides it, and `*` rules cover folders that say
+//! nothing more specific. Paths no rule covers keep what the token grants.
+//! An ACL never widens a token: the effective level is the lower of the two.
+
+use crate::api_types::Authorization;
+use crate::store::Store;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Store key of the server-wide ACL policy.
+pub const ACL_CONFIG_KEY: &str = ".config/acl.json";
+
+/// Folder key whose rules apply to every folder.
+pub const ALL_FOLDERS: &str = "*";
+
+/// Principal of a rule that applies to everyone.
+pub const ANYONE: &str = "*";
+
+/// Access a rule grants, from least to most.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AclRole {
+    None,
+    View,
+    Suggest,
+    Edit,
+}
+
+impl AclRole {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AclRole::None => "none",
+            AclRole::View => "view",
+            AclRole::Suggest => "suggest",
+            AclRole::Edit => "edit",
+        }
+    }
+
+    /// The most this role lets a token holding `authorization` do, or None
+    /// when it denies access outright.
+    pub fn cap(self, authorization: Authorization) -> Option<Authorization> {
+        let ceiling = match self {
+            AclRole::None => return None,
+            AclRole::View => Authorization::ReadOnly,
+            AclRole::Suggest => Authorization::Suggest,
+            AclRole::Edit => Authorization::Full,
+        };
+        Some(if rank(authorization) <= rank(ceiling) {
+            authorization
+        } else {
+            ceiling
+        })
+    }
+
+    /// Whether the role allows `authorization`-level access at all.
+    pub fn allows(self, authorization: Authorization) -> bool {
+        self.cap(authorization) == Some(authorization)
+    }
+}
+
+fn rank(authorization: Authorization) -> u8 {
+    match authorization {
+        Authorization::ReadOnly => 0,
+        Authorization::Suggest => 1,
+        Authorization::Full => 2,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AclRule {
+    /// In-folder path the rule covers: `/Drafts/**` for a subtree (including
+    /// `/Drafts` itself), `/Drafts/Week 1.md` for one entry, `/**` for the
+    /// whole folder.
+    pub path: String,
+    /// User ID the rule applies to; `*` or absent for everyone.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    pub role: AclRole,
+}
+
+impl AclRule {
+    /// The path the rule is rooted at and whether it covers the subtree below.
+    fn base(&self) -> (&str, bool) {
+        if self.path == "/**" || self.path == "/" {
+            ("", true)
+        } else if let Some(base) = self.path.strip_suffix("/**") {
+            (base, true)
+        } else {
+            (self.path.as_str(), false)
+        }
+    }
+
+    fn matches_path(&self, path: &str) -> bool {
+        let (base, subtree) = self.base();
+        path == base
+            || (subtree
+                && path
+                    .strip_prefix(base)
+                    .is_some_and(|rest| rest.starts_with('/') || rest.is_empty()))
+    }
+
+    fn matches_user(&self, user: Option<&str>) -> bool {
+        match self.user.as_deref() {
+            None | Some(ANYONE) => true,
+            Some(rule_user) => user == Some(rule_user),
+        }
+    }
+
+    fn is_user_specific(&self) -> bool {
+        !matches!(self.user.as_deref(), None | Some(ANYONE))
+    }
+
+    /// Precedence among rules matching the same path: deeper rules first,
+    /// then exact paths over subtrees, then user-specific over everyone.
+    fn specificity(&self) -> (usize, bool, bool) {
+        let (base, subtree) = self.base();
+        let depth = base.split('/').filter(|s| !s.is_empty()).count();
+        (depth, !subtree, self.is_user_specific())
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AclPolicy {
+    /// Rules by folder UUID or folder name; `*` applies to every folder.
+    #[serde(default)]
+    pub folders: BTreeMap<String, Vec<AclRule>>,
+}
+
+impl AclPolicy {
+    pub fn is_empty(&self) -> bool {
+        self.folders.values().all(|rules| rules.is_empty())
+    }
+
+    /// Reject rules whose path is not `/`-rooted or uses globs other than a
+    /// trailing `/**`.
+    pub fn validate(&self) -> Result<(), String> {
+        for (folder, rules) in &self.folders {
+            for rule in rules {
+                let (base, _) = rule.base();
+                if !rule.path.starts_with('/')
+                    || base.contains('*')
+                    || base.ends_with('/')
+                    || base.contains("//")
+                {
+                    return Err(format!(
+                        "folder '{}': invalid rule path '{}' (expected /a/b.md or /a/**)",
+                        folder, rule.path
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The role the policy gives `user` on `path` (e.g. `/Drafts/a.md`, or
+    /// `/` for the folder itself) in the folder known by `folder_keys` (its
+    /// UUID and name). None when no rule covers the path.
+    ///
+    /// Among matching rules the most specific wins; folder rules beat `*`
+    /// rules of the same specificity, and on a tie the lower role wins.
+    pub fn role_for(
+        &self,
+        folder_keys: &[&str],
+        path: &str,
+        user: Option<&str>,
+    ) -> Option<AclRole> {
+        let path = path.trim_end_matches('/');
+        let folder_rules = folder_keys
+            .iter()
+            .filter(|key| **key != ALL_FOLDERS)
+            .filter_map(|key| self.folders.get(*key))
+            .flatten()
+            .map(|rule| (rule, true));
+        let global_rules = self
+            .folders
+            .get(ALL_FOLDERS)
+            .into_iter()
+            .flatten()
+            .map(|rule| (rule, false));
+        folder_rules
+            .chain(global_rules)
+            .filter(|(rule, _)| rule.matches_path(path) && rule.matches_user(user))
+            .map(|(rule, folder_specific)| {
+                let (depth, exact, user_specific) = rule.specificity();
+                (
+                    (depth, exact, user_specific, folder_specific),
+                    std::cmp::Reverse(rule.role),
+                )
+            })
+            .max()
+            .map(|(_, std::cmp::Reverse(role))| role)
+    }
+
+    /// Rules in the folder known by `folder_keys` rooted at or below the
+    /// in-folder path `prefix` (e.g. `/Drafts`, or `` for the whole
+    /// folder), as (path, user) pairs with `None` for everyone. Moving the
+    /// subtree carries these paths along but leaves the rules behind.
+    pub fn rules_under(&self, folder_keys: &[&str], prefix: &str) -> Vec<(String, Option<String>)> {
+        let prefix = prefix.trim_end_matches('/');
+        let mut rules: Vec<(String, Option<String>)> = folder_keys
+            .iter()
+            .copied()
+            .filter(|key| *key != ALL_FOLDERS)
+            .chain(std::iter::once(ALL_FOLDERS))
+            .filter_map(|key| self.folders.get(key))
+            .flatten()
+            .filter(|rule| {
+                rule.base()
+                    .0
+                    .strip_prefix(prefix)
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+            })
+            .map(|rule| {
+                let user = rule.is_user_specific().then(|| rule.user.clone()).flatten();
+                (rule.base().0.to_string(), user)
+            })
+            .collect();
+        rules.sort();
+        rules.dedup();
+        rules
+    }
+
+    /// Cap `authorization` by the role for `path`; None when denied.
+    pub fn authorize(
+        &self,
+        folder_keys: &[&str],
+        path: &str,
+        user: Option<&str>,
+        authorization: Authorization,
+    ) -> Option<Authorization> {
+        match self.role_for(folder_keys, path, user) {
+            Some(role) => role.cap(authorization),
+            None => Some(authorization),
+        }
+    }
+}
+
+/// Read the policy from the store; an empty policy when none is saved.
+pub async fn load_policy(store: &dyn Store) -> Result<AclPolicy> {
+    let Some(data) = store.get(ACL_CONFIG_KEY).await? else {
+        return Ok(AclPolicy::default());
+    };
+    let policy: AclPolicy =
+        serde_json::from_slice(&data).with_context(|| format!("Invalid {}", ACL_CONFIG_KEY))?;
+    policy.validate().map_err(anyhow::Error::msg)?;
+    Ok(policy)
+}
+
+/// Validate `policy` and write it to the store.
+pub async fn save_policy(store: &dyn Store, policy: &AclPolicy) -> Result<()> {
+    policy.validate().map_err(anyhow::Error::msg)?;
+    store
+        .set(ACL_CONFIG_KEY, serde_json::to_vec_pretty(policy)?)
+        .await?;
+    Ok(())
+}
+
+/// `path` as the resolver keys it: no leading or trailing `/`, runs of `/`
+/// collapsed (`Lens//Drafts/a.md` is `Lens/Drafts/a.md`). None when a
+/// segment is `.` or `..`, which no stored path has, so that a rule can't
+/// be stepped around by spelling its path differently.
+pub fn canonical_path(path: &str) -> Option<String> {
+    let mut segments = Vec::new();
+    for segment in path.split('/').filter(|segment| !segment.is_empty()) {
+        if segment == "." || segment == ".." {
+            return None;
+        }
+        segments.push(segment);
+    }
+    Some(segments.join("/"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(path: &str, user: Option<&str>, role: AclRole) -> AclRule {
+        AclRule {
+            path: path.to_string(),
+            user: user.map(str::to_string),
+            role,
+        }
+    }
+
+    fn policy() -> AclPolicy {
+        let mut folders = BTreeMap::new();
+        folders.insert(
+            "Lens Edu".to_string(),
+            vec![
+                rule("/**", None, AclRole::View),
+                rule("/Drafts/**", None, AclRole::None),
+                rule("/Drafts/**", Some("ta"), AclRole::Edit),
+                rule("/Drafts/Public.md", None, AclRole::View),
+            ],
+        );
+        folders.insert(
+            ALL_FOLDERS.to_string(),
+            vec![rule("/Private/**", None, AclRole::None)],
+        );
+        AclPolicy { folders }
+    }
+
+    #[test]
+    fn nearest_ancestor_rule_wins() {
+        let policy = policy();
+        let keys = ["uuid-1", "Lens Edu"];
+        assert_eq!(
+            policy.role_for(&keys, "/Notes/a.md", None),
+            Some(AclRole::View)
+        );
+        assert_eq!(
+            policy.role_for(&keys, "/Drafts/Week 1/a.md", None),
+            Some(AclRole::None)
+        );
+        assert_eq!(policy.role_for(&keys, "/Drafts", None), Some(AclRole::None));
+        assert_eq!(
+            policy.role_for(&keys, "/Drafts/Public.md", None),
+            Some(AclRole::View)
+        );
+        assert_eq!(policy.role_for(&keys, "/", None), Some(AclRole::View));
+    }
+
+    #[test]
+    fn user_rules_override_everyone_rules() {
+        let policy = policy();
+        let keys = ["Lens Edu"];
+        assert_eq!(
+            policy.role_for(&keys, "/Drafts/a.md", Some("ta")),
+            Some(AclRole::Edit)
+        );
+        assert_eq!(
+            policy.role_for(&keys, "/Drafts/a.md", Some("student")),
+            Some(AclRole::None)
+        );
+    }
+
+    #[test]
+    fn all_folders_rules_are_inherited() {
+        let policy = policy();
+        assert_eq!(
+            policy.role_for(&["Lens"], "/Private/x.md", None),
+            Some(AclRole::None)
+        );
+        assert_eq!(policy.role_for(&["Lens"], "/Notes/x.md", None), None);
+        // A folder's own /** rule is shallower than the global /Private/**.
+        assert_eq!(
+            policy.role_for(&["Lens Edu"], "/Private/x.md", None),
+            Some(AclRole::None)
+        );
+    }
+
+    #[test]
+    fn subtree_rule_does_not_match_sibling_prefix() {
+        let policy = policy();
+        assert_eq!(
+            policy.role_for(&["Lens Edu"], "/Drafts2/a.md", None),
+            Some(AclRole::View)
+        );
+    }
+
+    #[test]
+    fn acl_never_widens_the_token() {
+        let policy = policy();
+        let keys = ["Lens Edu"];
+        assert_eq!(
+            policy.authorize(&keys, "/Notes/a.md", None, Authorization::Full),
+            Some(Authorization::ReadOnly)
+        );
+        assert_eq!(
+            policy.authorize(&keys, "/Drafts/a.md", Some("ta"), Authorization::Suggest),
+            Some(Authorization::Suggest)
+        );
+        assert_eq!(
+            policy.authorize(&keys, "/Drafts/a.md", None, Authorization::Full),
+            None
+        );
+        assert_eq!(
+            policy.authorize(&["Lens"], "/Notes/a.md", None, Authorization::Full),
+            Some(Authorization::Full)
+        );
+    }
+
+    #[test]
+    fn rules_under_lists_rules_in_a_subtree() {
+        let policy = policy();
+        let rule = |path: &str, user: Option<&str>| (path.to_string(), user.map(str::to_string));
+        assert_eq!(
+            policy.rules_under(&["Lens Edu"], "/Drafts"),
+            vec![
+                rule("/Drafts", None),
+                rule("/Drafts", Some("ta")),
+                rule("/Drafts/Public.md", None)
+            ]
+        );
+        assert_eq!(
+            policy.rules_under(&["Lens Edu"], "").len(),
+            5,
+            "the folder's four rules plus the `*` one"
+        );
+        assert!(policy.rules_under(&["Lens Edu"], "/Dr").is_empty());
+    }
+
+    #[test]
+    fn canonical_path_collapses_slashes_and_rejects_dot_segments() {
+        assert_eq!(
+            canonical_path("/Lens//Drafts/a.md/").as_deref(),
+            Some("Lens/Drafts/a.md")
+        );
+        assert_eq!(canonical_path("").as_deref(), Some(""));
+        assert_eq!(canonical_path("Lens/./Drafts/a.md"), None);
+        assert_eq!(canonical_path("Lens/Notes/../Drafts/a.md"), None);
+        // Dots inside a name are fine.
+        assert_eq!(
+            canonical_path("Lens/..hidden").as_deref(),
+            Some("Lens/..hidden")
+        );
+    }
+
+    #[test]
+    fn validate_rejects_inner_globs() {
+        let mut policy = policy();
+        assert!(policy.validate().is_ok());
+        policy
+            .folders
+            .get_mut("Lens Edu")
+            .unwrap()
+            .push(rule("/Drafts/*.md", None, AclRole::View));
+        assert!(policy.validate().is_err());
+    }
+
+    #[test]
+    fn parses_policy_json() {
+        let policy: AclPolicy = serde_json::from_str(
+            r#"{"folders": {"Lens Edu": [{"path": "/Drafts/**", "user": "ta", "role": "edit"}]}}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            policy.folders["Lens Edu"][0],
+            rule("/Drafts/**", Some("ta"), AclRole::Edit)
+        );
+    }
+}
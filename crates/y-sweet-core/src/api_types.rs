@@ -1,4 +1,5 @@
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
 /// Custom nanoid alphabet without underscore and hyphen (only alphanumeric characters)
 pub const NANOID_ALPHABET: &[char] = &[
@@ -60,6 +61,75 @@ pub struct DocumentVersionResponse {
     pub versions: Vec<DocumentVersionEntry>,
 }
 
+#[derive(Serialize)]
+pub struct DocumentVersionTextResponse {
+    #[serde(rename = "versionId")]
+    pub version_id: String,
+    /// The doc's markdown text as of this version
+    pub text: String,
+}
+
+#[derive(Serialize)]
+pub struct DocumentVersionDiffResponse {
+    /// Version the diff starts from
+    pub from: String,
+    /// Version the diff ends at, or "current" for the live doc
+    pub to: String,
+    /// Unified line diff; empty when the texts are identical
+    pub diff: String,
+}
+
+#[derive(Serialize)]
+pub struct DocumentRestoreResponse {
+    #[serde(rename = "versionId")]
+    pub version_id: String,
+    /// Number of edits applied to the live text; 0 when it already matched
+    pub edits: usize,
+    #[serde(rename = "asSuggestions")]
+    pub as_suggestions: bool,
+}
+
+/// Manifest of a named folder snapshot, as stored next to the snapshotted
+/// docs and returned when listing snapshots.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FolderSnapshotInfo {
+    pub name: String,
+    #[serde(rename = "folderDocId")]
+    pub folder_doc_id: String,
+    /// Creation time in milliseconds since the epoch
+    #[serde(rename = "createdAt")]
+    pub created_at: u64,
+    /// Content doc IDs captured by the snapshot, keyed by their path in the
+    /// folder at snapshot time
+    pub docs: BTreeMap<String, String>,
+}
+
+#[derive(Deserialize)]
+pub struct FolderSnapshotRequest {
+    pub name: String,
+}
+
+#[derive(Serialize)]
+pub struct FolderSnapshotRestoreResponse {
+    pub name: String,
+    /// Content docs whose live text differed from the snapshot
+    #[serde(rename = "docsRestored")]
+    pub docs_restored: usize,
+    /// filemeta_v0, docs and backlinks_v0 entries added, changed or removed
+    #[serde(rename = "folderEntriesRestored")]
+    pub folder_entries_restored: usize,
+}
+
+#[derive(Serialize)]
+pub struct RegressionRestoreResponse {
+    #[serde(rename = "eventId")]
+    pub event_id: String,
+    #[serde(rename = "docId")]
+    pub doc_id: String,
+    /// Number of edits applied to the live text; 0 when it already matched
+    pub edits: usize,
+}
+
 /// Validate that the file hash is a valid SHA256 hash (64 hex characters)
 pub fn validate_file_hash(hash: &str) -> bool {
     // SHA256 hash is 64 characters long hex string
@@ -75,6 +145,10 @@ pub fn validate_file_hash(hash: &str) -> bool {
 pub enum Authorization {
     #[serde(rename = "read-only")]
     ReadOnly,
+    /// Read access, plus updates that only add CriticMarkup suggestions and
+    /// comments to `contents` (see `suggest_guard`).
+    #[serde(rename = "suggest")]
+    Suggest,
     #[serde(rename = "full")]
     Full,
 }
@@ -0,0 +1,326 @@
+//! Server-side content regression detector.
+//!
+//! `scripts/detect-regressions.py` finds wiped or reverted files after the
+//! fact in a git mirror. The relay runs the same two checks as it persists
+//! each content doc: `RegressionDetector::observe` is handed the doc's
+//! `contents` text before every checkpoint and compares it with the texts it
+//! saw at earlier checkpoints.
+//!
+//! - A wipe is near-total deletion: the text drops to at most a tenth of its
+//!   previous size, from at least `MIN_WIPE_BYTES`.
+//! - A reversion is the text coming back to an earlier version exactly, with
+//!   other versions in between and at least `min_revert_gap` since that
+//!   version was first seen. Shorter gaps are ordinary undo/redo.
+//!
+//! History and events live in memory, so they start over on restart.
+
+use crate::api_types::NANOID_ALPHABET;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+
+/// Texts that never reached this size are not flagged as wiped.
+pub const MIN_WIPE_BYTES: usize = 50;
+
+/// Distinct texts remembered per doc for reversion checks.
+const HISTORY_LEN: usize = 32;
+
+/// Events kept for `GET /regressions`; older ones are dropped.
+const MAX_EVENTS: usize = 500;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RegressionConfig {
+    /// Minimum age, in milliseconds, of the version a text reverts to.
+    pub min_revert_gap_ms: u64,
+    /// Whether to save the last persisted state of a doc that regressed.
+    pub snapshots: bool,
+}
+
+impl Default for RegressionConfig {
+    fn default() -> Self {
+        Self {
+            min_revert_gap_ms: 60 * 60 * 1000,
+            snapshots: true,
+        }
+    }
+}
+
+impl RegressionConfig {
+    /// Read `RELAY_REGRESSION_MIN_GAP_MINUTES` (default 60) and
+    /// `RELAY_REGRESSION_SNAPSHOTS` (`0`/`false` to disable). Invalid values
+    /// are logged and ignored.
+    pub fn from_env() -> Self {
+        let mut config = Self::default();
+        if let Ok(minutes) = std::env::var("RELAY_REGRESSION_MIN_GAP_MINUTES") {
+            match minutes.trim().parse::<u64>() {
+                Ok(minutes) => config.min_revert_gap_ms = minutes * 60 * 1000,
+                Err(e) => tracing::warn!(
+                    "Ignoring RELAY_REGRESSION_MIN_GAP_MINUTES={}: {}",
+                    minutes,
+                    e
+                ),
+            }
+        }
+        if let Ok(snapshots) = std::env::var("RELAY_REGRESSION_SNAPSHOTS") {
+            match snapshots.trim().to_ascii_lowercase().as_str() {
+                "1" | "true" | "on" => config.snapshots = true,
+                "0" | "false" | "off" => config.snapshots = false,
+                other => tracing::warn!("Ignoring RELAY_REGRESSION_SNAPSHOTS={}", other),
+            }
+        }
+        config
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RegressionKind {
+    Wipe,
+    Reversion,
+}
+
+impl RegressionKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RegressionKind::Wipe => "wipe",
+            RegressionKind::Reversion => "reversion",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RegressionEvent {
+    pub id: String,
+    pub doc_id: String,
+    pub kind: RegressionKind,
+    /// Epoch ms of the checkpoint that showed the regression.
+    pub detected_at: u64,
+    /// Size in bytes of the text at the previous checkpoint.
+    pub previous_bytes: usize,
+    pub current_bytes: usize,
+    /// For reversions: epoch ms at which the restored-to text was first seen.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reverted_to_at: Option<u64>,
+    pub detail: String,
+    /// Store key of the doc's state from before the regression, if saved.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub snapshot_key: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Seen {
+    hash: [u8; 32],
+    bytes: usize,
+    at: u64,
+}
+
+pub struct RegressionDetector {
+    config: RegressionConfig,
+    history: Mutex<HashMap<String, VecDeque<Seen>>>,
+    events: Mutex<VecDeque<RegressionEvent>>,
+}
+
+impl RegressionDetector {
+    pub fn new(config: RegressionConfig) -> Self {
+        Self {
+            config,
+            history: Mutex::new(HashMap::new()),
+            events: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    pub fn config(&self) -> &RegressionConfig {
+        &self.config
+    }
+
+    /// Note the text of `doc_id` at a checkpoint taken at `now` (epoch ms).
+    /// Returns an event, not yet recorded, when the text regressed since the
+    /// previous checkpoint; the caller may attach a snapshot and then passes
+    /// it to `record`. The first text seen for a doc is only remembered.
+    pub fn observe(&self, doc_id: &str, text: &str, now: u64) -> Option<RegressionEvent> {
+        let seen = Seen {
+            hash: Sha256::digest(text.as_bytes()).into(),
+            bytes: text.len(),
+            at: now,
+        };
+        let mut history = self.history.lock().unwrap_or_else(|e| e.into_inner());
+        let versions = history.entry(doc_id.to_string()).or_default();
+        let Some(previous) = versions.back().copied() else {
+            versions.push_back(seen);
+            return None;
+        };
+        if previous.hash == seen.hash {
+            return None;
+        }
+
+        let event = |kind, reverted_to_at, detail| RegressionEvent {
+            id: format!("reg_{}", nanoid::nanoid!(12, NANOID_ALPHABET)),
+            doc_id: doc_id.to_string(),
+            kind,
+            detected_at: now,
+            previous_bytes: previous.bytes,
+            current_bytes: seen.bytes,
+            reverted_to_at,
+            detail,
+            snapshot_key: None,
+        };
+
+        let regression = if previous.bytes >= MIN_WIPE_BYTES && seen.bytes * 10 <= previous.bytes {
+            Some(event(
+                RegressionKind::Wipe,
+                None,
+                format!(
+                    "text shrank from {} to {} bytes",
+                    previous.bytes, seen.bytes
+                ),
+            ))
+        } else {
+            // `previous` differs from `seen`, so a match further back always
+            // has other versions in between.
+            versions
+                .iter()
+                .rev()
+                .skip(1)
+                .find(|old| old.hash == seen.hash)
+                .filter(|old| now.saturating_sub(old.at) >= self.config.min_revert_gap_ms)
+                .map(|old| {
+                    event(
+                        RegressionKind::Reversion,
+                        Some(old.at),
+                        format!(
+                            "text reverted to a version first seen {} minutes earlier",
+                            now.saturating_sub(old.at) / 60_000
+                        ),
+                    )
+                })
+        };
+
+        // Keep the first sighting of a text: reversion age counts from when
+        // a version first appeared.
+        if let Some(pos) = versions.iter().position(|old| old.hash == seen.hash) {
+            let first = versions.remove(pos).expect("position is in range");
+            versions.push_back(Seen {
+                at: first.at,
+                ..seen
+            });
+        } else {
+            versions.push_back(seen);
+        }
+        while versions.len() > HISTORY_LEN {
+            versions.pop_front();
+        }
+        regression
+    }
+
+    /// Keep `event` for `events()`.
+    pub fn record(&self, event: RegressionEvent) {
+        let mut events = self.events.lock().unwrap_or_else(|e| e.into_inner());
+        events.push_back(event);
+        while events.len() > MAX_EVENTS {
+            events.pop_front();
+        }
+    }
+
+    /// Recorded events, newest first.
+    pub fn events(&self) -> Vec<RegressionEvent> {
+        let events = self.events.lock().unwrap_or_else(|e| e.into_inner());
+        events.iter().rev().cloned().collect()
+    }
+
+    pub fn event(&self, id: &str) -> Option<RegressionEvent> {
+        let events = self.events.lock().unwrap_or_else(|e| e.into_inner());
+        events.iter().find(|event| event.id == id).cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MINUTE: u64 = 60_000;
+
+    fn detector() -> RegressionDetector {
+        RegressionDetector::new(RegressionConfig {
+            min_revert_gap_ms: 60 * MINUTE,
+            snapshots: false,
+        })
+    }
+
+    fn kind(event: Option<RegressionEvent>) -> Option<RegressionKind> {
+        event.map(|e| e.kind)
+    }
+
+    #[test]
+    fn ordinary_edits_are_not_flagged() {
+        let d = detector();
+        assert_eq!(d.observe("doc", "# Notes\n", 0), None);
+        assert_eq!(d.observe("doc", "# Notes\nmore\n", MINUTE), None);
+        assert_eq!(d.observe("doc", "# Notes\nmore\n", 2 * MINUTE), None);
+        assert_eq!(d.observe("doc", "# Notes\nedited\n", 3 * MINUTE), None);
+    }
+
+    #[test]
+    fn flags_near_total_deletion() {
+        let d = detector();
+        let long = "a line of real content\n".repeat(10);
+        d.observe("doc", &long, 0);
+        let event = d.observe("doc", "_", MINUTE).expect("wipe");
+        assert_eq!(event.kind, RegressionKind::Wipe);
+        assert_eq!(event.previous_bytes, long.len());
+        assert_eq!(event.current_bytes, 1);
+
+        // Small notes are not worth flagging.
+        d.observe("short", "tiny", 0);
+        assert_eq!(d.observe("short", "", MINUTE), None);
+        // Halving a note is editing.
+        d.observe("half", &long, 0);
+        assert_eq!(d.observe("half", &long[..long.len() / 2], MINUTE), None);
+    }
+
+    #[test]
+    fn flags_reversion_to_an_old_version() {
+        let d = detector();
+        d.observe("doc", "v1", 0);
+        d.observe("doc", "v2", 30 * MINUTE);
+        d.observe("doc", "v3", 90 * MINUTE);
+        let event = d.observe("doc", "v1", 120 * MINUTE).expect("reversion");
+        assert_eq!(event.kind, RegressionKind::Reversion);
+        assert_eq!(event.reverted_to_at, Some(0));
+    }
+
+    #[test]
+    fn quick_undo_is_not_a_reversion() {
+        let d = detector();
+        d.observe("doc", "v1", 0);
+        d.observe("doc", "v2", 90 * MINUTE);
+        assert_eq!(kind(d.observe("doc", "v3", 100 * MINUTE)), None);
+        // Back to v2, first seen 20 minutes ago.
+        assert_eq!(kind(d.observe("doc", "v2", 110 * MINUTE)), None);
+        assert_eq!(kind(d.observe("doc", "v3", 120 * MINUTE)), None);
+        // v2's age counts from its first sighting (90), not its latest (110).
+        assert_eq!(
+            kind(d.observe("doc", "v2", 160 * MINUTE)),
+            Some(RegressionKind::Reversion)
+        );
+    }
+
+    #[test]
+    fn records_events_newest_first() {
+        let d = detector();
+        let long = "x".repeat(100);
+        for doc in ["a", "b"] {
+            d.observe(doc, &long, 0);
+            let event = d.observe(doc, "", MINUTE).unwrap();
+            d.record(event);
+        }
+        let events = d.events();
+        assert_eq!(
+            events.iter().map(|e| e.doc_id.as_str()).collect::<Vec<_>>(),
+            vec!["b", "a"]
+        );
+        assert_eq!(d.event(&events[1].id).unwrap().doc_id, "a");
+        assert!(d.event("reg_missing").is_none());
+    }
+}
@@ -41,6 +41,19 @@ static DELETION_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?s)\{--(.*?
 static SUBSTITUTION_RE: LazyLock<Regex> =
     LazyLock::new(|| Regex::new(r"(?s)\{~~(.*?)~>(.*?)~~\}").unwrap());
 
+/// Every kind of markup a suggest-only connection may write, in one
+/// alternation so overlapping delimiters resolve left to right.
+static ANY_MARKUP_RE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(concat!(
+        r"(?s)",
+        r"\{\+\+(?P<add>.*?)\+\+\}",
+        r"|\{--(?P<del>.*?)--\}",
+        r"|\{~~(?P<old>.*?)~>(?P<new>.*?)~~\}",
+        r"|\{>>(?P<comment>.*?)<<\}",
+    ))
+    .unwrap()
+});
+
 /// Budget for context extraction. Newlines cost more to keep context compact.
 const CONTEXT_BUDGET: usize = 200;
 const NEWLINE_COST: usize = 50;
@@ -141,6 +154,25 @@ fn extract_metadata(raw: &str) -> (Option<String>, Option<u64>, &str) {
     (None, None, raw)
 }
 
+/// The text as it reads with every suggestion rejected: additions and
+/// comments dropped, deletions kept, substitutions back to their old text.
+///
+/// A suggest-only edit leaves this unchanged — it can only wrap, add or
+/// rewrite markup, never touch the text around it. Anything that is not
+/// well-formed markup (an unclosed `{++`, a stray `~>`) stays in as plain
+/// text, so writing it changes the result too.
+pub fn reject_all(text: &str) -> String {
+    ANY_MARKUP_RE
+        .replace_all(text, |caps: &regex::Captures| {
+            let kept = caps.name("del").or_else(|| caps.name("old"));
+            match kept {
+                Some(m) => extract_metadata(m.as_str()).2.to_string(),
+                None => String::new(),
+            }
+        })
+        .into_owned()
+}
+
 pub fn scan_suggestions(text: &str) -> Vec<Suggestion> {
     let mut suggestions = Vec::new();
 
@@ -300,6 +332,22 @@ mod tests {
         assert!(results.is_empty());
     }
 
+    #[test]
+    fn test_reject_all_restores_base_text() {
+        let text = concat!(
+            r#"Say {~~{"author":"AI","timestamp":2000}@@hello~>goodbye~~} "#,
+            r#"{--{"author":"AI","timestamp":1000}@@old --}"#,
+            "world{++ again++}{>>nice<<}."
+        );
+        assert_eq!(reject_all(text), "Say hello old world.");
+    }
+
+    #[test]
+    fn test_reject_all_keeps_malformed_markup() {
+        assert_eq!(reject_all("a {++b c"), "a {++b c");
+        assert_eq!(reject_all("a {~~b~~} c"), "a {~~b~~} c");
+    }
+
     #[test]
     fn test_context_truncation() {
         // Context should be truncated to ~200 chars
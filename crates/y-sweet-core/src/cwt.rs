@@ -1000,6 +1000,7 @@ pub fn permission_to_scope(permission: &Permission) -> String {
         Permission::Doc(doc_perm) => {
             let auth_str = match doc_perm.authorization {
                 Authorization::ReadOnly => "r",
+                Authorization::Suggest => "s",
                 Authorization::Full => "rw",
             };
             format!("doc:{}:{}", doc_perm.doc_id, auth_str)
@@ -1007,6 +1008,7 @@ pub fn permission_to_scope(permission: &Permission) -> String {
         Permission::File(file_perm) => {
             let auth_str = match file_perm.authorization {
                 Authorization::ReadOnly => "r",
+                Authorization::Suggest => "s",
                 Authorization::Full => "rw",
             };
             format!(
@@ -1017,6 +1019,7 @@ pub fn permission_to_scope(permission: &Permission) -> String {
         Permission::Prefix(prefix_perm) => {
             let auth_str = match prefix_perm.authorization {
                 Authorization::ReadOnly => "r",
+                Authorization::Suggest => "s",
                 Authorization::Full => "rw",
             };
             format!("prefix:{}:{}", prefix_perm.prefix, auth_str)
@@ -1035,6 +1038,7 @@ pub fn scope_to_permission(scope: &str) -> Result<Permission, CwtError> {
         ["doc", doc_id, auth_str] => {
             let authorization = match *auth_str {
                 "r" => Authorization::ReadOnly,
+                "s" => Authorization::Suggest,
                 "rw" => Authorization::Full,
                 _ => return Err(CwtError::InvalidClaims),
             };
@@ -1047,6 +1051,7 @@ pub fn scope_to_permission(scope: &str) -> Result<Permission, CwtError> {
         ["file", file_hash, doc_id, auth_str] => {
             let authorization = match *auth_str {
                 "r" => Authorization::ReadOnly,
+                "s" => Authorization::Suggest,
                 "rw" => Authorization::Full,
                 _ => return Err(CwtError::InvalidClaims),
             };
@@ -1062,6 +1067,7 @@ pub fn scope_to_permission(scope: &str) -> Result<Permission, CwtError> {
         ["prefix", prefix, auth_str] => {
             let authorization = match *auth_str {
                 "r" => Authorization::ReadOnly,
+                "s" => Authorization::Suggest,
                 "rw" => Authorization::Full,
                 _ => return Err(CwtError::InvalidClaims),
             };
@@ -1,11 +1,13 @@
 use crate::api_types::Authorization;
+use crate::filemeta_guard::FilemetaGuard;
+use crate::suggest_guard::SuggestGuard;
 use crate::sync::{
     self, awareness::Awareness, DefaultProtocol, EventMessage, Message, Protocol, SyncMessage,
     MSG_SYNC, MSG_SYNC_UPDATE,
 };
 use crate::sync_kv::SyncKv;
 use std::collections::HashSet;
-use std::sync::{Arc, OnceLock, RwLock};
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
 use yrs::{
     block::ClientID,
     encoding::write::Write,
@@ -60,6 +62,12 @@ pub struct DocConnection {
     /// Authenticated user identity from the connection token.
     /// When set, the server will register new client_ids under this user in the "users" map.
     user: Option<String>,
+
+    /// Checks client updates to folder docs' filemeta_v0 before they are applied.
+    filemeta_guard: Option<FilemetaGuard>,
+
+    /// Vets updates from suggest-only connections; None for other levels.
+    suggest_guard: Option<Mutex<SuggestGuard>>,
 }
 
 impl DocConnection {
@@ -208,6 +216,9 @@ impl DocConnection {
             expiration_time,
             sync_kv: None,
             user: None,
+            filemeta_guard: None,
+            suggest_guard: (authorization == Authorization::Suggest)
+                .then(|| Mutex::new(SuggestGuard::new())),
         }
     }
 
@@ -221,6 +232,53 @@ impl DocConnection {
         self.user = Some(user);
     }
 
+    /// Set the guard that vets this client's updates to filemeta_v0.
+    pub fn set_filemeta_guard(&mut self, guard: FilemetaGuard) {
+        self.filemeta_guard = Some(guard);
+    }
+
+    /// Run the filemeta guard, if set, on an update about to be applied.
+    fn guard_update(&self, awareness: &Awareness, update: &[u8]) -> Result<(), sync::Error> {
+        let Some(guard) = &self.filemeta_guard else {
+            return Ok(());
+        };
+        guard
+            .admit(awareness.doc(), update, self.user.as_deref())
+            .map_err(|violations| sync::Error::PermissionDenied {
+                reason: format!(
+                    "Update rejected by filemeta guard: {}",
+                    violations
+                        .iter()
+                        .map(|v| v.detail.as_str())
+                        .collect::<Vec<_>>()
+                        .join("; ")
+                ),
+            })
+    }
+
+    /// For suggest-only connections, refuse updates that do more than add or
+    /// change CriticMarkup in `contents`.
+    fn check_suggestion(&self, awareness: &Awareness, update: &[u8]) -> Result<(), sync::Error> {
+        let Some(guard) = &self.suggest_guard else {
+            return Ok(());
+        };
+        let mut guard = guard.lock().unwrap_or_else(|e| e.into_inner());
+        guard.check(awareness.doc(), update).map_err(|reason| {
+            tracing::info!(user = ?self.user, "Rejected update from suggest-only connection: {}", reason);
+            sync::Error::PermissionDenied {
+                reason: format!("Update rejected for suggest access: {}", reason),
+            }
+        })
+    }
+
+    /// Drop the suggest guard's shadow doc after a checked update failed to
+    /// reach the live doc.
+    fn discard_suggestion(&self) {
+        if let Some(guard) = &self.suggest_guard {
+            guard.lock().unwrap_or_else(|e| e.into_inner()).discard();
+        }
+    }
+
     /// Snapshot the current state vector's client_ids (for before/after comparison).
     fn snapshot_sv(&self, awareness: &Awareness) -> std::collections::HashSet<ClientID> {
         let txn = awareness.doc().transact();
@@ -363,7 +421,10 @@ impl DocConnection {
             });
         }
 
-        let can_write = matches!(self.authorization, Authorization::Full);
+        let can_write = matches!(
+            self.authorization,
+            Authorization::Full | Authorization::Suggest
+        );
         let a = &self.awareness;
         match msg {
             Message::Sync(msg) => match msg {
@@ -378,11 +439,18 @@ impl DocConnection {
 
                     if can_write {
                         let mut awareness = a.write().unwrap_or_else(|e| e.into_inner());
-                        let sv_before = self.snapshot_sv(&awareness);
-                        let result =
-                            protocol.handle_sync_step2(&mut awareness, Update::decode_v1(&update)?);
-                        if result.is_ok() {
-                            self.register_new_client_ids(&awareness, &sv_before);
+                        self.check_suggestion(&awareness, &update)?;
+                        let result = self.guard_update(&awareness, &update).and_then(|()| {
+                            let sv_before = self.snapshot_sv(&awareness);
+                            let result = protocol
+                                .handle_sync_step2(&mut awareness, Update::decode_v1(&update)?);
+                            if result.is_ok() {
+                                self.register_new_client_ids(&awareness, &sv_before);
+                            }
+                            result
+                        });
+                        if result.is_err() {
+                            self.discard_suggestion();
                         }
                         result
                     } else {
@@ -398,11 +466,18 @@ impl DocConnection {
 
                     if can_write {
                         let mut awareness = a.write().unwrap_or_else(|e| e.into_inner());
-                        let sv_before = self.snapshot_sv(&awareness);
-                        let result =
-                            protocol.handle_update(&mut awareness, Update::decode_v1(&update)?);
-                        if result.is_ok() {
-                            self.register_new_client_ids(&awareness, &sv_before);
+                        self.check_suggestion(&awareness, &update)?;
+                        let result = self.guard_update(&awareness, &update).and_then(|()| {
+                            let sv_before = self.snapshot_sv(&awareness);
+                            let result =
+                                protocol.handle_update(&mut awareness, Update::decode_v1(&update)?);
+                            if result.is_ok() {
+                                self.register_new_client_ids(&awareness, &sv_before);
+                            }
+                            result
+                        });
+                        if result.is_err() {
+                            self.discard_suggestion();
                         }
                         result
                     } else {
@@ -1035,4 +1110,107 @@ mod tests {
         };
         assert_eq!(len, 1);
     }
+
+    #[test]
+    fn test_filemeta_guard_rejects_corrupting_update() {
+        use crate::filemeta_guard::{FilemetaGuardConfig, GuardMode};
+        use yrs::WriteTxn;
+
+        let doc = yrs::Doc::new();
+        doc.get_or_insert_map("filemeta_v0");
+        let awareness = Arc::new(RwLock::new(Awareness::new(doc.clone())));
+        let mut connection = DocConnection::new(awareness, Authorization::Full, |_| {});
+        let alerts = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let alerts_clone = alerts.clone();
+        connection.set_filemeta_guard(FilemetaGuard::new(
+            FilemetaGuardConfig {
+                mode: GuardMode::Reject,
+                max_deletions: 50,
+            },
+            move |_| {
+                alerts_clone.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            },
+        ));
+
+        let client_update = |path: &str| {
+            let client = yrs::Doc::new();
+            let sv = client.transact().state_vector();
+            {
+                let mut txn = client.transact_mut();
+                let filemeta = txn.get_or_insert_map("filemeta_v0");
+                filemeta.insert(&mut txn, path, yrs::Any::String("not-a-map".into()));
+            }
+            client.transact().encode_state_as_update_v1(&sv)
+        };
+
+        let result = connection.handle_msg(
+            &DefaultProtocol,
+            Message::Sync(SyncMessage::Update(client_update("//bad.md"))),
+        );
+        if let Err(sync::Error::PermissionDenied { reason }) = result {
+            assert!(reason.contains("//bad.md is not normalized"), "{}", reason);
+        } else {
+            panic!("Expected PermissionDenied error from the filemeta guard");
+        }
+        assert_eq!(alerts.load(std::sync::atomic::Ordering::SeqCst), 1);
+        {
+            let txn = doc.transact();
+            let filemeta = txn.get_map("filemeta_v0").unwrap();
+            assert!(filemeta.get(&txn, "//bad.md").is_none());
+        }
+
+        let result = connection.handle_msg(
+            &DefaultProtocol,
+            Message::Sync(SyncMessage::Update(client_update("/good.md"))),
+        );
+        assert!(result.is_ok());
+        assert_eq!(alerts.load(std::sync::atomic::Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_suggest_connection_only_applies_critic_markup() {
+        use yrs::{GetString, Text, WriteTxn};
+
+        let doc = yrs::Doc::new();
+        {
+            let mut txn = doc.transact_mut();
+            let contents = txn.get_or_insert_text("contents");
+            contents.insert(&mut txn, 0, "Hello world");
+        }
+        let awareness = Arc::new(RwLock::new(Awareness::new(doc.clone())));
+        let connection = DocConnection::new(awareness, Authorization::Suggest, |_| {});
+
+        let client_update = |chunk: &str| {
+            let client = yrs::Doc::new();
+            let state = doc
+                .transact()
+                .encode_state_as_update_v1(&Default::default());
+            client
+                .transact_mut()
+                .apply_update(Update::decode_v1(&state).unwrap());
+            let sv = client.transact().state_vector();
+            {
+                let mut txn = client.transact_mut();
+                let contents = txn.get_or_insert_text("contents");
+                contents.insert(&mut txn, 5, chunk);
+            }
+            client.transact().encode_state_as_update_v1(&sv)
+        };
+
+        let result = connection.handle_msg(
+            &DefaultProtocol,
+            Message::Sync(SyncMessage::Update(client_update(" there"))),
+        );
+        assert!(matches!(result, Err(sync::Error::PermissionDenied { .. })));
+
+        let result = connection.handle_msg(
+            &DefaultProtocol,
+            Message::Sync(SyncMessage::Update(client_update("{++ there++}"))),
+        );
+        assert!(result.is_ok());
+
+        let txn = doc.transact();
+        let contents = txn.get_text("contents").unwrap();
+        assert_eq!(contents.get_string(&txn), "Hello{++ there++} world");
+    }
 }
@@ -0,0 +1,265 @@
+//! Dump every root type of a Y.Doc as JSON.
+//!
+//! Replaces the Node script behind `docs/ydoc-map-dumps.md`. Root types
+//! decoded from an update carry no type tag until something reads them, so
+//! the well-known names are read as the type the plugin gives them and any
+//! other root is read as a map, then as text, then as an array, whichever
+//! has content.
+
+use crate::doc_resolver::read_folder_name;
+use crate::doc_sync::doc_from_snapshot;
+use crate::store::Store;
+use anyhow::{Context, Result};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use yrs::{Array, Doc, GetString, Map, ReadTxn, Transact};
+
+/// Root maps written by the Relay plugin.
+pub const KNOWN_MAP_ROOTS: &[&str] = &[
+    "filemeta_v0",
+    "docs",
+    "backlinks_v0",
+    "folder_config",
+    "users",
+];
+
+/// Root texts written by the Relay plugin.
+pub const KNOWN_TEXT_ROOTS: &[&str] = &["contents"];
+
+/// Every root type of `doc`, keyed by root name.
+pub fn dump_doc(doc: &Doc) -> BTreeMap<String, Value> {
+    let txn = doc.transact();
+    let names: Vec<String> = txn.root_refs().map(|(name, _)| name.to_string()).collect();
+    names
+        .into_iter()
+        .map(|name| {
+            let value = dump_root(&txn, &name);
+            (name, value)
+        })
+        .collect()
+}
+
+/// Dump `doc_id` from its persisted `data.ysweet`, without loading it into
+/// a server. None when the store has no such doc.
+pub async fn dump_stored_doc(
+    store: &dyn Store,
+    doc_id: &str,
+) -> Result<Option<BTreeMap<String, Value>>> {
+    let Some(snapshot) = store.get(&format!("{}/data.ysweet", doc_id)).await? else {
+        return Ok(None);
+    };
+    let doc = doc_from_snapshot(&snapshot)
+        .with_context(|| format!("Failed to load {} from the store", doc_id))?;
+    Ok(Some(dump_doc(&doc)))
+}
+
+/// Doc ID of the stored folder doc whose `folder_config.name` is `name`.
+/// Reads every doc in the store, so it is meant for offline tooling.
+pub async fn find_stored_folder(store: &dyn Store, name: &str) -> Result<Option<String>> {
+    let mut doc_ids = store.list_doc_ids().await?;
+    doc_ids.sort();
+    for doc_id in doc_ids {
+        let Some(snapshot) = store.get(&format!("{}/data.ysweet", doc_id)).await? else {
+            continue;
+        };
+        let Ok(doc) = doc_from_snapshot(&snapshot) else {
+            continue;
+        };
+        let is_folder = {
+            let txn = doc.transact();
+            txn.get_map("filemeta_v0")
+                .is_some_and(|filemeta| filemeta.len(&txn) > 0)
+        };
+        if is_folder && read_folder_name(&doc, &doc_id) == name {
+            return Ok(Some(doc_id));
+        }
+    }
+    Ok(None)
+}
+
+fn dump_root<T: ReadTxn>(txn: &T, name: &str) -> Value {
+    if KNOWN_TEXT_ROOTS.contains(&name) {
+        return text_root(txn, name).unwrap_or(Value::Null);
+    }
+    if KNOWN_MAP_ROOTS.contains(&name) {
+        return map_root(txn, name).unwrap_or_else(|| Value::Object(Default::default()));
+    }
+    map_root(txn, name)
+        .filter(|value| value.as_object().is_some_and(|map| !map.is_empty()))
+        .or_else(|| text_root(txn, name).filter(|value| value.as_str() != Some("")))
+        .or_else(|| array_root(txn, name))
+        .unwrap_or(Value::Null)
+}
+
+fn map_root<T: ReadTxn>(txn: &T, name: &str) -> Option<Value> {
+    let map = txn.get_map(name)?;
+    Some(any_to_value(&map.to_json(txn)))
+}
+
+fn text_root<T: ReadTxn>(txn: &T, name: &str) -> Option<Value> {
+    let text = txn.get_text(name)?;
+    Some(Value::String(text.get_string(txn)))
+}
+
+fn array_root<T: ReadTxn>(txn: &T, name: &str) -> Option<Value> {
+    let array = txn.get_array(name)?;
+    Some(any_to_value(&array.to_json(txn)))
+}
+
+fn any_to_value(any: &yrs::Any) -> Value {
+    serde_json::to_value(any).unwrap_or(Value::Null)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::doc_sync::DocWithSyncKv;
+    use async_trait::async_trait;
+    use dashmap::DashMap;
+    use serde_json::json;
+    use std::sync::Arc;
+    use yrs::updates::decoder::Decode;
+    use yrs::{Any, MapPrelim, Text, WriteTxn};
+
+    fn folder_doc() -> Doc {
+        let doc = Doc::new();
+        {
+            let mut txn = doc.transact_mut();
+            let filemeta = txn.get_or_insert_map("filemeta_v0");
+            filemeta.insert(
+                &mut txn,
+                "/Notes.md",
+                MapPrelim::from([
+                    ("id".to_string(), Any::from("c1")),
+                    ("type".to_string(), Any::from("markdown")),
+                    ("version".to_string(), Any::from(0.0)),
+                ]),
+            );
+            let config = txn.get_or_insert_map("folder_config");
+            config.insert(&mut txn, "name", "Lens");
+            let backlinks = txn.get_or_insert_map("backlinks_v0");
+            backlinks.insert(&mut txn, "c1", Any::from(vec![Any::from("c2")]));
+        }
+        doc
+    }
+
+    /// Round-trip through an update so roots are untyped, as they are when a
+    /// doc is loaded from the store.
+    fn reloaded(doc: &Doc) -> Doc {
+        let update = doc
+            .transact()
+            .encode_state_as_update_v1(&yrs::StateVector::default());
+        let copy = Doc::new();
+        copy.transact_mut()
+            .apply_update(yrs::Update::decode_v1(&update).unwrap());
+        copy
+    }
+
+    #[test]
+    fn dumps_folder_doc_maps() {
+        let dump = dump_doc(&reloaded(&folder_doc()));
+        assert_eq!(
+            dump.keys().collect::<Vec<_>>(),
+            vec!["backlinks_v0", "filemeta_v0", "folder_config"]
+        );
+        assert_eq!(
+            dump["filemeta_v0"],
+            json!({ "/Notes.md": { "id": "c1", "type": "markdown", "version": 0.0 } })
+        );
+        assert_eq!(dump["folder_config"], json!({ "name": "Lens" }));
+        assert_eq!(dump["backlinks_v0"], json!({ "c1": ["c2"] }));
+    }
+
+    #[test]
+    fn dumps_content_text_and_unknown_roots() {
+        let doc = Doc::new();
+        {
+            let mut txn = doc.transact_mut();
+            let contents = txn.get_or_insert_text("contents");
+            contents.insert(&mut txn, 0, "# Hello\n");
+            let extra = txn.get_or_insert_map("extra");
+            extra.insert(&mut txn, "k", "v");
+            let notes = txn.get_or_insert_text("scratch");
+            notes.insert(&mut txn, 0, "note");
+        }
+        let dump = dump_doc(&reloaded(&doc));
+        assert_eq!(dump["contents"], json!("# Hello\n"));
+        assert_eq!(dump["extra"], json!({ "k": "v" }));
+        assert_eq!(dump["scratch"], json!("note"));
+    }
+
+    #[test]
+    fn empty_doc_has_no_roots() {
+        assert!(dump_doc(&Doc::new()).is_empty());
+    }
+
+    #[tokio::test]
+    async fn dumps_and_finds_folders_in_a_store() {
+        let store = MemoryStore::default();
+        let folder = DocWithSyncKv::new(
+            "relay-folder",
+            Some(Arc::new(Box::new(store.clone()))),
+            || (),
+            None,
+        )
+        .await
+        .unwrap();
+        {
+            let awareness = folder.awareness();
+            let guard = awareness.write().unwrap();
+            let mut txn = guard.doc.transact_mut();
+            txn.get_or_insert_map("filemeta_v0")
+                .insert(&mut txn, "/Notes.md", "c1");
+            txn.get_or_insert_map("folder_config")
+                .insert(&mut txn, "name", "Lens");
+        }
+        folder.sync_kv().persist().await.unwrap();
+
+        let dump = dump_stored_doc(&store, "relay-folder")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(dump["folder_config"], json!({ "name": "Lens" }));
+        assert!(dump_stored_doc(&store, "missing").await.unwrap().is_none());
+
+        assert_eq!(
+            find_stored_folder(&store, "Lens").await.unwrap().as_deref(),
+            Some("relay-folder")
+        );
+        assert_eq!(find_stored_folder(&store, "Other").await.unwrap(), None);
+    }
+
+    #[derive(Default, Clone)]
+    struct MemoryStore {
+        data: Arc<DashMap<String, Vec<u8>>>,
+    }
+
+    #[cfg_attr(not(feature = "single-threaded"), async_trait)]
+    #[cfg_attr(feature = "single-threaded", async_trait(?Send))]
+    impl Store for MemoryStore {
+        async fn init(&self) -> crate::store::Result<()> {
+            Ok(())
+        }
+        async fn get(&self, key: &str) -> crate::store::Result<Option<Vec<u8>>> {
+            Ok(self.data.get(key).map(|v| v.clone()))
+        }
+        async fn set(&self, key: &str, value: Vec<u8>) -> crate::store::Result<()> {
+            self.data.insert(key.to_owned(), value);
+            Ok(())
+        }
+        async fn remove(&self, key: &str) -> crate::store::Result<()> {
+            self.data.remove(key);
+            Ok(())
+        }
+        async fn exists(&self, key: &str) -> crate::store::Result<bool> {
+            Ok(self.data.contains_key(key))
+        }
+        async fn list_doc_ids(&self) -> crate::store::Result<Vec<String>> {
+            Ok(self
+                .data
+                .iter()
+                .filter_map(|entry| entry.key().strip_suffix("/data.ysweet").map(str::to_string))
+                .collect())
+        }
+    }
+}
@@ -106,10 +106,14 @@ impl DocWithSyncKv {
                     let sv = txn.state_vector().encode_v1();
 
                     // Create the event payload with business data, metadata, update, and state vector
-                    let event = DocumentUpdatedEvent::new(doc_key.clone())
+                    let mut event = DocumentUpdatedEvent::new(doc_key.clone())
                         .with_metadata(&sync_kv)
                         .with_update(event.update.to_vec())
                         .with_state_vector(sv);
+                    // Writes made for a specific user say so in their origin.
+                    event.user = txn
+                        .origin()
+                        .and_then(|o| crate::event::user_from_origin(o.as_ref()));
 
                     // Callback handles envelope creation and dispatch
                     callback(event, suppress_derived_index);
@@ -304,6 +308,20 @@ impl DocWithSyncKv {
     }
 }
 
+/// Rebuild a standalone Y.Doc from a `data.ysweet` snapshot, e.g. an older
+/// S3 version of a doc. The result is detached from any store.
+pub fn doc_from_snapshot(snapshot: &[u8]) -> Result<Doc> {
+    let sync_kv = SyncKv::from_snapshot(snapshot).context("Failed to decode snapshot")?;
+    let doc = Doc::new();
+    {
+        let mut txn = doc.transact_mut();
+        sync_kv
+            .load_doc(DOC_NAME, &mut txn)
+            .map_err(|_| anyhow!("Failed to load doc"))?;
+    }
+    Ok(doc)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -345,6 +363,38 @@ mod tests {
         assert!(!store.data.is_empty());
     }
 
+    #[tokio::test]
+    async fn doc_from_snapshot_restores_persisted_text() {
+        let store = MemoryStore::default();
+        let dwskv = DocWithSyncKv::new(
+            "test_doc",
+            Some(Arc::new(Box::new(store.clone()))),
+            || (),
+            None,
+        )
+        .await
+        .unwrap();
+        {
+            let awareness = dwskv.awareness();
+            let guard = awareness.write().unwrap();
+            let mut txn = guard.doc.transact_mut();
+            txn.get_or_insert_text("contents")
+                .insert(&mut txn, 0, "# Notes\nfirst draft\n");
+        }
+        dwskv.sync_kv().persist().await.unwrap();
+
+        let snapshot = store.data.get("test_doc/data.ysweet").unwrap().clone();
+        let doc = doc_from_snapshot(&snapshot).unwrap();
+        let txn = doc.transact();
+        let text = txn.get_text("contents").unwrap();
+        assert_eq!(
+            yrs::GetString::get_string(&text, &txn),
+            "# Notes\nfirst draft\n"
+        );
+
+        assert!(doc_from_snapshot(b"not a snapshot").is_err());
+    }
+
     #[derive(Default, Clone)]
     struct MemoryStore {
         data: Arc<DashMap<String, Vec<u8>>>,
@@ -0,0 +1,397 @@
+//! Pluggable text embeddings for hybrid and semantic search.
+//!
+//! `SearchIndex` stores one vector per document alongside its tantivy entry
+//! when an `EmbeddingProvider` is attached, and blends cosine similarity with
+//! BM25 in hybrid mode. Vectors are persisted with the index, so a document
+//! is embedded again only when its content changes, not on every start.
+//!
+//! `HttpEmbedder` asks a model server for embeddings: any endpoint speaking
+//! the OpenAI `/v1/embeddings` format, e.g. a self-hosted Ollama or
+//! text-embeddings-inference. It is the provider that searches by meaning.
+//!
+//! `HashingEmbedder` is a feature-hashing embedder over words and character
+//! trigrams: deterministic, dependency-free and fast, which makes it the stub
+//! used in tests. It matches shared words and word fragments, not meaning, so
+//! it is a second lexical ranker rather than semantic search.
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde_json::{json, Value};
+use std::sync::{mpsc, Arc};
+use std::time::Duration;
+
+/// Produces fixed-size, L2-normalized embedding vectors for text.
+///
+/// Implementations must be deterministic for a given input (the index never
+/// re-embeds unchanged documents) and cheap enough to call from the search
+/// worker's blocking context.
+pub trait EmbeddingProvider: Send + Sync {
+    /// Short identifier, used in logs and `RELAY_SEARCH_EMBEDDING`.
+    fn name(&self) -> &str;
+
+    /// Length of every vector returned by `embed`.
+    fn dimensions(&self) -> usize;
+
+    /// Embed `text`. The result has length `dimensions()` and unit norm
+    /// (or is all zeros for text with no embeddable content).
+    fn embed(&self, text: &str) -> Result<Vec<f32>>;
+
+    /// Identifies the vectors this provider produces. Persisted vectors
+    /// from a provider with a different id are ignored and recomputed.
+    fn model_id(&self) -> String {
+        format!("{}/{}", self.name(), self.dimensions())
+    }
+}
+
+/// Default dimensionality of `HashingEmbedder` vectors.
+pub const DEFAULT_HASHING_DIMENSIONS: usize = 384;
+
+/// Weight of a character trigram relative to a whole word. Trigrams let
+/// inflections ("energy" / "energetic") land near each other.
+const TRIGRAM_WEIGHT: f32 = 0.35;
+
+/// Feature-hashing embedder over lowercase words and character trigrams.
+#[derive(Debug, Clone)]
+pub struct HashingEmbedder {
+    dimensions: usize,
+}
+
+impl HashingEmbedder {
+    pub fn new(dimensions: usize) -> Self {
+        assert!(dimensions > 0, "embedding dimensions must be non-zero");
+        Self { dimensions }
+    }
+
+    fn add_feature(&self, vector: &mut [f32], feature: &str, weight: f32) {
+        let hash = fnv1a_64(feature.as_bytes());
+        let bucket = (hash % self.dimensions as u64) as usize;
+        // The top bit picks the sign so colliding features tend to cancel
+        // rather than pile up in one bucket.
+        let sign = if hash >> 63 == 0 { 1.0 } else { -1.0 };
+        vector[bucket] += sign * weight;
+    }
+}
+
+impl Default for HashingEmbedder {
+    fn default() -> Self {
+        Self::new(DEFAULT_HASHING_DIMENSIONS)
+    }
+}
+
+impl EmbeddingProvider for HashingEmbedder {
+    fn name(&self) -> &str {
+        "hashing"
+    }
+
+    fn dimensions(&self) -> usize {
+        self.dimensions
+    }
+
+    fn embed(&self, text: &str) -> Result<Vec<f32>> {
+        let mut vector = vec![0.0f32; self.dimensions];
+
+        for word in text
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|w| !w.is_empty())
+        {
+            let word = word.to_lowercase();
+            self.add_feature(&mut vector, &word, 1.0);
+
+            let padded: Vec<char> = format!("^{}$", word).chars().collect();
+            for window in padded.windows(3) {
+                let trigram: String = window.iter().collect();
+                self.add_feature(&mut vector, &trigram, TRIGRAM_WEIGHT);
+            }
+        }
+
+        normalize(&mut vector);
+        Ok(vector)
+    }
+}
+
+/// How long `HttpEmbedder` waits for one embedding.
+const HTTP_EMBEDDING_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A request to the `HttpEmbedder` thread and where to send its answer.
+type EmbeddingRequest = (String, mpsc::Sender<Result<Vec<f32>>>);
+
+/// Embeddings from a model server speaking the OpenAI `/v1/embeddings`
+/// format (`{"model", "input"}` in, `data[0].embedding` out).
+///
+/// `embed` is called from synchronous code, some of it on async runtime
+/// threads, so requests go to a dedicated thread with its own runtime and
+/// the caller blocks on the answer.
+pub struct HttpEmbedder {
+    model: String,
+    dimensions: usize,
+    requests: tokio::sync::mpsc::UnboundedSender<EmbeddingRequest>,
+}
+
+impl HttpEmbedder {
+    /// A provider posting to `url`. `api_key`, if any, is sent as a bearer
+    /// token.
+    pub fn new(url: &str, model: &str, dimensions: usize, api_key: Option<String>) -> Result<Self> {
+        if dimensions == 0 {
+            bail!("embedding dimensions must be non-zero");
+        }
+        let url = reqwest::Url::parse(url).context("invalid embedding URL")?;
+        let client = reqwest::Client::builder()
+            .timeout(HTTP_EMBEDDING_TIMEOUT)
+            .build()?;
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        let (requests, mut pending) = tokio::sync::mpsc::unbounded_channel::<EmbeddingRequest>();
+        let thread_model = model.to_string();
+        std::thread::Builder::new()
+            .name("http-embedder".into())
+            .spawn(move || {
+                runtime.block_on(async move {
+                    while let Some((text, reply)) = pending.recv().await {
+                        let result = request_embedding(
+                            &client,
+                            &url,
+                            &thread_model,
+                            api_key.as_deref(),
+                            &text,
+                            dimensions,
+                        )
+                        .await;
+                        let _ = reply.send(result);
+                    }
+                })
+            })?;
+        Ok(Self {
+            model: model.to_string(),
+            dimensions,
+            requests,
+        })
+    }
+
+    /// Configure from `RELAY_SEARCH_EMBEDDING_URL`,
+    /// `RELAY_SEARCH_EMBEDDING_MODEL` and `RELAY_SEARCH_EMBEDDING_DIMENSIONS`
+    /// (all required) and `RELAY_SEARCH_EMBEDDING_API_KEY` (optional).
+    pub fn from_env() -> Result<Self> {
+        let var = |name: &str| {
+            std::env::var(name)
+                .ok()
+                .filter(|value| !value.trim().is_empty())
+                .ok_or_else(|| anyhow!("{} is required for HTTP embeddings", name))
+        };
+        let url = var("RELAY_SEARCH_EMBEDDING_URL")?;
+        let model = var("RELAY_SEARCH_EMBEDDING_MODEL")?;
+        let dimensions = var("RELAY_SEARCH_EMBEDDING_DIMENSIONS")?;
+        let dimensions = dimensions.trim().parse().with_context(|| {
+            format!("invalid RELAY_SEARCH_EMBEDDING_DIMENSIONS '{}'", dimensions)
+        })?;
+        let api_key = var("RELAY_SEARCH_EMBEDDING_API_KEY").ok();
+        Self::new(url.trim(), model.trim(), dimensions, api_key)
+    }
+}
+
+impl EmbeddingProvider for HttpEmbedder {
+    fn name(&self) -> &str {
+        "http"
+    }
+
+    fn dimensions(&self) -> usize {
+        self.dimensions
+    }
+
+    fn embed(&self, text: &str) -> Result<Vec<f32>> {
+        let (reply, answer) = mpsc::channel();
+        self.requests
+            .send((text.to_string(), reply))
+            .map_err(|_| anyhow!("embedding thread has stopped"))?;
+        answer
+            .recv()
+            .map_err(|_| anyhow!("embedding thread has stopped"))?
+    }
+
+    fn model_id(&self) -> String {
+        format!("http/{}/{}", self.model, self.dimensions)
+    }
+}
+
+async fn request_embedding(
+    client: &reqwest::Client,
+    url: &reqwest::Url,
+    model: &str,
+    api_key: Option<&str>,
+    text: &str,
+    dimensions: usize,
+) -> Result<Vec<f32>> {
+    let mut request = client
+        .post(url.clone())
+        .json(&json!({ "model": model, "input": text }));
+    if let Some(api_key) = api_key {
+        request = request.bearer_auth(api_key);
+    }
+    let response: Value = request.send().await?.error_for_status()?.json().await?;
+    let mut vector = parse_embedding_response(&response)?;
+    if vector.len() != dimensions {
+        bail!(
+            "embedding model returned {} dimensions, expected {}",
+            vector.len(),
+            dimensions
+        );
+    }
+    normalize(&mut vector);
+    Ok(vector)
+}
+
+/// The first vector of an OpenAI-style embeddings response.
+fn parse_embedding_response(response: &Value) -> Result<Vec<f32>> {
+    response
+        .pointer("/data/0/embedding")
+        .and_then(Value::as_array)
+        .ok_or_else(|| anyhow!("embedding response has no data[0].embedding"))?
+        .iter()
+        .map(|x| {
+            x.as_f64()
+                .map(|x| x as f32)
+                .ok_or_else(|| anyhow!("embedding response holds a non-number"))
+        })
+        .collect()
+}
+
+/// Look up a provider by name, as configured via `RELAY_SEARCH_EMBEDDING`:
+/// `"http"` (configured by `HttpEmbedder::from_env`) or `"hashing"`.
+/// `"none"`, or no name at all, disables embeddings.
+pub fn provider_by_name(name: &str) -> Result<Option<Arc<dyn EmbeddingProvider>>> {
+    match name.trim().to_ascii_lowercase().as_str() {
+        "http" => Ok(Some(Arc::new(HttpEmbedder::from_env()?))),
+        "hashing" => Ok(Some(Arc::new(HashingEmbedder::default()))),
+        "" | "none" | "off" => Ok(None),
+        other => Err(anyhow!(
+            "Unknown embedding provider '{}' (expected 'http', 'hashing' or 'none')",
+            other
+        )),
+    }
+}
+
+/// Cosine similarity of two vectors. Returns 0.0 for mismatched lengths or
+/// zero vectors.
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() {
+        return 0.0;
+    }
+    let mut dot = 0.0f32;
+    let mut norm_a = 0.0f32;
+    let mut norm_b = 0.0f32;
+    for (x, y) in a.iter().zip(b) {
+        dot += x * y;
+        norm_a += x * x;
+        norm_b += y * y;
+    }
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return 0.0;
+    }
+    dot / (norm_a.sqrt() * norm_b.sqrt())
+}
+
+fn normalize(vector: &mut [f32]) {
+    let norm = vector.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        for x in vector.iter_mut() {
+            *x /= norm;
+        }
+    }
+}
+
+/// 64-bit FNV-1a. Used instead of `DefaultHasher` so vectors are stable
+/// across Rust releases.
+fn fnv1a_64(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in bytes {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hashing_embedder_is_deterministic() {
+        let embedder = HashingEmbedder::default();
+        let a = embedder
+            .embed("Plants convert sunlight into energy")
+            .unwrap();
+        let b = embedder
+            .embed("Plants convert sunlight into energy")
+            .unwrap();
+        assert_eq!(a, b);
+        assert_eq!(a.len(), DEFAULT_HASHING_DIMENSIONS);
+    }
+
+    #[test]
+    fn hashing_embedder_returns_unit_vectors() {
+        let embedder = HashingEmbedder::new(64);
+        let v = embedder.embed("photosynthesis in leaves").unwrap();
+        let norm = v.iter().map(|x| x * x).sum::<f32>().sqrt();
+        assert!((norm - 1.0).abs() < 1e-5, "norm was {}", norm);
+    }
+
+    #[test]
+    fn hashing_embedder_empty_text_is_zero_vector() {
+        let embedder = HashingEmbedder::new(16);
+        let v = embedder.embed("  -- ").unwrap();
+        assert!(v.iter().all(|x| *x == 0.0));
+    }
+
+    #[test]
+    fn related_text_scores_higher_than_unrelated() {
+        // Prevents: a broken hash/normalize step making every pair look
+        // equally similar, which would turn hybrid search into noise.
+        let embedder = HashingEmbedder::default();
+        let query = embedder.embed("how plants make energy").unwrap();
+        let related = embedder
+            .embed("Plants make their own energy from sunlight")
+            .unwrap();
+        let unrelated = embedder
+            .embed("The French revolution began in 1789")
+            .unwrap();
+        assert!(cosine_similarity(&query, &related) > cosine_similarity(&query, &unrelated));
+    }
+
+    #[test]
+    fn cosine_similarity_handles_degenerate_inputs() {
+        assert_eq!(cosine_similarity(&[1.0, 0.0], &[1.0]), 0.0);
+        assert_eq!(cosine_similarity(&[0.0, 0.0], &[1.0, 0.0]), 0.0);
+        assert!((cosine_similarity(&[1.0, 0.0], &[1.0, 0.0]) - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn parses_openai_style_responses() {
+        let response = json!({"data": [{"embedding": [0.5, -1, 2.25]}], "model": "m"});
+        assert_eq!(
+            parse_embedding_response(&response).unwrap(),
+            vec![0.5, -1.0, 2.25]
+        );
+        assert!(parse_embedding_response(&json!({"data": []})).is_err());
+        assert!(parse_embedding_response(&json!({"data": [{"embedding": ["x"]}]})).is_err());
+    }
+
+    #[test]
+    fn http_embedder_reports_an_unreachable_server() {
+        // Port 9 (discard) is closed on test machines; the call must fail
+        // rather than hang or panic inside the caller's runtime.
+        let embedder = HttpEmbedder::new("http://127.0.0.1:9/v1/embeddings", "m", 8, None).unwrap();
+        assert!(embedder.embed("text").is_err());
+        assert_eq!(embedder.model_id(), "http/m/8");
+        assert!(HttpEmbedder::new("not a url", "m", 8, None).is_err());
+    }
+
+    #[test]
+    fn provider_by_name_resolves_builtins() {
+        assert_eq!(
+            provider_by_name("hashing").unwrap().unwrap().name(),
+            "hashing"
+        );
+        assert!(provider_by_name("").unwrap().is_none());
+        assert!(provider_by_name("none").unwrap().is_none());
+        assert!(provider_by_name("openai").is_err());
+    }
+}
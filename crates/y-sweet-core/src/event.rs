@@ -140,6 +140,27 @@ impl DocumentUpdatedEvent {
     }
 }
 
+/// Prefix of a transaction origin naming the user a server-side write was
+/// made for (e.g. an MCP edit under a share token that identifies its user).
+/// The update event reports the rest of the origin as its `user`.
+pub const USER_ORIGIN_PREFIX: &str = "user:";
+
+/// Transaction origin for a write made on behalf of `user`.
+pub fn user_origin(user: &str) -> String {
+    format!("{}{}", USER_ORIGIN_PREFIX, user)
+}
+
+/// The user a transaction origin names, if it came from `user_origin`.
+pub fn user_from_origin(origin: &[u8]) -> Option<String> {
+    origin
+        .strip_prefix(USER_ORIGIN_PREFIX.as_bytes())
+        .map(|user| String::from_utf8_lossy(user).into_owned())
+}
+
+/// Event type sent when a document is deleted through the HTTP API or MCP
+/// (`Server::delete_path`). Carries the deleted path in its metadata.
+pub const DOCUMENT_DELETED_EVENT_TYPE: &str = "document.deleted";
+
 /// The envelope contains only routing and transport metadata
 #[derive(Clone, Debug)]
 pub struct EventEnvelope {
@@ -163,6 +184,20 @@ impl EventEnvelope {
         }
     }
 
+    /// Create an envelope that carries a document event under another event
+    /// type (e.g. `filemeta.guard`), for notifications about a document
+    /// that are not plain updates.
+    pub fn new_with_event_type(
+        channel: String,
+        event_type: &str,
+        event: DocumentUpdatedEvent,
+    ) -> Self {
+        Self {
+            event_type: event_type.to_string(),
+            ..Self::new(channel, event)
+        }
+    }
+
     /// Create an envelope with explicit timestamp (for testing)
     pub fn new_with_timestamp(
         channel: String,
@@ -1054,6 +1089,16 @@ mod tests {
         assert!(event.metadata.is_empty());
     }
 
+    #[test]
+    fn test_user_origin_roundtrip() {
+        let origin = user_origin("discord:1234");
+        assert_eq!(
+            user_from_origin(origin.as_bytes()).as_deref(),
+            Some("discord:1234")
+        );
+        assert_eq!(user_from_origin(b"link-indexer"), None);
+    }
+
     #[test]
     fn test_event_envelope_creation() {
         let doc_id = "test_doc_123".to_string();
@@ -0,0 +1,496 @@
+//! Write-time guard for folder docs' `filemeta_v0` maps.
+//!
+//! `relay fsck` cleans up corrupt folder metadata after the fact; this guard
+//! keeps a misbehaving client from writing it in the first place (see
+//! docs/filemeta-corruption-analysis.md). Before a client update to a folder
+//! doc is applied, `check_update` applies it to a scratch copy of the doc and
+//! compares the two maps. Only what the update changes counts: malformed
+//! paths it writes, paths it points at an ID another path already uses, and
+//! deleting more than `max_deletions` entries at once. `GuardMode` decides
+//! whether a violating update is applied anyway, rejected or quarantined.
+
+use crate::fsck::normalize_path;
+use crate::link_indexer::extract_id_from_filemeta_entry;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::str::FromStr;
+use std::sync::Arc;
+use yrs::{updates::decoder::Decode, Doc, Map, ReadTxn, StateVector, Transact, Update};
+
+/// Event type of the webhook/sync-protocol event sent when the guard trips.
+pub const FILEMETA_GUARD_EVENT_TYPE: &str = "filemeta.guard";
+
+/// Default for `RELAY_FILEMETA_GUARD_MAX_DELETIONS`.
+pub const DEFAULT_MAX_DELETIONS: usize = 50;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GuardMode {
+    /// Updates are not checked.
+    Off,
+    /// Violating updates are applied; the guard only records and reports them.
+    Alert,
+    /// Violating updates are dropped and the client gets a permission error.
+    Reject,
+    /// Like `Reject`, and the dropped update is kept in the store for review.
+    Quarantine,
+}
+
+impl GuardMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            GuardMode::Off => "off",
+            GuardMode::Alert => "alert",
+            GuardMode::Reject => "reject",
+            GuardMode::Quarantine => "quarantine",
+        }
+    }
+
+    /// Whether violating updates are kept out of the doc.
+    pub fn blocks(&self) -> bool {
+        matches!(self, GuardMode::Reject | GuardMode::Quarantine)
+    }
+}
+
+impl FromStr for GuardMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "off" => Ok(GuardMode::Off),
+            "alert" => Ok(GuardMode::Alert),
+            "reject" => Ok(GuardMode::Reject),
+            "quarantine" => Ok(GuardMode::Quarantine),
+            other => Err(format!(
+                "unknown filemeta guard mode '{}' (expected off, alert, reject or quarantine)",
+                other
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FilemetaGuardConfig {
+    pub mode: GuardMode,
+    /// Most entries one update may delete before it counts as a mass deletion.
+    /// Entries whose ID survives at another path (renames) are not deletions.
+    pub max_deletions: usize,
+}
+
+impl Default for FilemetaGuardConfig {
+    fn default() -> Self {
+        Self {
+            mode: GuardMode::Reject,
+            max_deletions: DEFAULT_MAX_DELETIONS,
+        }
+    }
+}
+
+impl FilemetaGuardConfig {
+    /// Read `RELAY_FILEMETA_GUARD` (`off`, `alert`, `reject`, `quarantine`)
+    /// and `RELAY_FILEMETA_GUARD_MAX_DELETIONS`. Unset or invalid values fall
+    /// back to the defaults; invalid ones are logged.
+    pub fn from_env() -> Self {
+        let mut config = Self::default();
+        if let Ok(mode) = std::env::var("RELAY_FILEMETA_GUARD") {
+            match mode.parse() {
+                Ok(mode) => config.mode = mode,
+                Err(e) => tracing::warn!("Ignoring RELAY_FILEMETA_GUARD: {}", e),
+            }
+        }
+        if let Ok(max) = std::env::var("RELAY_FILEMETA_GUARD_MAX_DELETIONS") {
+            match max.trim().parse() {
+                Ok(max) => config.max_deletions = max,
+                Err(e) => {
+                    tracing::warn!("Ignoring RELAY_FILEMETA_GUARD_MAX_DELETIONS={}: {}", max, e)
+                }
+            }
+        }
+        config
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GuardViolationKind {
+    /// The update writes a path that is not of the form `/a/b`.
+    InvalidPath,
+    /// The update writes a path whose ID another path already uses.
+    DuplicateId,
+    /// The update deletes more entries than `max_deletions`.
+    MassDeletion,
+}
+
+impl GuardViolationKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            GuardViolationKind::InvalidPath => "invalid_path",
+            GuardViolationKind::DuplicateId => "duplicate_id",
+            GuardViolationKind::MassDeletion => "mass_deletion",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct GuardViolation {
+    pub kind: GuardViolationKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    pub detail: String,
+}
+
+/// A tripped guard, as handed to the alert callback.
+#[derive(Debug, Clone)]
+pub struct GuardAlert {
+    /// The mode the guard was in, i.e. what happened to the update.
+    pub mode: GuardMode,
+    pub user: Option<String>,
+    pub violations: Vec<GuardViolation>,
+    /// The offending update, v1-encoded.
+    pub update: Vec<u8>,
+}
+
+pub type GuardAlertCallback = Arc<dyn Fn(GuardAlert) + Send + Sync>;
+
+/// The guard attached to a client connection: a config plus the callback
+/// that records, reports and (in quarantine mode) stores tripped updates.
+#[derive(Clone)]
+pub struct FilemetaGuard {
+    config: FilemetaGuardConfig,
+    on_alert: GuardAlertCallback,
+}
+
+impl FilemetaGuard {
+    pub fn new<F>(config: FilemetaGuardConfig, on_alert: F) -> Self
+    where
+        F: Fn(GuardAlert) + Send + Sync + 'static,
+    {
+        Self {
+            config,
+            on_alert: Arc::new(on_alert),
+        }
+    }
+
+    pub fn config(&self) -> &FilemetaGuardConfig {
+        &self.config
+    }
+
+    /// Decide whether `update` from `user` may be applied to `doc`. Calls the
+    /// alert callback when the update violates the guard; returns the
+    /// violations as the error when the mode blocks the update.
+    ///
+    /// Runs under the doc's awareness write lock, so the callback must not
+    /// block.
+    pub fn admit(
+        &self,
+        doc: &Doc,
+        update: &[u8],
+        user: Option<&str>,
+    ) -> Result<(), Vec<GuardViolation>> {
+        if self.config.mode == GuardMode::Off {
+            return Ok(());
+        }
+        let violations = check_update(doc, update, self.config.max_deletions);
+        if violations.is_empty() {
+            return Ok(());
+        }
+        (self.on_alert)(GuardAlert {
+            mode: self.config.mode,
+            user: user.map(str::to_string),
+            violations: violations.clone(),
+            update: update.to_vec(),
+        });
+        if self.config.mode.blocks() {
+            Err(violations)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+fn read_filemeta(txn: &impl ReadTxn) -> Option<BTreeMap<String, Option<String>>> {
+    let filemeta = txn.get_map("filemeta_v0")?;
+    Some(
+        filemeta
+            .iter(txn)
+            .map(|(path, value)| {
+                (
+                    path.to_string(),
+                    extract_id_from_filemeta_entry(&value, txn),
+                )
+            })
+            .collect(),
+    )
+}
+
+/// Violations that applying `update` (v1-encoded) to `doc` would introduce
+/// into its `filemeta_v0`. Problems already in the doc are left to fsck.
+/// Docs without a `filemeta_v0` map (content docs) are not checked, and
+/// neither are updates that fail to decode; applying those fails anyway.
+pub fn check_update(doc: &Doc, update: &[u8], max_deletions: usize) -> Vec<GuardViolation> {
+    let Ok(decoded) = Update::decode_v1(update) else {
+        return Vec::new();
+    };
+    let (before, state) = {
+        let txn = doc.transact();
+        let Some(before) = read_filemeta(&txn) else {
+            return Vec::new();
+        };
+        (
+            before,
+            txn.encode_state_as_update_v1(&StateVector::default()),
+        )
+    };
+
+    let scratch = Doc::new();
+    let after = {
+        let mut txn = scratch.transact_mut();
+        let Ok(state) = Update::decode_v1(&state) else {
+            return Vec::new();
+        };
+        txn.apply_update(state);
+        txn.apply_update(decoded);
+        read_filemeta(&txn).unwrap_or_default()
+    };
+
+    let mut paths_by_id: HashMap<&str, Vec<&str>> = HashMap::new();
+    for (path, id) in &after {
+        if let Some(id) = id {
+            paths_by_id
+                .entry(id.as_str())
+                .or_default()
+                .push(path.as_str());
+        }
+    }
+
+    let mut violations = Vec::new();
+    for (path, id) in &after {
+        if before.get(path) == Some(id) {
+            continue;
+        }
+        if normalize_path(path).as_deref() != Some(path.as_str()) {
+            let detail = match normalize_path(path) {
+                Some(normalized) => format!("{} is not normalized (expected {})", path, normalized),
+                None => format!("{} is not a valid path", path),
+            };
+            violations.push(GuardViolation {
+                kind: GuardViolationKind::InvalidPath,
+                path: Some(path.clone()),
+                id: id.clone(),
+                detail,
+            });
+            continue;
+        }
+        if let Some(id) = id {
+            let others: Vec<&str> = paths_by_id[id.as_str()]
+                .iter()
+                .copied()
+                .filter(|other| *other != path.as_str())
+                .collect();
+            if !others.is_empty() {
+                violations.push(GuardViolation {
+                    kind: GuardViolationKind::DuplicateId,
+                    path: Some(path.clone()),
+                    id: Some(id.clone()),
+                    detail: format!("{} reuses the ID of {}", path, others.join(", ")),
+                });
+            }
+        }
+    }
+
+    let deleted = before
+        .iter()
+        .filter(|(path, id)| {
+            !after.contains_key(*path)
+                && id
+                    .as_deref()
+                    .map_or(true, |id| !paths_by_id.contains_key(id))
+        })
+        .count();
+    if deleted > max_deletions {
+        violations.push(GuardViolation {
+            kind: GuardViolationKind::MassDeletion,
+            path: None,
+            id: None,
+            detail: format!(
+                "deletes {} of {} entries (limit {})",
+                deleted,
+                before.len(),
+                max_deletions
+            ),
+        });
+    }
+
+    violations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    use yrs::{Any, WriteTxn};
+
+    fn entry(id: &str, entry_type: &str) -> Any {
+        let mut map = HashMap::new();
+        map.insert("id".to_string(), Any::String(id.into()));
+        map.insert("type".to_string(), Any::String(entry_type.into()));
+        map.insert("version".to_string(), Any::Number(0.0));
+        Any::Map(map.into())
+    }
+
+    fn folder_doc(entries: &[(&str, &str)]) -> Doc {
+        let doc = Doc::new();
+        {
+            let mut txn = doc.transact_mut();
+            let filemeta = txn.get_or_insert_map("filemeta_v0");
+            for (path, id) in entries {
+                filemeta.insert(&mut txn, *path, entry(id, "markdown"));
+            }
+        }
+        doc
+    }
+
+    /// The update a client that is in sync with `server` produces by
+    /// running `edit` against its filemeta_v0.
+    fn client_update(
+        server: &Doc,
+        edit: impl FnOnce(&yrs::MapRef, &mut yrs::TransactionMut),
+    ) -> Vec<u8> {
+        let client = Doc::new();
+        let state = server
+            .transact()
+            .encode_state_as_update_v1(&StateVector::default());
+        client
+            .transact_mut()
+            .apply_update(Update::decode_v1(&state).unwrap());
+        let sv = client.transact().state_vector();
+        {
+            let mut txn = client.transact_mut();
+            let filemeta = txn.get_or_insert_map("filemeta_v0");
+            edit(&filemeta, &mut txn);
+        }
+        client.transact().encode_state_as_update_v1(&sv)
+    }
+
+    fn kinds(violations: &[GuardViolation]) -> Vec<(GuardViolationKind, Option<&str>)> {
+        violations
+            .iter()
+            .map(|v| (v.kind, v.path.as_deref()))
+            .collect()
+    }
+
+    #[test]
+    fn ordinary_edits_pass() {
+        let doc = folder_doc(&[("/a.md", "d1"), ("/b.md", "d2")]);
+        let update = client_update(&doc, |filemeta, txn| {
+            filemeta.insert(txn, "/c.md", entry("d3", "markdown"));
+            // A rename: the ID moves to a new path in one transaction.
+            filemeta.remove(txn, "/a.md");
+            filemeta.insert(txn, "/notes/a.md", entry("d1", "markdown"));
+            filemeta.remove(txn, "/b.md");
+        });
+        assert!(check_update(&doc, &update, 1).is_empty());
+    }
+
+    #[test]
+    fn flags_invalid_paths_and_duplicate_ids() {
+        // The shape of the 2026-02-23 corruption.
+        let doc = folder_doc(&[("/log.md", "d1"), ("/notes.md", "d2")]);
+        let update = client_update(&doc, |filemeta, txn| {
+            filemeta.insert(txn, "/", entry("f1", "folder"));
+            filemeta.insert(txn, "//log.md", entry("d1", "markdown"));
+            filemeta.insert(txn, "/copy.md", entry("d2", "markdown"));
+        });
+        assert_eq!(
+            kinds(&check_update(&doc, &update, 50)),
+            vec![
+                (GuardViolationKind::InvalidPath, Some("/")),
+                (GuardViolationKind::InvalidPath, Some("//log.md")),
+                (GuardViolationKind::DuplicateId, Some("/copy.md")),
+            ]
+        );
+    }
+
+    #[test]
+    fn existing_problems_are_not_blamed_on_the_update() {
+        let doc = folder_doc(&[("//log.md", "d1"), ("/log.md", "d1")]);
+        let update = client_update(&doc, |filemeta, txn| {
+            filemeta.insert(txn, "/new.md", entry("d2", "markdown"));
+        });
+        assert!(check_update(&doc, &update, 50).is_empty());
+    }
+
+    #[test]
+    fn flags_mass_deletions_over_the_limit() {
+        let doc = folder_doc(&[("/a.md", "d1"), ("/b.md", "d2"), ("/c.md", "d3")]);
+        let update = client_update(&doc, |filemeta, txn| {
+            filemeta.clear(txn);
+        });
+        let violations = check_update(&doc, &update, 2);
+        assert_eq!(
+            kinds(&violations),
+            vec![(GuardViolationKind::MassDeletion, None)]
+        );
+        assert_eq!(violations[0].detail, "deletes 3 of 3 entries (limit 2)");
+        assert!(check_update(&doc, &update, 3).is_empty());
+    }
+
+    #[test]
+    fn content_docs_are_not_checked() {
+        let doc = Doc::new();
+        doc.transact_mut().get_or_insert_text("contents");
+        let update = client_update(&doc, |filemeta, txn| {
+            filemeta.insert(txn, "//x.md", entry("d1", "markdown"));
+        });
+        assert!(check_update(&doc, &update, 50).is_empty());
+    }
+
+    #[test]
+    fn admit_follows_the_mode() {
+        let doc = folder_doc(&[("/a.md", "d1")]);
+        let update = client_update(&doc, |filemeta, txn| {
+            filemeta.insert(txn, "//a.md", entry("d1", "markdown"));
+        });
+
+        for (mode, admitted) in [
+            (GuardMode::Off, true),
+            (GuardMode::Alert, true),
+            (GuardMode::Reject, false),
+            (GuardMode::Quarantine, false),
+        ] {
+            let alerts = Arc::new(Mutex::new(Vec::new()));
+            let sink = alerts.clone();
+            let guard = FilemetaGuard::new(
+                FilemetaGuardConfig {
+                    mode,
+                    max_deletions: DEFAULT_MAX_DELETIONS,
+                },
+                move |alert| sink.lock().unwrap().push(alert),
+            );
+            assert_eq!(
+                guard.admit(&doc, &update, Some("alice")).is_ok(),
+                admitted,
+                "{:?}",
+                mode
+            );
+            let alerts = alerts.lock().unwrap();
+            if mode == GuardMode::Off {
+                assert!(alerts.is_empty());
+            } else {
+                assert_eq!(alerts.len(), 1);
+                assert_eq!(alerts[0].mode, mode);
+                assert_eq!(alerts[0].user.as_deref(), Some("alice"));
+                assert_eq!(alerts[0].update, update);
+            }
+        }
+    }
+
+    #[test]
+    fn parses_modes() {
+        assert_eq!("Quarantine".parse::<GuardMode>(), Ok(GuardMode::Quarantine));
+        assert_eq!(" off ".parse::<GuardMode>(), Ok(GuardMode::Off));
+        assert!("block".parse::<GuardMode>().is_err());
+    }
+}
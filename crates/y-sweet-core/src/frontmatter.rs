@@ -0,0 +1,162 @@
+//! Lightweight reader for the YAML frontmatter of markdown docs.
+//!
+//! Only the handful of keys the relay acts on are extracted (`tags`, `type`,
+//! `source_url`), with a line-based scan rather than a full YAML parser: the
+//! frontmatter is written by the article importer and by Obsidian, and both
+//! stick to plain scalars and simple lists.
+
+/// Structured fields read from a doc's frontmatter block.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Frontmatter {
+    /// `tags` entries, from either a block list or an inline `[a, b]` list.
+    pub tags: Vec<String>,
+    /// The `type` scalar (e.g. "article", "lens").
+    pub doc_type: Option<String>,
+    /// The first `source_url` value.
+    pub source_url: Option<String>,
+}
+
+impl Frontmatter {
+    /// Whether `tags` contains exactly `wanted`.
+    pub fn has_tag(&self, wanted: &str) -> bool {
+        self.tags.iter().any(|tag| tag == wanted)
+    }
+}
+
+/// Parse the frontmatter block: the lines between the first `---` fence and
+/// the next one. Text without a fence yields an empty `Frontmatter`.
+pub fn parse(text: &str) -> Frontmatter {
+    let mut frontmatter = Frontmatter::default();
+    let mut in_frontmatter = false;
+    let mut in_tags = false;
+
+    for line in text.lines() {
+        let trimmed_end = line.trim_end();
+        if trimmed_end == "---" {
+            if in_frontmatter {
+                break; // closing fence
+            }
+            in_frontmatter = true;
+            continue;
+        }
+        if !in_frontmatter {
+            continue;
+        }
+
+        if frontmatter.source_url.is_none() {
+            if let Some(rest) = trimmed_end.trim_start().strip_prefix("source_url:") {
+                frontmatter.source_url = scalar(rest);
+                continue;
+            }
+        }
+
+        let is_top_level = !line.starts_with(' ') && !line.starts_with('\t');
+        // "- item" directly under `tags:` is a list item even at column 0
+        let is_list_item = trimmed_end.trim_start().starts_with('-');
+        if is_top_level && !(in_tags && is_list_item) {
+            in_tags = false;
+            let Some((key, value)) = trimmed_end.split_once(':') else {
+                continue;
+            };
+            match key {
+                "tags" => {
+                    in_tags = true;
+                    frontmatter
+                        .tags
+                        .extend(value.split([',', '[', ']']).filter_map(unquote));
+                }
+                "type" => frontmatter.doc_type = scalar(value),
+                _ => {}
+            }
+            continue;
+        }
+
+        if in_tags {
+            if let Some(tag) = trimmed_end.trim().strip_prefix('-').and_then(unquote) {
+                frontmatter.tags.push(tag);
+            }
+        }
+    }
+
+    frontmatter
+}
+
+/// Byte offset where the content after the frontmatter block starts, or 0
+/// when the text does not open with a `---` fence.
+pub fn body_offset(text: &str) -> usize {
+    let mut offset = 0;
+    let mut in_frontmatter = false;
+    for line in text.split_inclusive('\n') {
+        let is_fence = line.trim_end() == "---";
+        offset += line.len();
+        if !in_frontmatter {
+            if !is_fence {
+                return 0;
+            }
+            in_frontmatter = true;
+        } else if is_fence {
+            return offset;
+        }
+    }
+    0
+}
+
+/// A scalar value with surrounding whitespace and quotes removed, or None
+/// when empty.
+fn scalar(value: &str) -> Option<String> {
+    let value = value.trim().trim_matches(['"', '\'']).trim();
+    (!value.is_empty()).then(|| value.to_string())
+}
+
+fn unquote(part: &str) -> Option<String> {
+    let part = part.trim().trim_matches(['"', '\'']);
+    (!part.is_empty()).then(|| part.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_block_list_tags_type_and_source_url() {
+        let fm = parse(
+            "---\ntype: article\nsource_url: \"https://example.com/a\"\ntags:\n  - lens\n  - \"article-stub\"\ntitle: X\n---\nBody\n",
+        );
+        assert_eq!(fm.tags, vec!["lens", "article-stub"]);
+        assert_eq!(fm.doc_type.as_deref(), Some("article"));
+        assert_eq!(fm.source_url.as_deref(), Some("https://example.com/a"));
+    }
+
+    #[test]
+    fn parses_inline_and_unindented_tag_lists() {
+        assert_eq!(
+            parse("---\ntags: [article-stub, 'validator-ignore']\n---\n").tags,
+            vec!["article-stub", "validator-ignore"]
+        );
+        // Obsidian writes list items at column 0
+        assert_eq!(
+            parse("---\ntags:\n- a\n- b\ntype: lens\n---\n").tags,
+            vec!["a", "b"]
+        );
+    }
+
+    #[test]
+    fn ignores_keys_after_closing_fence() {
+        let fm = parse("---\ntitle: X\n---\ntype: article\ntags: [x]\n");
+        assert_eq!(fm, Frontmatter::default());
+    }
+
+    #[test]
+    fn nested_tags_key_is_not_top_level() {
+        let fm = parse("---\nmeta:\n  tags: [x]\n---\n");
+        assert!(fm.tags.is_empty());
+    }
+
+    #[test]
+    fn body_offset_skips_frontmatter_block() {
+        let text = "---\ntype: lens\n---\nHello";
+        assert_eq!(&text[body_offset(text)..], "Hello");
+        assert_eq!(body_offset("Hello\n---\n"), 0);
+        assert_eq!(body_offset("---\nunterminated"), 0);
+    }
+}
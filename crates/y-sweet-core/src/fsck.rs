@@ -0,0 +1,436 @@
+//! Integrity checks for folder docs' `filemeta_v0` maps ("relay fsck").
+//!
+//! A bogus `"/"`/`"//"` folder entry plus duplicate-UUID paths once sent the
+//! Obsidian plugin into a `moveFolder` loop that destroyed files (see
+//! docs/filemeta-corruption-analysis.md). `check_folder` finds the structural
+//! problems in one folder doc and, in repair mode, fixes them in a single
+//! transaction. The checks that need the store (dangling IDs, orphaned
+//! content docs) run in `Server::fsck`, on the entries returned here.
+
+use crate::link_indexer::{
+    ensure_ancestor_folders, extract_filemeta_fields, extract_hash_from_filemeta_entry,
+    extract_id_from_filemeta_entry, extract_type_from_filemeta_entry,
+};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use yrs::{Any, Doc, Map, Out, ReadTxn, Transact, WriteTxn};
+
+/// Transaction origin for repairs, so they are identifiable in update logs.
+pub const FSCK_ORIGIN: &str = "fsck";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FsckIssueKind {
+    /// Path is not of the form `/a/b`: empty segments (`//x`), the bare root
+    /// `/`, a trailing slash, `.`/`..` segments or no leading slash.
+    MalformedPath,
+    /// The entry's ID is also used by another path in the same folder.
+    DuplicateId,
+    /// A parent folder of the path has no `folder` entry.
+    MissingAncestor,
+    /// The entry's ID has no content doc (or blob) in the store.
+    DanglingId,
+    /// A content doc in the store that no folder lists.
+    OrphanedDoc,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FsckIssue {
+    pub kind: FsckIssueKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    /// What repair mode does (or did) about the issue.
+    pub detail: String,
+    /// Whether repair mode fixed the issue. Dangling IDs and orphaned docs
+    /// are only reported: fixing them needs a human to decide where content
+    /// belongs.
+    pub repaired: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FolderFsckReport {
+    pub folder_doc_id: String,
+    pub folder_name: String,
+    /// filemeta_v0 entries after repair (or after a hypothetical repair)
+    pub entries: usize,
+    pub issues: Vec<FsckIssue>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct FsckReport {
+    pub repair: bool,
+    pub folders: Vec<FolderFsckReport>,
+    pub orphaned_docs: Vec<FsckIssue>,
+}
+
+impl FsckReport {
+    pub fn issue_count(&self) -> usize {
+        self.folders.iter().map(|f| f.issues.len()).sum::<usize>() + self.orphaned_docs.len()
+    }
+}
+
+/// A filemeta_v0 entry, reduced to the fields the checks look at.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilemetaEntry {
+    pub path: String,
+    pub id: Option<String>,
+    pub entry_type: Option<String>,
+    /// Blob content hash; entries with one live under `files/`, not as a Y.Doc.
+    pub hash: Option<String>,
+}
+
+impl FilemetaEntry {
+    pub fn is_folder(&self) -> bool {
+        self.entry_type.as_deref() == Some("folder")
+    }
+}
+
+/// Result of checking one folder doc.
+#[derive(Debug, Clone)]
+pub struct FolderCheck {
+    pub issues: Vec<FsckIssue>,
+    /// Entries as they are once the issues are repaired, sorted by path.
+    pub entries: Vec<FilemetaEntry>,
+}
+
+/// The well-formed spelling of a filemeta path: empty segments collapsed and
+/// a single leading slash. None for paths with nothing left (`"/"`, `"//"`)
+/// or with `.`/`..` segments, which have no safe spelling.
+pub fn normalize_path(path: &str) -> Option<String> {
+    let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+    if segments.is_empty() || segments.iter().any(|s| *s == "." || *s == "..") {
+        return None;
+    }
+    Some(format!("/{}", segments.join("/")))
+}
+
+fn read_entry(path: &str, value: &Out, txn: &impl ReadTxn) -> FilemetaEntry {
+    FilemetaEntry {
+        path: path.to_string(),
+        id: extract_id_from_filemeta_entry(value, txn),
+        entry_type: extract_type_from_filemeta_entry(value, txn),
+        hash: extract_hash_from_filemeta_entry(value, txn),
+    }
+}
+
+enum Fix {
+    Remove(String),
+    Move { from: String, to: String },
+}
+
+/// Check a folder doc's filemeta_v0 for malformed paths, duplicate IDs and
+/// missing ancestor folders. With `repair` the fixes are applied to `doc`
+/// (the caller must hold the doc's awareness write lock):
+///
+/// - a malformed path moves to its normalized spelling, or is removed when
+///   that is taken or does not exist (`"/"`, `"//"`);
+/// - of the paths sharing an ID, the one the legacy `docs` map points at (or
+///   else the first in sort order) stays and the others are removed;
+/// - missing ancestors are created via `ensure_ancestor_folders`.
+///
+/// The legacy `docs` map follows every move and removal.
+pub fn check_folder(doc: &Doc, repair: bool) -> FolderCheck {
+    let (mut model, legacy) = {
+        let txn = doc.transact();
+        let mut model = BTreeMap::new();
+        if let Some(filemeta) = txn.get_map("filemeta_v0") {
+            for (path, value) in filemeta.iter(&txn) {
+                model.insert(path.to_string(), read_entry(path, &value, &txn));
+            }
+        }
+        let mut legacy = HashMap::new();
+        if let Some(docs_map) = txn.get_map("docs") {
+            for (path, value) in docs_map.iter(&txn) {
+                if let Out::Any(Any::String(id)) = value {
+                    legacy.insert(path.to_string(), id.to_string());
+                }
+            }
+        }
+        (model, legacy)
+    };
+
+    let mut issues = Vec::new();
+    let mut fixes = Vec::new();
+
+    // 1. Malformed paths
+    let malformed: Vec<String> = model
+        .keys()
+        .filter(|path| normalize_path(path).as_deref() != Some(path.as_str()))
+        .cloned()
+        .collect();
+    for path in malformed {
+        let mut entry = model.remove(&path).expect("path from model");
+        let target = normalize_path(&path).filter(|target| !model.contains_key(target));
+        let detail = match (&target, normalize_path(&path)) {
+            (Some(target), _) => format!("move to {}", target),
+            (None, Some(taken)) => format!("remove: {} already exists", taken),
+            (None, None) => "remove: not a valid path".to_string(),
+        };
+        issues.push(FsckIssue {
+            kind: FsckIssueKind::MalformedPath,
+            path: Some(path.clone()),
+            id: entry.id.clone(),
+            detail,
+            repaired: repair,
+        });
+        match target {
+            Some(target) => {
+                fixes.push(Fix::Move {
+                    from: path,
+                    to: target.clone(),
+                });
+                entry.path = target.clone();
+                model.insert(target, entry);
+            }
+            None => fixes.push(Fix::Remove(path)),
+        }
+    }
+
+    // 2. Duplicate IDs
+    let mut by_id: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for entry in model.values() {
+        if let Some(id) = &entry.id {
+            by_id
+                .entry(id.clone())
+                .or_default()
+                .push(entry.path.clone());
+        }
+    }
+    for (id, paths) in by_id {
+        if paths.len() < 2 {
+            continue;
+        }
+        let agreed: Vec<&String> = paths
+            .iter()
+            .filter(|path| legacy.get(*path) == Some(&id))
+            .collect();
+        let keep = match agreed.as_slice() {
+            [only] => (*only).clone(),
+            _ => paths[0].clone(),
+        };
+        for path in paths.into_iter().filter(|path| *path != keep) {
+            issues.push(FsckIssue {
+                kind: FsckIssueKind::DuplicateId,
+                path: Some(path.clone()),
+                id: Some(id.clone()),
+                detail: format!("remove: {} has the same id", keep),
+                repaired: repair,
+            });
+            model.remove(&path);
+            fixes.push(Fix::Remove(path));
+        }
+    }
+
+    // 3. Missing ancestor folders
+    let mut missing = BTreeSet::new();
+    for path in model.keys() {
+        let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+        for i in 1..segments.len() {
+            let ancestor = format!("/{}", segments[..i].join("/"));
+            if !model.contains_key(&ancestor) {
+                missing.insert(ancestor);
+            }
+        }
+    }
+    for ancestor in &missing {
+        issues.push(FsckIssue {
+            kind: FsckIssueKind::MissingAncestor,
+            path: Some(ancestor.clone()),
+            id: None,
+            detail: "create folder entry".to_string(),
+            repaired: repair,
+        });
+        model.insert(
+            ancestor.clone(),
+            FilemetaEntry {
+                path: ancestor.clone(),
+                id: None,
+                entry_type: Some("folder".to_string()),
+                hash: None,
+            },
+        );
+    }
+
+    if repair && !issues.is_empty() {
+        let mut txn = doc.transact_mut_with(FSCK_ORIGIN);
+        let filemeta = txn.get_or_insert_map("filemeta_v0");
+        let docs_map = txn.get_or_insert_map("docs");
+        for fix in fixes {
+            match fix {
+                Fix::Remove(path) => {
+                    filemeta.remove(&mut txn, &path);
+                    docs_map.remove(&mut txn, &path);
+                }
+                Fix::Move { from, to } => {
+                    if let Some(value) = filemeta.get(&txn, &from) {
+                        let fields = extract_filemeta_fields(&value, &txn);
+                        filemeta.insert(&mut txn, to.as_str(), Any::Map(fields.into()));
+                    }
+                    filemeta.remove(&mut txn, &from);
+                    if let Some(id) = legacy.get(&from) {
+                        docs_map.insert(&mut txn, to.as_str(), Any::String(id.as_str().into()));
+                    }
+                    docs_map.remove(&mut txn, &from);
+                }
+            }
+        }
+        let paths: Vec<String> = model.keys().cloned().collect();
+        for path in paths {
+            ensure_ancestor_folders(&filemeta, &docs_map, &mut txn, &path);
+        }
+    }
+
+    FolderCheck {
+        issues,
+        entries: model.into_values().collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(id: &str, entry_type: &str) -> Any {
+        let mut map = HashMap::new();
+        map.insert("id".to_string(), Any::String(id.into()));
+        map.insert("type".to_string(), Any::String(entry_type.into()));
+        map.insert("version".to_string(), Any::Number(0.0));
+        Any::Map(map.into())
+    }
+
+    fn folder_doc(entries: &[(&str, &str, &str)]) -> Doc {
+        let doc = Doc::new();
+        {
+            let mut txn = doc.transact_mut();
+            let filemeta = txn.get_or_insert_map("filemeta_v0");
+            let docs_map = txn.get_or_insert_map("docs");
+            for (path, id, entry_type) in entries {
+                filemeta.insert(&mut txn, *path, entry(id, entry_type));
+                docs_map.insert(&mut txn, *path, Any::String((*id).into()));
+            }
+        }
+        doc
+    }
+
+    fn paths(doc: &Doc, map_name: &str) -> Vec<String> {
+        let txn = doc.transact();
+        let mut paths: Vec<String> = txn
+            .get_map(map_name)
+            .map(|m| m.keys(&txn).map(str::to_string).collect())
+            .unwrap_or_default();
+        paths.sort();
+        paths
+    }
+
+    fn kinds(check: &FolderCheck) -> Vec<(FsckIssueKind, &str)> {
+        check
+            .issues
+            .iter()
+            .map(|i| (i.kind, i.path.as_deref().unwrap_or_default()))
+            .collect()
+    }
+
+    #[test]
+    fn normalize_path_collapses_empty_segments() {
+        assert_eq!(normalize_path("/a/b.md").as_deref(), Some("/a/b.md"));
+        assert_eq!(normalize_path("//a//b.md").as_deref(), Some("/a/b.md"));
+        assert_eq!(normalize_path("a/").as_deref(), Some("/a"));
+        assert_eq!(normalize_path("/"), None);
+        assert_eq!(normalize_path("//"), None);
+        assert_eq!(normalize_path("/a/../b.md"), None);
+    }
+
+    #[test]
+    fn clean_folder_has_no_issues() {
+        let doc = folder_doc(&[
+            ("/Chris", "f1", "folder"),
+            ("/Chris/log.md", "d1", "markdown"),
+        ]);
+        let check = check_folder(&doc, true);
+        assert!(check.issues.is_empty());
+        assert_eq!(check.entries.len(), 2);
+    }
+
+    #[test]
+    fn reports_the_incident_shape_without_changing_the_doc() {
+        // The 2026-02-23 Lens corruption: bogus root folders sharing one id,
+        // and files duplicated at `//` and `///` depths.
+        let doc = folder_doc(&[
+            ("/", "f1", "folder"),
+            ("//", "f1", "folder"),
+            ("/log.md", "d1", "markdown"),
+            ("//log.md", "d1", "markdown"),
+            ("///log.md", "d1", "markdown"),
+        ]);
+        let check = check_folder(&doc, false);
+        assert_eq!(
+            kinds(&check),
+            vec![
+                (FsckIssueKind::MalformedPath, "/"),
+                (FsckIssueKind::MalformedPath, "//"),
+                (FsckIssueKind::MalformedPath, "///log.md"),
+                (FsckIssueKind::MalformedPath, "//log.md"),
+            ]
+        );
+        assert!(check.issues.iter().all(|i| !i.repaired));
+        assert_eq!(check.entries.len(), 1);
+        assert_eq!(
+            paths(&doc, "filemeta_v0").len(),
+            5,
+            "dry run must not write"
+        );
+    }
+
+    #[test]
+    fn repair_moves_or_removes_malformed_paths() {
+        let doc = folder_doc(&[
+            ("/", "f1", "folder"),
+            ("/log.md", "d1", "markdown"),
+            ("//log.md", "d1", "markdown"),
+            ("//notes.md", "d2", "markdown"),
+        ]);
+        let check = check_folder(&doc, true);
+        assert!(check.issues.iter().all(|i| i.repaired));
+        assert_eq!(paths(&doc, "filemeta_v0"), vec!["/log.md", "/notes.md"]);
+        assert_eq!(paths(&doc, "docs"), vec!["/log.md", "/notes.md"]);
+        assert!(check_folder(&doc, false).issues.is_empty());
+    }
+
+    #[test]
+    fn repair_keeps_the_duplicate_the_legacy_map_agrees_with() {
+        let doc = folder_doc(&[("/a.md", "d1", "markdown"), ("/b.md", "d1", "markdown")]);
+        {
+            let mut txn = doc.transact_mut();
+            let docs_map = txn.get_or_insert_map("docs");
+            docs_map.remove(&mut txn, "/a.md");
+        }
+        let check = check_folder(&doc, true);
+        assert_eq!(kinds(&check), vec![(FsckIssueKind::DuplicateId, "/a.md")]);
+        assert_eq!(paths(&doc, "filemeta_v0"), vec!["/b.md"]);
+    }
+
+    #[test]
+    fn repair_creates_missing_ancestor_folders() {
+        let doc = folder_doc(&[("/A/B/doc.md", "d1", "markdown")]);
+        let check = check_folder(&doc, true);
+        assert_eq!(
+            kinds(&check),
+            vec![
+                (FsckIssueKind::MissingAncestor, "/A"),
+                (FsckIssueKind::MissingAncestor, "/A/B"),
+            ]
+        );
+        assert_eq!(
+            paths(&doc, "filemeta_v0"),
+            vec!["/A", "/A/B", "/A/B/doc.md"]
+        );
+        let txn = doc.transact();
+        let folder = txn.get_map("filemeta_v0").unwrap().get(&txn, "/A").unwrap();
+        assert_eq!(
+            extract_type_from_filemeta_entry(&folder, &txn).as_deref(),
+            Some("folder")
+        );
+    }
+}
@@ -1,21 +1,33 @@
+pub mod acl;
 pub mod api_types;
 pub mod auth;
 pub mod config;
+pub mod content_regression;
 pub mod critic_scanner;
 pub mod critic_surgical;
 pub mod cwt;
 pub mod doc_connection;
+pub mod doc_dump;
 pub mod doc_resolver;
 pub mod doc_sync;
+pub mod embedding;
 pub mod event;
+pub mod filemeta_guard;
+pub mod frontmatter;
+pub mod fsck;
 pub mod link_indexer;
 pub mod link_parser;
 pub mod metrics;
 pub mod permanent_user_data;
 pub mod search_index;
+pub mod search_query;
+pub mod sections;
+pub mod suggest_guard;
+pub mod share_revocation;
 pub mod share_token;
 pub mod store;
 pub mod suggestions_index;
 pub mod sync;
 pub mod sync_kv;
+pub mod trash;
 pub mod webhook;
@@ -1741,7 +1741,7 @@ impl LinkIndexer {
 
     /// Server glue: unwraps DocWithSyncKv, delegates to core function.
     /// Resolves links across ALL loaded folder docs (cross-folder backlinks).
-    pub(crate) fn index_document(
+    pub fn index_document(
         &self,
         doc_id: &str,
         docs: &DashMap<String, DocWithSyncKv>,
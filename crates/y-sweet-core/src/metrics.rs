@@ -29,6 +29,15 @@ pub struct RelayMetrics {
     pub worker_panics_total: CounterVec,
     pub worker_panic_budget_exceeded_total: CounterVec,
     pub worker_alive: GaugeVec,
+
+    // Search index metrics
+    pub search_startup_docs: GaugeVec,
+
+    // Folder metadata guard metrics
+    pub filemeta_guard_trips_total: CounterVec,
+
+    // Content regression metrics
+    pub content_regressions_total: CounterVec,
 }
 
 static RELAY_METRICS: OnceLock<Result<Arc<RelayMetrics>, prometheus::Error>> = OnceLock::new();
@@ -200,6 +209,36 @@ impl RelayMetrics {
         )?;
         registry.register(Box::new(worker_alive.clone()))?;
 
+        // Search index metrics
+        let search_startup_docs = GaugeVec::new(
+            Opts::new(
+                "relay_server_search_startup_docs",
+                "Documents handled by the last startup search reindex, by outcome (skipped, reindexed, removed)",
+            ),
+            &["outcome"],
+        )?;
+        registry.register(Box::new(search_startup_docs.clone()))?;
+
+        // Folder metadata guard metrics
+        let filemeta_guard_trips_total = CounterVec::new(
+            Opts::new(
+                "relay_server_filemeta_guard_trips_total",
+                "Client updates to folder metadata flagged by the filemeta guard, by violation kind and action taken",
+            ),
+            &["kind", "action"],
+        )?;
+        registry.register(Box::new(filemeta_guard_trips_total.clone()))?;
+
+        // Content regression metrics
+        let content_regressions_total = CounterVec::new(
+            Opts::new(
+                "relay_server_content_regressions_total",
+                "Documents whose text was wiped or reverted to an old version between checkpoints, by kind",
+            ),
+            &["kind"],
+        )?;
+        registry.register(Box::new(content_regressions_total.clone()))?;
+
         Ok(Arc::new(Self {
             webhook_requests_total,
             webhook_request_duration_seconds,
@@ -218,6 +257,9 @@ impl RelayMetrics {
             worker_panics_total,
             worker_panic_budget_exceeded_total,
             worker_alive,
+            search_startup_docs,
+            filemeta_guard_trips_total,
+            content_regressions_total,
         }))
     }
 
@@ -333,6 +375,27 @@ impl RelayMetrics {
             .with_label_values(&[worker])
             .set(if alive { 1.0 } else { 0.0 });
     }
+
+    // Search index metrics methods
+    pub fn set_search_startup_docs(&self, outcome: &str, count: usize) {
+        self.search_startup_docs
+            .with_label_values(&[outcome])
+            .set(count as f64);
+    }
+
+    // Folder metadata guard metrics methods
+    pub fn record_filemeta_guard_trip(&self, kind: &str, action: &str) {
+        self.filemeta_guard_trips_total
+            .with_label_values(&[kind, action])
+            .inc();
+    }
+
+    // Content regression metrics methods
+    pub fn record_content_regression(&self, kind: &str) {
+        self.content_regressions_total
+            .with_label_values(&[kind])
+            .inc();
+    }
 }
 
 impl Default for RelayMetrics {
@@ -458,6 +521,77 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_search_startup_docs_metric() {
+        let metrics = RelayMetrics::new_for_test().unwrap();
+
+        metrics.set_search_startup_docs("skipped", 120);
+        metrics.set_search_startup_docs("reindexed", 3);
+
+        assert_eq!(
+            metrics
+                .search_startup_docs
+                .with_label_values(&["skipped"])
+                .get(),
+            120.0
+        );
+        assert_eq!(
+            metrics
+                .search_startup_docs
+                .with_label_values(&["reindexed"])
+                .get(),
+            3.0
+        );
+    }
+
+    #[test]
+    fn test_filemeta_guard_trip_metric() {
+        let metrics = RelayMetrics::new_for_test().unwrap();
+
+        metrics.record_filemeta_guard_trip("invalid_path", "reject");
+        metrics.record_filemeta_guard_trip("invalid_path", "reject");
+        metrics.record_filemeta_guard_trip("mass_deletion", "alert");
+
+        assert_eq!(
+            metrics
+                .filemeta_guard_trips_total
+                .with_label_values(&["invalid_path", "reject"])
+                .get(),
+            2.0
+        );
+        assert_eq!(
+            metrics
+                .filemeta_guard_trips_total
+                .with_label_values(&["mass_deletion", "alert"])
+                .get(),
+            1.0
+        );
+    }
+
+    #[test]
+    fn test_content_regression_metric() {
+        let metrics = RelayMetrics::new_for_test().unwrap();
+
+        metrics.record_content_regression("wipe");
+        metrics.record_content_regression("reversion");
+        metrics.record_content_regression("wipe");
+
+        assert_eq!(
+            metrics
+                .content_regressions_total
+                .with_label_values(&["wipe"])
+                .get(),
+            2.0
+        );
+        assert_eq!(
+            metrics
+                .content_regressions_total
+                .with_label_values(&["reversion"])
+                .get(),
+            1.0
+        );
+    }
+
     #[test]
     fn test_auth_error_metric_labels() {
         use crate::auth::AuthError;
@@ -1,13 +1,29 @@
-use anyhow::Result;
+use crate::embedding::{cosine_similarity, EmbeddingProvider};
+use crate::frontmatter;
+use crate::search_query::{self, AtomKind, QueryAst};
+use crate::sections;
+use anyhow::{anyhow, Result};
+use dashmap::DashMap;
 use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::path::Path;
-use std::sync::Mutex;
-use tantivy::collector::TopDocs;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tantivy::collector::{Count, DocSetCollector, TopDocs};
 use tantivy::directory::{MmapDirectory, RamDirectory};
-use tantivy::query::QueryParser;
-use tantivy::schema::{Field, Schema, TextFieldIndexing, TextOptions, Value, STORED, STRING};
+use tantivy::query::{
+    AllQuery, BooleanQuery, BoostQuery, ConstScoreQuery, EmptyQuery, FuzzyTermQuery, Occur,
+    PhraseQuery, Query, RegexQuery, TermQuery,
+};
+use tantivy::schema::{
+    Field, FieldType, IndexRecordOption, Schema, TextFieldIndexing, TextOptions, Value, STORED,
+    STRING,
+};
 use tantivy::snippet::SnippetGenerator;
-use tantivy::{doc, Index, IndexReader, IndexWriter, ReloadPolicy, TantivyDocument, Term};
+use tantivy::tokenizer::{LowerCaser, RawTokenizer, TextAnalyzer, TokenStream};
+use tantivy::{DocAddress, Index, IndexReader, IndexWriter, ReloadPolicy, TantivyDocument, Term};
 
 /// A single search result with relevance score and snippet.
 #[derive(Debug, Clone, Serialize)]
@@ -15,34 +31,329 @@ pub struct SearchResult {
     pub doc_id: String,
     pub title: String,
     pub folder: String,
+    /// Path within the folder, e.g. "/Biology/Photosynthesis.md". Empty for
+    /// documents indexed without a location.
+    pub path: String,
+    /// Frontmatter `tags`, lowercased.
+    pub tags: Vec<String>,
+    /// Frontmatter `type`, lowercased.
+    #[serde(rename = "type")]
+    pub doc_type: Option<String>,
+    /// Section results only: headings from the outermost ancestor down to
+    /// the matched section's own heading. Empty for the preamble.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub heading_path: Vec<String>,
+    /// Section results only: 1-based line of the section in the doc.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line: Option<u64>,
+    /// Section results only: `#heading` fragment for the editor URL.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub anchor: Option<String>,
+    /// When the document's text last changed, in ms since the Unix epoch.
+    /// None if unknown (indexed at startup from a store without mtimes).
+    pub modified: Option<u64>,
     pub snippet: String,
     pub score: f32,
 }
 
+/// Per-value document counts for the frontmatter fields of a search's
+/// matching documents.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct SearchFacets {
+    pub tags: BTreeMap<String, usize>,
+    pub types: BTreeMap<String, usize>,
+}
+
+impl SearchFacets {
+    fn count(&mut self, tags: &[String], doc_type: Option<&str>) {
+        for tag in tags {
+            *self.tags.entry(tag.clone()).or_default() += 1;
+        }
+        if let Some(doc_type) = doc_type {
+            *self.types.entry(doc_type.to_string()).or_default() += 1;
+        }
+    }
+}
+
+/// One page of results plus the total hit count and facet counts, as
+/// returned by `search_with_facets`.
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchResponse {
+    pub results: Vec<SearchResult>,
+    /// Number of hits across all pages.
+    pub total_hits: usize,
+    pub facets: SearchFacets,
+}
+
+/// Where a document lives. Folder and path are indexed as exact-match
+/// fields so searches can be scoped to a folder or a path prefix.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DocLocation {
+    /// Folder display name, e.g. "Lens".
+    pub folder: String,
+    /// Folder UUID (the folder doc id without its relay prefix).
+    pub folder_id: String,
+    /// Path within the folder, e.g. "/Biology/Photosynthesis.md".
+    pub path: String,
+}
+
+impl DocLocation {
+    /// A location known only by folder name.
+    pub fn in_folder(folder: &str) -> Self {
+        Self {
+            folder: folder.to_string(),
+            ..Self::default()
+        }
+    }
+}
+
+/// Parameters for `search_with_options`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchOptions {
+    pub limit: usize,
+    /// Number of hits to skip, for paging through a result set.
+    pub offset: usize,
+    pub mode: SearchMode,
+    /// Result order. Anything but `Relevance` needs `SearchMode::Bm25`.
+    pub sort: SearchSort,
+    /// Only documents in this folder, matched against folder name or UUID.
+    pub folder: Option<String>,
+    /// Only documents at or under this in-folder path, e.g. "/Biology".
+    /// Matches whole path segments: "/Bio" does not match "/Biology/x.md".
+    pub path_prefix: Option<String>,
+    /// Whether hits are whole documents or heading-delimited sections.
+    pub granularity: SearchGranularity,
+    /// Documents to leave out, e.g. those the caller's ACL hides. Applied
+    /// with the scope, so totals, facets and paging never count them.
+    pub exclude_doc_ids: Vec<String>,
+}
+
+impl SearchOptions {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            offset: 0,
+            mode: SearchMode::Bm25,
+            sort: SearchSort::Relevance,
+            folder: None,
+            path_prefix: None,
+            granularity: SearchGranularity::Document,
+            exclude_doc_ids: Vec::new(),
+        }
+    }
+}
+
+/// The unit a search ranks and returns.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchGranularity {
+    /// One hit per document.
+    #[default]
+    Document,
+    /// One hit per heading-delimited section, with heading path, line and
+    /// anchor. Keyword (`Bm25`) ranking only.
+    Section,
+}
+
+impl SearchGranularity {
+    /// Parse a granularity name as accepted by `GET /search?granularity=`
+    /// and the MCP tool.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "document" | "doc" => Some(SearchGranularity::Document),
+            "section" | "heading" => Some(SearchGranularity::Section),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SearchGranularity::Document => "document",
+            SearchGranularity::Section => "section",
+        }
+    }
+
+    /// Value of the indexed `kind` field for entries of this granularity.
+    fn kind(&self) -> &'static str {
+        match self {
+            SearchGranularity::Document => "doc",
+            SearchGranularity::Section => "section",
+        }
+    }
+}
+
+/// How `search_with_mode` ranks documents.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchMode {
+    /// Keyword ranking only (the tantivy BM25 score).
+    #[default]
+    Bm25,
+    /// BM25 blended with embedding similarity.
+    Hybrid,
+    /// Embedding similarity only.
+    Semantic,
+}
+
+impl SearchMode {
+    /// Parse a mode name as accepted by `GET /search?mode=` and the MCP tool.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "bm25" | "keyword" => Some(SearchMode::Bm25),
+            "hybrid" => Some(SearchMode::Hybrid),
+            "semantic" | "vector" => Some(SearchMode::Semantic),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SearchMode::Bm25 => "bm25",
+            SearchMode::Hybrid => "hybrid",
+            SearchMode::Semantic => "semantic",
+        }
+    }
+}
+
+/// Order of search results.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchSort {
+    /// Best match first, as ranked by the search mode.
+    #[default]
+    Relevance,
+    /// Most recently modified first.
+    Modified,
+    /// By title, case-insensitive.
+    Title,
+    /// By folder, then path within the folder, case-insensitive.
+    Path,
+}
+
+impl SearchSort {
+    /// Parse a sort name as accepted by `GET /search?sort=` and the MCP tool.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "relevance" | "score" => Some(SearchSort::Relevance),
+            "modified" | "recent" | "newest" => Some(SearchSort::Modified),
+            "title" => Some(SearchSort::Title),
+            "path" => Some(SearchSort::Path),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SearchSort::Relevance => "relevance",
+            SearchSort::Modified => "modified",
+            SearchSort::Title => "title",
+            SearchSort::Path => "path",
+        }
+    }
+}
+
+/// Share of the hybrid score taken by vector similarity; the rest is the
+/// max-normalized BM25 score.
+const HYBRID_VECTOR_WEIGHT: f32 = 0.6;
+
+/// Tokenizer for frontmatter keyword fields: the whole value is one token,
+/// lowercased, so `tag:Lens` and `tag:lens` match the same documents.
+const KEYWORD_TOKENIZER: &str = "keyword_lowercase";
+
+/// Vector-only candidates below this cosine similarity are dropped, so a
+/// semantic query over unrelated content returns nothing rather than noise.
+const MIN_VECTOR_SIMILARITY: f32 = 0.15;
+
+/// How far the store's clock may run ahead of ours before
+/// `SearchIndex::unchanged_since` mistakes a changed snapshot for an old one.
+pub const STORE_CLOCK_SKEW_MS: u64 = 60_000;
+
+/// Vector-only candidates looked up per tantivy query in blended search.
+const VECTOR_LOOKUP_BATCH: usize = 128;
+
+/// A blended-search candidate: a keyword hit with its snippet, or the stored
+/// entry of a hit found by embedding similarity alone.
+enum BlendedHit {
+    Keyword(SearchResult),
+    Vector(TantivyDocument),
+}
+
+/// Mixed into every content hash. Bump it when `make_documents` changes what
+/// gets indexed for the same input, so a persisted index reindexes every
+/// document on the next start instead of keeping stale entries.
+const INDEX_FORMAT_VERSION: &str = "1";
+
 /// Full-text search index backed by tantivy.
 ///
 /// Provides BM25-ranked full-text search with snippet generation over documents
 /// identified by unique `doc_id`. Thread-safe: the IndexWriter is wrapped in a Mutex.
+///
+/// Each document is indexed as one `kind: doc` entry plus one `kind: section`
+/// entry per heading-delimited section, all sharing its `doc_id` so a single
+/// delete term replaces or removes them together. Searches filter on `kind`
+/// according to `SearchOptions::granularity`.
+///
+/// The whole-doc entry stores a hash of the indexed title, body and
+/// location, and when it was written. An index persisted with `new(path)`
+/// survives restarts: `unchanged_since` lets startup skip documents whose
+/// stored snapshot is older than their entry without loading them, and
+/// `skip_if_unchanged` catches the rest whose hash didn't move.
+///
+/// With an `EmbeddingProvider` attached, every indexed document also gets an
+/// embedding vector, enabling `SearchMode::Hybrid` and `SearchMode::Semantic`.
+/// Vectors are stored on the whole-doc entry, tagged with the provider's
+/// `model_id`, and loaded into memory when the provider is attached. Queries
+/// scan them linearly, which is fine at knowledge-base scale (thousands of
+/// docs).
 pub struct SearchIndex {
-    #[allow(dead_code)]
     index: Index,
-    #[allow(dead_code)]
     schema: Schema,
     doc_id_field: Field,
     title_field: Field,
     body_field: Field,
     folder_field: Field,
+    folder_id_field: Field,
+    path_field: Field,
+    path_prefix_field: Field,
+    tag_field: Field,
+    type_field: Field,
+    source_url_field: Field,
+    kind_field: Field,
+    heading_field: Field,
+    heading_path_field: Field,
+    line_field: Field,
+    anchor_field: Field,
+    modified_field: Field,
+    content_hash_field: Field,
+    indexed_at_field: Field,
+    format_field: Field,
+    embedding_field: Field,
+    embedding_model_field: Field,
     writer: Mutex<IndexWriter>,
     reader: IndexReader,
-    query_parser: QueryParser,
+    embedder: Option<Arc<dyn EmbeddingProvider>>,
+    vectors: DashMap<String, Vec<f32>>,
 }
 
 impl SearchIndex {
-    /// Create a new SearchIndex with MmapDirectory at the given path.
+    /// Open the SearchIndex persisted at the given path, creating it if
+    /// needed. An index written with a different schema (an older relay
+    /// version) is deleted and recreated empty.
     pub fn new(path: &Path) -> Result<Self> {
         std::fs::create_dir_all(path)?;
-        let dir = MmapDirectory::open(path)?;
-        Self::build(dir)
+        match Self::build(MmapDirectory::open(path)?) {
+            Err(e) if is_schema_mismatch(&e) => {
+                tracing::warn!(
+                    "Search index at {:?} has an outdated schema ({}); rebuilding",
+                    path,
+                    e
+                );
+                std::fs::remove_dir_all(path)?;
+                std::fs::create_dir_all(path)?;
+                Self::build(MmapDirectory::open(path)?)
+            }
+            result => result,
+        }
     }
 
     /// Create a new SearchIndex backed by RAM (for tests).
@@ -69,14 +380,67 @@ impl SearchIndex {
         let title_field = schema_builder.add_text_field("title", text_options.clone());
 
         // body: TEXT + STORED (STORED is required for snippet generation)
-        let body_field = schema_builder.add_text_field("body", text_options);
-
-        // folder: STORED only (not searchable in v1)
-        let folder_field = schema_builder.add_text_field("folder", STORED);
+        let body_field = schema_builder.add_text_field("body", text_options.clone());
+
+        // folder / folder_id: STRING (exact match, for scoping) + STORED
+        let folder_field = schema_builder.add_text_field("folder", STRING | STORED);
+        let folder_id_field = schema_builder.add_text_field("folder_id", STRING | STORED);
+
+        // path: STORED for display; path_prefix: every ancestor directory
+        // ("/A/", "/A/B/") plus the path itself, so a prefix filter is a
+        // single exact term lookup
+        let path_field = schema_builder.add_text_field("path", STORED);
+        let path_prefix_field = schema_builder.add_text_field("path_prefix", STRING);
+
+        // Frontmatter fields, queryable as `tag:lens type:article`. tag is
+        // multi-valued. source_url keeps its case (URL paths are
+        // case-sensitive).
+        let keyword_options = TextOptions::default()
+            .set_indexing_options(
+                TextFieldIndexing::default()
+                    .set_tokenizer(KEYWORD_TOKENIZER)
+                    .set_index_option(IndexRecordOption::Basic),
+            )
+            .set_stored();
+        let tag_field = schema_builder.add_text_field("tag", keyword_options.clone());
+        let type_field = schema_builder.add_text_field("type", keyword_options);
+        let source_url_field = schema_builder.add_text_field("source_url", STRING | STORED);
+
+        // Section entries: kind tells them apart from whole-doc entries. A
+        // section's own heading is searchable; the path, line and anchor are
+        // only stored for display.
+        let kind_field = schema_builder.add_text_field("kind", STRING);
+        let heading_field = schema_builder.add_text_field("heading", text_options);
+        let heading_path_field = schema_builder.add_text_field("heading_path", STORED);
+        let line_field = schema_builder.add_u64_field("line", STORED);
+        let anchor_field = schema_builder.add_text_field("anchor", STORED);
+
+        // Last change to the doc's text (ms since epoch), on every entry so
+        // both granularities can sort by it
+        let modified_field = schema_builder.add_u64_field("modified", STORED);
+
+        // Whole-doc entries only: what was indexed, for incremental reindex
+        let content_hash_field = schema_builder.add_text_field("content_hash", STORED);
+        // ...when it was written (ms since epoch) and by which index format,
+        // so startup can skip docs whose stored snapshot is older without
+        // loading them
+        let indexed_at_field = schema_builder.add_u64_field("indexed_at", STORED);
+        let format_field = schema_builder.add_text_field("format", STORED);
+
+        // Whole-doc entries only: the document's embedding (little-endian
+        // f32s) and the `model_id` of the provider that produced it
+        let embedding_field = schema_builder.add_bytes_field("embedding", STORED);
+        let embedding_model_field = schema_builder.add_text_field("embedding_model", STORED);
 
         let schema = schema_builder.build();
 
         let index = Index::open_or_create(dir, schema.clone())?;
+        index.tokenizers().register(
+            KEYWORD_TOKENIZER,
+            TextAnalyzer::builder(RawTokenizer::default())
+                .filter(LowerCaser)
+                .build(),
+        );
 
         let writer: IndexWriter = index.writer(15_000_000)?; // 15MB budget
 
@@ -85,11 +449,6 @@ impl SearchIndex {
             .reload_policy(ReloadPolicy::OnCommitWithDelay)
             .try_into()?;
 
-        // QueryParser with AND semantics and title boost 2x
-        let mut query_parser = QueryParser::for_index(&index, vec![title_field, body_field]);
-        query_parser.set_conjunction_by_default();
-        query_parser.set_field_boost(title_field, 2.0);
-
         Ok(SearchIndex {
             index,
             schema,
@@ -97,32 +456,299 @@ impl SearchIndex {
             title_field,
             body_field,
             folder_field,
+            folder_id_field,
+            path_field,
+            path_prefix_field,
+            tag_field,
+            type_field,
+            source_url_field,
+            kind_field,
+            heading_field,
+            heading_path_field,
+            line_field,
+            anchor_field,
+            modified_field,
+            content_hash_field,
+            indexed_at_field,
+            format_field,
+            embedding_field,
+            embedding_model_field,
             writer: Mutex::new(writer),
             reader,
-            query_parser,
+            embedder: None,
+            vectors: DashMap::new(),
         })
     }
 
+    /// Attach an embedding provider, enabling hybrid and semantic search.
+    /// Vectors persisted by the same model are loaded; documents without one
+    /// get it when they are next indexed.
+    pub fn with_embedding_provider(mut self, provider: Arc<dyn EmbeddingProvider>) -> Self {
+        self.embedder = Some(provider);
+        if let Err(e) = self.load_vectors() {
+            tracing::warn!("Failed to load persisted search embeddings: {}", e);
+        }
+        self
+    }
+
+    /// Fill `vectors` from the whole-doc entries embedded by the attached
+    /// provider's model.
+    fn load_vectors(&self) -> Result<()> {
+        let Some(embedder) = &self.embedder else {
+            return Ok(());
+        };
+        let model_id = embedder.model_id();
+        let searcher = self.reader.searcher();
+        let whole_docs = TermQuery::new(
+            Term::from_field_text(self.kind_field, SearchGranularity::Document.kind()),
+            IndexRecordOption::Basic,
+        );
+        for address in searcher.search(&whole_docs, &DocSetCollector)? {
+            let retrieved: TantivyDocument = searcher.doc(address)?;
+            if retrieved
+                .get_first(self.embedding_model_field)
+                .and_then(|v| v.as_str())
+                != Some(model_id.as_str())
+            {
+                continue;
+            }
+            let doc_id = retrieved
+                .get_first(self.doc_id_field)
+                .and_then(|v| v.as_str());
+            let vector = retrieved
+                .get_first(self.embedding_field)
+                .and_then(|v| v.as_bytes())
+                .and_then(|bytes| decode_vector(bytes, embedder.dimensions()));
+            if let (Some(doc_id), Some(vector)) = (doc_id, vector) {
+                self.vectors.insert(doc_id.to_string(), vector);
+            }
+        }
+        Ok(())
+    }
+
+    /// Name of the attached embedding provider, if any.
+    pub fn embedding_provider_name(&self) -> Option<&str> {
+        self.embedder.as_ref().map(|e| e.name())
+    }
+
+    /// Compute the embedding for a document, or None without a provider.
+    /// Title is repeated so it weighs more than any single body sentence,
+    /// mirroring the BM25 title boost.
+    fn embed_document(&self, title: &str, body: &str) -> Result<Option<Vec<f32>>> {
+        match &self.embedder {
+            Some(embedder) => Ok(Some(embedder.embed(&format!("{title}\n{title}\n{body}"))?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Keep the in-memory copy of `doc_id`'s vector in step with what was
+    /// just written. A failed embedding drops the old vector, which described
+    /// the previous content, and is returned once the keyword entry is in.
+    fn store_vector(&self, doc_id: &str, embedding: Result<Option<Vec<f32>>>) -> Result<()> {
+        match embedding {
+            Ok(Some(vector)) => {
+                self.vectors.insert(doc_id.to_string(), vector);
+                Ok(())
+            }
+            Ok(None) => Ok(()),
+            Err(e) => {
+                self.vectors.remove(doc_id);
+                Err(e)
+            }
+        }
+    }
+
+    /// If `doc_id` is indexed with exactly this title, body and location
+    /// (and, with a provider attached, has its embedding), return true.
+    /// Otherwise return false, and the caller should reindex it.
+    pub fn skip_if_unchanged(
+        &self,
+        doc_id: &str,
+        title: &str,
+        body: &str,
+        location: &DocLocation,
+    ) -> Result<bool> {
+        let searcher = self.reader.searcher();
+        let Some(address) = self.find_whole_doc(&searcher, doc_id)? else {
+            return Ok(false);
+        };
+        let indexed: TantivyDocument = searcher.doc(address)?;
+        let indexed_hash = indexed
+            .get_first(self.content_hash_field)
+            .and_then(|v| v.as_str());
+        if indexed_hash != Some(content_hash(title, body, location).as_str()) {
+            return Ok(false);
+        }
+        Ok(self.embedder.is_none() || self.vectors.contains_key(doc_id))
+    }
+
+    /// Whether `doc_id` can be skipped without reading its body: it is
+    /// indexed by this index format with exactly this title and location,
+    /// has its embedding if a provider is attached, and its entry was
+    /// written at least `STORE_CLOCK_SKEW_MS` after `stored_modified` (the
+    /// store's last-modified time of the doc's snapshot, ms since epoch).
+    pub fn unchanged_since(
+        &self,
+        doc_id: &str,
+        title: &str,
+        location: &DocLocation,
+        stored_modified: u64,
+    ) -> Result<bool> {
+        let searcher = self.reader.searcher();
+        let Some(address) = self.find_whole_doc(&searcher, doc_id)? else {
+            return Ok(false);
+        };
+        let indexed: TantivyDocument = searcher.doc(address)?;
+        let text = |field| indexed.get_first(field).and_then(|v| v.as_str());
+        if text(self.format_field) != Some(INDEX_FORMAT_VERSION)
+            || text(self.title_field) != Some(title)
+            || text(self.folder_field) != Some(location.folder.as_str())
+            || text(self.folder_id_field) != Some(location.folder_id.as_str())
+            || text(self.path_field) != Some(location.path.as_str())
+        {
+            return Ok(false);
+        }
+        let Some(indexed_at) = indexed
+            .get_first(self.indexed_at_field)
+            .and_then(|v| v.as_u64())
+        else {
+            return Ok(false);
+        };
+        if stored_modified.saturating_add(STORE_CLOCK_SKEW_MS) > indexed_at {
+            return Ok(false);
+        }
+        Ok(self.embedder.is_none() || self.vectors.contains_key(doc_id))
+    }
+
+    /// Rewrite the entries of a document `skip_if_unchanged` accepted, so
+    /// its `indexed_at` moves past the store's snapshot time and the next
+    /// `unchanged_since` check passes. Keeps the modification time and the
+    /// loaded embedding. Call `flush()` after a batch.
+    pub fn refresh_buffered(
+        &self,
+        doc_id: &str,
+        title: &str,
+        body: &str,
+        location: &DocLocation,
+    ) -> Result<()> {
+        let searcher = self.reader.searcher();
+        let modified = match self.find_whole_doc(&searcher, doc_id)? {
+            Some(address) => searcher
+                .doc::<TantivyDocument>(address)?
+                .get_first(self.modified_field)
+                .and_then(|v| v.as_u64()),
+            None => None,
+        };
+        let vector = self.vectors.get(doc_id).map(|vector| vector.clone());
+        let mut writer = self.writer.lock().map_err(|e| anyhow::anyhow!("{}", e))?;
+        writer.delete_term(Term::from_field_text(self.doc_id_field, doc_id));
+        for document in
+            self.make_documents(doc_id, title, body, location, modified, vector.as_deref())
+        {
+            writer.add_document(document)?;
+        }
+        Ok(())
+    }
+
+    /// Ids of every indexed document.
+    pub fn indexed_doc_ids(&self) -> Result<HashSet<String>> {
+        let searcher = self.reader.searcher();
+        let whole_docs = TermQuery::new(
+            Term::from_field_text(self.kind_field, SearchGranularity::Document.kind()),
+            IndexRecordOption::Basic,
+        );
+        let mut ids = HashSet::new();
+        for address in searcher.search(&whole_docs, &DocSetCollector)? {
+            let retrieved: TantivyDocument = searcher.doc(address)?;
+            if let Some(doc_id) = retrieved
+                .get_first(self.doc_id_field)
+                .and_then(|v| v.as_str())
+            {
+                ids.insert(doc_id.to_string());
+            }
+        }
+        Ok(ids)
+    }
+
+    /// Folder ids (folder doc ids without their relay prefix) of every
+    /// indexed document.
+    pub fn indexed_folder_ids(&self) -> Result<HashSet<String>> {
+        let searcher = self.reader.searcher();
+        let whole_docs = TermQuery::new(
+            Term::from_field_text(self.kind_field, SearchGranularity::Document.kind()),
+            IndexRecordOption::Basic,
+        );
+        let mut ids = HashSet::new();
+        for address in searcher.search(&whole_docs, &DocSetCollector)? {
+            let retrieved: TantivyDocument = searcher.doc(address)?;
+            if let Some(folder_id) = retrieved
+                .get_first(self.folder_id_field)
+                .and_then(|v| v.as_str())
+                .filter(|folder_id| !folder_id.is_empty())
+            {
+                ids.insert(folder_id.to_string());
+            }
+        }
+        Ok(ids)
+    }
+
+    /// Address of the whole-doc entry for `doc_id`, if indexed.
+    fn find_whole_doc(
+        &self,
+        searcher: &tantivy::Searcher,
+        doc_id: &str,
+    ) -> Result<Option<DocAddress>> {
+        let by_id: Box<dyn Query> = Box::new(TermQuery::new(
+            Term::from_field_text(self.doc_id_field, doc_id),
+            IndexRecordOption::Basic,
+        ));
+        let whole_doc: Box<dyn Query> = Box::new(TermQuery::new(
+            Term::from_field_text(self.kind_field, SearchGranularity::Document.kind()),
+            IndexRecordOption::Basic,
+        ));
+        let lookup = BooleanQuery::new(vec![(Occur::Must, by_id), (Occur::Must, whole_doc)]);
+        Ok(searcher
+            .search(&lookup, &TopDocs::with_limit(1))?
+            .first()
+            .map(|(_, address)| *address))
+    }
+
     /// Add or update a document in the index.
     ///
     /// This is idempotent: if a document with the same `doc_id` already exists,
     /// it is deleted before the new version is added.
     pub fn add_document(&self, doc_id: &str, title: &str, body: &str, folder: &str) -> Result<()> {
+        self.add_document_at(doc_id, title, body, &DocLocation::in_folder(folder))
+    }
+
+    /// Like `add_document`, with the full folder/path location indexed for
+    /// scoped searches.
+    ///
+    /// The document's modification time becomes now, unless the body equals
+    /// the indexed one (a rename or move), which keeps the previous time.
+    pub fn add_document_at(
+        &self,
+        doc_id: &str,
+        title: &str,
+        body: &str,
+        location: &DocLocation,
+    ) -> Result<()> {
+        let modified = self.next_modified(doc_id, body)?;
+        let embedding = self.embed_document(title, body);
+        let vector = embedding.as_ref().ok().and_then(Option::as_deref);
         let mut writer = self.writer.lock().map_err(|e| anyhow::anyhow!("{}", e))?;
         // Delete existing document with same doc_id
         let term = Term::from_field_text(self.doc_id_field, doc_id);
         writer.delete_term(term);
-        // Add the new document
-        writer.add_document(doc!(
-            self.doc_id_field => doc_id,
-            self.title_field => title,
-            self.body_field => body,
-            self.folder_field => folder,
-        ))?;
+        // Add the new document and its sections
+        for document in self.make_documents(doc_id, title, body, location, modified, vector) {
+            writer.add_document(document)?;
+        }
         writer.commit()?;
         // Reload the reader to pick up changes immediately
         self.reader.reload()?;
-        Ok(())
+        drop(writer);
+        self.store_vector(doc_id, embedding)
     }
 
     /// Remove a document from the index by doc_id.
@@ -132,6 +758,7 @@ impl SearchIndex {
         writer.delete_term(term);
         writer.commit()?;
         self.reader.reload()?;
+        self.vectors.remove(doc_id);
         Ok(())
     }
 
@@ -143,16 +770,123 @@ impl SearchIndex {
         body: &str,
         folder: &str,
     ) -> Result<()> {
+        self.add_document_buffered_at(doc_id, title, body, &DocLocation::in_folder(folder), None)
+    }
+
+    /// Buffered variant of `add_document_at`. Call `flush()` after a batch.
+    ///
+    /// `modified` (ms since epoch) is the document's known modification
+    /// time, e.g. from the store; None derives it as `add_document_at` does.
+    pub fn add_document_buffered_at(
+        &self,
+        doc_id: &str,
+        title: &str,
+        body: &str,
+        location: &DocLocation,
+        modified: Option<u64>,
+    ) -> Result<()> {
+        let modified = match modified {
+            Some(modified) => Some(modified),
+            None => self.next_modified(doc_id, body)?,
+        };
+        let embedding = self.embed_document(title, body);
+        let vector = embedding.as_ref().ok().and_then(Option::as_deref);
         let mut writer = self.writer.lock().map_err(|e| anyhow::anyhow!("{}", e))?;
         let term = Term::from_field_text(self.doc_id_field, doc_id);
         writer.delete_term(term);
-        writer.add_document(doc!(
-            self.doc_id_field => doc_id,
-            self.title_field => title,
-            self.body_field => body,
-            self.folder_field => folder,
-        ))?;
-        Ok(())
+        for document in self.make_documents(doc_id, title, body, location, modified, vector) {
+            writer.add_document(document)?;
+        }
+        drop(writer);
+        self.store_vector(doc_id, embedding)
+    }
+
+    /// Modification time for a new version of `doc_id`: the indexed
+    /// version's if the body is unchanged, otherwise now.
+    fn next_modified(&self, doc_id: &str, body: &str) -> Result<Option<u64>> {
+        let searcher = self.reader.searcher();
+        if let Some(address) = self.find_whole_doc(&searcher, doc_id)? {
+            let previous: TantivyDocument = searcher.doc(address)?;
+            if previous.get_first(self.body_field).and_then(|v| v.as_str()) == Some(body) {
+                return Ok(previous
+                    .get_first(self.modified_field)
+                    .and_then(|v| v.as_u64()));
+            }
+        }
+        Ok(Some(now_millis()))
+    }
+
+    /// The whole-doc entry followed by one entry per section. Sections carry
+    /// the doc's title, location and frontmatter so the same queries and
+    /// scope filters apply to them.
+    fn make_documents(
+        &self,
+        doc_id: &str,
+        title: &str,
+        body: &str,
+        location: &DocLocation,
+        modified: Option<u64>,
+        vector: Option<&[f32]>,
+    ) -> Vec<TantivyDocument> {
+        let meta = frontmatter::parse(body);
+        let mut document = self.make_document(doc_id, title, location, &meta, modified);
+        document.add_text(self.kind_field, SearchGranularity::Document.kind());
+        document.add_text(self.body_field, body);
+        document.add_text(self.content_hash_field, content_hash(title, body, location));
+        document.add_u64(self.indexed_at_field, now_millis());
+        document.add_text(self.format_field, INDEX_FORMAT_VERSION);
+        if let (Some(vector), Some(embedder)) = (vector, &self.embedder) {
+            document.add_bytes(self.embedding_field, encode_vector(vector).as_slice());
+            document.add_text(self.embedding_model_field, embedder.model_id());
+        }
+
+        let mut documents = vec![document];
+        for section in sections::split(body) {
+            let mut document = self.make_document(doc_id, title, location, &meta, modified);
+            document.add_text(self.kind_field, SearchGranularity::Section.kind());
+            document.add_text(self.body_field, &section.text);
+            document.add_text(self.heading_field, section.heading());
+            for heading in &section.heading_path {
+                document.add_text(self.heading_path_field, heading);
+            }
+            document.add_u64(self.line_field, section.line as u64);
+            document.add_text(self.anchor_field, &section.anchor);
+            documents.push(document);
+        }
+        documents
+    }
+
+    /// Fields shared by whole-doc and section entries.
+    fn make_document(
+        &self,
+        doc_id: &str,
+        title: &str,
+        location: &DocLocation,
+        meta: &frontmatter::Frontmatter,
+        modified: Option<u64>,
+    ) -> TantivyDocument {
+        let mut document = TantivyDocument::new();
+        document.add_text(self.doc_id_field, doc_id);
+        document.add_text(self.title_field, title);
+        document.add_text(self.folder_field, &location.folder);
+        document.add_text(self.folder_id_field, &location.folder_id);
+        document.add_text(self.path_field, &location.path);
+        for prefix in path_prefixes(&location.path) {
+            document.add_text(self.path_prefix_field, prefix);
+        }
+        for tag in &meta.tags {
+            document.add_text(self.tag_field, tag.to_lowercase());
+        }
+        if let Some(doc_type) = &meta.doc_type {
+            document.add_text(self.type_field, doc_type.to_lowercase());
+        }
+        if let Some(source_url) = &meta.source_url {
+            document.add_text(self.source_url_field, source_url);
+        }
+        if let Some(modified) = modified {
+            document.add_u64(self.modified_field, modified);
+        }
+        document
     }
 
     /// Commit buffered changes and reload the reader.
@@ -165,97 +899,841 @@ impl SearchIndex {
 
     /// Search the index and return ranked results with snippets.
     ///
-    /// Returns an empty Vec for empty or whitespace-only queries.
-    /// Uses `parse_query_lenient` to tolerate syntax errors in the query string.
+    /// Returns an empty Vec for empty or whitespace-only queries. The query
+    /// language is described in `search_query`; a malformed query fails with
+    /// a `search_query::QueryParseError` (retrievable via `downcast_ref`).
     ///
     /// When Tantivy's snippet generator can't find highlights in the body
     /// (e.g. title-only matches), falls back to a manual substring search
     /// that extracts context around the first matching query term.
     pub fn search(&self, query: &str, limit: usize) -> Result<Vec<SearchResult>> {
+        self.search_with_options(query, &SearchOptions::new(limit))
+    }
+
+    /// Search using the given ranking mode. See `search_with_options`.
+    pub fn search_with_mode(
+        &self,
+        query: &str,
+        limit: usize,
+        mode: SearchMode,
+    ) -> Result<Vec<SearchResult>> {
+        let options = SearchOptions {
+            mode,
+            ..SearchOptions::new(limit)
+        };
+        self.search_with_options(query, &options)
+    }
+
+    /// Search with a ranking mode and optional folder / path-prefix scope.
+    ///
+    /// `Bm25` ranks by keyword relevance only. `Hybrid` scores each candidate
+    /// as a weighted sum of its max-normalized BM25 score and the cosine
+    /// similarity between query and document embeddings, so conceptual
+    /// queries find documents that share few literal terms. `Semantic` ranks
+    /// by embedding similarity alone. Both embedding modes fail if no
+    /// provider is attached.
+    pub fn search_with_options(
+        &self,
+        query: &str,
+        options: &SearchOptions,
+    ) -> Result<Vec<SearchResult>> {
+        Ok(self.search_page(query, options)?.0)
+    }
+
+    /// Like `search_with_options`, also returning the total number of hits
+    /// across all pages. In the embedding modes that is the number of
+    /// candidates ranked: keyword hits plus sufficiently similar vectors,
+    /// each capped at four times `offset + limit` (at least 50).
+    pub fn search_page(
+        &self,
+        query: &str,
+        options: &SearchOptions,
+    ) -> Result<(Vec<SearchResult>, usize)> {
         // Guard: empty or whitespace-only queries return nothing
         if query.trim().is_empty() {
-            return Ok(Vec::new());
+            return Ok((Vec::new(), 0));
+        }
+        if options.granularity == SearchGranularity::Section && options.mode != SearchMode::Bm25 {
+            return Err(anyhow!(
+                "section granularity is only supported in bm25 mode, not {}",
+                options.mode.as_str()
+            ));
+        }
+        if options.sort != SearchSort::Relevance && options.mode != SearchMode::Bm25 {
+            return Err(anyhow!(
+                "sorting by {} is only supported in bm25 mode, not {}",
+                options.sort.as_str(),
+                options.mode.as_str()
+            ));
         }
+        let filter = self.scope_filter(options);
+        match options.mode {
+            SearchMode::Bm25 => {
+                self.search_bm25(query, options.limit, options.offset, options.sort, &*filter)
+            }
+            SearchMode::Hybrid | SearchMode::Semantic => {
+                self.search_blended(query, options, &*filter)
+            }
+        }
+    }
 
-        let (parsed_query, _errors) = self.query_parser.parse_query_lenient(query);
+    fn search_bm25(
+        &self,
+        query: &str,
+        limit: usize,
+        offset: usize,
+        sort: SearchSort,
+        filter: &dyn Query,
+    ) -> Result<(Vec<SearchResult>, usize)> {
+        let (parsed_query, scoped_query) = self.parse_scoped(query, filter)?;
 
         let searcher = self.reader.searcher();
-        let top_docs = searcher.search(&parsed_query, &TopDocs::with_limit(limit))?;
+        let total = searcher.search(&*scoped_query, &Count)?;
+        if limit == 0 || offset >= total {
+            return Ok((Vec::new(), total));
+        }
+        let top_docs = match sort {
+            SearchSort::Relevance => searcher.search(
+                &*scoped_query,
+                &TopDocs::with_limit(limit).and_offset(offset),
+            )?,
+            _ => {
+                let all = searcher.search(&*scoped_query, &TopDocs::with_limit(total))?;
+                let mut sorted = self.sort_hits(&searcher, all, sort)?;
+                sorted.truncate(offset + limit);
+                sorted.split_off(offset)
+            }
+        };
 
         // Set up snippet generator for the body field
         let mut snippet_generator =
             SnippetGenerator::create(&searcher, &*parsed_query, self.body_field)?;
         snippet_generator.set_max_num_chars(200);
 
-        // Extract query terms for fallback snippet generation
-        let query_terms: Vec<String> = query
-            .split_whitespace()
-            .filter(|t| !t.is_empty())
-            .map(|t| t.to_lowercase())
-            .collect();
+        let query_terms = fallback_query_terms(query);
 
         let mut results = Vec::with_capacity(top_docs.len());
         for (score, doc_address) in top_docs {
             let retrieved: TantivyDocument = searcher.doc(doc_address)?;
+            results.push(self.to_result(&retrieved, score, Some(&snippet_generator), &query_terms));
+        }
 
-            let doc_id = retrieved
-                .get_first(self.doc_id_field)
-                .and_then(|v| v.as_str())
-                .unwrap_or("")
-                .to_string();
-            let title = retrieved
-                .get_first(self.title_field)
-                .and_then(|v| v.as_str())
-                .unwrap_or("")
-                .to_string();
-            let folder = retrieved
-                .get_first(self.folder_field)
+        Ok((results, total))
+    }
+
+    /// Order scored hits by a stored-field sort. Ties fall back to doc_id
+    /// and then line, so pages are stable between requests.
+    fn sort_hits(
+        &self,
+        searcher: &tantivy::Searcher,
+        hits: Vec<(f32, DocAddress)>,
+        sort: SearchSort,
+    ) -> Result<Vec<(f32, DocAddress)>> {
+        if sort == SearchSort::Relevance {
+            return Ok(hits); // already ranked by score
+        }
+        let text = |retrieved: &TantivyDocument, field: Field| {
+            retrieved
+                .get_first(field)
                 .and_then(|v| v.as_str())
                 .unwrap_or("")
-                .to_string();
+                .to_lowercase()
+        };
+        let mut keyed = Vec::with_capacity(hits.len());
+        for (score, address) in hits {
+            let retrieved: TantivyDocument = searcher.doc(address)?;
+            let modified = retrieved
+                .get_first(self.modified_field)
+                .and_then(|v| v.as_u64());
+            let title = text(&retrieved, self.title_field);
+            // Unknown modification times sort last
+            let key = match sort {
+                SearchSort::Modified => (Reverse(modified), title),
+                SearchSort::Relevance | SearchSort::Title => (Reverse(None), title),
+                SearchSort::Path => (
+                    Reverse(None),
+                    format!(
+                        "{}\u{0}{}",
+                        text(&retrieved, self.folder_field),
+                        text(&retrieved, self.path_field)
+                    ),
+                ),
+            };
+            let doc_id = text(&retrieved, self.doc_id_field);
+            let line = retrieved
+                .get_first(self.line_field)
+                .and_then(|v| v.as_u64());
+            keyed.push((key, doc_id, line, score, address));
+        }
+        keyed.sort_by(|a, b| (&a.0, &a.1, a.2).cmp(&(&b.0, &b.1, b.2)));
+        Ok(keyed
+            .into_iter()
+            .map(|(_, _, _, score, address)| (score, address))
+            .collect())
+    }
 
-            let body = retrieved
-                .get_first(self.body_field)
-                .and_then(|v| v.as_str())
-                .unwrap_or("");
+    /// Parse a keyword query and combine it with the scope filter. Returns
+    /// the bare parsed query (for snippets) and the scoped one (for matching).
+    fn parse_scoped(
+        &self,
+        query: &str,
+        filter: &dyn Query,
+    ) -> Result<(Box<dyn Query>, Box<dyn Query>)> {
+        let parsed_query = self.build_query(&search_query::parse(query)?)?;
+
+        // The scope filter must match but must not change the ranking
+        let scoped_query: Box<dyn Query> = Box::new(BooleanQuery::new(vec![
+            (Occur::Must, parsed_query.box_clone()),
+            (
+                Occur::Must,
+                Box::new(ConstScoreQuery::new(filter.box_clone(), 0.0)),
+            ),
+        ]));
+        Ok((parsed_query, scoped_query))
+    }
 
-            let snippet = snippet_generator.snippet_from_doc(&retrieved);
-            let snippet_html = if snippet.highlighted().is_empty() {
-                // Tantivy found no highlights in the body (likely a title-only match).
-                // Fall back to manual substring search in the body text.
-                generate_fallback_snippet(body, &query_terms, 200)
+    /// Turn a parsed query into a tantivy query. Unqualified atoms search
+    /// title (boost 2x), body and section heading (boost 1.5x). An atom
+    /// with no indexable tokens (e.g. only punctuation) is dropped.
+    fn build_query(&self, ast: &QueryAst) -> Result<Box<dyn Query>> {
+        let mut clauses: Vec<(Occur, Box<dyn Query>)> = Vec::new();
+        for clause in &ast.clauses {
+            let mut alternatives = Vec::new();
+            for atom in &clause.alternatives {
+                let fields = match atom.field.as_deref() {
+                    None => vec![
+                        (self.title_field, Some(2.0)),
+                        (self.body_field, None),
+                        (self.heading_field, Some(1.5)),
+                    ],
+                    Some(name) => vec![(self.query_field(name)?, None)],
+                };
+                for (field, boost) in fields {
+                    if let Some(query) = self.field_query(field, &atom.kind)? {
+                        let query: Box<dyn Query> = match boost {
+                            Some(boost) => Box::new(BoostQuery::new(query, boost)),
+                            None => query,
+                        };
+                        alternatives.push((Occur::Should, query));
+                    }
+                }
+            }
+            let occur = if clause.exclude {
+                Occur::MustNot
             } else {
-                render_snippet_with_mark(&snippet, body)
+                Occur::Must
             };
+            match alternatives.len() {
+                0 => {}
+                1 => clauses.push((occur, alternatives.remove(0).1)),
+                _ => clauses.push((occur, Box::new(BooleanQuery::new(alternatives)))),
+            }
+        }
 
-            results.push(SearchResult {
-                doc_id,
-                title,
-                folder,
-                snippet: snippet_html,
-                score,
-            });
+        if clauses.is_empty() {
+            return Ok(Box::new(EmptyQuery));
+        }
+        // A purely negative query ("-draft") excludes from everything
+        if clauses.iter().all(|(occur, _)| *occur == Occur::MustNot) {
+            clauses.push((Occur::Must, Box::new(AllQuery)));
         }
+        Ok(Box::new(BooleanQuery::new(clauses)))
+    }
 
-        Ok(results)
+    /// The query for one term or phrase in one field, or None when the text
+    /// produces no tokens in that field.
+    fn field_query(&self, field: Field, kind: &AtomKind) -> Result<Option<Box<dyn Query>>> {
+        let text = match kind {
+            AtomKind::Term { text, .. } | AtomKind::Phrase { text, .. } => text,
+        };
+        let tokens = self.tokenize(field, text)?;
+        let Some((last, leading)) = tokens.split_last() else {
+            return Ok(None);
+        };
+        let term = |token: &String| Term::from_field_text(field, token);
+        let record_option = self.record_option(field);
+
+        let query: Box<dyn Query> = match kind {
+            AtomKind::Term { prefix: true, .. } => {
+                // "set-up*" tokenizes to [set, up]: leading tokens must match
+                // exactly, the last one as a prefix
+                let mut parts: Vec<(Occur, Box<dyn Query>)> = leading
+                    .iter()
+                    .map(|token| {
+                        let query: Box<dyn Query> =
+                            Box::new(TermQuery::new(term(token), record_option));
+                        (Occur::Must, query)
+                    })
+                    .collect();
+                let pattern = format!("{}.*", regex::escape(last));
+                parts.push((
+                    Occur::Must,
+                    Box::new(RegexQuery::from_pattern(&pattern, field)?),
+                ));
+                Box::new(BooleanQuery::new(parts))
+            }
+            AtomKind::Term {
+                fuzzy: Some(distance),
+                ..
+            } => {
+                let parts: Vec<(Occur, Box<dyn Query>)> = tokens
+                    .iter()
+                    .map(|token| {
+                        let query: Box<dyn Query> =
+                            Box::new(FuzzyTermQuery::new(term(token), *distance, true));
+                        (Occur::Must, query)
+                    })
+                    .collect();
+                Box::new(BooleanQuery::new(parts))
+            }
+            _ if leading.is_empty() => Box::new(TermQuery::new(term(last), record_option)),
+            AtomKind::Phrase { slop, .. } => {
+                let mut phrase = PhraseQuery::new(tokens.iter().map(term).collect());
+                phrase.set_slop(*slop);
+                Box::new(phrase)
+            }
+            // A single word that tokenizes into several ("set-up")
+            AtomKind::Term { .. } => Box::new(PhraseQuery::new(tokens.iter().map(term).collect())),
+        };
+        Ok(Some(query))
     }
-}
 
-/// Render a tantivy Snippet using `<mark>` tags instead of the default `<b>` tags.
-/// HTML-escapes non-highlighted text to prevent injection via `dangerouslySetInnerHTML`.
-/// Adds "..." when the fragment is a subset of the full body text.
-fn render_snippet_with_mark(snippet: &tantivy::snippet::Snippet, full_body: &str) -> String {
-    let fragment = snippet.fragment();
-    let highlighted = snippet.highlighted();
+    /// Field behind a `field:` qualifier (validated by the parser).
+    fn query_field(&self, name: &str) -> Result<Field> {
+        match name {
+            "title" => Ok(self.title_field),
+            "body" => Ok(self.body_field),
+            "heading" => Ok(self.heading_field),
+            "tag" => Ok(self.tag_field),
+            "type" => Ok(self.type_field),
+            "source_url" => Ok(self.source_url_field),
+            other => Err(anyhow!("Unknown search field '{}'", other)),
+        }
+    }
 
-    if highlighted.is_empty() {
-        return escape_html(fragment);
+    /// Run `text` through the field's tokenizer, as indexing did.
+    fn tokenize(&self, field: Field, text: &str) -> Result<Vec<String>> {
+        let mut analyzer = self.index.tokenizer_for_field(field)?;
+        let mut stream = analyzer.token_stream(text);
+        let mut tokens = Vec::new();
+        while stream.advance() {
+            tokens.push(stream.token().text.clone());
+        }
+        Ok(tokens)
     }
 
-    let mut result = String::new();
-    let mut pos = 0;
+    /// Term frequencies are used for scoring where the field indexes them.
+    fn record_option(&self, field: Field) -> IndexRecordOption {
+        let has_freqs = match self.schema.get_field_entry(field).field_type() {
+            FieldType::Str(options) => options
+                .get_indexing_options()
+                .is_some_and(|indexing| indexing.index_option().has_freq()),
+            _ => false,
+        };
+        if has_freqs {
+            IndexRecordOption::WithFreqs
+        } else {
+            IndexRecordOption::Basic
+        }
+    }
 
-    for range in highlighted {
+    /// Search for one page of results, also returning the total hit count
+    /// and frontmatter tag/type counts over the matches.
+    ///
+    /// In `Bm25` mode facets cover every document matching the query and
+    /// scope, not just the returned page. Embedding modes have no crisp
+    /// match set, so their facets cover the returned results.
+    pub fn search_with_facets(
+        &self,
+        query: &str,
+        options: &SearchOptions,
+    ) -> Result<SearchResponse> {
+        let (results, total_hits) = self.search_page(query, options)?;
+        let mut facets = SearchFacets::default();
+        if options.mode == SearchMode::Bm25 {
+            if !query.trim().is_empty() {
+                let filter = self.scope_filter(options);
+                let (_, scoped_query) = self.parse_scoped(query, &*filter)?;
+                let searcher = self.reader.searcher();
+                for address in searcher.search(&*scoped_query, &DocSetCollector)? {
+                    let retrieved: TantivyDocument = searcher.doc(address)?;
+                    let (tags, doc_type) = self.stored_frontmatter(&retrieved);
+                    facets.count(&tags, doc_type.as_deref());
+                }
+            }
+        } else {
+            for result in &results {
+                facets.count(&result.tags, result.doc_type.as_deref());
+            }
+        }
+        Ok(SearchResponse {
+            results,
+            total_hits,
+            facets,
+        })
+    }
+
+    fn stored_frontmatter(&self, retrieved: &TantivyDocument) -> (Vec<String>, Option<String>) {
+        let tags = retrieved
+            .get_all(self.tag_field)
+            .filter_map(|v| v.as_str())
+            .map(str::to_string)
+            .collect();
+        let doc_type = retrieved
+            .get_first(self.type_field)
+            .and_then(|v| v.as_str())
+            .map(str::to_string);
+        (tags, doc_type)
+    }
+
+    fn search_blended(
+        &self,
+        query: &str,
+        options: &SearchOptions,
+        filter: &dyn Query,
+    ) -> Result<(Vec<SearchResult>, usize)> {
+        let mode = options.mode;
+        let limit = options.limit;
+        let offset = options.offset;
+        let embedder = self.embedder.as_ref().ok_or_else(|| {
+            anyhow!(
+                "{} search requires an embedding provider, none is configured",
+                mode.as_str()
+            )
+        })?;
+
+        let query_vector = embedder.embed(query)?;
+        let candidate_limit = ((offset + limit) * 4).max(50);
+
+        // Vector leg: cosine against every stored embedding, most similar
+        // first.
+        let mut similarities: Vec<(String, f32)> = self
+            .vectors
+            .iter()
+            .map(|entry| {
+                let similarity = cosine_similarity(&query_vector, entry.value());
+                (entry.key().clone(), similarity)
+            })
+            .collect();
+        similarities.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        let similarity_of: HashMap<&str, f32> = similarities
+            .iter()
+            .map(|(doc_id, similarity)| (doc_id.as_str(), *similarity))
+            .collect();
+
+        // Keyword leg (hybrid only). Keeps its snippets, which highlight the
+        // matched terms better than the fallback used for vector-only hits.
+        let keyword_hits = if mode == SearchMode::Hybrid {
+            self.search_bm25(query, candidate_limit, 0, SearchSort::Relevance, filter)?
+                .0
+        } else {
+            Vec::new()
+        };
+        let max_bm25 = keyword_hits.iter().map(|r| r.score).fold(0.0f32, f32::max);
+
+        let mut scored: Vec<(String, f32, BlendedHit)> = Vec::new();
+        let mut seen = HashSet::new();
+        for hit in keyword_hits {
+            let bm25 = if max_bm25 > 0.0 {
+                hit.score / max_bm25
+            } else {
+                0.0
+            };
+            let similarity = similarity_of
+                .get(hit.doc_id.as_str())
+                .copied()
+                .unwrap_or(0.0)
+                .max(0.0);
+            let score = HYBRID_VECTOR_WEIGHT * similarity + (1.0 - HYBRID_VECTOR_WEIGHT) * bm25;
+            seen.insert(hit.doc_id.clone());
+            scored.push((hit.doc_id.clone(), score, BlendedHit::Keyword(hit)));
+        }
+
+        // Vector-only hits, like the keyword leg capped at candidate_limit:
+        // look the most similar ones up in batches, applying the scope
+        // filter. A vector can also briefly outlive its tantivy doc between
+        // delete and commit; such hits are skipped.
+        let searcher = self.reader.searcher();
+        let vector_only: Vec<&(String, f32)> = similarities
+            .iter()
+            .take_while(|(_, similarity)| *similarity >= MIN_VECTOR_SIMILARITY)
+            .filter(|(doc_id, _)| !seen.contains(doc_id))
+            .collect();
+        let mut vector_hits = 0;
+        for batch in vector_only.chunks(VECTOR_LOOKUP_BATCH) {
+            if vector_hits >= candidate_limit {
+                break;
+            }
+            let doc_ids: Vec<&str> = batch.iter().map(|(doc_id, _)| doc_id.as_str()).collect();
+            let mut found = self.lookup_in_scope(&searcher, &doc_ids, filter)?;
+            for (doc_id, similarity) in batch {
+                let Some(retrieved) = found.remove(doc_id) else {
+                    continue;
+                };
+                let score = match mode {
+                    SearchMode::Hybrid => HYBRID_VECTOR_WEIGHT * similarity,
+                    _ => *similarity,
+                };
+                scored.push((doc_id.clone(), score, BlendedHit::Vector(retrieved)));
+                vector_hits += 1;
+                if vector_hits >= candidate_limit {
+                    break;
+                }
+            }
+        }
+        scored.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+        let query_terms = fallback_query_terms(query);
+        let total = scored.len();
+        let results = scored
+            .into_iter()
+            .skip(offset)
+            .take(limit)
+            .map(|(_, score, hit)| match hit {
+                BlendedHit::Keyword(mut hit) => {
+                    hit.score = score;
+                    hit
+                }
+                BlendedHit::Vector(retrieved) => {
+                    self.to_result(&retrieved, score, None, &query_terms)
+                }
+            })
+            .collect();
+
+        Ok((results, total))
+    }
+
+    /// The first entry within `filter` for each of `doc_ids`, found with one
+    /// query rather than one per id.
+    fn lookup_in_scope(
+        &self,
+        searcher: &tantivy::Searcher,
+        doc_ids: &[&str],
+        filter: &dyn Query,
+    ) -> Result<HashMap<String, TantivyDocument>> {
+        let any_id: Vec<(Occur, Box<dyn Query>)> = doc_ids
+            .iter()
+            .map(|doc_id| {
+                let query: Box<dyn Query> = Box::new(TermQuery::new(
+                    Term::from_field_text(self.doc_id_field, doc_id),
+                    IndexRecordOption::Basic,
+                ));
+                (Occur::Should, query)
+            })
+            .collect();
+        let lookup = BooleanQuery::new(vec![
+            (
+                Occur::Must,
+                Box::new(BooleanQuery::new(any_id)) as Box<dyn Query>,
+            ),
+            (Occur::Must, filter.box_clone()),
+        ]);
+        // Address order, so a doc's first section wins at section granularity
+        let mut addresses: Vec<DocAddress> = searcher
+            .search(&lookup, &DocSetCollector)?
+            .into_iter()
+            .collect();
+        addresses.sort();
+        let mut found = HashMap::new();
+        for address in addresses {
+            let retrieved: TantivyDocument = searcher.doc(address)?;
+            if let Some(doc_id) = retrieved
+                .get_first(self.doc_id_field)
+                .and_then(|v| v.as_str())
+            {
+                if !found.contains_key(doc_id) {
+                    found.insert(doc_id.to_string(), retrieved);
+                }
+            }
+        }
+        Ok(found)
+    }
+
+    /// Build the restriction for a search: entries of the requested
+    /// granularity, within the folder / path-prefix scope if any.
+    fn scope_filter(&self, options: &SearchOptions) -> Box<dyn Query> {
+        let kind: Box<dyn Query> = Box::new(TermQuery::new(
+            Term::from_field_text(self.kind_field, options.granularity.kind()),
+            IndexRecordOption::Basic,
+        ));
+        let mut clauses: Vec<(Occur, Box<dyn Query>)> = vec![(Occur::Must, kind)];
+
+        if let Some(folder) = options.folder.as_deref().map(str::trim) {
+            if !folder.is_empty() {
+                let either: Vec<(Occur, Box<dyn Query>)> =
+                    [self.folder_field, self.folder_id_field]
+                        .into_iter()
+                        .map(|field| {
+                            let query: Box<dyn Query> = Box::new(TermQuery::new(
+                                Term::from_field_text(field, folder),
+                                IndexRecordOption::Basic,
+                            ));
+                            (Occur::Should, query)
+                        })
+                        .collect();
+                clauses.push((Occur::Must, Box::new(BooleanQuery::new(either))));
+            }
+        }
+
+        if let Some(prefix) = options.path_prefix.as_deref() {
+            let terms = path_prefix_query_terms(prefix);
+            if !terms.is_empty() {
+                let any: Vec<(Occur, Box<dyn Query>)> = terms
+                    .into_iter()
+                    .map(|term| {
+                        let query: Box<dyn Query> = Box::new(TermQuery::new(
+                            Term::from_field_text(self.path_prefix_field, &term),
+                            IndexRecordOption::Basic,
+                        ));
+                        (Occur::Should, query)
+                    })
+                    .collect();
+                clauses.push((Occur::Must, Box::new(BooleanQuery::new(any))));
+            }
+        }
+
+        for doc_id in &options.exclude_doc_ids {
+            let query: Box<dyn Query> = Box::new(TermQuery::new(
+                Term::from_field_text(self.doc_id_field, doc_id),
+                IndexRecordOption::Basic,
+            ));
+            clauses.push((Occur::MustNot, query));
+        }
+
+        Box::new(BooleanQuery::new(clauses))
+    }
+
+    /// Build a `SearchResult` from a stored document. Without a snippet
+    /// generator (vector-only hits) the snippet comes from the fallback
+    /// substring search, or the start of the body if no term occurs in it.
+    fn to_result(
+        &self,
+        retrieved: &TantivyDocument,
+        score: f32,
+        snippet_generator: Option<&SnippetGenerator>,
+        query_terms: &[String],
+    ) -> SearchResult {
+        let doc_id = retrieved
+            .get_first(self.doc_id_field)
+            .and_then(|v| v.as_str())
+            .unwrap_or("")
+            .to_string();
+        let title = retrieved
+            .get_first(self.title_field)
+            .and_then(|v| v.as_str())
+            .unwrap_or("")
+            .to_string();
+        let folder = retrieved
+            .get_first(self.folder_field)
+            .and_then(|v| v.as_str())
+            .unwrap_or("")
+            .to_string();
+
+        let path = retrieved
+            .get_first(self.path_field)
+            .and_then(|v| v.as_str())
+            .unwrap_or("")
+            .to_string();
+
+        let (tags, doc_type) = self.stored_frontmatter(retrieved);
+
+        let heading_path: Vec<String> = retrieved
+            .get_all(self.heading_path_field)
+            .filter_map(|v| v.as_str())
+            .map(str::to_string)
+            .collect();
+        let line = retrieved
+            .get_first(self.line_field)
+            .and_then(|v| v.as_u64());
+        // Present on every section entry; empty for the untitled preamble
+        let anchor = retrieved
+            .get_first(self.anchor_field)
+            .and_then(|v| v.as_str())
+            .filter(|a| !a.is_empty())
+            .map(|a| format!("#{}", a));
+        let modified = retrieved
+            .get_first(self.modified_field)
+            .and_then(|v| v.as_u64());
+
+        let body = retrieved
+            .get_first(self.body_field)
+            .and_then(|v| v.as_str())
+            .unwrap_or("");
+
+        let snippet_html = match snippet_generator {
+            Some(generator) => {
+                let snippet = generator.snippet_from_doc(retrieved);
+                if !snippet.highlighted().is_empty() {
+                    render_snippet_with_mark(&snippet, body)
+                } else if query_terms.is_empty() {
+                    // Pure field query (e.g. `tag:lens`): nothing to
+                    // highlight, so show how the document starts.
+                    generate_leading_snippet(body, 200)
+                } else {
+                    // Tantivy found no highlights in the body (likely a title-only match).
+                    // Fall back to manual substring search in the body text.
+                    generate_fallback_snippet(body, query_terms, 200)
+                }
+            }
+            None => {
+                let fallback = generate_fallback_snippet(body, query_terms, 200);
+                if fallback.is_empty() {
+                    generate_leading_snippet(body, 200)
+                } else {
+                    fallback
+                }
+            }
+        };
+
+        SearchResult {
+            doc_id,
+            title,
+            folder,
+            path,
+            tags,
+            doc_type,
+            heading_path,
+            line,
+            anchor,
+            modified,
+            snippet: snippet_html,
+            score,
+        }
+    }
+}
+
+/// Whether opening an index failed because it was written with another
+/// schema.
+fn is_schema_mismatch(error: &anyhow::Error) -> bool {
+    matches!(
+        error.downcast_ref::<tantivy::TantivyError>(),
+        Some(tantivy::TantivyError::SchemaError(_))
+    )
+}
+
+/// Current time in ms since epoch.
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+/// Hex SHA-256 of everything `make_documents` derives entries from.
+fn content_hash(title: &str, body: &str, location: &DocLocation) -> String {
+    let mut hasher = Sha256::new();
+    for part in [
+        INDEX_FORMAT_VERSION,
+        title,
+        body,
+        location.folder.as_str(),
+        location.folder_id.as_str(),
+        location.path.as_str(),
+    ] {
+        // Length-prefixed, so moving text between parts changes the hash
+        hasher.update(part.len().to_le_bytes());
+        hasher.update(part.as_bytes());
+    }
+    data_encoding::HEXLOWER.encode(&hasher.finalize())
+}
+
+/// An embedding as stored in the index: little-endian f32s.
+fn encode_vector(vector: &[f32]) -> Vec<u8> {
+    vector.iter().flat_map(|x| x.to_le_bytes()).collect()
+}
+
+/// Read back an `encode_vector` result, or None if it doesn't hold exactly
+/// `dimensions` values.
+fn decode_vector(bytes: &[u8], dimensions: usize) -> Option<Vec<f32>> {
+    if bytes.len() != dimensions * 4 {
+        return None;
+    }
+    Some(
+        bytes
+            .chunks_exact(4)
+            .map(|chunk| f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+            .collect(),
+    )
+}
+
+/// Normalize an in-folder path to start with "/".
+fn normalize_path(path: &str) -> String {
+    let path = path.trim();
+    if path.starts_with('/') {
+        path.to_string()
+    } else {
+        format!("/{}", path)
+    }
+}
+
+/// Terms indexed into `path_prefix` for a document path: each ancestor
+/// directory with a trailing slash, then the path itself.
+/// "/A/B/c.md" -> ["/A/", "/A/B/", "/A/B/c.md"].
+fn path_prefixes(path: &str) -> Vec<String> {
+    if path.trim().is_empty() {
+        return Vec::new();
+    }
+    let path = normalize_path(path);
+    let mut prefixes: Vec<String> = path
+        .match_indices('/')
+        .skip(1)
+        .map(|(i, _)| path[..=i].to_string())
+        .collect();
+    prefixes.push(path);
+    prefixes
+}
+
+/// Terms a `path_prefix` filter matches: the directory form ("/A/") and the
+/// exact-path form ("/A"). Empty for "/" or "", which scope nothing.
+fn path_prefix_query_terms(prefix: &str) -> Vec<String> {
+    let prefix = normalize_path(prefix);
+    if prefix == "/" {
+        return Vec::new();
+    }
+    if prefix.ends_with('/') {
+        vec![prefix]
+    } else {
+        vec![format!("{}/", prefix), prefix]
+    }
+}
+
+/// Lowercased query terms for fallback snippet generation, without operators
+/// or terms that never occur in the body (`tag:lens`, `-draft`). Embedding
+/// modes accept any text, so an unparseable query falls back to its words.
+fn fallback_query_terms(query: &str) -> Vec<String> {
+    match search_query::parse(query) {
+        Ok(ast) => ast.highlight_terms(),
+        Err(_) => query.split_whitespace().map(str::to_lowercase).collect(),
+    }
+}
+
+/// Escaped leading excerpt of the body, cut at a word boundary, for hits
+/// that matched on meaning rather than on any literal term.
+fn generate_leading_snippet(body: &str, max_chars: usize) -> String {
+    let body = body[frontmatter::body_offset(body)..].trim_start();
+    if body.len() <= max_chars {
+        return escape_html(body);
+    }
+    let safe = snap_char_boundary_backward(body, max_chars);
+    let end = body[..safe].rfind(' ').unwrap_or(safe);
+    format!("{}...", escape_html(&body[..end]))
+}
+
+/// Render a tantivy Snippet using `<mark>` tags instead of the default `<b>` tags.
+/// HTML-escapes non-highlighted text to prevent injection via `dangerouslySetInnerHTML`.
+/// Adds "..." when the fragment is a subset of the full body text.
+fn render_snippet_with_mark(snippet: &tantivy::snippet::Snippet, full_body: &str) -> String {
+    let fragment = snippet.fragment();
+    let highlighted = snippet.highlighted();
+
+    if highlighted.is_empty() {
+        return escape_html(fragment);
+    }
+
+    let mut result = String::new();
+    let mut pos = 0;
+
+    for range in highlighted {
         // Append escaped text before this highlight
         if range.start > pos {
             result.push_str(&escape_html(&fragment[pos..range.start]));
@@ -1051,4 +2529,942 @@ mod tests {
         let results = index.search("tutor", 10).unwrap();
         assert_eq!(results.len(), 1);
     }
+
+    // === Hybrid / semantic search ===
+
+    fn create_hybrid_index() -> SearchIndex {
+        create_index()
+            .with_embedding_provider(Arc::new(crate::embedding::HashingEmbedder::default()))
+    }
+
+    #[test]
+    fn search_mode_from_name_accepts_aliases() {
+        assert_eq!(SearchMode::from_name("BM25"), Some(SearchMode::Bm25));
+        assert_eq!(SearchMode::from_name("hybrid"), Some(SearchMode::Hybrid));
+        assert_eq!(SearchMode::from_name("vector"), Some(SearchMode::Semantic));
+        assert_eq!(SearchMode::from_name("fuzzy"), None);
+    }
+
+    #[test]
+    fn hybrid_search_without_provider_errors() {
+        let index = create_index();
+        index.add_document("doc1", "Doc", "plants", "Lens").unwrap();
+        assert!(index
+            .search_with_mode("plants", 10, SearchMode::Hybrid)
+            .is_err());
+        // BM25 mode never needs a provider
+        assert_eq!(
+            index
+                .search_with_mode("plants", 10, SearchMode::Bm25)
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+
+    #[test]
+    fn hybrid_search_finds_docs_missing_some_query_terms() {
+        // Prevents: conceptual queries returning nothing because BM25's AND
+        // semantics require every word ("how", "make") to appear literally.
+        let index = create_hybrid_index();
+        index
+            .add_document(
+                "doc1",
+                "Photosynthesis",
+                "Plants convert sunlight into chemical energy.",
+                "Lens",
+            )
+            .unwrap();
+        index
+            .add_document(
+                "doc2",
+                "French Revolution",
+                "The revolution began in 1789 with the storming of the Bastille.",
+                "Lens",
+            )
+            .unwrap();
+
+        assert!(index
+            .search("how plants make energy", 10)
+            .unwrap()
+            .is_empty());
+
+        let results = index
+            .search_with_mode("how plants make energy", 10, SearchMode::Hybrid)
+            .unwrap();
+        assert!(!results.is_empty());
+        assert_eq!(results[0].doc_id, "doc1");
+        assert!(
+            results.iter().all(|r| r.doc_id != "doc2"),
+            "unrelated doc should fall below the similarity floor"
+        );
+        assert!(
+            !results[0].snippet.is_empty(),
+            "vector-only hits need a snippet"
+        );
+    }
+
+    #[test]
+    fn hybrid_search_ranks_keyword_and_vector_match_first() {
+        let index = create_hybrid_index();
+        index
+            .add_document(
+                "doc1",
+                "Cell Biology",
+                "Mitochondria produce energy for the cell.",
+                "Lens",
+            )
+            .unwrap();
+        index
+            .add_document("doc2", "Energy", "Plants store energy in sugars.", "Lens")
+            .unwrap();
+        let results = index
+            .search_with_mode("plants energy", 10, SearchMode::Hybrid)
+            .unwrap();
+        assert_eq!(results[0].doc_id, "doc2");
+        assert!(results[0].score <= 1.0, "hybrid scores are normalized");
+    }
+
+    #[test]
+    fn semantic_search_forgets_removed_documents() {
+        let index = create_hybrid_index();
+        index
+            .add_document(
+                "doc1",
+                "Photosynthesis",
+                "Plants convert sunlight into energy.",
+                "Lens",
+            )
+            .unwrap();
+        index.remove_document("doc1").unwrap();
+        let results = index
+            .search_with_mode("plants sunlight", 10, SearchMode::Semantic)
+            .unwrap();
+        assert!(results.is_empty());
+    }
+
+    #[test]
+    fn semantic_search_covers_buffered_documents() {
+        let index = create_hybrid_index();
+        index
+            .add_document_buffered(
+                "doc1",
+                "Photosynthesis",
+                "Plants convert sunlight into energy.",
+                "Lens",
+            )
+            .unwrap();
+        index.flush().unwrap();
+        let results = index
+            .search_with_mode("sunlight plants", 10, SearchMode::Semantic)
+            .unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].title, "Photosynthesis");
+    }
+
+    // === Folder / path scoping ===
+
+    fn location(folder: &str, folder_id: &str, path: &str) -> DocLocation {
+        DocLocation {
+            folder: folder.to_string(),
+            folder_id: folder_id.to_string(),
+            path: path.to_string(),
+        }
+    }
+
+    fn create_scoped_index() -> SearchIndex {
+        let index = create_index();
+        index
+            .add_document_at(
+                "doc1",
+                "Photosynthesis",
+                "Plants and energy.",
+                &location("Lens", "folder-a", "/Biology/Photosynthesis.md"),
+            )
+            .unwrap();
+        index
+            .add_document_at(
+                "doc2",
+                "Respiration",
+                "Cells and energy.",
+                &location("Lens", "folder-a", "/Biology/Cells/Respiration.md"),
+            )
+            .unwrap();
+        index
+            .add_document_at(
+                "doc3",
+                "Energy Policy",
+                "Governments and energy.",
+                &location("Lens Edu", "folder-b", "/Bioethics/Energy Policy.md"),
+            )
+            .unwrap();
+        index
+    }
+
+    fn scoped_ids(
+        index: &SearchIndex,
+        folder: Option<&str>,
+        path_prefix: Option<&str>,
+    ) -> Vec<String> {
+        let options = SearchOptions {
+            folder: folder.map(str::to_string),
+            path_prefix: path_prefix.map(str::to_string),
+            ..SearchOptions::new(10)
+        };
+        let mut ids: Vec<String> = index
+            .search_with_options("energy", &options)
+            .unwrap()
+            .into_iter()
+            .map(|r| r.doc_id)
+            .collect();
+        ids.sort();
+        ids
+    }
+
+    #[test]
+    fn path_prefixes_lists_ancestors_and_path() {
+        assert_eq!(
+            path_prefixes("/A/B/c.md"),
+            vec![
+                "/A/".to_string(),
+                "/A/B/".to_string(),
+                "/A/B/c.md".to_string()
+            ]
+        );
+        assert_eq!(path_prefixes("c.md"), vec!["/c.md".to_string()]);
+        assert!(path_prefixes("").is_empty());
+    }
+
+    #[test]
+    fn folder_filter_matches_name_or_uuid() {
+        let index = create_scoped_index();
+        assert_eq!(scoped_ids(&index, None, None), vec!["doc1", "doc2", "doc3"]);
+        assert_eq!(scoped_ids(&index, Some("Lens"), None), vec!["doc1", "doc2"]);
+        assert_eq!(scoped_ids(&index, Some("folder-b"), None), vec!["doc3"]);
+        assert!(scoped_ids(&index, Some("Nope"), None).is_empty());
+    }
+
+    #[test]
+    fn path_prefix_filter_matches_whole_segments() {
+        // Prevents: "/Bio" leaking "/Bioethics" hits into a "/Bio" folder scope
+        let index = create_scoped_index();
+        assert_eq!(
+            scoped_ids(&index, None, Some("/Biology")),
+            vec!["doc1", "doc2"]
+        );
+        assert_eq!(
+            scoped_ids(&index, None, Some("Biology/Cells/")),
+            vec!["doc2"]
+        );
+        assert!(scoped_ids(&index, None, Some("/Bio")).is_empty());
+        assert_eq!(
+            scoped_ids(&index, None, Some("/Biology/Photosynthesis.md")),
+            vec!["doc1"]
+        );
+        // "/" scopes nothing
+        assert_eq!(scoped_ids(&index, None, Some("/")).len(), 3);
+    }
+
+    #[test]
+    fn folder_and_path_prefix_combine() {
+        let index = create_scoped_index();
+        assert_eq!(
+            scoped_ids(&index, Some("Lens"), Some("/Biology/Cells")),
+            vec!["doc2"]
+        );
+        assert!(scoped_ids(&index, Some("Lens Edu"), Some("/Biology")).is_empty());
+    }
+
+    #[test]
+    fn scoped_search_returns_path() {
+        let index = create_scoped_index();
+        let results = index
+            .search_with_options(
+                "photosynthesis",
+                &SearchOptions {
+                    folder: Some("Lens".into()),
+                    ..SearchOptions::new(10)
+                },
+            )
+            .unwrap();
+        assert_eq!(results[0].path, "/Biology/Photosynthesis.md");
+    }
+
+    #[test]
+    fn semantic_search_respects_scope() {
+        let index = create_scoped_index()
+            .with_embedding_provider(Arc::new(crate::embedding::HashingEmbedder::default()));
+        // Re-add so the docs get vectors now that a provider is attached
+        index
+            .add_document_at(
+                "doc1",
+                "Photosynthesis",
+                "Plants and energy.",
+                &location("Lens", "folder-a", "/Biology/Photosynthesis.md"),
+            )
+            .unwrap();
+        index
+            .add_document_at(
+                "doc3",
+                "Energy Policy",
+                "Governments and energy.",
+                &location("Lens Edu", "folder-b", "/Bioethics/Energy Policy.md"),
+            )
+            .unwrap();
+        let options = SearchOptions {
+            mode: SearchMode::Semantic,
+            folder: Some("folder-b".into()),
+            ..SearchOptions::new(10)
+        };
+        let results = index.search_with_options("energy", &options).unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].doc_id, "doc3");
+    }
+
+    fn create_frontmatter_index() -> SearchIndex {
+        let index = create_index();
+        index
+            .add_document(
+                "doc1",
+                "Alignment Intro",
+                "---\ntype: Article\ntags: [lens, Intro]\nsource_url: https://example.com/a\n---\nAn introduction to alignment.",
+                "Lens",
+            )
+            .unwrap();
+        index
+            .add_document(
+                "doc2",
+                "Alignment Lens",
+                "---\ntype: lens\ntags:\n  - lens\n---\nA lens on alignment.",
+                "Lens",
+            )
+            .unwrap();
+        index
+            .add_document(
+                "doc3",
+                "Alignment Notes",
+                "Notes on alignment without frontmatter.",
+                "Lens",
+            )
+            .unwrap();
+        index
+    }
+
+    fn sorted_ids(results: &[SearchResult]) -> Vec<&str> {
+        let mut ids: Vec<&str> = results.iter().map(|r| r.doc_id.as_str()).collect();
+        ids.sort();
+        ids
+    }
+
+    #[test]
+    fn field_queries_match_frontmatter_case_insensitively() {
+        let index = create_frontmatter_index();
+        let results = index.search("tag:lens", 10).unwrap();
+        assert_eq!(sorted_ids(&results), vec!["doc1", "doc2"]);
+
+        let results = index.search("tag:LENS type:article", 10).unwrap();
+        assert_eq!(sorted_ids(&results), vec!["doc1"]);
+        assert_eq!(results[0].tags, vec!["lens", "intro"]);
+        assert_eq!(results[0].doc_type.as_deref(), Some("article"));
+
+        let results = index
+            .search("source_url:\"https://example.com/a\"", 10)
+            .unwrap();
+        assert_eq!(sorted_ids(&results), vec!["doc1"]);
+    }
+
+    #[test]
+    fn field_query_combines_with_free_text() {
+        let index = create_frontmatter_index();
+        let results = index.search("+alignment +type:lens", 10).unwrap();
+        assert_eq!(sorted_ids(&results), vec!["doc2"]);
+        assert!(
+            results[0].snippet.contains("<mark>alignment</mark>"),
+            "snippet: {}",
+            results[0].snippet
+        );
+    }
+
+    #[test]
+    fn pure_field_query_snippet_skips_frontmatter() {
+        let index = create_frontmatter_index();
+        let results = index.search("type:article", 10).unwrap();
+        assert_eq!(results[0].snippet, "An introduction to alignment.");
+    }
+
+    #[test]
+    fn facets_count_all_matches_not_just_the_page() {
+        let index = create_frontmatter_index();
+        let response = index
+            .search_with_facets("alignment", &SearchOptions::new(1))
+            .unwrap();
+        assert_eq!(response.results.len(), 1);
+        assert_eq!(response.facets.tags.get("lens"), Some(&2));
+        assert_eq!(response.facets.tags.get("intro"), Some(&1));
+        assert_eq!(response.facets.types.get("article"), Some(&1));
+        assert_eq!(response.facets.types.get("lens"), Some(&1));
+    }
+
+    #[test]
+    fn facets_respect_scope_filter() {
+        let index = create_frontmatter_index();
+        index
+            .add_document_at(
+                "doc4",
+                "Alignment Elsewhere",
+                "---\ntags: [other]\n---\nAlignment.",
+                &location("Lens Edu", "folder-b", "/Elsewhere.md"),
+            )
+            .unwrap();
+        let options = SearchOptions {
+            folder: Some("Lens Edu".into()),
+            ..SearchOptions::new(10)
+        };
+        let response = index.search_with_facets("alignment", &options).unwrap();
+        assert_eq!(sorted_ids(&response.results), vec!["doc4"]);
+        assert_eq!(
+            response.facets.tags,
+            BTreeMap::from([("other".to_string(), 1)])
+        );
+        assert!(response.facets.types.is_empty());
+    }
+
+    fn section_options(limit: usize) -> SearchOptions {
+        SearchOptions {
+            granularity: SearchGranularity::Section,
+            ..SearchOptions::new(limit)
+        }
+    }
+
+    const LONG_LENS: &str = "---\ntype: lens\n---\nOverview of the lens.\n\n# Background\nSome history.\n\n## Mesa-optimization\nInner optimizers can pursue proxy goals.\n\n# Exercises\nWrite about reward hacking.\n";
+
+    #[test]
+    fn section_search_returns_heading_path_line_and_anchor() {
+        let index = create_index();
+        index
+            .add_document("doc1", "Alignment Lens", LONG_LENS, "Lens")
+            .unwrap();
+        let results = index
+            .search_with_options("optimizers", &section_options(10))
+            .unwrap();
+        assert_eq!(results.len(), 1);
+        let hit = &results[0];
+        assert_eq!(hit.doc_id, "doc1");
+        assert_eq!(hit.title, "Alignment Lens");
+        assert_eq!(hit.heading_path, vec!["Background", "Mesa-optimization"]);
+        assert_eq!(hit.line, Some(9));
+        assert_eq!(hit.anchor.as_deref(), Some("#mesa-optimization"));
+        assert!(
+            hit.snippet.contains("<mark>optimizers</mark>"),
+            "snippet: {}",
+            hit.snippet
+        );
+    }
+
+    #[test]
+    fn section_search_ranks_sections_not_documents() {
+        // Prevents: long documents drowning section hits, or a single
+        // document showing up once per section in document-level search
+        let index = create_index();
+        index
+            .add_document("doc1", "Alignment Lens", LONG_LENS, "Lens")
+            .unwrap();
+        let docs = index.search("lens", 10).unwrap();
+        assert_eq!(docs.len(), 1, "document search must not return sections");
+        assert!(docs[0].anchor.is_none());
+        assert!(docs[0].heading_path.is_empty());
+
+        // Title terms match every section of the doc; a heading match ranks first
+        let sections = index
+            .search_with_options("lens exercises", &section_options(10))
+            .unwrap();
+        assert_eq!(sections[0].anchor.as_deref(), Some("#exercises"));
+
+        // Untitled preamble has a line but no anchor
+        let preamble = index
+            .search_with_options("overview", &section_options(10))
+            .unwrap();
+        assert_eq!(preamble.len(), 1);
+        assert_eq!(preamble[0].line, Some(4));
+        assert!(preamble[0].anchor.is_none());
+    }
+
+    #[test]
+    fn section_entries_are_replaced_and_removed_with_their_document() {
+        let index = create_index();
+        index
+            .add_document("doc1", "Notes", "# Old heading\nstale text", "Lens")
+            .unwrap();
+        index
+            .add_document("doc1", "Notes", "# New heading\nfresh text", "Lens")
+            .unwrap();
+        assert!(index
+            .search_with_options("stale", &section_options(10))
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            index
+                .search_with_options("fresh", &section_options(10))
+                .unwrap()
+                .len(),
+            1
+        );
+        index.remove_document("doc1").unwrap();
+        assert!(index
+            .search_with_options("fresh", &section_options(10))
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn section_search_requires_bm25_mode() {
+        let index = create_index()
+            .with_embedding_provider(Arc::new(crate::embedding::HashingEmbedder::default()));
+        let options = SearchOptions {
+            mode: SearchMode::Hybrid,
+            ..section_options(10)
+        };
+        assert!(index.search_with_options("anything", &options).is_err());
+    }
+
+    fn create_operator_index() -> SearchIndex {
+        let index = create_index();
+        for (id, title, body) in [
+            (
+                "doc1",
+                "Photosynthesis",
+                "Plants capture light with chlorophyll.",
+            ),
+            (
+                "doc2",
+                "Photography",
+                "Cameras capture light on a sensor. Draft.",
+            ),
+            ("doc3", "Respiration", "Cells release energy from glucose."),
+        ] {
+            index.add_document(id, title, body, "Lens").unwrap();
+        }
+        index
+    }
+
+    fn ids(index: &SearchIndex, query: &str) -> Vec<String> {
+        let mut ids: Vec<String> = index
+            .search(query, 10)
+            .unwrap()
+            .into_iter()
+            .map(|r| r.doc_id)
+            .collect();
+        ids.sort();
+        ids
+    }
+
+    #[test]
+    fn prefix_fuzzy_or_and_exclusion_operators() {
+        let index = create_operator_index();
+        assert_eq!(ids(&index, "photo*"), vec!["doc1", "doc2"]);
+        assert_eq!(ids(&index, "chlorophyl~"), vec!["doc1"]);
+        assert_eq!(ids(&index, "glucose OR chlorophyll"), vec!["doc1", "doc3"]);
+        assert_eq!(ids(&index, "capture -draft"), vec!["doc1"]);
+        assert_eq!(ids(&index, "-capture"), vec!["doc3"]);
+        assert_eq!(ids(&index, "title:photography"), vec!["doc2"]);
+        assert_eq!(ids(&index, "body:photography"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn prefix_match_snippet_uses_fallback_terms() {
+        let index = create_operator_index();
+        let results = index.search("chloro*", 10).unwrap();
+        assert!(
+            results[0].snippet.contains("<mark>chloro</mark>"),
+            "snippet: {}",
+            results[0].snippet
+        );
+    }
+
+    #[test]
+    fn malformed_query_reports_structured_errors() {
+        let index = create_operator_index();
+        let err = index.search("light \"unclosed", 10).unwrap_err();
+        let parse_error = err
+            .downcast_ref::<search_query::QueryParseError>()
+            .expect("parse errors should be a QueryParseError");
+        assert_eq!(parse_error.errors.len(), 1);
+        assert_eq!(parse_error.errors[0].start, 6);
+
+        // Facet search goes through the same parser
+        assert!(index
+            .search_with_facets("author:me", &SearchOptions::new(10))
+            .is_err());
+    }
+
+    #[test]
+    fn punctuation_only_terms_are_ignored() {
+        let index = create_operator_index();
+        assert_eq!(ids(&index, "glucose !!!"), vec!["doc3"]);
+        assert!(ids(&index, "!!!").is_empty());
+    }
+
+    // === Pagination and sorting ===
+
+    /// Four docs that all mention "energy", with known modification times.
+    /// doc4 ("Zymase") has the most occurrences of "energy", so it ranks
+    /// first by relevance.
+    fn create_sortable_index() -> SearchIndex {
+        let index = create_index();
+        let docs = [
+            (
+                "doc1",
+                "Mitochondria",
+                "/Cells/Mitochondria.md",
+                "Energy.",
+                3_000,
+            ),
+            (
+                "doc2",
+                "Chloroplast",
+                "/Plants/Chloroplast.md",
+                "Energy.",
+                1_000,
+            ),
+            ("doc3", "atp", "/Cells/ATP.md", "Energy.", 4_000),
+            (
+                "doc4",
+                "Zymase",
+                "/Archive/Zymase.md",
+                "Energy energy energy.",
+                2_000,
+            ),
+        ];
+        for (id, title, path, body, modified) in docs {
+            index
+                .add_document_buffered_at(
+                    id,
+                    title,
+                    body,
+                    &location("Lens", "folder-a", path),
+                    Some(modified),
+                )
+                .unwrap();
+        }
+        index.flush().unwrap();
+        index
+    }
+
+    fn page_ids(index: &SearchIndex, options: &SearchOptions) -> (Vec<String>, usize) {
+        let (results, total) = index.search_page("energy", options).unwrap();
+        (results.into_iter().map(|r| r.doc_id).collect(), total)
+    }
+
+    #[test]
+    fn offset_pages_through_results_with_total() {
+        let index = create_sortable_index();
+        let mut seen = Vec::new();
+        for offset in [0, 2] {
+            let options = SearchOptions {
+                offset,
+                ..SearchOptions::new(2)
+            };
+            let (ids, total) = page_ids(&index, &options);
+            assert_eq!(total, 4);
+            assert_eq!(ids.len(), 2);
+            seen.extend(ids);
+        }
+        assert_eq!(seen[0], "doc4");
+        seen.sort();
+        assert_eq!(seen, vec!["doc1", "doc2", "doc3", "doc4"]);
+
+        let past_end = SearchOptions {
+            offset: 10,
+            ..SearchOptions::new(2)
+        };
+        assert_eq!(page_ids(&index, &past_end), (Vec::new(), 4));
+
+        let response = index
+            .search_with_facets("energy", &SearchOptions::new(1))
+            .unwrap();
+        assert_eq!(response.results.len(), 1);
+        assert_eq!(response.total_hits, 4);
+    }
+
+    #[test]
+    fn excluded_docs_are_not_counted() {
+        let index = create_sortable_index();
+        let options = SearchOptions {
+            exclude_doc_ids: vec!["doc1".into(), "doc3".into()],
+            ..SearchOptions::new(10)
+        };
+        let (mut ids, total) = page_ids(&index, &options);
+        ids.sort();
+        assert_eq!((ids, total), (vec!["doc2".into(), "doc4".into()], 2));
+    }
+
+    #[test]
+    fn sorts_by_modified_title_and_path() {
+        let index = create_sortable_index();
+        let sorted = |sort: SearchSort, offset: usize| {
+            let options = SearchOptions {
+                sort,
+                offset,
+                ..SearchOptions::new(10)
+            };
+            page_ids(&index, &options).0
+        };
+        assert_eq!(
+            sorted(SearchSort::Modified, 0),
+            vec!["doc3", "doc1", "doc4", "doc2"]
+        );
+        assert_eq!(sorted(SearchSort::Modified, 2), vec!["doc4", "doc2"]);
+        // Case-insensitive: "atp" sorts before "Chloroplast"
+        assert_eq!(
+            sorted(SearchSort::Title, 0),
+            vec!["doc3", "doc2", "doc1", "doc4"]
+        );
+        assert_eq!(
+            sorted(SearchSort::Path, 0),
+            vec!["doc4", "doc3", "doc1", "doc2"]
+        );
+
+        let results = index.search("energy", 10).unwrap();
+        let doc3 = results.iter().find(|r| r.doc_id == "doc3").unwrap();
+        assert_eq!(doc3.modified, Some(4_000));
+    }
+
+    #[test]
+    fn sort_other_than_relevance_requires_bm25() {
+        let index = create_hybrid_index();
+        let options = SearchOptions {
+            mode: SearchMode::Hybrid,
+            sort: SearchSort::Modified,
+            ..SearchOptions::new(10)
+        };
+        assert!(index.search_with_options("energy", &options).is_err());
+        assert_eq!(SearchSort::from_name("Newest"), Some(SearchSort::Modified));
+        assert_eq!(SearchSort::from_name("size"), None);
+    }
+
+    #[test]
+    fn reindexing_unchanged_body_keeps_modified_time() {
+        let index = create_index();
+        let modified = |index: &SearchIndex| index.search("energy", 1).unwrap()[0].modified;
+        index
+            .add_document_buffered_at(
+                "doc1",
+                "Old",
+                "Energy.",
+                &DocLocation::default(),
+                Some(1_000),
+            )
+            .unwrap();
+        index.flush().unwrap();
+
+        // A rename reindexes the same body
+        index
+            .add_document("doc1", "New", "Energy.", "Lens")
+            .unwrap();
+        assert_eq!(modified(&index), Some(1_000));
+
+        index
+            .add_document("doc1", "New", "Energy, edited.", "Lens")
+            .unwrap();
+        assert!(modified(&index).unwrap() > 1_000);
+    }
+
+    // === Persistence and incremental reindex ===
+
+    /// A fresh directory under the system temp dir, removed on drop.
+    struct TempIndexDir(std::path::PathBuf);
+
+    impl TempIndexDir {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!(
+                "search-index-test-{}-{}",
+                name,
+                std::process::id()
+            ));
+            let _ = std::fs::remove_dir_all(&path);
+            TempIndexDir(path)
+        }
+    }
+
+    impl Drop for TempIndexDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn reopened_index_skips_only_unchanged_documents() {
+        let dir = TempIndexDir::new("skip");
+        let lens = location("Lens", "folder-a", "/Photosynthesis.md");
+        {
+            let index = SearchIndex::new(&dir.0).unwrap();
+            index
+                .add_document_at("doc1", "Photosynthesis", "Plants and light.", &lens)
+                .unwrap();
+            index
+                .add_document_at("doc2", "Respiration", "Cells and energy.", &lens)
+                .unwrap();
+        }
+
+        let index = SearchIndex::new(&dir.0).unwrap();
+        assert_eq!(
+            index.indexed_doc_ids().unwrap(),
+            HashSet::from(["doc1".to_string(), "doc2".to_string()])
+        );
+        assert_eq!(index.search("light", 10).unwrap()[0].doc_id, "doc1");
+
+        assert!(index
+            .skip_if_unchanged("doc1", "Photosynthesis", "Plants and light.", &lens)
+            .unwrap());
+        assert!(!index
+            .skip_if_unchanged("doc1", "Photosynthesis", "Plants and sun.", &lens)
+            .unwrap());
+        assert!(!index
+            .skip_if_unchanged("doc1", "Renamed", "Plants and light.", &lens)
+            .unwrap());
+        let moved = location("Lens", "folder-a", "/Biology/Photosynthesis.md");
+        assert!(!index
+            .skip_if_unchanged("doc1", "Photosynthesis", "Plants and light.", &moved)
+            .unwrap());
+        assert!(!index
+            .skip_if_unchanged("doc3", "New", "Unindexed.", &lens)
+            .unwrap());
+    }
+
+    #[test]
+    fn unchanged_since_compares_the_snapshot_time_with_the_entry() {
+        let index = SearchIndex::new_in_memory().unwrap();
+        let lens = location("Lens", "folder-a", "/Photosynthesis.md");
+        index
+            .add_document_at("doc1", "Photosynthesis", "Plants and light.", &lens)
+            .unwrap();
+        let old_snapshot = now_millis() - 10 * STORE_CLOCK_SKEW_MS;
+        let new_snapshot = now_millis();
+
+        assert!(index
+            .unchanged_since("doc1", "Photosynthesis", &lens, old_snapshot)
+            .unwrap());
+        assert!(!index
+            .unchanged_since("doc1", "Photosynthesis", &lens, new_snapshot)
+            .unwrap());
+        assert!(!index
+            .unchanged_since("doc1", "Renamed", &lens, old_snapshot)
+            .unwrap());
+        let moved = location("Lens", "folder-a", "/Biology/Photosynthesis.md");
+        assert!(!index
+            .unchanged_since("doc1", "Photosynthesis", &moved, old_snapshot)
+            .unwrap());
+        assert!(!index
+            .unchanged_since("doc2", "New", &lens, old_snapshot)
+            .unwrap());
+        assert_eq!(
+            index.indexed_folder_ids().unwrap(),
+            HashSet::from(["folder-a".to_string()])
+        );
+    }
+
+    #[test]
+    fn embeddings_survive_a_restart() {
+        let dir = TempIndexDir::new("vectors");
+        let lens = DocLocation::in_folder("Lens");
+        let body = "Plants convert sunlight into energy.";
+        let hashing = || Arc::new(crate::embedding::HashingEmbedder::default());
+        SearchIndex::new(&dir.0)
+            .unwrap()
+            .with_embedding_provider(hashing())
+            .add_document_at("doc1", "Photosynthesis", body, &lens)
+            .unwrap();
+
+        let index = SearchIndex::new(&dir.0)
+            .unwrap()
+            .with_embedding_provider(hashing());
+        let results = index
+            .search_with_mode("sunlight plants", 10, SearchMode::Semantic)
+            .unwrap();
+        assert_eq!(results.len(), 1);
+        assert!(index
+            .skip_if_unchanged("doc1", "Photosynthesis", body, &lens)
+            .unwrap());
+    }
+
+    #[test]
+    fn documents_without_embeddings_from_this_model_are_reindexed() {
+        let dir = TempIndexDir::new("vectors-model");
+        let lens = DocLocation::in_folder("Lens");
+        let body = "Plants convert sunlight into energy.";
+        SearchIndex::new(&dir.0)
+            .unwrap()
+            .add_document_at("doc1", "Photosynthesis", body, &lens)
+            .unwrap();
+
+        // Indexed before a provider was attached: no vector to load.
+        let index = SearchIndex::new(&dir.0)
+            .unwrap()
+            .with_embedding_provider(Arc::new(crate::embedding::HashingEmbedder::new(64)));
+        assert!(index
+            .search_with_mode("sunlight plants", 10, SearchMode::Semantic)
+            .unwrap()
+            .is_empty());
+        assert!(!index
+            .skip_if_unchanged("doc1", "Photosynthesis", body, &lens)
+            .unwrap());
+        index
+            .add_document_at("doc1", "Photosynthesis", body, &lens)
+            .unwrap();
+        drop(index);
+
+        // A different model ignores the stored vector.
+        let index = SearchIndex::new(&dir.0)
+            .unwrap()
+            .with_embedding_provider(Arc::new(crate::embedding::HashingEmbedder::default()));
+        assert!(!index
+            .skip_if_unchanged("doc1", "Photosynthesis", body, &lens)
+            .unwrap());
+    }
+
+    #[test]
+    fn blended_search_finds_vector_hits_beyond_one_lookup_batch() {
+        // Prevents: the batched vector-only lookup dropping hits after the
+        // first batch, or letting out-of-scope docs use up the candidates.
+        let index = SearchIndex::new_in_memory()
+            .unwrap()
+            .with_embedding_provider(Arc::new(crate::embedding::HashingEmbedder::default()));
+        for i in 0..VECTOR_LOOKUP_BATCH + 20 {
+            let folder = if i % 2 == 0 { "Lens" } else { "Other" };
+            index
+                .add_document_buffered(
+                    &format!("doc{i}"),
+                    &format!("Photosynthesis {i}"),
+                    "Plants convert sunlight into energy.",
+                    folder,
+                )
+                .unwrap();
+        }
+        index.flush().unwrap();
+        let options = SearchOptions {
+            mode: SearchMode::Semantic,
+            folder: Some("Lens".into()),
+            ..SearchOptions::new(200)
+        };
+        let (results, total) = index.search_page("sunlight plants", &options).unwrap();
+        assert_eq!(total, (VECTOR_LOOKUP_BATCH + 20) / 2);
+        assert_eq!(results.len(), total);
+        assert!(results.iter().all(|r| r.folder == "Lens"));
+    }
+
+    #[test]
+    fn index_with_outdated_schema_is_rebuilt() {
+        let dir = TempIndexDir::new("schema");
+        std::fs::create_dir_all(&dir.0).unwrap();
+        let mut old_schema = Schema::builder();
+        old_schema.add_text_field("doc_id", STRING | STORED);
+        Index::create_in_dir(&dir.0, old_schema.build()).unwrap();
+
+        let index = SearchIndex::new(&dir.0).unwrap();
+        assert!(index.indexed_doc_ids().unwrap().is_empty());
+        index
+            .add_document("doc1", "Photosynthesis", "Plants and light.", "Lens")
+            .unwrap();
+        assert_eq!(index.search("light", 10).unwrap().len(), 1);
+    }
 }
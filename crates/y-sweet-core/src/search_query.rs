@@ -0,0 +1,570 @@
+//! Query language for `SearchIndex` (see docs/search-query-language.md).
+//!
+//! ```text
+//! photosynthesis light     both terms (AND is the default)
+//! "light reactions"        phrase; "light reactions"~2 allows 2 words of slop
+//! photo*                   prefix
+//! photosinthesis~          fuzzy, 1 edit (~2 for two)
+//! chlorophyll OR carotene  either term
+//! -draft                   exclude
+//! tag:lens type:article    field-qualified (title, body, heading, tag, type, source_url)
+//! ```
+//!
+//! Parsing is strict: every malformed part is reported with its position
+//! instead of being dropped, so callers can show the user what to fix.
+
+use serde::Serialize;
+use std::fmt;
+
+/// Fields a query may name explicitly, e.g. `tag:lens`.
+pub const QUERY_FIELDS: &[&str] = &["title", "body", "heading", "tag", "type", "source_url"];
+
+/// Largest edit distance accepted by `term~N`.
+pub const MAX_FUZZY_DISTANCE: u8 = 2;
+
+/// A parsed query: every clause must match (or, if excluded, must not).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueryAst {
+    pub clauses: Vec<Clause>,
+}
+
+/// One AND-ed clause. Several alternatives mean `a OR b OR ...`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Clause {
+    pub exclude: bool,
+    pub alternatives: Vec<Atom>,
+}
+
+/// A single term or phrase, optionally restricted to one field.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Atom {
+    /// One of `QUERY_FIELDS`, or None for the default fields.
+    pub field: Option<String>,
+    pub kind: AtomKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AtomKind {
+    Term {
+        text: String,
+        /// `term*`
+        prefix: bool,
+        /// `term~` / `term~N`
+        fuzzy: Option<u8>,
+    },
+    Phrase {
+        text: String,
+        /// `"a b"~N`
+        slop: u32,
+    },
+}
+
+/// One malformed part of a query. Offsets are in characters (not bytes)
+/// into the query string, end exclusive.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct QuerySyntaxError {
+    pub message: String,
+    pub start: usize,
+    pub end: usize,
+}
+
+/// Every syntax error found in a query.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct QueryParseError {
+    pub errors: Vec<QuerySyntaxError>,
+}
+
+impl fmt::Display for QueryParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid search query: ")?;
+        for (i, error) in self.errors.iter().enumerate() {
+            if i > 0 {
+                write!(f, "; ")?;
+            }
+            write!(f, "{} (at {})", error.message, error.start)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for QueryParseError {}
+
+impl QueryAst {
+    /// Lowercased words the query asks to find in the body, used to locate
+    /// a snippet when tantivy's highlighter finds nothing. Excluded clauses
+    /// and fields other than the default/body ones are skipped.
+    pub fn highlight_terms(&self) -> Vec<String> {
+        let mut terms = Vec::new();
+        for clause in self.clauses.iter().filter(|c| !c.exclude) {
+            for atom in &clause.alternatives {
+                if !matches!(atom.field.as_deref(), None | Some("body")) {
+                    continue;
+                }
+                let text = match &atom.kind {
+                    AtomKind::Term { text, .. } | AtomKind::Phrase { text, .. } => text,
+                };
+                terms.extend(text.split_whitespace().map(str::to_lowercase));
+            }
+        }
+        terms
+    }
+}
+
+/// Parse a query. Empty or whitespace-only input yields no clauses.
+pub fn parse(query: &str) -> Result<QueryAst, QueryParseError> {
+    Parser::new(query).run()
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    clauses: Vec<Clause>,
+    errors: Vec<QuerySyntaxError>,
+}
+
+impl Parser {
+    fn new(query: &str) -> Self {
+        Self {
+            chars: query.chars().collect(),
+            pos: 0,
+            clauses: Vec::new(),
+            errors: Vec::new(),
+        }
+    }
+
+    fn run(mut self) -> Result<QueryAst, QueryParseError> {
+        // Start of a pending `OR` keyword, waiting for its right-hand side
+        let mut pending_or: Option<usize> = None;
+
+        loop {
+            self.skip_whitespace();
+            let Some(c) = self.peek() else { break };
+            let start = self.pos;
+
+            if c == '(' || c == ')' {
+                self.pos += 1;
+                self.error("Parentheses are not supported", start, self.pos);
+                continue;
+            }
+
+            let word = self.peek_word();
+            if word == "OR" {
+                self.pos += 2;
+                if pending_or.is_some() || self.clauses.is_empty() {
+                    self.error("'OR' needs a term on each side", start, self.pos);
+                } else {
+                    pending_or = Some(start);
+                }
+                continue;
+            }
+            if word == "AND" {
+                self.pos += 3; // AND is the default, so the keyword is a no-op
+                continue;
+            }
+
+            let exclude = match c {
+                '-' | '+' => {
+                    self.pos += 1;
+                    if self.peek().is_none_or(char::is_whitespace) {
+                        self.error(
+                            &format!("'{}' must be followed by a term", c),
+                            start,
+                            self.pos,
+                        );
+                        continue;
+                    }
+                    c == '-'
+                }
+                _ => false,
+            };
+
+            let Some(atom) = self.parse_atom() else {
+                continue;
+            };
+
+            match pending_or.take() {
+                Some(or_start) => {
+                    let previous = self.clauses.last_mut().expect("OR follows a clause");
+                    if exclude || previous.exclude {
+                        self.error("'-' cannot be combined with OR", or_start, self.pos);
+                    } else {
+                        previous.alternatives.push(atom);
+                    }
+                }
+                None => self.clauses.push(Clause {
+                    exclude,
+                    alternatives: vec![atom],
+                }),
+            }
+        }
+
+        if let Some(or_start) = pending_or {
+            self.error("'OR' needs a term on each side", or_start, or_start + 2);
+        }
+
+        if self.errors.is_empty() {
+            Ok(QueryAst {
+                clauses: self.clauses,
+            })
+        } else {
+            Err(QueryParseError {
+                errors: self.errors,
+            })
+        }
+    }
+
+    /// Parse `[field:](term|"phrase")[*|~N]` at the current position.
+    fn parse_atom(&mut self) -> Option<Atom> {
+        let start = self.pos;
+        let mut field = None;
+
+        if self.peek() != Some('"') {
+            let word = self.peek_word();
+            if let Some((name, _)) = word.split_once(':') {
+                if is_identifier(name) {
+                    let name_end = start + name.chars().count();
+                    self.pos = name_end + 1;
+                    if !QUERY_FIELDS.contains(&name) {
+                        self.error(
+                            &format!(
+                                "Unknown field '{}' (expected one of: {})",
+                                name,
+                                QUERY_FIELDS.join(", ")
+                            ),
+                            start,
+                            name_end,
+                        );
+                        self.skip_word();
+                        return None;
+                    }
+                    if self.peek().is_none_or(char::is_whitespace) {
+                        self.error(&format!("Missing value after '{}:'", name), start, self.pos);
+                        return None;
+                    }
+                    field = Some(name.to_string());
+                }
+            }
+        }
+
+        let kind = if self.peek() == Some('"') {
+            self.parse_phrase(start)?
+        } else {
+            self.parse_term(start)?
+        };
+        Some(Atom { field, kind })
+    }
+
+    fn parse_phrase(&mut self, start: usize) -> Option<AtomKind> {
+        self.pos += 1; // opening quote
+        let text_start = self.pos;
+        while self.peek().is_some_and(|c| c != '"') {
+            self.pos += 1;
+        }
+        if self.peek().is_none() {
+            self.error("Unterminated phrase: missing closing '\"'", start, self.pos);
+            return None;
+        }
+        let text: String = self.chars[text_start..self.pos].iter().collect();
+        self.pos += 1; // closing quote
+
+        let suffix_start = self.pos;
+        let suffix = self.take_word();
+        let slop = match suffix.strip_prefix('~') {
+            _ if suffix.is_empty() => 0,
+            Some(n) => match n.parse::<u32>() {
+                Ok(slop) => slop,
+                Err(_) => {
+                    self.error(
+                        "Phrase slop must be a number, e.g. \"a b\"~2",
+                        suffix_start,
+                        self.pos,
+                    );
+                    return None;
+                }
+            },
+            None if suffix == "*" => {
+                self.error(
+                    "Prefix search is only supported on single terms",
+                    start,
+                    self.pos,
+                );
+                return None;
+            }
+            None => {
+                self.error("Unexpected text after phrase", suffix_start, self.pos);
+                return None;
+            }
+        };
+
+        if text.trim().is_empty() {
+            self.error("Empty phrase", start, self.pos);
+            return None;
+        }
+        Some(AtomKind::Phrase { text, slop })
+    }
+
+    fn parse_term(&mut self, start: usize) -> Option<AtomKind> {
+        let raw = self.take_word();
+        let (text, fuzzy) = match raw.split_once('~') {
+            None => (raw.as_str(), None),
+            Some((text, distance)) => {
+                let distance = if distance.is_empty() {
+                    Some(1)
+                } else {
+                    distance
+                        .parse::<u8>()
+                        .ok()
+                        .filter(|d| (1..=MAX_FUZZY_DISTANCE).contains(d))
+                };
+                match distance {
+                    Some(d) => (text, Some(d)),
+                    None => {
+                        self.error(
+                            &format!("Fuzzy distance must be 1 or {}", MAX_FUZZY_DISTANCE),
+                            start,
+                            self.pos,
+                        );
+                        return None;
+                    }
+                }
+            }
+        };
+        let (text, prefix) = match text.strip_suffix('*') {
+            Some(stem) => (stem, true),
+            None => (text, false),
+        };
+
+        if text.contains('*') {
+            self.error(
+                "Wildcards are only supported at the end of a term",
+                start,
+                self.pos,
+            );
+            return None;
+        }
+        if prefix && fuzzy.is_some() {
+            self.error(
+                "A term cannot be both prefix (*) and fuzzy (~)",
+                start,
+                self.pos,
+            );
+            return None;
+        }
+        if text.is_empty() {
+            self.error("Empty term", start, self.pos);
+            return None;
+        }
+        Some(AtomKind::Term {
+            text: text.to_string(),
+            prefix,
+            fuzzy,
+        })
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    /// The run of characters from the current position up to whitespace, a
+    /// quote or a parenthesis, without consuming it.
+    fn peek_word(&self) -> String {
+        self.chars[self.pos..]
+            .iter()
+            .take_while(|c| !is_word_boundary(**c))
+            .collect()
+    }
+
+    fn take_word(&mut self) -> String {
+        let word = self.peek_word();
+        self.pos += word.chars().count();
+        word
+    }
+
+    fn skip_word(&mut self) {
+        self.take_word();
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.pos += 1;
+        }
+    }
+
+    fn error(&mut self, message: &str, start: usize, end: usize) {
+        self.errors.push(QuerySyntaxError {
+            message: message.to_string(),
+            start,
+            end,
+        });
+    }
+}
+
+fn is_word_boundary(c: char) -> bool {
+    c.is_whitespace() || c == '"' || c == '(' || c == ')'
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn term(text: &str) -> Atom {
+        Atom {
+            field: None,
+            kind: AtomKind::Term {
+                text: text.to_string(),
+                prefix: false,
+                fuzzy: None,
+            },
+        }
+    }
+
+    fn clause(exclude: bool, alternatives: Vec<Atom>) -> Clause {
+        Clause {
+            exclude,
+            alternatives,
+        }
+    }
+
+    fn messages(query: &str) -> Vec<String> {
+        parse(query)
+            .unwrap_err()
+            .errors
+            .into_iter()
+            .map(|e| e.message)
+            .collect()
+    }
+
+    #[test]
+    fn parses_terms_or_groups_and_exclusions() {
+        let ast = parse("cells energy OR atp -draft AND +plants").unwrap();
+        assert_eq!(
+            ast.clauses,
+            vec![
+                clause(false, vec![term("cells")]),
+                clause(false, vec![term("energy"), term("atp")]),
+                clause(true, vec![term("draft")]),
+                clause(false, vec![term("plants")]),
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_prefix_fuzzy_phrase_and_fields() {
+        let ast = parse("photo* photosinthesis~ colour~2 \"light reactions\"~1 tag:lens source_url:https://x.org/a").unwrap();
+        let kinds: Vec<(Option<&str>, &AtomKind)> = ast
+            .clauses
+            .iter()
+            .map(|c| (c.alternatives[0].field.as_deref(), &c.alternatives[0].kind))
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                (
+                    None,
+                    &AtomKind::Term {
+                        text: "photo".into(),
+                        prefix: true,
+                        fuzzy: None
+                    }
+                ),
+                (
+                    None,
+                    &AtomKind::Term {
+                        text: "photosinthesis".into(),
+                        prefix: false,
+                        fuzzy: Some(1)
+                    }
+                ),
+                (
+                    None,
+                    &AtomKind::Term {
+                        text: "colour".into(),
+                        prefix: false,
+                        fuzzy: Some(2)
+                    }
+                ),
+                (
+                    None,
+                    &AtomKind::Phrase {
+                        text: "light reactions".into(),
+                        slop: 1
+                    }
+                ),
+                (
+                    Some("tag"),
+                    &AtomKind::Term {
+                        text: "lens".into(),
+                        prefix: false,
+                        fuzzy: None
+                    }
+                ),
+                (
+                    Some("source_url"),
+                    &AtomKind::Term {
+                        text: "https://x.org/a".into(),
+                        prefix: false,
+                        fuzzy: None
+                    }
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_every_malformed_part_with_positions() {
+        let err = parse("ok \"unclosed").unwrap_err();
+        assert_eq!(
+            err.errors,
+            vec![QuerySyntaxError {
+                message: "Unterminated phrase: missing closing '\"'".into(),
+                start: 3,
+                end: 12,
+            }]
+        );
+
+        assert_eq!(
+            messages("OR a b OR - colour~5 ph*to (x) author:me tag:"),
+            vec![
+                "'OR' needs a term on each side",
+                "'-' must be followed by a term",
+                "Fuzzy distance must be 1 or 2",
+                "Wildcards are only supported at the end of a term",
+                "Parentheses are not supported",
+                "Parentheses are not supported",
+                "Unknown field 'author' (expected one of: title, body, heading, tag, type, source_url)",
+                "Missing value after 'tag:'",
+            ]
+        );
+        assert_eq!(messages("a OR -b"), vec!["'-' cannot be combined with OR"]);
+        assert_eq!(messages("a OR"), vec!["'OR' needs a term on each side"]);
+    }
+
+    #[test]
+    fn positions_count_characters_not_bytes() {
+        let err = parse("über (").unwrap_err();
+        assert_eq!(err.errors[0].start, 5);
+    }
+
+    #[test]
+    fn non_identifier_colons_are_plain_text() {
+        let ast = parse("10:30").unwrap();
+        assert_eq!(ast.clauses, vec![clause(false, vec![term("10:30")])]);
+    }
+
+    #[test]
+    fn highlight_terms_skip_exclusions_and_metadata_fields() {
+        let ast = parse("photo* \"light reactions\" -draft tag:lens body:Leaf").unwrap();
+        assert_eq!(
+            ast.highlight_terms(),
+            vec!["photo", "light", "reactions", "leaf"]
+        );
+    }
+}
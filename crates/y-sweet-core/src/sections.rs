@@ -0,0 +1,209 @@
+//! Splits markdown docs into heading-delimited sections for section-level
+//! search.
+//!
+//! A section runs from an ATX heading (`#` .. `######`) to the next heading of
+//! any level, so a parent section holds only the text before its first
+//! subsection. Text before the first heading forms an untitled preamble
+//! section. Headings inside fenced code blocks are ignored, and the
+//! frontmatter block is skipped.
+
+use crate::frontmatter;
+use std::collections::HashMap;
+
+/// One heading-delimited slice of a markdown doc.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Section {
+    /// Headings from the outermost ancestor down to this section's own
+    /// heading. Empty for the preamble.
+    pub heading_path: Vec<String>,
+    /// 1-based line of the heading (of the first content line for the
+    /// preamble) in the full doc text, frontmatter included.
+    pub line: usize,
+    /// URL fragment for the heading, without the `#`. Empty for the preamble.
+    pub anchor: String,
+    /// The section's text, excluding its heading line.
+    pub text: String,
+}
+
+impl Section {
+    /// The section's own heading, or "" for the preamble.
+    pub fn heading(&self) -> &str {
+        self.heading_path.last().map(String::as_str).unwrap_or("")
+    }
+}
+
+/// Split `markdown` into sections in document order. A blank preamble is
+/// omitted; headings are always kept, even with no text under them.
+pub fn split(markdown: &str) -> Vec<Section> {
+    let offset = frontmatter::body_offset(markdown);
+    let first_line = markdown[..offset].lines().count() + 1;
+
+    let mut sections = Vec::new();
+    let mut stack: Vec<(usize, String)> = Vec::new();
+    let mut slugs = HeadingSlugs::default();
+    let mut current = Section {
+        heading_path: Vec::new(),
+        line: first_line,
+        anchor: String::new(),
+        text: String::new(),
+    };
+    let mut fence: Option<&str> = None;
+
+    for (index, line) in markdown[offset..].lines().enumerate() {
+        let trimmed = line.trim_start();
+        if let Some(marker) = fence {
+            if trimmed.starts_with(marker) {
+                fence = None;
+            }
+        } else if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            fence = Some(&trimmed[..3]);
+        } else if let Some((level, heading)) = parse_heading(line) {
+            push_section(&mut sections, current);
+            while stack.last().is_some_and(|(l, _)| *l >= level) {
+                stack.pop();
+            }
+            stack.push((level, heading.to_string()));
+            current = Section {
+                heading_path: stack.iter().map(|(_, h)| h.clone()).collect(),
+                line: first_line + index,
+                anchor: slugs.next(heading),
+                text: String::new(),
+            };
+            continue;
+        }
+        current.text.push_str(line);
+        current.text.push('\n');
+    }
+    push_section(&mut sections, current);
+    sections
+}
+
+fn push_section(sections: &mut Vec<Section>, section: Section) {
+    if section.heading_path.is_empty() && section.text.trim().is_empty() {
+        return;
+    }
+    sections.push(section);
+}
+
+/// Parse an ATX heading line into (level, text). Allows up to three spaces
+/// of indentation and strips an optional closing `#` sequence.
+fn parse_heading(line: &str) -> Option<(usize, &str)> {
+    let indent = line.len() - line.trim_start_matches(' ').len();
+    if indent > 3 {
+        return None;
+    }
+    let rest = &line[indent..];
+    let level = rest.len() - rest.trim_start_matches('#').len();
+    if !(1..=6).contains(&level) {
+        return None;
+    }
+    let rest = &rest[level..];
+    if !rest.is_empty() && !rest.starts_with([' ', '\t']) {
+        return None; // "#tag", not a heading
+    }
+    let text = rest.trim();
+    let text = match text.trim_end_matches('#') {
+        stripped if stripped.is_empty() || stripped.ends_with([' ', '\t']) => stripped.trim_end(),
+        _ => text,
+    };
+    if text.is_empty() {
+        return None;
+    }
+    Some((level, text))
+}
+
+/// URL fragment for a heading: lowercased, punctuation dropped, whitespace
+/// runs turned into `-` (the GitHub convention).
+pub fn heading_slug(heading: &str) -> String {
+    let mut slug = String::with_capacity(heading.len());
+    let mut pending_dash = false;
+    for c in heading.trim().chars() {
+        if c.is_alphanumeric() || c == '-' || c == '_' {
+            if pending_dash && !slug.is_empty() {
+                slug.push('-');
+            }
+            pending_dash = false;
+            slug.extend(c.to_lowercase());
+        } else if c.is_whitespace() {
+            pending_dash = true;
+        }
+    }
+    slug
+}
+
+/// Hands out unique slugs within one doc: a repeated heading gets `-1`,
+/// `-2`, ... appended, as GitHub does.
+#[derive(Default)]
+struct HeadingSlugs {
+    seen: HashMap<String, usize>,
+}
+
+impl HeadingSlugs {
+    fn next(&mut self, heading: &str) -> String {
+        let slug = heading_slug(heading);
+        let count = self.seen.entry(slug.clone()).or_insert(0);
+        let unique = if *count == 0 {
+            slug
+        } else {
+            format!("{}-{}", slug, count)
+        };
+        *count += 1;
+        unique
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_on_headings_with_paths_and_lines() {
+        let doc = "Intro text\n# Cells\nAbout cells\n## Membrane\nLipids\n# Energy\nATP\n";
+        let sections = split(doc);
+        let summary: Vec<(Vec<String>, usize, &str)> = sections
+            .iter()
+            .map(|s| (s.heading_path.clone(), s.line, s.text.as_str()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (vec![], 1, "Intro text\n"),
+                (vec!["Cells".to_string()], 2, "About cells\n"),
+                (
+                    vec!["Cells".to_string(), "Membrane".to_string()],
+                    4,
+                    "Lipids\n"
+                ),
+                (vec!["Energy".to_string()], 6, "ATP\n"),
+            ]
+        );
+        assert_eq!(sections[2].heading(), "Membrane");
+        assert_eq!(sections[2].anchor, "membrane");
+    }
+
+    #[test]
+    fn skips_frontmatter_and_counts_its_lines() {
+        let sections = split("---\ntype: lens\n---\n# Title\nBody\n");
+        assert_eq!(sections.len(), 1);
+        assert_eq!(sections[0].heading_path, vec!["Title"]);
+        assert_eq!(sections[0].line, 4);
+    }
+
+    #[test]
+    fn ignores_headings_in_code_fences_and_hashtags() {
+        let sections = split("# Real\n```\n# not a heading\n```\n#tag line\n");
+        assert_eq!(sections.len(), 1);
+        assert!(sections[0].text.contains("# not a heading"));
+        assert!(sections[0].text.contains("#tag line"));
+    }
+
+    #[test]
+    fn slugs_are_github_style_and_unique() {
+        assert_eq!(heading_slug("What is AI Safety?"), "what-is-ai-safety");
+        assert_eq!(heading_slug("  Step 1: set-up  "), "step-1-set-up");
+        let sections = split("# Notes\n# Notes\n## Closing ##\n");
+        let anchors: Vec<&str> = sections.iter().map(|s| s.anchor.as_str()).collect();
+        assert_eq!(anchors, vec!["notes", "notes-1", "closing"]);
+        assert_eq!(sections[2].heading(), "Closing");
+    }
+}
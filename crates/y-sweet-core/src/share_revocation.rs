@@ -0,0 +1,244 @@
+//! Revocation list for share tokens.
+//!
+//! A share token is valid until its expiry by construction, so a leaked link
+//! can only be cut off server-side. The list, kept in the store at
+//! `.config/share_revocations.json`, names single tokens by fingerprint (a
+//! hash of the token, so the list never holds usable credentials) and whole
+//! folders by cutoff: every token for the folder issued before the cutoff.
+//! v1 tokens do not record when they were issued, so a cutoff can't tell
+//! the leaked ones from any minted after it; cutoffs skip them and a v1
+//! token is revoked by fingerprint only.
+
+use crate::share_token::ShareTokenPayload;
+use crate::store::Store;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// Store key of the revocation list.
+pub const REVOCATIONS_CONFIG_KEY: &str = ".config/share_revocations.json";
+
+/// Hex chars of SHA-256 kept in a fingerprint.
+const FINGERPRINT_LEN: usize = 32;
+
+/// Stable identifier of a token for revocation: a truncated SHA-256 of the
+/// token string, in hex.
+pub fn token_fingerprint(token: &str) -> String {
+    let digest = Sha256::digest(token.trim().as_bytes());
+    let hex: String = digest.iter().map(|b| format!("{:02x}", b)).collect();
+    hex[..FINGERPRINT_LEN].to_string()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RevokedToken {
+    pub fingerprint: String,
+    /// Expiry of the revoked token (unix seconds), when known; the entry
+    /// can be dropped once it passes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expiry: Option<u32>,
+    /// When the token was revoked (unix seconds).
+    pub revoked_at: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RevokedFolder {
+    /// Folder UUID, or the all-folders sentinel for all-folder tokens.
+    pub folder: String,
+    /// Tokens issued before this (unix seconds) are revoked. Tokens that
+    /// don't record an issue time (v1) are not covered.
+    pub issued_before: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RevocationList {
+    #[serde(default)]
+    pub tokens: Vec<RevokedToken>,
+    #[serde(default)]
+    pub folders: Vec<RevokedFolder>,
+}
+
+impl RevocationList {
+    pub fn is_empty(&self) -> bool {
+        self.tokens.is_empty() && self.folders.is_empty()
+    }
+
+    /// Whether `token`, which decoded to `payload`, is revoked.
+    /// `issued_at` is when the token was signed, if it says.
+    pub fn is_revoked(
+        &self,
+        token: &str,
+        payload: &ShareTokenPayload,
+        issued_at: Option<u64>,
+    ) -> bool {
+        if self.is_empty() {
+            return false;
+        }
+        let folder_revoked = self.folders.iter().any(|rule| {
+            rule.folder.eq_ignore_ascii_case(&payload.folder)
+                && issued_at.is_some_and(|issued| issued < rule.issued_before)
+        });
+        if folder_revoked {
+            return true;
+        }
+        let fingerprint = token_fingerprint(token);
+        self.tokens
+            .iter()
+            .any(|entry| entry.fingerprint == fingerprint)
+    }
+
+    /// Add a token entry unless its fingerprint is already listed.
+    pub fn revoke_token(&mut self, entry: RevokedToken) -> bool {
+        if self
+            .tokens
+            .iter()
+            .any(|existing| existing.fingerprint == entry.fingerprint)
+        {
+            return false;
+        }
+        self.tokens.push(entry);
+        true
+    }
+
+    /// Add a folder cutoff, keeping only the latest cutoff per folder.
+    pub fn revoke_folder(&mut self, rule: RevokedFolder) {
+        match self
+            .folders
+            .iter_mut()
+            .find(|existing| existing.folder.eq_ignore_ascii_case(&rule.folder))
+        {
+            Some(existing) => {
+                if rule.issued_before > existing.issued_before {
+                    *existing = rule;
+                }
+            }
+            None => self.folders.push(rule),
+        }
+    }
+
+    /// Drop token entries whose token has expired by `now` (unix seconds).
+    /// Returns how many were dropped.
+    pub fn prune_expired(&mut self, now: u64) -> usize {
+        let before = self.tokens.len();
+        self.tokens
+            .retain(|entry| entry.expiry.map_or(true, |expiry| u64::from(expiry) >= now));
+        before - self.tokens.len()
+    }
+
+    /// Reject fingerprints that are not lowercase hex of the right length.
+    pub fn validate(&self) -> Result<(), String> {
+        for entry in &self.tokens {
+            if entry.fingerprint.len() != FINGERPRINT_LEN
+                || !entry
+                    .fingerprint
+                    .chars()
+                    .all(|c| c.is_ascii_digit() || ('a'..='f').contains(&c))
+            {
+                return Err(format!(
+                    "invalid fingerprint '{}' (expected {} lowercase hex chars)",
+                    entry.fingerprint, FINGERPRINT_LEN
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Read the list from the store; empty when none is saved.
+pub async fn load_revocations(store: &dyn Store) -> Result<RevocationList> {
+    let Some(data) = store.get(REVOCATIONS_CONFIG_KEY).await? else {
+        return Ok(RevocationList::default());
+    };
+    let list: RevocationList = serde_json::from_slice(&data)
+        .with_context(|| format!("Invalid {}", REVOCATIONS_CONFIG_KEY))?;
+    list.validate().map_err(anyhow::Error::msg)?;
+    Ok(list)
+}
+
+/// Validate `list` and write it to the store.
+pub async fn save_revocations(store: &dyn Store, list: &RevocationList) -> Result<()> {
+    list.validate().map_err(anyhow::Error::msg)?;
+    store
+        .set(REVOCATIONS_CONFIG_KEY, serde_json::to_vec_pretty(list)?)
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::share_token::{SharePurpose, ShareRole};
+
+    const FOLDER: &str = "fbd5eb54-73cc-41b0-ac28-2b93d3b4244e";
+
+    fn payload() -> ShareTokenPayload {
+        ShareTokenPayload {
+            purpose: SharePurpose::Share,
+            role: ShareRole::Edit,
+            folder: FOLDER.to_string(),
+            expiry: 2_000_000_000,
+            user: None,
+            issued_at: None,
+        }
+    }
+
+    #[test]
+    fn fingerprint_is_stable_and_short() {
+        let fingerprint = token_fingerprint("abc");
+        assert_eq!(fingerprint.len(), FINGERPRINT_LEN);
+        assert_eq!(fingerprint, token_fingerprint(" abc\n"));
+        assert_ne!(fingerprint, token_fingerprint("abd"));
+    }
+
+    #[test]
+    fn revokes_by_fingerprint() {
+        let mut list = RevocationList::default();
+        assert!(list.revoke_token(RevokedToken {
+            fingerprint: token_fingerprint("leaked"),
+            expiry: Some(2_000_000_000),
+            revoked_at: 1,
+            note: None,
+        }));
+        assert!(list.is_revoked("leaked", &payload(), None));
+        assert!(!list.is_revoked("other", &payload(), None));
+        assert!(list.validate().is_ok());
+    }
+
+    #[test]
+    fn folder_cutoff_spares_later_tokens() {
+        let mut list = RevocationList::default();
+        list.revoke_folder(RevokedFolder {
+            folder: FOLDER.to_uppercase(),
+            issued_before: 1_000,
+            note: None,
+        });
+        assert!(list.is_revoked("t", &payload(), Some(999)));
+        assert!(!list.is_revoked("t", &payload(), Some(1_000)));
+        // No issue time (v1): not covered, or a cutoff would revoke every
+        // v1 token for the folder forever.
+        assert!(!list.is_revoked("t", &payload(), None));
+        let other = ShareTokenPayload {
+            folder: "00000000-0000-0000-0000-000000000001".into(),
+            ..payload()
+        };
+        assert!(!list.is_revoked("t", &other, Some(999)));
+    }
+
+    #[test]
+    fn prunes_expired_entries() {
+        let mut list = RevocationList::default();
+        for (token, expiry) in [("a", Some(10)), ("b", Some(100)), ("c", None)] {
+            list.revoke_token(RevokedToken {
+                fingerprint: token_fingerprint(token),
+                expiry,
+                revoked_at: 1,
+                note: None,
+            });
+        }
+        assert_eq!(list.prune_expired(50), 1);
+        assert_eq!(list.tokens.len(), 2);
+    }
+}
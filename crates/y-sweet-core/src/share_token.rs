@@ -1,11 +1,20 @@
+use crate::share_revocation::RevocationList;
 use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
 use hmac::{Hmac, Mac};
 use sha2::Sha256;
 use std::time::{SystemTime, UNIX_EPOCH};
+use thiserror::Error;
 
 type HmacSha256 = Hmac<Sha256>;
 
 const PAYLOAD_LEN: usize = 22; // 1 purpose + 1 role + 16 uuid + 4 expiry
+/// First byte of a v2 payload. v1 payloads start with a purpose byte (0 or
+/// 1), so a v2 token can never be read as v1 or the other way round.
+const V2_MARKER: u8 = 0x82;
+/// 1 marker + 1 purpose + 1 role + 16 uuid + 4 expiry + 4 issued_at + 1 user length
+const V2_HEADER_LEN: usize = 28;
+/// Longest user id a v2 token can carry, in bytes.
+pub const MAX_USER_LEN: usize = 64;
 const SIG_LEN: usize = 8;
 const DEV_SECRET: &str = "lens-editor-dev-secret-do-not-use-in-production";
 const ALL_FOLDERS_SENTINEL: &str = "00000000-0000-0000-0000-000000000000";
@@ -70,6 +79,10 @@ pub struct ShareTokenPayload {
     pub role: ShareRole,
     pub folder: String, // UUID "xxxxxxxx-xxxx-xxxx-xxxx-xxxxxxxxxxxx"
     pub expiry: u32,    // unix seconds
+    /// Who the token was issued to. Only v2 tokens carry one.
+    pub user: Option<String>,
+    /// When the token was signed (unix seconds). Only v2 tokens carry one.
+    pub issued_at: Option<u32>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -81,6 +94,9 @@ pub struct McpAccess {
     /// tokens only (None for the legacy API key) — used to forward the
     /// caller's own token to sibling services (e.g. lens-editor importers).
     pub raw_token: Option<String>,
+    /// User the share token was issued to; None for v1 tokens and the legacy
+    /// API key.
+    pub user: Option<String>,
 }
 
 impl ShareTokenPayload {
@@ -101,10 +117,20 @@ impl ShareTokenPayload {
             folder_uuid,
             folder_name: None,
             raw_token: None,
+            user: self.user.clone(),
         }
     }
 }
 
+/// Why a payload can't be signed.
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum ShareTokenError {
+    #[error("The folder is not a UUID")]
+    InvalidFolder,
+    #[error("The user id is longer than {MAX_USER_LEN} bytes or contains control characters")]
+    InvalidUser,
+}
+
 /// Pack a UUID string into 16 raw bytes.
 fn uuid_to_bytes(uuid: &str) -> Option<[u8; 16]> {
     let hex: String = uuid.chars().filter(|c| *c != '-').collect();
@@ -131,29 +157,75 @@ fn bytes_to_uuid(bytes: &[u8]) -> String {
     )
 }
 
-fn pack_payload(payload: &ShareTokenPayload) -> Option<[u8; PAYLOAD_LEN]> {
-    let uuid_bytes = uuid_to_bytes(&payload.folder)?;
-    let mut buf = [0u8; PAYLOAD_LEN];
-    buf[0] = payload.purpose.to_byte();
-    buf[1] = payload.role.to_byte();
-    buf[2..18].copy_from_slice(&uuid_bytes);
-    buf[18..22].copy_from_slice(&payload.expiry.to_be_bytes());
-    Some(buf)
+/// Pack a payload. Payloads without a user or issue time use the v1 layout,
+/// so they stay readable by every verifier already deployed; the rest use v2:
+/// marker + purpose + role + uuid + expiry + issued_at + user length + user.
+fn pack_payload(payload: &ShareTokenPayload) -> Result<Vec<u8>, ShareTokenError> {
+    let uuid_bytes = uuid_to_bytes(&payload.folder).ok_or(ShareTokenError::InvalidFolder)?;
+    if payload.user.is_none() && payload.issued_at.is_none() {
+        let mut buf = Vec::with_capacity(PAYLOAD_LEN);
+        buf.push(payload.purpose.to_byte());
+        buf.push(payload.role.to_byte());
+        buf.extend_from_slice(&uuid_bytes);
+        buf.extend_from_slice(&payload.expiry.to_be_bytes());
+        return Ok(buf);
+    }
+
+    let user = payload.user.as_deref().unwrap_or("");
+    if user.len() > MAX_USER_LEN || user.chars().any(char::is_control) {
+        return Err(ShareTokenError::InvalidUser);
+    }
+    let mut buf = Vec::with_capacity(V2_HEADER_LEN + user.len());
+    buf.push(V2_MARKER);
+    buf.push(payload.purpose.to_byte());
+    buf.push(payload.role.to_byte());
+    buf.extend_from_slice(&uuid_bytes);
+    buf.extend_from_slice(&payload.expiry.to_be_bytes());
+    buf.extend_from_slice(&payload.issued_at.unwrap_or(0).to_be_bytes());
+    buf.push(user.len() as u8);
+    buf.extend_from_slice(user.as_bytes());
+    Ok(buf)
 }
 
 fn unpack_payload(buf: &[u8]) -> Option<ShareTokenPayload> {
-    if buf.len() != PAYLOAD_LEN {
+    if buf.len() == PAYLOAD_LEN {
+        let purpose = SharePurpose::from_byte(buf[0])?;
+        let role = ShareRole::from_byte(buf[1])?;
+        let folder = bytes_to_uuid(&buf[2..18]);
+        let expiry = u32::from_be_bytes([buf[18], buf[19], buf[20], buf[21]]);
+        return Some(ShareTokenPayload {
+            purpose,
+            role,
+            folder,
+            expiry,
+            user: None,
+            issued_at: None,
+        });
+    }
+
+    if buf.len() < V2_HEADER_LEN || buf[0] != V2_MARKER {
+        return None;
+    }
+    let purpose = SharePurpose::from_byte(buf[1])?;
+    let role = ShareRole::from_byte(buf[2])?;
+    let folder = bytes_to_uuid(&buf[3..19]);
+    let expiry = u32::from_be_bytes([buf[19], buf[20], buf[21], buf[22]]);
+    let issued_at = u32::from_be_bytes([buf[23], buf[24], buf[25], buf[26]]);
+    let user_len = buf[27] as usize;
+    if user_len > MAX_USER_LEN || buf.len() != V2_HEADER_LEN + user_len {
+        return None;
+    }
+    let user = std::str::from_utf8(&buf[V2_HEADER_LEN..]).ok()?;
+    if user.chars().any(char::is_control) {
         return None;
     }
-    let purpose = SharePurpose::from_byte(buf[0])?;
-    let role = ShareRole::from_byte(buf[1])?;
-    let folder = bytes_to_uuid(&buf[2..18]);
-    let expiry = u32::from_be_bytes([buf[18], buf[19], buf[20], buf[21]]);
     Some(ShareTokenPayload {
         purpose,
         role,
         folder,
         expiry,
+        user: (!user.is_empty()).then(|| user.to_string()),
+        issued_at: (issued_at != 0).then_some(issued_at),
     })
 }
 
@@ -167,25 +239,28 @@ fn compute_hmac(payload: &[u8], secret: &str) -> [u8; SIG_LEN] {
     sig
 }
 
-/// Sign a share token payload into a compact base64url string (~40 chars).
-pub fn sign_share_token(payload: &ShareTokenPayload, secret: &str) -> String {
-    let packed = pack_payload(payload).expect("Invalid payload");
+/// Sign a share token payload into a compact base64url string (~40 chars,
+/// plus the user id for v2 tokens).
+pub fn sign_share_token(
+    payload: &ShareTokenPayload,
+    secret: &str,
+) -> Result<String, ShareTokenError> {
+    let packed = pack_payload(payload)?;
     let sig = compute_hmac(&packed, secret);
-    let mut token_bytes = Vec::with_capacity(PAYLOAD_LEN + SIG_LEN);
+    let mut token_bytes = Vec::with_capacity(packed.len() + SIG_LEN);
     token_bytes.extend_from_slice(&packed);
     token_bytes.extend_from_slice(&sig);
-    URL_SAFE_NO_PAD.encode(&token_bytes)
+    Ok(URL_SAFE_NO_PAD.encode(&token_bytes))
 }
 
 /// Verify and decode a share token. Returns None if invalid, expired, or tampered.
 pub fn verify_share_token(token: &str, secret: &str) -> Option<ShareTokenPayload> {
     let raw = URL_SAFE_NO_PAD.decode(token).ok()?;
-    if raw.len() != PAYLOAD_LEN + SIG_LEN {
+    if raw.len() < PAYLOAD_LEN + SIG_LEN || raw.len() > V2_HEADER_LEN + MAX_USER_LEN + SIG_LEN {
         return None;
     }
 
-    let packed = &raw[..PAYLOAD_LEN];
-    let sig = &raw[PAYLOAD_LEN..];
+    let (packed, sig) = raw.split_at(raw.len() - SIG_LEN);
 
     // Constant-time compare, matching Node's timingSafeEqual.
     let mut mac =
@@ -212,11 +287,12 @@ pub fn verify_share_token(token: &str, secret: &str) -> Option<ShareTokenPayload
 ///
 /// Only `purpose == Share` tokens grant MCP access: an `add-video` token is
 /// scoped to the video-import bookmarklet endpoint and must not double as a
-/// general MCP credential.
+/// general MCP credential. Signed tokens on `revocations` are refused.
 pub fn decode_mcp_key(
     token: &str,
     share_secret: Option<&str>,
     legacy_api_key: Option<&str>,
+    revocations: Option<&RevocationList>,
 ) -> Option<McpAccess> {
     // Try signed token first
     if let Some(secret) = share_secret {
@@ -224,6 +300,10 @@ pub fn decode_mcp_key(
             if payload.purpose != SharePurpose::Share {
                 return None;
             }
+            let issued_at = payload.issued_at.map(u64::from);
+            if revocations.is_some_and(|list| list.is_revoked(token, &payload, issued_at)) {
+                return None;
+            }
             let mut access = payload.to_mcp_access();
             access.raw_token = Some(token.to_string());
             return Some(access);
@@ -238,6 +318,7 @@ pub fn decode_mcp_key(
                 folder_uuid: None,
                 folder_name: None,
                 raw_token: None,
+                user: None,
             });
         }
     }
@@ -258,13 +339,23 @@ mod tests {
             role,
             folder: TEST_FOLDER.to_string(),
             expiry: FAR_FUTURE_EXPIRY,
+            user: None,
+            issued_at: None,
+        }
+    }
+
+    fn make_user_payload(user: &str) -> ShareTokenPayload {
+        ShareTokenPayload {
+            user: Some(user.to_string()),
+            issued_at: Some(1_800_000_000),
+            ..make_test_payload(ShareRole::Edit)
         }
     }
 
     #[test]
     fn sign_and_verify_roundtrip() {
         let payload = make_test_payload(ShareRole::Edit);
-        let token = sign_share_token(&payload, DEV_SECRET);
+        let token = sign_share_token(&payload, DEV_SECRET).unwrap();
         let decoded = verify_share_token(&token, DEV_SECRET).expect("should verify");
         assert_eq!(decoded, payload);
     }
@@ -272,7 +363,7 @@ mod tests {
     #[test]
     fn verify_rejects_tampered_token() {
         let payload = make_test_payload(ShareRole::Edit);
-        let mut token = sign_share_token(&payload, DEV_SECRET);
+        let mut token = sign_share_token(&payload, DEV_SECRET).unwrap();
         // Flip a character
         let bytes = unsafe { token.as_bytes_mut() };
         bytes[5] = if bytes[5] == b'A' { b'B' } else { b'A' };
@@ -285,14 +376,14 @@ mod tests {
             expiry: 1, // long expired
             ..make_test_payload(ShareRole::Edit)
         };
-        let token = sign_share_token(&payload, DEV_SECRET);
+        let token = sign_share_token(&payload, DEV_SECRET).unwrap();
         assert!(verify_share_token(&token, DEV_SECRET).is_none());
     }
 
     #[test]
     fn verify_rejects_wrong_secret() {
         let payload = make_test_payload(ShareRole::Edit);
-        let token = sign_share_token(&payload, DEV_SECRET);
+        let token = sign_share_token(&payload, DEV_SECRET).unwrap();
         assert!(verify_share_token(&token, "wrong-secret").is_none());
     }
 
@@ -317,6 +408,44 @@ mod tests {
         assert!(verify_share_token(&token, DEV_SECRET).is_none());
     }
 
+    #[test]
+    fn v2_roundtrip_carries_user() {
+        let payload = make_user_payload("discord:1234");
+        let token = sign_share_token(&payload, DEV_SECRET).unwrap();
+        let decoded = verify_share_token(&token, DEV_SECRET).expect("should verify");
+        assert_eq!(decoded, payload);
+    }
+
+    #[test]
+    fn payload_without_user_keeps_v1_layout() {
+        // Verifiers that only know v1 must keep accepting these.
+        let token = sign_share_token(&make_test_payload(ShareRole::Edit), DEV_SECRET).unwrap();
+        assert_eq!(
+            URL_SAFE_NO_PAD.decode(&token).unwrap().len(),
+            PAYLOAD_LEN + SIG_LEN
+        );
+    }
+
+    #[test]
+    fn verify_rejects_v2_with_wrong_user_length() {
+        let mut packed = pack_payload(&make_user_payload("alice")).unwrap();
+        packed[27] += 1; // claims one more byte than follows
+        let sig = compute_hmac(&packed, DEV_SECRET);
+        packed.extend_from_slice(&sig);
+        let token = URL_SAFE_NO_PAD.encode(&packed);
+        assert!(verify_share_token(&token, DEV_SECRET).is_none());
+    }
+
+    #[test]
+    fn sign_rejects_invalid_users() {
+        for user in ["x".repeat(MAX_USER_LEN + 1), "bad\nuser".to_string()] {
+            assert_eq!(
+                sign_share_token(&make_user_payload(&user), DEV_SECRET),
+                Err(ShareTokenError::InvalidUser)
+            );
+        }
+    }
+
     #[test]
     fn all_roles_roundtrip() {
         for role in [
@@ -326,7 +455,7 @@ mod tests {
             ShareRole::View,
         ] {
             let payload = make_test_payload(role);
-            let token = sign_share_token(&payload, DEV_SECRET);
+            let token = sign_share_token(&payload, DEV_SECRET).unwrap();
             let decoded = verify_share_token(&token, DEV_SECRET).expect("should verify");
             assert_eq!(decoded.role, role);
         }
@@ -339,7 +468,7 @@ mod tests {
                 purpose,
                 ..make_test_payload(ShareRole::Edit)
             };
-            let token = sign_share_token(&payload, DEV_SECRET);
+            let token = sign_share_token(&payload, DEV_SECRET).unwrap();
             let decoded = verify_share_token(&token, DEV_SECRET).expect("should verify");
             assert_eq!(decoded.purpose, purpose);
         }
@@ -381,9 +510,10 @@ mod tests {
     #[test]
     fn decode_mcp_key_prefers_signed_token() {
         let payload = make_test_payload(ShareRole::View);
-        let token = sign_share_token(&payload, DEV_SECRET);
+        let token = sign_share_token(&payload, DEV_SECRET).unwrap();
         // Even though token matches legacy key, signed token takes priority
-        let access = decode_mcp_key(&token, Some(DEV_SECRET), Some(&token)).expect("should decode");
+        let access =
+            decode_mcp_key(&token, Some(DEV_SECRET), Some(&token), None).expect("should decode");
         // Signed token says View = read-only
         assert!(!access.writable);
         assert_eq!(access.folder_uuid, Some(TEST_FOLDER.to_string()));
@@ -392,38 +522,90 @@ mod tests {
     #[test]
     fn decode_mcp_key_sets_raw_token() {
         let payload = make_test_payload(ShareRole::Edit);
-        let token = sign_share_token(&payload, DEV_SECRET);
-        let access = decode_mcp_key(&token, Some(DEV_SECRET), None).expect("should decode");
+        let token = sign_share_token(&payload, DEV_SECRET).unwrap();
+        let access = decode_mcp_key(&token, Some(DEV_SECRET), None, None).expect("should decode");
         assert_eq!(access.raw_token, Some(token));
     }
 
+    #[test]
+    fn decode_mcp_key_carries_user() {
+        let token = sign_share_token(&make_user_payload("discord:1234"), DEV_SECRET).unwrap();
+        let access = decode_mcp_key(&token, Some(DEV_SECRET), None, None).expect("should decode");
+        assert_eq!(access.user.as_deref(), Some("discord:1234"));
+    }
+
     #[test]
     fn decode_mcp_key_rejects_add_video_purpose() {
         let payload = ShareTokenPayload {
             purpose: SharePurpose::AddVideo,
             ..make_test_payload(ShareRole::Edit)
         };
-        let token = sign_share_token(&payload, DEV_SECRET);
+        let token = sign_share_token(&payload, DEV_SECRET).unwrap();
         // Verifies fine as a token, but must not grant MCP access...
         assert!(verify_share_token(&token, DEV_SECRET).is_some());
-        assert!(decode_mcp_key(&token, Some(DEV_SECRET), None).is_none());
+        assert!(decode_mcp_key(&token, Some(DEV_SECRET), None, None).is_none());
         // ...even when a legacy key is configured (no fall-through).
-        assert!(decode_mcp_key(&token, Some(DEV_SECRET), Some("legacy-key")).is_none());
+        assert!(decode_mcp_key(&token, Some(DEV_SECRET), Some("legacy-key"), None).is_none());
     }
 
     #[test]
     fn decode_mcp_key_falls_back_to_legacy() {
         let legacy = "my-legacy-api-key";
-        let access = decode_mcp_key(legacy, Some(DEV_SECRET), Some(legacy)).expect("should decode");
+        let access =
+            decode_mcp_key(legacy, Some(DEV_SECRET), Some(legacy), None).expect("should decode");
         assert!(access.writable);
         assert!(access.folder_uuid.is_none());
         assert!(access.raw_token.is_none());
     }
 
+    #[test]
+    fn decode_mcp_key_rejects_revoked_token() {
+        use crate::share_revocation::{token_fingerprint, RevokedToken};
+
+        let token = sign_share_token(&make_test_payload(ShareRole::Edit), DEV_SECRET).unwrap();
+        let mut revocations = RevocationList::default();
+        assert!(decode_mcp_key(&token, Some(DEV_SECRET), None, Some(&revocations)).is_some());
+        revocations.revoke_token(RevokedToken {
+            fingerprint: token_fingerprint(&token),
+            expiry: Some(FAR_FUTURE_EXPIRY),
+            revoked_at: 0,
+            note: None,
+        });
+        assert!(decode_mcp_key(&token, Some(DEV_SECRET), None, Some(&revocations)).is_none());
+        // A folder cutoff spares v2 tokens issued after it.
+        let mut revocations = RevocationList::default();
+        revocations.revoke_folder(crate::share_revocation::RevokedFolder {
+            folder: TEST_FOLDER.to_string(),
+            issued_before: 1_800_000_000,
+            note: None,
+        });
+        let later = sign_share_token(&make_user_payload("alice"), DEV_SECRET).unwrap();
+        let earlier = sign_share_token(
+            &ShareTokenPayload {
+                issued_at: Some(1_700_000_000),
+                ..make_user_payload("alice")
+            },
+            DEV_SECRET,
+        )
+        .unwrap();
+        assert!(decode_mcp_key(&later, Some(DEV_SECRET), None, Some(&revocations)).is_some());
+        assert!(decode_mcp_key(&earlier, Some(DEV_SECRET), None, Some(&revocations)).is_none());
+        // v1 tokens carry no issue time and are left to fingerprint entries.
+        assert!(decode_mcp_key(&token, Some(DEV_SECRET), None, Some(&revocations)).is_some());
+        // The legacy API key is not a share token and is not affected.
+        assert!(decode_mcp_key(
+            "legacy",
+            Some(DEV_SECRET),
+            Some("legacy"),
+            Some(&revocations)
+        )
+        .is_some());
+    }
+
     #[test]
     fn decode_mcp_key_rejects_unknown() {
-        assert!(decode_mcp_key("unknown", Some(DEV_SECRET), Some("other-key")).is_none());
-        assert!(decode_mcp_key("unknown", None, None).is_none());
+        assert!(decode_mcp_key("unknown", Some(DEV_SECRET), Some("other-key"), None).is_none());
+        assert!(decode_mcp_key("unknown", None, None, None).is_none());
     }
 
     /// Cross-verification: tokens generated by lens-editor's Node.js
@@ -461,5 +643,13 @@ mod tests {
             assert_eq!(payload.folder, TEST_FOLDER);
             assert_eq!(payload.expiry, 2_000_000_000);
         }
+
+        // v2: user "discord:1234", issuedAt 1800000000.
+        let payload = verify_share_token(
+            "ggAB-9XrVHPMQbCsKCuT07QkTnc1lABrSdIADGRpc2NvcmQ6MTIzNJzh3G-JzV_n",
+            DEV_SECRET,
+        )
+        .expect("should verify Node.js v2 token");
+        assert_eq!(payload, make_user_payload("discord:1234"));
     }
 }
@@ -0,0 +1,513 @@
+//! Server-side check for updates from suggest-only connections.
+//!
+//! The editor only lets a suggester write CriticMarkup, but nothing stops a
+//! client from sending plain edits over the sync protocol. Before such an
+//! update is applied, `SuggestGuard::check` applies it to a shadow copy of
+//! the doc and requires that `contents` reads the same with every suggestion
+//! rejected (`critic_scanner::reject_all`), i.e. that the update only adds,
+//! rewrites or removes additions, deletions, substitutions and comments.
+//! Other roots may not change at all, apart from the `users` map the editor
+//! writes its client IDs to (PermanentUserData). That map drives attribution,
+//! so there an update may only register the client IDs it itself writes
+//! with: append them to an actor's `ids` and `meta`, or add a new actor.
+//!
+//! The shadow lives as long as the connection and is caught up from the live
+//! doc by state vector before each check, so a keystroke costs the changes
+//! since the last one rather than a copy of the whole doc. Deep observers on
+//! the shadow's roots report which roots an update touched; only `contents`
+//! is ever compared.
+
+use crate::critic_scanner::reject_all;
+use crate::doc_dump::KNOWN_TEXT_ROOTS;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::sync::{Arc, Mutex};
+use yrs::types::Events;
+use yrs::{
+    updates::decoder::Decode, Any, Array, DeepObservable, Doc, GetString, Map, Out, ReadTxn,
+    StateVector, Subscription, Transact, TransactionMut, Update, WriteTxn,
+};
+
+/// Root the editor registers its client IDs in; suggesters write it too.
+const USERS_ROOT: &str = "users";
+const CONTENTS_ROOT: &str = "contents";
+
+fn contents(doc: &Doc) -> String {
+    let txn = doc.transact();
+    txn.get_text(CONTENTS_ROOT)
+        .map(|text| text.get_string(&txn))
+        .unwrap_or_default()
+}
+
+fn root_names(doc: &Doc) -> BTreeSet<String> {
+    let txn = doc.transact();
+    txn.root_refs().map(|(name, _)| name.to_string()).collect()
+}
+
+/// One actor of the `users` map: its client IDs, `ds` length and `meta`
+/// records keyed by client ID. `None` for an entry that is not a map.
+#[derive(PartialEq)]
+struct UserEntry {
+    ids: Vec<u64>,
+    ds_len: u32,
+    meta: BTreeMap<String, Option<Any>>,
+}
+
+fn users(doc: &Doc) -> BTreeMap<String, Option<UserEntry>> {
+    let txn = doc.transact();
+    let Some(users) = txn.get_map(USERS_ROOT) else {
+        return BTreeMap::new();
+    };
+    users
+        .iter(&txn)
+        .map(|(actor, value)| {
+            let Out::YMap(entry) = value else {
+                return (actor.to_string(), None);
+            };
+            let ids = match entry.get(&txn, "ids") {
+                Some(Out::YArray(ids)) => ids
+                    .iter(&txn)
+                    .filter_map(|id| match id {
+                        Out::Any(Any::Number(n)) => Some(n as u64),
+                        Out::Any(Any::BigInt(n)) => Some(n as u64),
+                        _ => None,
+                    })
+                    .collect(),
+                _ => Vec::new(),
+            };
+            let ds_len = match entry.get(&txn, "ds") {
+                Some(Out::YArray(ds)) => ds.len(&txn),
+                _ => 0,
+            };
+            let meta = match entry.get(&txn, "meta") {
+                Some(Out::YMap(meta)) => meta
+                    .iter(&txn)
+                    .map(|(key, record)| {
+                        let record = match record {
+                            Out::Any(any) => Some(any),
+                            _ => None,
+                        };
+                        (key.to_string(), record)
+                    })
+                    .collect(),
+                _ => BTreeMap::new(),
+            };
+            (actor.to_string(), Some(UserEntry { ids, ds_len, meta }))
+        })
+        .collect()
+}
+
+/// Check that going from `before` to `after` only registered `own` client
+/// IDs, none of which any actor held already.
+fn check_users(
+    before: &BTreeMap<String, Option<UserEntry>>,
+    after: &BTreeMap<String, Option<UserEntry>>,
+    own: &HashSet<u64>,
+) -> Result<(), String> {
+    let registered: HashSet<u64> = before
+        .values()
+        .flatten()
+        .flat_map(|entry| entry.ids.iter().copied())
+        .collect();
+    let own_id = |id: &u64| own.contains(id) && !registered.contains(id);
+    let own_key = |key: &String| key.parse::<u64>().is_ok_and(|id| own.contains(&id));
+
+    if let Some(actor) = before.keys().find(|actor| !after.contains_key(*actor)) {
+        return Err(format!(
+            "suggest access cannot change users entry {}",
+            actor
+        ));
+    }
+    for (actor, entry) in after {
+        let allowed = match (before.get(actor), entry) {
+            (Some(old), new) if old == new => true,
+            (None, Some(new)) => {
+                new.ds_len == 0 && new.ids.iter().all(own_id) && new.meta.keys().all(own_key)
+            }
+            (Some(Some(old)), Some(new)) => {
+                new.ds_len == old.ds_len
+                    && old
+                        .meta
+                        .iter()
+                        .all(|(key, record)| new.meta.get(key) == Some(record))
+                    && new
+                        .meta
+                        .keys()
+                        .filter(|key| !old.meta.contains_key(*key))
+                        .all(own_key)
+                    && new
+                        .ids
+                        .strip_prefix(old.ids.as_slice())
+                        .is_some_and(|ids| ids.iter().all(own_id))
+            }
+            _ => false,
+        };
+        if !allowed {
+            return Err(format!(
+                "suggest access cannot change users entry {}",
+                actor
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// A suggest connection's copy of the doc, with an observer on every root.
+struct Shadow {
+    doc: Doc,
+    observed: HashSet<String>,
+    touched: Arc<Mutex<BTreeSet<String>>>,
+    subscriptions: Vec<Subscription>,
+}
+
+impl Shadow {
+    fn new() -> Self {
+        Self {
+            doc: Doc::new(),
+            observed: HashSet::new(),
+            touched: Arc::new(Mutex::new(BTreeSet::new())),
+            subscriptions: Vec::new(),
+        }
+    }
+
+    /// Whether the shadow holds changes `live` does not, e.g. an update that
+    /// passed this check but was refused further down.
+    fn is_ahead_of(&self, live: &Doc) -> bool {
+        let live_sv = live.transact().state_vector();
+        let sv = self.doc.transact().state_vector();
+        sv.iter()
+            .any(|(client, clock)| live_sv.get(client) < *clock)
+    }
+
+    /// Apply whatever `live` has that the shadow does not.
+    fn catch_up(&mut self, live: &Doc) -> Result<(), String> {
+        let sv: StateVector = self.doc.transact().state_vector();
+        let diff = live.transact().encode_state_as_update_v1(&sv);
+        let diff = Update::decode_v1(&diff).map_err(|e| format!("invalid doc state: {}", e))?;
+        self.doc.transact_mut().apply_update(diff);
+        self.observe_new_roots();
+        self.touched
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clear();
+        Ok(())
+    }
+
+    /// Roots decoded from an update carry no type until something reads them,
+    /// so text roots are read as text and every other root as a map; deep
+    /// observers fire for changes anywhere below either.
+    fn observe_new_roots(&mut self) {
+        for name in root_names(&self.doc) {
+            if self.observed.contains(&name) {
+                continue;
+            }
+            let touched = self.touched.clone();
+            let root = name.clone();
+            let on_change = move |_: &TransactionMut, _: &Events| {
+                touched
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .insert(root.clone());
+            };
+            let subscription = if KNOWN_TEXT_ROOTS.contains(&name.as_str()) {
+                let text = self.doc.transact_mut().get_or_insert_text(name.as_str());
+                text.observe_deep(on_change)
+            } else {
+                let map = self.doc.transact_mut().get_or_insert_map(name.as_str());
+                map.observe_deep(on_change)
+            };
+            self.subscriptions.push(subscription);
+            self.observed.insert(name);
+        }
+    }
+
+    /// Apply `update` to the shadow and judge what it changed; `live` still
+    /// holds the doc as it was before.
+    fn apply_and_check(&mut self, live: &Doc, update: Update) -> Result<(), String> {
+        let roots_before = root_names(&self.doc);
+        let users_before = users(&self.doc);
+        let sv_before = self.doc.transact().state_vector();
+        {
+            let mut txn = self.doc.transact_mut();
+            txn.apply_update(update);
+            // An update that can't be integrated yet would be checked here as
+            // a no-op and applied in full once its dependencies arrive.
+            if txn.has_missing_updates() {
+                return Err("update depends on changes the server has not seen".to_string());
+            }
+        }
+
+        let mut touched =
+            std::mem::take(&mut *self.touched.lock().unwrap_or_else(|e| e.into_inner()));
+        touched.extend(
+            root_names(&self.doc)
+                .into_iter()
+                .filter(|name| !roots_before.contains(name)),
+        );
+        self.observe_new_roots();
+
+        if let Some(name) = touched
+            .iter()
+            .find(|name| *name != USERS_ROOT && *name != CONTENTS_ROOT)
+        {
+            return Err(format!("suggest access cannot change {}", name));
+        }
+        if touched.contains(CONTENTS_ROOT)
+            && reject_all(&contents(live)) != reject_all(&contents(&self.doc))
+        {
+            return Err("edits to contents must be CriticMarkup suggestions".to_string());
+        }
+        if touched.contains(USERS_ROOT) {
+            // The clients this update wrote new items as.
+            let own: HashSet<u64> = self
+                .doc
+                .transact()
+                .state_vector()
+                .iter()
+                .filter(|(client, clock)| sv_before.get(client) < **clock)
+                .map(|(client, _)| *client)
+                .collect();
+            check_users(&users_before, &users(&self.doc), &own)?;
+        }
+        Ok(())
+    }
+}
+
+/// Checks the updates of one suggest-only connection.
+#[derive(Default)]
+pub struct SuggestGuard {
+    shadow: Option<Shadow>,
+}
+
+impl SuggestGuard {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Why applying `update` (v1-encoded) to `doc` is not a pure suggestion,
+    /// or Ok when it is.
+    pub fn check(&mut self, doc: &Doc, update: &[u8]) -> Result<(), String> {
+        let decoded = Update::decode_v1(update).map_err(|e| format!("invalid update: {}", e))?;
+        if self.shadow.as_ref().is_some_and(|s| s.is_ahead_of(doc)) {
+            self.shadow = None;
+        }
+        let shadow = self.shadow.get_or_insert_with(Shadow::new);
+        let result = shadow
+            .catch_up(doc)
+            .and_then(|()| shadow.apply_and_check(doc, decoded));
+        if result.is_err() {
+            // The shadow now holds an update the live doc will never see.
+            self.shadow = None;
+        }
+        result
+    }
+
+    /// Forget the shadow, for when an update this guard passed was refused
+    /// further down and so never reached the live doc.
+    pub fn discard(&mut self) {
+        self.shadow = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use yrs::{ArrayPrelim, MapPrelim, Text, WriteTxn};
+
+    fn doc_with(text: &str) -> Doc {
+        let doc = Doc::new();
+        {
+            let mut txn = doc.transact_mut();
+            let contents = txn.get_or_insert_text(CONTENTS_ROOT);
+            contents.insert(&mut txn, 0, text);
+        }
+        doc
+    }
+
+    /// Update a client holding `doc` sends after running `edit` on its copy.
+    fn client_update(doc: &Doc, edit: impl FnOnce(&Doc)) -> Vec<u8> {
+        let client = Doc::new();
+        {
+            let state = doc
+                .transact()
+                .encode_state_as_update_v1(&StateVector::default());
+            client
+                .transact_mut()
+                .apply_update(Update::decode_v1(&state).unwrap());
+        }
+        let sv = client.transact().state_vector();
+        edit(&client);
+        client.transact().encode_state_as_update_v1(&sv)
+    }
+
+    fn insert(at: u32, chunk: &'static str) -> impl FnOnce(&Doc) {
+        move |doc: &Doc| {
+            let mut txn = doc.transact_mut();
+            let contents = txn.get_or_insert_text(CONTENTS_ROOT);
+            contents.insert(&mut txn, at, chunk);
+        }
+    }
+
+    #[test]
+    fn accepts_added_suggestion() {
+        let doc = doc_with("Hello world");
+        let update = client_update(&doc, insert(5, "{++ there++}"));
+        assert_eq!(SuggestGuard::new().check(&doc, &update), Ok(()));
+    }
+
+    #[test]
+    fn accepts_wrapping_text_in_deletion_and_comment() {
+        let doc = doc_with("Hello world");
+        let update = client_update(&doc, |client| {
+            let mut txn = client.transact_mut();
+            let contents = txn.get_or_insert_text(CONTENTS_ROOT);
+            contents.insert(&mut txn, 11, "--}{>>why?<<}");
+            contents.insert(&mut txn, 6, "{--");
+        });
+        assert_eq!(SuggestGuard::new().check(&doc, &update), Ok(()));
+    }
+
+    #[test]
+    fn keeps_checking_as_the_doc_moves_on() {
+        let doc = doc_with("Hello world");
+        let mut guard = SuggestGuard::new();
+        let apply = |update: &[u8]| {
+            doc.transact_mut()
+                .apply_update(Update::decode_v1(update).unwrap());
+        };
+
+        let first = client_update(&doc, insert(5, "{++ there++}"));
+        assert_eq!(guard.check(&doc, &first), Ok(()));
+        apply(&first);
+
+        // A full-access client edits the doc between suggestions.
+        apply(&client_update(&doc, insert(0, "Oh, ")));
+
+        let plain = client_update(&doc, insert(0, "Well. "));
+        assert!(guard.check(&doc, &plain).is_err());
+
+        let second = client_update(&doc, insert(0, "{>>greeting<<}"));
+        assert_eq!(guard.check(&doc, &second), Ok(()));
+
+        // Passed but never applied: the next check must not build on it.
+        guard.discard();
+        let third = client_update(&doc, insert(0, "{++Hi. ++}"));
+        assert_eq!(guard.check(&doc, &third), Ok(()));
+    }
+
+    #[test]
+    fn rejects_plain_edit() {
+        let doc = doc_with("Hello world");
+        let update = client_update(&doc, insert(5, " there"));
+        assert!(SuggestGuard::new().check(&doc, &update).is_err());
+    }
+
+    #[test]
+    fn rejects_accepting_a_suggestion() {
+        let doc = doc_with("Hello {++there++}");
+        let update = client_update(&doc, |client| {
+            let mut txn = client.transact_mut();
+            let contents = txn.get_or_insert_text(CONTENTS_ROOT);
+            contents.remove_range(&mut txn, 14, 3);
+            contents.remove_range(&mut txn, 6, 3);
+        });
+        assert!(SuggestGuard::new().check(&doc, &update).is_err());
+    }
+
+    #[test]
+    fn rejects_unclosed_markup() {
+        let doc = doc_with("Hello world");
+        let update = client_update(&doc, insert(5, "{++ there"));
+        assert!(SuggestGuard::new().check(&doc, &update).is_err());
+    }
+
+    /// Register `client`'s own client ID under `actor`, the way the editor's
+    /// provenance layer does.
+    fn register(actor: &'static str) -> impl FnOnce(&Doc) {
+        move |client: &Doc| {
+            let id = client.client_id();
+            let users = client.get_or_insert_map(USERS_ROOT);
+            let mut txn = client.transact_mut();
+            let entry = match users.get(&txn, actor) {
+                Some(Out::YMap(entry)) => entry,
+                _ => users.insert(&mut txn, actor, MapPrelim::default()),
+            };
+            let ids = match entry.get(&txn, "ids") {
+                Some(Out::YArray(ids)) => ids,
+                _ => entry.insert(&mut txn, "ids", ArrayPrelim::default()),
+            };
+            ids.push_back(&mut txn, Any::Number(id as f64));
+            let meta = match entry.get(&txn, "meta") {
+                Some(Out::YMap(meta)) => meta,
+                _ => entry.insert(&mut txn, "meta", MapPrelim::default()),
+            };
+            meta.insert(&mut txn, id.to_string(), Any::from(1.0));
+        }
+    }
+
+    #[test]
+    fn accepts_registering_own_client_id() {
+        let doc = doc_with("Hello world");
+        let update = client_update(&doc, register("human:Sam"));
+        assert_eq!(SuggestGuard::new().check(&doc, &update), Ok(()));
+        doc.transact_mut()
+            .apply_update(Update::decode_v1(&update).unwrap());
+
+        // A later session appends its own ID to the existing actor.
+        let update = client_update(&doc, register("human:Sam"));
+        assert_eq!(SuggestGuard::new().check(&doc, &update), Ok(()));
+    }
+
+    #[test]
+    fn rejects_changing_other_users_entries() {
+        let doc = doc_with("Hello world");
+        doc.transact_mut()
+            .apply_update(Update::decode_v1(&client_update(&doc, register("human:Alex"))).unwrap());
+        let alex_id = match users(&doc).get("human:Alex") {
+            Some(Some(entry)) => entry.ids[0],
+            _ => panic!("human:Alex not registered"),
+        };
+
+        // Claiming Alex's client ID re-attributes Alex's text.
+        let update = client_update(&doc, |client| {
+            let users = client.get_or_insert_map(USERS_ROOT);
+            let mut txn = client.transact_mut();
+            let entry = users.insert(&mut txn, "human:Sam", MapPrelim::default());
+            let ids = entry.insert(&mut txn, "ids", ArrayPrelim::default());
+            ids.push_back(&mut txn, Any::Number(alex_id as f64));
+        });
+        let err = SuggestGuard::new().check(&doc, &update).unwrap_err();
+        assert!(err.contains("human:Sam"), "{}", err);
+
+        // Removing or rewriting Alex's entry is refused too.
+        let update = client_update(&doc, |client| {
+            let users = client.get_or_insert_map(USERS_ROOT);
+            let mut txn = client.transact_mut();
+            users.remove(&mut txn, "human:Alex");
+        });
+        let err = SuggestGuard::new().check(&doc, &update).unwrap_err();
+        assert!(err.contains("human:Alex"), "{}", err);
+
+        let update = client_update(&doc, |client| {
+            let users = client.get_or_insert_map(USERS_ROOT);
+            let mut txn = client.transact_mut();
+            if let Some(Out::YMap(entry)) = users.get(&txn, "human:Alex") {
+                if let Some(Out::YArray(ids)) = entry.get(&txn, "ids") {
+                    ids.remove(&mut txn, 0);
+                }
+            }
+        });
+        assert!(SuggestGuard::new().check(&doc, &update).is_err());
+    }
+
+    #[test]
+    fn rejects_changes_to_other_roots() {
+        let doc = doc_with("Hello world");
+        let update = client_update(&doc, |client| {
+            let mut txn = client.transact_mut();
+            let filemeta = txn.get_or_insert_map("filemeta_v0");
+            filemeta.insert(&mut txn, "/a.md", "x");
+        });
+        let err = SuggestGuard::new().check(&doc, &update).unwrap_err();
+        assert!(err.contains("filemeta_v0"), "{}", err);
+    }
+}
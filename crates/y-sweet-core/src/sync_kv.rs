@@ -107,6 +107,42 @@ where
     BTreeMap::from_cbor_value(cbor_value).map_err(D::Error::custom)
 }
 
+/// Snapshot creation time and metadata, present only in the CBOR format.
+type SnapshotHeader = (u64, Option<BTreeMap<String, ciborium::value::Value>>);
+
+/// Decode a `data.ysweet` snapshot into its key-value map. Tries the CBOR
+/// format first and falls back to the legacy bincode format.
+fn decode_snapshot(
+    snapshot: &[u8],
+) -> Result<(BTreeMap<Vec<u8>, Vec<u8>>, Option<SnapshotHeader>)> {
+    // Try CBOR format first
+    let decoded = match ciborium::de::from_reader::<YSweetData, _>(snapshot) {
+        Ok(y_data) => {
+            tracing::info!("Loaded CBOR format data (version {})", y_data.version);
+            (y_data.data, Some((y_data.created_at, y_data.metadata)))
+        }
+        Err(cbor_err) => {
+            // Fallback to bincode for backward compatibility
+            tracing::info!(
+                "CBOR deserialization failed ({}), trying bincode format",
+                cbor_err
+            );
+            match bincode::deserialize(snapshot) {
+                Ok(data) => {
+                    tracing::info!(
+                        "Loaded bincode format data, will migrate to CBOR on next persist"
+                    );
+                    (data, None)
+                }
+                Err(bincode_err) => {
+                    anyhow::bail!("Failed to deserialize data in both CBOR and bincode formats. CBOR: {}, Bincode: {}", cbor_err, bincode_err);
+                }
+            }
+        }
+    };
+    Ok(decoded)
+}
+
 pub struct SyncKv {
     data: Arc<Mutex<BTreeMap<Vec<u8>, Vec<u8>>>>,
     store: Option<Arc<Box<dyn Store>>>,
@@ -132,31 +168,12 @@ impl SyncKv {
             if let Some(snapshot) = store.get(&key).await.context("Failed to get from store.")? {
                 tracing::info!(size=?snapshot.len(), "Loading snapshot");
 
-                // Try CBOR format first
-                match ciborium::de::from_reader::<YSweetData, _>(&snapshot[..]) {
-                    Ok(y_data) => {
-                        created_at = Some(y_data.created_at);
-                        metadata = y_data.metadata;
-                        tracing::info!("Loaded CBOR format data (version {})", y_data.version);
-                        y_data.data
-                    }
-                    Err(cbor_err) => {
-                        // Fallback to bincode for backward compatibility
-                        tracing::info!(
-                            "CBOR deserialization failed ({}), trying bincode format",
-                            cbor_err
-                        );
-                        match bincode::deserialize(&snapshot) {
-                            Ok(data) => {
-                                tracing::info!("Loaded bincode format data, will migrate to CBOR on next persist");
-                                data
-                            }
-                            Err(bincode_err) => {
-                                anyhow::bail!("Failed to deserialize data in both CBOR and bincode formats. CBOR: {}, Bincode: {}", cbor_err, bincode_err);
-                            }
-                        }
-                    }
+                let (data, header) = decode_snapshot(&snapshot)?;
+                if let Some((snapshot_created_at, snapshot_metadata)) = header {
+                    created_at = Some(snapshot_created_at);
+                    metadata = snapshot_metadata;
                 }
+                data
             } else {
                 BTreeMap::new()
             }
@@ -176,6 +193,26 @@ impl SyncKv {
         })
     }
 
+    /// A detached, store-less SyncKv holding a snapshot's contents, for
+    /// reading old versions of a doc. Changes to it are never persisted.
+    pub fn from_snapshot(snapshot: &[u8]) -> Result<Self> {
+        let (data, header) = decode_snapshot(snapshot)?;
+        let (created_at, metadata) = match header {
+            Some((created_at, metadata)) => (Some(created_at), metadata),
+            None => (None, None),
+        };
+        Ok(Self {
+            data: Arc::new(Mutex::new(data)),
+            store: None,
+            key: String::new(),
+            dirty: AtomicBool::new(false),
+            dirty_callback: Box::new(|| ()),
+            shutdown: AtomicBool::new(false),
+            created_at,
+            metadata: Arc::new(Mutex::new(metadata)),
+        })
+    }
+
     fn mark_dirty(&self) {
         if self.shutdown.load(Ordering::SeqCst) {
             return;
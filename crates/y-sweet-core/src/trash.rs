@@ -0,0 +1,287 @@
+//! Per-folder trash for `filemeta_v0` entries.
+//!
+//! When an entry leaves a folder's `filemeta_v0` without turning up in
+//! another folder, it is recorded in the folder doc's `trash_v0` map, keyed
+//! by its ID, with the path it had, its filemeta fields, when it was deleted
+//! and by whom. The content doc or blob stays in the store until the entry
+//! is purged, so restoring it only has to put the filemeta entry back.
+
+use crate::link_indexer::{
+    ensure_ancestor_folders, extract_filemeta_fields, extract_id_from_filemeta_entry,
+};
+use serde::Serialize;
+use std::collections::HashMap;
+use yrs::{Any, Map, ReadTxn, TransactionMut, WriteTxn};
+
+/// Root map of a folder doc holding its trashed entries.
+pub const TRASH_MAP: &str = "trash_v0";
+
+/// Transaction origin for trash bookkeeping (trashing, restoring, purging).
+pub const TRASH_ORIGIN: &str = "trash";
+
+/// A folder's `filemeta_v0` by entry ID: (path, fields).
+pub type FilemetaById = HashMap<String, (String, HashMap<String, Any>)>;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrashItem {
+    pub id: String,
+    /// The path the entry had when it was deleted (e.g. "/Notes/Idea.md").
+    pub path: String,
+    #[serde(rename = "type")]
+    pub entry_type: String,
+    /// Milliseconds since the epoch.
+    pub deleted_at: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deleted_by: Option<String>,
+    /// The entry's filemeta fields, written back on restore.
+    #[serde(skip)]
+    pub meta: HashMap<String, Any>,
+}
+
+impl TrashItem {
+    /// SHA-256 hash of a blob entry; None for docs and folders.
+    pub fn hash(&self) -> Option<&str> {
+        match self.meta.get("hash") {
+            Some(Any::String(hash)) => Some(hash),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RestoreError {
+    /// No trashed entry has that ID.
+    NotFound(String),
+    /// Something else lives at the entry's old path now.
+    PathTaken(String),
+}
+
+impl std::fmt::Display for RestoreError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NotFound(id) => write!(f, "No trashed entry with ID {}", id),
+            Self::PathTaken(path) => write!(f, "Path {} is in use", path),
+        }
+    }
+}
+
+impl std::error::Error for RestoreError {}
+
+/// Read `filemeta_v0` keyed by entry ID. Entries without an ID are skipped.
+pub fn filemeta_by_id<T: ReadTxn>(txn: &T) -> FilemetaById {
+    let Some(filemeta) = txn.get_map("filemeta_v0") else {
+        return HashMap::new();
+    };
+    filemeta
+        .iter(txn)
+        .filter_map(|(path, value)| {
+            let id = extract_id_from_filemeta_entry(&value, txn)?;
+            Some((id, (path.to_string(), extract_filemeta_fields(&value, txn))))
+        })
+        .collect()
+}
+
+/// Record an entry in the trash. An ID that is already trashed keeps its
+/// first record, so a deletion seen twice is not re-dated. Returns whether
+/// the entry was added.
+pub fn put_in_trash(
+    txn: &mut TransactionMut,
+    id: &str,
+    path: &str,
+    meta: &HashMap<String, Any>,
+    deleted_at: u64,
+    deleted_by: Option<&str>,
+) -> bool {
+    let trash = txn.get_or_insert_map(TRASH_MAP);
+    if trash.get(&*txn, id).is_some() {
+        return false;
+    }
+    let mut record = HashMap::new();
+    record.insert("path".to_string(), Any::from(path));
+    record.insert("deletedAt".to_string(), Any::Number(deleted_at as f64));
+    if let Some(deleted_by) = deleted_by {
+        record.insert("deletedBy".to_string(), Any::from(deleted_by));
+    }
+    record.insert("meta".to_string(), Any::Map(meta.clone().into()));
+    trash.insert(txn, id, Any::Map(record.into()));
+    true
+}
+
+/// Every trashed entry of the folder, most recently deleted first.
+pub fn list_trash<T: ReadTxn>(txn: &T) -> Vec<TrashItem> {
+    let Some(trash) = txn.get_map(TRASH_MAP) else {
+        return Vec::new();
+    };
+    let mut items: Vec<TrashItem> = trash
+        .iter(txn)
+        .filter_map(|(id, value)| match value.to_json(txn) {
+            Any::Map(record) => Some(trash_item(id, &record)),
+            _ => None,
+        })
+        .collect();
+    items.sort_by(|a, b| b.deleted_at.cmp(&a.deleted_at).then(a.path.cmp(&b.path)));
+    items
+}
+
+fn trash_item(id: &str, record: &HashMap<String, Any>) -> TrashItem {
+    let string = |key: &str| match record.get(key) {
+        Some(Any::String(value)) => Some(value.to_string()),
+        _ => None,
+    };
+    let meta = match record.get("meta") {
+        Some(Any::Map(meta)) => (**meta).clone(),
+        _ => HashMap::new(),
+    };
+    let entry_type = match meta.get("type") {
+        Some(Any::String(entry_type)) => entry_type.to_string(),
+        _ => "markdown".to_string(),
+    };
+    TrashItem {
+        id: id.to_string(),
+        path: string("path").unwrap_or_default(),
+        entry_type,
+        deleted_at: match record.get("deletedAt") {
+            Some(Any::Number(n)) => *n as u64,
+            Some(Any::BigInt(n)) => *n as u64,
+            _ => 0,
+        },
+        deleted_by: string("deletedBy"),
+        meta,
+    }
+}
+
+/// Put a trashed entry back at its old path and drop it from the trash.
+/// Missing parent folders that are in the trash come back with it; any
+/// others are recreated.
+pub fn restore_from_trash(txn: &mut TransactionMut, id: &str) -> Result<TrashItem, RestoreError> {
+    let trashed = list_trash(&*txn);
+    let item = trashed
+        .iter()
+        .find(|item| item.id == id)
+        .cloned()
+        .ok_or_else(|| RestoreError::NotFound(id.to_string()))?;
+    let filemeta = txn.get_or_insert_map("filemeta_v0");
+    if filemeta.get(&*txn, &item.path).is_some() {
+        return Err(RestoreError::PathTaken(item.path));
+    }
+    let docs_map = txn.get_or_insert_map("docs");
+    let trash = txn.get_or_insert_map(TRASH_MAP);
+    for (end, _) in item.path.match_indices('/').skip(1) {
+        let ancestor = &item.path[..end];
+        if filemeta.get(&*txn, ancestor).is_some() {
+            continue;
+        }
+        let Some(folder) = trashed
+            .iter()
+            .find(|t| t.path == ancestor && t.entry_type == "folder")
+        else {
+            continue;
+        };
+        filemeta.insert(txn, ancestor, Any::Map(folder.meta.clone().into()));
+        docs_map.insert(txn, ancestor, Any::from(folder.id.as_str()));
+        trash.remove(txn, &folder.id);
+    }
+    ensure_ancestor_folders(&filemeta, &docs_map, txn, &item.path);
+    filemeta.insert(txn, item.path.as_str(), Any::Map(item.meta.clone().into()));
+    if item.entry_type == "markdown" || item.entry_type == "folder" {
+        docs_map.insert(txn, item.path.as_str(), Any::from(item.id.as_str()));
+    }
+    trash.remove(txn, id);
+    Ok(item)
+}
+
+/// Drop entries from the trash without restoring them.
+pub fn remove_from_trash(txn: &mut TransactionMut, ids: &[String]) -> usize {
+    let trash = txn.get_or_insert_map(TRASH_MAP);
+    ids.iter()
+        .filter(|id| trash.remove(txn, id).is_some())
+        .count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use yrs::{Doc, Transact};
+
+    fn entry(id: &str, entry_type: &str) -> Any {
+        let mut meta = HashMap::new();
+        meta.insert("id".to_string(), Any::from(id));
+        meta.insert("type".to_string(), Any::from(entry_type));
+        meta.insert("version".to_string(), Any::Number(0.0));
+        Any::Map(meta.into())
+    }
+
+    #[test]
+    fn trashed_entry_restores_at_its_old_path() {
+        let doc = Doc::new();
+        {
+            let mut txn = doc.transact_mut();
+            let filemeta = txn.get_or_insert_map("filemeta_v0");
+            filemeta.insert(&mut txn, "/Notes", entry("f1", "folder"));
+            filemeta.insert(&mut txn, "/Notes/a.md", entry("c1", "markdown"));
+        }
+        let before = filemeta_by_id(&doc.transact());
+        {
+            let mut txn = doc.transact_mut();
+            let filemeta = txn.get_or_insert_map("filemeta_v0");
+            filemeta.remove(&mut txn, "/Notes/a.md");
+            filemeta.remove(&mut txn, "/Notes");
+        }
+        {
+            let mut txn = doc.transact_mut();
+            for id in ["c1", "f1"] {
+                let (path, meta) = &before[id];
+                assert!(put_in_trash(&mut txn, id, path, meta, 1_000, Some("Alice")));
+            }
+            // Seen again: the first record wins.
+            let (path, meta) = &before["c1"];
+            assert!(!put_in_trash(&mut txn, "c1", path, meta, 2_000, None));
+        }
+
+        let items = list_trash(&doc.transact());
+        assert_eq!(items.len(), 2);
+        let doc_item = items.iter().find(|item| item.id == "c1").unwrap();
+        assert_eq!(doc_item.path, "/Notes/a.md");
+        assert_eq!(doc_item.entry_type, "markdown");
+        assert_eq!(doc_item.deleted_at, 1_000);
+        assert_eq!(doc_item.deleted_by.as_deref(), Some("Alice"));
+
+        // The trashed parent folder comes back with the doc.
+        let restored = restore_from_trash(&mut doc.transact_mut(), "c1").unwrap();
+        assert_eq!(restored.path, "/Notes/a.md");
+        let txn = doc.transact();
+        let after = filemeta_by_id(&txn);
+        assert_eq!(after["c1"], before["c1"]);
+        assert_eq!(after["f1"], before["f1"]);
+        assert_eq!(
+            txn.get_map("docs").unwrap().get(&txn, "/Notes/a.md"),
+            Some(yrs::Out::Any(Any::from("c1")))
+        );
+        assert!(list_trash(&txn).is_empty());
+    }
+
+    #[test]
+    fn restore_refuses_taken_paths_and_unknown_ids() {
+        let doc = Doc::new();
+        {
+            let mut txn = doc.transact_mut();
+            let mut meta = HashMap::new();
+            meta.insert("id".to_string(), Any::from("c1"));
+            put_in_trash(&mut txn, "c1", "/a.md", &meta, 1, None);
+            txn.get_or_insert_map("filemeta_v0")
+                .insert(&mut txn, "/a.md", entry("c2", "markdown"));
+        }
+        let mut txn = doc.transact_mut();
+        assert_eq!(
+            restore_from_trash(&mut txn, "c1"),
+            Err(RestoreError::PathTaken("/a.md".into()))
+        );
+        assert_eq!(
+            restore_from_trash(&mut txn, "nope"),
+            Err(RestoreError::NotFound("nope".into()))
+        );
+        assert_eq!(remove_from_trash(&mut txn, &["c1".to_string()]), 1);
+        assert!(list_trash(&txn).is_empty());
+    }
+}
@@ -5,6 +5,7 @@
  *   npx tsx scripts/generate-share-link.ts --role edit --folder fbd5eb54-73cc-41b0-ac28-2b93d3b4244e --expires 7d
  *   npx tsx scripts/generate-share-link.ts --role suggest --folder fbd5eb54-73cc-41b0-ac28-2b93d3b4244e --expires 24h
  *   npx tsx scripts/generate-share-link.ts --role view --folder fbd5eb54-73cc-41b0-ac28-2b93d3b4244e
+ *   npx tsx scripts/generate-share-link.ts --role edit --all-folders --user chris
  */
 import { signShareToken } from '../server/share-token.ts';
 import type { ShareTokenPayload } from '../server/share-token.ts';
@@ -32,6 +33,8 @@ Options:
   --all-folders               Grant access to all folders
   --purpose <share|add-video>  Token purpose (default: "share")
   --expires <duration>         Token lifetime: e.g. "24h", "7d", "2w" (default: "14d")
+  --user <id>                 User the token is issued to; MCP edits are attributed
+                              to them (max 64 bytes)
   --base-url <url>            Base URL for the editor (default: http://localhost:5173)
 
 Examples:
@@ -59,6 +62,7 @@ const folder = allFolders ? ALL_FOLDERS_SENTINEL : getArg('--folder');
 const purpose = (getArg('--purpose') || 'share') as 'share' | 'add-video';
 const expires = getArg('--expires') || '14d';
 const baseUrl = getArg('--base-url') || 'http://localhost:5173';
+const user = getArg('--user');
 
 if (!role || !ROLE_ORDER.includes(role)) {
   console.error(`Error: --role is required and must be one of: ${ROLE_ORDER.join(', ')}`);
@@ -83,6 +87,9 @@ const payload: ShareTokenPayload = {
   role,
   folder,
   expiry: parseExpiry(expires),
+  // Always record the issue time (v2 format) so folder revocation cutoffs cover the link.
+  issuedAt: Math.floor(Date.now() / 1000),
+  ...(user ? { user } : {}),
 };
 
 const token = signShareToken(payload);
@@ -94,5 +101,6 @@ console.log(`Purpose: ${purpose}`);
 console.log(`Role:    ${role}`);
 console.log(`Folder:  ${folder === ALL_FOLDERS_SENTINEL ? 'All folders' : folder}`);
 console.log(`Expires: ${new Date(payload.expiry * 1000).toISOString()}`);
+if (user) console.log(`User:    ${user}`);
 console.log(`Token:   ${token} (${token.length} chars)`);
 console.log(`\nURL:\n${url}\n`);
@@ -1,6 +1,7 @@
 import { describe, it, expect, vi, beforeEach } from 'vitest';
 import { createAuthHandler, AuthError } from './auth-middleware.ts';
 import { signShareToken } from './share-token.ts';
+import { setShareRevocations, tokenFingerprint } from './share-revocations.ts';
 import type { ShareTokenPayload } from './share-token.ts';
 
 // Mock fetch globally
@@ -84,7 +85,7 @@ describe('auth-middleware', () => {
     );
   });
 
-  it('should request full relay token for suggest role', async () => {
+  it('should request suggest relay token for suggest role', async () => {
     const suggestPayload: ShareTokenPayload = { ...validPayload, role: 'suggest' };
     const token = signShareToken(suggestPayload);
     // Folder lookup mock
@@ -98,14 +99,21 @@ describe('auth-middleware', () => {
       json: async () => ({
         url: 'ws://localhost:8190/d/doc123/ws',
         docId: 'doc123',
-        token: 'relay-full-token',
+        token: 'relay-suggest-token',
       }),
     });
 
     const result = await handler({ token, docId: 'doc123' });
 
     expect(result.role).toBe('suggest');
-    expect(result.clientToken.authorization).toBe('full');
+    expect(result.clientToken.authorization).toBe('suggest');
+    // The relay enforces suggest-only sync, so it must be asked for it.
+    expect(mockFetch).toHaveBeenCalledWith(
+      'http://localhost:8190/doc/doc123/auth',
+      expect.objectContaining({
+        body: JSON.stringify({ authorization: 'suggest' }),
+      }),
+    );
   });
 
   it('should throw AuthError 401 for invalid token', async () => {
@@ -115,6 +123,18 @@ describe('auth-middleware', () => {
       .rejects.toThrow('Invalid or expired share token');
   });
 
+  it('should throw AuthError 401 for a token revoked on the relay', async () => {
+    const token = signShareToken(validPayload);
+    setShareRevocations({ tokens: [{ fingerprint: tokenFingerprint(token) }], folders: [] });
+    try {
+      await expect(handler({ token, docId: 'doc123' }))
+        .rejects.toThrow('Invalid or expired share token');
+      expect(mockFetch).not.toHaveBeenCalled();
+    } finally {
+      setShareRevocations({ tokens: [], folders: [] });
+    }
+  });
+
   it('should throw AuthError 502 when relay returns error', async () => {
     const token = signShareToken(validPayload);
     // Folder lookup mock
@@ -55,8 +55,11 @@ export function createAuthHandler(config: AuthHandlerConfig) {
       }
     }
 
-    // 3. Determine relay authorization level
-    const relayAuth = payload.role === 'view' ? 'read-only' : 'full';
+    // 3. Determine relay authorization level. Suggesters get a `suggest` token,
+    // so the relay itself rejects any sync update that isn't a CriticMarkup
+    // suggestion — the editor's suggestion mode is not the only guard.
+    const relayAuth: ClientToken['authorization'] =
+      payload.role === 'view' ? 'read-only' : payload.role === 'suggest' ? 'suggest' : 'full';
 
     // 4. Mint relay doc token by proxying to relay server
     const headers: Record<string, string> = {
@@ -2,9 +2,10 @@ import http from 'node:http';
 import { Readable } from 'node:stream';
 import httpProxy from 'http-proxy';
 import { getRequestListener } from '@hono/node-server';
-import { validateProxyToken, checkProxyAccessWithBody } from './relay-proxy-auth.ts';
+import { validateProxyToken, checkProxyAccessWithBody, withShareTokenUser } from './relay-proxy-auth.ts';
 import { initDiscordGateway } from './discord/routes.ts';
 import { createApp } from './app.ts';
+import { startShareRevocationSync } from './share-revocations.ts';
 
 const relayUrl = process.env.RELAY_URL || 'http://relay-server:8080';
 const relayServerToken = process.env.RELAY_SERVER_TOKEN;
@@ -83,7 +84,9 @@ const server = http.createServer(async (req, res) => {
       return;
     }
 
-    req.url = relayPath;
+    // The relay evaluates its ACL for `?user=`, so that must be the share token's user.
+    const forwardedQuery = withShareTokenUser(pathOnly, query, auth);
+    req.url = forwardedQuery ? `${pathOnly}?${forwardedQuery}` : pathOnly;
     if (relayServerToken) {
       req.headers['authorization'] = `Bearer ${relayServerToken}`;
     }
@@ -108,6 +111,10 @@ const server = http.createServer(async (req, res) => {
   }
 });
 
+// Keep the relay's share token revocation list in sync, so revoked links are
+// refused here as well as on the relay's MCP endpoint.
+startShareRevocationSync({ relayServerUrl: relayUrl, relayServerToken });
+
 // Start Discord Gateway (eager — connects on startup, no-op without DISCORD_BOT_TOKEN)
 initDiscordGateway();
 
@@ -1,5 +1,5 @@
 import { describe, it, expect } from 'vitest';
-import { validateProxyToken, checkProxyAccess, checkProxyAccessWithBody, type ProxyAuthResult } from './relay-proxy-auth.ts';
+import { validateProxyToken, checkProxyAccess, checkProxyAccessWithBody, withShareTokenUser, type ProxyAuthResult } from './relay-proxy-auth.ts';
 import { signShareToken } from './share-token.ts';
 
 const FOLDER_A = 'fbd5eb54-73cc-41b0-ac28-2b93d3b4244e';
@@ -165,3 +165,25 @@ describe('checkProxyAccess', () => {
     expect(checkProxyAccessWithBody('POST', '/suggestions/apply', `folder_id=${RELAY_ID}-${FOLDER_B}`, scopedAuth).allowed).toBe(false);
   });
 });
+
+describe('withShareTokenUser', () => {
+  const withUser: ProxyAuthResult = {
+    ...makeAuth(FOLDER_A),
+    payload: { ...makeAuth(FOLDER_A).payload, user: 'ta-42', issuedAt: Math.floor(Date.now() / 1000) },
+  };
+
+  it('replaces a client-supplied user with the token user', () => {
+    const query = withShareTokenUser('/search', 'q=test&user=admin', withUser);
+    expect(new URLSearchParams(query).getAll('user')).toEqual(['ta-42']);
+    expect(new URLSearchParams(query).get('q')).toBe('test');
+  });
+
+  it('drops the user for tokens without one', () => {
+    const query = withShareTokenUser('/suggestions', `folder_id=${RELAY_ID}-${FOLDER_A}&user=ta-42`, makeAuth(FOLDER_A));
+    expect(new URLSearchParams(query).has('user')).toBe(false);
+  });
+
+  it('leaves other endpoints alone', () => {
+    expect(withShareTokenUser('/doc/resolve/abc', 'user=x', withUser)).toBe('user=x');
+  });
+});
@@ -152,3 +152,19 @@ export function checkProxyAccessWithBody(
 
   return checkProxyAccess(method, path, query, auth);
 }
+
+/** Relay endpoints that evaluate the ACL policy for the `?user=` query parameter. */
+const ACL_USER_PATHS = new Set(['/search', '/suggestions', '/suggestions/apply']);
+
+/**
+ * Rewrite the query of an ACL-evaluated relay endpoint so `user` is the share
+ * token's own user (or absent, i.e. anonymous). The relay trusts `?user=` from
+ * server-token callers, so the browser must never choose it.
+ */
+export function withShareTokenUser(path: string, query: string, auth: ProxyAuthResult): string {
+  if (!ACL_USER_PATHS.has(path)) return query;
+  const params = new URLSearchParams(query);
+  params.delete('user');
+  if (auth.payload.user) params.set('user', auth.payload.user);
+  return params.toString();
+}
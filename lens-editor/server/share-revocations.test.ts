@@ -0,0 +1,73 @@
+import { describe, it, expect, vi, afterEach } from 'vitest';
+import { signShareToken, verifyShareToken } from './share-token.ts';
+import type { ShareTokenPayload } from './share-token.ts';
+import {
+  refreshShareRevocations,
+  setShareRevocations,
+  tokenFingerprint,
+} from './share-revocations.ts';
+
+const mockFetch = vi.fn();
+vi.stubGlobal('fetch', mockFetch);
+
+describe('share-revocations', () => {
+  const folder = 'fbd5eb54-73cc-41b0-ac28-2b93d3b4244e';
+  const payload: ShareTokenPayload = {
+    purpose: 'share',
+    role: 'edit',
+    folder,
+    expiry: Math.floor(Date.now() / 1000) + 3600,
+  };
+
+  afterEach(() => {
+    setShareRevocations({ tokens: [], folders: [] });
+    vi.clearAllMocks();
+  });
+
+  it('fingerprints tokens the way the relay does', () => {
+    // SHA-256("abc"), first 32 hex chars — same as token_fingerprint in Rust.
+    expect(tokenFingerprint('abc')).toBe('ba7816bf8f01cfea414140de5dae2223');
+    expect(tokenFingerprint(' abc\n')).toBe(tokenFingerprint('abc'));
+  });
+
+  it('refuses a token listed by fingerprint', () => {
+    const token = signShareToken(payload);
+    const other = signShareToken({ ...payload, role: 'view' });
+    setShareRevocations({ tokens: [{ fingerprint: tokenFingerprint(token) }], folders: [] });
+    expect(verifyShareToken(token)).toBeNull();
+    expect(verifyShareToken(other)).not.toBeNull();
+  });
+
+  it('applies folder cutoffs to tokens that record their issue time', () => {
+    const earlier = signShareToken({ ...payload, user: 'alice', issuedAt: 1_700_000_000 });
+    const later = signShareToken({ ...payload, user: 'alice', issuedAt: 1_900_000_000 });
+    const v1 = signShareToken(payload);
+    setShareRevocations({
+      tokens: [],
+      folders: [{ folder: folder.toUpperCase(), issued_before: 1_800_000_000 }],
+    });
+    expect(verifyShareToken(earlier)).toBeNull();
+    expect(verifyShareToken(later)).not.toBeNull();
+    // v1 tokens have no issue time and are only revoked by fingerprint.
+    expect(verifyShareToken(v1)).not.toBeNull();
+  });
+
+  it('loads the list from the relay and keeps it when a refresh fails', async () => {
+    const token = signShareToken(payload);
+    mockFetch.mockResolvedValueOnce({
+      ok: true,
+      json: async () => ({ tokens: [{ fingerprint: tokenFingerprint(token), revoked_at: 1 }], folders: [] }),
+    });
+    const config = { relayServerUrl: 'http://localhost:8190', relayServerToken: 'test-server-token' };
+    expect(await refreshShareRevocations(config)).toBe(true);
+    expect(mockFetch).toHaveBeenCalledWith(
+      'http://localhost:8190/share-tokens/revocations',
+      { headers: { Authorization: 'Bearer test-server-token' } },
+    );
+    expect(verifyShareToken(token)).toBeNull();
+
+    mockFetch.mockResolvedValueOnce({ ok: false, status: 503 });
+    expect(await refreshShareRevocations(config)).toBe(false);
+    expect(verifyShareToken(token)).toBeNull();
+  });
+});
@@ -0,0 +1,83 @@
+import { createHash } from 'node:crypto';
+import type { ShareTokenPayload } from './share-token.ts';
+
+/**
+ * The relay's share token revocation list (`.config/share_revocations.json`),
+ * polled from GET /share-tokens/revocations so that share links revoked there
+ * stop working here too. Mirrors crates/y-sweet-core/src/share_revocation.rs.
+ */
+export interface RevocationList {
+  tokens: { fingerprint: string; expiry?: number }[];
+  folders: { folder: string; issued_before: number }[];
+}
+
+interface RevocationSyncConfig {
+  relayServerUrl: string;
+  relayServerToken?: string;
+}
+
+const FINGERPRINT_LEN = 32; // hex chars of SHA-256
+const DEFAULT_POLL_MS = 60_000;
+
+let current: RevocationList = { tokens: [], folders: [] };
+
+/** Truncated SHA-256 of the token, in hex: how the relay names a revoked token. */
+export function tokenFingerprint(token: string): string {
+  return createHash('sha256').update(token.trim()).digest('hex').slice(0, FINGERPRINT_LEN);
+}
+
+/**
+ * Whether `token`, which verified to `payload`, is revoked. Folder cutoffs only
+ * cover tokens that record when they were issued (v2); v1 tokens are revoked
+ * by fingerprint.
+ */
+export function isShareTokenRevoked(token: string, payload: ShareTokenPayload): boolean {
+  const { issuedAt } = payload;
+  const folder = payload.folder.toLowerCase();
+  if (issuedAt !== undefined && current.folders.some(
+    rule => rule.folder.toLowerCase() === folder && issuedAt < rule.issued_before,
+  )) {
+    return true;
+  }
+  if (current.tokens.length === 0) return false;
+  const fingerprint = tokenFingerprint(token);
+  return current.tokens.some(entry => entry.fingerprint === fingerprint);
+}
+
+export function setShareRevocations(list: RevocationList): void {
+  current = { tokens: list.tokens ?? [], folders: list.folders ?? [] };
+}
+
+/**
+ * Fetch the list from the relay. On failure the previous list stays in force,
+ * so a relay restart doesn't un-revoke anything.
+ */
+export async function refreshShareRevocations(config: RevocationSyncConfig): Promise<boolean> {
+  const headers: Record<string, string> = {};
+  if (config.relayServerToken) {
+    headers['Authorization'] = `Bearer ${config.relayServerToken}`;
+  }
+  try {
+    const res = await fetch(`${config.relayServerUrl}/share-tokens/revocations`, { headers });
+    if (!res.ok) {
+      console.warn(`[share-revocations] Relay returned ${res.status}; keeping the previous list`);
+      return false;
+    }
+    setShareRevocations(await res.json() as RevocationList);
+    return true;
+  } catch (err) {
+    console.warn(`[share-revocations] Fetch failed; keeping the previous list: ${err}`);
+    return false;
+  }
+}
+
+/** Load the list now and re-poll every `intervalMs`. Returns a stop function. */
+export function startShareRevocationSync(
+  config: RevocationSyncConfig,
+  intervalMs = DEFAULT_POLL_MS,
+): () => void {
+  void refreshShareRevocations(config);
+  const timer = setInterval(() => void refreshShareRevocations(config), intervalMs);
+  timer.unref?.();
+  return () => clearInterval(timer);
+}
@@ -91,6 +91,33 @@ describe('share-token', () => {
       expect(shareToken).not.toBe(addVideoToken);
     });
 
+    it('should sign and verify a v2 token carrying a user', () => {
+      const payload: ShareTokenPayload = {
+        ...validPayload,
+        user: 'discord:1234',
+        issuedAt: Math.floor(Date.now() / 1000),
+      };
+      const token = signShareToken(payload);
+      expect(verifyShareToken(token)).toEqual(payload);
+      expect(decodeShareTokenPayload(token)).toEqual(payload);
+    });
+
+    it('should keep the v1 layout when no user or issue time is set', () => {
+      // Verifiers that only know v1 must keep accepting these.
+      expect(Buffer.from(signShareToken(validPayload), 'base64url').length).toBe(30);
+    });
+
+    it('should reject a v2 token whose user was altered', () => {
+      const token = signShareToken({ ...validPayload, user: 'alice' });
+      const raw = Buffer.from(token, 'base64url');
+      raw[raw.length - 9] ^= 1; // last byte of the user
+      expect(verifyShareToken(raw.toString('base64url'))).toBeNull();
+    });
+
+    it('should throw when signing an over-long user', () => {
+      expect(() => signShareToken({ ...validPayload, user: 'x'.repeat(65) })).toThrow('invalid user');
+    });
+
     // Prevents: unmapped role silently minting a signed byte-0 token — since
     // byte 0 decodes as 'admin', a caller passing an unvalidated role string
     // would fail open into a production-promotion token.
@@ -1,5 +1,6 @@
 import { createHmac, timingSafeEqual } from 'node:crypto';
 import { ROLE_ORDER, type UserRole } from '../shared/types.ts';
+import { isShareTokenRevoked } from './share-revocations.ts';
 
 export type TokenPurpose = 'share' | 'add-video';
 
@@ -8,6 +9,8 @@ export interface ShareTokenPayload {
   role: UserRole;
   folder: string;   // UUID string
   expiry: number;    // unix seconds
+  user?: string;     // who the token was issued to (v2 only)
+  issuedAt?: number; // unix seconds (v2 only)
 }
 
 const DEV_SECRET = 'lens-editor-dev-secret-do-not-use-in-production';
@@ -29,7 +32,16 @@ const BYTE_TO_PURPOSE: Record<number, TokenPurpose> = { 0: 'share', 1: 'add-vide
 const ROLE_TO_BYTE: Record<UserRole, number> = { admin: 0, edit: 1, suggest: 2, view: 3 };
 const BYTE_TO_ROLE: Record<number, UserRole> = { 0: 'admin', 1: 'edit', 2: 'suggest', 3: 'view' };
 
-const PAYLOAD_LEN = 22;  // 1 purpose + 1 role + 16 uuid + 4 expiry
+const PAYLOAD_LEN = 22;  // v1: 1 purpose + 1 role + 16 uuid + 4 expiry
+// v2 leads with a marker byte no v1 purpose byte can take, then
+// purpose + role + uuid + expiry + issuedAt:4 + userLen:1 + user (UTF-8).
+// Mirrors crates/y-sweet-core/src/share_token.rs.
+const V2_MARKER = 0x82;
+const V2_HEADER_LEN = 28;
+export const MAX_USER_LEN = 64;
+// Same set as Rust's char::is_control; users end up in CriticMarkup JSON.
+// eslint-disable-next-line no-control-regex
+const CONTROL_CHARS = /[\u0000-\u001f\u007f-\u009f]/;
 const SIG_LEN = 8;       // truncated HMAC-SHA256
 
 /** True when `role` has at least `min`'s privilege (ROLE_ORDER is highest -> lowest). */
@@ -61,28 +73,71 @@ function packPayload(payload: ShareTokenPayload): Buffer {
   if (roleByte === undefined) {
     throw new Error(`Cannot sign token with unknown role "${payload.role}"`);
   }
-  const buf = Buffer.alloc(PAYLOAD_LEN);
-  buf[0] = purposeByte;
-  buf[1] = roleByte;
-  uuidToBytes(payload.folder).copy(buf, 2);
-  buf.writeUInt32BE(payload.expiry, 18);
+  // No user or issue time: keep the v1 layout every deployed verifier reads.
+  if (payload.user === undefined && payload.issuedAt === undefined) {
+    const buf = Buffer.alloc(PAYLOAD_LEN);
+    buf[0] = purposeByte;
+    buf[1] = roleByte;
+    uuidToBytes(payload.folder).copy(buf, 2);
+    buf.writeUInt32BE(payload.expiry, 18);
+    return buf;
+  }
+  const user = Buffer.from(payload.user ?? '', 'utf8');
+  if (user.length > MAX_USER_LEN || CONTROL_CHARS.test(payload.user ?? '')) {
+    throw new Error(`Cannot sign token with invalid user "${payload.user}"`);
+  }
+  const buf = Buffer.alloc(V2_HEADER_LEN + user.length);
+  buf[0] = V2_MARKER;
+  buf[1] = purposeByte;
+  buf[2] = roleByte;
+  uuidToBytes(payload.folder).copy(buf, 3);
+  buf.writeUInt32BE(payload.expiry, 19);
+  buf.writeUInt32BE(payload.issuedAt ?? 0, 23);
+  buf[27] = user.length;
+  user.copy(buf, V2_HEADER_LEN);
   return buf;
 }
 
 function unpackPayload(buf: Buffer): ShareTokenPayload | null {
-  if (buf.length < PAYLOAD_LEN) return null;
-  const purpose = BYTE_TO_PURPOSE[buf[0]];
+  if (buf.length === PAYLOAD_LEN) {
+    const purpose = BYTE_TO_PURPOSE[buf[0]];
+    if (purpose === undefined) return null;
+    const role = BYTE_TO_ROLE[buf[1]];
+    if (!role) return null;
+    const folder = bytesToUuid(buf.subarray(2, 18));
+    const expiry = buf.readUInt32BE(18);
+    return { purpose, role, folder, expiry };
+  }
+  if (buf.length < V2_HEADER_LEN || buf[0] !== V2_MARKER) return null;
+  const purpose = BYTE_TO_PURPOSE[buf[1]];
   if (purpose === undefined) return null;
-  const role = BYTE_TO_ROLE[buf[1]];
+  const role = BYTE_TO_ROLE[buf[2]];
   if (!role) return null;
-  const folder = bytesToUuid(buf.subarray(2, 18));
-  const expiry = buf.readUInt32BE(18);
-  return { purpose, role, folder, expiry };
+  const folder = bytesToUuid(buf.subarray(3, 19));
+  const expiry = buf.readUInt32BE(19);
+  const issuedAt = buf.readUInt32BE(23);
+  const userLen = buf[27];
+  if (userLen > MAX_USER_LEN || buf.length !== V2_HEADER_LEN + userLen) return null;
+  let user: string;
+  try {
+    user = new TextDecoder('utf-8', { fatal: true }).decode(buf.subarray(V2_HEADER_LEN));
+  } catch {
+    return null;
+  }
+  if (CONTROL_CHARS.test(user)) return null;
+  const payload: ShareTokenPayload = { purpose, role, folder, expiry };
+  if (user) payload.user = user;
+  if (issuedAt) payload.issuedAt = issuedAt;
+  return payload;
 }
 
 /**
- * Sign a share token. Returns a compact base64url string (~40 chars).
- * Format: base64url(purpose:1 + role:1 + uuid:16 + expiry:4 + hmac:8)
+ * Sign a share token. Returns a compact base64url string (~40 chars, plus the
+ * user for v2 tokens).
+ * v1 format: base64url(purpose:1 + role:1 + uuid:16 + expiry:4 + hmac:8)
+ * v2 format: base64url(0x82 + purpose:1 + role:1 + uuid:16 + expiry:4 +
+ *            issuedAt:4 + userLen:1 + user + hmac:8), used when `user` or
+ *            `issuedAt` is set.
  */
 export function signShareToken(payload: ShareTokenPayload): string {
   const secret = getSecret();
@@ -93,7 +148,8 @@ export function signShareToken(payload: ShareTokenPayload): string {
 }
 
 /**
- * Verify and decode a share token. Returns null if invalid or expired.
+ * Verify and decode a share token. Returns null if invalid, expired, or on
+ * the relay's revocation list.
  */
 export function verifyShareToken(token: string): ShareTokenPayload | null {
   let raw: Buffer;
@@ -103,10 +159,12 @@ export function verifyShareToken(token: string): ShareTokenPayload | null {
     return null;
   }
 
-  if (raw.length !== PAYLOAD_LEN + SIG_LEN) return null;
+  if (raw.length < PAYLOAD_LEN + SIG_LEN || raw.length > V2_HEADER_LEN + MAX_USER_LEN + SIG_LEN) {
+    return null;
+  }
 
-  const packed = raw.subarray(0, PAYLOAD_LEN);
-  const sig = raw.subarray(PAYLOAD_LEN);
+  const packed = raw.subarray(0, raw.length - SIG_LEN);
+  const sig = raw.subarray(raw.length - SIG_LEN);
 
   const secret = getSecret();
   const expectedSig = createHmac('sha256', secret).update(packed).digest().subarray(0, SIG_LEN);
@@ -119,6 +177,8 @@ export function verifyShareToken(token: string): ShareTokenPayload | null {
   // Check expiration
   if (payload.expiry < Math.floor(Date.now() / 1000)) return null;
 
+  if (isShareTokenRevoked(token, payload)) return null;
+
   return payload;
 }
 
@@ -129,7 +189,8 @@ export function verifyShareToken(token: string): ShareTokenPayload | null {
 export function decodeShareTokenPayload(token: string): ShareTokenPayload | null {
   try {
     const raw = Buffer.from(token, 'base64url');
-    return unpackPayload(raw);
+    if (raw.length <= SIG_LEN) return null;
+    return unpackPayload(raw.subarray(0, raw.length - SIG_LEN));
   } catch {
     return null;
   }
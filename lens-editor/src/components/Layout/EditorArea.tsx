@@ -41,6 +41,7 @@ import { useDisplayName } from '../../contexts/DisplayNameContext';
 import { persistentHighlightLine } from '../Editor/extensions/headingFlash';
 import { revealFrontmatterPos } from '../Editor/extensions/frontmatter';
 import { utf8ByteToUtf16Offset } from '../../lib/text-offsets';
+import { findHeadingLine } from '../../lib/heading-anchor';
 import { resolveAnchorYFromView, resolveAnchorYFromDOM } from '../../lib/anchor-resolver';
 import { findPathByUuid } from '../../lib/uuid-to-path';
 import { pathToSegments } from '../../lib/path-display';
@@ -351,6 +352,8 @@ export function EditorArea({ currentDocId }: { currentDocId: string }) {
   //   ?pos={offset} — review page "Open" links; a UTF-8 byte offset from the
   //                   relay's suggestion scan, translated to a CM position here
   //   #L{number}    — line anchors
+  //   #{heading}    — heading anchors from section search / get_url; a hash
+  //                   that names no heading is left alone
   // One effect handles both (?pos wins when both are present) so there is a
   // single jump and a single cleanup that strips every consumed target from
   // the URL — even malformed ones, which would otherwise linger and confuse
@@ -362,9 +365,15 @@ export function EditorArea({ currentDocId }: { currentDocId: string }) {
     const params = new URLSearchParams(window.location.search);
     const posParam = params.get('pos');
     const lineMatch = window.location.hash.match(/^#L(\d+)$/i);
-    if (posParam === null && !lineMatch) return;
-
     const doc = editorView.state.doc;
+    let headingLine: number | null = null;
+    if (!lineMatch && window.location.hash.length > 1) {
+      let anchor = window.location.hash.slice(1);
+      try { anchor = decodeURIComponent(anchor); } catch { /* keep raw */ }
+      headingLine = findHeadingLine(doc.toString(), anchor);
+    }
+    if (posParam === null && !lineMatch && headingLine === null) return;
+
     let target: number | null = null;
     if (posParam !== null) {
       const byteOffset = parseInt(posParam, 10);
@@ -376,12 +385,15 @@ export function EditorArea({ currentDocId }: { currentDocId: string }) {
       const lineNum = parseInt(lineMatch[1], 10);
       if (lineNum >= 1) target = doc.line(Math.min(lineNum, doc.lines)).from;
     }
+    if (target === null && headingLine !== null) {
+      target = doc.line(Math.min(headingLine, doc.lines)).from;
+    }
 
     if (target !== null) jumpToPos(editorView, target);
 
     params.delete('pos');
     const qs = params.toString();
-    const hash = lineMatch ? '' : window.location.hash;
+    const hash = lineMatch || headingLine !== null ? '' : window.location.hash;
     history.replaceState(null, '', window.location.pathname + (qs ? `?${qs}` : '') + hash);
   }, [synced, editorView, routerLocation]);
 
@@ -3,7 +3,7 @@ export interface ClientToken {
   baseUrl: string;
   docId: string;
   token?: string;
-  authorization: 'full' | 'read-only';
+  authorization: 'full' | 'suggest' | 'read-only';
 }
 
 /**
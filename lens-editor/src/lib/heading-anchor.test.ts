@@ -0,0 +1,25 @@
+import { describe, it, expect } from 'vitest';
+import { headingSlug, findHeadingLine } from './heading-anchor';
+
+describe('headingSlug', () => {
+  it('matches the relay slug rules', () => {
+    expect(headingSlug('What is AI Safety?')).toBe('what-is-ai-safety');
+    expect(headingSlug('  Step 1: set-up  ')).toBe('step-1-set-up');
+  });
+});
+
+describe('findHeadingLine', () => {
+  const doc = '---\ntitle: x\n---\n# Notes\ntext\n```\n# Notes\n```\n## Notes ##\n';
+
+  it('finds the heading line for an anchor', () => {
+    expect(findHeadingLine(doc, 'notes')).toBe(4);
+  });
+
+  it('numbers repeated headings and skips code fences', () => {
+    expect(findHeadingLine(doc, 'notes-1')).toBe(9);
+  });
+
+  it('returns null for unknown anchors', () => {
+    expect(findHeadingLine(doc, 'missing')).toBeNull();
+  });
+});
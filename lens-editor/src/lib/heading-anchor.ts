@@ -0,0 +1,50 @@
+/**
+ * `#heading` URL anchors, as returned by the relay's section search and the
+ * MCP `get_url` tool. Must stay in sync with `heading_slug` and `split` in
+ * `crates/y-sweet-core/src/sections.rs`.
+ */
+
+/** Lowercase, drop punctuation, join whitespace runs with `-` (GitHub style). */
+export function headingSlug(heading: string): string {
+  return heading
+    .trim()
+    .toLowerCase()
+    .replace(/[^\p{L}\p{N}\s_-]/gu, '')
+    .trim()
+    .split(/\s+/)
+    .filter(Boolean)
+    .join('-');
+}
+
+const ATX_HEADING = /^ {0,3}(#{1,6})(?:[ \t]+(.*?))?[ \t]*$/;
+
+/**
+ * 1-based line of the heading whose anchor is `anchor` (without `#`), or null.
+ * Repeated headings get `-1`, `-2`, ... suffixes; headings inside fenced code
+ * blocks are skipped.
+ */
+export function findHeadingLine(text: string, anchor: string): number | null {
+  const seen = new Map<string, number>();
+  let fence: string | null = null;
+  const lines = text.split('\n');
+  for (let i = 0; i < lines.length; i++) {
+    const trimmed = lines[i].trimStart();
+    if (fence) {
+      if (trimmed.startsWith(fence)) fence = null;
+      continue;
+    }
+    if (trimmed.startsWith('```') || trimmed.startsWith('~~~')) {
+      fence = trimmed.slice(0, 3);
+      continue;
+    }
+    const match = lines[i].match(ATX_HEADING);
+    if (!match || !match[2]) continue;
+    const heading = match[2].replace(/[ \t]+#+$/, '').replace(/^#+$/, '');
+    if (!heading) continue;
+    const slug = headingSlug(heading);
+    const count = seen.get(slug) ?? 0;
+    seen.set(slug, count + 1);
+    if ((count === 0 ? slug : `${slug}-${count}`) === anchor) return i + 1;
+  }
+  return null;
+}
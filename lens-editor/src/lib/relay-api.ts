@@ -418,14 +418,25 @@ export interface SearchResult {
   path?: string;    // Display path, e.g. "Lens / Physics" (client-enriched)
   snippet: string;  // HTML with <mark> tags
   score: number;
+  modified?: number | null;  // ms since epoch of the last text change, null if unknown
 }
 
+export type SearchSort = 'relevance' | 'modified' | 'title' | 'path';
+
 export interface SearchResponse {
   results: SearchResult[];
-  total_hits: number;
+  total_hits: number;  // across all pages
+  offset: number;
+  next_cursor: string | null;  // pass back as `cursor` for the next page
+  sort: SearchSort;
   query: string;
 }
 
+export interface SearchPageOptions {
+  cursor?: string;
+  sort?: SearchSort;
+}
+
 // --- Move API ---
 
 export interface MoveDocumentResponse {
@@ -522,9 +533,12 @@ export async function movePath(
 export async function searchDocuments(
   query: string,
   limit: number = 20,
-  signal?: AbortSignal
+  signal?: AbortSignal,
+  page: SearchPageOptions = {}
 ): Promise<SearchResponse> {
   const params = new URLSearchParams({ q: query, limit: String(limit) });
+  if (page.cursor) params.set('cursor', page.cursor);
+  if (page.sort) params.set('sort', page.sort);
   const response = await fetch(`/api/relay/search?${params}`, { signal, headers: relayHeaders() });
   if (!response.ok) {
     throw new Error(`Search failed: ${response.status}`);
@@ -70,10 +70,14 @@ export default defineConfig(() => {
 
     return {
       name: 'relay-proxy-auth',
-      configureServer(server) {
+      async configureServer(server) {
+        // Refuse share links revoked on the relay, as the production server does.
+        const { startShareRevocationSync } = await import('./server/share-revocations.ts');
+        startShareRevocationSync({ relayServerUrl: relayTarget, relayServerToken });
+
         // Validate share token on /api/relay/ proxy requests
         server.middlewares.use('/api/relay', async (req, res, next) => {
-          const { validateProxyToken, checkProxyAccessWithBody } = await import('./server/relay-proxy-auth.ts');
+          const { validateProxyToken, checkProxyAccessWithBody, withShareTokenUser } = await import('./server/relay-proxy-auth.ts');
 
           const shareToken = req.headers['x-share-token'] as string | undefined;
           const auth = validateProxyToken(shareToken);
@@ -112,6 +116,9 @@ export default defineConfig(() => {
             return;
           }
 
+          // The relay evaluates its ACL for `?user=`, so that must be the share token's user.
+          const forwardedQuery = withShareTokenUser(pathOnly, query, auth);
+          req.url = forwardedQuery ? `${pathOnly}?${forwardedQuery}` : pathOnly;
           delete req.headers['x-share-token'];
           next();
         });